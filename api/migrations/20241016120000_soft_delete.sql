@@ -0,0 +1,3 @@
+alter table app.sources add column deleted_at timestamptz;
+alter table app.sinks add column deleted_at timestamptz;
+alter table app.pipelines add column deleted_at timestamptz;
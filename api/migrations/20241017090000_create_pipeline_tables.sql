@@ -0,0 +1,11 @@
+create table
+    app.pipeline_tables (
+        pipeline_id bigint references app.pipelines (id) not null,
+        table_id bigint not null,
+        schema_name text not null,
+        table_name text not null,
+        status text not null check (status in ('copying', 'copied', 'streaming')),
+        lsn pg_lsn,
+        updated_at timestamptz not null default now(),
+        primary key (pipeline_id, table_id)
+    );
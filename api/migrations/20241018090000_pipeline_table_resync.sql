@@ -0,0 +1 @@
+alter table app.pipeline_tables add column resync_requested boolean not null default false;
@@ -0,0 +1,25 @@
+create table
+    app.audit_log (
+        id bigint generated always as identity primary key,
+        tenant_id text not null,
+        actor text not null,
+        action text not null check (action in ('create', 'update', 'delete')),
+        resource_type text not null check (resource_type in ('source', 'sink', 'pipeline')),
+        resource_id bigint not null,
+        before jsonb,
+        after jsonb,
+        created_at timestamptz not null default now()
+    );
+
+create index audit_log_tenant_id_idx on app.audit_log (tenant_id, id);
+
+-- entries are never changed or removed once written, not even with their tenant
+create function app.reject_audit_log_change() returns trigger language plpgsql as $$
+begin
+    raise exception 'app.audit_log is append-only';
+end;
+$$;
+
+create trigger audit_log_append_only
+    before update or delete on app.audit_log
+    for each row execute function app.reject_audit_log_change();
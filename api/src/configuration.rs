@@ -112,6 +112,9 @@ pub struct Settings {
     pub worker: WorkerSettings,
     pub encryption_key: EncryptionKey,
     pub api_key: String,
+    /// Traces are only exported if set
+    #[serde(default)]
+    pub telemetry: Option<TelemetrySettings>,
 }
 
 impl Display for Settings {
@@ -121,7 +124,11 @@ impl Display for Settings {
         writeln!(f, "  application:\n{}", self.application)?;
         writeln!(f, "  worker:\n{}", self.worker)?;
         writeln!(f, "  encryption_key:\n{}", self.encryption_key)?;
-        writeln!(f, "  api_key: REDACTED")
+        writeln!(f, "  api_key: REDACTED")?;
+        if let Some(telemetry) = &self.telemetry {
+            writeln!(f, "  telemetry:\n{telemetry}")?;
+        }
+        Ok(())
     }
 }
 
@@ -209,6 +216,19 @@ impl Display for WorkerSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct TelemetrySettings {
+    /// OTLP gRPC endpoint spans are exported to, e.g. `http://localhost:4317`.
+    /// Replicators started by the api export their spans to it as well.
+    pub otlp_endpoint: String,
+}
+
+impl Display for TelemetrySettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "    otlp_endpoint: {}", self.otlp_endpoint)
+    }
+}
+
 pub fn get_settings<'a, T: serde::Deserialize<'a>>() -> Result<T, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
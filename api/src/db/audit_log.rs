@@ -0,0 +1,176 @@
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+
+use super::{sinks::SinkConfig, sources::SourceConfig};
+
+/// The change an audit record was written for
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+        }
+    }
+
+    fn from_db(action: &str) -> Self {
+        match action {
+            "create" => AuditAction::Create,
+            "update" => AuditAction::Update,
+            "delete" => AuditAction::Delete,
+            // the action column's check constraint doesn't allow other values
+            action => unreachable!("invalid audit action: {action}"),
+        }
+    }
+}
+
+/// The kind of item an audit record was written for
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditResourceType {
+    Source,
+    Sink,
+    Pipeline,
+}
+
+impl AuditResourceType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditResourceType::Source => "source",
+            AuditResourceType::Sink => "sink",
+            AuditResourceType::Pipeline => "pipeline",
+        }
+    }
+
+    fn from_db(resource_type: &str) -> Self {
+        match resource_type {
+            "source" => AuditResourceType::Source,
+            "sink" => AuditResourceType::Sink,
+            "pipeline" => AuditResourceType::Pipeline,
+            // the resource_type column's check constraint doesn't allow other values
+            resource_type => unreachable!("invalid audit resource type: {resource_type}"),
+        }
+    }
+}
+
+/// A change to a source, sink or pipeline. `before` is `None` for a created
+/// item and `after` for a deleted one. Neither holds secrets, see
+/// [`source_state`] and [`sink_state`].
+pub struct AuditEntry<'a> {
+    pub tenant_id: &'a str,
+    pub actor: &'a str,
+    pub action: AuditAction,
+    pub resource_type: AuditResourceType,
+    pub resource_id: i64,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+pub struct AuditRecord {
+    pub id: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    pub resource_type: AuditResourceType,
+    pub resource_id: i64,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: String,
+}
+
+/// The audited state of a source, with its password redacted
+pub fn source_state(name: &str, config: &SourceConfig) -> Value {
+    json!({ "name": name, "config": config.to_redacted_json() })
+}
+
+/// The audited state of a sink, with its service account key redacted
+pub fn sink_state(name: &str, config: &SinkConfig) -> Value {
+    json!({ "name": name, "config": config.to_redacted_json() })
+}
+
+/// The audited state of a pipeline, whose config holds no secrets
+pub fn pipeline_state(
+    source_id: i64,
+    sink_id: i64,
+    publication_name: &str,
+    config: &Value,
+) -> Value {
+    json!({
+        "source_id": source_id,
+        "sink_id": sink_id,
+        "publication_name": publication_name,
+        "config": config,
+    })
+}
+
+/// Appends `entry` to its tenant's audit trail
+pub async fn record_audit_entry(pool: &PgPool, entry: AuditEntry<'_>) -> Result<(), sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+pub async fn record_audit_entry_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    entry: AuditEntry<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into app.audit_log (tenant_id, actor, action, resource_type, resource_id, before, after)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        entry.tenant_id,
+        entry.actor,
+        entry.action.as_str(),
+        entry.resource_type.as_str(),
+        entry.resource_id,
+        entry.before,
+        entry.after,
+    )
+    .execute(&mut **txn)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the audit trail of a tenant, oldest record first
+pub async fn read_audit_log(
+    pool: &PgPool,
+    tenant_id: &str,
+) -> Result<Vec<AuditRecord>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"
+        select id, actor, action, resource_type, resource_id, before, after,
+            created_at::text as "created_at!"
+        from app.audit_log
+        where tenant_id = $1
+        order by id
+        "#,
+        tenant_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|r| AuditRecord {
+            id: r.id,
+            actor: r.actor,
+            action: AuditAction::from_db(&r.action),
+            resource_type: AuditResourceType::from_db(&r.resource_type),
+            resource_id: r.resource_id,
+            before: r.before,
+            after: r.after,
+            created_at: r.created_at,
+        })
+        .collect())
+}
@@ -0,0 +1,107 @@
+use pg_escape::quote_literal;
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection, Row};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConnectivityReport {
+    /// Whether a connection to the source could be established
+    pub connected: bool,
+
+    /// Why the connection failed, if it did
+    pub connection_error: Option<String>,
+
+    /// Whether the user is a superuser or has the `replication` attribute
+    pub replication_privilege: bool,
+
+    /// Value of the `wal_level` setting, which must be `logical`
+    pub wal_level: Option<String>,
+
+    /// Whether the publication exists, `None` if no publication was checked
+    pub publication_exists: Option<bool>,
+
+    /// Whether the slot exists
+    pub slot_exists: bool,
+
+    /// Whether a missing slot could be created
+    pub slot_creatable: bool,
+
+    /// Whether every check passed
+    pub ok: bool,
+}
+
+pub async fn check_connectivity(
+    options: &PgConnectOptions,
+    slot_name: &str,
+    publication: Option<&str>,
+) -> Result<ConnectivityReport, sqlx::Error> {
+    let mut connection = match PgConnection::connect_with(options).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            return Ok(ConnectivityReport {
+                connected: false,
+                connection_error: Some(e.to_string()),
+                replication_privilege: false,
+                wal_level: None,
+                publication_exists: None,
+                slot_exists: false,
+                slot_creatable: false,
+                ok: false,
+            })
+        }
+    };
+
+    let query = r#"
+        select rolreplication or rolsuper as can_replicate
+        from pg_roles
+        where rolname = current_user;
+        "#;
+    let replication_privilege: bool = connection.fetch_one(query).await?.get("can_replicate");
+
+    let wal_level: String = connection
+        .fetch_one("show wal_level;")
+        .await?
+        .get("wal_level");
+
+    let publication_exists = match publication {
+        Some(publication) => {
+            let query = format!(
+                "select exists (select 1 from pg_publication where pubname = {}) as exists;",
+                quote_literal(publication)
+            );
+            Some(connection.fetch_one(query.as_str()).await?.get("exists"))
+        }
+        None => None,
+    };
+
+    let query = format!(
+        "select exists (select 1 from pg_replication_slots where slot_name = {}) as exists;",
+        quote_literal(slot_name)
+    );
+    let slot_exists: bool = connection.fetch_one(query.as_str()).await?.get("exists");
+
+    let query = r#"
+        select (select count(*) from pg_replication_slots)
+            < current_setting('max_replication_slots')::int as has_free_slot;
+        "#;
+    let has_free_slot: bool = connection.fetch_one(query).await?.get("has_free_slot");
+
+    let slot_creatable =
+        !slot_exists && replication_privilege && wal_level == "logical" && has_free_slot;
+
+    let ok = replication_privilege
+        && wal_level == "logical"
+        && publication_exists != Some(false)
+        && (slot_exists || slot_creatable);
+
+    Ok(ConnectivityReport {
+        connected: true,
+        connection_error: None,
+        replication_privilege,
+        wal_level: Some(wal_level),
+        publication_exists,
+        slot_exists,
+        slot_creatable,
+        ok,
+    })
+}
@@ -1,8 +1,12 @@
+pub mod audit_log;
+pub mod connectivity;
 pub mod images;
+pub mod pipeline_tables;
 pub mod pipelines;
 pub mod publications;
 pub mod replicators;
 pub mod sinks;
+pub mod slots;
 pub mod sources;
 pub mod tables;
 pub mod tenants;
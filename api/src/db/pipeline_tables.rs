@@ -0,0 +1,131 @@
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+/// Replication status of a table, recorded by the pipeline's replicator
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineTableStatus {
+    Copying,
+    Copied,
+    Streaming,
+}
+
+impl PipelineTableStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PipelineTableStatus::Copying => "copying",
+            PipelineTableStatus::Copied => "copied",
+            PipelineTableStatus::Streaming => "streaming",
+        }
+    }
+
+    fn from_db(status: &str) -> Self {
+        match status {
+            "copying" => PipelineTableStatus::Copying,
+            "copied" => PipelineTableStatus::Copied,
+            "streaming" => PipelineTableStatus::Streaming,
+            // the status column's check constraint doesn't allow other values
+            status => unreachable!("invalid pipeline table status: {status}"),
+        }
+    }
+}
+
+pub struct PipelineTable {
+    pub table_id: i64,
+    pub schema_name: String,
+    pub table_name: String,
+    pub status: PipelineTableStatus,
+    pub lsn: Option<String>,
+    /// Set by [`request_pipeline_table_resync`] until the replicator picks up
+    /// the request. Not written by [`update_pipeline_table_status`].
+    pub resync_requested: bool,
+}
+
+/// Records the status of a table the same way the replicator does
+pub async fn update_pipeline_table_status(
+    pool: &PgPool,
+    pipeline_id: i64,
+    table: &PipelineTable,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into app.pipeline_tables (pipeline_id, table_id, schema_name, table_name, status, lsn)
+        values ($1, $2, $3, $4, $5, $6::text::pg_lsn)
+        on conflict (pipeline_id, table_id) do update
+        set schema_name = excluded.schema_name,
+            table_name = excluded.table_name,
+            status = excluded.status,
+            lsn = excluded.lsn,
+            updated_at = now()
+        "#,
+        pipeline_id,
+        table.table_id,
+        table.schema_name,
+        table.table_name,
+        table.status.as_str(),
+        table.lsn,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn read_pipeline_tables(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Vec<PipelineTable>, sqlx::Error> {
+    let mut records = sqlx::query!(
+        r#"
+        select pt.table_id, pt.schema_name, pt.table_name, pt.status, pt.lsn::text as lsn,
+            pt.resync_requested
+        from app.pipeline_tables pt
+        join app.pipelines p on pt.pipeline_id = p.id
+        where p.tenant_id = $1 and pt.pipeline_id = $2
+        order by pt.schema_name, pt.table_name
+        "#,
+        tenant_id,
+        pipeline_id,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .drain(..)
+        .map(|r| PipelineTable {
+            table_id: r.table_id,
+            schema_name: r.schema_name,
+            table_name: r.table_name,
+            status: PipelineTableStatus::from_db(&r.status),
+            lsn: r.lsn,
+            resync_requested: r.resync_requested,
+        })
+        .collect())
+}
+
+/// Asks the pipeline's replicator to copy a table again. Returns `None` if the
+/// pipeline has no such table.
+pub async fn request_pipeline_table_resync(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
+    table_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.pipeline_tables pt
+        set resync_requested = true
+        from app.pipelines p
+        where pt.pipeline_id = p.id and p.tenant_id = $1 and pt.pipeline_id = $2 and pt.table_id = $3
+        returning pt.table_id
+        "#,
+        tenant_id,
+        pipeline_id,
+        table_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|r| r.table_id))
+}
@@ -1,19 +1,113 @@
-use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
 
 use super::replicators::create_replicator_txn;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
 pub struct PipelineConfig {
     pub config: BatchConfig,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
 pub struct BatchConfig {
     /// maximum batch size in number of events
     pub max_size: usize,
 
     /// maximum duration, in seconds, to wait for a batch to fill
     pub max_fill_secs: u64,
+
+    /// number of workers which serialize rows for the sink in parallel
+    #[serde(default = "default_serialization_workers")]
+    pub serialization_workers: usize,
+
+    /// maximum number of table copy batches read but not yet written to the sink
+    #[serde(default = "default_max_in_flight_batches")]
+    pub max_in_flight_batches: usize,
+
+    /// flush a batch as soon as the source is idle instead of after
+    /// max_fill_secs, confirming its lsn promptly on low traffic sources
+    #[serde(default)]
+    pub flush_on_idle: bool,
+
+    /// maximum duration, in seconds, of a write to the sink before the
+    /// pipeline fails with a transient error. `None` waits indefinitely
+    #[serde(default)]
+    pub sink_write_timeout_secs: Option<u64>,
+
+    /// maximum duration, in seconds, to wait for the sink to be reachable
+    /// before the pipeline starts. `None` starts without waiting
+    #[serde(default)]
+    pub sink_ready_timeout_secs: Option<u64>,
+
+    /// adapt the batch size, up to max_size, to how long the sink takes to
+    /// write batches. `None` keeps batches at max_size
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveBatchConfig>,
+
+    /// max_size and max_fill_secs overrides of tables, by table id
+    #[serde(default)]
+    pub table_overrides: BTreeMap<u32, TableBatchConfig>,
+
+    /// kinds of changes applied to tables, by table id. Tables missing
+    /// have all their changes applied
+    #[serde(default)]
+    pub table_operations: BTreeMap<u32, TableOperationsConfig>,
+
+    /// skip records which fail conversion, logging them, instead of stopping
+    /// the pipeline. `None` stops the pipeline on the first such record
+    #[serde(default)]
+    pub dead_letter: Option<DeadLetterConfig>,
+}
+
+/// Bounds of an adaptive batch size, which grows while the sink writes batches
+/// faster than the target time and shrinks while it writes them slower
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+pub struct AdaptiveBatchConfig {
+    /// batch size in number of events to start with and not go below
+    pub min_size: usize,
+
+    /// duration, in milliseconds, batch writes to the sink should take
+    pub target_write_ms: u64,
+}
+
+/// Batch settings of a table which differ from those of its pipeline
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+pub struct TableBatchConfig {
+    /// maximum batch size in number of events
+    pub max_size: Option<usize>,
+
+    /// maximum duration, in seconds, to wait for a batch to fill
+    pub max_fill_secs: Option<u64>,
+}
+
+/// How many records failing conversion are skipped before the pipeline is
+/// stopped, since skipping every record would hide a problem to fix
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+pub struct DeadLetterConfig {
+    /// maximum number of records skipped within window_secs
+    pub max_dead_letters: usize,
+
+    /// duration, in seconds, of the window max_dead_letters applies to
+    pub window_secs: u64,
+}
+
+/// Kinds of changes applied to a table, the others are skipped
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
+pub struct TableOperationsConfig {
+    pub insert: bool,
+    pub update: bool,
+    pub delete: bool,
+    pub truncate: bool,
+}
+
+fn default_serialization_workers() -> usize {
+    1
+}
+
+fn default_max_in_flight_batches() -> usize {
+    1
 }
 
 pub struct Pipeline {
@@ -26,6 +120,7 @@ pub struct Pipeline {
     pub replicator_id: i64,
     pub publication_name: String,
     pub config: serde_json::Value,
+    pub deleted: bool,
 }
 
 pub async fn create_pipeline(
@@ -37,9 +132,32 @@ pub async fn create_pipeline(
     publication_name: String,
     config: &PipelineConfig,
 ) -> Result<i64, sqlx::Error> {
-    let config = serde_json::to_value(config).expect("failed to serialize config");
     let mut txn = pool.begin().await?;
-    let replicator_id = create_replicator_txn(&mut txn, tenant_id, image_id).await?;
+    let id = create_pipeline_txn(
+        &mut txn,
+        tenant_id,
+        source_id,
+        sink_id,
+        image_id,
+        publication_name,
+        config,
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(id)
+}
+
+pub async fn create_pipeline_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    source_id: i64,
+    sink_id: i64,
+    image_id: i64,
+    publication_name: String,
+    config: &PipelineConfig,
+) -> Result<i64, sqlx::Error> {
+    let config = serde_json::to_value(config).expect("failed to serialize config");
+    let replicator_id = create_replicator_txn(txn, tenant_id, image_id).await?;
     let record = sqlx::query!(
         r#"
         insert into app.pipelines (tenant_id, source_id, sink_id, replicator_id, publication_name, config)
@@ -53,13 +171,41 @@ pub async fn create_pipeline(
         publication_name,
         config
     )
-    .fetch_one(&mut *txn)
+    .fetch_one(&mut **txn)
     .await?;
-    txn.commit().await?;
 
     Ok(record.id)
 }
 
+/// Returns the id of the tenant's pipeline, which isn't deleted, from the
+/// source to the sink of the publication if there is one
+pub async fn find_pipeline_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    source_id: i64,
+    sink_id: i64,
+    publication_name: &str,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select id
+        from app.pipelines
+        where tenant_id = $1 and source_id = $2 and sink_id = $3 and publication_name = $4
+            and deleted_at is null
+        order by id
+        limit 1
+        "#,
+        tenant_id,
+        source_id,
+        sink_id,
+        publication_name
+    )
+    .fetch_optional(&mut **txn)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
 pub async fn read_pipeline(
     pool: &PgPool,
     tenant_id: &str,
@@ -75,11 +221,12 @@ pub async fn read_pipeline(
             sn.name as sink_name,
             replicator_id,
             publication_name,
-            p.config
+            p.config,
+            p.deleted_at is not null as "deleted!"
         from app.pipelines p
         join app.sources sr on p.source_id = sr.id
         join app.sinks sn on p.sink_id = sn.id
-        where p.tenant_id = $1 and p.id = $2
+        where p.tenant_id = $1 and p.id = $2 and p.deleted_at is null
         "#,
         tenant_id,
         pipeline_id,
@@ -97,6 +244,7 @@ pub async fn read_pipeline(
         replicator_id: r.replicator_id,
         publication_name: r.publication_name,
         config: r.config,
+        deleted: r.deleted,
     }))
 }
 
@@ -108,13 +256,37 @@ pub async fn update_pipeline(
     sink_id: i64,
     publication_name: String,
     config: &PipelineConfig,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let res = update_pipeline_txn(
+        &mut txn,
+        tenant_id,
+        pipeline_id,
+        source_id,
+        sink_id,
+        publication_name,
+        config,
+    )
+    .await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn update_pipeline_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    pipeline_id: i64,
+    source_id: i64,
+    sink_id: i64,
+    publication_name: String,
+    config: &PipelineConfig,
 ) -> Result<Option<i64>, sqlx::Error> {
     let config = serde_json::to_value(config).expect("failed to serialize config");
     let record = sqlx::query!(
         r#"
         update app.pipelines
         set source_id = $1, sink_id = $2, publication_name = $3, config = $4
-        where tenant_id = $5 and id = $6
+        where tenant_id = $5 and id = $6 and deleted_at is null
         returning id
         "#,
         source_id,
@@ -124,21 +296,55 @@ pub async fn update_pipeline(
         tenant_id,
         pipeline_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **txn)
     .await?;
 
     Ok(record.map(|r| r.id))
 }
 
+/// Marks a pipeline as deleted. It is kept so that it can be restored.
 pub async fn delete_pipeline(
     pool: &PgPool,
     tenant_id: &str,
     pipeline_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let res = delete_pipeline_txn(&mut txn, tenant_id, pipeline_id).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn delete_pipeline_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    pipeline_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.pipelines
+        set deleted_at = now()
+        where tenant_id = $1 and id = $2 and deleted_at is null
+        returning id
+        "#,
+        tenant_id,
+        pipeline_id
+    )
+    .fetch_optional(&mut **txn)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+pub async fn restore_pipeline(
+    pool: &PgPool,
+    tenant_id: &str,
+    pipeline_id: i64,
 ) -> Result<Option<i64>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        delete from app.pipelines
-        where tenant_id = $1 and id = $2
+        update app.pipelines
+        set deleted_at = null
+        where tenant_id = $1 and id = $2 and deleted_at is not null
         returning id
         "#,
         tenant_id,
@@ -150,9 +356,25 @@ pub async fn delete_pipeline(
     Ok(record.map(|r| r.id))
 }
 
+/// Returns the number of pipelines of all tenants, excluding deleted ones
+pub async fn count_pipelines(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select count(*) as "count!"
+        from app.pipelines
+        where deleted_at is null
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.count)
+}
+
 pub async fn read_all_pipelines(
     pool: &PgPool,
     tenant_id: &str,
+    include_deleted: bool,
 ) -> Result<Vec<Pipeline>, sqlx::Error> {
     let mut record = sqlx::query!(
         r#"
@@ -164,13 +386,15 @@ pub async fn read_all_pipelines(
             sn.name as sink_name,
             replicator_id,
             publication_name,
-            p.config
+            p.config,
+            p.deleted_at is not null as "deleted!"
         from app.pipelines p
         join app.sources sr on p.source_id = sr.id
         join app.sinks sn on p.sink_id = sn.id
-        where p.tenant_id = $1
+        where p.tenant_id = $1 and ($2 or p.deleted_at is null)
         "#,
         tenant_id,
+        include_deleted,
     )
     .fetch_all(pool)
     .await?;
@@ -187,6 +411,7 @@ pub async fn read_all_pipelines(
             replicator_id: r.replicator_id,
             publication_name: r.publication_name,
             config: r.config,
+            deleted: r.deleted,
         })
         .collect())
 }
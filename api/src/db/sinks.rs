@@ -1,15 +1,16 @@
 use aws_lc_rs::{aead::Nonce, error::Unspecified};
 use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use std::{
     fmt::{Debug, Formatter},
     str::{from_utf8, Utf8Error},
 };
 use thiserror::Error;
+use utoipa::ToSchema;
 
-use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey};
+use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionAlgorithm, EncryptionKey};
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
 pub enum SinkConfig {
     BigQuery {
         /// BigQuery project id
@@ -24,6 +25,18 @@ pub enum SinkConfig {
 }
 
 impl SinkConfig {
+    /// The config as json with its service account key redacted like
+    /// [`Debug`] does, for records which must not hold secrets
+    pub fn to_redacted_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("failed to serialize config");
+        match self {
+            SinkConfig::BigQuery { .. } => {
+                value["BigQuery"]["service_account_key"] = serde_json::json!("REDACTED");
+            }
+        }
+        value
+    }
+
     fn into_db_config(self, encryption_key: &EncryptionKey) -> Result<SinkConfigInDb, Unspecified> {
         let SinkConfig::BigQuery {
             project_id,
@@ -31,12 +44,12 @@ impl SinkConfig {
             service_account_key,
         } = self;
 
-        let (encrypted_sa_key, nonce) =
-            encrypt(service_account_key.as_bytes(), &encryption_key.key)?;
+        let (encrypted_sa_key, nonce) = encrypt(service_account_key.as_bytes(), encryption_key)?;
         let encrypted_encoded_sa_key = BASE64_STANDARD.encode(encrypted_sa_key);
         let encoded_nonce = BASE64_STANDARD.encode(nonce.as_ref());
         let encrypted_sa_key = EncryptedValue {
             id: encryption_key.id,
+            algorithm: EncryptionAlgorithm::CURRENT,
             nonce: encoded_nonce,
             value: encrypted_encoded_sa_key,
         };
@@ -101,7 +114,8 @@ impl SinkConfigInDb {
         let decrypted_sa_key = from_utf8(&decrypt(
             encrypted_sa_key_bytes,
             nonce,
-            &encryption_key.key,
+            encrypted_sa_key.algorithm,
+            encryption_key,
         )?)?
         .to_string();
 
@@ -139,6 +153,7 @@ pub struct Sink {
     pub tenant_id: String,
     pub name: String,
     pub config: SinkConfig,
+    pub deleted: bool,
 }
 
 pub async fn create_sink(
@@ -147,6 +162,19 @@ pub async fn create_sink(
     name: &str,
     config: SinkConfig,
     encryption_key: &EncryptionKey,
+) -> Result<i64, SinksDbError> {
+    let mut txn = pool.begin().await?;
+    let res = create_sink_txn(&mut txn, tenant_id, name, config, encryption_key).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn create_sink_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    name: &str,
+    config: SinkConfig,
+    encryption_key: &EncryptionKey,
 ) -> Result<i64, SinksDbError> {
     let db_config = config.into_db_config(encryption_key)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
@@ -160,7 +188,7 @@ pub async fn create_sink(
         name,
         db_config
     )
-    .fetch_one(pool)
+    .fetch_one(&mut **txn)
     .await?;
 
     Ok(record.id)
@@ -174,9 +202,9 @@ pub async fn read_sink(
 ) -> Result<Option<Sink>, SinksDbError> {
     let record = sqlx::query!(
         r#"
-        select id, tenant_id, name, config
+        select id, tenant_id, name, config, deleted_at is not null as "deleted!"
         from app.sinks
-        where tenant_id = $1 and id = $2
+        where tenant_id = $1 and id = $2 and deleted_at is null
         "#,
         tenant_id,
         sink_id,
@@ -193,6 +221,7 @@ pub async fn read_sink(
                 tenant_id: r.tenant_id,
                 name: r.name,
                 config,
+                deleted: r.deleted,
             };
             Ok::<Sink, SinksDbError>(source)
         })
@@ -207,6 +236,20 @@ pub async fn update_sink(
     sink_id: i64,
     config: SinkConfig,
     encryption_key: &EncryptionKey,
+) -> Result<Option<i64>, SinksDbError> {
+    let mut txn = pool.begin().await?;
+    let res = update_sink_txn(&mut txn, tenant_id, name, sink_id, config, encryption_key).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn update_sink_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    name: &str,
+    sink_id: i64,
+    config: SinkConfig,
+    encryption_key: &EncryptionKey,
 ) -> Result<Option<i64>, SinksDbError> {
     let db_config = config.into_db_config(encryption_key)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
@@ -214,7 +257,7 @@ pub async fn update_sink(
         r#"
         update app.sinks
         set config = $1, name = $2
-        where tenant_id = $3 and id = $4
+        where tenant_id = $3 and id = $4 and deleted_at is null
         returning id
         "#,
         db_config,
@@ -222,21 +265,87 @@ pub async fn update_sink(
         tenant_id,
         sink_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **txn)
     .await?;
 
     Ok(record.map(|r| r.id))
 }
 
+/// Marks a sink as deleted. It is kept so that it can be restored.
 pub async fn delete_sink(
     pool: &PgPool,
     tenant_id: &str,
     sink_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let res = delete_sink_txn(&mut txn, tenant_id, sink_id).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn delete_sink_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.sinks
+        set deleted_at = now()
+        where tenant_id = $1 and id = $2 and deleted_at is null
+        returning id
+        "#,
+        tenant_id,
+        sink_id
+    )
+    .fetch_optional(&mut **txn)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// Whether a pipeline which isn't deleted still uses the sink.
+pub async fn sink_in_use(
+    pool: &PgPool,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let res = sink_in_use_txn(&mut txn, tenant_id, sink_id).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn sink_in_use_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    sink_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select exists (select id
+        from app.pipelines
+        where tenant_id = $1 and sink_id = $2 and deleted_at is null) as "exists!"
+        "#,
+        tenant_id,
+        sink_id
+    )
+    .fetch_one(&mut **txn)
+    .await?;
+
+    Ok(record.exists)
+}
+
+pub async fn restore_sink(
+    pool: &PgPool,
+    tenant_id: &str,
+    sink_id: i64,
 ) -> Result<Option<i64>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        delete from app.sinks
-        where tenant_id = $1 and id = $2
+        update app.sinks
+        set deleted_at = null
+        where tenant_id = $1 and id = $2 and deleted_at is not null
         returning id
         "#,
         tenant_id,
@@ -251,15 +360,17 @@ pub async fn delete_sink(
 pub async fn read_all_sinks(
     pool: &PgPool,
     tenant_id: &str,
+    include_deleted: bool,
     encryption_key: &EncryptionKey,
 ) -> Result<Vec<Sink>, SinksDbError> {
     let records = sqlx::query!(
         r#"
-        select id, tenant_id, name, config
+        select id, tenant_id, name, config, deleted_at is not null as "deleted!"
         from app.sinks
-        where tenant_id = $1
+        where tenant_id = $1 and ($2 or deleted_at is null)
         "#,
         tenant_id,
+        include_deleted,
     )
     .fetch_all(pool)
     .await?;
@@ -273,6 +384,7 @@ pub async fn read_all_sinks(
             tenant_id: record.tenant_id,
             name: record.name,
             config,
+            deleted: record.deleted,
         };
         sinks.push(source);
     }
@@ -289,7 +401,7 @@ pub async fn sink_exists(
         r#"
         select exists (select id
         from app.sinks
-        where tenant_id = $1 and id = $2) as "exists!"
+        where tenant_id = $1 and id = $2 and deleted_at is null) as "exists!"
         "#,
         tenant_id,
         sink_id,
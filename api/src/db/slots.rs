@@ -0,0 +1,42 @@
+use pg_escape::quote_literal;
+use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection, Row};
+
+/// What [`drop_slot`] did with a slot
+#[derive(Debug, PartialEq, Eq)]
+pub enum DropSlotOutcome {
+    /// The slot was dropped and the WAL it retained released
+    Dropped,
+    /// There is no slot with the name
+    Missing,
+    /// The slot wasn't dropped because a connection, like a running
+    /// replicator's, is streaming from it
+    InUse,
+}
+
+/// Drops the replication slot named `slot_name` unless it is in use
+pub async fn drop_slot(
+    slot_name: &str,
+    options: &PgConnectOptions,
+) -> Result<DropSlotOutcome, sqlx::Error> {
+    let mut connection = PgConnection::connect_with(options).await?;
+
+    let query = format!(
+        "select active from pg_replication_slots where slot_name = {};",
+        quote_literal(slot_name)
+    );
+    let Some(row) = connection.fetch_optional(query.as_str()).await? else {
+        return Ok(DropSlotOutcome::Missing);
+    };
+    let active: bool = row.get("active");
+    if active {
+        return Ok(DropSlotOutcome::InUse);
+    }
+
+    let query = format!(
+        "select pg_drop_replication_slot({});",
+        quote_literal(slot_name)
+    );
+    connection.execute(query.as_str()).await?;
+
+    Ok(DropSlotOutcome::Dropped)
+}
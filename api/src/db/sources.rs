@@ -1,21 +1,27 @@
 use aws_lc_rs::{aead::Nonce, error::Unspecified};
 use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
+use pg_escape::quote_identifier;
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
-    PgPool,
+    PgPool, Postgres, Transaction,
 };
 use std::{
     fmt::{Debug, Formatter},
     str::{from_utf8, Utf8Error},
 };
 use thiserror::Error;
+use utoipa::ToSchema;
 
-use crate::encryption::{decrypt, encrypt, EncryptedValue, EncryptionKey};
+use crate::{
+    encryption::{decrypt, encrypt, EncryptedValue, EncryptionAlgorithm, EncryptionKey},
+    replicator_config::{AuthMethod, OutputPlugin, SlotOptions},
+};
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 enum SourceConfigInDb {
     Postgres {
-        /// Host on which Postgres is running
+        /// Host on which Postgres is running, or the directory of its Unix
+        /// domain socket if it starts with a `/`
         host: String,
 
         /// Port on which Postgres is running
@@ -32,9 +38,81 @@ enum SourceConfigInDb {
 
         /// Postgres slot name
         slot_name: String,
+
+        /// How the replication slot is obtained when replication starts
+        #[serde(default)]
+        slot_options: SlotOptions,
+
+        /// Postgres statement_timeout, in milliseconds. 0 disables the timeout
+        #[serde(default)]
+        statement_timeout_ms: u64,
+
+        /// Duration, in seconds, the connection can be idle before a tcp keepalive is sent
+        #[serde(default = "default_tcp_keepalives_idle_secs")]
+        tcp_keepalives_idle_secs: u64,
+
+        /// Timeout, in seconds, to connect to Postgres
+        #[serde(default)]
+        connect_timeout_secs: Option<u64>,
+
+        /// Replicate the partitions of a partitioned table as rows of the
+        /// partitioned table instead of as separate tables
+        #[serde(default)]
+        route_partitions_to_parent: bool,
+
+        /// Number of rows read from Postgres ahead of the sink while copying
+        /// tables. 0 disables reading ahead
+        #[serde(default)]
+        copy_prefetch_rows: usize,
+
+        /// How to authenticate to Postgres
+        #[serde(default)]
+        auth: AuthMethod,
+
+        /// Logical decoding output plugin changes are streamed with
+        #[serde(default)]
+        output_plugin: OutputPlugin,
+
+        /// Stream large transactions before they commit, with pgoutput on
+        /// Postgres 14 or later
+        #[serde(default)]
+        stream_in_progress_transactions: bool,
+
+        /// Copy each table in its own transaction instead of all in one, which
+        /// holds no snapshot longer than a table's copy but gives up the
+        /// consistency between tables and may apply changes to later copied
+        /// tables twice
+        #[serde(default)]
+        snapshot_per_table: bool,
+
+        /// Name the replicator's connections report in `pg_stat_activity` and
+        /// `pg_stat_replication`, `pg_replicate-<pipeline id>` if not set
+        #[serde(default)]
+        application_name: Option<String>,
+
+        /// `host:port` of a SOCKS5 proxy, like an `ssh -D` tunnel to a bastion,
+        /// which the replicator connects to Postgres through. The api's own
+        /// connections to the source, like the one validating it, are direct
+        #[serde(default)]
+        socks5_proxy: Option<String>,
+
+        /// Role the replicator's and the api's connections switch to right
+        /// after connecting, the login user's own if not set
+        #[serde(default)]
+        role: Option<String>,
+
+        /// Schemas the connections look up unqualified names in, set right
+        /// after connecting and switching to `role`. `$user` is the role's
+        /// own schema. The server's `search_path` if not set
+        #[serde(default)]
+        search_path: Option<Vec<String>>,
     },
 }
 
+fn default_tcp_keepalives_idle_secs() -> u64 {
+    30
+}
+
 impl SourceConfigInDb {
     fn into_config(self, encryption_key: &EncryptionKey) -> Result<SourceConfig, SourcesDbError> {
         let SourceConfigInDb::Postgres {
@@ -44,6 +122,20 @@ impl SourceConfigInDb {
             username,
             password: encrypted_password,
             slot_name,
+            slot_options,
+            statement_timeout_ms,
+            tcp_keepalives_idle_secs,
+            connect_timeout_secs,
+            route_partitions_to_parent,
+            copy_prefetch_rows,
+            auth,
+            output_plugin,
+            stream_in_progress_transactions,
+            snapshot_per_table,
+            application_name,
+            socks5_proxy,
+            role,
+            search_path,
         } = self;
 
         let decrypted_password = encrypted_password
@@ -61,7 +153,8 @@ impl SourceConfigInDb {
                 let decrypted_password = from_utf8(&decrypt(
                     encrypted_password_bytes,
                     nonce,
-                    &encryption_key.key,
+                    encrypted_password.algorithm,
+                    encryption_key,
                 )?)?
                 .to_string();
                 Ok(decrypted_password)
@@ -75,14 +168,29 @@ impl SourceConfigInDb {
             username,
             password: decrypted_password,
             slot_name,
+            slot_options,
+            statement_timeout_ms,
+            tcp_keepalives_idle_secs,
+            connect_timeout_secs,
+            route_partitions_to_parent,
+            copy_prefetch_rows,
+            auth,
+            output_plugin,
+            stream_in_progress_transactions,
+            snapshot_per_table,
+            application_name,
+            socks5_proxy,
+            role,
+            search_path,
         })
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema)]
 pub enum SourceConfig {
     Postgres {
-        /// Host on which Postgres is running
+        /// Host on which Postgres is running, or the directory of its Unix
+        /// domain socket if it starts with a `/`
         host: String,
 
         /// Port on which Postgres is running
@@ -99,10 +207,98 @@ pub enum SourceConfig {
 
         /// Postgres slot name
         slot_name: String,
+
+        /// How the replication slot is obtained when replication starts
+        #[serde(default)]
+        slot_options: SlotOptions,
+
+        /// Postgres statement_timeout, in milliseconds. 0 disables the timeout
+        #[serde(default)]
+        statement_timeout_ms: u64,
+
+        /// Duration, in seconds, the connection can be idle before a tcp keepalive is sent
+        #[serde(default = "default_tcp_keepalives_idle_secs")]
+        tcp_keepalives_idle_secs: u64,
+
+        /// Timeout, in seconds, to connect to Postgres
+        #[serde(default)]
+        connect_timeout_secs: Option<u64>,
+
+        /// Replicate the partitions of a partitioned table as rows of the
+        /// partitioned table instead of as separate tables
+        #[serde(default)]
+        route_partitions_to_parent: bool,
+
+        /// Number of rows read from Postgres ahead of the sink while copying
+        /// tables. 0 disables reading ahead
+        #[serde(default)]
+        copy_prefetch_rows: usize,
+
+        /// How to authenticate to Postgres
+        #[serde(default)]
+        auth: AuthMethod,
+
+        /// Logical decoding output plugin changes are streamed with
+        #[serde(default)]
+        output_plugin: OutputPlugin,
+
+        /// Stream large transactions before they commit, with pgoutput on
+        /// Postgres 14 or later
+        #[serde(default)]
+        stream_in_progress_transactions: bool,
+
+        /// Copy each table in its own transaction instead of all in one, which
+        /// holds no snapshot longer than a table's copy but gives up the
+        /// consistency between tables and may apply changes to later copied
+        /// tables twice
+        #[serde(default)]
+        snapshot_per_table: bool,
+
+        /// Name the replicator's connections report in `pg_stat_activity` and
+        /// `pg_stat_replication`, `pg_replicate-<pipeline id>` if not set
+        #[serde(default)]
+        application_name: Option<String>,
+
+        /// `host:port` of a SOCKS5 proxy, like an `ssh -D` tunnel to a bastion,
+        /// which the replicator connects to Postgres through. The api's own
+        /// connections to the source, like the one validating it, are direct
+        #[serde(default)]
+        socks5_proxy: Option<String>,
+
+        /// Role the replicator's and the api's connections switch to right
+        /// after connecting, the login user's own if not set
+        #[serde(default)]
+        role: Option<String>,
+
+        /// Schemas the connections look up unqualified names in, set right
+        /// after connecting and switching to `role`. `$user` is the role's
+        /// own schema. The server's `search_path` if not set
+        #[serde(default)]
+        search_path: Option<Vec<String>>,
     },
 }
 
 impl SourceConfig {
+    /// The config as json with its password redacted like [`Debug`] does, for
+    /// records which must not hold secrets
+    pub fn to_redacted_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("failed to serialize config");
+        match self {
+            SourceConfig::Postgres { password, .. } => {
+                if password.is_some() {
+                    value["Postgres"]["password"] = serde_json::json!("REDACTED");
+                }
+            }
+        }
+        value
+    }
+
+    pub fn slot_name(&self) -> &str {
+        match self {
+            SourceConfig::Postgres { slot_name, .. } => slot_name,
+        }
+    }
+
     pub fn connect_options(&self) -> PgConnectOptions {
         match self {
             SourceConfig::Postgres {
@@ -112,6 +308,20 @@ impl SourceConfig {
                 username,
                 password,
                 slot_name: _,
+                slot_options: _,
+                statement_timeout_ms,
+                tcp_keepalives_idle_secs: _,
+                connect_timeout_secs: _,
+                route_partitions_to_parent: _,
+                copy_prefetch_rows: _,
+                auth: _,
+                output_plugin: _,
+                stream_in_progress_transactions: _,
+                snapshot_per_table: _,
+                application_name,
+                socks5_proxy: _,
+                role,
+                search_path,
             } => {
                 let ssl_mode = PgSslMode::Prefer;
 
@@ -120,7 +330,26 @@ impl SourceConfig {
                     .port(*port)
                     .database(name)
                     .username(username)
-                    .ssl_mode(ssl_mode);
+                    .ssl_mode(ssl_mode)
+                    .options([("statement_timeout", statement_timeout_ms)]);
+                let options = match application_name {
+                    Some(application_name) => options.application_name(application_name),
+                    None => options,
+                };
+                let options = match role {
+                    Some(role) => options.options([("role", role)]),
+                    None => options,
+                };
+                let options = match search_path {
+                    Some(search_path) => {
+                        let schemas: Vec<_> = search_path
+                            .iter()
+                            .map(|schema| quote_identifier(schema))
+                            .collect();
+                        options.options([("search_path", schemas.join(","))])
+                    }
+                    None => options,
+                };
                 if let Some(password) = password {
                     options.password(password)
                 } else {
@@ -141,16 +370,30 @@ impl SourceConfig {
             username,
             password,
             slot_name,
+            slot_options,
+            statement_timeout_ms,
+            tcp_keepalives_idle_secs,
+            connect_timeout_secs,
+            route_partitions_to_parent,
+            copy_prefetch_rows,
+            auth,
+            output_plugin,
+            stream_in_progress_transactions,
+            snapshot_per_table,
+            application_name,
+            socks5_proxy,
+            role,
+            search_path,
         } = self;
 
         let encrypted_password = password
             .map(|password| {
-                let (encrypted_password, nonce) =
-                    encrypt(password.as_bytes(), &encryption_key.key)?;
+                let (encrypted_password, nonce) = encrypt(password.as_bytes(), encryption_key)?;
                 let encrypted_encoded_password = BASE64_STANDARD.encode(encrypted_password);
                 let encoded_nonce = BASE64_STANDARD.encode(nonce.as_ref());
                 Ok::<EncryptedValue, Unspecified>(EncryptedValue {
                     id: encryption_key.id,
+                    algorithm: EncryptionAlgorithm::CURRENT,
                     nonce: encoded_nonce,
                     value: encrypted_encoded_password,
                 })
@@ -164,6 +407,20 @@ impl SourceConfig {
             username,
             password: encrypted_password,
             slot_name,
+            slot_options,
+            statement_timeout_ms,
+            tcp_keepalives_idle_secs,
+            connect_timeout_secs,
+            route_partitions_to_parent,
+            copy_prefetch_rows,
+            auth,
+            output_plugin,
+            stream_in_progress_transactions,
+            snapshot_per_table,
+            application_name,
+            socks5_proxy,
+            role,
+            search_path,
         })
     }
 }
@@ -178,6 +435,20 @@ impl Debug for SourceConfig {
                 username,
                 password: _,
                 slot_name,
+                slot_options,
+                statement_timeout_ms,
+                tcp_keepalives_idle_secs,
+                connect_timeout_secs,
+                route_partitions_to_parent,
+                copy_prefetch_rows,
+                auth,
+                output_plugin,
+                stream_in_progress_transactions,
+                snapshot_per_table,
+                application_name,
+                socks5_proxy,
+                role,
+                search_path,
             } => f
                 .debug_struct("Postgres")
                 .field("host", host)
@@ -186,6 +457,23 @@ impl Debug for SourceConfig {
                 .field("username", username)
                 .field("password", &"REDACTED")
                 .field("slot_name", slot_name)
+                .field("slot_options", slot_options)
+                .field("statement_timeout_ms", statement_timeout_ms)
+                .field("tcp_keepalives_idle_secs", tcp_keepalives_idle_secs)
+                .field("connect_timeout_secs", connect_timeout_secs)
+                .field("route_partitions_to_parent", route_partitions_to_parent)
+                .field("copy_prefetch_rows", copy_prefetch_rows)
+                .field("auth", auth)
+                .field("output_plugin", output_plugin)
+                .field(
+                    "stream_in_progress_transactions",
+                    stream_in_progress_transactions,
+                )
+                .field("snapshot_per_table", snapshot_per_table)
+                .field("application_name", application_name)
+                .field("socks5_proxy", socks5_proxy)
+                .field("role", role)
+                .field("search_path", search_path)
                 .finish(),
         }
     }
@@ -196,6 +484,7 @@ pub struct Source {
     pub tenant_id: String,
     pub name: String,
     pub config: SourceConfig,
+    pub deleted: bool,
 }
 
 #[derive(Debug, Error)]
@@ -225,6 +514,19 @@ pub async fn create_source(
     name: &str,
     config: SourceConfig,
     encryption_key: &EncryptionKey,
+) -> Result<i64, SourcesDbError> {
+    let mut txn = pool.begin().await?;
+    let res = create_source_txn(&mut txn, tenant_id, name, config, encryption_key).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn create_source_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    name: &str,
+    config: SourceConfig,
+    encryption_key: &EncryptionKey,
 ) -> Result<i64, SourcesDbError> {
     let db_config = config.into_db_config(encryption_key)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
@@ -238,7 +540,7 @@ pub async fn create_source(
         name,
         db_config
     )
-    .fetch_one(pool)
+    .fetch_one(&mut **txn)
     .await?;
 
     Ok(record.id)
@@ -252,9 +554,9 @@ pub async fn read_source(
 ) -> Result<Option<Source>, SourcesDbError> {
     let record = sqlx::query!(
         r#"
-        select id, tenant_id, name, config
+        select id, tenant_id, name, config, deleted_at is not null as "deleted!"
         from app.sources
-        where tenant_id = $1 and id = $2
+        where tenant_id = $1 and id = $2 and deleted_at is null
         "#,
         tenant_id,
         source_id,
@@ -271,6 +573,7 @@ pub async fn read_source(
                 tenant_id: r.tenant_id,
                 name: r.name,
                 config,
+                deleted: r.deleted,
             };
             Ok::<Source, SourcesDbError>(source)
         })
@@ -285,6 +588,21 @@ pub async fn update_source(
     source_id: i64,
     config: SourceConfig,
     encryption_key: &EncryptionKey,
+) -> Result<Option<i64>, SourcesDbError> {
+    let mut txn = pool.begin().await?;
+    let res =
+        update_source_txn(&mut txn, tenant_id, name, source_id, config, encryption_key).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn update_source_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    name: &str,
+    source_id: i64,
+    config: SourceConfig,
+    encryption_key: &EncryptionKey,
 ) -> Result<Option<i64>, SourcesDbError> {
     let db_config = config.into_db_config(encryption_key)?;
     let db_config = serde_json::to_value(db_config).expect("failed to serialize config");
@@ -292,7 +610,7 @@ pub async fn update_source(
         r#"
         update app.sources
         set config = $1, name = $2
-        where tenant_id = $3 and id = $4
+        where tenant_id = $3 and id = $4 and deleted_at is null
         returning id
         "#,
         db_config,
@@ -300,21 +618,87 @@ pub async fn update_source(
         tenant_id,
         source_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **txn)
     .await?;
 
     Ok(record.map(|r| r.id))
 }
 
+/// Marks a source as deleted. It is kept so that it can be restored.
 pub async fn delete_source(
     pool: &PgPool,
     tenant_id: &str,
     source_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let res = delete_source_txn(&mut txn, tenant_id, source_id).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn delete_source_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        update app.sources
+        set deleted_at = now()
+        where tenant_id = $1 and id = $2 and deleted_at is null
+        returning id
+        "#,
+        tenant_id,
+        source_id
+    )
+    .fetch_optional(&mut **txn)
+    .await?;
+
+    Ok(record.map(|r| r.id))
+}
+
+/// Whether a pipeline which isn't deleted still uses the source.
+pub async fn source_in_use(
+    pool: &PgPool,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let mut txn = pool.begin().await?;
+    let res = source_in_use_txn(&mut txn, tenant_id, source_id).await?;
+    txn.commit().await?;
+    Ok(res)
+}
+
+pub async fn source_in_use_txn(
+    txn: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    source_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        select exists (select id
+        from app.pipelines
+        where tenant_id = $1 and source_id = $2 and deleted_at is null) as "exists!"
+        "#,
+        tenant_id,
+        source_id
+    )
+    .fetch_one(&mut **txn)
+    .await?;
+
+    Ok(record.exists)
+}
+
+pub async fn restore_source(
+    pool: &PgPool,
+    tenant_id: &str,
+    source_id: i64,
 ) -> Result<Option<i64>, sqlx::Error> {
     let record = sqlx::query!(
         r#"
-        delete from app.sources
-        where tenant_id = $1 and id = $2
+        update app.sources
+        set deleted_at = null
+        where tenant_id = $1 and id = $2 and deleted_at is not null
         returning id
         "#,
         tenant_id,
@@ -329,15 +713,17 @@ pub async fn delete_source(
 pub async fn read_all_sources(
     pool: &PgPool,
     tenant_id: &str,
+    include_deleted: bool,
     encryption_key: &EncryptionKey,
 ) -> Result<Vec<Source>, SourcesDbError> {
     let records = sqlx::query!(
         r#"
-        select id, tenant_id, name, config
+        select id, tenant_id, name, config, deleted_at is not null as "deleted!"
         from app.sources
-        where tenant_id = $1
+        where tenant_id = $1 and ($2 or deleted_at is null)
         "#,
         tenant_id,
+        include_deleted,
     )
     .fetch_all(pool)
     .await?;
@@ -351,6 +737,7 @@ pub async fn read_all_sources(
             tenant_id: record.tenant_id,
             name: record.name,
             config,
+            deleted: record.deleted,
         };
         sources.push(source);
     }
@@ -367,7 +754,7 @@ pub async fn source_exists(
         r#"
         select exists (select id
         from app.sources
-        where tenant_id = $1 and id = $2) as "exists!"
+        where tenant_id = $1 and id = $2 and deleted_at is null) as "exists!"
         "#,
         tenant_id,
         source_id
@@ -377,3 +764,109 @@ pub async fn source_exists(
 
     Ok(record.exists)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        db::sources::SourceConfig,
+        replicator_config::{AuthMethod, OutputPlugin, SlotOptions},
+    };
+
+    #[test]
+    pub fn connect_options_apply_statement_timeout_test() {
+        let config = SourceConfig::Postgres {
+            host: "localhost".to_string(),
+            port: 5432,
+            name: "postgres".to_string(),
+            username: "postgres".to_string(),
+            password: None,
+            slot_name: "slot".to_string(),
+            slot_options: SlotOptions::CreateIfMissing,
+            statement_timeout_ms: 5000,
+            tcp_keepalives_idle_secs: 30,
+            connect_timeout_secs: None,
+            route_partitions_to_parent: false,
+            copy_prefetch_rows: 0,
+            auth: AuthMethod::Any,
+            output_plugin: OutputPlugin::Pgoutput,
+            stream_in_progress_transactions: false,
+            snapshot_per_table: false,
+            application_name: None,
+            socks5_proxy: None,
+            role: None,
+            search_path: None,
+        };
+        let options = config.connect_options();
+        assert_eq!(options.get_options(), Some("-c statement_timeout=5000"));
+    }
+
+    #[test]
+    pub fn connect_options_apply_role_and_search_path_test() {
+        let config = SourceConfig::Postgres {
+            host: "localhost".to_string(),
+            port: 5432,
+            name: "postgres".to_string(),
+            username: "postgres".to_string(),
+            password: None,
+            slot_name: "slot".to_string(),
+            slot_options: SlotOptions::CreateIfMissing,
+            statement_timeout_ms: 0,
+            tcp_keepalives_idle_secs: 30,
+            connect_timeout_secs: None,
+            route_partitions_to_parent: false,
+            copy_prefetch_rows: 0,
+            auth: AuthMethod::Any,
+            output_plugin: OutputPlugin::Pgoutput,
+            stream_in_progress_transactions: false,
+            snapshot_per_table: false,
+            application_name: None,
+            socks5_proxy: None,
+            role: Some("reporting".to_string()),
+            search_path: Some(vec!["inventory".to_string(), "$user".to_string()]),
+        };
+        let options = config.connect_options();
+        assert_eq!(
+            options.get_options(),
+            Some(r#"-c statement_timeout=0 -c role=reporting -c search_path=inventory,"$user""#)
+        );
+    }
+
+    #[test]
+    pub fn deserialize_config_without_connection_options_test() {
+        let config = r#"{
+            "Postgres": {
+                "host": "localhost",
+                "port": 5432,
+                "name": "postgres",
+                "username": "postgres",
+                "password": null,
+                "slot_name": "slot"
+            }
+        }"#;
+        let actual = serde_json::from_str::<SourceConfig>(config);
+        let expected = SourceConfig::Postgres {
+            host: "localhost".to_string(),
+            port: 5432,
+            name: "postgres".to_string(),
+            username: "postgres".to_string(),
+            password: None,
+            slot_name: "slot".to_string(),
+            slot_options: SlotOptions::CreateIfMissing,
+            statement_timeout_ms: 0,
+            tcp_keepalives_idle_secs: 30,
+            connect_timeout_secs: None,
+            route_partitions_to_parent: false,
+            copy_prefetch_rows: 0,
+            auth: AuthMethod::Any,
+            output_plugin: OutputPlugin::Pgoutput,
+            stream_in_progress_transactions: false,
+            snapshot_per_table: false,
+            application_name: None,
+            socks5_proxy: None,
+            role: None,
+            search_path: None,
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+}
@@ -1,5 +1,7 @@
+use pg_escape::quote_literal;
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection, Row};
+use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize)]
 pub struct Table {
@@ -7,6 +9,20 @@ pub struct Table {
     pub name: String,
 }
 
+/// Information to plan the replication of a table with
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TableInfo {
+    pub schema: String,
+    pub name: String,
+    /// Estimate from the last vacuum or analyze, null if the table never had one
+    pub estimated_row_count: Option<i64>,
+    /// Size on disk, in bytes, including indexes and toast data
+    pub size_bytes: i64,
+    /// True if updates and deletes to the table can be replicated, i.e. its
+    /// replica identity is full or it is the default and the table has a primary key
+    pub has_replica_identity: bool,
+}
+
 pub async fn get_tables(options: &PgConnectOptions) -> Result<Vec<Table>, sqlx::Error> {
     let mut connection = PgConnection::connect_with(options).await?;
     let query = r#"
@@ -35,3 +51,61 @@ pub async fn get_tables(options: &PgConnectOptions) -> Result<Vec<Table>, sqlx::
         .collect();
     Ok(tables)
 }
+
+/// Returns the info of every table in the publication named `publication_name`,
+/// or `None` if there is no such publication
+pub async fn get_publication_table_infos(
+    publication_name: &str,
+    options: &PgConnectOptions,
+) -> Result<Option<Vec<TableInfo>>, sqlx::Error> {
+    let mut query = String::from(
+        r#"
+        select p.pubname,
+            n.nspname as "schema?",
+            c.relname as "name?",
+            c.reltuples::int8 as "estimated_row_count?",
+            pg_total_relation_size(c.oid) as "size_bytes?",
+            case c.relreplident
+                when 'f' then true
+                when 'd' then exists (
+                    select 1 from pg_index i where i.indrelid = c.oid and i.indisprimary
+                )
+                else false
+            end as "has_replica_identity?"
+        from pg_publication p
+        left join pg_publication_tables pt on p.pubname = pt.pubname
+        left join pg_namespace n on n.nspname = pt.schemaname
+        left join pg_class c on c.relnamespace = n.oid and c.relname = pt.tablename
+        where p.pubname =
+        "#,
+    );
+
+    query.push_str(&quote_literal(publication_name));
+    query.push_str(r#" order by "schema?", "name?";"#);
+
+    let mut connection = PgConnection::connect_with(options).await?;
+
+    let rows = connection.fetch_all(query.as_str()).await?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+
+    let mut table_infos = vec![];
+    for row in rows {
+        let schema: Option<String> = row.get("schema?");
+        let name: Option<String> = row.get("name?");
+        if let (Some(schema), Some(name)) = (schema, name) {
+            // reltuples is -1 for tables which were never vacuumed or analyzed
+            let estimated_row_count: i64 = row.get("estimated_row_count?");
+            table_infos.push(TableInfo {
+                schema,
+                name,
+                estimated_row_count: (estimated_row_count >= 0).then_some(estimated_row_count),
+                size_bytes: row.get("size_bytes?"),
+                has_replica_identity: row.get("has_replica_identity?"),
+            });
+        }
+    }
+
+    Ok(Some(table_infos))
+}
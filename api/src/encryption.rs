@@ -9,28 +9,59 @@ pub struct EncryptionKey {
     pub key: RandomizedNonceKey,
 }
 
+/// The algorithm an [`EncryptedValue`] was encrypted with. Values stored
+/// before the algorithm was recorded are [`EncryptionAlgorithm::Aes256GcmV1`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionAlgorithm {
+    /// AES-256-GCM without associated data
+    #[default]
+    Aes256GcmV1,
+    /// AES-256-GCM with the key id as associated data, so that a value
+    /// doesn't decrypt once its key id is changed
+    Aes256GcmV2,
+}
+
+impl EncryptionAlgorithm {
+    /// The algorithm new values are encrypted with. Values encrypted with
+    /// [`EncryptionAlgorithm::Aes256GcmV2`] are decrypted, but new ones are
+    /// only written with it once every running API instance can read them.
+    pub const CURRENT: EncryptionAlgorithm = EncryptionAlgorithm::Aes256GcmV1;
+
+    fn aad(self, key_id: u32) -> Aad<Vec<u8>> {
+        match self {
+            EncryptionAlgorithm::Aes256GcmV1 => Aad::from(vec![]),
+            EncryptionAlgorithm::Aes256GcmV2 => Aad::from(key_id.to_be_bytes().to_vec()),
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct EncryptedValue {
     pub id: u32,
+    #[serde(default)]
+    pub algorithm: EncryptionAlgorithm,
     pub nonce: String,
     pub value: String,
 }
 
-pub fn encrypt(
-    plaintext: &[u8],
-    key: &RandomizedNonceKey,
-) -> Result<(Vec<u8>, Nonce), Unspecified> {
+/// Encrypts `plaintext` with [`EncryptionAlgorithm::CURRENT`]
+pub fn encrypt(plaintext: &[u8], key: &EncryptionKey) -> Result<(Vec<u8>, Nonce), Unspecified> {
     let mut in_out = plaintext.to_vec();
-    let nonce = key.seal_in_place_append_tag(Aad::empty(), &mut in_out)?;
+    let aad = EncryptionAlgorithm::CURRENT.aad(key.id);
+    let nonce = key.key.seal_in_place_append_tag(aad, &mut in_out)?;
     Ok((in_out, nonce))
 }
 
 pub fn decrypt(
     mut ciphertext: Vec<u8>,
     nonce: Nonce,
-    key: &RandomizedNonceKey,
+    algorithm: EncryptionAlgorithm,
+    key: &EncryptionKey,
 ) -> Result<Vec<u8>, Unspecified> {
-    let plaintext = key.open_in_place(nonce, Aad::empty(), &mut ciphertext)?;
+    let plaintext = key
+        .key
+        .open_in_place(nonce, algorithm.aad(key.id), &mut ciphertext)?;
     Ok(plaintext.to_vec())
 }
 
@@ -41,3 +72,85 @@ pub fn generate_random_key<const T: usize>() -> Result<RandomizedNonceKey, Unspe
     let key = RandomizedNonceKey::new(&AES_256_GCM, &key_bytes)?;
     Ok(key)
 }
+
+#[cfg(test)]
+mod tests {
+    use aws_lc_rs::aead::{Aad, Nonce};
+    use base64::{prelude::BASE64_STANDARD, Engine};
+
+    use super::{
+        decrypt, encrypt, generate_random_key, EncryptedValue, EncryptionAlgorithm, EncryptionKey,
+    };
+
+    fn encryption_key(id: u32) -> EncryptionKey {
+        EncryptionKey {
+            id,
+            key: generate_random_key::<32>().unwrap(),
+        }
+    }
+
+    fn decrypt_value(value: EncryptedValue, key: &EncryptionKey) -> Result<Vec<u8>, ()> {
+        let ciphertext = BASE64_STANDARD.decode(value.value).unwrap();
+        let nonce = Nonce::try_assume_unique_for_key(&BASE64_STANDARD.decode(value.nonce).unwrap())
+            .unwrap();
+        decrypt(ciphertext, nonce, value.algorithm, key).map_err(|_| ())
+    }
+
+    #[test]
+    fn v1_value_without_an_algorithm_decrypts() {
+        let key = encryption_key(0);
+        let mut ciphertext = b"secret".to_vec();
+        let nonce = key
+            .key
+            .seal_in_place_append_tag(Aad::empty(), &mut ciphertext)
+            .unwrap();
+        let json = format!(
+            r#"{{"id":0,"nonce":"{}","value":"{}"}}"#,
+            BASE64_STANDARD.encode(nonce.as_ref()),
+            BASE64_STANDARD.encode(ciphertext),
+        );
+
+        let value: EncryptedValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(value.algorithm, EncryptionAlgorithm::Aes256GcmV1);
+        assert_eq!(decrypt_value(value, &key).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn current_value_round_trips() {
+        let key = encryption_key(3);
+        let (ciphertext, nonce) = encrypt(b"secret", &key).unwrap();
+        let value = EncryptedValue {
+            id: key.id,
+            algorithm: EncryptionAlgorithm::CURRENT,
+            nonce: BASE64_STANDARD.encode(nonce.as_ref()),
+            value: BASE64_STANDARD.encode(ciphertext),
+        };
+
+        let json = serde_json::to_string(&value).unwrap();
+        let value: EncryptedValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(value.algorithm, EncryptionAlgorithm::Aes256GcmV1);
+        assert_eq!(decrypt_value(value, &key).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn v2_value_decrypts_only_as_v2() {
+        let key = encryption_key(3);
+        let mut ciphertext = b"secret".to_vec();
+        let nonce = key
+            .key
+            .seal_in_place_append_tag(Aad::from(3u32.to_be_bytes()), &mut ciphertext)
+            .unwrap();
+        let value = |algorithm| EncryptedValue {
+            id: key.id,
+            algorithm,
+            nonce: BASE64_STANDARD.encode(nonce.as_ref()),
+            value: BASE64_STANDARD.encode(&ciphertext),
+        };
+
+        assert_eq!(
+            decrypt_value(value(EncryptionAlgorithm::Aes256GcmV2), &key).unwrap(),
+            b"secret"
+        );
+        assert!(decrypt_value(value(EncryptionAlgorithm::Aes256GcmV1), &key).is_err());
+    }
+}
@@ -3,6 +3,7 @@ pub mod configuration;
 pub mod db;
 pub mod encryption;
 pub mod k8s_client;
+pub mod metrics;
 pub mod replicator_config;
 pub mod routes;
 pub mod startup;
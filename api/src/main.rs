@@ -4,14 +4,21 @@ use anyhow::anyhow;
 use api::{
     configuration::{get_settings, DatabaseSettings, Settings},
     startup::Application,
-    telemetry::{get_subscriber, init_subscriber},
+    telemetry::{get_subscriber, init_subscriber, init_tracer},
 };
 use tracing::info;
 use tracing_log::log::error;
 
 #[actix_web::main]
 pub async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("api".into(), "info".into(), std::io::stdout);
+    // Only the server's settings configure telemetry, reading them fails for
+    // the migrate command if they're incomplete
+    let tracer = get_settings::<'_, Settings>()
+        .ok()
+        .and_then(|settings| settings.telemetry)
+        .map(|telemetry| init_tracer("api", &telemetry.otlp_endpoint))
+        .transpose()?;
+    let subscriber = get_subscriber("api".into(), "info".into(), std::io::stdout, tracer);
     init_subscriber(subscriber);
     let mut args = env::args();
 
@@ -33,6 +40,7 @@ pub async fn main() -> anyhow::Result<()> {
         info!("{configuration}");
         let application = Application::build(configuration.clone()).await?;
         application.run_until_stopped().await?;
+        opentelemetry::global::shutdown_tracer_provider();
     } else {
         let message = "invalid command line arguments";
         error!("{message}");
@@ -0,0 +1,46 @@
+use std::{sync::OnceLock, time::Duration};
+
+use actix_web::http::{Method, StatusCode};
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+pub const HTTP_REQUESTS_TOTAL: &str = "api_http_requests_total";
+pub const HTTP_REQUEST_DURATION_SECONDS: &str = "api_http_request_duration_seconds";
+pub const PIPELINES: &str = "api_pipelines";
+pub const DB_POOL_CONNECTIONS: &str = "api_db_pool_connections";
+pub const DB_POOL_IDLE_CONNECTIONS: &str = "api_db_pool_idle_connections";
+
+const HTTP_REQUEST_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Returns the handle to render metrics with. The recorder is global, so it is
+/// installed only once even if the server is started multiple times, e.g. in tests.
+pub fn prometheus_handle() -> PrometheusHandle {
+    PROMETHEUS_HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(
+                    Matcher::Full(HTTP_REQUEST_DURATION_SECONDS.to_string()),
+                    HTTP_REQUEST_DURATION_BUCKETS,
+                )
+                .expect("failed to set http request duration buckets")
+                .install_recorder()
+                .expect("failed to install prometheus recorder")
+        })
+        .clone()
+}
+
+/// Records a served request. `path` is the matched route pattern, e.g.
+/// `/v1/pipelines/{pipeline_id}`, to keep the number of label values bounded.
+pub fn record_request(method: &Method, path: String, status: StatusCode, duration: Duration) {
+    let labels = [
+        ("method", method.to_string()),
+        ("path", path),
+        ("status", status.as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION_SECONDS, &labels).record(duration.as_secs_f64());
+}
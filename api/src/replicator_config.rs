@@ -1,9 +1,12 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
+
+use utoipa::ToSchema;
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub enum SourceConfig {
     Postgres {
-        /// Host on which Postgres is running
+        /// Host on which Postgres is running, or the directory of its Unix
+        /// domain socket if it starts with a `/`
         host: String,
 
         /// Port on which Postgres is running
@@ -18,11 +21,127 @@ pub enum SourceConfig {
         /// Postgres slot name
         slot_name: String,
 
+        /// How the replication slot is obtained when replication starts
+        #[serde(default)]
+        slot_options: SlotOptions,
+
         /// Postgres publication name
         publication: String,
+
+        /// Postgres statement_timeout, in milliseconds. 0 disables the timeout
+        #[serde(default)]
+        statement_timeout_ms: u64,
+
+        /// Duration, in seconds, the connection can be idle before a tcp keepalive is sent
+        #[serde(default = "default_tcp_keepalives_idle_secs")]
+        tcp_keepalives_idle_secs: u64,
+
+        /// Timeout, in seconds, to connect to Postgres
+        #[serde(default)]
+        connect_timeout_secs: Option<u64>,
+
+        /// Replicate the partitions of a partitioned table as rows of the
+        /// partitioned table instead of as separate tables
+        #[serde(default)]
+        route_partitions_to_parent: bool,
+
+        /// Number of rows read from Postgres ahead of the sink while copying
+        /// tables. 0 disables reading ahead
+        #[serde(default)]
+        copy_prefetch_rows: usize,
+
+        /// How to authenticate to Postgres
+        #[serde(default)]
+        auth: AuthMethod,
+
+        /// Logical decoding output plugin changes are streamed with
+        #[serde(default)]
+        output_plugin: OutputPlugin,
+
+        /// Stream large transactions before they commit, with pgoutput on
+        /// Postgres 14 or later
+        #[serde(default)]
+        stream_in_progress_transactions: bool,
+
+        /// Copy each table in its own transaction instead of all in one, which
+        /// holds no snapshot longer than a table's copy but gives up the
+        /// consistency between tables and may apply changes to later copied
+        /// tables twice
+        #[serde(default)]
+        snapshot_per_table: bool,
+
+        /// Name the connections report in `pg_stat_activity` and
+        /// `pg_stat_replication`
+        #[serde(default)]
+        application_name: Option<String>,
+
+        /// `host:port` of a SOCKS5 proxy to connect to Postgres through
+        #[serde(default)]
+        socks5_proxy: Option<String>,
+
+        /// Role the connections switch to right after connecting, the login
+        /// user's own if not set
+        #[serde(default)]
+        role: Option<String>,
+
+        /// Schemas the connections look up unqualified names in, set right
+        /// after connecting and switching to `role`. `$user` is the role's
+        /// own schema. The server's `search_path` if not set
+        #[serde(default)]
+        search_path: Option<Vec<String>>,
     },
 }
 
+/// How the replication slot is obtained when replication starts
+#[derive(
+    Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema,
+)]
+pub enum SlotOptions {
+    /// Use an existing slot, failing if it doesn't exist
+    UseExisting,
+
+    /// Use an existing slot or create it if it doesn't exist
+    #[default]
+    CreateIfMissing,
+
+    /// Create a temporary slot which is dropped when the replicator disconnects
+    Temporary,
+}
+
+/// How to authenticate to Postgres
+#[derive(
+    Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema,
+)]
+pub enum AuthMethod {
+    /// Whichever method the server asks for, using channel binding with
+    /// SCRAM-SHA-256 if the connection allows it
+    #[default]
+    Any,
+
+    /// Only SCRAM-SHA-256 with channel binding, which resists MITM attacks.
+    /// Connecting fails if the server offers a weaker method. Channel binding
+    /// needs a TLS connection, which the source doesn't make yet.
+    ScramSha256ChannelBinding,
+}
+
+/// Logical decoding output plugin changes are streamed with
+#[derive(
+    Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, ToSchema,
+)]
+pub enum OutputPlugin {
+    /// Postgres' built in plugin, which streams the changes of the publication
+    #[default]
+    Pgoutput,
+
+    /// The wal2json extension, for servers where publications can't be used.
+    /// The publication still names the tables which are replicated.
+    Wal2Json,
+}
+
+fn default_tcp_keepalives_idle_secs() -> u64 {
+    30
+}
+
 impl Debug for SourceConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -32,7 +151,21 @@ impl Debug for SourceConfig {
                 name,
                 username,
                 slot_name,
+                slot_options,
                 publication,
+                statement_timeout_ms,
+                tcp_keepalives_idle_secs,
+                connect_timeout_secs,
+                route_partitions_to_parent,
+                copy_prefetch_rows,
+                auth,
+                output_plugin,
+                stream_in_progress_transactions,
+                snapshot_per_table,
+                application_name,
+                socks5_proxy,
+                role,
+                search_path,
             } => f
                 .debug_struct("Postgres")
                 .field("host", host)
@@ -40,7 +173,24 @@ impl Debug for SourceConfig {
                 .field("name", name)
                 .field("username", username)
                 .field("slot_name", slot_name)
+                .field("slot_options", slot_options)
                 .field("publication", publication)
+                .field("statement_timeout_ms", statement_timeout_ms)
+                .field("tcp_keepalives_idle_secs", tcp_keepalives_idle_secs)
+                .field("connect_timeout_secs", connect_timeout_secs)
+                .field("route_partitions_to_parent", route_partitions_to_parent)
+                .field("copy_prefetch_rows", copy_prefetch_rows)
+                .field("auth", auth)
+                .field("output_plugin", output_plugin)
+                .field(
+                    "stream_in_progress_transactions",
+                    stream_in_progress_transactions,
+                )
+                .field("snapshot_per_table", snapshot_per_table)
+                .field("application_name", application_name)
+                .field("socks5_proxy", socks5_proxy)
+                .field("role", role)
+                .field("search_path", search_path)
                 .finish(),
         }
     }
@@ -79,6 +229,107 @@ pub struct BatchConfig {
 
     /// maximum duration, in seconds, to wait for a batch to fill
     pub max_fill_secs: u64,
+
+    /// number of workers which serialize rows for the sink in parallel
+    #[serde(default = "default_serialization_workers")]
+    pub serialization_workers: usize,
+
+    /// maximum number of table copy batches read but not yet written to the sink
+    #[serde(default = "default_max_in_flight_batches")]
+    pub max_in_flight_batches: usize,
+
+    /// flush a batch as soon as the source is idle instead of after
+    /// max_fill_secs, confirming its lsn promptly on low traffic sources
+    #[serde(default)]
+    pub flush_on_idle: bool,
+
+    /// maximum duration, in seconds, of a write to the sink before the
+    /// pipeline fails with a transient error. `None` waits indefinitely
+    #[serde(default)]
+    pub sink_write_timeout_secs: Option<u64>,
+
+    /// maximum duration, in seconds, to wait for the sink to be reachable
+    /// before the pipeline starts. `None` starts without waiting
+    #[serde(default)]
+    pub sink_ready_timeout_secs: Option<u64>,
+
+    /// adapt the batch size, up to max_size, to how long the sink takes to
+    /// write batches. `None` keeps batches at max_size
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveBatchConfig>,
+
+    /// max_size and max_fill_secs overrides of tables, by table id
+    #[serde(default)]
+    pub table_overrides: BTreeMap<u32, TableBatchConfig>,
+
+    /// kinds of changes applied to tables, by table id. Tables missing
+    /// have all their changes applied
+    #[serde(default)]
+    pub table_operations: BTreeMap<u32, TableOperationsConfig>,
+
+    /// skip records which fail conversion, logging them, instead of stopping
+    /// the pipeline. `None` stops the pipeline on the first such record
+    #[serde(default)]
+    pub dead_letter: Option<DeadLetterConfig>,
+}
+
+/// Bounds of an adaptive batch size, which grows while the sink writes batches
+/// faster than the target time and shrinks while it writes them slower
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct AdaptiveBatchConfig {
+    /// batch size in number of events to start with and not go below
+    pub min_size: usize,
+
+    /// duration, in milliseconds, batch writes to the sink should take
+    pub target_write_ms: u64,
+}
+
+/// Batch settings of a table which differ from those of its pipeline
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableBatchConfig {
+    /// maximum batch size in number of events
+    pub max_size: Option<usize>,
+
+    /// maximum duration, in seconds, to wait for a batch to fill
+    pub max_fill_secs: Option<u64>,
+}
+
+/// How many records failing conversion are skipped before the pipeline is
+/// stopped, since skipping every record would hide a problem to fix
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct DeadLetterConfig {
+    /// maximum number of records skipped within window_secs
+    pub max_dead_letters: usize,
+
+    /// duration, in seconds, of the window max_dead_letters applies to
+    pub window_secs: u64,
+}
+
+/// Kinds of changes applied to a table, the others are skipped
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TableOperationsConfig {
+    pub insert: bool,
+    pub update: bool,
+    pub delete: bool,
+    pub truncate: bool,
+}
+
+fn default_serialization_workers() -> usize {
+    1
+}
+
+fn default_max_in_flight_batches() -> usize {
+    1
+}
+
+/// Where, and as part of which trace, a replicator exports its spans
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct TelemetryConfig {
+    /// OTLP gRPC endpoint spans are exported to
+    pub otlp_endpoint: String,
+
+    /// W3C `traceparent` of the request which started the replicator
+    pub trace_parent: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -86,11 +337,18 @@ pub struct Config {
     pub source: SourceConfig,
     pub sink: SinkConfig,
     pub batch: BatchConfig,
+    /// Spans are not exported if not set
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::replicator_config::{BatchConfig, Config, SinkConfig, SourceConfig};
+    use std::collections::BTreeMap;
+
+    use crate::replicator_config::{
+        AuthMethod, BatchConfig, Config, OutputPlugin, SinkConfig, SlotOptions, SourceConfig,
+    };
 
     #[test]
     pub fn deserialize_settings_test() {
@@ -102,7 +360,11 @@ mod tests {
                     "name": "postgres",
                     "username": "postgres",
                     "slot_name": "replicator_slot",
-                    "publication": "replicator_publication"
+                    "slot_options": "Temporary",
+                    "publication": "replicator_publication",
+                    "statement_timeout_ms": 0,
+                    "tcp_keepalives_idle_secs": 30,
+                    "connect_timeout_secs": 10
                 }
             },
             "sink": {
@@ -124,7 +386,21 @@ mod tests {
                 name: "postgres".to_string(),
                 username: "postgres".to_string(),
                 slot_name: "replicator_slot".to_string(),
+                slot_options: SlotOptions::Temporary,
                 publication: "replicator_publication".to_string(),
+                statement_timeout_ms: 0,
+                tcp_keepalives_idle_secs: 30,
+                connect_timeout_secs: Some(10),
+                route_partitions_to_parent: false,
+                copy_prefetch_rows: 0,
+                auth: AuthMethod::Any,
+                output_plugin: OutputPlugin::Pgoutput,
+                stream_in_progress_transactions: false,
+                snapshot_per_table: false,
+                application_name: None,
+                socks5_proxy: None,
+                role: None,
+                search_path: None,
             },
             sink: SinkConfig::BigQuery {
                 project_id: "project-id".to_string(),
@@ -133,7 +409,17 @@ mod tests {
             batch: BatchConfig {
                 max_size: 1000,
                 max_fill_secs: 10,
+                serialization_workers: 1,
+                max_in_flight_batches: 1,
+                flush_on_idle: false,
+                sink_write_timeout_secs: None,
+                sink_ready_timeout_secs: None,
+                adaptive: None,
+                table_overrides: BTreeMap::new(),
+                table_operations: BTreeMap::new(),
+                dead_letter: None,
             },
+            telemetry: None,
         };
         assert!(actual.is_ok());
         assert_eq!(expected, actual.unwrap());
@@ -148,7 +434,21 @@ mod tests {
                 name: "postgres".to_string(),
                 username: "postgres".to_string(),
                 slot_name: "replicator_slot".to_string(),
+                slot_options: SlotOptions::Temporary,
                 publication: "replicator_publication".to_string(),
+                statement_timeout_ms: 0,
+                tcp_keepalives_idle_secs: 30,
+                connect_timeout_secs: Some(10),
+                route_partitions_to_parent: false,
+                copy_prefetch_rows: 0,
+                auth: AuthMethod::Any,
+                output_plugin: OutputPlugin::Pgoutput,
+                stream_in_progress_transactions: false,
+                snapshot_per_table: false,
+                application_name: None,
+                socks5_proxy: None,
+                role: None,
+                search_path: None,
             },
             sink: SinkConfig::BigQuery {
                 project_id: "project-id".to_string(),
@@ -157,9 +457,19 @@ mod tests {
             batch: BatchConfig {
                 max_size: 1000,
                 max_fill_secs: 10,
+                serialization_workers: 4,
+                max_in_flight_batches: 2,
+                flush_on_idle: false,
+                sink_write_timeout_secs: None,
+                sink_ready_timeout_secs: None,
+                adaptive: None,
+                table_overrides: BTreeMap::new(),
+                table_operations: BTreeMap::new(),
+                dead_letter: None,
             },
+            telemetry: None,
         };
-        let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","slot_name":"replicator_slot","publication":"replicator_publication"}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id"}},"batch":{"max_size":1000,"max_fill_secs":10}}"#;
+        let expected = r#"{"source":{"Postgres":{"host":"localhost","port":5432,"name":"postgres","username":"postgres","slot_name":"replicator_slot","slot_options":"Temporary","publication":"replicator_publication","statement_timeout_ms":0,"tcp_keepalives_idle_secs":30,"connect_timeout_secs":10,"route_partitions_to_parent":false,"copy_prefetch_rows":0,"auth":"Any","output_plugin":"Pgoutput","stream_in_progress_transactions":false,"snapshot_per_table":false,"application_name":null,"socks5_proxy":null,"role":null,"search_path":null}},"sink":{"BigQuery":{"project_id":"project-id","dataset_id":"dataset-id"}},"batch":{"max_size":1000,"max_fill_secs":10,"serialization_workers":4,"max_in_flight_batches":2,"flush_on_idle":false,"sink_write_timeout_secs":null,"sink_ready_timeout_secs":null,"adaptive":null,"table_overrides":{},"table_operations":{},"dead_letter":null},"telemetry":null}"#;
         let actual = serde_json::to_string(&actual);
         assert!(actual.is_ok());
         assert_eq!(expected, actual.unwrap());
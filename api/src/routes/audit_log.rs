@@ -0,0 +1,107 @@
+use actix_web::{
+    get,
+    http::StatusCode,
+    web::{Data, Json},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{
+        self,
+        audit_log::{AuditAction, AuditResourceType},
+    },
+    routes::extract_tenant_id,
+};
+
+use super::{ErrorCode, ErrorMessage, TenantIdError};
+
+#[derive(Debug, Error)]
+enum AuditLogError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("tenant id error: {0}")]
+    TenantId(#[from] TenantIdError),
+}
+
+impl AuditLogError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            AuditLogError::DatabaseError(_) => "internal server error".to_string(),
+            // Every other message is ok, as they do not divulge sensitive information
+            e => e.to_string(),
+        }
+    }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            AuditLogError::DatabaseError(_) => ErrorCode::Internal,
+            AuditLogError::TenantId(_) => ErrorCode::Validation,
+        }
+    }
+}
+
+impl ResponseError for AuditLogError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuditLogError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditLogError::TenantId(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ErrorMessage::new(self.code(), self.to_message()).response(self.status_code())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GetAuditRecordResponse {
+    id: i64,
+    /// Who made the change, as named by the request's `actor` header
+    actor: String,
+    action: AuditAction,
+    resource_type: AuditResourceType,
+    resource_id: i64,
+    /// The item before the change, with secrets redacted. `null` if it was created
+    #[schema(value_type = Object)]
+    before: Option<serde_json::Value>,
+    /// The item after the change, with secrets redacted. `null` if it was deleted
+    #[schema(value_type = Object)]
+    after: Option<serde_json::Value>,
+    created_at: String,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    responses(
+        (status = 200, description = "Return the audit trail of the tenant's sources, sinks and pipelines, oldest change first", body = Vec<GetAuditRecordResponse>),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/audit_log")]
+pub async fn read_audit_log(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+) -> Result<impl Responder, AuditLogError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let records = db::audit_log::read_audit_log(&pool, tenant_id)
+        .await?
+        .into_iter()
+        .map(|record| GetAuditRecordResponse {
+            id: record.id,
+            actor: record.actor,
+            action: record.action,
+            resource_type: record.resource_type,
+            resource_id: record.resource_id,
+            before: record.before,
+            after: record.after,
+            created_at: record.created_at,
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(records))
+}
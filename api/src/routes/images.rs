@@ -1,18 +1,19 @@
 use actix_web::{
     delete, get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     post,
     web::{Data, Json, Path},
     HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
 
 use crate::db;
 
-use super::ErrorMessage;
+use super::{ErrorCode, ErrorMessage};
 
 #[derive(Debug, Error)]
 enum ImageError {
@@ -32,6 +33,20 @@ impl ImageError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            ImageError::DatabaseError(_) => ErrorCode::Internal,
+            ImageError::ImageNotFound(_) => ErrorCode::NotFound,
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            ImageError::ImageNotFound(id) => json!({ "image_id": id }),
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for ImageError {
@@ -43,14 +58,9 @@ impl ResponseError for ImageError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
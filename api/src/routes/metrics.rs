@@ -0,0 +1,66 @@
+use actix_web::{get, http::StatusCode, web::Data, HttpResponse, Responder, ResponseError};
+use metrics::gauge;
+use metrics_exporter_prometheus::PrometheusHandle;
+use sqlx::PgPool;
+use thiserror::Error;
+
+use crate::{
+    db,
+    metrics::{DB_POOL_CONNECTIONS, DB_POOL_IDLE_CONNECTIONS, PIPELINES},
+    routes::{ErrorCode, ErrorMessage},
+};
+
+#[derive(Debug, Error)]
+enum MetricsError {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl MetricsError {
+    fn to_message(&self) -> String {
+        match self {
+            // Do not expose internal database details in error messages
+            MetricsError::DatabaseError(_) => "internal server error".to_string(),
+        }
+    }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            MetricsError::DatabaseError(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ResponseError for MetricsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MetricsError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ErrorMessage::new(self.code(), self.to_message()).response(self.status_code())
+    }
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text exposition format"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/metrics")]
+pub async fn metrics(
+    pool: Data<PgPool>,
+    prometheus_handle: Data<PrometheusHandle>,
+) -> Result<impl Responder, MetricsError> {
+    // Gauges which are read from the database are refreshed on every scrape
+    let pipelines = db::pipelines::count_pipelines(&pool).await?;
+    gauge!(PIPELINES).set(pipelines as f64);
+    gauge!(DB_POOL_CONNECTIONS).set(pool.size() as f64);
+    gauge!(DB_POOL_IDLE_CONNECTIONS).set(pool.num_idle() as f64);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(prometheus_handle.render()))
+}
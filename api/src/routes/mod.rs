@@ -1,17 +1,95 @@
-use actix_web::HttpRequest;
-use serde::Serialize;
+use actix_web::{
+    error::InternalError,
+    http::{header::ContentType, StatusCode},
+    HttpRequest, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
+use utoipa::ToSchema;
 
+pub mod audit_log;
 pub mod health_check;
 pub mod images;
+pub mod metrics;
+pub mod openapi;
 pub mod pipelines;
 pub mod sinks;
 pub mod sources;
 pub mod tenants;
 
-#[derive(Serialize)]
+/// Lets clients tell errors apart without parsing their messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request refers to another tenant's source or sink
+    CrossTenant,
+    /// The requested item doesn't exist
+    NotFound,
+    /// The request is malformed or misses something
+    Validation,
+    /// The request conflicts with an existing item
+    Conflict,
+    /// Something went wrong on the server
+    Internal,
+}
+
+/// Body of every error response
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorMessage {
-    pub error: String,
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Error specific fields, like the id of an item which wasn't found
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+}
+
+impl ErrorMessage {
+    pub fn new(code: ErrorCode, message: String) -> ErrorMessage {
+        ErrorMessage {
+            error: ErrorBody {
+                code,
+                message,
+                details: json!({}),
+            },
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> ErrorMessage {
+        self.error.details = details;
+        self
+    }
+
+    pub fn response(&self, status_code: StatusCode) -> HttpResponse {
+        let body = serde_json::to_string(self).expect("failed to serialize error message");
+        HttpResponse::build(status_code)
+            .insert_header(ContentType::json())
+            .body(body)
+    }
+}
+
+/// Renders errors of a request's json body, path or query extractors as an
+/// [`ErrorMessage`] instead of actix's plain text
+pub fn extractor_error_handler<E>(err: E, _req: &HttpRequest) -> actix_web::Error
+where
+    E: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    let response =
+        ErrorMessage::new(ErrorCode::Validation, err.to_string()).response(StatusCode::BAD_REQUEST);
+    InternalError::from_response(err, response).into()
+}
+
+/// Query parameters accepted by the endpoints listing pipelines, sources and sinks
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// Also list soft deleted items when true
+    #[serde(default)]
+    pub include_deleted: bool,
 }
 
 #[derive(Debug, Error)]
@@ -33,3 +111,13 @@ fn extract_tenant_id(req: &HttpRequest) -> Result<&str, TenantIdError> {
         .map_err(|_| TenantIdError::TenantIdIllFormed)?;
     Ok(tenant_id)
 }
+
+/// Returns who makes the request, for the audit log. Callers share the api
+/// key, so they name the user they act for in the `actor` header. Requests
+/// without it are recorded as made by `unknown`.
+fn extract_actor(req: &HttpRequest) -> &str {
+    req.headers()
+        .get("actor")
+        .and_then(|actor| actor.to_str().ok())
+        .unwrap_or("unknown")
+}
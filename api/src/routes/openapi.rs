@@ -0,0 +1,12 @@
+use actix_web::{get, web::Data, HttpResponse, Responder};
+use utoipa::openapi::OpenApi;
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "OpenAPI spec of the api"),
+    )
+)]
+#[get("/openapi.json")]
+pub async fn openapi_json(openapi: Data<OpenApi>) -> impl Responder {
+    HttpResponse::Ok().json(openapi.as_ref())
+}
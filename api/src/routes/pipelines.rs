@@ -2,32 +2,38 @@ use std::sync::Arc;
 
 use actix_web::{
     delete, get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
 
 use crate::{
+    configuration::TelemetrySettings,
     db::{
         self,
+        audit_log::{pipeline_state, AuditAction, AuditEntry, AuditResourceType},
         images::Image,
+        pipeline_tables::PipelineTableStatus,
         pipelines::{Pipeline, PipelineConfig},
         replicators::Replicator,
         sinks::{sink_exists, Sink, SinkConfig, SinksDbError},
+        slots::DropSlotOutcome,
         sources::{source_exists, Source, SourceConfig, SourcesDbError},
     },
     encryption::EncryptionKey,
     k8s_client::{HttpK8sClient, K8sClient, K8sError, PodPhase},
     replicator_config,
-    routes::extract_tenant_id,
+    routes::{extract_actor, extract_tenant_id},
+    telemetry::current_trace_parent,
 };
 
-use super::{ErrorMessage, TenantIdError};
+use super::{ErrorCode, ErrorMessage, ListQuery, TenantIdError};
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Secrets {
@@ -43,6 +49,9 @@ enum PipelineError {
     #[error("pipeline with id {0} not found")]
     PipelineNotFound(i64),
 
+    #[error("table with id {table_id} of pipeline with id {pipeline_id} not found")]
+    PipelineTableNotFound { pipeline_id: i64, table_id: i64 },
+
     #[error("source with id {0} not found")]
     SourceNotFound(i64),
 
@@ -72,6 +81,18 @@ enum PipelineError {
 
     #[error("sinks db error: {0}")]
     SinksDb(#[from] SinksDbError),
+
+    #[error("slot {0} is in use, stop the pipeline before deleting it")]
+    SlotInUse(String),
+
+    #[error("publication {0} not found")]
+    PublicationNotFound(String),
+
+    #[error("pipeline at index {index} of the batch is invalid: {error}")]
+    InvalidBatchItem {
+        index: usize,
+        error: Box<PipelineError>,
+    },
 }
 
 impl PipelineError {
@@ -83,6 +104,51 @@ impl PipelineError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            PipelineError::DatabaseError(_)
+            | PipelineError::InvalidConfig(_)
+            | PipelineError::ReplicatorNotFound(_)
+            | PipelineError::ImageNotFound(_)
+            | PipelineError::NoDefaultImageFound
+            | PipelineError::SourcesDb(_)
+            | PipelineError::SinksDb(_)
+            | PipelineError::K8sError(_) => ErrorCode::Internal,
+            PipelineError::PipelineNotFound(_) | PipelineError::PipelineTableNotFound { .. } => {
+                ErrorCode::NotFound
+            }
+            PipelineError::TenantId(_) | PipelineError::PublicationNotFound(_) => {
+                ErrorCode::Validation
+            }
+            PipelineError::SlotInUse(_) => ErrorCode::Conflict,
+            // The source or sink exists, if at all, for another tenant
+            PipelineError::SourceNotFound(_) | PipelineError::SinkNotFound(_) => {
+                ErrorCode::CrossTenant
+            }
+            PipelineError::InvalidBatchItem { error, .. } => error.code(),
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            PipelineError::PipelineNotFound(id) => json!({ "pipeline_id": id }),
+            PipelineError::PipelineTableNotFound {
+                pipeline_id,
+                table_id,
+            } => json!({ "pipeline_id": pipeline_id, "table_id": table_id }),
+            PipelineError::SourceNotFound(id) => json!({ "source_id": id }),
+            PipelineError::SinkNotFound(id) => json!({ "sink_id": id }),
+            PipelineError::SlotInUse(slot_name) => json!({ "slot_name": slot_name }),
+            PipelineError::PublicationNotFound(name) => json!({ "publication_name": name }),
+            PipelineError::InvalidBatchItem { index, error } => {
+                let mut details = error.details();
+                details["index"] = json!(index);
+                details
+            }
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for PipelineError {
@@ -96,22 +162,22 @@ impl ResponseError for PipelineError {
             | PipelineError::SourcesDb(_)
             | PipelineError::SinksDb(_)
             | PipelineError::K8sError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            PipelineError::PipelineNotFound(_) => StatusCode::NOT_FOUND,
+            PipelineError::PipelineNotFound(_) | PipelineError::PipelineTableNotFound { .. } => {
+                StatusCode::NOT_FOUND
+            }
             PipelineError::TenantId(_)
             | PipelineError::SourceNotFound(_)
-            | PipelineError::SinkNotFound(_) => StatusCode::BAD_REQUEST,
+            | PipelineError::SinkNotFound(_)
+            | PipelineError::PublicationNotFound(_) => StatusCode::BAD_REQUEST,
+            PipelineError::SlotInUse(_) => StatusCode::CONFLICT,
+            PipelineError::InvalidBatchItem { error, .. } => error.status_code(),
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
@@ -128,6 +194,14 @@ pub struct PostPipelineResponse {
     id: i64,
 }
 
+/// Result of a pipeline of a batch, in the order of the batch
+#[derive(Serialize, ToSchema)]
+pub struct PostPipelinesBatchItemResponse {
+    id: i64,
+    /// false if the same pipeline existed already and was left as is
+    created: bool,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct GetPipelineResponse {
     id: i64,
@@ -139,6 +213,7 @@ pub struct GetPipelineResponse {
     replicator_id: i64,
     publication_name: String,
     config: PipelineConfig,
+    deleted: bool,
 }
 
 #[utoipa::path(
@@ -171,8 +246,15 @@ pub async fn create_pipeline(
         .await?
         .ok_or(PipelineError::NoDefaultImageFound)?;
 
-    let id = db::pipelines::create_pipeline(
-        &pool,
+    let after = pipeline_state(
+        pipeline.source_id,
+        pipeline.sink_id,
+        &pipeline.publication_name,
+        &serde_json::to_value(&config)?,
+    );
+    let mut txn = pool.begin().await?;
+    let id = db::pipelines::create_pipeline_txn(
+        &mut txn,
         tenant_id,
         pipeline.source_id,
         pipeline.sink_id,
@@ -181,11 +263,137 @@ pub async fn create_pipeline(
         &config,
     )
     .await?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Create,
+        resource_type: AuditResourceType::Pipeline,
+        resource_id: id,
+        before: None,
+        after: Some(after),
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
 
     let response = PostPipelineResponse { id };
     Ok(Json(response))
 }
 
+/// Checks that the source and sink of a pipeline are the tenant's and that
+/// its publication exists on the source
+async fn validate_pipeline(
+    pool: &PgPool,
+    tenant_id: &str,
+    encryption_key: &EncryptionKey,
+    pipeline: &PostPipelineRequest,
+) -> Result<(), PipelineError> {
+    let source = db::sources::read_source(pool, tenant_id, pipeline.source_id, encryption_key)
+        .await?
+        .ok_or(PipelineError::SourceNotFound(pipeline.source_id))?;
+
+    if !sink_exists(pool, tenant_id, pipeline.sink_id).await? {
+        return Err(PipelineError::SinkNotFound(pipeline.sink_id));
+    }
+
+    let options = source.config.connect_options();
+    if db::publications::read_publication(&pipeline.publication_name, &options)
+        .await?
+        .is_none()
+    {
+        return Err(PipelineError::PublicationNotFound(
+            pipeline.publication_name.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = [PostPipelineRequest],
+    responses(
+        (status = 200, description = "Create new pipelines, or none if any of them is invalid", body = [PostPipelinesBatchItemResponse]),
+        (status = 400, description = "A pipeline of the batch is invalid"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines:batch")]
+pub async fn create_pipelines(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    pipelines: Json<Vec<PostPipelineRequest>>,
+) -> Result<impl Responder, PipelineError> {
+    let pipelines = pipelines.0;
+    let tenant_id = extract_tenant_id(&req)?;
+
+    // Every pipeline is validated before any is created, so that an invalid
+    // one rejects the whole batch
+    for (index, pipeline) in pipelines.iter().enumerate() {
+        validate_pipeline(&pool, tenant_id, &encryption_key, pipeline)
+            .await
+            .map_err(|error| PipelineError::InvalidBatchItem {
+                index,
+                error: Box::new(error),
+            })?;
+    }
+
+    let image = db::images::read_default_image(&pool)
+        .await?
+        .ok_or(PipelineError::NoDefaultImageFound)?;
+
+    // A pipeline of the same source, sink and publication as an existing one
+    // isn't created again, so that a retried batch creates nothing twice
+    let actor = extract_actor(&req);
+    let mut txn = pool.begin().await?;
+    let mut response = Vec::with_capacity(pipelines.len());
+    for pipeline in pipelines {
+        let existing_id = db::pipelines::find_pipeline_txn(
+            &mut txn,
+            tenant_id,
+            pipeline.source_id,
+            pipeline.sink_id,
+            &pipeline.publication_name,
+        )
+        .await?;
+        if let Some(id) = existing_id {
+            response.push(PostPipelinesBatchItemResponse { id, created: false });
+            continue;
+        }
+
+        let after = pipeline_state(
+            pipeline.source_id,
+            pipeline.sink_id,
+            &pipeline.publication_name,
+            &serde_json::to_value(&pipeline.config)?,
+        );
+        let id = db::pipelines::create_pipeline_txn(
+            &mut txn,
+            tenant_id,
+            pipeline.source_id,
+            pipeline.sink_id,
+            image.id,
+            pipeline.publication_name,
+            &pipeline.config,
+        )
+        .await?;
+        let entry = AuditEntry {
+            tenant_id,
+            actor,
+            action: AuditAction::Create,
+            resource_type: AuditResourceType::Pipeline,
+            resource_id: id,
+            before: None,
+            after: Some(after),
+        };
+        db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+        response.push(PostPipelinesBatchItemResponse { id, created: true });
+    }
+    txn.commit().await?;
+
+    Ok(Json(response))
+}
+
 #[utoipa::path(
     context_path = "/v1",
     params(
@@ -220,6 +428,7 @@ pub async fn read_pipeline(
                 replicator_id: s.replicator_id,
                 publication_name: s.publication_name,
                 config,
+                deleted: s.deleted,
             })
         })
         .transpose()?
@@ -263,8 +472,19 @@ pub async fn update_pipeline(
         return Err(PipelineError::SinkNotFound(sink_id));
     }
 
-    db::pipelines::update_pipeline(
-        &pool,
+    let before = db::pipelines::read_pipeline(&pool, tenant_id, pipeline_id)
+        .await?
+        .map(|p| pipeline_state(p.source_id, p.sink_id, &p.publication_name, &p.config))
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let after = pipeline_state(
+        source_id,
+        sink_id,
+        &publication_name,
+        &serde_json::to_value(config)?,
+    );
+    let mut txn = pool.begin().await?;
+    db::pipelines::update_pipeline_txn(
+        &mut txn,
         tenant_id,
         pipeline_id,
         source_id,
@@ -274,30 +494,142 @@ pub async fn update_pipeline(
     )
     .await?
     .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Update,
+        resource_type: AuditResourceType::Pipeline,
+        resource_id: pipeline_id,
+        before: Some(before),
+        after: Some(after),
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Query parameters of the endpoint deleting a pipeline
+#[derive(Deserialize)]
+pub struct DeletePipelineQuery {
+    /// Keep the pipeline's replication slot on the source, e.g. to restore
+    /// the pipeline later without copying its tables again
+    #[serde(default)]
+    pub keep_slot: bool,
+    /// Also drop the pipeline's publication on the source
+    #[serde(default)]
+    pub drop_publication: bool,
+}
+
 #[utoipa::path(
     context_path = "/v1",
     params(
         ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+        ("keep_slot" = Option<bool>, Query, description = "Keep the replication slot of the pipeline on the source"),
+        ("drop_publication" = Option<bool>, Query, description = "Drop the publication of the pipeline on the source"),
     ),
     responses(
-        (status = 200, description = "Delete pipeline with id = pipeline_id"),
+        (status = 200, description = "Delete pipeline with id = pipeline_id and drop its replication slot unless another pipeline of its source uses it"),
         (status = 404, description = "Pipeline not found"),
+        (status = 409, description = "Replication slot is in use by the running pipeline"),
         (status = 500, description = "Internal server error")
     )
 )]
 #[delete("/pipelines/{pipeline_id}")]
 pub async fn delete_pipeline(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    pipeline_id: Path<i64>,
+    query: Query<DeletePipelineQuery>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    let pipeline = db::pipelines::read_pipeline(&pool, tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let before = pipeline_state(
+        pipeline.source_id,
+        pipeline.sink_id,
+        &pipeline.publication_name,
+        &pipeline.config,
+    );
+    let source_id = pipeline.source_id;
+    let source = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+        .await?
+        .ok_or(PipelineError::SourceNotFound(source_id))?;
+    let options = source.config.connect_options();
+
+    // The slot and the publication belong to the source, so they stay as
+    // long as another pipeline of the source still uses them.
+    let others: Vec<_> = db::pipelines::read_all_pipelines(&pool, tenant_id, false)
+        .await?
+        .into_iter()
+        .filter(|other| other.source_id == source_id && other.id != pipeline_id)
+        .collect();
+
+    // An orphaned slot retains WAL on the source forever. A running
+    // replicator keeps its slot active, so the slot isn't dropped, and the
+    // pipeline not deleted, until the pipeline was stopped.
+    if !query.keep_slot && others.is_empty() {
+        let SourceConfig::Postgres { slot_name, .. } = &source.config;
+        if db::slots::drop_slot(slot_name, &options).await? == DropSlotOutcome::InUse {
+            return Err(PipelineError::SlotInUse(slot_name.clone()));
+        }
+    }
+    let publication_in_use = others
+        .iter()
+        .any(|other| other.publication_name == pipeline.publication_name);
+    if query.drop_publication && !publication_in_use {
+        db::publications::drop_publication(&pipeline.publication_name, &options).await?;
+    }
+
+    let mut txn = pool.begin().await?;
+    db::pipelines::delete_pipeline_txn(&mut txn, tenant_id, pipeline_id)
+        .await?
+        .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Delete,
+        resource_type: AuditResourceType::Pipeline,
+        resource_id: pipeline_id,
+        before: Some(before),
+        after: None,
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Restores a deleted pipeline.
+///
+/// Restoring doesn't bring back a slot dropped when the pipeline was deleted.
+/// The replicator then creates a new slot, which starts after the changes
+/// made in the meantime, and stops with a slot gap error unless the tables
+/// of the pipeline are copied again. Delete a pipeline with `keep_slot` to
+/// restore it without copying its tables again.
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Restore deleted pipeline with id = pipeline_id, without a replication slot dropped when it was deleted"),
+        (status = 404, description = "Deleted pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/restore")]
+pub async fn restore_pipeline(
     req: HttpRequest,
     pool: Data<PgPool>,
     pipeline_id: Path<i64>,
 ) -> Result<impl Responder, PipelineError> {
     let tenant_id = extract_tenant_id(&req)?;
     let pipeline_id = pipeline_id.into_inner();
-    db::pipelines::delete_pipeline(&pool, tenant_id, pipeline_id)
+    db::pipelines::restore_pipeline(&pool, tenant_id, pipeline_id)
         .await?
         .ok_or(PipelineError::PipelineNotFound(pipeline_id))?;
     Ok(HttpResponse::Ok().finish())
@@ -305,6 +637,9 @@ pub async fn delete_pipeline(
 
 #[utoipa::path(
     context_path = "/v1",
+    params(
+        ("include_deleted" = Option<bool>, Query, description = "Also return deleted pipelines"),
+    ),
     responses(
         (status = 200, description = "Return all pipelines"),
         (status = 500, description = "Internal server error")
@@ -314,10 +649,12 @@ pub async fn delete_pipeline(
 pub async fn read_all_pipelines(
     req: HttpRequest,
     pool: Data<PgPool>,
+    query: Query<ListQuery>,
 ) -> Result<impl Responder, PipelineError> {
     let tenant_id = extract_tenant_id(&req)?;
+    let include_deleted = query.include_deleted;
     let mut pipelines = vec![];
-    for pipeline in db::pipelines::read_all_pipelines(&pool, tenant_id).await? {
+    for pipeline in db::pipelines::read_all_pipelines(&pool, tenant_id, include_deleted).await? {
         let config: PipelineConfig = serde_json::from_value(pipeline.config)?;
         let sink = GetPipelineResponse {
             id: pipeline.id,
@@ -329,6 +666,7 @@ pub async fn read_all_pipelines(
             replicator_id: pipeline.replicator_id,
             publication_name: pipeline.publication_name,
             config,
+            deleted: pipeline.deleted,
         };
         pipelines.push(sink);
     }
@@ -348,6 +686,7 @@ pub async fn start_pipeline(
     pool: Data<PgPool>,
     encryption_key: Data<EncryptionKey>,
     k8s_client: Data<Arc<HttpK8sClient>>,
+    telemetry: Data<Option<TelemetrySettings>>,
     pipeline_id: Path<i64>,
 ) -> Result<impl Responder, PipelineError> {
     let tenant_id = extract_tenant_id(&req)?;
@@ -356,7 +695,7 @@ pub async fn start_pipeline(
     let (pipeline, replicator, image, source, sink) =
         read_data(&pool, tenant_id, pipeline_id, &encryption_key).await?;
 
-    let (secrets, config) = create_configs(source.config, sink.config, pipeline)?;
+    let (secrets, config) = create_configs(source.config, sink.config, pipeline, &telemetry)?;
     let prefix = create_prefix(tenant_id, replicator.id);
 
     create_or_update_secrets(&k8s_client, &prefix, secrets).await?;
@@ -395,6 +734,87 @@ pub async fn stop_pipeline(
     Ok(HttpResponse::Ok().finish())
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct GetPipelineTableResponse {
+    table_id: i64,
+    schema_name: String,
+    table_name: String,
+    status: PipelineTableStatus,
+    lsn: Option<String>,
+    resync_requested: bool,
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+    ),
+    responses(
+        (status = 200, description = "Return replicated tables of pipeline with id = pipeline_id", body = Vec<GetPipelineTableResponse>),
+        (status = 404, description = "Pipeline not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/pipelines/{pipeline_id}/tables")]
+pub async fn read_pipeline_tables(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id: Path<i64>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let pipeline_id = pipeline_id.into_inner();
+
+    if db::pipelines::read_pipeline(&pool, tenant_id, pipeline_id)
+        .await?
+        .is_none()
+    {
+        return Err(PipelineError::PipelineNotFound(pipeline_id));
+    }
+
+    let mut tables = vec![];
+    for table in db::pipeline_tables::read_pipeline_tables(&pool, tenant_id, pipeline_id).await? {
+        let table = GetPipelineTableResponse {
+            table_id: table.table_id,
+            schema_name: table.schema_name,
+            table_name: table.table_name,
+            status: table.status,
+            lsn: table.lsn,
+            resync_requested: table.resync_requested,
+        };
+        tables.push(table);
+    }
+    Ok(Json(tables))
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("pipeline_id" = i64, Path, description = "Id of the pipeline"),
+        ("table_id" = i64, Path, description = "Id of the table"),
+    ),
+    responses(
+        (status = 200, description = "Request that the table with id = table_id is copied again by the running pipeline with id = pipeline_id"),
+        (status = 404, description = "Pipeline table not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/pipelines/{pipeline_id}/tables/{table_id}/resync")]
+pub async fn resync_pipeline_table(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    pipeline_id_and_table_id: Path<(i64, i64)>,
+) -> Result<impl Responder, PipelineError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let (pipeline_id, table_id) = pipeline_id_and_table_id.into_inner();
+    db::pipeline_tables::request_pipeline_table_resync(&pool, tenant_id, pipeline_id, table_id)
+        .await?
+        .ok_or(PipelineError::PipelineTableNotFound {
+            pipeline_id,
+            table_id,
+        })?;
+    Ok(HttpResponse::Ok().finish())
+}
+
 #[derive(Serialize, ToSchema)]
 pub enum PipelineStatus {
     Stopped,
@@ -471,6 +891,7 @@ fn create_configs(
     source_config: SourceConfig,
     sink_config: SinkConfig,
     pipeline: Pipeline,
+    telemetry: &Option<TelemetrySettings>,
 ) -> Result<(Secrets, replicator_config::Config), PipelineError> {
     let SourceConfig::Postgres {
         host,
@@ -479,6 +900,20 @@ fn create_configs(
         username,
         password: postgres_password,
         slot_name,
+        slot_options,
+        statement_timeout_ms,
+        tcp_keepalives_idle_secs,
+        connect_timeout_secs,
+        route_partitions_to_parent,
+        copy_prefetch_rows,
+        auth,
+        output_plugin,
+        stream_in_progress_transactions,
+        snapshot_per_table,
+        application_name,
+        socks5_proxy,
+        role,
+        search_path,
     } = source_config;
 
     let SinkConfig::BigQuery {
@@ -493,13 +928,29 @@ fn create_configs(
     };
 
     let publication = pipeline.publication_name;
+    let application_name =
+        application_name.unwrap_or_else(|| format!("pg_replicate-{}", pipeline.id));
     let source_config = replicator_config::SourceConfig::Postgres {
         host,
         port,
         name,
         username,
         slot_name,
+        slot_options,
         publication,
+        statement_timeout_ms,
+        tcp_keepalives_idle_secs,
+        connect_timeout_secs,
+        route_partitions_to_parent,
+        copy_prefetch_rows,
+        auth,
+        output_plugin,
+        stream_in_progress_transactions,
+        snapshot_per_table,
+        application_name: Some(application_name),
+        socks5_proxy,
+        role,
+        search_path,
     };
 
     let sink_config = replicator_config::SinkConfig::BigQuery {
@@ -512,12 +963,62 @@ fn create_configs(
     let batch_config = replicator_config::BatchConfig {
         max_size: batch_config.max_size,
         max_fill_secs: batch_config.max_fill_secs,
+        serialization_workers: batch_config.serialization_workers,
+        max_in_flight_batches: batch_config.max_in_flight_batches,
+        flush_on_idle: batch_config.flush_on_idle,
+        sink_write_timeout_secs: batch_config.sink_write_timeout_secs,
+        sink_ready_timeout_secs: batch_config.sink_ready_timeout_secs,
+        adaptive: batch_config
+            .adaptive
+            .map(|adaptive| replicator_config::AdaptiveBatchConfig {
+                min_size: adaptive.min_size,
+                target_write_ms: adaptive.target_write_ms,
+            }),
+        table_overrides: batch_config
+            .table_overrides
+            .into_iter()
+            .map(|(table_id, table_config)| {
+                let table_config = replicator_config::TableBatchConfig {
+                    max_size: table_config.max_size,
+                    max_fill_secs: table_config.max_fill_secs,
+                };
+                (table_id, table_config)
+            })
+            .collect(),
+        table_operations: batch_config
+            .table_operations
+            .into_iter()
+            .map(|(table_id, operations)| {
+                let operations = replicator_config::TableOperationsConfig {
+                    insert: operations.insert,
+                    update: operations.update,
+                    delete: operations.delete,
+                    truncate: operations.truncate,
+                };
+                (table_id, operations)
+            })
+            .collect(),
+        dead_letter: batch_config.dead_letter.map(|dead_letter| {
+            replicator_config::DeadLetterConfig {
+                max_dead_letters: dead_letter.max_dead_letters,
+                window_secs: dead_letter.window_secs,
+            }
+        }),
     };
 
+    // The replicator's spans continue the trace of the request starting it
+    let telemetry_config = telemetry
+        .as_ref()
+        .map(|telemetry| replicator_config::TelemetryConfig {
+            otlp_endpoint: telemetry.otlp_endpoint.clone(),
+            trace_parent: current_trace_parent(),
+        });
+
     let config = replicator_config::Config {
         source: source_config,
         sink: sink_config,
         batch: batch_config,
+        telemetry: telemetry_config,
     };
 
     Ok((secrets, config))
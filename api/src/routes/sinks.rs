@@ -1,11 +1,12 @@
 use actix_web::{
     delete, get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
@@ -13,13 +14,14 @@ use utoipa::ToSchema;
 use crate::{
     db::{
         self,
+        audit_log::{sink_state, AuditAction, AuditEntry, AuditResourceType},
         sinks::{SinkConfig, SinksDbError},
     },
     encryption::EncryptionKey,
-    routes::extract_tenant_id,
+    routes::{extract_actor, extract_tenant_id},
 };
 
-use super::{ErrorMessage, TenantIdError};
+use super::{ErrorCode, ErrorMessage, ListQuery, TenantIdError};
 
 #[derive(Debug, Error)]
 enum SinkError {
@@ -29,6 +31,9 @@ enum SinkError {
     #[error("sink with id {0} not found")]
     SinkNotFound(i64),
 
+    #[error("sink with id {0} is used by a pipeline, delete the pipeline first")]
+    SinkInUse(i64),
+
     #[error("tenant id error: {0}")]
     TenantId(#[from] TenantIdError),
 
@@ -45,6 +50,24 @@ impl SinkError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            SinkError::DatabaseError(_) | SinkError::SinksDb(_) => ErrorCode::Internal,
+            SinkError::SinkNotFound(_) => ErrorCode::NotFound,
+            SinkError::SinkInUse(_) => ErrorCode::Conflict,
+            SinkError::TenantId(_) => ErrorCode::Validation,
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            SinkError::SinkNotFound(id) | SinkError::SinkInUse(id) => {
+                json!({ "sink_id": id })
+            }
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for SinkError {
@@ -54,19 +77,15 @@ impl ResponseError for SinkError {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
             SinkError::SinkNotFound(_) => StatusCode::NOT_FOUND,
+            SinkError::SinkInUse(_) => StatusCode::CONFLICT,
             SinkError::TenantId(_) => StatusCode::BAD_REQUEST,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
@@ -91,6 +110,7 @@ pub struct GetSinkResponse {
     #[schema(example = "BigQuery Sink")]
     name: String,
     config: SinkConfig,
+    deleted: bool,
 }
 
 #[utoipa::path(
@@ -112,7 +132,21 @@ pub async fn create_sink(
     let tenant_id = extract_tenant_id(&req)?;
     let name = sink.name;
     let config = sink.config;
-    let id = db::sinks::create_sink(&pool, tenant_id, &name, config, &encryption_key).await?;
+    let after = sink_state(&name, &config);
+    let mut txn = pool.begin().await?;
+    let id =
+        db::sinks::create_sink_txn(&mut txn, tenant_id, &name, config, &encryption_key).await?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Create,
+        resource_type: AuditResourceType::Sink,
+        resource_id: id,
+        before: None,
+        after: Some(after),
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
     let response = PostSinkResponse { id };
     Ok(Json(response))
 }
@@ -144,6 +178,7 @@ pub async fn read_sink(
             tenant_id: s.tenant_id,
             name: s.name,
             config: s.config,
+            deleted: s.deleted,
         })
         .ok_or(SinkError::SinkNotFound(sink_id))?;
     Ok(Json(response))
@@ -174,9 +209,26 @@ pub async fn update_sink(
     let sink_id = sink_id.into_inner();
     let name = sink.name;
     let config = sink.config;
-    db::sinks::update_sink(&pool, tenant_id, &name, sink_id, config, &encryption_key)
+    let before = db::sinks::read_sink(&pool, tenant_id, sink_id, &encryption_key)
+        .await?
+        .map(|s| sink_state(&s.name, &s.config))
+        .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let after = sink_state(&name, &config);
+    let mut txn = pool.begin().await?;
+    db::sinks::update_sink_txn(&mut txn, tenant_id, &name, sink_id, config, &encryption_key)
         .await?
         .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Update,
+        resource_type: AuditResourceType::Sink,
+        resource_id: sink_id,
+        before: Some(before),
+        after: Some(after),
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -188,6 +240,7 @@ pub async fn update_sink(
     responses(
         (status = 200, description = "Delete sink with id = sink_id"),
         (status = 404, description = "Sink not found"),
+        (status = 409, description = "Sink is used by a pipeline"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -195,18 +248,66 @@ pub async fn update_sink(
 pub async fn delete_sink(
     req: HttpRequest,
     pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
     sink_id: Path<i64>,
 ) -> Result<impl Responder, SinkError> {
     let tenant_id = extract_tenant_id(&req)?;
     let sink_id = sink_id.into_inner();
-    db::sinks::delete_sink(&pool, tenant_id, sink_id)
+    let before = db::sinks::read_sink(&pool, tenant_id, sink_id, &encryption_key)
+        .await?
+        .map(|s| sink_state(&s.name, &s.config))
+        .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let mut txn = pool.begin().await?;
+    if db::sinks::sink_in_use_txn(&mut txn, tenant_id, sink_id).await? {
+        return Err(SinkError::SinkInUse(sink_id));
+    }
+    db::sinks::delete_sink_txn(&mut txn, tenant_id, sink_id)
         .await?
         .ok_or(SinkError::SinkNotFound(sink_id))?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Delete,
+        resource_type: AuditResourceType::Sink,
+        resource_id: sink_id,
+        before: Some(before),
+        after: None,
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
 #[utoipa::path(
     context_path = "/v1",
+    params(
+        ("sink_id" = i64, Path, description = "Id of the sink"),
+    ),
+    responses(
+        (status = 200, description = "Restore deleted sink with id = sink_id"),
+        (status = 404, description = "Deleted sink not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sinks/{sink_id}/restore")]
+pub async fn restore_sink(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    sink_id: Path<i64>,
+) -> Result<impl Responder, SinkError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let sink_id = sink_id.into_inner();
+    db::sinks::restore_sink(&pool, tenant_id, sink_id)
+        .await?
+        .ok_or(SinkError::SinkNotFound(sink_id))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("include_deleted" = Option<bool>, Query, description = "Also return deleted sinks"),
+    ),
     responses(
         (status = 200, description = "Return all sinks"),
         (status = 500, description = "Internal server error")
@@ -217,15 +318,20 @@ pub async fn read_all_sinks(
     req: HttpRequest,
     pool: Data<PgPool>,
     encryption_key: Data<EncryptionKey>,
+    query: Query<ListQuery>,
 ) -> Result<impl Responder, SinkError> {
     let tenant_id = extract_tenant_id(&req)?;
+    let include_deleted = query.include_deleted;
     let mut sinks = vec![];
-    for sink in db::sinks::read_all_sinks(&pool, tenant_id, &encryption_key).await? {
+    for sink in
+        db::sinks::read_all_sinks(&pool, tenant_id, include_deleted, &encryption_key).await?
+    {
         let sink = GetSinkResponse {
             id: sink.id,
             tenant_id: sink.tenant_id,
             name: sink.name,
             config: sink.config,
+            deleted: sink.deleted,
         };
         sinks.push(sink);
     }
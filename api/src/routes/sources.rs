@@ -1,23 +1,26 @@
 use actix_web::{
     delete, get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     post,
-    web::{Data, Json, Path},
+    web::{Data, Json, Path, Query},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
 
-use super::{ErrorMessage, TenantIdError};
+use super::{ErrorCode, ErrorMessage, ListQuery, TenantIdError};
 use crate::{
     db::{
         self,
+        audit_log::{source_state, AuditAction, AuditEntry, AuditResourceType},
+        connectivity::ConnectivityReport,
         sources::{SourceConfig, SourcesDbError},
     },
     encryption::EncryptionKey,
-    routes::extract_tenant_id,
+    routes::{extract_actor, extract_tenant_id},
 };
 
 pub mod publications;
@@ -31,6 +34,9 @@ enum SourceError {
     #[error("source with id {0} not found")]
     SourceNotFound(i64),
 
+    #[error("source with id {0} is used by a pipeline, delete the pipeline first")]
+    SourceInUse(i64),
+
     #[error("tenant id error: {0}")]
     TenantId(#[from] TenantIdError),
 
@@ -47,6 +53,24 @@ impl SourceError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            SourceError::DatabaseError(_) | SourceError::SourcesDb(_) => ErrorCode::Internal,
+            SourceError::SourceNotFound(_) => ErrorCode::NotFound,
+            SourceError::SourceInUse(_) => ErrorCode::Conflict,
+            SourceError::TenantId(_) => ErrorCode::Validation,
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            SourceError::SourceNotFound(id) | SourceError::SourceInUse(id) => {
+                json!({ "source_id": id })
+            }
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for SourceError {
@@ -56,19 +80,15 @@ impl ResponseError for SourceError {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
             SourceError::SourceNotFound(_) => StatusCode::NOT_FOUND,
+            SourceError::SourceInUse(_) => StatusCode::CONFLICT,
             SourceError::TenantId(_) => StatusCode::BAD_REQUEST,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
@@ -84,6 +104,14 @@ pub struct PostSourceResponse {
     id: i64,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct ValidateSourceRequest {
+    #[schema(required = true)]
+    pub config: SourceConfig,
+    #[schema(example = "my_publication")]
+    pub publication: Option<String>,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct GetSourceResponse {
     #[schema(example = 1)]
@@ -93,6 +121,7 @@ pub struct GetSourceResponse {
     #[schema(example = "Postgres Source")]
     name: String,
     config: SourceConfig,
+    deleted: bool,
 }
 
 #[utoipa::path(
@@ -114,11 +143,48 @@ pub async fn create_source(
     let tenant_id = extract_tenant_id(&req)?;
     let name = source.name;
     let config = source.config;
-    let id = db::sources::create_source(&pool, tenant_id, &name, config, &encryption_key).await?;
+    let after = source_state(&name, &config);
+    let mut txn = pool.begin().await?;
+    let id =
+        db::sources::create_source_txn(&mut txn, tenant_id, &name, config, &encryption_key).await?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Create,
+        resource_type: AuditResourceType::Source,
+        resource_id: id,
+        before: None,
+        after: Some(after),
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
     let response = PostSourceResponse { id };
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    context_path = "/v1",
+    request_body = ValidateSourceRequest,
+    responses(
+        (status = 200, description = "Check connectivity of a source config", body = ConnectivityReport),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sources/validate")]
+pub async fn validate_source(
+    source: Json<ValidateSourceRequest>,
+) -> Result<impl Responder, SourceError> {
+    let source = source.0;
+    let options = source.config.connect_options();
+    let report = db::connectivity::check_connectivity(
+        &options,
+        source.config.slot_name(),
+        source.publication.as_deref(),
+    )
+    .await?;
+    Ok(Json(report))
+}
+
 #[utoipa::path(
     context_path = "/v1",
     params(
@@ -146,6 +212,7 @@ pub async fn read_source(
             tenant_id: s.tenant_id,
             name: s.name,
             config: s.config,
+            deleted: s.deleted,
         })
         .ok_or(SourceError::SourceNotFound(source_id))?;
     Ok(Json(response))
@@ -176,9 +243,33 @@ pub async fn update_source(
     let source_id = source_id.into_inner();
     let name = source.name;
     let config = source.config;
-    db::sources::update_source(&pool, tenant_id, &name, source_id, config, &encryption_key)
+    let before = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
         .await?
+        .map(|s| source_state(&s.name, &s.config))
         .ok_or(SourceError::SourceNotFound(source_id))?;
+    let after = source_state(&name, &config);
+    let mut txn = pool.begin().await?;
+    db::sources::update_source_txn(
+        &mut txn,
+        tenant_id,
+        &name,
+        source_id,
+        config,
+        &encryption_key,
+    )
+    .await?
+    .ok_or(SourceError::SourceNotFound(source_id))?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Update,
+        resource_type: AuditResourceType::Source,
+        resource_id: source_id,
+        before: Some(before),
+        after: Some(after),
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -190,6 +281,7 @@ pub async fn update_source(
     responses(
         (status = 200, description = "Delete source with id = source_id"),
         (status = 404, description = "Source not found"),
+        (status = 409, description = "Source is used by a pipeline"),
         (status = 500, description = "Internal server error")
     )
 )]
@@ -197,18 +289,66 @@ pub async fn update_source(
 pub async fn delete_source(
     req: HttpRequest,
     pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
     source_id: Path<i64>,
 ) -> Result<impl Responder, SourceError> {
     let tenant_id = extract_tenant_id(&req)?;
     let source_id = source_id.into_inner();
-    db::sources::delete_source(&pool, tenant_id, source_id)
+    let before = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
         .await?
+        .map(|s| source_state(&s.name, &s.config))
         .ok_or(SourceError::SourceNotFound(source_id))?;
+    let mut txn = pool.begin().await?;
+    if db::sources::source_in_use_txn(&mut txn, tenant_id, source_id).await? {
+        return Err(SourceError::SourceInUse(source_id));
+    }
+    db::sources::delete_source_txn(&mut txn, tenant_id, source_id)
+        .await?
+        .ok_or(SourceError::SourceNotFound(source_id))?;
+    let entry = AuditEntry {
+        tenant_id,
+        actor: extract_actor(&req),
+        action: AuditAction::Delete,
+        resource_type: AuditResourceType::Source,
+        resource_id: source_id,
+        before: Some(before),
+        after: None,
+    };
+    db::audit_log::record_audit_entry_txn(&mut txn, entry).await?;
+    txn.commit().await?;
     Ok(HttpResponse::Ok().finish())
 }
 
 #[utoipa::path(
     context_path = "/v1",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Restore deleted source with id = source_id"),
+        (status = 404, description = "Deleted source not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[post("/sources/{source_id}/restore")]
+pub async fn restore_source(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    source_id: Path<i64>,
+) -> Result<impl Responder, SourceError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let source_id = source_id.into_inner();
+    db::sources::restore_source(&pool, tenant_id, source_id)
+        .await?
+        .ok_or(SourceError::SourceNotFound(source_id))?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("include_deleted" = Option<bool>, Query, description = "Also return deleted sources"),
+    ),
     responses(
         (status = 200, description = "Return all sources"),
         (status = 500, description = "Internal server error")
@@ -219,15 +359,20 @@ pub async fn read_all_sources(
     req: HttpRequest,
     pool: Data<PgPool>,
     encryption_key: Data<EncryptionKey>,
+    query: Query<ListQuery>,
 ) -> Result<impl Responder, SourceError> {
     let tenant_id = extract_tenant_id(&req)?;
+    let include_deleted = query.include_deleted;
     let mut sources = vec![];
-    for source in db::sources::read_all_sources(&pool, tenant_id, &encryption_key).await? {
+    for source in
+        db::sources::read_all_sources(&pool, tenant_id, include_deleted, &encryption_key).await?
+    {
         let source = GetSourceResponse {
             id: source.id,
             tenant_id: source.tenant_id,
             name: source.name,
             config: source.config,
+            deleted: source.deleted,
         };
         sources.push(source);
     }
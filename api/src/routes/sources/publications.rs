@@ -1,11 +1,12 @@
 use actix_web::{
     delete, get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     post,
     web::{Data, Json, Path},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use serde::Deserialize;
+use serde_json::json;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
@@ -13,7 +14,7 @@ use utoipa::ToSchema;
 use crate::{
     db::{self, publications::Publication, sources::SourcesDbError, tables::Table},
     encryption::EncryptionKey,
-    routes::{extract_tenant_id, ErrorMessage, TenantIdError},
+    routes::{extract_tenant_id, ErrorCode, ErrorMessage, TenantIdError},
 };
 
 #[derive(Debug, Error)]
@@ -43,6 +44,26 @@ impl PublicationError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            PublicationError::DatabaseError(_) | PublicationError::SourcesDb(_) => {
+                ErrorCode::Internal
+            }
+            PublicationError::SourceNotFound(_) | PublicationError::PublicationNotFound(_) => {
+                ErrorCode::NotFound
+            }
+            PublicationError::TenantId(_) => ErrorCode::Validation,
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            PublicationError::SourceNotFound(id) => json!({ "source_id": id }),
+            PublicationError::PublicationNotFound(name) => json!({ "publication_name": name }),
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for PublicationError {
@@ -59,14 +80,9 @@ impl ResponseError for PublicationError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
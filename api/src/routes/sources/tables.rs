@@ -1,6 +1,6 @@
 use actix_web::{
     get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     web::{Data, Json, Path},
     HttpRequest, HttpResponse, Responder, ResponseError,
 };
@@ -10,7 +10,7 @@ use thiserror::Error;
 use crate::{
     db::{self, sources::SourcesDbError},
     encryption::EncryptionKey,
-    routes::{extract_tenant_id, ErrorMessage, TenantIdError},
+    routes::{extract_tenant_id, ErrorCode, ErrorMessage, TenantIdError},
 };
 
 #[derive(Debug, Error)]
@@ -21,6 +21,9 @@ enum TableError {
     #[error("source with id {0} not found")]
     SourceNotFound(i64),
 
+    #[error("publication with name {0} not found")]
+    PublicationNotFound(String),
+
     #[error("tenant id error: {0}")]
     TenantId(#[from] TenantIdError),
 
@@ -37,6 +40,24 @@ impl TableError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            TableError::DatabaseError(_) | TableError::SourcesDb(_) => ErrorCode::Internal,
+            TableError::SourceNotFound(_) | TableError::PublicationNotFound(_) => {
+                ErrorCode::NotFound
+            }
+            TableError::TenantId(_) => ErrorCode::Validation,
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            TableError::SourceNotFound(id) => json!({ "source_id": id }),
+            TableError::PublicationNotFound(name) => json!({ "publication_name": name }),
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for TableError {
@@ -45,20 +66,17 @@ impl ResponseError for TableError {
             TableError::DatabaseError(_) | TableError::SourcesDb(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
-            TableError::SourceNotFound(_) => StatusCode::NOT_FOUND,
+            TableError::SourceNotFound(_) | TableError::PublicationNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
             TableError::TenantId(_) => StatusCode::BAD_REQUEST,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
@@ -92,3 +110,38 @@ pub async fn read_table_names(
 
     Ok(Json(tables))
 }
+
+#[utoipa::path(
+    context_path = "/v1",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        ("publication_name" = String, Path, description = "Name of the publication"),
+    ),
+    responses(
+        (status = 200, description = "Return size estimates and replica identities of the tables in publication with name = publication_name from source with id = source_id", body = Vec<TableInfo>),
+        (status = 404, description = "Source or publication not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+#[get("/sources/{source_id}/publications/{publication_name}/tables")]
+pub async fn read_publication_table_infos(
+    req: HttpRequest,
+    pool: Data<PgPool>,
+    encryption_key: Data<EncryptionKey>,
+    source_id_and_pub_name: Path<(i64, String)>,
+) -> Result<impl Responder, TableError> {
+    let tenant_id = extract_tenant_id(&req)?;
+    let (source_id, publication_name) = source_id_and_pub_name.into_inner();
+
+    let config = db::sources::read_source(&pool, tenant_id, source_id, &encryption_key)
+        .await?
+        .map(|s| s.config)
+        .ok_or(TableError::SourceNotFound(source_id))?;
+
+    let options = config.connect_options();
+    let table_infos = db::tables::get_publication_table_infos(&publication_name, &options)
+        .await?
+        .ok_or(TableError::PublicationNotFound(publication_name))?;
+
+    Ok(Json(table_infos))
+}
@@ -1,18 +1,19 @@
 use actix_web::{
     delete, get,
-    http::{header::ContentType, StatusCode},
+    http::StatusCode,
     post, put,
     web::{Data, Json, Path},
     HttpResponse, Responder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::PgPool;
 use thiserror::Error;
 use utoipa::ToSchema;
 
 use crate::db;
 
-use super::ErrorMessage;
+use super::{ErrorCode, ErrorMessage};
 
 #[derive(Deserialize, ToSchema)]
 pub struct CreateTenantRequest {
@@ -40,6 +41,9 @@ enum TenantError {
 
     #[error("tenant with id {0} not found")]
     TenantNotFound(String),
+
+    #[error("tenant with id {0} already exists")]
+    TenantAlreadyExists(String),
 }
 
 impl TenantError {
@@ -51,6 +55,23 @@ impl TenantError {
             e => e.to_string(),
         }
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            TenantError::DatabaseError(_) => ErrorCode::Internal,
+            TenantError::TenantNotFound(_) => ErrorCode::NotFound,
+            TenantError::TenantAlreadyExists(_) => ErrorCode::Conflict,
+        }
+    }
+
+    fn details(&self) -> serde_json::Value {
+        match self {
+            TenantError::TenantNotFound(id) | TenantError::TenantAlreadyExists(id) => {
+                json!({ "tenant_id": id })
+            }
+            _ => json!({}),
+        }
+    }
 }
 
 impl ResponseError for TenantError {
@@ -58,18 +79,14 @@ impl ResponseError for TenantError {
         match self {
             TenantError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TenantError::TenantNotFound(_) => StatusCode::NOT_FOUND,
+            TenantError::TenantAlreadyExists(_) => StatusCode::CONFLICT,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = ErrorMessage {
-            error: self.to_message(),
-        };
-        let body =
-            serde_json::to_string(&error_message).expect("failed to serialize error message");
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(body)
+        ErrorMessage::new(self.code(), self.to_message())
+            .with_details(self.details())
+            .response(self.status_code())
     }
 }
 
@@ -86,7 +103,8 @@ pub struct GetTenantResponse {
     request_body = CreateTenantRequest,
     responses(
         (status = 200, description = "Create new tenant", body = PostTenantResponse),
-        (status = 500, description = "Internal server error")
+        (status = 409, description = "Tenant already exists", body = ErrorMessage),
+        (status = 500, description = "Internal server error", body = ErrorMessage)
     )
 )]
 #[post("/tenants")]
@@ -97,7 +115,14 @@ pub async fn create_tenant(
     let tenant = tenant.0;
     let id = tenant.id;
     let name = tenant.name;
-    let id = db::tenants::create_tenant(&pool, &id, &name).await?;
+    let id = db::tenants::create_tenant(&pool, &id, &name)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                TenantError::TenantAlreadyExists(id.clone())
+            }
+            e => TenantError::DatabaseError(e),
+        })?;
     let response = PostTenantResponse { id };
     Ok(Json(response))
 }
@@ -1,6 +1,9 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{net::TcpListener, sync::Arc, time::Instant};
 
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::{
+    dev::{Server, Service},
+    web, App, HttpServer,
+};
 use actix_web_httpauth::middleware::HttpAuthentication;
 use aws_lc_rs::aead::{RandomizedNonceKey, AES_256_GCM};
 use base64::{prelude::BASE64_STANDARD, Engine};
@@ -11,24 +14,43 @@ use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
     authentication::auth_validator,
-    configuration::{DatabaseSettings, Settings},
-    db::publications::Publication,
+    configuration::{DatabaseSettings, Settings, TelemetrySettings},
+    db::{
+        audit_log::{AuditAction, AuditResourceType},
+        connectivity::ConnectivityReport,
+        pipeline_tables::PipelineTableStatus,
+        pipelines::{
+            AdaptiveBatchConfig, BatchConfig, DeadLetterConfig, PipelineConfig, TableBatchConfig,
+            TableOperationsConfig,
+        },
+        publications::Publication,
+        sinks::SinkConfig,
+        sources::SourceConfig,
+        tables::TableInfo,
+    },
     encryption,
     k8s_client::HttpK8sClient,
+    replicator_config::{AuthMethod, OutputPlugin, SlotOptions},
     routes::{
+        audit_log::{read_audit_log, GetAuditRecordResponse},
+        extractor_error_handler,
         health_check::health_check,
         images::{
             create_image, delete_image, read_all_images, read_image, update_image,
             GetImageResponse, PostImageRequest, PostImageResponse,
         },
+        metrics::metrics,
+        openapi::openapi_json,
         pipelines::{
-            create_pipeline, delete_pipeline, get_pipeline_status, read_all_pipelines,
-            read_pipeline, start_pipeline, stop_pipeline, update_pipeline, GetPipelineResponse,
-            PostPipelineRequest, PostPipelineResponse,
+            create_pipeline, create_pipelines, delete_pipeline, get_pipeline_status,
+            read_all_pipelines, read_pipeline, read_pipeline_tables, restore_pipeline,
+            resync_pipeline_table, start_pipeline, stop_pipeline, update_pipeline,
+            GetPipelineResponse, GetPipelineTableResponse, PostPipelineRequest,
+            PostPipelineResponse, PostPipelinesBatchItemResponse,
         },
         sinks::{
-            create_sink, delete_sink, read_all_sinks, read_sink, update_sink, GetSinkResponse,
-            PostSinkRequest, PostSinkResponse,
+            create_sink, delete_sink, read_all_sinks, read_sink, restore_sink, update_sink,
+            GetSinkResponse, PostSinkRequest, PostSinkResponse,
         },
         sources::{
             create_source, delete_source,
@@ -36,14 +58,16 @@ use crate::{
                 create_publication, delete_publication, read_all_publications, read_publication,
                 update_publication, CreatePublicationRequest, UpdatePublicationRequest,
             },
-            read_all_sources, read_source,
-            tables::read_table_names,
-            update_source, GetSourceResponse, PostSourceRequest, PostSourceResponse,
+            read_all_sources, read_source, restore_source,
+            tables::{read_publication_table_infos, read_table_names},
+            update_source, validate_source, GetSourceResponse, PostSourceRequest,
+            PostSourceResponse, ValidateSourceRequest,
         },
         tenants::{
             create_or_update_tenant, create_tenant, delete_tenant, read_all_tenants, read_tenant,
             update_tenant, CreateTenantRequest, GetTenantResponse, PostTenantResponse,
         },
+        ErrorBody, ErrorCode, ErrorMessage,
     },
 };
 
@@ -76,6 +100,7 @@ impl Application {
             encryption_key,
             api_key,
             Some(k8s_client),
+            configuration.telemetry,
         )
         .await?;
 
@@ -114,27 +139,35 @@ pub async fn run(
     encryption_key: encryption::EncryptionKey,
     api_key: String,
     http_k8s_client: Option<HttpK8sClient>,
+    telemetry: Option<TelemetrySettings>,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
     let encryption_key = web::Data::new(encryption_key);
     let api_key = web::Data::new(api_key);
+    let telemetry = web::Data::new(telemetry);
     let k8s_client = http_k8s_client.map(|client| web::Data::new(Arc::new(client)));
 
     #[derive(OpenApi)]
     #[openapi(
         paths(
             crate::routes::health_check::health_check,
+            crate::routes::metrics::metrics,
+            crate::routes::openapi::openapi_json,
             crate::routes::images::create_image,
             crate::routes::images::read_image,
             crate::routes::images::update_image,
             crate::routes::images::delete_image,
             crate::routes::images::read_all_images,
             crate::routes::pipelines::create_pipeline,
+            crate::routes::pipelines::create_pipelines,
             crate::routes::pipelines::read_pipeline,
             crate::routes::pipelines::update_pipeline,
             crate::routes::pipelines::delete_pipeline,
+            crate::routes::pipelines::restore_pipeline,
             crate::routes::pipelines::read_all_pipelines,
             crate::routes::pipelines::get_pipeline_status,
+            crate::routes::pipelines::read_pipeline_tables,
+            crate::routes::pipelines::resync_pipeline_table,
             crate::routes::tenants::create_tenant,
             crate::routes::tenants::create_or_update_tenant,
             crate::routes::tenants::read_tenant,
@@ -145,38 +178,66 @@ pub async fn run(
             crate::routes::sources::read_source,
             crate::routes::sources::update_source,
             crate::routes::sources::delete_source,
+            crate::routes::sources::restore_source,
             crate::routes::sources::read_all_sources,
+            crate::routes::sources::validate_source,
             crate::routes::sources::publications::create_publication,
             crate::routes::sources::publications::read_publication,
             crate::routes::sources::publications::update_publication,
             crate::routes::sources::publications::delete_publication,
             crate::routes::sources::publications::read_all_publications,
             crate::routes::sources::tables::read_table_names,
+            crate::routes::sources::tables::read_publication_table_infos,
             crate::routes::sinks::create_sink,
             crate::routes::sinks::read_sink,
             crate::routes::sinks::update_sink,
             crate::routes::sinks::delete_sink,
+            crate::routes::sinks::restore_sink,
             crate::routes::sinks::read_all_sinks,
+            crate::routes::audit_log::read_audit_log,
         ),
         components(schemas(
+            ErrorMessage,
+            ErrorBody,
+            ErrorCode,
             PostImageRequest,
             PostImageResponse,
             GetImageResponse,
             PostPipelineRequest,
             PostPipelineResponse,
+            PostPipelinesBatchItemResponse,
             GetPipelineResponse,
+            GetPipelineTableResponse,
+            PipelineTableStatus,
             CreateTenantRequest,
             PostTenantResponse,
             GetTenantResponse,
             PostSourceRequest,
             PostSourceResponse,
             GetSourceResponse,
+            ValidateSourceRequest,
+            ConnectivityReport,
             CreatePublicationRequest,
             UpdatePublicationRequest,
             Publication,
+            TableInfo,
             PostSinkRequest,
             PostSinkResponse,
             GetSinkResponse,
+            SourceConfig,
+            SlotOptions,
+            AuthMethod,
+            OutputPlugin,
+            SinkConfig,
+            PipelineConfig,
+            BatchConfig,
+            AdaptiveBatchConfig,
+            TableBatchConfig,
+            TableOperationsConfig,
+            DeadLetterConfig,
+            GetAuditRecordResponse,
+            AuditAction,
+            AuditResourceType,
         ))
     )]
     struct ApiDoc;
@@ -184,12 +245,37 @@ pub async fn run(
     //TODO: replace all the context_path = v1 in route modules with the nest attribute
     //when it is available in utoipa 5.0.0: https://github.com/juhaku/utoipa/pull/930
     let openapi = ApiDoc::openapi();
+    let openapi_data = web::Data::new(openapi.clone());
+    let prometheus_handle = web::Data::new(crate::metrics::prometheus_handle());
 
     let server = HttpServer::new(move || {
         let authentication = HttpAuthentication::bearer(auth_validator);
         let app = App::new()
             .wrap(TracingLogger::default())
+            .wrap_fn(|req, srv| {
+                let start = Instant::now();
+                let method = req.method().clone();
+                let path = req
+                    .match_pattern()
+                    .unwrap_or_else(|| "unmatched".to_string());
+                let response = srv.call(req);
+                async move {
+                    let response = response.await?;
+                    crate::metrics::record_request(
+                        &method,
+                        path,
+                        response.status(),
+                        start.elapsed(),
+                    );
+                    Ok(response)
+                }
+            })
+            .app_data(web::JsonConfig::default().error_handler(extractor_error_handler))
+            .app_data(web::PathConfig::default().error_handler(extractor_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(extractor_error_handler))
             .service(health_check)
+            .service(metrics)
+            .service(openapi_json)
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
             )
@@ -204,28 +290,36 @@ pub async fn run(
                     .service(delete_tenant)
                     .service(read_all_tenants)
                     //sources
+                    .service(validate_source)
                     .service(create_source)
                     .service(read_source)
                     .service(update_source)
                     .service(delete_source)
+                    .service(restore_source)
                     .service(read_all_sources)
                     //sinks
                     .service(create_sink)
                     .service(read_sink)
                     .service(update_sink)
                     .service(delete_sink)
+                    .service(restore_sink)
                     .service(read_all_sinks)
                     //pipelines
                     .service(create_pipeline)
+                    .service(create_pipelines)
                     .service(read_pipeline)
                     .service(update_pipeline)
                     .service(delete_pipeline)
+                    .service(restore_pipeline)
                     .service(read_all_pipelines)
                     .service(start_pipeline)
                     .service(stop_pipeline)
                     .service(get_pipeline_status)
+                    .service(read_pipeline_tables)
+                    .service(resync_pipeline_table)
                     //tables
                     .service(read_table_names)
+                    .service(read_publication_table_infos)
                     //publications
                     .service(create_publication)
                     .service(read_publication)
@@ -237,11 +331,16 @@ pub async fn run(
                     .service(read_image)
                     .service(update_image)
                     .service(delete_image)
-                    .service(read_all_images),
+                    .service(read_all_images)
+                    //audit log
+                    .service(read_audit_log),
             )
             .app_data(connection_pool.clone())
             .app_data(encryption_key.clone())
-            .app_data(api_key.clone());
+            .app_data(api_key.clone())
+            .app_data(telemetry.clone())
+            .app_data(openapi_data.clone())
+            .app_data(prometheus_handle.clone());
         if let Some(k8s_client) = k8s_client.clone() {
             app.app_data(k8s_client.clone())
         } else {
@@ -0,0 +1,195 @@
+use api::{
+    db::{
+        audit_log::{AuditAction, AuditResourceType},
+        sinks::SinkConfig,
+        sources::SourceConfig,
+    },
+    replicator_config::{AuthMethod, OutputPlugin, SlotOptions},
+};
+use serde_json::json;
+
+use crate::{
+    tenants::{create_tenant, create_tenant_with_id_and_name},
+    test_app::{
+        spawn_app, AuditRecordResponse, CreateSinkRequest, CreateSinkResponse, CreateSourceRequest,
+        CreateSourceResponse, TestApp, UpdateSinkRequest, UpdateSourceRequest,
+    },
+};
+
+const SERVICE_ACCOUNT_KEY: &str = "audited-service-account-key";
+
+const PASSWORD: &str = "audited-password";
+
+fn sink_config(dataset_id: &str) -> SinkConfig {
+    SinkConfig::BigQuery {
+        project_id: "project-id".to_string(),
+        dataset_id: dataset_id.to_string(),
+        service_account_key: SERVICE_ACCOUNT_KEY.to_string(),
+    }
+}
+
+fn source_config(port: u16) -> SourceConfig {
+    SourceConfig::Postgres {
+        host: "localhost".to_string(),
+        port,
+        name: "postgres".to_string(),
+        username: "postgres".to_string(),
+        password: Some(PASSWORD.to_string()),
+        slot_name: "slot".to_string(),
+        slot_options: SlotOptions::CreateIfMissing,
+        statement_timeout_ms: 0,
+        tcp_keepalives_idle_secs: 30,
+        connect_timeout_secs: None,
+        route_partitions_to_parent: false,
+        copy_prefetch_rows: 0,
+        auth: AuthMethod::Any,
+        output_plugin: OutputPlugin::Pgoutput,
+        stream_in_progress_transactions: false,
+        snapshot_per_table: false,
+        application_name: None,
+        socks5_proxy: None,
+        role: None,
+        search_path: None,
+    }
+}
+
+async fn read_audit_log(app: &TestApp, tenant_id: &str) -> (String, Vec<AuditRecordResponse>) {
+    let response = app.read_audit_log(tenant_id).await;
+    assert!(response.status().is_success());
+    let body = response.text().await.expect("failed to read response");
+    let records = serde_json::from_str(&body).expect("failed to deserialize response");
+    (body, records)
+}
+
+#[tokio::test]
+async fn updating_a_sink_records_its_config_before_and_after_the_update() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink = CreateSinkRequest {
+        name: "BigQuery Sink".to_string(),
+        config: sink_config("dataset-id"),
+    };
+    let response = app.create_sink(tenant_id, &sink).await;
+    let response: CreateSinkResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let sink_id = response.id;
+
+    // Act
+    let sink = UpdateSinkRequest {
+        name: "BigQuery Sink".to_string(),
+        config: sink_config("dataset-id-updated"),
+    };
+    let response = app
+        .update_sink_as(tenant_id, sink_id, &sink, "alice@example.com")
+        .await;
+    assert!(response.status().is_success());
+
+    // Assert
+    let (body, records) = read_audit_log(&app, tenant_id).await;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].action, AuditAction::Create);
+    assert_eq!(records[0].actor, "unknown");
+    let record = &records[1];
+    assert_eq!(record.actor, "alice@example.com");
+    assert_eq!(record.action, AuditAction::Update);
+    assert_eq!(record.resource_type, AuditResourceType::Sink);
+    assert_eq!(record.resource_id, sink_id);
+    let redacted_config = |dataset_id| {
+        json!({
+            "BigQuery": {
+                "project_id": "project-id",
+                "dataset_id": dataset_id,
+                "service_account_key": "REDACTED",
+            }
+        })
+    };
+    assert_eq!(
+        record.before,
+        Some(json!({ "name": "BigQuery Sink", "config": redacted_config("dataset-id") }))
+    );
+    assert_eq!(
+        record.after,
+        Some(json!({ "name": "BigQuery Sink", "config": redacted_config("dataset-id-updated") }))
+    );
+    assert!(!body.contains(SERVICE_ACCOUNT_KEY));
+}
+
+#[tokio::test]
+async fn source_passwords_never_appear_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source = CreateSourceRequest {
+        name: "Postgres Source".to_string(),
+        config: source_config(5432),
+    };
+    let response = app.create_source(tenant_id, &source).await;
+    let response: CreateSourceResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let source_id = response.id;
+
+    // Act
+    let source = UpdateSourceRequest {
+        name: "Postgres Source".to_string(),
+        config: source_config(5433),
+    };
+    let response = app.update_source(tenant_id, source_id, &source).await;
+    assert!(response.status().is_success());
+    let response = app.delete_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+
+    // Assert
+    let (body, records) = read_audit_log(&app, tenant_id).await;
+    let actions: Vec<AuditAction> = records.iter().map(|record| record.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::Create,
+            AuditAction::Update,
+            AuditAction::Delete
+        ]
+    );
+    assert!(records
+        .iter()
+        .all(|record| record.resource_type == AuditResourceType::Source
+            && record.resource_id == source_id));
+    let update = &records[1];
+    let before = update.before.as_ref().expect("update without before");
+    let after = update.after.as_ref().expect("update without after");
+    assert_eq!(before["config"]["Postgres"]["port"], 5432);
+    assert_eq!(after["config"]["Postgres"]["port"], 5433);
+    assert_eq!(after["config"]["Postgres"]["password"], "REDACTED");
+    assert!(records[2].after.is_none());
+    assert!(!body.contains(PASSWORD));
+}
+
+#[tokio::test]
+async fn audit_log_holds_only_the_changes_of_its_tenant() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let other_tenant_id = &create_tenant_with_id_and_name(
+        &app,
+        "tsrqponmlkjihgfedcba".to_string(),
+        "OtherTenant".to_string(),
+    )
+    .await;
+    let sink = CreateSinkRequest {
+        name: "BigQuery Sink".to_string(),
+        config: sink_config("dataset-id"),
+    };
+
+    // Act
+    app.create_sink(tenant_id, &sink).await;
+
+    // Assert
+    let (_, records) = read_audit_log(&app, tenant_id).await;
+    assert_eq!(records.len(), 1);
+    let (_, records) = read_audit_log(&app, other_tenant_id).await;
+    assert!(records.is_empty());
+}
@@ -1,6 +1,9 @@
+mod audit_log;
 mod database;
 mod health_check;
 mod images;
+mod metrics;
+mod openapi;
 mod pipelines;
 mod sinks;
 mod sources;
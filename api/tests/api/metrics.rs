@@ -0,0 +1,33 @@
+use crate::test_app::spawn_app;
+
+#[tokio::test]
+async fn metrics_are_served_in_prometheus_format() {
+    // Arrange
+    let app = spawn_app().await;
+    app.api_client
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Act
+    let response = app.read_metrics().await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let body = response.text().await.expect("failed to read response");
+    for line in body.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (_, value) = line
+            .rsplit_once(' ')
+            .unwrap_or_else(|| panic!("sample without a value: {line}"));
+        value
+            .parse::<f64>()
+            .unwrap_or_else(|_| panic!("sample with an invalid value: {line}"));
+    }
+    assert!(body.contains("# TYPE api_pipelines gauge"));
+    assert!(body.contains("# TYPE api_http_requests_total counter"));
+    assert!(body.contains(r#"path="/health_check""#));
+}
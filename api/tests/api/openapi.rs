@@ -0,0 +1,31 @@
+use serde_json::Value;
+
+use crate::test_app::spawn_app;
+
+#[tokio::test]
+async fn openapi_spec_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.read_openapi_spec().await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let spec: Value = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let paths = &spec["paths"];
+    assert!(paths["/v1/pipelines"]["post"].is_object());
+    assert!(paths["/v1/pipelines"]["get"].is_object());
+    assert!(paths["/v1/pipelines:batch"]["post"].is_object());
+    assert!(paths["/v1/pipelines/{pipeline_id}"]["get"].is_object());
+    assert!(paths["/v1/pipelines/{pipeline_id}"]["post"].is_object());
+    assert!(paths["/v1/pipelines/{pipeline_id}"]["delete"].is_object());
+    let schemas = &spec["components"]["schemas"];
+    assert!(schemas["BatchConfig"].is_object());
+    assert!(schemas["PipelineConfig"].is_object());
+    assert!(schemas["SourceConfig"].is_object());
+    assert!(schemas["SinkConfig"].is_object());
+}
@@ -1,15 +1,30 @@
-use api::db::pipelines::{BatchConfig, PipelineConfig};
+use std::collections::BTreeMap;
+
+use api::{
+    configuration::{get_settings, Settings},
+    db::{
+        pipeline_tables::{update_pipeline_table_status, PipelineTable, PipelineTableStatus},
+        pipelines::{
+            AdaptiveBatchConfig, BatchConfig, DeadLetterConfig, PipelineConfig, TableBatchConfig,
+            TableOperationsConfig,
+        },
+    },
+    routes::{ErrorCode, ErrorMessage},
+};
+use pg_escape::{quote_identifier, quote_literal};
 use reqwest::StatusCode;
+use sqlx::{Connection, Executor, PgConnection, Row};
+use uuid::Uuid;
 
 use crate::{
     images::create_default_image,
     sinks::create_sink,
-    sources::create_source,
+    sources::{create_source, create_test_database_source},
     tenants::create_tenant,
     tenants::create_tenant_with_id_and_name,
     test_app::{
-        spawn_app, CreatePipelineRequest, CreatePipelineResponse, PipelineResponse, TestApp,
-        UpdatePipelineRequest,
+        spawn_app, CreatePipelineRequest, CreatePipelineResponse, CreatePipelinesBatchItemResponse,
+        PipelineResponse, PipelineTableResponse, TestApp, UpdatePipelineRequest,
     },
 };
 
@@ -18,6 +33,15 @@ fn new_pipeline_config() -> PipelineConfig {
         config: BatchConfig {
             max_size: 1000,
             max_fill_secs: 5,
+            serialization_workers: 1,
+            max_in_flight_batches: 1,
+            flush_on_idle: false,
+            sink_write_timeout_secs: None,
+            sink_ready_timeout_secs: None,
+            adaptive: None,
+            table_overrides: BTreeMap::new(),
+            table_operations: BTreeMap::new(),
+            dead_letter: None,
         },
     }
 }
@@ -27,6 +51,35 @@ fn updated_pipeline_config() -> PipelineConfig {
         config: BatchConfig {
             max_size: 2000,
             max_fill_secs: 10,
+            serialization_workers: 4,
+            max_in_flight_batches: 2,
+            flush_on_idle: false,
+            sink_write_timeout_secs: Some(60),
+            sink_ready_timeout_secs: Some(300),
+            adaptive: Some(AdaptiveBatchConfig {
+                min_size: 100,
+                target_write_ms: 500,
+            }),
+            table_overrides: BTreeMap::from([(
+                16385,
+                TableBatchConfig {
+                    max_size: Some(10000),
+                    max_fill_secs: None,
+                },
+            )]),
+            table_operations: BTreeMap::from([(
+                16385,
+                TableOperationsConfig {
+                    insert: true,
+                    update: false,
+                    delete: false,
+                    truncate: false,
+                },
+            )]),
+            dead_letter: Some(DeadLetterConfig {
+                max_dead_letters: 10,
+                window_secs: 60,
+            }),
         },
     }
 }
@@ -111,6 +164,16 @@ async fn pipeline_with_another_tenants_source_cant_be_created() {
 
     // Assert
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::CrossTenant);
+    assert_eq!(
+        response.error.message,
+        format!("source with id {source2_id} not found")
+    );
+    assert_eq!(response.error.details["source_id"], source2_id);
 }
 
 #[tokio::test]
@@ -146,6 +209,208 @@ async fn pipeline_with_another_tenants_sink_cant_be_created() {
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
 
+/// Creates a publication with a unique name in the test database
+async fn create_test_publication(connection: &mut PgConnection) -> String {
+    let publication_name = format!("publication_{}", Uuid::new_v4().simple());
+    connection
+        .execute(&*format!(
+            "create publication {};",
+            quote_identifier(&publication_name)
+        ))
+        .await
+        .expect("Failed to create publication");
+    publication_name
+}
+
+#[tokio::test]
+async fn a_batch_of_pipelines_can_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let mut connection = connect_to_test_database().await;
+    let publication1_name = create_test_publication(&mut connection).await;
+    let publication2_name = create_test_publication(&mut connection).await;
+
+    // Act
+    let pipelines = [
+        CreatePipelineRequest {
+            source_id,
+            sink_id,
+            publication_name: publication1_name.clone(),
+            config: new_pipeline_config(),
+        },
+        CreatePipelineRequest {
+            source_id,
+            sink_id,
+            publication_name: publication2_name.clone(),
+            config: updated_pipeline_config(),
+        },
+    ];
+    let response = app.create_pipelines(tenant_id, &pipelines).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<CreatePipelinesBatchItemResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 2);
+    assert!(response.iter().all(|item| item.created));
+    let pipeline1: PipelineResponse = app
+        .read_pipeline(tenant_id, response[0].id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipeline1.publication_name, publication1_name);
+    assert_eq!(pipeline1.config, new_pipeline_config());
+    let pipeline2: PipelineResponse = app
+        .read_pipeline(tenant_id, response[1].id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(pipeline2.publication_name, publication2_name);
+    assert_eq!(pipeline2.config, updated_pipeline_config());
+}
+
+#[tokio::test]
+async fn a_batch_with_another_tenants_source_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant1_id = &create_tenant_with_id_and_name(
+        &app,
+        "abcdefghijklmnopqrst".to_string(),
+        "tenant_1".to_string(),
+    )
+    .await;
+    let tenant2_id = &create_tenant_with_id_and_name(
+        &app,
+        "tsrqponmlkjihgfedcba".to_string(),
+        "tenant_2".to_string(),
+    )
+    .await;
+    let (source1_id, _) = create_test_database_source(&app, tenant1_id).await;
+    let source2_id = create_source(&app, tenant2_id).await;
+    let sink1_id = create_sink(&app, tenant1_id).await;
+    let mut connection = connect_to_test_database().await;
+    let publication_name = create_test_publication(&mut connection).await;
+
+    // Act
+    let pipelines = [
+        CreatePipelineRequest {
+            source_id: source1_id,
+            sink_id: sink1_id,
+            publication_name: publication_name.clone(),
+            config: new_pipeline_config(),
+        },
+        CreatePipelineRequest {
+            source_id: source2_id,
+            sink_id: sink1_id,
+            publication_name,
+            config: new_pipeline_config(),
+        },
+    ];
+    let response = app.create_pipelines(tenant1_id, &pipelines).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::CrossTenant);
+    assert_eq!(response.error.details["index"], 1);
+    assert_eq!(response.error.details["source_id"], source2_id);
+    // Not even the valid pipeline was created
+    let response: Vec<PipelineResponse> = app
+        .read_all_pipelines(tenant1_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.is_empty());
+}
+
+#[tokio::test]
+async fn a_batch_with_a_missing_publication_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let publication_name = format!("publication_{}", Uuid::new_v4().simple());
+
+    // Act
+    let pipelines = [CreatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name: publication_name.clone(),
+        config: new_pipeline_config(),
+    }];
+    let response = app.create_pipelines(tenant_id, &pipelines).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::Validation);
+    assert_eq!(response.error.details["index"], 0);
+    assert_eq!(response.error.details["publication_name"], publication_name);
+}
+
+#[tokio::test]
+async fn a_retried_batch_creates_no_pipeline_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let mut connection = connect_to_test_database().await;
+    let publication_name = create_test_publication(&mut connection).await;
+    let pipelines = [CreatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name,
+        config: new_pipeline_config(),
+    }];
+    let response: Vec<CreatePipelinesBatchItemResponse> = app
+        .create_pipelines(tenant_id, &pipelines)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    let pipeline_id = response[0].id;
+
+    // Act
+    let response = app.create_pipelines(tenant_id, &pipelines).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<CreatePipelinesBatchItemResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 1);
+    assert_eq!(response[0].id, pipeline_id);
+    assert!(!response[0].created);
+    let response: Vec<PipelineResponse> = app
+        .read_all_pipelines(tenant_id)
+        .await
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 1);
+}
+
 #[tokio::test]
 async fn an_existing_pipeline_can_be_read() {
     // Arrange
@@ -373,7 +638,7 @@ async fn an_existing_pipeline_can_be_deleted() {
     let app = spawn_app().await;
     create_default_image(&app).await;
     let tenant_id = &create_tenant(&app).await;
-    let source_id = create_source(&app, tenant_id).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
     let sink_id = create_sink(&app, tenant_id).await;
 
     let pipeline = CreatePipelineRequest {
@@ -398,6 +663,181 @@ async fn an_existing_pipeline_can_be_deleted() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+async fn connect_to_test_database() -> PgConnection {
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    PgConnection::connect_with(&settings.database.with_db())
+        .await
+        .expect("Failed to connect to Postgres")
+}
+
+/// Returns the lsn of the oldest WAL which the slot named `slot_name` retains
+/// or `None` if there is no such slot
+async fn slot_restart_lsn(connection: &mut PgConnection, slot_name: &str) -> Option<String> {
+    let query = format!(
+        "select restart_lsn::text from pg_replication_slots where slot_name = {};",
+        quote_literal(slot_name)
+    );
+    connection
+        .fetch_optional(query.as_str())
+        .await
+        .expect("Failed to query pg_replication_slots")
+        .map(|row| row.get("restart_lsn"))
+}
+
+#[tokio::test]
+async fn deleting_a_pipeline_drops_its_slot() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, slot_name) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let mut connection = connect_to_test_database().await;
+    connection
+        .execute(&*format!(
+            "select pg_create_logical_replication_slot({}, 'pgoutput');",
+            quote_literal(&slot_name)
+        ))
+        .await
+        .expect("Failed to create slot");
+    assert!(slot_restart_lsn(&mut connection, &slot_name)
+        .await
+        .is_some());
+
+    // Act
+    let response = app.delete_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    // No slot retains WAL for the pipeline anymore
+    assert_eq!(slot_restart_lsn(&mut connection, &slot_name).await, None);
+}
+
+#[tokio::test]
+async fn deleting_a_pipeline_keeps_its_slot_when_asked_to() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, slot_name) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let mut connection = connect_to_test_database().await;
+    connection
+        .execute(&*format!(
+            "select pg_create_logical_replication_slot({}, 'pgoutput');",
+            quote_literal(&slot_name)
+        ))
+        .await
+        .expect("Failed to create slot");
+
+    // Act
+    let response = app
+        .delete_pipeline_with_query(tenant_id, pipeline_id, "keep_slot=true")
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    assert!(slot_restart_lsn(&mut connection, &slot_name)
+        .await
+        .is_some());
+    connection
+        .execute(&*format!(
+            "select pg_drop_replication_slot({});",
+            quote_literal(&slot_name)
+        ))
+        .await
+        .expect("Failed to drop slot");
+}
+
+#[tokio::test]
+async fn deleting_a_pipeline_keeps_the_slot_another_pipeline_of_its_source_uses() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, slot_name) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let other_pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let mut connection = connect_to_test_database().await;
+    connection
+        .execute(&*format!(
+            "select pg_create_logical_replication_slot({}, 'pgoutput');",
+            quote_literal(&slot_name)
+        ))
+        .await
+        .expect("Failed to create slot");
+
+    // Act
+    let response = app.delete_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    assert!(slot_restart_lsn(&mut connection, &slot_name)
+        .await
+        .is_some());
+
+    // The last pipeline of the source takes the slot with it
+    let response = app.delete_pipeline(tenant_id, other_pipeline_id).await;
+    assert!(response.status().is_success());
+    assert_eq!(slot_restart_lsn(&mut connection, &slot_name).await, None);
+}
+
+#[tokio::test]
+async fn deleting_a_pipeline_drops_its_publication_when_asked_to() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let publication_name = format!("publication_{}", Uuid::new_v4().simple());
+    let mut connection = connect_to_test_database().await;
+    connection
+        .execute(&*format!(
+            "create publication {};",
+            quote_identifier(&publication_name)
+        ))
+        .await
+        .expect("Failed to create publication");
+    let pipeline = CreatePipelineRequest {
+        source_id,
+        sink_id,
+        publication_name: publication_name.clone(),
+        config: new_pipeline_config(),
+    };
+    let response = app.create_pipeline(tenant_id, &pipeline).await;
+    let response: CreatePipelineResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+
+    // Act
+    let response = app
+        .delete_pipeline_with_query(tenant_id, response.id, "drop_publication=true")
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let query = format!(
+        "select exists (select 1 from pg_publication where pubname = {}) as exists;",
+        quote_literal(&publication_name)
+    );
+    let exists: bool = connection
+        .fetch_one(query.as_str())
+        .await
+        .expect("Failed to query pg_publication")
+        .get("exists");
+    assert!(!exists);
+}
+
 #[tokio::test]
 async fn a_non_existing_pipeline_cant_be_deleted() {
     // Arrange
@@ -411,6 +851,162 @@ async fn a_non_existing_pipeline_cant_be_deleted() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn a_deleted_pipeline_is_listed_only_when_deleted_pipelines_are_included() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    app.delete_pipeline(tenant_id, pipeline_id).await;
+
+    // Act
+    let response = app.read_all_pipelines(tenant_id).await;
+    let response_including_deleted = app.read_all_pipelines_including_deleted(tenant_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<PipelineResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.is_empty());
+    assert!(response_including_deleted.status().is_success());
+    let response: Vec<PipelineResponse> = response_including_deleted
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 1);
+    assert_eq!(response[0].id, pipeline_id);
+    assert!(response[0].deleted);
+}
+
+#[tokio::test]
+async fn a_deleted_pipeline_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    app.delete_pipeline(tenant_id, pipeline_id).await;
+
+    // Act
+    let response = app.restore_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_pipeline(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+    let response: PipelineResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, pipeline_id);
+    assert!(!response.deleted);
+}
+
+#[tokio::test]
+async fn a_pipeline_which_is_not_deleted_cant_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let response = app.restore_pipeline(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn a_source_used_by_a_pipeline_cant_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config()).await;
+
+    // Act
+    let response = app.delete_source(tenant_id, source_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::Conflict);
+    assert_eq!(response.error.details["source_id"], source_id);
+    let response = app.read_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn a_sink_used_by_a_pipeline_cant_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config()).await;
+
+    // Act
+    let response = app.delete_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::Conflict);
+    assert_eq!(response.error.details["sink_id"], sink_id);
+    let response = app.read_sink(tenant_id, sink_id).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn a_source_and_sink_can_be_deleted_after_their_pipeline() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let (source_id, _) = create_test_database_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let response = app.delete_pipeline(tenant_id, pipeline_id).await;
+    assert!(response.status().is_success());
+
+    // Act
+    let source_response = app.delete_source(tenant_id, source_id).await;
+    let sink_response = app.delete_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert!(source_response.status().is_success());
+    assert!(sink_response.status().is_success());
+}
+
 #[tokio::test]
 async fn all_pipelines_can_be_read() {
     // Arrange
@@ -459,3 +1055,162 @@ async fn all_pipelines_can_be_read() {
         }
     }
 }
+
+#[tokio::test]
+async fn a_new_pipeline_has_no_tables() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let response = app.read_pipeline_tables(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<PipelineTableResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.is_empty());
+}
+
+#[tokio::test]
+async fn pipeline_tables_are_copying_then_streaming() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    let mut table = PipelineTable {
+        table_id: 16384,
+        schema_name: "public".to_string(),
+        table_name: "users".to_string(),
+        status: PipelineTableStatus::Copying,
+        lsn: None,
+        resync_requested: false,
+    };
+
+    // Act
+    update_pipeline_table_status(&app.connection_pool, pipeline_id, &table)
+        .await
+        .expect("failed to update table status");
+    let copying_response = app.read_pipeline_tables(tenant_id, pipeline_id).await;
+    table.status = PipelineTableStatus::Streaming;
+    table.lsn = Some("0/16B3748".to_string());
+    update_pipeline_table_status(&app.connection_pool, pipeline_id, &table)
+        .await
+        .expect("failed to update table status");
+    let streaming_response = app.read_pipeline_tables(tenant_id, pipeline_id).await;
+
+    // Assert
+    assert!(copying_response.status().is_success());
+    let copying_response: Vec<PipelineTableResponse> = copying_response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(copying_response.len(), 1);
+    assert_eq!(copying_response[0].table_id, 16384);
+    assert_eq!(copying_response[0].schema_name, "public");
+    assert_eq!(copying_response[0].table_name, "users");
+    assert_eq!(copying_response[0].status, PipelineTableStatus::Copying);
+    assert_eq!(copying_response[0].lsn, None);
+
+    assert!(streaming_response.status().is_success());
+    let streaming_response: Vec<PipelineTableResponse> = streaming_response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(streaming_response.len(), 1);
+    assert_eq!(streaming_response[0].table_id, 16384);
+    assert_eq!(streaming_response[0].status, PipelineTableStatus::Streaming);
+    assert_eq!(streaming_response[0].lsn, Some("0/16B3748".to_string()));
+}
+
+#[tokio::test]
+async fn tables_of_a_non_existing_pipeline_cant_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let response = app.read_pipeline_tables(tenant_id, 42).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn a_pipeline_table_can_be_resynced() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+    for (table_id, table_name) in [(16384, "users"), (16385, "orders")] {
+        let table = PipelineTable {
+            table_id,
+            schema_name: "public".to_string(),
+            table_name: table_name.to_string(),
+            status: PipelineTableStatus::Streaming,
+            lsn: Some("0/16B3748".to_string()),
+            resync_requested: false,
+        };
+        update_pipeline_table_status(&app.connection_pool, pipeline_id, &table)
+            .await
+            .expect("failed to update table status");
+    }
+
+    // Act
+    let response = app
+        .resync_pipeline_table(tenant_id, pipeline_id, 16384)
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_pipeline_tables(tenant_id, pipeline_id).await;
+    let response: Vec<PipelineTableResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 2);
+    assert_eq!(response[0].table_name, "orders");
+    assert!(!response[0].resync_requested);
+    assert_eq!(response[1].table_name, "users");
+    assert!(response[1].resync_requested);
+    assert_eq!(response[1].status, PipelineTableStatus::Streaming);
+}
+
+#[tokio::test]
+async fn a_non_existing_pipeline_table_cant_be_resynced() {
+    // Arrange
+    let app = spawn_app().await;
+    create_default_image(&app).await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+    let pipeline_id =
+        create_pipeline_with_config(&app, tenant_id, source_id, sink_id, new_pipeline_config())
+            .await;
+
+    // Act
+    let response = app
+        .resync_pipeline_table(tenant_id, pipeline_id, 16384)
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
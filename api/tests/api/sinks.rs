@@ -211,6 +211,74 @@ async fn a_non_existing_sink_cant_be_deleted() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn a_deleted_sink_is_listed_only_when_deleted_sinks_are_included() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    app.delete_sink(tenant_id, sink_id).await;
+
+    // Act
+    let response = app.read_all_sinks(tenant_id).await;
+    let response_including_deleted = app.read_all_sinks_including_deleted(tenant_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<SinkResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.is_empty());
+    assert!(response_including_deleted.status().is_success());
+    let response: Vec<SinkResponse> = response_including_deleted
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 1);
+    assert_eq!(response[0].id, sink_id);
+    assert!(response[0].deleted);
+}
+
+#[tokio::test]
+async fn a_deleted_sink_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    app.delete_sink(tenant_id, sink_id).await;
+
+    // Act
+    let response = app.restore_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_sink(tenant_id, sink_id).await;
+    assert!(response.status().is_success());
+    let response: SinkResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, sink_id);
+    assert!(!response.deleted);
+}
+
+#[tokio::test]
+async fn a_sink_which_is_not_deleted_cant_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let sink_id = create_sink(&app, tenant_id).await;
+
+    // Act
+    let response = app.restore_sink(tenant_id, sink_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn all_sinks_can_be_read() {
     // Arrange
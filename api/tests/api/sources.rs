@@ -1,11 +1,20 @@
-use api::db::sources::SourceConfig;
+use api::{
+    configuration::{get_settings, Settings},
+    db::{connectivity::ConnectivityReport, sources::SourceConfig, tables::TableInfo},
+    replicator_config::{AuthMethod, OutputPlugin, SlotOptions},
+    routes::{ErrorCode, ErrorMessage},
+};
+use pg_escape::{quote_identifier, quote_literal};
 use reqwest::StatusCode;
+use secrecy::ExposeSecret;
+use sqlx::{Connection, Executor, PgConnection};
+use uuid::Uuid;
 
 use crate::{
     tenants::create_tenant,
     test_app::{
         spawn_app, CreateSourceRequest, CreateSourceResponse, SourceResponse, TestApp,
-        UpdateSourceRequest,
+        UpdateSourceRequest, ValidateSourceRequest,
     },
 };
 
@@ -21,6 +30,20 @@ fn new_source_config() -> SourceConfig {
         username: "postgres".to_string(),
         password: Some("postgres".to_string()),
         slot_name: "slot".to_string(),
+        slot_options: SlotOptions::CreateIfMissing,
+        statement_timeout_ms: 0,
+        tcp_keepalives_idle_secs: 30,
+        connect_timeout_secs: None,
+        route_partitions_to_parent: false,
+        copy_prefetch_rows: 0,
+        auth: AuthMethod::Any,
+        output_plugin: OutputPlugin::Pgoutput,
+        stream_in_progress_transactions: false,
+        snapshot_per_table: false,
+        application_name: None,
+        socks5_proxy: None,
+        role: None,
+        search_path: None,
     }
 }
 
@@ -36,6 +59,20 @@ fn updated_source_config() -> SourceConfig {
         username: "sergtsop".to_string(),
         password: Some("sergtsop".to_string()),
         slot_name: "tols".to_string(),
+        slot_options: SlotOptions::UseExisting,
+        statement_timeout_ms: 60_000,
+        tcp_keepalives_idle_secs: 10,
+        connect_timeout_secs: Some(5),
+        route_partitions_to_parent: false,
+        copy_prefetch_rows: 0,
+        auth: AuthMethod::ScramSha256ChannelBinding,
+        output_plugin: OutputPlugin::Pgoutput,
+        stream_in_progress_transactions: false,
+        snapshot_per_table: false,
+        application_name: None,
+        socks5_proxy: None,
+        role: None,
+        search_path: None,
     }
 }
 
@@ -123,6 +160,13 @@ async fn a_non_existing_source_cant_be_read() {
 
     // Assert
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::NotFound);
+    assert_eq!(response.error.message, "source with id 42 not found");
+    assert_eq!(response.error.details["source_id"], 42);
 }
 
 #[tokio::test]
@@ -220,6 +264,74 @@ async fn a_non_existing_source_cant_be_deleted() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn a_deleted_source_is_listed_only_when_deleted_sources_are_included() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+
+    app.delete_source(tenant_id, source_id).await;
+
+    // Act
+    let response = app.read_all_sources(tenant_id).await;
+    let response_including_deleted = app.read_all_sources_including_deleted(tenant_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<SourceResponse> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.is_empty());
+    assert!(response_including_deleted.status().is_success());
+    let response: Vec<SourceResponse> = response_including_deleted
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 1);
+    assert_eq!(response[0].id, source_id);
+    assert!(response[0].deleted);
+}
+
+#[tokio::test]
+async fn a_deleted_source_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+
+    app.delete_source(tenant_id, source_id).await;
+
+    // Act
+    let response = app.restore_source(tenant_id, source_id).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response = app.read_source(tenant_id, source_id).await;
+    assert!(response.status().is_success());
+    let response: SourceResponse = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.id, source_id);
+    assert!(!response.deleted);
+}
+
+#[tokio::test]
+async fn a_source_which_is_not_deleted_cant_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let source_id = create_source(&app, tenant_id).await;
+
+    // Act
+    let response = app.restore_source(tenant_id, source_id).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn all_sources_can_be_read() {
     // Arrange
@@ -255,3 +367,275 @@ async fn all_sources_can_be_read() {
         }
     }
 }
+
+/// Creates a source connecting to the test database, which, unlike the
+/// source of [`create_source`], the api can reach. Returns the source's id and
+/// its slot name, which is unique.
+pub async fn create_test_database_source(app: &TestApp, tenant_id: &str) -> (i64, String) {
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let password = settings
+        .database
+        .password
+        .as_ref()
+        .map(|p| p.expose_secret().to_string());
+    let config = test_database_source_config(settings.database.username, password);
+    let SourceConfig::Postgres { slot_name, .. } = &config;
+    let slot_name = slot_name.clone();
+    let source_id = create_source_with_config(app, tenant_id, new_name(), config).await;
+    (source_id, slot_name)
+}
+
+fn test_database_source_config(username: String, password: Option<String>) -> SourceConfig {
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    SourceConfig::Postgres {
+        host: settings.database.host,
+        port: settings.database.port,
+        name: settings.database.name,
+        username,
+        password,
+        slot_name: Uuid::new_v4().simple().to_string(),
+        slot_options: SlotOptions::CreateIfMissing,
+        statement_timeout_ms: 0,
+        tcp_keepalives_idle_secs: 30,
+        connect_timeout_secs: Some(5),
+        route_partitions_to_parent: false,
+        copy_prefetch_rows: 0,
+        auth: AuthMethod::Any,
+        output_plugin: OutputPlugin::Pgoutput,
+        stream_in_progress_transactions: false,
+        snapshot_per_table: false,
+        application_name: None,
+        socks5_proxy: None,
+        role: None,
+        search_path: None,
+    }
+}
+
+#[tokio::test]
+async fn a_valid_source_config_passes_validation() {
+    // Arrange
+    let app = spawn_app().await;
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let password = settings
+        .database
+        .password
+        .as_ref()
+        .map(|p| p.expose_secret().to_string());
+    let config = test_database_source_config(settings.database.username, password);
+
+    // Act
+    let source = ValidateSourceRequest {
+        config,
+        publication: None,
+    };
+    let response = app.validate_source(&source).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: ConnectivityReport = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.connected);
+    assert!(response.connection_error.is_none());
+    assert!(response.replication_privilege);
+    assert!(!response.slot_exists);
+    assert_eq!(response.publication_exists, None);
+}
+
+#[tokio::test]
+async fn source_connections_report_their_application_name() {
+    // Arrange
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let password = settings
+        .database
+        .password
+        .as_ref()
+        .map(|p| p.expose_secret().to_string());
+    let mut config = test_database_source_config(settings.database.username, password);
+    let SourceConfig::Postgres {
+        application_name, ..
+    } = &mut config;
+    *application_name = Some("pg_replicate-42".to_string());
+
+    // Act
+    let mut connection = PgConnection::connect_with(&config.connect_options())
+        .await
+        .expect("Failed to connect to Postgres");
+
+    // Assert
+    let reported: (Option<String>,) = sqlx::query_as(
+        "select application_name from pg_stat_activity where pid = pg_backend_pid()",
+    )
+    .fetch_one(&mut connection)
+    .await
+    .expect("Failed to query pg_stat_activity");
+    assert_eq!(reported.0.as_deref(), Some("pg_replicate-42"));
+}
+
+#[tokio::test]
+async fn composite_type_in_a_schema_on_the_search_path_resolves() {
+    // Arrange
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let password = settings
+        .database
+        .password
+        .as_ref()
+        .map(|p| p.expose_secret().to_string());
+    let mut config = test_database_source_config(settings.database.username, password);
+    let schema = format!("inventory_{}", Uuid::new_v4().simple());
+    let mut connection = PgConnection::connect_with(&config.connect_options())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(&*format!(
+            "create schema {schema}; create type {schema}.dimensions as (width int4, height int4);"
+        ))
+        .await
+        .expect("Failed to create composite type");
+    let SourceConfig::Postgres { search_path, .. } = &mut config;
+    *search_path = Some(vec![schema.clone(), "$user".to_string()]);
+
+    // Act
+    let mut search_path_connection = PgConnection::connect_with(&config.connect_options())
+        .await
+        .expect("Failed to connect to Postgres");
+    let resolved: (Option<String>,) =
+        sqlx::query_as("select to_regtype('dimensions')::oid::regtype::text")
+            .fetch_one(&mut search_path_connection)
+            .await
+            .expect("Failed to resolve type");
+
+    // Assert
+    assert_eq!(resolved.0, Some("dimensions".to_string()));
+    let without_search_path: (Option<String>,) =
+        sqlx::query_as("select to_regtype('dimensions')::oid::regtype::text")
+            .fetch_one(&mut connection)
+            .await
+            .expect("Failed to resolve type");
+    assert_eq!(without_search_path.0, None);
+    connection
+        .execute(&*format!("drop schema {schema} cascade"))
+        .await
+        .expect("Failed to drop schema");
+}
+
+#[tokio::test]
+async fn a_source_config_without_replication_privileges_fails_validation() {
+    // Arrange
+    let app = spawn_app().await;
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let username = format!("no_replication_{}", Uuid::new_v4().simple());
+    let password = "no_replication".to_string();
+    let mut connection = PgConnection::connect_with(&settings.database.with_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(&*format!(
+            "create role {} login nosuperuser noreplication password {};",
+            quote_identifier(&username),
+            quote_literal(&password)
+        ))
+        .await
+        .expect("Failed to create role");
+    let config = test_database_source_config(username, Some(password));
+
+    // Act
+    let source = ValidateSourceRequest {
+        config,
+        publication: None,
+    };
+    let response = app.validate_source(&source).await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: ConnectivityReport = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert!(response.connected);
+    assert!(!response.replication_privilege);
+    assert!(!response.slot_creatable);
+    assert!(!response.ok);
+}
+
+#[tokio::test]
+async fn publication_table_infos_can_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let password = settings
+        .database
+        .password
+        .as_ref()
+        .map(|p| p.expose_secret().to_string());
+    let config = test_database_source_config(settings.database.username.clone(), password);
+    let source_id = create_source_with_config(&app, tenant_id, new_name(), config).await;
+
+    let suffix = Uuid::new_v4().simple().to_string();
+    let with_pk = format!("with_pk_{suffix}");
+    let without_pk = format!("without_pk_{suffix}");
+    let publication_name = format!("publication_{suffix}");
+    let mut connection = PgConnection::connect_with(&settings.database.with_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(&*format!(
+            "create table public.{with_pk} (id int primary key);
+            insert into public.{with_pk} select generate_series(1, 100);
+            analyze public.{with_pk};
+            create table public.{without_pk} (id int);
+            create publication {publication_name} for table public.{with_pk}, public.{without_pk};"
+        ))
+        .await
+        .expect("Failed to create tables");
+
+    // Act
+    let response = app
+        .read_publication_table_infos(tenant_id, source_id, &publication_name)
+        .await;
+
+    // Assert
+    assert!(response.status().is_success());
+    let response: Vec<TableInfo> = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.len(), 2);
+
+    let table_info = &response[0];
+    assert_eq!(table_info.schema, "public");
+    assert_eq!(table_info.name, with_pk);
+    assert_eq!(table_info.estimated_row_count, Some(100));
+    assert!(table_info.size_bytes > 0);
+    assert!(table_info.has_replica_identity);
+
+    let table_info = &response[1];
+    assert_eq!(table_info.schema, "public");
+    assert_eq!(table_info.name, without_pk);
+    assert!(!table_info.has_replica_identity);
+}
+
+#[tokio::test]
+async fn table_infos_of_a_non_existing_publication_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+    let settings = get_settings::<'_, Settings>().expect("Failed to read configuration");
+    let password = settings
+        .database
+        .password
+        .as_ref()
+        .map(|p| p.expose_secret().to_string());
+    let config = test_database_source_config(settings.database.username.clone(), password);
+    let source_id = create_source_with_config(&app, tenant_id, new_name(), config).await;
+
+    // Act
+    let response = app
+        .read_publication_table_infos(tenant_id, source_id, "non_existing_publication")
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -1,3 +1,4 @@
+use api::routes::{ErrorCode, ErrorMessage};
 use reqwest::StatusCode;
 
 use crate::test_app::{
@@ -55,6 +56,29 @@ async fn tenant_can_be_created() {
     assert_eq!(response.name, tenant.name);
 }
 
+#[tokio::test]
+async fn tenant_with_an_existing_id_cant_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let tenant_id = &create_tenant(&app).await;
+
+    // Act
+    let tenant = CreateTenantRequest {
+        id: tenant_id.clone(),
+        name: "OtherTenant".to_string(),
+    };
+    let response = app.create_tenant(&tenant).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response: ErrorMessage = response
+        .json()
+        .await
+        .expect("failed to deserialize response");
+    assert_eq!(response.error.code, ErrorCode::Conflict);
+    assert_eq!(response.error.details["tenant_id"], tenant_id.as_str());
+}
+
 #[tokio::test]
 async fn create_or_update_tenant_creates_a_new_tenant() {
     // Arrange
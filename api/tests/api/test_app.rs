@@ -2,12 +2,19 @@ use std::net::TcpListener;
 
 use api::{
     configuration::{get_settings, Settings},
-    db::{pipelines::PipelineConfig, sinks::SinkConfig, sources::SourceConfig},
+    db::{
+        audit_log::{AuditAction, AuditResourceType},
+        pipeline_tables::PipelineTableStatus,
+        pipelines::PipelineConfig,
+        sinks::SinkConfig,
+        sources::SourceConfig,
+    },
     encryption::{self, generate_random_key},
     startup::{get_connection_pool, run},
 };
 use reqwest::{IntoUrl, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::database::configure_database;
@@ -16,6 +23,7 @@ pub struct TestApp {
     pub address: String,
     pub api_client: reqwest::Client,
     pub api_key: String,
+    pub connection_pool: PgPool,
 }
 
 #[derive(Serialize)]
@@ -51,6 +59,12 @@ pub struct CreateSourceResponse {
     pub id: i64,
 }
 
+#[derive(Serialize)]
+pub struct ValidateSourceRequest {
+    pub config: SourceConfig,
+    pub publication: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct UpdateSourceRequest {
     pub name: String,
@@ -63,6 +77,7 @@ pub struct SourceResponse {
     pub tenant_id: String,
     pub name: String,
     pub config: SourceConfig,
+    pub deleted: bool,
 }
 
 #[derive(Serialize)]
@@ -88,6 +103,7 @@ pub struct SinkResponse {
     pub tenant_id: String,
     pub name: String,
     pub config: SinkConfig,
+    pub deleted: bool,
 }
 
 #[derive(Serialize)]
@@ -103,6 +119,12 @@ pub struct CreatePipelineResponse {
     pub id: i64,
 }
 
+#[derive(Deserialize)]
+pub struct CreatePipelinesBatchItemResponse {
+    pub id: i64,
+    pub created: bool,
+}
+
 #[derive(Deserialize)]
 pub struct PipelineResponse {
     pub id: i64,
@@ -112,6 +134,17 @@ pub struct PipelineResponse {
     pub replicator_id: i64,
     pub publication_name: String,
     pub config: PipelineConfig,
+    pub deleted: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PipelineTableResponse {
+    pub table_id: i64,
+    pub schema_name: String,
+    pub table_name: String,
+    pub status: PipelineTableStatus,
+    pub lsn: Option<String>,
+    pub resync_requested: bool,
 }
 
 #[derive(Serialize)]
@@ -122,6 +155,18 @@ pub struct UpdatePipelineRequest {
     pub config: PipelineConfig,
 }
 
+#[derive(Deserialize)]
+pub struct AuditRecordResponse {
+    pub id: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    pub resource_type: AuditResourceType,
+    pub resource_id: i64,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub created_at: String,
+}
+
 #[derive(Serialize)]
 pub struct CreateImageRequest {
     pub name: String,
@@ -218,6 +263,22 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_openapi_spec(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/openapi.json", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn read_metrics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_source(
         &self,
         tenant_id: &str,
@@ -239,6 +300,30 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_publication_table_infos(
+        &self,
+        tenant_id: &str,
+        source_id: i64,
+        publication_name: &str,
+    ) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/sources/{source_id}/publications/{publication_name}/tables",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn validate_source(&self, source: &ValidateSourceRequest) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sources/validate", &self.address))
+            .json(source)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn update_source(
         &self,
         tenant_id: &str,
@@ -261,6 +346,14 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn restore_source(&self, tenant_id: &str, source_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sources/{source_id}/restore", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn read_all_sources(&self, tenant_id: &str) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/sources", &self.address))
             .header("tenant_id", tenant_id)
@@ -269,6 +362,14 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_all_sources_including_deleted(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/sources?include_deleted=true", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_sink(
         &self,
         tenant_id: &str,
@@ -304,6 +405,22 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn update_sink_as(
+        &self,
+        tenant_id: &str,
+        sink_id: i64,
+        sink: &UpdateSinkRequest,
+        actor: &str,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sinks/{sink_id}", &self.address))
+            .header("tenant_id", tenant_id)
+            .header("actor", actor)
+            .json(sink)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn delete_sink(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
         self.delete_authenticated(format!("{}/v1/sinks/{sink_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -312,6 +429,14 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn restore_sink(&self, tenant_id: &str, sink_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/sinks/{sink_id}/restore", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn read_all_sinks(&self, tenant_id: &str) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/sinks", &self.address))
             .header("tenant_id", tenant_id)
@@ -320,6 +445,14 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_all_sinks_including_deleted(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/sinks?include_deleted=true", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_pipeline(
         &self,
         tenant_id: &str,
@@ -333,6 +466,19 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn create_pipelines(
+        &self,
+        tenant_id: &str,
+        pipelines: &[CreatePipelineRequest],
+    ) -> reqwest::Response {
+        self.post_authenticated(format!("{}/v1/pipelines:batch", &self.address))
+            .header("tenant_id", tenant_id)
+            .json(pipelines)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn read_pipeline(&self, tenant_id: &str, pipeline_id: i64) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/pipelines/{pipeline_id}", &self.address))
             .header("tenant_id", tenant_id)
@@ -363,6 +509,33 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn delete_pipeline_with_query(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        query: &str,
+    ) -> reqwest::Response {
+        self.delete_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}?{query}",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+    }
+
+    pub async fn restore_pipeline(&self, tenant_id: &str, pipeline_id: i64) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/restore",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
     pub async fn read_all_pipelines(&self, tenant_id: &str) -> reqwest::Response {
         self.get_authenticated(format!("{}/v1/pipelines", &self.address))
             .header("tenant_id", tenant_id)
@@ -371,6 +544,56 @@ impl TestApp {
             .expect("failed to execute request")
     }
 
+    pub async fn read_all_pipelines_including_deleted(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/pipelines?include_deleted=true",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn read_pipeline_tables(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+    ) -> reqwest::Response {
+        self.get_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/tables",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn resync_pipeline_table(
+        &self,
+        tenant_id: &str,
+        pipeline_id: i64,
+        table_id: i64,
+    ) -> reqwest::Response {
+        self.post_authenticated(format!(
+            "{}/v1/pipelines/{pipeline_id}/tables/{table_id}/resync",
+            &self.address
+        ))
+        .header("tenant_id", tenant_id)
+        .send()
+        .await
+        .expect("failed to execute request")
+    }
+
+    pub async fn read_audit_log(&self, tenant_id: &str) -> reqwest::Response {
+        self.get_authenticated(format!("{}/v1/audit_log", &self.address))
+            .header("tenant_id", tenant_id)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn create_image(&self, image: &CreateImageRequest) -> reqwest::Response {
         self.post_authenticated(format!("{}/v1/images", &self.address))
             .json(image)
@@ -429,6 +652,7 @@ pub async fn spawn_app() -> TestApp {
         encryption_key,
         api_key.clone(),
         None,
+        None,
     )
     .await
     .expect("failed to bind address");
@@ -439,5 +663,6 @@ pub async fn spawn_app() -> TestApp {
         address,
         api_client,
         api_key,
+        connection_pool,
     }
 }
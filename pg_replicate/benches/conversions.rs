@@ -0,0 +1,159 @@
+//! Benchmarks of the conversion of rows from Postgres' text format, which
+//! runs for every column of every row copied or streamed.
+//!
+//! See the Performance section of the README for how to run them and compare
+//! against a baseline.
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pg_replicate::{
+    conversions::{
+        cdc_event::{CdcEventConverter, TupleColumnMapper},
+        table_row::TableRowConverter,
+    },
+    table::ColumnSchema,
+};
+use postgres_replication::protocol::TupleData;
+use tokio_postgres::types::Type;
+
+/// Types of common columns with a typical value of each in the text format
+const COLUMNS: &[(&str, Type, &str)] = &[
+    ("int", Type::INT8, "1234567890"),
+    (
+        "text",
+        Type::TEXT,
+        "The quick brown fox jumps over the lazy dog",
+    ),
+    (
+        "timestamp",
+        Type::TIMESTAMPTZ,
+        "2024-01-15 10:30:00.123456+00",
+    ),
+    ("numeric", Type::NUMERIC, "12345678.900123"),
+    (
+        "jsonb",
+        Type::JSONB,
+        r#"{"id": 42, "tags": ["a", "b"], "name": "widget", "price": 9.99}"#,
+    ),
+    ("int_array", Type::INT4_ARRAY, "{1,2,3,4,5,6,7,8}"),
+    (
+        "text_array",
+        Type::TEXT_ARRAY,
+        r#"{alpha,beta,"with space",NULL}"#,
+    ),
+];
+
+/// Number of columns of rows holding a single type
+const SINGLE_TYPE_ROW_WIDTH: usize = 16;
+
+/// Numbers of columns of rows cycling through all the types
+const MIXED_ROW_WIDTHS: &[usize] = &[8, 64];
+
+struct Row {
+    column_schemas: Vec<ColumnSchema>,
+    values: Vec<&'static str>,
+}
+
+impl Row {
+    fn new<'a>(columns: impl Iterator<Item = &'a (&'static str, Type, &'static str)>) -> Row {
+        let (column_schemas, values) = columns
+            .enumerate()
+            .map(|(i, (_, typ, value))| {
+                let column_schema = ColumnSchema {
+                    name: format!("column_{i}"),
+                    typ: typ.clone(),
+                    modifier: -1,
+                    nullable: true,
+                    primary: i == 0,
+                    identity: false,
+                    domain: None,
+                };
+                (column_schema, *value)
+            })
+            .unzip();
+        Row {
+            column_schemas,
+            values,
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The row as sent by `copy ... to stdout`
+    fn copy_row(&self) -> Vec<u8> {
+        let mut row = self.values.join("\t");
+        row.push('\n');
+        row.into_bytes()
+    }
+
+    /// The row as sent in an insert of the logical replication protocol
+    fn tuple_data(&self) -> Vec<TupleData> {
+        self.values
+            .iter()
+            .map(|&value| TupleData::Text(Bytes::from_static(value.as_bytes())))
+            .collect()
+    }
+}
+
+/// Rows of a single type each, and rows of mixed types of different widths
+fn rows() -> Vec<(String, Row)> {
+    let mut rows = vec![];
+    for column in COLUMNS {
+        let row = Row::new(std::iter::repeat(column).take(SINGLE_TYPE_ROW_WIDTH));
+        rows.push((column.0.to_string(), row));
+    }
+    for &width in MIXED_ROW_WIDTHS {
+        let row = Row::new(COLUMNS.iter().cycle().take(width));
+        rows.push((format!("mixed_{width}"), row));
+    }
+    rows
+}
+
+fn copy_rows(c: &mut Criterion) {
+    let mut group = c.benchmark_group("copy");
+    for (name, row) in rows() {
+        let copy_row = row.copy_row();
+        group.throughput(Throughput::Elements(row.width() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &copy_row,
+            |b, copy_row| {
+                b.iter(|| {
+                    TableRowConverter::try_from(copy_row, &row.column_schemas)
+                        .expect("failed to convert copy row")
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn cdc_rows(c: &mut Criterion) {
+    let tuple_columns = TupleColumnMapper::default();
+    let mut group = c.benchmark_group("cdc");
+    for (name, row) in rows() {
+        let tuple_data = row.tuple_data();
+        group.throughput(Throughput::Elements(row.width() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &tuple_data,
+            |b, tuple_data| {
+                b.iter(|| {
+                    CdcEventConverter::try_from_tuple_data_slice(
+                        1,
+                        &row.column_schemas,
+                        tuple_data,
+                        &tuple_columns,
+                    )
+                    .expect("failed to convert tuple")
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, copy_rows, cdc_rows);
+criterion_main!(benches);
@@ -2,6 +2,7 @@ use std::{error::Error, time::Duration};
 
 use clap::{Args, Parser, Subcommand};
 use pg_replicate::{
+    clients::postgres::{ConnectionOptions, SlotOptions},
     pipeline::{
         batching::{data_pipeline::BatchDataPipeline, BatchConfig},
         sinks::duckdb::DuckDbSink,
@@ -124,7 +125,9 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
                 &db_args.db_username,
                 db_args.db_password,
                 None,
+                SlotOptions::default(),
                 TableNamesFrom::Vec(table_names),
+                &ConnectionOptions::default(),
             )
             .await?;
             (postgres_source, PipelineAction::TableCopiesOnly)
@@ -140,7 +143,9 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
                 &db_args.db_username,
                 db_args.db_password,
                 Some(slot_name),
+                SlotOptions::default(),
                 TableNamesFrom::Publication(publication),
+                &ConnectionOptions::default(),
             )
             .await?;
 
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, fs};
+use std::{collections::HashSet, fmt::Display, fs};
 
 use bytes::{Buf, BufMut};
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
@@ -20,8 +20,9 @@ use tokio_postgres::types::{PgLsn, Type};
 use tracing::info;
 use uuid::Uuid;
 
+use crate::conversions::geometry::{Geometry, Point};
 use crate::conversions::numeric::PgNumeric;
-use crate::conversions::{ArrayCell, Cell};
+use crate::conversions::{hex, ArrayCell, Cell};
 use crate::{
     conversions::table_row::TableRow,
     table::{ColumnSchema, TableId, TableSchema},
@@ -30,6 +31,86 @@ use crate::{
 pub struct BigQueryClient {
     project_id: String,
     client: Client,
+    numeric_policy: NumericPolicy,
+}
+
+/// BigQuery's decimal types, whose ranges are narrower than Postgres' numeric
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BigQueryNumericType {
+    /// 29 digits before and 9 after the decimal point
+    Numeric,
+    /// 38 digits before and 38 after the decimal point
+    BigNumeric,
+}
+
+impl BigQueryNumericType {
+    /// Returns the narrowest type which holds `numeric`, or `None` if neither
+    /// does, like for `NaN`, the infinities and values with more digits
+    pub fn for_value(numeric: &PgNumeric) -> Option<BigQueryNumericType> {
+        let integer_digits = numeric.integer_digits()?;
+        let scale = numeric.scale()?;
+        if integer_digits <= 29 && scale <= 9 {
+            Some(BigQueryNumericType::Numeric)
+        } else if integer_digits <= 38 && scale <= 38 {
+            Some(BigQueryNumericType::BigNumeric)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for BigQueryNumericType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BigQueryNumericType::Numeric => f.write_str("numeric"),
+            BigQueryNumericType::BigNumeric => f.write_str("bignumeric"),
+        }
+    }
+}
+
+/// Which type numeric columns have in BigQuery, which decides what happens
+/// to values beyond the range of `NUMERIC`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumericPolicy {
+    /// Columns are `BIGNUMERIC`, which holds such values. Writing values
+    /// beyond its range fails.
+    #[default]
+    Promote,
+    /// Columns are `STRING` and values are written as text, which holds
+    /// every numeric including `NaN` and the infinities
+    Stringify,
+    /// Columns are `NUMERIC` and writing such values fails
+    Error,
+}
+
+impl NumericPolicy {
+    /// The type of numeric columns, `None` if they are strings
+    pub fn column_type(&self) -> Option<BigQueryNumericType> {
+        match self {
+            NumericPolicy::Promote => Some(BigQueryNumericType::BigNumeric),
+            NumericPolicy::Stringify => None,
+            NumericPolicy::Error => Some(BigQueryNumericType::Numeric),
+        }
+    }
+
+    /// Fails with the type of numeric columns if `numeric` is beyond its range
+    pub fn check(&self, numeric: &PgNumeric) -> Result<(), BigQueryNumericType> {
+        let Some(column_type) = self.column_type() else {
+            return Ok(());
+        };
+        match BigQueryNumericType::for_value(numeric) {
+            Some(value_type) if value_type <= column_type => Ok(()),
+            _ => Err(column_type),
+        }
+    }
+}
+
+/// A column of a table in BigQuery as reported by INFORMATION_SCHEMA.COLUMNS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigQueryColumn {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
 }
 
 //TODO: fix all SQL injections
@@ -42,7 +123,11 @@ impl BigQueryClient {
         let service_account_key = parse_service_account_key(gcp_sa_key)?;
         let client = Client::from_service_account_key(service_account_key, false).await?;
 
-        Ok(BigQueryClient { project_id, client })
+        Ok(BigQueryClient {
+            project_id,
+            client,
+            numeric_policy: NumericPolicy::default(),
+        })
     }
 
     pub async fn new_with_key(
@@ -52,7 +137,27 @@ impl BigQueryClient {
         let service_account_key = parse_service_account_key(gcp_sa_key)?;
         let client = Client::from_service_account_key(service_account_key, false).await?;
 
-        Ok(BigQueryClient { project_id, client })
+        Ok(BigQueryClient {
+            project_id,
+            client,
+            numeric_policy: NumericPolicy::default(),
+        })
+    }
+
+    /// Sets the type numeric columns are created with, see [`NumericPolicy`]
+    pub fn set_numeric_policy(&mut self, numeric_policy: NumericPolicy) {
+        self.numeric_policy = numeric_policy;
+    }
+
+    pub fn numeric_policy(&self) -> NumericPolicy {
+        self.numeric_policy
+    }
+
+    /// Replaces the client with one authenticated by a new service account key
+    pub async fn reload_key(&mut self, gcp_sa_key: &str) -> Result<(), BQError> {
+        let service_account_key = parse_service_account_key(gcp_sa_key)?;
+        self.client = Client::from_service_account_key(service_account_key, false).await?;
+        Ok(())
     }
 
     pub async fn create_table_if_missing(
@@ -104,6 +209,18 @@ impl BigQueryClient {
         }
     }
 
+    /// Returns the type of a column for `typ`, which for numerics depends on
+    /// the numeric policy
+    fn bigquery_type(&self, typ: &Type) -> &'static str {
+        match (typ, self.numeric_policy) {
+            (&Type::NUMERIC, NumericPolicy::Stringify) => "string",
+            (&Type::NUMERIC, NumericPolicy::Error) => "numeric",
+            (&Type::NUMERIC_ARRAY, NumericPolicy::Stringify) => "array<string>",
+            (&Type::NUMERIC_ARRAY, NumericPolicy::Error) => "array<numeric>",
+            _ => Self::postgres_to_bigquery_type(typ),
+        }
+    }
+
     fn is_array_type(typ: &Type) -> bool {
         matches!(
             typ,
@@ -131,12 +248,12 @@ impl BigQueryClient {
         )
     }
 
-    fn column_spec(column_schema: &ColumnSchema, s: &mut String) {
+    fn column_spec(&self, column_schema: &ColumnSchema, s: &mut String) {
         s.push('`');
         s.push_str(&column_schema.name);
         s.push('`');
         s.push(' ');
-        let typ = Self::postgres_to_bigquery_type(&column_schema.typ);
+        let typ = self.bigquery_type(&column_schema.typ);
         s.push_str(typ);
         if !column_schema.nullable && !Self::is_array_type(&column_schema.typ) {
             s.push_str(" not null");
@@ -159,12 +276,12 @@ impl BigQueryClient {
         s.push_str(") not enforced");
     }
 
-    fn create_columns_spec(column_schemas: &[ColumnSchema]) -> String {
+    fn create_columns_spec(&self, column_schemas: &[ColumnSchema]) -> String {
         let mut s = String::new();
         s.push('(');
 
         for column_schema in column_schemas.iter() {
-            Self::column_spec(column_schema, &mut s);
+            self.column_spec(column_schema, &mut s);
             s.push(',');
         }
 
@@ -190,7 +307,7 @@ impl BigQueryClient {
         table_name: &str,
         column_schemas: &[ColumnSchema],
     ) -> Result<(), BQError> {
-        let columns_spec = Self::create_columns_spec(column_schemas);
+        let columns_spec = self.create_columns_spec(column_schemas);
         let max_staleness_option = Self::max_staleness_option(5);
         let project_id = &self.project_id;
         info!("creating table {project_id}.{dataset_id}.{table_name} in bigquery");
@@ -200,6 +317,70 @@ impl BigQueryClient {
         Ok(())
     }
 
+    /// Returns the column a table created by [`BigQueryClient::create_table`]
+    /// would have for `column_schema`
+    pub fn expected_column(&self, column_schema: &ColumnSchema) -> BigQueryColumn {
+        BigQueryColumn {
+            name: column_schema.name.clone(),
+            data_type: self.bigquery_type(&column_schema.typ).to_string(),
+            nullable: column_schema.nullable || Self::is_array_type(&column_schema.typ),
+        }
+    }
+
+    pub async fn get_table_columns(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+    ) -> Result<Vec<BigQueryColumn>, BQError> {
+        let query = format!(
+            "select column_name, data_type, is_nullable
+            from {dataset_id}.INFORMATION_SCHEMA.COLUMNS
+            where table_name = '{table_name}'
+            order by ordinal_position;",
+        );
+
+        let mut rs = self.query(query).await?;
+
+        let mut columns = vec![];
+        while rs.next_row() {
+            let name = rs
+                .get_string_by_name("column_name")?
+                .expect("no column named `column_name` found in query result");
+            let data_type = rs
+                .get_string_by_name("data_type")?
+                .expect("no column named `data_type` found in query result");
+            let is_nullable = rs
+                .get_string_by_name("is_nullable")?
+                .expect("no column named `is_nullable` found in query result");
+            columns.push(BigQueryColumn {
+                name,
+                data_type,
+                nullable: is_nullable == "YES",
+            });
+        }
+
+        Ok(columns)
+    }
+
+    /// Adds a nullable column for `column_schema`. BigQuery doesn't allow
+    /// adding required columns to an existing table.
+    pub async fn add_column(
+        &self,
+        dataset_id: &str,
+        table_name: &str,
+        column_schema: &ColumnSchema,
+    ) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        let column_name = &column_schema.name;
+        let typ = self.bigquery_type(&column_schema.typ);
+        info!("adding column {column_name} to table {project_id}.{dataset_id}.{table_name} in bigquery");
+        let query = format!(
+            "alter table `{project_id}.{dataset_id}.{table_name}` add column `{column_name}` {typ}"
+        );
+        let _ = self.query(query).await?;
+        Ok(())
+    }
+
     pub async fn get_default_stream(
         &mut self,
         dataset_id: &str,
@@ -258,6 +439,23 @@ impl BigQueryClient {
         Ok((lsn as u64).into())
     }
 
+    pub async fn get_row_count(&self, dataset_id: &str, table_name: &str) -> Result<u64, BQError> {
+        let project_id = &self.project_id;
+        let query =
+            format!("select count(*) as row_count from `{project_id}.{dataset_id}.{table_name}`",);
+
+        let mut rs = self.query(query).await?;
+
+        let row_count: i64 = if rs.next_row() {
+            rs.get_i64_by_name("row_count")?
+                .expect("no column named `row_count` found in query result")
+        } else {
+            0
+        };
+
+        Ok(row_count as u64)
+    }
+
     pub async fn set_last_lsn(&self, dataset_id: &str, lsn: PgLsn) -> Result<(), BQError> {
         let lsn: u64 = lsn.into();
 
@@ -314,6 +512,31 @@ impl BigQueryClient {
         Ok(())
     }
 
+    pub async fn delete_from_copied_tables(
+        &self,
+        dataset_id: &str,
+        table_id: TableId,
+    ) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        let query = format!(
+            "delete from `{project_id}.{dataset_id}.copied_tables` where table_id = {table_id}",
+        );
+
+        let _ = self.query(query).await?;
+
+        Ok(())
+    }
+
+    pub async fn truncate_table(&self, dataset_id: &str, table_name: &str) -> Result<(), BQError> {
+        let project_id = &self.project_id;
+        info!("truncating table {project_id}.{dataset_id}.{table_name} in bigquery");
+        let query = format!("truncate table `{project_id}.{dataset_id}.{table_name}`",);
+
+        let _ = self.query(query).await?;
+
+        Ok(())
+    }
+
     pub async fn insert_row(
         &self,
         dataset_id: &str,
@@ -327,12 +550,12 @@ impl BigQueryClient {
         Ok(())
     }
 
-    pub async fn stream_rows(
+    pub async fn stream_rows<M: Message>(
         &mut self,
         dataset_id: &str,
         table_name: String,
         table_descriptor: &TableDescriptor,
-        mut table_rows: &[TableRow],
+        mut table_rows: &[M],
     ) -> Result<(), BQError> {
         let default_stream = StreamName::new_default(
             self.project_id.clone(),
@@ -406,7 +629,7 @@ impl BigQueryClient {
         match cell {
             Cell::Null => s.push_str("null"),
             Cell::Bool(b) => s.push_str(&format!("{b}")),
-            Cell::String(str) => s.push_str(&format!("'{str}'")),
+            Cell::String(str) | Cell::Xml(str) => s.push_str(&format!("'{str}'")),
             Cell::I16(i) => s.push_str(&format!("{i}")),
             Cell::I32(i) => s.push_str(&format!("{i}")),
             Cell::I64(i) => s.push_str(&format!("{i}")),
@@ -424,6 +647,8 @@ impl BigQueryClient {
                 let bytes: String = b.iter().map(|b| *b as char).collect();
                 s.push_str(&format!("b'{bytes}'"))
             }
+            Cell::Geometry(g) => s.push_str(&format!("'{g}'")),
+            Cell::Ewkb(b) => s.push_str(&format!("'{}'", hex::to_hex(b))),
             Cell::Array(_) => unreachable!(),
         }
     }
@@ -576,6 +801,47 @@ impl BigQueryClient {
     }
 }
 
+/// A table row already encoded as a protobuf message, so that rows can be
+/// encoded in parallel before they are streamed
+#[derive(Debug, Default)]
+pub struct EncodedRow(Vec<u8>);
+
+impl EncodedRow {
+    pub fn new(table_row: &TableRow) -> EncodedRow {
+        EncodedRow(table_row.encode_to_vec())
+    }
+}
+
+impl Message for EncodedRow {
+    fn encode_raw(&self, buf: &mut impl BufMut)
+    where
+        Self: Sized,
+    {
+        buf.put_slice(&self.0);
+    }
+
+    fn merge_field(
+        &mut self,
+        _tag: u32,
+        _wire_type: prost::encoding::WireType,
+        _buf: &mut impl Buf,
+        _ctx: prost::encoding::DecodeContext,
+    ) -> Result<(), prost::DecodeError>
+    where
+        Self: Sized,
+    {
+        unimplemented!("merge_field not implemented yet");
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
 impl Message for TableRow {
     fn encode_raw(&self, buf: &mut impl BufMut)
     where
@@ -625,7 +891,7 @@ impl Cell {
             Cell::Bool(b) => {
                 ::prost::encoding::bool::encode(tag, b, buf);
             }
-            Cell::String(s) => {
+            Cell::String(s) | Cell::Xml(s) => {
                 ::prost::encoding::string::encode(tag, s, buf);
             }
             Cell::I16(i) => {
@@ -678,6 +944,14 @@ impl Cell {
             Cell::Bytes(b) => {
                 ::prost::encoding::bytes::encode(tag, b, buf);
             }
+            Cell::Geometry(g) => {
+                let s = g.to_string();
+                ::prost::encoding::string::encode(tag, &s, buf)
+            }
+            Cell::Ewkb(b) => {
+                let s = hex::to_hex(b);
+                ::prost::encoding::string::encode(tag, &s, buf)
+            }
             Cell::Array(a) => {
                 a.clone().encode_raw(tag, buf);
             }
@@ -688,7 +962,7 @@ impl Cell {
         match self {
             Cell::Null => 0,
             Cell::Bool(b) => ::prost::encoding::bool::encoded_len(tag, b),
-            Cell::String(s) => ::prost::encoding::string::encoded_len(tag, s),
+            Cell::String(s) | Cell::Xml(s) => ::prost::encoding::string::encoded_len(tag, s),
             Cell::I16(i) => {
                 let val = *i as i32;
                 ::prost::encoding::int32::encoded_len(tag, &val)
@@ -727,6 +1001,14 @@ impl Cell {
             }
             Cell::U32(i) => ::prost::encoding::uint32::encoded_len(tag, i),
             Cell::Bytes(b) => ::prost::encoding::bytes::encoded_len(tag, b),
+            Cell::Geometry(g) => {
+                let s = g.to_string();
+                ::prost::encoding::string::encoded_len(tag, &s)
+            }
+            Cell::Ewkb(b) => {
+                let s = hex::to_hex(b);
+                ::prost::encoding::string::encoded_len(tag, &s)
+            }
             Cell::Array(array_cell) => array_cell.clone().encoded_len(tag),
         }
     }
@@ -735,7 +1017,7 @@ impl Cell {
         match self {
             Cell::Null => {}
             Cell::Bool(b) => *b = false,
-            Cell::String(s) => s.clear(),
+            Cell::String(s) | Cell::Xml(s) => s.clear(),
             Cell::I16(i) => *i = 0,
             Cell::I32(i) => *i = 0,
             Cell::I64(i) => *i = 0,
@@ -749,7 +1031,8 @@ impl Cell {
             Cell::Uuid(u) => *u = Uuid::default(),
             Cell::Json(j) => *j = serde_json::Value::default(),
             Cell::U32(u) => *u = 0,
-            Cell::Bytes(b) => b.clear(),
+            Cell::Bytes(b) | Cell::Ewkb(b) => b.clear(),
+            Cell::Geometry(g) => *g = Geometry::Point(Point::default()),
             Cell::Array(vec) => {
                 vec.clear();
             }
@@ -1074,13 +1357,72 @@ impl From<&TableSchema> for TableDescriptor {
             number += 1;
         }
 
-        field_descriptors.push(FieldDescriptor {
-            number,
-            name: "_CHANGE_TYPE".to_string(),
-            typ: ColumnType::String,
-            mode: ColumnMode::Required,
-        });
+        // BigQuery only accepts change types for tables with a primary key,
+        // rows of other tables are always appended
+        if table_schema.has_primary_keys() {
+            field_descriptors.push(FieldDescriptor {
+                number,
+                name: "_CHANGE_TYPE".to_string(),
+                typ: ColumnType::String,
+                mode: ColumnMode::Required,
+            });
+        }
 
         TableDescriptor { field_descriptors }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::conversions::numeric::PgNumeric;
+
+    use super::{BigQueryNumericType, NumericPolicy};
+
+    fn numeric(value: &str) -> PgNumeric {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn numerics_are_classified_by_the_narrowest_type_holding_them() {
+        let within_numeric = numeric("12345678901234567890.123456789");
+        let needs_bignumeric = numeric("123456789012345678901234567890.5");
+        let exceeds_both = numeric(&format!("1{}", "0".repeat(40)));
+
+        assert_eq!(
+            BigQueryNumericType::for_value(&within_numeric),
+            Some(BigQueryNumericType::Numeric)
+        );
+        assert_eq!(
+            BigQueryNumericType::for_value(&needs_bignumeric),
+            Some(BigQueryNumericType::BigNumeric)
+        );
+        assert_eq!(BigQueryNumericType::for_value(&exceeds_both), None);
+        assert_eq!(BigQueryNumericType::for_value(&PgNumeric::NaN), None);
+    }
+
+    #[test]
+    fn numerics_beyond_the_column_type_are_rejected_per_policy() {
+        let within_numeric = numeric("1.5");
+        let needs_bignumeric = numeric("0.1234567890");
+        let exceeds_both = numeric(&format!("1{}", "0".repeat(40)));
+
+        let policy = NumericPolicy::Promote;
+        assert!(policy.check(&within_numeric).is_ok());
+        assert!(policy.check(&needs_bignumeric).is_ok());
+        assert_eq!(
+            policy.check(&exceeds_both),
+            Err(BigQueryNumericType::BigNumeric)
+        );
+
+        let policy = NumericPolicy::Error;
+        assert!(policy.check(&within_numeric).is_ok());
+        assert_eq!(
+            policy.check(&needs_bignumeric),
+            Err(BigQueryNumericType::Numeric)
+        );
+
+        let policy = NumericPolicy::Stringify;
+        assert!(policy.check(&exceeds_both).is_ok());
+        assert!(policy.check(&PgNumeric::NaN).is_ok());
+    }
+}
@@ -10,7 +10,7 @@ use std::{collections::HashMap, sync::Arc};
 use tokio_postgres::types::{PgLsn, Type};
 
 use crate::{
-    conversions::{table_row::TableRow, Cell},
+    conversions::{hex, table_row::TableRow, Cell},
     table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 use deltalake::arrow::array::{
@@ -234,8 +234,12 @@ impl DeltaClient {
                 Arc::new(StringArray::from(vec![result]))
             }
             Cell::Json(value) => Arc::new(StringArray::from(vec![value.to_string()])),
+            Cell::Geometry(value) => Arc::new(StringArray::from(vec![value.to_string()])),
+            Cell::Ewkb(value) => Arc::new(StringArray::from(vec![hex::to_hex(value)])),
             Cell::Bool(value) => Arc::new(BooleanArray::from(vec![*value])),
-            Cell::String(value) => Arc::new(StringArray::from(vec![value.to_string()])),
+            Cell::String(value) | Cell::Xml(value) => {
+                Arc::new(StringArray::from(vec![value.to_string()]))
+            }
             Cell::I16(value) => Arc::new(Int32Array::from(vec![*value as i32])),
             Cell::I32(value) => Arc::new(Int32Array::from(vec![*value])),
             Cell::U32(value) => Arc::new(UInt32Array::from(vec![*value])),
@@ -281,6 +285,8 @@ impl DeltaClient {
                 modifier: 0,
                 nullable: false,
                 primary: true,
+                identity: false,
+                domain: None,
             },
             ColumnSchema {
                 name: "lsn".to_string(),
@@ -288,6 +294,8 @@ impl DeltaClient {
                 modifier: 0,
                 nullable: false,
                 primary: false,
+                identity: false,
+                domain: None,
             },
         ];
 
@@ -8,7 +8,7 @@ use duckdb::{
 use tokio_postgres::types::{PgLsn, Type};
 
 use crate::{
-    conversions::{table_row::TableRow, ArrayCell, Cell},
+    conversions::{hex, table_row::TableRow, ArrayCell, Cell},
     table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 
@@ -137,22 +137,37 @@ impl DuckDbClient {
         }
     }
 
-    fn duckdb_column_spec(column_schema: &ColumnSchema, s: &mut String) {
+    /// Name of the sequence generating the values of an identity column,
+    /// named like Postgres names those of `serial` columns
+    fn sequence_name(table_name: &TableName, column_name: &str) -> String {
+        format!(
+            "{}.{}_{}_seq",
+            table_name.schema, table_name.name, column_name
+        )
+    }
+
+    fn duckdb_column_spec(table_name: &TableName, column_schema: &ColumnSchema, s: &mut String) {
         s.push_str(&column_schema.name);
         s.push(' ');
         let typ = Self::postgres_to_duckdb_type(&column_schema.typ);
         s.push_str(typ);
+        // DuckDB has no identity columns, but a default from a sequence is
+        // what Postgres' serial columns are too
+        if column_schema.identity {
+            let sequence_name = Self::sequence_name(table_name, &column_schema.name);
+            s.push_str(&format!(" default nextval('{sequence_name}')"));
+        }
         if column_schema.primary {
             s.push_str(" primary key");
         };
     }
 
-    fn create_columns_spec(column_schemas: &[ColumnSchema]) -> String {
+    fn create_columns_spec(table_name: &TableName, column_schemas: &[ColumnSchema]) -> String {
         let mut s = String::new();
         s.push('(');
 
         for (i, column_schema) in column_schemas.iter().enumerate() {
-            Self::duckdb_column_spec(column_schema, &mut s);
+            Self::duckdb_column_spec(table_name, column_schema, &mut s);
             if i < column_schemas.len() - 1 {
                 s.push_str(", ");
             }
@@ -168,7 +183,12 @@ impl DuckDbClient {
         table_name: &TableName,
         column_schemas: &[ColumnSchema],
     ) -> Result<(), duckdb::Error> {
-        let columns_spec = Self::create_columns_spec(column_schemas);
+        for column_schema in column_schemas.iter().filter(|cs| cs.identity) {
+            let sequence_name = Self::sequence_name(table_name, &column_schema.name);
+            let query = format!("create sequence if not exists {sequence_name}");
+            self.conn.execute(&query, [])?;
+        }
+        let columns_spec = Self::create_columns_spec(table_name, column_schemas);
         let query = format!(
             "create table {}.{} {}",
             table_name.schema, table_name.name, columns_spec
@@ -218,40 +238,39 @@ impl DuckDbClient {
         s
     }
 
+    /// Updates the row whose replica identity columns, at `key_columns`,
+    /// have the values in `key_row`
     pub fn update_row(
         &self,
         table_schema: &TableSchema,
+        key_columns: &[usize],
+        key_row: &TableRow,
         table_row: &TableRow,
     ) -> Result<(), duckdb::Error> {
         let table_name = &table_schema.table_name;
         let column_schemas = &table_schema.column_schemas;
         let table_name = format!("{}.{}", table_name.schema, table_name.name);
-        let query = Self::create_update_row_query(&table_name, column_schemas);
+        let query = Self::create_update_row_query(&table_name, column_schemas, key_columns);
         let mut stmt = self.conn.prepare(&query)?;
-        let non_identity_cells = column_schemas
-            .iter()
-            .zip(table_row.values.iter())
-            .filter(|(s, _)| !s.primary)
-            .map(|(_, c)| c);
-        let identity_cells = column_schemas
-            .iter()
-            .zip(table_row.values.iter())
-            .filter(|(s, _)| s.primary)
-            .map(|(_, c)| c);
-        stmt.execute(params_from_iter(non_identity_cells.chain(identity_cells)))?;
+        let key_cells = key_columns.iter().map(|&i| &key_row.values[i]);
+        stmt.execute(params_from_iter(table_row.values.iter().chain(key_cells)))?;
         Ok(())
     }
 
-    fn create_update_row_query(table_name: &str, column_schemas: &[ColumnSchema]) -> String {
+    fn create_update_row_query(
+        table_name: &str,
+        column_schemas: &[ColumnSchema],
+        key_columns: &[usize],
+    ) -> String {
         let mut s = String::new();
 
         s.push_str("update ");
         s.push_str(table_name);
         s.push_str(" set ");
 
+        // key columns are set too, as the update may have changed them
         let mut remove_comma = false;
-        let non_identity_columns = column_schemas.iter().filter(|s| !s.primary);
-        for column in non_identity_columns {
+        for column in column_schemas {
             s.push_str(&column.name);
             s.push_str(" = ?,");
             remove_comma = true;
@@ -260,17 +279,21 @@ impl DuckDbClient {
             s.pop();
         }
 
-        Self::add_identities_where_clause(&mut s, column_schemas);
+        Self::add_identities_where_clause(&mut s, column_schemas, key_columns);
 
         s
     }
 
     /// Adds a where clause for the identity columns
-    fn add_identities_where_clause(s: &mut String, column_schemas: &[ColumnSchema]) {
+    fn add_identities_where_clause(
+        s: &mut String,
+        column_schemas: &[ColumnSchema],
+        key_columns: &[usize],
+    ) {
         s.push_str(" where ");
 
         let mut remove_and = false;
-        let identity_columns = column_schemas.iter().filter(|s| s.primary);
+        let identity_columns = key_columns.iter().map(|&i| &column_schemas[i]);
         for column in identity_columns {
             s.push_str(&column.name);
             s.push_str(" = ? and ");
@@ -285,32 +308,35 @@ impl DuckDbClient {
         }
     }
 
+    /// Deletes the row whose replica identity columns, at `key_columns`,
+    /// have the values in `table_row`
     pub fn delete_row(
         &self,
         table_schema: &TableSchema,
+        key_columns: &[usize],
         table_row: &TableRow,
     ) -> Result<(), duckdb::Error> {
         let table_name = &table_schema.table_name;
         let column_schemas = &table_schema.column_schemas;
         let table_name = format!("{}.{}", table_name.schema, table_name.name);
-        let query = Self::create_delete_row_query(&table_name, column_schemas);
+        let query = Self::create_delete_row_query(&table_name, column_schemas, key_columns);
         let mut stmt = self.conn.prepare(&query)?;
-        let identity_cells = column_schemas
-            .iter()
-            .zip(table_row.values.iter())
-            .filter(|(s, _)| s.primary)
-            .map(|(_, c)| c);
-        stmt.execute(params_from_iter(identity_cells))?;
+        let key_cells = key_columns.iter().map(|&i| &table_row.values[i]);
+        stmt.execute(params_from_iter(key_cells))?;
         Ok(())
     }
 
-    fn create_delete_row_query(table_name: &str, column_schemas: &[ColumnSchema]) -> String {
+    fn create_delete_row_query(
+        table_name: &str,
+        column_schemas: &[ColumnSchema],
+        key_columns: &[usize],
+    ) -> String {
         let mut s = String::new();
 
         s.push_str("delete from ");
         s.push_str(table_name);
 
-        Self::add_identities_where_clause(&mut s, column_schemas);
+        Self::add_identities_where_clause(&mut s, column_schemas, key_columns);
 
         s
     }
@@ -384,7 +410,7 @@ impl From<Cell> for Value {
         match value {
             Cell::Null => Value::Null,
             Cell::Bool(b) => Value::Boolean(b),
-            Cell::String(s) => Value::Text(s),
+            Cell::String(s) | Cell::Xml(s) => Value::Text(s),
             Cell::I16(i) => Value::SmallInt(i),
             Cell::I32(i) => Value::Int(i),
             Cell::U32(u) => Value::UInt(u),
@@ -420,6 +446,8 @@ impl From<Cell> for Value {
                 Value::Text(s)
             }
             Cell::Bytes(b) => Value::Blob(b),
+            Cell::Geometry(g) => Value::Text(g.to_string()),
+            Cell::Ewkb(b) => Value::Text(hex::to_hex(&b)),
             Cell::Array(a) => a.into(),
         }
     }
@@ -599,3 +627,74 @@ impl ToSql for Cell {
         Ok(ToSqlOutput::Owned(value))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::Type;
+
+    use crate::table::{ColumnSchema, TableName};
+
+    use super::DuckDbClient;
+
+    fn column_schema(name: &str, typ: Type, primary: bool, identity: bool) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: !primary,
+            primary,
+            identity,
+            domain: None,
+        }
+    }
+
+    #[test]
+    fn serial_column_is_created_with_a_default_from_a_sequence() {
+        let client = DuckDbClient::open_in_memory().unwrap();
+        let table_name = TableName {
+            schema: "public".to_string(),
+            name: "items".to_string(),
+        };
+        let column_schemas = [
+            column_schema("id", Type::INT4, true, true),
+            column_schema("name", Type::TEXT, false, false),
+        ];
+
+        client.create_schema_if_missing("public").unwrap();
+        client.create_table(&table_name, &column_schemas).unwrap();
+        client
+            .conn
+            .execute("insert into public.items (name) values ('a'), ('b')", [])
+            .unwrap();
+
+        let mut stmt = client
+            .conn
+            .prepare("select id from public.items order by id")
+            .unwrap();
+        let ids: Vec<i32> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn only_identity_columns_have_a_default() {
+        let table_name = TableName {
+            schema: "public".to_string(),
+            name: "items".to_string(),
+        };
+        let column_schemas = [
+            column_schema("id", Type::INT8, true, true),
+            column_schema("count", Type::INT8, false, false),
+        ];
+
+        let columns_spec = DuckDbClient::create_columns_spec(&table_name, &column_schemas);
+
+        assert_eq!(
+            columns_spec,
+            "(id int8 default nextval('public.items_id_seq') primary key, count int8)"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashSet;
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+use tokio_postgres::types::{Kind, PgLsn, Type};
+use tracing::info;
+
+use crate::{
+    conversions::table_row::TableRow,
+    table::{ColumnSchema, TableId, TableName},
+};
+
+#[derive(Debug, Error)]
+pub enum ElasticsearchClientError {
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("request to {0} failed with status {1}: {2}")]
+    Request(String, StatusCode, String),
+
+    #[error("bulk request failed: {0}")]
+    Bulk(String),
+}
+
+/// A single operation in a bulk request
+pub enum BulkOperation {
+    /// Index a document, replacing any existing document with the same id.
+    /// Without an id the document gets an auto-generated one.
+    Index {
+        index: String,
+        id: Option<String>,
+        document: Map<String, Value>,
+    },
+    Delete {
+        index: String,
+        id: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct ReplicationState {
+    copied_tables: HashSet<TableId>,
+    last_lsn: u64,
+}
+
+/// A client for Elasticsearch or OpenSearch. Each table is stored in its own
+/// index, named `{index_prefix}{schema}.{table}` in lower case.
+pub struct ElasticsearchClient {
+    client: reqwest::Client,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+    index_prefix: String,
+}
+
+const STATE_DOCUMENT_ID: &str = "state";
+
+impl ElasticsearchClient {
+    pub fn new(
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+        index_prefix: String,
+    ) -> ElasticsearchClient {
+        ElasticsearchClient {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            username,
+            password,
+            index_prefix,
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let builder = self.client.request(method, format!("{}/{path}", self.url));
+        match &self.username {
+            Some(username) => builder.basic_auth(username, self.password.as_ref()),
+            None => builder,
+        }
+    }
+
+    async fn check_response(
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, ElasticsearchClientError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let url = response.url().to_string();
+        let body = response.text().await?;
+        Err(ElasticsearchClientError::Request(url, status, body))
+    }
+
+    pub fn index_name(&self, table_name: &TableName) -> String {
+        format!("{}{}", self.index_prefix, table_name).to_lowercase()
+    }
+
+    fn state_index_name(&self) -> String {
+        format!("{}_pg_replicate", self.index_prefix).to_lowercase()
+    }
+
+    pub async fn index_exists(&self, index: &str) -> Result<bool, ElasticsearchClientError> {
+        let response = self.request(Method::HEAD, index).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        Self::check_response(response).await?;
+        Ok(true)
+    }
+
+    /// Creates an index for a table with a mapping derived from its column
+    /// schemas, unless the index already exists
+    pub async fn create_index_if_missing(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<(), ElasticsearchClientError> {
+        let index = self.index_name(table_name);
+        if self.index_exists(&index).await? {
+            return Ok(());
+        }
+
+        let mut properties = Map::new();
+        for column_schema in column_schemas {
+            properties.insert(
+                column_schema.name.clone(),
+                Self::postgres_to_elasticsearch(&column_schema.typ),
+            );
+        }
+        let body = json!({ "mappings": { "properties": properties } });
+
+        info!("creating index {index}");
+        let response = self.request(Method::PUT, &index).json(&body).send().await?;
+        Self::check_response(response).await?;
+        Ok(())
+    }
+
+    fn postgres_to_elasticsearch(typ: &Type) -> Value {
+        if let Kind::Array(typ) = typ.kind() {
+            // Elasticsearch fields hold arrays of their mapped type
+            return Self::postgres_to_elasticsearch(typ);
+        }
+        match *typ {
+            Type::BOOL => json!({ "type": "boolean" }),
+            Type::INT2 => json!({ "type": "short" }),
+            Type::INT4 => json!({ "type": "integer" }),
+            Type::INT8 | Type::OID => json!({ "type": "long" }),
+            Type::FLOAT4 => json!({ "type": "float" }),
+            Type::FLOAT8 => json!({ "type": "double" }),
+            Type::DATE => json!({ "type": "date", "format": "strict_date" }),
+            Type::TIMESTAMP | Type::TIMESTAMPTZ => json!({ "type": "date" }),
+            Type::BYTEA => json!({ "type": "binary" }),
+            Type::JSON | Type::JSONB => json!({ "type": "object", "enabled": false }),
+            Type::CHAR | Type::BPCHAR | Type::VARCHAR | Type::NAME | Type::TEXT | Type::XML => {
+                json!({
+                    "type": "text",
+                    "fields": { "keyword": { "type": "keyword", "ignore_above": 256 } }
+                })
+            }
+            // numeric is a keyword to not lose precision
+            _ => json!({ "type": "keyword" }),
+        }
+    }
+
+    /// Deletes all documents of a table's index, if it exists
+    pub async fn delete_all_documents(
+        &self,
+        table_name: &TableName,
+    ) -> Result<(), ElasticsearchClientError> {
+        let index = self.index_name(table_name);
+        if !self.index_exists(&index).await? {
+            return Ok(());
+        }
+        info!("deleting all documents in index {index}");
+        let response = self
+            .request(
+                Method::POST,
+                &format!("{index}/_delete_by_query?refresh=true&conflicts=proceed"),
+            )
+            .json(&json!({ "query": { "match_all": {} } }))
+            .send()
+            .await?;
+        Self::check_response(response).await?;
+        Ok(())
+    }
+
+    /// Sends `operations` in a single bulk request. Fails if any operation
+    /// failed, except deletes of documents which don't exist.
+    pub async fn bulk(
+        &self,
+        operations: Vec<BulkOperation>,
+    ) -> Result<(), ElasticsearchClientError> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        let mut body = vec![];
+        for operation in operations {
+            match operation {
+                BulkOperation::Index {
+                    index,
+                    id,
+                    document,
+                } => {
+                    let action = match id {
+                        Some(id) => json!({ "index": { "_index": index, "_id": id } }),
+                        None => json!({ "index": { "_index": index } }),
+                    };
+                    serde_json::to_writer(&mut body, &action)?;
+                    body.push(b'\n');
+                    serde_json::to_writer(&mut body, &document)?;
+                    body.push(b'\n');
+                }
+                BulkOperation::Delete { index, id } => {
+                    let action = json!({ "delete": { "_index": index, "_id": id } });
+                    serde_json::to_writer(&mut body, &action)?;
+                    body.push(b'\n');
+                }
+            }
+        }
+
+        let response = self
+            .request(Method::POST, "_bulk")
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body)
+            .send()
+            .await?;
+        let response: Value = Self::check_response(response).await?.json().await?;
+
+        if response["errors"].as_bool() != Some(true) {
+            return Ok(());
+        }
+        let items = response["items"].as_array().into_iter().flatten();
+        for item in items {
+            let Some(result) = item.as_object().and_then(|item| item.values().next()) else {
+                continue;
+            };
+            let status = result["status"].as_u64().unwrap_or_default();
+            let is_missing_delete =
+                item.get("delete").is_some() && status == StatusCode::NOT_FOUND.as_u16() as u64;
+            if status >= 300 && !is_missing_delete {
+                return Err(ElasticsearchClientError::Bulk(result["error"].to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_state(&self) -> Result<ReplicationState, ElasticsearchClientError> {
+        let path = format!("{}/_doc/{STATE_DOCUMENT_ID}", self.state_index_name());
+        let response = self.request(Method::GET, &path).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(ReplicationState::default());
+        }
+        let response: Value = Self::check_response(response).await?.json().await?;
+        Ok(serde_json::from_value(response["_source"].clone())?)
+    }
+
+    async fn put_state(&self, state: &ReplicationState) -> Result<(), ElasticsearchClientError> {
+        let path = format!(
+            "{}/_doc/{STATE_DOCUMENT_ID}?refresh=true",
+            self.state_index_name()
+        );
+        let response = self.request(Method::PUT, &path).json(state).send().await?;
+        Self::check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn get_copied_table_ids(&self) -> Result<HashSet<TableId>, ElasticsearchClientError> {
+        Ok(self.get_state().await?.copied_tables)
+    }
+
+    pub async fn insert_into_copied_tables(
+        &self,
+        table_id: TableId,
+    ) -> Result<(), ElasticsearchClientError> {
+        let mut state = self.get_state().await?;
+        state.copied_tables.insert(table_id);
+        self.put_state(&state).await
+    }
+
+    pub async fn get_last_lsn(&self) -> Result<PgLsn, ElasticsearchClientError> {
+        Ok(self.get_state().await?.last_lsn.into())
+    }
+
+    pub async fn set_last_lsn(&self, lsn: PgLsn) -> Result<(), ElasticsearchClientError> {
+        let mut state = self.get_state().await?;
+        state.last_lsn = lsn.into();
+        self.put_state(&state).await
+    }
+
+    /// Converts a table row to a document
+    pub fn table_row_to_document(
+        column_schemas: &[ColumnSchema],
+        table_row: &TableRow,
+    ) -> Map<String, Value> {
+        column_schemas
+            .iter()
+            .zip(&table_row.values)
+            .map(|(column_schema, cell)| (column_schema.name.clone(), cell.to_json_value()))
+            .collect()
+    }
+
+    /// Returns the document id of a table row, made of its primary key
+    /// values, or `None` if the table has no primary key
+    pub fn document_id(column_schemas: &[ColumnSchema], table_row: &TableRow) -> Option<String> {
+        let key_values: Vec<String> = column_schemas
+            .iter()
+            .zip(&table_row.values)
+            .filter(|(column_schema, _)| column_schema.primary)
+            .map(|(_, cell)| match cell.to_json_value() {
+                Value::String(s) => s,
+                value => value.to_string(),
+            })
+            .collect();
+        if key_values.is_empty() {
+            None
+        } else {
+            Some(key_values.join("|"))
+        }
+    }
+}
@@ -4,4 +4,9 @@ pub mod bigquery;
 pub mod delta;
 #[cfg(feature = "duckdb")]
 pub mod duckdb;
+#[cfg(feature = "elasticsearch")]
+pub mod elasticsearch;
+#[cfg(feature = "object_store")]
+pub mod object_store;
 pub mod postgres;
+pub mod socks5;
@@ -0,0 +1,491 @@
+use std::{collections::HashSet, sync::Arc};
+
+use arrow::{
+    datatypes::{DataType, Field, Schema},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use object_store::{
+    aws::AmazonS3Builder, azure::MicrosoftAzureBuilder, gcp::GoogleCloudStorageBuilder,
+    memory::InMemory, path::Path, ObjectStore, PutPayload,
+};
+use parquet::{arrow::ArrowWriter, errors::ParquetError};
+use serde_json::Map;
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    conversions::{
+        arrow::{ArrowConversionError, ArrowConverter},
+        table_row::TableRow,
+    },
+    table::{ColumnSchema, TableId, TableName},
+};
+
+/// The object store backend to write to
+#[derive(Clone)]
+pub enum ObjectStoreBackend {
+    S3 {
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+    },
+    Gcs {
+        bucket: String,
+        service_account_key: String,
+    },
+    Azure {
+        account: String,
+        container: String,
+        access_key: String,
+    },
+    /// An in-memory store, useful for tests
+    Memory,
+}
+
+/// The format of the files written to the object store
+#[derive(Debug, Clone, Copy)]
+pub enum ObjectFormat {
+    /// One JSON object per line
+    Jsonl,
+    Parquet,
+}
+
+impl ObjectFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ObjectFormat::Jsonl => "jsonl",
+            ObjectFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ObjectStoreClientError {
+    #[error("object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+
+    #[error("arrow conversion error: {0}")]
+    ArrowConversion(#[from] ArrowConversionError),
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReplicationState {
+    copied_tables: HashSet<TableId>,
+    last_lsn: u64,
+}
+
+/// A client which writes table rows as files to an object store. Files are
+/// partitioned by table, e.g. `{prefix}/{schema}.{table}/{uuid}.jsonl`.
+pub struct ObjectStoreClient {
+    backend: ObjectStoreBackend,
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    format: ObjectFormat,
+}
+
+pub const CHANGE_TYPE_COLUMN_NAME: &str = "_change_type";
+
+impl ObjectStoreClient {
+    pub fn new(
+        backend: ObjectStoreBackend,
+        prefix: &str,
+        format: ObjectFormat,
+    ) -> Result<ObjectStoreClient, ObjectStoreClientError> {
+        let store = Self::build_store(backend.clone())?;
+        Ok(ObjectStoreClient {
+            backend,
+            store,
+            prefix: Path::from(prefix),
+            format,
+        })
+    }
+
+    fn build_store(
+        backend: ObjectStoreBackend,
+    ) -> Result<Arc<dyn ObjectStore>, ObjectStoreClientError> {
+        let store: Arc<dyn ObjectStore> = match backend {
+            ObjectStoreBackend::S3 {
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+            } => Arc::new(
+                AmazonS3Builder::new()
+                    .with_bucket_name(bucket)
+                    .with_region(region)
+                    .with_access_key_id(access_key_id)
+                    .with_secret_access_key(secret_access_key)
+                    .build()?,
+            ),
+            ObjectStoreBackend::Gcs {
+                bucket,
+                service_account_key,
+            } => Arc::new(
+                GoogleCloudStorageBuilder::new()
+                    .with_bucket_name(bucket)
+                    .with_service_account_key(service_account_key)
+                    .build()?,
+            ),
+            ObjectStoreBackend::Azure {
+                account,
+                container,
+                access_key,
+            } => Arc::new(
+                MicrosoftAzureBuilder::new()
+                    .with_account(account)
+                    .with_container_name(container)
+                    .with_access_key(access_key)
+                    .build()?,
+            ),
+            ObjectStoreBackend::Memory => Arc::new(InMemory::new()),
+        };
+        Ok(store)
+    }
+
+    pub fn backend(&self) -> &ObjectStoreBackend {
+        &self.backend
+    }
+
+    /// Replaces the store with one built from `backend`, e.g. to pick up
+    /// rotated credentials. Files written before are kept.
+    pub fn reload_backend(
+        &mut self,
+        backend: ObjectStoreBackend,
+    ) -> Result<(), ObjectStoreClientError> {
+        self.store = Self::build_store(backend.clone())?;
+        self.backend = backend;
+        Ok(())
+    }
+
+    fn state_path(&self) -> Path {
+        self.prefix.child("_pg_replicate").child("state.json")
+    }
+
+    fn table_path(&self, table_name: &TableName) -> Path {
+        self.prefix.child(table_name.to_string())
+    }
+
+    async fn get_state(&self) -> Result<ReplicationState, ObjectStoreClientError> {
+        match self.store.get(&self.state_path()).await {
+            Ok(result) => {
+                let bytes = result.bytes().await?;
+                Ok(serde_json::from_slice(&bytes)?)
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(ReplicationState {
+                copied_tables: HashSet::new(),
+                last_lsn: 0,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put_state(&self, state: &ReplicationState) -> Result<(), ObjectStoreClientError> {
+        let bytes = serde_json::to_vec(state)?;
+        self.store
+            .put(&self.state_path(), PutPayload::from(bytes))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_copied_table_ids(&self) -> Result<HashSet<TableId>, ObjectStoreClientError> {
+        Ok(self.get_state().await?.copied_tables)
+    }
+
+    pub async fn insert_into_copied_tables(
+        &self,
+        table_id: TableId,
+    ) -> Result<(), ObjectStoreClientError> {
+        let mut state = self.get_state().await?;
+        state.copied_tables.insert(table_id);
+        self.put_state(&state).await
+    }
+
+    pub async fn get_last_lsn(&self) -> Result<PgLsn, ObjectStoreClientError> {
+        Ok(self.get_state().await?.last_lsn.into())
+    }
+
+    pub async fn set_last_lsn(&self, lsn: PgLsn) -> Result<(), ObjectStoreClientError> {
+        let mut state = self.get_state().await?;
+        state.last_lsn = lsn.into();
+        self.put_state(&state).await
+    }
+
+    /// Deletes all files written for a table
+    pub async fn delete_table_files(
+        &self,
+        table_name: &TableName,
+    ) -> Result<(), ObjectStoreClientError> {
+        let table_path = self.table_path(table_name);
+        info!("deleting files under {table_path}");
+        let mut objects = self.store.list(Some(&table_path));
+        while let Some(object) = objects.next().await {
+            self.store.delete(&object?.location).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes `table_rows` to a new file under the table's path. The last value
+    /// of each row must be the change type of the row.
+    pub async fn write_rows(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+        table_rows: &[TableRow],
+    ) -> Result<Path, ObjectStoreClientError> {
+        let bytes = match self.format {
+            ObjectFormat::Jsonl => Self::encode_jsonl(column_schemas, table_rows)?,
+            ObjectFormat::Parquet => Self::encode_parquet(column_schemas, table_rows)?,
+        };
+        let file_name = format!("{}.{}", Uuid::new_v4(), self.format.extension());
+        let path = self.table_path(table_name).child(file_name);
+        self.store.put(&path, PutPayload::from(bytes)).await?;
+        Ok(path)
+    }
+
+    fn column_names(column_schemas: &[ColumnSchema]) -> impl Iterator<Item = &str> {
+        column_schemas
+            .iter()
+            .map(|cs| cs.name.as_str())
+            .chain(std::iter::once(CHANGE_TYPE_COLUMN_NAME))
+    }
+
+    fn encode_jsonl(
+        column_schemas: &[ColumnSchema],
+        table_rows: &[TableRow],
+    ) -> Result<Bytes, ObjectStoreClientError> {
+        let mut buf = vec![];
+        for table_row in table_rows {
+            let mut object = Map::new();
+            for (name, cell) in Self::column_names(column_schemas).zip(&table_row.values) {
+                object.insert(name.to_string(), cell.to_json_value());
+            }
+            serde_json::to_writer(&mut buf, &object)?;
+            buf.push(b'\n');
+        }
+        Ok(buf.into())
+    }
+
+    fn encode_parquet(
+        column_schemas: &[ColumnSchema],
+        table_rows: &[TableRow],
+    ) -> Result<Bytes, ObjectStoreClientError> {
+        let fields: Vec<Field> = ArrowConverter::fields(column_schemas)
+            .into_iter()
+            .chain(std::iter::once(Field::new(
+                CHANGE_TYPE_COLUMN_NAME,
+                DataType::Utf8,
+                false,
+            )))
+            .collect();
+        let columns = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| ArrowConverter::column_to_array(field.data_type(), table_rows, i))
+            .collect::<Result<Vec<_>, _>>()?;
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        let mut buf = vec![];
+        let mut writer = ArrowWriter::try_new(&mut buf, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(buf.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, Int32Array, StringArray};
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use object_store::path::Path;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::{json, Value};
+    use tokio_postgres::types::{PgLsn, Type};
+
+    use crate::{
+        conversions::{table_row::TableRow, Cell},
+        table::{ColumnSchema, TableName},
+    };
+
+    use super::{ObjectFormat, ObjectStoreBackend, ObjectStoreClient};
+
+    fn client(format: ObjectFormat) -> ObjectStoreClient {
+        ObjectStoreClient::new(ObjectStoreBackend::Memory, "exports", format).unwrap()
+    }
+
+    fn table_name(name: &str) -> TableName {
+        TableName {
+            schema: "public".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn column_schemas() -> Vec<ColumnSchema> {
+        [("id", Type::INT4), ("name", Type::TEXT)]
+            .into_iter()
+            .map(|(name, typ)| ColumnSchema {
+                name: name.to_string(),
+                typ,
+                modifier: -1,
+                nullable: true,
+                primary: name == "id",
+                identity: false,
+                domain: None,
+            })
+            .collect()
+    }
+
+    fn table_rows() -> Vec<TableRow> {
+        vec![
+            TableRow {
+                values: vec![
+                    Cell::I32(1),
+                    Cell::String("alice".to_string()),
+                    Cell::String("UPSERT".to_string()),
+                ],
+            },
+            TableRow {
+                values: vec![Cell::I32(2), Cell::Null, Cell::String("DELETE".to_string())],
+            },
+        ]
+    }
+
+    async fn files(client: &ObjectStoreClient) -> Vec<String> {
+        let mut files: Vec<String> = client
+            .store
+            .list(None)
+            .map(|object| object.unwrap().location.to_string())
+            .collect()
+            .await;
+        files.sort();
+        files
+    }
+
+    async fn read(client: &ObjectStoreClient, path: &Path) -> Bytes {
+        client.store.get(path).await.unwrap().bytes().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn jsonl_rows_are_written_to_a_new_file_under_the_table_path() {
+        let client = client(ObjectFormat::Jsonl);
+
+        let first = client
+            .write_rows(&table_name("users"), &column_schemas(), &table_rows())
+            .await
+            .unwrap();
+        let second = client
+            .write_rows(&table_name("users"), &column_schemas(), &table_rows()[..1])
+            .await
+            .unwrap();
+
+        assert_ne!(first, second);
+        for path in [&first, &second] {
+            assert!(path.as_ref().starts_with("exports/public.users/"), "{path}");
+            assert!(path.as_ref().ends_with(".jsonl"), "{path}");
+        }
+        let mut expected = vec![first.to_string(), second.to_string()];
+        expected.sort();
+        assert_eq!(files(&client).await, expected);
+
+        let bytes = read(&client, &first).await;
+        let lines: Vec<Value> = bytes
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                json!({"id": 1, "name": "alice", "_change_type": "UPSERT"}),
+                json!({"id": 2, "name": null, "_change_type": "DELETE"}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn parquet_rows_are_written_with_a_change_type_column() {
+        let client = client(ObjectFormat::Parquet);
+
+        let path = client
+            .write_rows(&table_name("users"), &column_schemas(), &table_rows())
+            .await
+            .unwrap();
+
+        assert!(path.as_ref().starts_with("exports/public.users/"), "{path}");
+        assert!(path.as_ref().ends_with(".parquet"), "{path}");
+        assert_eq!(files(&client).await, vec![path.to_string()]);
+
+        let bytes = read(&client, &path).await;
+        let batches = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        let schema = batch.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["id", "name", "_change_type"]);
+
+        let ids = batch.column(0).as_any().downcast_ref::<Int32Array>();
+        let ids: Vec<_> = ids.unwrap().iter().collect();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+        let names = batch.column(1).as_any().downcast_ref::<StringArray>();
+        let names: Vec<_> = names.unwrap().iter().collect();
+        assert_eq!(names, vec![Some("alice"), None]);
+        let change_types = batch.column(2).as_any().downcast_ref::<StringArray>();
+        let change_types: Vec<_> = change_types.unwrap().iter().collect();
+        assert_eq!(change_types, vec![Some("UPSERT"), Some("DELETE")]);
+    }
+
+    #[tokio::test]
+    async fn deleting_table_files_keeps_other_tables_and_the_state() {
+        let client = client(ObjectFormat::Jsonl);
+        client
+            .write_rows(&table_name("users"), &column_schemas(), &table_rows())
+            .await
+            .unwrap();
+        let orders = client
+            .write_rows(&table_name("orders"), &column_schemas(), &table_rows())
+            .await
+            .unwrap();
+        client.insert_into_copied_tables(16385).await.unwrap();
+        client.set_last_lsn(PgLsn::from(42)).await.unwrap();
+
+        client
+            .delete_table_files(&table_name("users"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            files(&client).await,
+            vec![
+                "exports/_pg_replicate/state.json".to_string(),
+                orders.to_string(),
+            ]
+        );
+        assert_eq!(client.get_last_lsn().await.unwrap(), PgLsn::from(42));
+        assert!(client
+            .get_copied_table_ids()
+            .await
+            .unwrap()
+            .contains(&16385));
+    }
+}
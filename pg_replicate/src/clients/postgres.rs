@@ -1,24 +1,225 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 use pg_escape::{quote_identifier, quote_literal};
-use postgres_replication::LogicalReplicationStream;
+use postgres_replication::{LogicalReplicationStream, ReplicationStream};
 use thiserror::Error;
+use tokio::{net::TcpStream, sync::OnceCell};
 use tokio_postgres::{
-    config::ReplicationMode,
+    config::{ChannelBinding, Host, ReplicationMode},
     types::{Kind, PgLsn, Type},
-    Client as PostgresClient, Config, CopyOutStream, NoTls, SimpleQueryMessage,
+    Client as PostgresClient, Config, CopyOutStream, NoTls, SimpleQueryMessage, SimpleQueryRow,
 };
 use tracing::{info, warn};
 
-use crate::table::{ColumnSchema, TableId, TableName, TableSchema};
+use crate::{
+    clients::socks5,
+    table::{ColumnSchema, TableId, TableInfo, TableName, TableSchema},
+};
 
+#[derive(Debug)]
 pub struct SlotInfo {
     pub confirmed_flush_lsn: PgLsn,
+    /// The oldest LSN whose WAL the slot retains, which changes can still be
+    /// streamed from
+    pub restart_lsn: PgLsn,
+    /// True if the slot was just created, in which case the current
+    /// transaction reads from the slot's snapshot and `confirmed_flush_lsn`
+    /// is the LSN at which that snapshot was taken
+    pub created: bool,
+}
+
+/// A type resolved by [`ReplicationClient::resolve_type`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedType {
+    /// The type values have, which for a domain is its base type
+    pub typ: Type,
+    /// Name of the domain, if the resolved type was one
+    pub domain: Option<String>,
+}
+
+/// Result of checking whether a database can be replicated from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectivityReport {
+    /// Whether the user is a superuser or has the `replication` attribute
+    pub replication_privilege: bool,
+
+    /// Value of the `wal_level` setting, which must be `logical`
+    pub wal_level: String,
+
+    /// Whether the publication exists, `None` if no publication was checked
+    pub publication_exists: Option<bool>,
+
+    /// Whether the slot exists, `None` if no slot was checked
+    pub slot_exists: Option<bool>,
+
+    /// Whether a missing slot could be created, `None` if no slot was checked
+    pub slot_creatable: Option<bool>,
+}
+
+impl ConnectivityReport {
+    /// Returns true if every check passed
+    pub fn is_ok(&self) -> bool {
+        self.replication_privilege
+            && self.wal_level == "logical"
+            && self.publication_exists != Some(false)
+            && (self.slot_exists == Some(true) || self.slot_creatable != Some(false))
+    }
+}
+
+/// How the replication slot is obtained when a source starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlotOptions {
+    /// Use an existing slot, failing if it doesn't exist
+    UseExisting,
+    /// Use an existing slot or create it if it doesn't exist
+    #[default]
+    CreateIfMissing,
+    /// Create a temporary slot which Postgres drops when the connection closes.
+    /// Nothing is retained across restarts, so only useful for ephemeral consumers.
+    Temporary,
+}
+
+/// Logical decoding output plugin which the changes of a slot are decoded with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputPlugin {
+    /// Postgres' built in plugin, which streams the changes of a publication
+    #[default]
+    Pgoutput,
+    /// The wal2json extension, for servers without publications set up. Its
+    /// changes are streamed in its JSON format version 2.
+    Wal2Json,
+}
+
+impl OutputPlugin {
+    fn name(&self) -> &'static str {
+        match self {
+            OutputPlugin::Pgoutput => "pgoutput",
+            OutputPlugin::Wal2Json => "wal2json",
+        }
+    }
+}
+
+/// How a replication connection authenticates to Postgres
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Whichever method the server asks for, using channel binding with
+    /// SCRAM-SHA-256 if the connection allows it
+    #[default]
+    Any,
+    /// Only SCRAM-SHA-256 with channel binding. Connecting fails if the
+    /// server offers a weaker method like md5 or a cleartext password, or if
+    /// channel binding isn't possible. Channel binding needs TLS, so with
+    /// [`ReplicationClient::connect_no_tls`] connecting always fails.
+    ScramSha256ChannelBinding,
+}
+
+/// Options applied to a replication connection
+#[derive(Debug, Clone)]
+pub struct ConnectionOptions {
+    /// `statement_timeout` of the connection. Zero disables the timeout which
+    /// is what long running table copies usually need
+    pub statement_timeout: Duration,
+
+    /// Time the connection can be idle before a tcp keepalive is sent
+    pub tcp_keepalives_idle: Duration,
+
+    /// Timeout to establish the connection, `None` waits indefinitely
+    pub connect_timeout: Option<Duration>,
+
+    /// Authentication methods the connection accepts
+    pub auth: AuthMethod,
+
+    /// Plugin which slots created by the connection decode changes with
+    pub output_plugin: OutputPlugin,
+
+    /// Stream large transactions from pgoutput before they commit, which
+    /// needs Postgres 14 or later
+    pub stream_in_progress_transactions: bool,
+
+    /// `application_name` of the connection, which identifies it in
+    /// `pg_stat_activity` and its slot's walsender in `pg_stat_replication`
+    pub application_name: Option<String>,
+
+    /// `host:port` of a SOCKS5 proxy to connect through, for databases only
+    /// reachable from a bastion. TCP keepalives aren't sent on connections
+    /// through a proxy.
+    pub socks5_proxy: Option<String>,
+
+    /// Role the connections switch to with `SET ROLE` right after connecting,
+    /// for a login user which is granted the privileges of another role
+    pub role: Option<String>,
+
+    /// `search_path` the connections set right after connecting, after
+    /// switching to `role`. Schemas are quoted, so `$user` is the user's
+    /// schema as in Postgres' default `"$user", public`. An empty list sets
+    /// an empty `search_path`, in which only `pg_catalog` is searched.
+    pub search_path: Option<Vec<String>>,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions {
+            statement_timeout: Duration::ZERO,
+            tcp_keepalives_idle: Duration::from_secs(30),
+            connect_timeout: None,
+            auth: AuthMethod::Any,
+            output_plugin: OutputPlugin::Pgoutput,
+            stream_in_progress_transactions: false,
+            application_name: None,
+            socks5_proxy: None,
+            role: None,
+            search_path: None,
+        }
+    }
+}
+
+/// The statements setting `options`' role and search path, run on every
+/// connection right after it is established
+fn session_statements(options: &ConnectionOptions) -> Vec<String> {
+    let mut statements = vec![];
+    if let Some(role) = &options.role {
+        statements.push(format!("SET ROLE {}", quote_identifier(role)));
+    }
+    if let Some(search_path) = &options.search_path {
+        // `SET search_path TO` without a schema is a syntax error
+        let schemas = if search_path.is_empty() {
+            "''".to_string()
+        } else {
+            search_path
+                .iter()
+                .map(|schema| quote_identifier(schema))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        statements.push(format!("SET search_path TO {schemas}"));
+    }
+    statements
 }
 
 /// A client for Postgres logical replication
 pub struct ReplicationClient {
-    postgres_client: PostgresClient,
+    /// Shared with the streams of cursors, which fetch rows on it
+    postgres_client: Arc<PostgresClient>,
+    /// Config of `query_client`, which is the replication connection's config
+    /// without the replication mode
+    query_config: Config,
+    /// Regular connection for queries which must run while the replication
+    /// connection is streaming, connected on first use
+    query_client: OnceCell<Arc<PostgresClient>>,
+    output_plugin: OutputPlugin,
+    socks5_proxy: Option<String>,
+    /// Statements run on both connections right after they are established
+    session_statements: Vec<String>,
+    /// Types resolved by [`ReplicationClient::resolve_type`] and the
+    /// client's [`CatalogTypeResolver`]s, by oid
+    type_cache: Arc<Mutex<HashMap<u32, ResolvedType>>>,
 }
 
 #[derive(Debug, Error)]
@@ -52,48 +253,247 @@ pub enum ReplicationClientError {
 
     #[error("failed to create slot")]
     FailedToCreateSlot,
+
+    #[error("slot {0} doesn't exist")]
+    MissingSlot(String),
+
+    #[error("slot {0} is in use by another connection")]
+    SlotInUse(String),
+
+    #[error("column {0} in {1} has an invalid value")]
+    InvalidColumnValue(String, String),
+
+    #[error("type with oid {0} doesn't exist")]
+    MissingType(u32),
+
+    #[error("socks5 proxy error: {0}")]
+    Socks5Proxy(std::io::Error),
+
+    #[error("unix domain sockets can't be connected to through a proxy")]
+    UnixSocketThroughProxy,
 }
 
 impl ReplicationClient {
-    /// Connect to a postgres database in logical replication mode without TLS
+    /// Connect to a postgres database in logical replication mode without TLS.
+    /// Like with libpq, a `host` starting with a `/` is the directory of the
+    /// server's Unix domain socket, the file `.s.PGSQL.<port>` in it, which
+    /// avoids the network stack for a replicator on the same machine.
     pub async fn connect_no_tls(
         host: &str,
         port: u16,
         database: &str,
         username: &str,
         password: Option<String>,
+        options: &ConnectionOptions,
     ) -> Result<ReplicationClient, ReplicationClientError> {
         info!("connecting to postgres");
 
+        let query_config = Self::query_config(host, port, database, username, password, options);
+        let mut config = query_config.clone();
+        config.replication_mode(ReplicationMode::Logical);
+
+        let socks5_proxy = options.socks5_proxy.clone();
+        let postgres_client =
+            Self::connect_with(&config, socks5_proxy.as_deref(), "connection").await?;
+
+        let session_statements = session_statements(options);
+        Self::run_session_statements(&postgres_client, &session_statements).await?;
+
+        info!("successfully connected to postgres");
+
+        Ok(ReplicationClient {
+            postgres_client: Arc::new(postgres_client),
+            query_config,
+            query_client: OnceCell::new(),
+            output_plugin: options.output_plugin,
+            socks5_proxy,
+            session_statements,
+            type_cache: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Connects to a postgres database without TLS with a regular connection,
+    /// set up like the connections of a [`ReplicationClient`] with the same
+    /// arguments, e.g. to monitor the database alongside a pipeline
+    pub async fn connect_query_client_no_tls(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        options: &ConnectionOptions,
+    ) -> Result<PostgresClient, ReplicationClientError> {
+        let config = Self::query_config(host, port, database, username, password, options);
+        let client =
+            Self::connect_with(&config, options.socks5_proxy.as_deref(), "query connection")
+                .await?;
+        Self::run_session_statements(&client, &session_statements(options)).await?;
+        Ok(client)
+    }
+
+    /// Returns the config of a regular connection with `options`, which the
+    /// replication connection adds the replication mode to
+    fn query_config(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        options: &ConnectionOptions,
+    ) -> Config {
         let mut config = Config::new();
         config
             .host(host)
             .port(port)
             .dbname(database)
             .user(username)
-            // CR alee: thread this thru config
+            .options(&format!(
+                "-c statement_timeout={}",
+                options.statement_timeout.as_millis()
+            ))
             .keepalives(true)
-            .keepalives_idle(std::time::Duration::from_secs(30))
-            .keepalives_interval(std::time::Duration::from_secs(30))
-            .keepalives_retries(3)
-            .replication_mode(ReplicationMode::Logical);
+            .keepalives_idle(options.tcp_keepalives_idle)
+            .keepalives_interval(Duration::from_secs(30))
+            .keepalives_retries(3);
 
         if let Some(password) = password {
             config.password(password);
         }
 
-        let (postgres_client, connection) = config.connect(NoTls).await?;
+        if let Some(connect_timeout) = options.connect_timeout {
+            config.connect_timeout(connect_timeout);
+        }
 
-        tokio::spawn(async move {
-            info!("waiting for connection to terminate");
-            if let Err(e) = connection.await {
-                warn!("connection error: {}", e);
+        if let Some(application_name) = &options.application_name {
+            config.application_name(application_name);
+        }
+
+        // Requiring channel binding makes tokio_postgres refuse md5 and
+        // cleartext passwords as well as SCRAM without channel binding
+        let channel_binding = match options.auth {
+            AuthMethod::Any => ChannelBinding::Prefer,
+            AuthMethod::ScramSha256ChannelBinding => ChannelBinding::Require,
+        };
+        config.channel_binding(channel_binding);
+
+        config
+    }
+
+    /// Connects with `config`, through the SOCKS5 proxy at `socks5_proxy` if
+    /// there is one, and spawns the task driving the connection, which logs
+    /// its errors as errors of `connection_name`
+    async fn connect_with(
+        config: &Config,
+        socks5_proxy: Option<&str>,
+        connection_name: &'static str,
+    ) -> Result<PostgresClient, ReplicationClientError> {
+        let client = match socks5_proxy {
+            Some(socks5_proxy) => {
+                let stream = Self::connect_to_proxy(config, socks5_proxy).await?;
+                let (client, connection) = config.connect_raw(stream, NoTls).await?;
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        warn!("{connection_name} error: {}", e);
+                    }
+                });
+                client
             }
-        });
+            None => {
+                let (client, connection) = config.connect(NoTls).await?;
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        warn!("{connection_name} error: {}", e);
+                    }
+                });
+                client
+            }
+        };
+        Ok(client)
+    }
 
-        info!("successfully connected to postgres");
+    /// Opens a tunnel through the SOCKS5 proxy at `socks5_proxy` to the host
+    /// and port of `config`, within its connect timeout if it has one
+    async fn connect_to_proxy(
+        config: &Config,
+        socks5_proxy: &str,
+    ) -> Result<TcpStream, ReplicationClientError> {
+        let host = match config.get_hosts().first() {
+            Some(Host::Tcp(host)) => host.clone(),
+            #[cfg(unix)]
+            Some(Host::Unix(_)) => return Err(ReplicationClientError::UnixSocketThroughProxy),
+            None => "localhost".to_string(),
+        };
+        let port = config.get_ports().first().copied().unwrap_or(5432);
+        info!("connecting to {host}:{port} through socks5 proxy {socks5_proxy}");
+        let connect = socks5::connect(socks5_proxy, &host, port);
+        let stream = match config.get_connect_timeout() {
+            Some(connect_timeout) => tokio::time::timeout(*connect_timeout, connect)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "timed out connecting through the proxy",
+                    ))
+                }),
+            None => connect.await,
+        };
+        stream.map_err(ReplicationClientError::Socks5Proxy)
+    }
+
+    /// Runs `statements` on `client`. Replication connections accept simple
+    /// queries, so this works on both of the connections.
+    async fn run_session_statements(
+        client: &PostgresClient,
+        statements: &[String],
+    ) -> Result<(), ReplicationClientError> {
+        for statement in statements {
+            client.simple_query(statement).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_query_client(&self) -> Result<&Arc<PostgresClient>, ReplicationClientError> {
+        let query_client = self
+            .query_client
+            .get_or_try_init(|| async {
+                let query_client = Self::connect_with(
+                    &self.query_config,
+                    self.socks5_proxy.as_deref(),
+                    "query connection",
+                )
+                .await?;
+                Self::run_session_statements(&query_client, &self.session_statements).await?;
+                Ok::<_, ReplicationClientError>(Arc::new(query_client))
+            })
+            .await?;
+        Ok(query_client)
+    }
+
+    /// Returns the current WAL write position of the database. Queried on a
+    /// separate connection because the replication connection can't run
+    /// queries while it is streaming changes.
+    pub async fn get_current_wal_lsn(&self) -> Result<PgLsn, ReplicationClientError> {
+        let query_client = self.get_query_client().await?;
+        let query = "select pg_current_wal_lsn() as current_wal_lsn;";
+
+        for message in query_client.simple_query(query).await? {
+            if let SimpleQueryMessage::Row(row) = message {
+                let current_wal_lsn = row
+                    .get("current_wal_lsn")
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "current_wal_lsn".to_string(),
+                        "pg_current_wal_lsn".to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::InvalidPgLsn)?;
+                return Ok(current_wal_lsn);
+            }
+        }
 
-        Ok(ReplicationClient { postgres_client })
+        Err(ReplicationClientError::MissingColumn(
+            "current_wal_lsn".to_string(),
+            "pg_current_wal_lsn".to_string(),
+        ))
     }
 
     /// Starts a read-only trasaction with repeatable read isolation level
@@ -115,7 +515,10 @@ impl ReplicationClient {
         Ok(())
     }
 
-    /// Returns a [CopyOutStream] for a table
+    /// Returns a [CopyOutStream] for a table. Rows are copied in the text
+    /// format, which is also what pgoutput sends in cdc events, so both are
+    /// parsed by [`crate::conversions::text::TextFormatConverter`] and any type
+    /// supported in one is supported in the other.
     pub async fn get_table_copy_stream(
         &self,
         table_name: &TableName,
@@ -130,6 +533,104 @@ impl ReplicationClient {
         Ok(stream)
     }
 
+    /// Returns a [CopyOutStream] of only the `column_schemas` columns of a
+    /// table, in their order, copied in the same format as
+    /// [`ReplicationClient::get_table_copy_stream`]. Other columns aren't
+    /// selected by the copy query so they are never sent by Postgres.
+    pub async fn get_table_columns_copy_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<CopyOutStream, ReplicationClientError> {
+        let copy_query = columns_copy_query(table_name, column_schemas);
+
+        let stream = self.postgres_client.copy_out_simple(&copy_query).await?;
+
+        Ok(stream)
+    }
+
+    /// Returns a [CopyOutStream] of the rows of all `partitions` of a
+    /// partitioned table, copied in the same format as
+    /// [`ReplicationClient::get_table_copy_stream`]. The columns of each
+    /// partition are selected by name so that they are in the order of
+    /// `column_schemas`.
+    pub async fn get_partitions_copy_stream(
+        &self,
+        partitions: &[TableName],
+        column_schemas: &[ColumnSchema],
+    ) -> Result<CopyOutStream, ReplicationClientError> {
+        let columns = select_list(column_schemas);
+        let selects = partitions
+            .iter()
+            .map(|p| format!("select {columns} from {}", p.as_quoted_identifier()))
+            .collect::<Vec<_>>()
+            .join(" union all ");
+        let copy_query = format!(r#"COPY ({selects}) TO STDOUT WITH (FORMAT text);"#);
+
+        let stream = self.postgres_client.copy_out_simple(&copy_query).await?;
+
+        Ok(stream)
+    }
+
+    /// Declares a cursor of the rows of all `tables`, which are a table or
+    /// the partitions of a partitioned table, and returns a stream of its
+    /// rows fetched `fetch_rows` at a time. Each fetch is a statement of its
+    /// own, so `statement_timeout` limits a fetch rather than the whole copy,
+    /// and only a fetch's rows are buffered. Rows are in the same format as
+    /// those of [`ReplicationClient::get_table_copy_stream`]. The cursor is
+    /// declared in the current transaction, whose snapshot it reads from, and
+    /// is closed after its last row.
+    pub async fn get_cursor_stream(
+        &self,
+        tables: &[TableName],
+        column_schemas: &[ColumnSchema],
+        fetch_rows: usize,
+    ) -> Result<CursorStream, ReplicationClientError> {
+        let columns = select_list(column_schemas);
+        let selects = tables
+            .iter()
+            .map(|t| format!("select {columns} from {}", t.as_quoted_identifier()))
+            .collect::<Vec<_>>()
+            .join(" union all ");
+        // A cursor left open by a copy which failed is closed along with it
+        let declare_query =
+            format!("CLOSE ALL; DECLARE {COPY_CURSOR} NO SCROLL CURSOR FOR {selects};");
+        self.postgres_client.simple_query(&declare_query).await?;
+
+        let fetch_rows = fetch_rows.max(1);
+        let fetch_query = Arc::new(format!("FETCH {fetch_rows} FROM {COPY_CURSOR};"));
+        let client = self.postgres_client.clone();
+        let stream = futures::stream::try_unfold(Some(client), move |client| {
+            let fetch_query = fetch_query.clone();
+            async move {
+                let Some(client) = client else {
+                    return Ok(None);
+                };
+                let rows: Vec<Bytes> = client
+                    .simple_query(&fetch_query)
+                    .await?
+                    .iter()
+                    .filter_map(|message| match message {
+                        SimpleQueryMessage::Row(row) => Some(copy_text_row(row)),
+                        _ => None,
+                    })
+                    .collect();
+                if rows.len() < fetch_rows {
+                    client
+                        .simple_query(&format!("CLOSE {COPY_CURSOR};"))
+                        .await?;
+                    return Ok(Some((rows, None)));
+                }
+                Ok::<_, tokio_postgres::Error>(Some((rows, Some(client))))
+            }
+        })
+        .map_ok(|rows| futures::stream::iter(rows.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed();
+
+        Ok(stream)
+    }
+
     /// Returns a vector of columns of a table
     pub async fn get_column_schemas(
         &self,
@@ -140,12 +641,27 @@ impl ReplicationClient {
                 a.atttypid,
                 a.atttypmod,
                 a.attnotnull,
-                coalesce(i.indisprimary, false) as primary
+                coalesce(i.indisprimary, false) as primary,
+                coalesce(r.indisreplident, false) as replica_identity_key,
+                a.attidentity <> '' or exists (
+                    select 1
+                    from pg_attrdef ad
+                    join pg_depend d
+                        on d.classid = 'pg_attrdef'::regclass
+                        and d.objid = ad.oid
+                        and d.refclassid = 'pg_class'::regclass
+                    join pg_class s on s.oid = d.refobjid and s.relkind = 'S'
+                    where ad.adrelid = a.attrelid and ad.adnum = a.attnum
+                ) as identity
             from pg_attribute a
             left join pg_index i
                 on a.attrelid = i.indrelid
                 and a.attnum = any(i.indkey)
                 and i.indisprimary = true
+            left join pg_index r
+                on a.attrelid = r.indrelid
+                and a.attnum = any(r.indkey)
+                and r.indisreplident = true
             where a.attnum > 0::int2
             and not a.attisdropped
             and a.attgenerated = ''
@@ -155,6 +671,7 @@ impl ReplicationClient {
         );
 
         let mut column_schemas = vec![];
+        let mut replica_identity_keys = vec![];
 
         for message in self
             .postgres_client
@@ -179,13 +696,7 @@ impl ReplicationClient {
                     .parse()
                     .map_err(|_| ReplicationClientError::OidColumnNotU32)?;
 
-                //TODO: For now we assume all types are simple, fix it later
-                let typ = Type::from_oid(type_oid).unwrap_or(Type::new(
-                    format!("unnamed(oid: {type_oid})"),
-                    type_oid,
-                    Kind::Simple,
-                    "pg_catalog".to_string(),
-                ));
+                let ResolvedType { typ, domain } = self.resolve_type(type_oid).await?;
 
                 let modifier = row
                     .try_get("atttypmod")?
@@ -212,19 +723,69 @@ impl ReplicationClient {
                         ))?
                         == "t";
 
+                let replica_identity_key = row.try_get("replica_identity_key")?.ok_or(
+                    ReplicationClientError::MissingColumn(
+                        "indisreplident".to_string(),
+                        "pg_index".to_string(),
+                    ),
+                )? == "t";
+                replica_identity_keys.push(replica_identity_key);
+
+                // Identity columns own their sequence, `serial` columns and
+                // others defaulting to `nextval` have defaults depending on one
+                let identity =
+                    row.try_get("identity")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "attidentity".to_string(),
+                            "pg_attribute".to_string(),
+                        ))?
+                        == "t";
+
                 column_schemas.push(ColumnSchema {
                     name,
                     typ,
                     modifier,
                     nullable,
                     primary,
+                    identity,
+                    domain,
                 })
             }
         }
 
+        use_replica_identity_key(&mut column_schemas, &replica_identity_keys);
+
         Ok(column_schemas)
     }
 
+    /// Resolves the type with oid `type_oid`. Types not built into Postgres,
+    /// like enums, domains and the types of extensions, have no fixed oid and
+    /// are looked up in the catalog, including types created after the
+    /// client connected. Domains resolve to the type they are based on, whose
+    /// text format their values have, and keep the name of the domain. Types
+    /// of extensions, like PostGIS' geometry, are recognized by name. Looked
+    /// up types are cached.
+    pub async fn resolve_type(
+        &self,
+        type_oid: u32,
+    ) -> Result<ResolvedType, ReplicationClientError> {
+        resolve_type(&self.postgres_client, &self.type_cache, type_oid).await
+    }
+
+    /// A resolver of the types of columns which appear while changes are
+    /// streamed. It looks them up on the regular connection, since the
+    /// replication connection can't run queries while streaming, and shares
+    /// the client's cache of resolved types.
+    pub async fn type_resolver(&self) -> Result<CatalogTypeResolver, ReplicationClientError> {
+        Ok(CatalogTypeResolver {
+            query_client: self.get_query_client().await?.clone(),
+            type_cache: self.type_cache.clone(),
+        })
+    }
+
+    /// Returns the schemas of `table_names`, including those of tables
+    /// without a key, which pipelines whose sink applies rows by key refuse
+    /// to replicate
     pub async fn get_table_schemas(
         &self,
         table_names: &[TableName],
@@ -233,20 +794,13 @@ impl ReplicationClient {
 
         for table_name in table_names {
             let table_schema = self.get_table_schema(table_name.clone()).await?;
-            if !table_schema.has_primary_keys() {
-                warn!(
-                    "table {} with id {} will not be copied because it has no primary key",
-                    table_schema.table_name, table_schema.table_id
-                );
-                continue;
-            }
             table_schemas.insert(table_schema.table_id, table_schema);
         }
 
         Ok(table_schemas)
     }
 
-    async fn get_table_schema(
+    pub async fn get_table_schema(
         &self,
         table_name: TableName,
     ) -> Result<TableSchema, ReplicationClientError> {
@@ -262,9 +816,155 @@ impl ReplicationClient {
         })
     }
 
+    /// Returns size estimates and whether changes can be replicated for each
+    /// of `table_names`
+    pub async fn get_table_infos(
+        &self,
+        table_names: &[TableName],
+    ) -> Result<Vec<TableInfo>, ReplicationClientError> {
+        let mut table_infos = Vec::with_capacity(table_names.len());
+        for table_name in table_names {
+            let query = format!(
+                "select c.reltuples::int8 as estimated_row_count,
+                    pg_total_relation_size(c.oid) as size_bytes,
+                    case c.relreplident
+                        when 'f' then true
+                        when 'd' then exists (
+                            select 1 from pg_index i where i.indrelid = c.oid and i.indisprimary
+                        )
+                        when 'i' then exists (
+                            select 1 from pg_index i where i.indrelid = c.oid and i.indisreplident
+                        )
+                        else false
+                    end as has_replica_identity
+                from pg_class c
+                where c.oid = {}::regclass;",
+                quote_literal(&table_name.as_quoted_identifier())
+            );
+
+            let mut table_info = None;
+            for message in self.postgres_client.simple_query(&query).await? {
+                if let SimpleQueryMessage::Row(row) = message {
+                    let get_column = |column: &str| {
+                        row.try_get(column)?
+                            .ok_or(ReplicationClientError::MissingColumn(
+                                column.to_string(),
+                                "pg_class".to_string(),
+                            ))
+                    };
+                    let parse_i64 = |column: &str| {
+                        get_column(column)?.parse::<i64>().map_err(|_| {
+                            ReplicationClientError::InvalidColumnValue(
+                                column.to_string(),
+                                "pg_class".to_string(),
+                            )
+                        })
+                    };
+                    // reltuples is -1 for tables which were never vacuumed or analyzed
+                    let estimated_row_count = parse_i64("estimated_row_count")?;
+                    table_info = Some(TableInfo {
+                        table_name: table_name.clone(),
+                        estimated_row_count: (estimated_row_count >= 0)
+                            .then_some(estimated_row_count),
+                        size_bytes: parse_i64("size_bytes")?,
+                        has_replica_identity: get_column("has_replica_identity")? == "t",
+                    });
+                }
+            }
+            table_infos
+                .push(table_info.ok_or(ReplicationClientError::MissingTable(table_name.clone()))?);
+        }
+        Ok(table_infos)
+    }
+
+    /// Returns the number of rows of a table
+    pub async fn get_row_count(
+        &self,
+        table_name: &TableName,
+    ) -> Result<u64, ReplicationClientError> {
+        let query = format!(
+            "select count(*) as row_count from {};",
+            table_name.as_quoted_identifier()
+        );
+
+        for message in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = message {
+                let row_count = row
+                    .try_get("row_count")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "row_count".to_string(),
+                        table_name.to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| {
+                        ReplicationClientError::InvalidColumnValue(
+                            "row_count".to_string(),
+                            table_name.to_string(),
+                        )
+                    })?;
+                return Ok(row_count);
+            }
+        }
+
+        Err(ReplicationClientError::MissingTable(table_name.clone()))
+    }
+
+    /// Returns the name of the root partitioned table of a partition, or
+    /// `None` if `table_id` isn't a partition
+    pub async fn get_partition_root(
+        &self,
+        table_id: TableId,
+    ) -> Result<Option<TableName>, ReplicationClientError> {
+        // Walks up pg_inherits for as long as the parent is a partitioned
+        // table, so that sub-partitions are routed to the topmost table
+        let query = format!(
+            "with recursive ancestors as (
+                select i.inhparent as parent, 1 as depth
+                from pg_inherits i
+                join pg_partitioned_table p on p.partrelid = i.inhparent
+                where i.inhrelid = {table_id}
+                union all
+                select i.inhparent, a.depth + 1
+                from pg_inherits i
+                join pg_partitioned_table p on p.partrelid = i.inhparent
+                join ancestors a on i.inhrelid = a.parent
+            )
+            select n.nspname, c.relname
+            from ancestors a
+            join pg_class c on c.oid = a.parent
+            join pg_namespace n on n.oid = c.relnamespace
+            order by a.depth desc
+            limit 1;"
+        );
+
+        for message in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = message {
+                let schema = row
+                    .try_get("nspname")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "nspname".to_string(),
+                        "pg_namespace".to_string(),
+                    ))?
+                    .to_string();
+                let name = row
+                    .try_get("relname")?
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "relname".to_string(),
+                        "pg_class".to_string(),
+                    ))?
+                    .to_string();
+                return Ok(Some(TableName { schema, name }));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Returns the table id (called relation id in Postgres) of a table
-    /// Also checks whether the replica identity is default or full and
-    /// returns an error if not.
+    /// Also checks whether the replica identity is default, full, a unique
+    /// index or nothing and returns an error if not. Changes of tables with
+    /// replica identity nothing are handled by the cdc stream's
+    /// [`crate::conversions::cdc_event::NoReplicaIdentityPolicy`].
     pub async fn get_table_id(
         &self,
         table: &TableName,
@@ -293,7 +993,7 @@ impl ReplicationClient {
                             "pg_class".to_string(),
                         ))?;
 
-                if !(replica_identity == "d" || replica_identity == "f") {
+                if !matches!(replica_identity, "d" | "f" | "i" | "n") {
                     return Err(ReplicationClientError::ReplicaIdentityNotSupported(
                         replica_identity.to_string(),
                     ));
@@ -314,11 +1014,11 @@ impl ReplicationClient {
         Ok(None)
     }
 
-    /// Returns the slot info of an existing slot. The slot info currently only has the
-    /// confirmed_flush_lsn column of the pg_replication_slots table.
+    /// Returns the slot info of an existing slot, from the confirmed_flush_lsn
+    /// and restart_lsn columns of the pg_replication_slots table.
     async fn get_slot(&self, slot_name: &str) -> Result<Option<SlotInfo>, ReplicationClientError> {
         let query = format!(
-            r#"select confirmed_flush_lsn from pg_replication_slots where slot_name = {};"#,
+            r#"select confirmed_flush_lsn, restart_lsn from pg_replication_slots where slot_name = {};"#,
             quote_literal(slot_name)
         );
 
@@ -334,9 +1034,19 @@ impl ReplicationClient {
                     ))?
                     .parse()
                     .map_err(|_| ReplicationClientError::InvalidPgLsn)?;
+                let restart_lsn = row
+                    .get("restart_lsn")
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "restart_lsn".to_string(),
+                        "pg_replication_slots".to_string(),
+                    ))?
+                    .parse()
+                    .map_err(|_| ReplicationClientError::InvalidPgLsn)?;
 
                 return Ok(Some(SlotInfo {
                     confirmed_flush_lsn,
+                    restart_lsn,
+                    created: false,
                 }));
             }
         }
@@ -349,10 +1059,16 @@ impl ReplicationClient {
     /// `syntax error at or near "CREATE_REPLICATION_SLOT"``
     ///
     /// Returns the consistent_point column as slot info.
-    async fn create_slot(&self, slot_name: &str) -> Result<SlotInfo, ReplicationClientError> {
+    async fn create_slot(
+        &self,
+        slot_name: &str,
+        temporary: bool,
+    ) -> Result<SlotInfo, ReplicationClientError> {
         let query = format!(
-            r#"CREATE_REPLICATION_SLOT {} LOGICAL pgoutput USE_SNAPSHOT"#,
-            quote_identifier(slot_name)
+            r#"CREATE_REPLICATION_SLOT {} {}LOGICAL {} USE_SNAPSHOT"#,
+            quote_identifier(slot_name),
+            if temporary { "TEMPORARY " } else { "" },
+            self.output_plugin.name()
         );
         let results = self.postgres_client.simple_query(&query).await?;
 
@@ -368,6 +1084,8 @@ impl ReplicationClient {
                     .map_err(|_| ReplicationClientError::InvalidPgLsn)?;
                 return Ok(SlotInfo {
                     confirmed_flush_lsn: consistent_point,
+                    restart_lsn: consistent_point,
+                    created: true,
                 });
             }
         }
@@ -380,12 +1098,70 @@ impl ReplicationClient {
         &self,
         slot_name: &str,
     ) -> Result<SlotInfo, ReplicationClientError> {
-        if let Some(slot_info) = self.get_slot(slot_name).await? {
-            Ok(slot_info)
-        } else {
-            self.rollback_txn().await?;
-            self.begin_readonly_transaction().await?;
-            Ok(self.create_slot(slot_name).await?)
+        self.get_slot_with_options(slot_name, SlotOptions::CreateIfMissing)
+            .await
+    }
+
+    /// Returns the slot info of the slot named `slot_name`, creating the slot
+    /// first if `slot_options` asks for it.
+    pub async fn get_slot_with_options(
+        &self,
+        slot_name: &str,
+        slot_options: SlotOptions,
+    ) -> Result<SlotInfo, ReplicationClientError> {
+        match slot_options {
+            SlotOptions::UseExisting => self
+                .get_slot(slot_name)
+                .await?
+                .ok_or(ReplicationClientError::MissingSlot(slot_name.to_string())),
+            SlotOptions::CreateIfMissing => {
+                if let Some(slot_info) = self.get_slot(slot_name).await? {
+                    Ok(slot_info)
+                } else {
+                    self.rollback_txn().await?;
+                    self.begin_readonly_transaction().await?;
+                    Ok(self.create_slot(slot_name, false).await?)
+                }
+            }
+            SlotOptions::Temporary => {
+                self.rollback_txn().await?;
+                self.begin_readonly_transaction().await?;
+                Ok(self.create_slot(slot_name, true).await?)
+            }
+        }
+    }
+
+    /// Drops the slot named `slot_name`, which releases the WAL it retains.
+    /// Returns false if there is no such slot. Fails with
+    /// [`ReplicationClientError::SlotInUse`] instead of dropping a slot which
+    /// a replicator is streaming from.
+    pub async fn drop_slot(&self, slot_name: &str) -> Result<bool, ReplicationClientError> {
+        let query = format!(
+            r#"select active from pg_replication_slots where slot_name = {};"#,
+            quote_literal(slot_name)
+        );
+        let mut active = None;
+        for msg in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let value = row
+                    .get("active")
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "active".to_string(),
+                        "pg_replication_slots".to_string(),
+                    ))?;
+                active = Some(value == "t");
+            }
+        }
+        match active {
+            None => Ok(false),
+            Some(true) => Err(ReplicationClientError::SlotInUse(slot_name.to_string())),
+            Some(false) => {
+                // Without WAIT this fails rather than blocks if the slot
+                // became active since it was checked
+                let query = format!(r#"DROP_REPLICATION_SLOT {}"#, quote_identifier(slot_name));
+                self.postgres_client.simple_query(&query).await?;
+                Ok(true)
+            }
         }
     }
 
@@ -449,6 +1225,144 @@ impl ReplicationClient {
         Ok(false)
     }
 
+    /// Returns true if deletes are published by `publication`
+    pub async fn publication_publishes_deletes(
+        &self,
+        publication: &str,
+    ) -> Result<bool, ReplicationClientError> {
+        let query = format!(
+            "select pubdelete from pg_publication where pubname = {};",
+            quote_literal(publication)
+        );
+        for msg in self.postgres_client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let pubdelete =
+                    row.try_get("pubdelete")?
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "pubdelete".to_string(),
+                            "pg_publication".to_string(),
+                        ))?;
+                return Ok(pubdelete == "t");
+            }
+        }
+        Err(ReplicationClientError::MissingPublication(
+            publication.to_string(),
+        ))
+    }
+
+    /// Returns true if the connected user is a superuser or has the `replication` attribute
+    pub async fn has_replication_privilege(&self) -> Result<bool, ReplicationClientError> {
+        let query = "select rolreplication or rolsuper as can_replicate from pg_roles where rolname = current_user;";
+        for msg in self.postgres_client.simple_query(query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let can_replicate =
+                    row.get("can_replicate")
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "can_replicate".to_string(),
+                            "pg_roles".to_string(),
+                        ))?;
+                return Ok(can_replicate == "t");
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the value of the `wal_level` setting
+    pub async fn get_wal_level(&self) -> Result<String, ReplicationClientError> {
+        for msg in self.postgres_client.simple_query("show wal_level;").await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let wal_level = row
+                    .get("wal_level")
+                    .ok_or(ReplicationClientError::MissingColumn(
+                        "wal_level".to_string(),
+                        "pg_settings".to_string(),
+                    ))?
+                    .to_string();
+                return Ok(wal_level);
+            }
+        }
+        Err(ReplicationClientError::MissingColumn(
+            "wal_level".to_string(),
+            "pg_settings".to_string(),
+        ))
+    }
+
+    /// Returns true if the `track_commit_timestamp` setting is on
+    pub async fn tracks_commit_timestamps(&self) -> Result<bool, ReplicationClientError> {
+        for msg in self
+            .postgres_client
+            .simple_query("show track_commit_timestamp;")
+            .await?
+        {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let track_commit_timestamp = row.get("track_commit_timestamp").ok_or(
+                    ReplicationClientError::MissingColumn(
+                        "track_commit_timestamp".to_string(),
+                        "pg_settings".to_string(),
+                    ),
+                )?;
+                return Ok(track_commit_timestamp == "on");
+            }
+        }
+        Err(ReplicationClientError::MissingColumn(
+            "track_commit_timestamp".to_string(),
+            "pg_settings".to_string(),
+        ))
+    }
+
+    /// Returns true if fewer than `max_replication_slots` slots are in use
+    pub async fn has_free_replication_slot(&self) -> Result<bool, ReplicationClientError> {
+        let query = "select (select count(*) from pg_replication_slots) < current_setting('max_replication_slots')::int as has_free_slot;";
+        for msg in self.postgres_client.simple_query(query).await? {
+            if let SimpleQueryMessage::Row(row) = msg {
+                let has_free_slot =
+                    row.get("has_free_slot")
+                        .ok_or(ReplicationClientError::MissingColumn(
+                            "has_free_slot".to_string(),
+                            "pg_replication_slots".to_string(),
+                        ))?;
+                return Ok(has_free_slot == "t");
+            }
+        }
+        Ok(false)
+    }
+
+    /// Checks whether the database can be replicated from with the given
+    /// publication and slot without creating anything
+    pub async fn check_connectivity(
+        &self,
+        publication: Option<&str>,
+        slot_name: Option<&str>,
+    ) -> Result<ConnectivityReport, ReplicationClientError> {
+        let replication_privilege = self.has_replication_privilege().await?;
+        let wal_level = self.get_wal_level().await?;
+
+        let publication_exists = match publication {
+            Some(publication) => Some(self.publication_exists(publication).await?),
+            None => None,
+        };
+
+        let (slot_exists, slot_creatable) = match slot_name {
+            Some(slot_name) => {
+                let slot_exists = self.get_slot(slot_name).await?.is_some();
+                let slot_creatable = !slot_exists
+                    && replication_privilege
+                    && wal_level == "logical"
+                    && self.has_free_replication_slot().await?;
+                (Some(slot_exists), Some(slot_creatable))
+            }
+            None => (None, None),
+        };
+
+        Ok(ConnectivityReport {
+            replication_privilege,
+            wal_level,
+            publication_exists,
+            slot_exists,
+            slot_creatable,
+        })
+    }
+
     pub async fn get_logical_replication_stream(
         &self,
         publication: &str,
@@ -476,4 +1390,806 @@ impl ReplicationClient {
 
         Ok(stream)
     }
+
+    /// Starts streaming changes from a pgoutput slot with protocol version 2,
+    /// in which Postgres streams large transactions before they commit
+    /// instead of spilling them to disk until they do. Messages are returned
+    /// undecoded as the messages of streamed transactions carry xids which
+    /// [`LogicalReplicationStream`] can't decode. With `messages`, the
+    /// messages written with `pg_logical_emit_message` are streamed too.
+    pub async fn get_streaming_logical_replication_stream(
+        &self,
+        publication: &str,
+        slot_name: &str,
+        start_lsn: PgLsn,
+        messages: bool,
+    ) -> Result<ReplicationStream, ReplicationClientError> {
+        let options = format!(
+            r#"("proto_version" '2', "streaming" 'on', "messages" {}, "publication_names" {})"#,
+            if messages { "'true'" } else { "'false'" },
+            quote_literal(publication),
+        );
+
+        let query = format!(
+            r#"START_REPLICATION SLOT {} LOGICAL {} {}"#,
+            quote_identifier(slot_name),
+            start_lsn,
+            options
+        );
+
+        let copy_stream = self
+            .postgres_client
+            .copy_both_simple::<bytes::Bytes>(&query)
+            .await?;
+
+        Ok(ReplicationStream::new(copy_stream))
+    }
+
+    /// Starts streaming the changes of `table_names` from a slot created with
+    /// [`OutputPlugin::Wal2Json`]. Messages hold one change, begin or commit
+    /// each, with the xid, commit timestamp and lsns of transactions.
+    pub async fn get_wal2json_replication_stream(
+        &self,
+        slot_name: &str,
+        start_lsn: PgLsn,
+        table_names: &[TableName],
+    ) -> Result<ReplicationStream, ReplicationClientError> {
+        let add_tables = table_names
+            .iter()
+            .map(|table_name| table_name.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let options = format!(
+            r#"("format-version" '2', "include-xids" '1', "include-timestamp" '1', "include-lsn" '1', "add-tables" {})"#,
+            quote_literal(&add_tables),
+        );
+
+        let query = format!(
+            r#"START_REPLICATION SLOT {} LOGICAL {} {}"#,
+            quote_identifier(slot_name),
+            start_lsn,
+            options
+        );
+
+        let copy_stream = self
+            .postgres_client
+            .copy_both_simple::<bytes::Bytes>(&query)
+            .await?;
+
+        Ok(ReplicationStream::new(copy_stream))
+    }
+}
+
+/// Resolves the type with oid `type_oid` on `client`, see
+/// [`ReplicationClient::resolve_type`]
+async fn resolve_type(
+    client: &PostgresClient,
+    type_cache: &Mutex<HashMap<u32, ResolvedType>>,
+    type_oid: u32,
+) -> Result<ResolvedType, ReplicationClientError> {
+    if let Some(typ) = Type::from_oid(type_oid) {
+        return Ok(ResolvedType { typ, domain: None });
+    }
+    // The guard mustn't be held across the lookup's awaits
+    let cached = type_cache
+        .lock()
+        .expect("type cache lock poisoned")
+        .get(&type_oid)
+        .cloned();
+    if let Some(resolved) = cached {
+        return Ok(resolved);
+    }
+
+    let mut oid = type_oid;
+    // The outermost domain, if a domain is based on another domain
+    let mut domain = None;
+    let typ = loop {
+        let query = format!(
+            "select t.typname, n.nspname, t.typtype, t.typbasetype
+            from pg_type t
+            join pg_namespace n on n.oid = t.typnamespace
+            where t.oid = {oid}"
+        );
+        let mut type_row = None;
+        for message in client.simple_query(&query).await? {
+            if let SimpleQueryMessage::Row(row) = message {
+                let get_column = |column: &str| {
+                    row.try_get(column)?.map(|value| value.to_string()).ok_or(
+                        ReplicationClientError::MissingColumn(
+                            column.to_string(),
+                            "pg_type".to_string(),
+                        ),
+                    )
+                };
+                type_row = Some((
+                    get_column("typname")?,
+                    get_column("nspname")?,
+                    get_column("typtype")?,
+                    get_column("typbasetype")?,
+                ));
+            }
+        }
+        let Some((name, schema, typtype, base_type_oid)) = type_row else {
+            return Err(ReplicationClientError::MissingType(oid));
+        };
+
+        match typtype.as_str() {
+            "d" => {
+                domain.get_or_insert(name);
+                oid = base_type_oid
+                    .parse()
+                    .map_err(|_| ReplicationClientError::OidColumnNotU32)?;
+                if let Some(typ) = Type::from_oid(oid) {
+                    break typ;
+                }
+            }
+            "e" => {
+                let labels = get_enum_labels(client, oid).await?;
+                break Type::new(name, oid, Kind::Enum(labels), schema);
+            }
+            _ => break Type::new(name, oid, Kind::Simple, schema),
+        }
+    };
+
+    let resolved = ResolvedType { typ, domain };
+    type_cache
+        .lock()
+        .expect("type cache lock poisoned")
+        .insert(type_oid, resolved.clone());
+    Ok(resolved)
+}
+
+async fn get_enum_labels(
+    client: &PostgresClient,
+    type_oid: u32,
+) -> Result<Vec<String>, ReplicationClientError> {
+    let query = format!(
+        "select enumlabel from pg_enum where enumtypid = {type_oid} order by enumsortorder"
+    );
+    let mut labels = vec![];
+    for message in client.simple_query(&query).await? {
+        if let SimpleQueryMessage::Row(row) = message {
+            let label = row
+                .try_get("enumlabel")?
+                .ok_or(ReplicationClientError::MissingColumn(
+                    "enumlabel".to_string(),
+                    "pg_enum".to_string(),
+                ))?;
+            labels.push(label.to_string());
+        }
+    }
+    Ok(labels)
+}
+
+/// Resolves types which appear in relation messages while changes are
+/// streamed, see [`crate::pipeline::sources::postgres::CdcStream`]
+#[async_trait]
+pub trait TypeResolver: Send + Sync {
+    async fn resolve_type(&self, type_oid: u32) -> Result<ResolvedType, ReplicationClientError>;
+}
+
+/// Resolves types on the regular connection of a [`ReplicationClient`], see
+/// [`ReplicationClient::type_resolver`]
+pub struct CatalogTypeResolver {
+    query_client: Arc<PostgresClient>,
+    type_cache: Arc<Mutex<HashMap<u32, ResolvedType>>>,
+}
+
+#[async_trait]
+impl TypeResolver for CatalogTypeResolver {
+    async fn resolve_type(&self, type_oid: u32) -> Result<ResolvedType, ReplicationClientError> {
+        resolve_type(&self.query_client, &self.type_cache, type_oid).await
+    }
+}
+
+/// Makes the columns of the unique index a table's replica identity uses,
+/// flagged in `replica_identity_keys`, its key if it has no primary key. Like
+/// a primary key the index can't be partial or have nullable columns, and
+/// changes are streamed with the values of its columns.
+fn use_replica_identity_key(column_schemas: &mut [ColumnSchema], replica_identity_keys: &[bool]) {
+    if column_schemas
+        .iter()
+        .any(|column_schema| column_schema.primary)
+    {
+        return;
+    }
+    for (column_schema, replica_identity_key) in
+        column_schemas.iter_mut().zip(replica_identity_keys)
+    {
+        column_schema.primary = *replica_identity_key;
+    }
+}
+
+/// Returns the quoted names of `column_schemas` separated by commas
+fn select_list(column_schemas: &[ColumnSchema]) -> String {
+    column_schemas
+        .iter()
+        .map(|c| quote_identifier(&c.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Rows of a table fetched from a cursor by
+/// [`ReplicationClient::get_cursor_stream`]
+pub type CursorStream = BoxStream<'static, Result<Bytes, tokio_postgres::Error>>;
+
+/// Name of the cursor tables are copied with
+const COPY_CURSOR: &str = "pg_replicate_copy";
+
+/// Converts a row fetched with a simple query, whose values are in the text
+/// format, to the line COPY's text format has for it, so that rows copied
+/// with a cursor convert the same as rows copied with COPY
+fn copy_text_row(row: &SimpleQueryRow) -> Bytes {
+    let values = (0..row.len()).map(|i| row.get(i));
+    copy_text_line(values)
+}
+
+pub(crate) fn copy_text_line<'a>(values: impl Iterator<Item = Option<&'a str>>) -> Bytes {
+    let mut line = String::new();
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            line.push('\t');
+        }
+        let Some(value) = value else {
+            line.push_str("\\N");
+            continue;
+        };
+        for c in value.chars() {
+            match c {
+                '\\' => line.push_str("\\\\"),
+                '\t' => line.push_str("\\t"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                c => line.push(c),
+            }
+        }
+    }
+    line.push('\n');
+    Bytes::from(line)
+}
+
+fn columns_copy_query(table_name: &TableName, column_schemas: &[ColumnSchema]) -> String {
+    format!(
+        r#"COPY (select {} from {}) TO STDOUT WITH (FORMAT text);"#,
+        select_list(column_schemas),
+        table_name.as_quoted_identifier()
+    )
+}
+
+/// Connections to the database which the tests run with the
+/// `postgres_tests` feature use, see `.github/workflows/general.yml`
+#[cfg(all(test, feature = "postgres_tests"))]
+pub(crate) mod test_database {
+    use tokio_postgres::NoTls;
+    use uuid::Uuid;
+
+    /// Host, port, username and password of the test database
+    pub(crate) fn connection_settings() -> (String, u16, String, String) {
+        let host = std::env::var("POSTGRES_HOST").unwrap_or_else(|_| "localhost".to_string());
+        let port = std::env::var("POSTGRES_PORT")
+            .map(|port| port.parse().expect("POSTGRES_PORT isn't a port"))
+            .unwrap_or(5432);
+        let username = std::env::var("POSTGRES_USER").unwrap_or_else(|_| "postgres".to_string());
+        let password =
+            std::env::var("POSTGRES_PASSWORD").unwrap_or_else(|_| "postgres".to_string());
+        (host, port, username, password)
+    }
+
+    /// A regular connection to create tables and publications and to write
+    /// changes with
+    pub(crate) async fn sql_client() -> tokio_postgres::Client {
+        let (host, port, username, password) = connection_settings();
+        let (client, connection) = tokio_postgres::Config::new()
+            .host(&host)
+            .port(port)
+            .dbname("postgres")
+            .user(&username)
+            .password(password)
+            .connect(NoTls)
+            .await
+            .expect("failed to connect to postgres");
+        tokio::spawn(connection);
+        client
+    }
+
+    /// A name of a table, publication or slot starting with `prefix` which no
+    /// other test run uses
+    pub(crate) fn unique_name(prefix: &str) -> String {
+        format!("{prefix}_{}", Uuid::new_v4().simple())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use tokio::net::UnixListener;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        clients::socks5::spawn_test_proxy,
+        table::{ColumnSchema, TableName},
+    };
+
+    use super::{
+        columns_copy_query, copy_text_line, session_statements, use_replica_identity_key,
+        ConnectionOptions, ReplicationClient,
+    };
+
+    fn column_schema(name: &str, typ: Type) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: true,
+            primary: false,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    #[test]
+    fn excluded_columns_are_not_selected_by_the_copy_query() {
+        let table_name = TableName {
+            schema: "public".to_string(),
+            name: "Files".to_string(),
+        };
+        // the table also has a `contents` bytea column, which is excluded
+        let column_schemas = vec![
+            column_schema("id", Type::INT8),
+            column_schema("file name", Type::TEXT),
+        ];
+
+        let query = columns_copy_query(&table_name, &column_schemas);
+
+        assert_eq!(
+            query,
+            r#"COPY (select id, "file name" from public."Files") TO STDOUT WITH (FORMAT text);"#
+        );
+        assert!(!query.contains("contents"));
+    }
+
+    #[test]
+    fn unique_index_of_the_replica_identity_is_the_key_without_a_primary_key() {
+        // create table accounts (email text not null, name text);
+        // create unique index accounts_email on accounts (email);
+        // alter table accounts replica identity using index accounts_email;
+        let mut column_schemas = vec![
+            column_schema("email", Type::TEXT),
+            column_schema("name", Type::TEXT),
+        ];
+
+        use_replica_identity_key(&mut column_schemas, &[true, false]);
+
+        let keys: Vec<_> = column_schemas.iter().map(|c| c.primary).collect();
+        assert_eq!(keys, vec![true, false]);
+    }
+
+    #[test]
+    fn primary_key_is_kept_over_the_replica_identity_index() {
+        let mut column_schemas = vec![
+            ColumnSchema {
+                primary: true,
+                ..column_schema("id", Type::INT8)
+            },
+            column_schema("email", Type::TEXT),
+        ];
+
+        use_replica_identity_key(&mut column_schemas, &[false, true]);
+
+        let keys: Vec<_> = column_schemas.iter().map(|c| c.primary).collect();
+        assert_eq!(keys, vec![true, false]);
+    }
+
+    #[test]
+    fn fetched_rows_are_escaped_like_copy_rows() {
+        let values = [
+            Some("1"),
+            Some("tab\there"),
+            Some("two\nlines\r"),
+            Some(r"C:\temp"),
+            Some(""),
+            None,
+        ];
+
+        let line = copy_text_line(values.into_iter());
+
+        assert_eq!(
+            &line[..],
+            &b"1\ttab\\there\ttwo\\nlines\\r\tC:\\\\temp\t\t\\N\n"[..]
+        );
+    }
+
+    #[test]
+    fn role_is_set_before_the_search_path() {
+        let options = ConnectionOptions {
+            role: Some("Reporting".to_string()),
+            search_path: Some(vec!["inventory".to_string(), "$user".to_string()]),
+            ..ConnectionOptions::default()
+        };
+
+        assert_eq!(
+            session_statements(&options),
+            vec![
+                r#"SET ROLE "Reporting""#.to_string(),
+                r#"SET search_path TO inventory, "$user""#.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_search_path_is_set_to_an_empty_string() {
+        let options = ConnectionOptions {
+            search_path: Some(vec![]),
+            ..ConnectionOptions::default()
+        };
+
+        assert_eq!(
+            session_statements(&options),
+            vec!["SET search_path TO ''".to_string()]
+        );
+    }
+
+    #[test]
+    fn nothing_is_set_by_default() {
+        assert!(session_statements(&ConnectionOptions::default()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn host_starting_with_a_slash_connects_to_a_unix_socket() {
+        let socket_dir =
+            std::env::temp_dir().join(format!("pg_replicate_socket_test_{}", std::process::id()));
+        std::fs::create_dir_all(&socket_dir).expect("failed to create socket dir");
+        let listener =
+            UnixListener::bind(socket_dir.join(".s.PGSQL.5433")).expect("failed to bind socket");
+        let accepted = tokio::spawn(async move { listener.accept().await.is_ok() });
+
+        // The listener isn't Postgres and closes the connection it accepts,
+        // so connecting fails after reaching the socket
+        let result = ReplicationClient::connect_no_tls(
+            socket_dir.to_str().expect("socket dir isn't utf-8"),
+            5433,
+            "postgres",
+            "postgres",
+            None,
+            &ConnectionOptions::default(),
+        )
+        .await;
+        std::fs::remove_dir_all(&socket_dir).expect("failed to remove socket dir");
+
+        assert!(result.is_err());
+        assert!(accepted.await.expect("accepting task panicked"));
+    }
+
+    #[tokio::test]
+    async fn connection_goes_through_the_socks5_proxy() {
+        // Stands in for a Postgres only the proxy can reach. It reads the
+        // startup message and closes the connection, so connecting fails
+        // after reaching it.
+        let target = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind target");
+        let target_address = target.local_addr().expect("target has no address");
+        let received_startup = tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.expect("failed to accept");
+            let len = stream
+                .read_i32()
+                .await
+                .expect("failed to read startup message");
+            len > 0
+        });
+        let (proxy_address, proxy) = spawn_test_proxy(target_address).await;
+        let options = ConnectionOptions {
+            socks5_proxy: Some(proxy_address.to_string()),
+            ..ConnectionOptions::default()
+        };
+
+        let result = ReplicationClient::connect_no_tls(
+            "postgres.internal",
+            5433,
+            "postgres",
+            "postgres",
+            None,
+            &options,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(received_startup.await.expect("target task panicked"));
+        assert_eq!(
+            proxy.await.expect("proxy task panicked"),
+            ("postgres.internal".to_string(), 5433)
+        );
+    }
+
+    #[tokio::test]
+    async fn query_connection_goes_through_the_socks5_proxy() {
+        let target = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind target");
+        let target_address = target.local_addr().expect("target has no address");
+        let received_startup = tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.expect("failed to accept");
+            let len = stream
+                .read_i32()
+                .await
+                .expect("failed to read startup message");
+            len > 0
+        });
+        let (proxy_address, proxy) = spawn_test_proxy(target_address).await;
+        let options = ConnectionOptions {
+            socks5_proxy: Some(proxy_address.to_string()),
+            ..ConnectionOptions::default()
+        };
+
+        let result = ReplicationClient::connect_query_client_no_tls(
+            "postgres.internal",
+            5433,
+            "postgres",
+            "postgres",
+            None,
+            &options,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(received_startup.await.expect("target task panicked"));
+        assert_eq!(
+            proxy.await.expect("proxy task panicked"),
+            ("postgres.internal".to_string(), 5433)
+        );
+    }
+
+    #[cfg(feature = "postgres_tests")]
+    mod postgres {
+        use std::time::Duration;
+
+        use futures::StreamExt;
+        use postgres_replication::protocol::{LogicalReplicationMessage, ReplicationMessage};
+        use tokio_postgres::types::PgLsn;
+
+        use crate::{
+            clients::postgres::{
+                test_database::{connection_settings, sql_client, unique_name},
+                AuthMethod, ConnectionOptions, ReplicationClient, ReplicationClientError,
+                SlotOptions,
+            },
+            table::TableName,
+        };
+
+        async fn client() -> ReplicationClient {
+            let (host, port, username, password) = connection_settings();
+            let client = ReplicationClient::connect_no_tls(
+                &host,
+                port,
+                "postgres",
+                &username,
+                Some(password),
+                &ConnectionOptions::default(),
+            )
+            .await
+            .expect("failed to connect to postgres");
+            client
+                .begin_readonly_transaction()
+                .await
+                .expect("failed to begin transaction");
+            client
+        }
+
+        /// A slot name no other test run uses
+        fn slot_name() -> String {
+            unique_name("test_slot")
+        }
+
+        #[tokio::test]
+        async fn missing_slot_is_not_created_when_using_an_existing_one() {
+            let client = client().await;
+            let slot_name = slot_name();
+
+            let result = client
+                .get_slot_with_options(&slot_name, SlotOptions::UseExisting)
+                .await;
+
+            match result {
+                Err(e @ ReplicationClientError::MissingSlot(_)) => {
+                    assert_eq!(e.to_string(), format!("slot {slot_name} doesn't exist"))
+                }
+                other => panic!("expected a missing slot error, got {other:?}"),
+            }
+            assert!(!client.drop_slot(&slot_name).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn missing_slot_is_created_and_then_reused() {
+            let client = client().await;
+            let slot_name = slot_name();
+
+            let created = client
+                .get_slot_with_options(&slot_name, SlotOptions::CreateIfMissing)
+                .await
+                .unwrap();
+            client.commit_txn().await.unwrap();
+            client.begin_readonly_transaction().await.unwrap();
+            let reused = client
+                .get_slot_with_options(&slot_name, SlotOptions::CreateIfMissing)
+                .await
+                .unwrap();
+            let existing = client
+                .get_slot_with_options(&slot_name, SlotOptions::UseExisting)
+                .await
+                .unwrap();
+            client.commit_txn().await.unwrap();
+            assert!(client.drop_slot(&slot_name).await.unwrap());
+
+            assert!(created.created);
+            assert!(!reused.created);
+            assert_eq!(reused.confirmed_flush_lsn, created.confirmed_flush_lsn);
+            assert!(!existing.created);
+            assert_eq!(existing.confirmed_flush_lsn, created.confirmed_flush_lsn);
+        }
+
+        #[tokio::test]
+        async fn temporary_slot_is_dropped_when_its_connection_closes() {
+            let client = client().await;
+            let slot_name = slot_name();
+
+            let temporary = client
+                .get_slot_with_options(&slot_name, SlotOptions::Temporary)
+                .await
+                .unwrap();
+            let other_client = client().await;
+            let existing = other_client
+                .get_slot_with_options(&slot_name, SlotOptions::UseExisting)
+                .await;
+            drop(client);
+
+            assert!(temporary.created);
+            assert!(
+                existing.is_ok(),
+                "temporary slot isn't visible: {existing:?}"
+            );
+            // Postgres drops the slot once it notices the connection closed
+            for _ in 0..50 {
+                let result = other_client
+                    .get_slot_with_options(&slot_name, SlotOptions::UseExisting)
+                    .await;
+                if matches!(result, Err(ReplicationClientError::MissingSlot(_))) {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            panic!("temporary slot {slot_name} outlived its connection");
+        }
+
+        #[tokio::test]
+        async fn unique_index_of_the_replica_identity_is_the_key_of_a_table() {
+            let sql_client = sql_client().await;
+            let table = unique_name("test_accounts");
+            sql_client
+                .batch_execute(&format!(
+                    "create table {table} (id int, email text not null, name text);
+                    create unique index {table}_email on {table} (email);
+                    alter table {table} replica identity using index {table}_email;"
+                ))
+                .await
+                .unwrap();
+            let client = client().await;
+            let table_name = TableName {
+                schema: "public".to_string(),
+                name: table.clone(),
+            };
+
+            let table_schemas = client
+                .get_table_schemas(std::slice::from_ref(&table_name))
+                .await;
+            drop(client);
+            sql_client
+                .batch_execute(&format!("drop table {table};"))
+                .await
+                .unwrap();
+
+            let table_schemas = table_schemas.unwrap();
+            let table_schema = table_schemas.values().next().expect("table was skipped");
+            let keys: Vec<_> = table_schema
+                .column_schemas
+                .iter()
+                .map(|column_schema| (column_schema.name.as_str(), column_schema.primary))
+                .collect();
+            assert_eq!(keys, vec![("id", false), ("email", true), ("name", false)]);
+        }
+
+        #[tokio::test]
+        async fn requiring_channel_binding_refuses_a_connection_without_it() {
+            let (host, port, username, password) = connection_settings();
+            let options = ConnectionOptions {
+                auth: AuthMethod::ScramSha256ChannelBinding,
+                ..ConnectionOptions::default()
+            };
+
+            let result = ReplicationClient::connect_no_tls(
+                &host,
+                port,
+                "postgres",
+                &username,
+                Some(password),
+                &options,
+            )
+            .await;
+
+            // Without TLS no channel binding is possible, whichever method
+            // the server asks for
+            match result {
+                Err(ReplicationClientError::TokioPostgresError(e)) => {
+                    assert!(
+                        e.to_string().contains("authentication"),
+                        "expected an authentication error, got {e}"
+                    )
+                }
+                Err(e) => panic!("expected an authentication error, got {e}"),
+                Ok(_) => panic!("connected without channel binding"),
+            }
+        }
+
+        #[tokio::test]
+        async fn current_wal_lsn_is_at_or_after_every_streamed_lsn() {
+            let sql_client = sql_client().await;
+            let table = unique_name("test_table");
+            let publication = unique_name("test_publication");
+            sql_client
+                .batch_execute(&format!(
+                    "create table {table} (id int primary key);
+                    create publication {publication} for table {table};"
+                ))
+                .await
+                .unwrap();
+            let client = client().await;
+            let slot_name = slot_name();
+            let slot = client
+                .get_slot_with_options(&slot_name, SlotOptions::Temporary)
+                .await
+                .unwrap();
+            client.commit_txn().await.unwrap();
+            sql_client
+                .batch_execute(&format!("insert into {table} values (1), (2);"))
+                .await
+                .unwrap();
+
+            let stream = client
+                .get_logical_replication_stream(&publication, &slot_name, slot.confirmed_flush_lsn)
+                .await
+                .unwrap();
+            let mut stream = Box::pin(stream);
+            let mut last_streamed_lsn = slot.confirmed_flush_lsn;
+            loop {
+                let message = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                    .await
+                    .expect("the insert's commit wasn't streamed")
+                    .expect("the stream ended")
+                    .unwrap();
+                if let ReplicationMessage::XLogData(xlog_data) = message {
+                    last_streamed_lsn = last_streamed_lsn.max(PgLsn::from(xlog_data.wal_end()));
+                    if let LogicalReplicationMessage::Commit(commit_body) = xlog_data.data() {
+                        last_streamed_lsn =
+                            last_streamed_lsn.max(PgLsn::from(commit_body.end_lsn()));
+                        break;
+                    }
+                }
+            }
+            let current_wal_lsn = client.get_current_wal_lsn().await.unwrap();
+            drop(stream);
+            drop(client);
+            sql_client
+                .batch_execute(&format!(
+                    "drop publication {publication}; drop table {table};"
+                ))
+                .await
+                .unwrap();
+
+            assert!(
+                current_wal_lsn >= last_streamed_lsn,
+                "current wal lsn {current_wal_lsn} is before streamed lsn {last_streamed_lsn}"
+            );
+        }
+    }
 }
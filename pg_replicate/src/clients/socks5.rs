@@ -0,0 +1,230 @@
+//! Connects to a host through a SOCKS5 proxy (RFC 1928) which needs no
+//! authentication, like the dynamic port forwarding of `ssh -D`, so that a
+//! Postgres only reachable from a bastion can be replicated from without a
+//! separate tunnel process forwarding a local port.
+
+use std::{
+    io::{Error, ErrorKind},
+    net::IpAddr,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const CONNECT: u8 = 0x01;
+const RESERVED: u8 = 0x00;
+const IPV4: u8 = 0x01;
+const DOMAIN_NAME: u8 = 0x03;
+const IPV6: u8 = 0x04;
+const SUCCEEDED: u8 = 0x00;
+
+/// Connects to the SOCKS5 proxy at `proxy`, a `host:port`, and asks it to
+/// connect to `host` on `port`. The returned stream is the tunnel to `host`.
+/// Host names are resolved by the proxy, so names only the bastion can
+/// resolve work.
+pub async fn connect(proxy: &str, host: &str, port: u16) -> Result<TcpStream, Error> {
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    stream.write_all(&[VERSION, 1, NO_AUTHENTICATION]).await?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    if method[0] != VERSION {
+        return Err(invalid_reply(format!("unknown version {:#04x}", method[0])));
+    }
+    if method[1] != NO_AUTHENTICATION {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "socks5 proxy requires authentication",
+        ));
+    }
+
+    let mut request = vec![VERSION, CONNECT, RESERVED];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len()).map_err(|_| {
+                Error::new(ErrorKind::InvalidInput, "host name longer than 255 bytes")
+            })?;
+            request.push(DOMAIN_NAME);
+            request.push(len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(invalid_reply(format!("unknown version {:#04x}", reply[0])));
+    }
+    if reply[1] != SUCCEEDED {
+        return Err(Error::new(
+            ErrorKind::ConnectionRefused,
+            format!(
+                "socks5 proxy failed to connect to {host}:{port}: {}",
+                reply_message(reply[1])
+            ),
+        ));
+    }
+
+    // The reply ends with the address the proxy connected from, which isn't needed
+    let address_len = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN_NAME => stream.read_u8().await? as usize,
+        address_type => {
+            return Err(invalid_reply(format!(
+                "unknown address type {address_type:#04x}"
+            )))
+        }
+    };
+    let mut bound_address = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(stream)
+}
+
+fn invalid_reply(message: String) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid socks5 reply: {message}"),
+    )
+}
+
+fn reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// A SOCKS5 proxy for tests which accepts a single connection and tunnels it
+/// to `target`, whatever address it was asked to connect to. Returns the
+/// proxy's address and a task returning the host and port it was asked for.
+#[cfg(test)]
+pub(crate) async fn spawn_test_proxy(
+    target: std::net::SocketAddr,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<(String, u16)>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind proxy");
+    let address = listener.local_addr().expect("proxy has no address");
+    let task = tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await.expect("failed to accept");
+
+        let mut greeting = [0u8; 2];
+        client.read_exact(&mut greeting).await.unwrap();
+        let mut methods = vec![0u8; greeting[1] as usize];
+        client.read_exact(&mut methods).await.unwrap();
+        client
+            .write_all(&[VERSION, NO_AUTHENTICATION])
+            .await
+            .unwrap();
+
+        let mut request = [0u8; 4];
+        client.read_exact(&mut request).await.unwrap();
+        let host = match request[3] {
+            DOMAIN_NAME => {
+                let len = client.read_u8().await.unwrap();
+                let mut host = vec![0u8; len as usize];
+                client.read_exact(&mut host).await.unwrap();
+                String::from_utf8(host).unwrap()
+            }
+            IPV4 => {
+                let mut ip = [0u8; 4];
+                client.read_exact(&mut ip).await.unwrap();
+                std::net::Ipv4Addr::from(ip).to_string()
+            }
+            address_type => panic!("unexpected address type {address_type}"),
+        };
+        let port = client.read_u16().await.unwrap();
+
+        let mut upstream = TcpStream::connect(target).await.unwrap();
+        client
+            .write_all(&[VERSION, SUCCEEDED, RESERVED, IPV4, 127, 0, 0, 1, 0, 0])
+            .await
+            .unwrap();
+        // Tunnels until either side closes the connection
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+        (host, port)
+    });
+    (address, task)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{connect, spawn_test_proxy};
+
+    #[tokio::test]
+    async fn connection_is_tunneled_through_the_proxy() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_address = target.local_addr().unwrap();
+        let echo = tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            let mut message = [0u8; 4];
+            stream.read_exact(&mut message).await.unwrap();
+            stream.write_all(&message).await.unwrap();
+        });
+        let (proxy_address, proxy) = spawn_test_proxy(target_address).await;
+
+        let mut stream = connect(&proxy_address.to_string(), "db.internal", 5432)
+            .await
+            .expect("failed to connect through the proxy");
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        drop(stream);
+
+        assert_eq!(&echoed, b"ping");
+        echo.await.unwrap();
+        assert_eq!(proxy.await.unwrap(), ("db.internal".to_string(), 5432));
+    }
+
+    #[tokio::test]
+    async fn failed_proxy_connection_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            client.read_exact(&mut greeting).await.unwrap();
+            client.write_all(&[0x05, 0x00]).await.unwrap();
+            let mut request = [0u8; 10];
+            client.read_exact(&mut request).await.unwrap();
+            // host unreachable
+            client
+                .write_all(&[0x05, 0x04, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+        });
+
+        let result = connect(&proxy_address.to_string(), "10.0.0.1", 5432).await;
+
+        let error = result.expect_err("connecting succeeded");
+        assert!(error.to_string().contains("host unreachable"), "{error}");
+    }
+}
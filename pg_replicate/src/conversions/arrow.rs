@@ -0,0 +1,671 @@
+use std::sync::Arc;
+
+use arrow::{
+    array::{
+        ArrayRef, BinaryArray, BooleanArray, Date32Array, Decimal128Array, Float32Array,
+        Float64Array, Int16Array, Int32Array, Int64Array, ListArray, StringArray,
+        Time64MicrosecondArray, TimestampMicrosecondArray, UInt32Array,
+    },
+    buffer::{NullBuffer, OffsetBuffer},
+    datatypes::{DataType, Field, Schema, TimeUnit},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use chrono::{NaiveDate, Timelike};
+use thiserror::Error;
+use tokio_postgres::types::Type;
+
+use crate::table::{ColumnSchema, TableSchema};
+
+use super::{hex, table_row::TableRow, ArrayCell, Cell};
+
+/// Largest precision of a [`DataType::Decimal128`]
+const MAX_DECIMAL128_PRECISION: u8 = 38;
+
+/// Time zone of `timestamptz` columns, whose values are converted to UTC
+const TIMESTAMPTZ_TIME_ZONE: &str = "UTC";
+
+#[derive(Debug, Error)]
+pub enum ArrowConversionError {
+    #[error("arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("numeric {value} doesn't fit decimal({precision}, {scale})")]
+    DecimalOutOfRange {
+        value: String,
+        precision: u8,
+        scale: i8,
+    },
+}
+
+/// Converts table rows to Arrow record batches for sinks writing Arrow based
+/// formats like Parquet. Postgres types map to Arrow types as follows:
+///
+/// - `bool`, `int2`, `int4`, `int8`, `oid`, `float4` and `float8` to their
+///   Arrow counterparts
+/// - `numeric` with a precision of at most 38 to `Decimal128` of the same
+///   precision and scale. Other `numeric`s, which Arrow can't hold exactly,
+///   to `Utf8`
+/// - `date` to `Date32`, `time` to `Time64` in microseconds and `timestamp`
+///   and `timestamptz` to `Timestamp` in microseconds, the latter in UTC
+/// - `bytea` to `Binary`
+/// - arrays to `List`s of their element's type
+/// - all other types, including `json`, `jsonb` and `uuid`, to `Utf8`
+pub struct ArrowConverter;
+
+impl ArrowConverter {
+    /// Returns the Arrow type of the values of a column of type `typ` with
+    /// the type modifier `modifier`
+    pub fn data_type(typ: &Type, modifier: i32) -> DataType {
+        match *typ {
+            Type::BOOL => DataType::Boolean,
+            Type::INT2 => DataType::Int16,
+            Type::INT4 => DataType::Int32,
+            Type::INT8 => DataType::Int64,
+            Type::OID => DataType::UInt32,
+            Type::FLOAT4 => DataType::Float32,
+            Type::FLOAT8 => DataType::Float64,
+            Type::NUMERIC => match Self::numeric_precision_and_scale(modifier) {
+                Some((precision, scale)) => DataType::Decimal128(precision, scale),
+                None => DataType::Utf8,
+            },
+            Type::DATE => DataType::Date32,
+            Type::TIME => DataType::Time64(TimeUnit::Microsecond),
+            Type::TIMESTAMP => DataType::Timestamp(TimeUnit::Microsecond, None),
+            Type::TIMESTAMPTZ => {
+                DataType::Timestamp(TimeUnit::Microsecond, Some(TIMESTAMPTZ_TIME_ZONE.into()))
+            }
+            Type::BYTEA => DataType::Binary,
+            Type::BOOL_ARRAY => Self::list(&Type::BOOL, modifier),
+            Type::CHAR_ARRAY
+            | Type::BPCHAR_ARRAY
+            | Type::VARCHAR_ARRAY
+            | Type::NAME_ARRAY
+            | Type::TEXT_ARRAY
+            | Type::UUID_ARRAY
+            | Type::JSON_ARRAY
+            | Type::JSONB_ARRAY => Self::list(&Type::TEXT, modifier),
+            Type::INT2_ARRAY => Self::list(&Type::INT2, modifier),
+            Type::INT4_ARRAY => Self::list(&Type::INT4, modifier),
+            Type::INT8_ARRAY => Self::list(&Type::INT8, modifier),
+            Type::OID_ARRAY => Self::list(&Type::OID, modifier),
+            Type::FLOAT4_ARRAY => Self::list(&Type::FLOAT4, modifier),
+            Type::FLOAT8_ARRAY => Self::list(&Type::FLOAT8, modifier),
+            Type::NUMERIC_ARRAY => Self::list(&Type::NUMERIC, modifier),
+            Type::DATE_ARRAY => Self::list(&Type::DATE, modifier),
+            Type::TIME_ARRAY => Self::list(&Type::TIME, modifier),
+            Type::TIMESTAMP_ARRAY => Self::list(&Type::TIMESTAMP, modifier),
+            Type::TIMESTAMPTZ_ARRAY => Self::list(&Type::TIMESTAMPTZ, modifier),
+            Type::BYTEA_ARRAY => Self::list(&Type::BYTEA, modifier),
+            _ => DataType::Utf8,
+        }
+    }
+
+    /// Arrays have the type modifier of their elements
+    fn list(element_type: &Type, modifier: i32) -> DataType {
+        let element_field = Field::new("item", Self::data_type(element_type, modifier), true);
+        DataType::List(Arc::new(element_field))
+    }
+
+    /// Returns the precision and scale of a `numeric(precision, scale)`, `None`
+    /// for a `numeric` without a precision or one too precise for a
+    /// [`DataType::Decimal128`]
+    fn numeric_precision_and_scale(modifier: i32) -> Option<(u8, i8)> {
+        // the modifier of numeric(p, s) is ((p << 16) | s) + 4, and -1 for a
+        // plain numeric
+        if modifier < 4 {
+            return None;
+        }
+        let modifier = modifier - 4;
+        let precision = (modifier >> 16) & 0xffff;
+        let scale = modifier & 0xffff;
+        if precision < 1 || precision > MAX_DECIMAL128_PRECISION as i32 || scale > precision {
+            return None;
+        }
+        Some((precision as u8, scale as i8))
+    }
+
+    /// Returns the Arrow fields of `column_schemas`. They are all nullable as
+    /// the rows of deletes only hold the values of key columns.
+    pub fn fields(column_schemas: &[ColumnSchema]) -> Vec<Field> {
+        column_schemas
+            .iter()
+            .map(|column_schema| {
+                let data_type = Self::data_type(&column_schema.typ, column_schema.modifier);
+                Field::new(&column_schema.name, data_type, true)
+            })
+            .collect()
+    }
+
+    pub fn schema(table_schema: &TableSchema) -> Schema {
+        Schema::new(Self::fields(&table_schema.column_schemas))
+    }
+
+    /// Converts `table_rows` of the table `table_schema` to a record batch
+    /// with the schema [`ArrowConverter::schema`] returns
+    pub fn try_from_rows(
+        table_schema: &TableSchema,
+        table_rows: &[TableRow],
+    ) -> Result<RecordBatch, ArrowConversionError> {
+        let schema = Arc::new(Self::schema(table_schema));
+        let columns = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(index, field)| Self::column_to_array(field.data_type(), table_rows, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+
+    /// Converts the `index`th values of `table_rows` to an array of
+    /// `data_type`. Values of a type other than the one `data_type` maps
+    /// from are converted to nulls.
+    pub fn column_to_array(
+        data_type: &DataType,
+        table_rows: &[TableRow],
+        index: usize,
+    ) -> Result<ArrayRef, ArrowConversionError> {
+        let cells: Vec<&Cell> = table_rows.iter().map(|row| &row.values[index]).collect();
+        Self::cells_to_array(data_type, &cells)
+    }
+
+    fn cells_to_array(
+        data_type: &DataType,
+        cells: &[&Cell],
+    ) -> Result<ArrayRef, ArrowConversionError> {
+        let cells = cells.iter().copied();
+        let array: ArrayRef = match data_type {
+            DataType::Boolean => Arc::new(cells.map(Cell::as_bool).collect::<BooleanArray>()),
+            DataType::Int16 => Arc::new(cells.map(Cell::as_i16).collect::<Int16Array>()),
+            DataType::Int32 => Arc::new(cells.map(Cell::as_i32).collect::<Int32Array>()),
+            DataType::Int64 => Arc::new(cells.map(Cell::as_i64).collect::<Int64Array>()),
+            DataType::UInt32 => Arc::new(cells.map(Cell::as_u32).collect::<UInt32Array>()),
+            DataType::Float32 => Arc::new(cells.map(Cell::as_f32).collect::<Float32Array>()),
+            DataType::Float64 => Arc::new(cells.map(Cell::as_f64).collect::<Float64Array>()),
+            DataType::Decimal128(precision, scale) => {
+                let values = cells
+                    .map(|cell| Self::decimal(cell, *precision, *scale))
+                    .collect::<Result<Decimal128Array, _>>()?;
+                Arc::new(values.with_precision_and_scale(*precision, *scale)?)
+            }
+            DataType::Date32 => Arc::new(
+                cells
+                    .map(|cell| cell.as_date().map(Self::days_since_epoch))
+                    .collect::<Date32Array>(),
+            ),
+            DataType::Time64(TimeUnit::Microsecond) => Arc::new(
+                cells
+                    .map(|cell| {
+                        cell.as_time().map(|time| {
+                            time.num_seconds_from_midnight() as i64 * 1_000_000
+                                + time.nanosecond() as i64 / 1_000
+                        })
+                    })
+                    .collect::<Time64MicrosecondArray>(),
+            ),
+            DataType::Timestamp(TimeUnit::Microsecond, None) => Arc::new(
+                cells
+                    .map(|cell| {
+                        cell.as_timestamp()
+                            .map(|timestamp| timestamp.and_utc().timestamp_micros())
+                    })
+                    .collect::<TimestampMicrosecondArray>(),
+            ),
+            DataType::Timestamp(TimeUnit::Microsecond, time_zone) => Arc::new(
+                cells
+                    .map(|cell| {
+                        cell.as_timestamptz()
+                            .map(|timestamp| timestamp.timestamp_micros())
+                    })
+                    .collect::<TimestampMicrosecondArray>()
+                    .with_timezone_opt(time_zone.clone()),
+            ),
+            DataType::Binary => Arc::new(cells.map(Cell::as_bytes).collect::<BinaryArray>()),
+            DataType::List(element_field) => {
+                let mut offsets = vec![0];
+                let mut validity = vec![];
+                let mut elements = vec![];
+                for cell in cells {
+                    match cell.as_array().and_then(Self::array_elements) {
+                        Some(array_elements) => {
+                            elements.extend(array_elements);
+                            validity.push(true);
+                        }
+                        None => validity.push(false),
+                    }
+                    offsets.push(elements.len() as i32);
+                }
+                let elements: Vec<&Cell> = elements.iter().collect();
+                let values = Self::cells_to_array(element_field.data_type(), &elements)?;
+                Arc::new(ListArray::try_new(
+                    element_field.clone(),
+                    OffsetBuffer::new(offsets.into()),
+                    values,
+                    Some(NullBuffer::from(validity)),
+                )?)
+            }
+            _ => Arc::new(cells.map(Self::cell_to_string).collect::<StringArray>()),
+        };
+        Ok(array)
+    }
+
+    fn days_since_epoch(date: NaiveDate) -> i32 {
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("invalid epoch");
+        date.signed_duration_since(epoch).num_days() as i32
+    }
+
+    fn decimal(
+        cell: &Cell,
+        precision: u8,
+        scale: i8,
+    ) -> Result<Option<i128>, ArrowConversionError> {
+        let Some(numeric) = cell.as_numeric() else {
+            return Ok(None);
+        };
+        let value = numeric.to_string();
+        match parse_decimal(&value, precision, scale) {
+            Some(decimal) => Ok(Some(decimal)),
+            None => Err(ArrowConversionError::DecimalOutOfRange {
+                value,
+                precision,
+                scale,
+            }),
+        }
+    }
+
+    /// Returns the elements of `array` as cells, `None` for a null array
+    fn array_elements(array: &ArrayCell) -> Option<Vec<Cell>> {
+        fn elements<T: Clone>(values: &[Option<T>], cell: impl Fn(T) -> Cell) -> Vec<Cell> {
+            values
+                .iter()
+                .map(|value| value.clone().map_or(Cell::Null, &cell))
+                .collect()
+        }
+
+        let elements = match array {
+            ArrayCell::Null => return None,
+            ArrayCell::Bool(values) => elements(values, Cell::Bool),
+            ArrayCell::String(values) => elements(values, Cell::String),
+            ArrayCell::I16(values) => elements(values, Cell::I16),
+            ArrayCell::I32(values) => elements(values, Cell::I32),
+            ArrayCell::U32(values) => elements(values, Cell::U32),
+            ArrayCell::I64(values) => elements(values, Cell::I64),
+            ArrayCell::F32(values) => elements(values, Cell::F32),
+            ArrayCell::F64(values) => elements(values, Cell::F64),
+            ArrayCell::Numeric(values) => elements(values, Cell::Numeric),
+            ArrayCell::Date(values) => elements(values, Cell::Date),
+            ArrayCell::Time(values) => elements(values, Cell::Time),
+            ArrayCell::TimeStamp(values) => elements(values, Cell::TimeStamp),
+            ArrayCell::TimeStampTz(values) => elements(values, Cell::TimeStampTz),
+            ArrayCell::Uuid(values) => elements(values, Cell::Uuid),
+            ArrayCell::Json(values) => elements(values, Cell::Json),
+            ArrayCell::Bytes(values) => elements(values, Cell::Bytes),
+        };
+        Some(elements)
+    }
+
+    /// Formats the values of `Utf8` columns like Postgres' text format does
+    fn cell_to_string(cell: &Cell) -> Option<String> {
+        let value = match cell {
+            Cell::Null => return None,
+            Cell::Bool(value) => value.to_string(),
+            Cell::String(value) | Cell::Xml(value) => value.clone(),
+            Cell::I16(value) => value.to_string(),
+            Cell::I32(value) => value.to_string(),
+            Cell::U32(value) => value.to_string(),
+            Cell::I64(value) => value.to_string(),
+            Cell::F32(value) => value.to_string(),
+            Cell::F64(value) => value.to_string(),
+            Cell::Numeric(value) => value.to_string(),
+            Cell::Date(value) => value.format("%Y-%m-%d").to_string(),
+            Cell::Time(value) => value.format("%H:%M:%S%.f").to_string(),
+            Cell::TimeStamp(value) => value.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+            Cell::TimeStampTz(value) => value.to_rfc3339(),
+            Cell::Uuid(value) => value.to_string(),
+            Cell::Json(value) => value.to_string(),
+            Cell::Bytes(value) => format!("\\x{}", hex::to_hex(value).to_lowercase()),
+            Cell::Geometry(value) => value.to_string(),
+            Cell::Ewkb(value) => hex::to_hex(value),
+            Cell::Array(array) => {
+                let elements = Self::array_elements(array)?;
+                let elements = elements
+                    .iter()
+                    .map(|element| {
+                        Self::cell_to_string(element)
+                            .map_or(serde_json::Value::Null, serde_json::Value::String)
+                    })
+                    .collect();
+                serde_json::Value::Array(elements).to_string()
+            }
+        };
+        Some(value)
+    }
+}
+
+/// Parses the decimal number `value`, optionally in scientific notation, to
+/// its digits scaled by 10^`scale`, rounding half away from zero. Returns
+/// `None` if `value` isn't a finite number or has more than `precision`
+/// digits once scaled.
+fn parse_decimal(value: &str, precision: u8, scale: i8) -> Option<i128> {
+    let (mantissa, exponent) = match value.find(['e', 'E']) {
+        Some(index) => (&value[..index], value[index + 1..].parse::<i32>().ok()?),
+        None => (value, 0),
+    };
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => (true, mantissa),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let mut digits: i128 = 0;
+    for digit in integer.bytes().chain(fraction.bytes()) {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        digits = digits
+            .checked_mul(10)?
+            .checked_add((digit - b'0') as i128)?;
+    }
+
+    let shift = scale as i32 + exponent - fraction.len() as i32;
+    let scaled = if shift >= 0 {
+        digits.checked_mul(10i128.checked_pow(shift as u32)?)?
+    } else {
+        match 10i128.checked_pow(shift.unsigned_abs()) {
+            Some(divisor) => digits.checked_add(divisor / 2)? / divisor,
+            None => 0,
+        }
+    };
+    if scaled >= 10i128.pow(precision as u32) {
+        return None;
+    }
+    Some(if negative { -scaled } else { scaled })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{
+            Array, BinaryArray, BooleanArray, Date32Array, Decimal128Array, Float64Array,
+            Int32Array, Int64Array, ListArray, StringArray, Time64MicrosecondArray,
+            TimestampMicrosecondArray,
+        },
+        datatypes::{DataType, Field, TimeUnit},
+    };
+    use chrono::{DateTime, NaiveDate, NaiveTime};
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        conversions::{table_row::TableRow, ArrayCell, Cell},
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::{parse_decimal, ArrowConversionError, ArrowConverter};
+
+    /// Type modifier of a `numeric(precision, scale)`
+    fn numeric_modifier(precision: i32, scale: i32) -> i32 {
+        ((precision << 16) | scale) + 4
+    }
+
+    fn items_schema(columns: &[(&str, Type, i32)]) -> TableSchema {
+        let column_schemas = columns
+            .iter()
+            .map(|(name, typ, modifier)| ColumnSchema {
+                name: name.to_string(),
+                typ: typ.clone(),
+                modifier: *modifier,
+                nullable: true,
+                primary: false,
+                identity: false,
+                domain: None,
+            })
+            .collect();
+        TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "items".to_string(),
+            },
+            table_id: 1,
+            column_schemas,
+        }
+    }
+
+    fn rows(values: Vec<Vec<Cell>>) -> Vec<TableRow> {
+        values
+            .into_iter()
+            .map(|values| TableRow { values })
+            .collect()
+    }
+
+    fn column<T: 'static>(table_schema: &TableSchema, rows: &[TableRow]) -> T
+    where
+        T: Array + Clone,
+    {
+        let batch = ArrowConverter::try_from_rows(table_schema, rows).unwrap();
+        batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<T>()
+            .expect("unexpected array type")
+            .clone()
+    }
+
+    #[test]
+    fn schema_maps_postgres_types_to_arrow_types() {
+        let table_schema = items_schema(&[
+            ("flag", Type::BOOL, -1),
+            ("id", Type::INT8, -1),
+            ("price", Type::NUMERIC, numeric_modifier(10, 2)),
+            ("amount", Type::NUMERIC, -1),
+            ("born", Type::DATE, -1),
+            ("at", Type::TIME, -1),
+            ("created", Type::TIMESTAMP, -1),
+            ("updated", Type::TIMESTAMPTZ, -1),
+            ("tags", Type::TEXT_ARRAY, -1),
+            ("doc", Type::JSONB, -1),
+            ("blob", Type::BYTEA, -1),
+            ("uid", Type::UUID, -1),
+        ]);
+
+        let schema = ArrowConverter::schema(&table_schema);
+
+        let data_types: Vec<&DataType> = schema
+            .fields()
+            .iter()
+            .map(|field| field.data_type())
+            .collect();
+        assert_eq!(
+            data_types,
+            vec![
+                &DataType::Boolean,
+                &DataType::Int64,
+                &DataType::Decimal128(10, 2),
+                &DataType::Utf8,
+                &DataType::Date32,
+                &DataType::Time64(TimeUnit::Microsecond),
+                &DataType::Timestamp(TimeUnit::Microsecond, None),
+                &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                &DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                &DataType::Utf8,
+                &DataType::Binary,
+                &DataType::Utf8,
+            ]
+        );
+        assert!(schema.fields().iter().all(|field| field.is_nullable()));
+    }
+
+    #[test]
+    fn scalar_cells_are_converted_with_nulls() {
+        let table_schema = items_schema(&[("flag", Type::BOOL, -1)]);
+        let array: BooleanArray = column(
+            &table_schema,
+            &rows(vec![vec![Cell::Bool(true)], vec![Cell::Null]]),
+        );
+        assert_eq!(array, BooleanArray::from(vec![Some(true), None]));
+
+        let table_schema = items_schema(&[("id", Type::INT4, -1)]);
+        let array: Int32Array = column(&table_schema, &rows(vec![vec![Cell::I32(7)]]));
+        assert_eq!(array, Int32Array::from(vec![7]));
+
+        let table_schema = items_schema(&[("id", Type::INT8, -1)]);
+        let array: Int64Array = column(&table_schema, &rows(vec![vec![Cell::I64(i64::MAX)]]));
+        assert_eq!(array, Int64Array::from(vec![i64::MAX]));
+
+        let table_schema = items_schema(&[("ratio", Type::FLOAT8, -1)]);
+        let array: Float64Array = column(&table_schema, &rows(vec![vec![Cell::F64(0.5)]]));
+        assert_eq!(array, Float64Array::from(vec![0.5]));
+
+        let table_schema = items_schema(&[("name", Type::TEXT, -1)]);
+        let array: StringArray = column(
+            &table_schema,
+            &rows(vec![vec![Cell::String("ada".to_string())]]),
+        );
+        assert_eq!(array, StringArray::from(vec!["ada"]));
+    }
+
+    #[test]
+    fn numeric_is_converted_to_a_decimal_of_the_columns_scale() {
+        let table_schema = items_schema(&[("price", Type::NUMERIC, numeric_modifier(10, 2))]);
+        let table_rows = rows(vec![
+            vec![Cell::Numeric("123.45".parse().unwrap())],
+            vec![Cell::Numeric("-0.5".parse().unwrap())],
+            vec![Cell::Null],
+        ]);
+
+        let array: Decimal128Array = column(&table_schema, &table_rows);
+
+        assert_eq!(array.precision(), 10);
+        assert_eq!(array.scale(), 2);
+        assert_eq!(array.value(0), 12345);
+        assert_eq!(array.value(1), -50);
+        assert!(array.is_null(2));
+    }
+
+    #[test]
+    fn numeric_which_doesnt_fit_the_decimal_is_rejected() {
+        let table_schema = items_schema(&[("price", Type::NUMERIC, numeric_modifier(4, 2))]);
+
+        for value in ["123.45", "NaN", "Infinity"] {
+            let table_rows = rows(vec![vec![Cell::Numeric(value.parse().unwrap())]]);
+            let result = ArrowConverter::try_from_rows(&table_schema, &table_rows);
+            assert!(matches!(
+                result,
+                Err(ArrowConversionError::DecimalOutOfRange { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn numeric_without_a_precision_is_converted_to_a_string() {
+        let table_schema = items_schema(&[("amount", Type::NUMERIC, -1)]);
+        let table_rows = rows(vec![vec![Cell::Numeric("1.5".parse().unwrap())]]);
+
+        let array: StringArray = column(&table_schema, &table_rows);
+
+        assert_eq!(array, StringArray::from(vec!["1.5"]));
+    }
+
+    #[test]
+    fn decimals_are_parsed_and_scaled() {
+        assert_eq!(parse_decimal("1.005", 10, 2), Some(101));
+        assert_eq!(parse_decimal("-1.005", 10, 2), Some(-101));
+        assert_eq!(parse_decimal("1.5E+2", 10, 0), Some(150));
+        assert_eq!(parse_decimal("42", 4, 2), Some(4200));
+        assert_eq!(parse_decimal("100", 4, 2), None);
+        assert_eq!(parse_decimal("NaN", 4, 2), None);
+    }
+
+    #[test]
+    fn dates_and_times_are_converted_to_days_and_microseconds() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let time = NaiveTime::from_hms_micro_opt(1, 2, 3, 456_789).unwrap();
+        let table_schema = items_schema(&[
+            ("born", Type::DATE, -1),
+            ("at", Type::TIME, -1),
+            ("created", Type::TIMESTAMP, -1),
+            ("updated", Type::TIMESTAMPTZ, -1),
+        ]);
+        let timestamptz = DateTime::from_timestamp_micros(1_704_153_723_456_789).unwrap();
+        let table_rows = rows(vec![vec![
+            Cell::Date(date),
+            Cell::Time(time),
+            Cell::TimeStamp(date.and_time(time)),
+            Cell::TimeStampTz(timestamptz),
+        ]]);
+
+        let batch = ArrowConverter::try_from_rows(&table_schema, &table_rows).unwrap();
+
+        let dates = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Date32Array>()
+            .unwrap();
+        assert_eq!(dates.value(0), 19724);
+        let times = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Time64MicrosecondArray>()
+            .unwrap();
+        assert_eq!(times.value(0), 3_723_456_789);
+        let timestamps = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(timestamps.value(0), 1_704_157_323_456_789);
+        let timestamptzs = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(timestamptzs.value(0), 1_704_153_723_456_789);
+        assert_eq!(timestamptzs.timezone(), Some("UTC"));
+    }
+
+    #[test]
+    fn arrays_are_converted_to_lists() {
+        let table_schema = items_schema(&[("scores", Type::INT4_ARRAY, -1)]);
+        let table_rows = rows(vec![
+            vec![Cell::Array(ArrayCell::I32(vec![Some(1), None, Some(3)]))],
+            vec![Cell::Null],
+            vec![Cell::Array(ArrayCell::I32(vec![]))],
+        ]);
+
+        let array: ListArray = column(&table_schema, &table_rows);
+
+        assert_eq!(array.len(), 3);
+        let first = array.value(0);
+        let first = first.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(first, &Int32Array::from(vec![Some(1), None, Some(3)]));
+        assert!(array.is_null(1));
+        assert!(array.is_valid(2));
+        assert_eq!(array.value(2).len(), 0);
+    }
+
+    #[test]
+    fn json_is_converted_to_a_string() {
+        let table_schema = items_schema(&[("doc", Type::JSONB, -1)]);
+        let table_rows = rows(vec![vec![Cell::Json(serde_json::json!({"a": [1, 2]}))]]);
+
+        let array: StringArray = column(&table_schema, &table_rows);
+
+        assert_eq!(array, StringArray::from(vec![r#"{"a":[1,2]}"#]));
+    }
+
+    #[test]
+    fn bytea_is_converted_to_binary() {
+        let table_schema = items_schema(&[("blob", Type::BYTEA, -1)]);
+        let table_rows = rows(vec![vec![Cell::Bytes(vec![0xde, 0xad])], vec![Cell::Null]]);
+
+        let array: BinaryArray = column(&table_schema, &table_rows);
+
+        assert_eq!(
+            array,
+            BinaryArray::from(vec![Some([0xde, 0xad].as_slice()), None])
+        );
+    }
+}
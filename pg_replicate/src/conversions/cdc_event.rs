@@ -1,15 +1,23 @@
 use core::str;
-use std::{collections::HashMap, str::Utf8Error};
+use std::{
+    collections::{HashMap, HashSet},
+    str::Utf8Error,
+};
 
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use postgres_replication::protocol::{
-    BeginBody, CommitBody, DeleteBody, InsertBody, LogicalReplicationMessage, RelationBody,
+    self, DeleteBody, InsertBody, LogicalReplicationMessage, RelationBody, ReplicaIdentity,
     ReplicationMessage, TupleData, TypeBody, UpdateBody,
 };
 use thiserror::Error;
+use tokio_postgres::types::{Kind, Type};
+use tracing::{info, warn};
 
 use crate::{
+    clients::postgres::ResolvedType,
     pipeline::batching::BatchBoundary,
-    table::{ColumnSchema, TableId, TableSchema},
+    table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 
 use super::{
@@ -43,25 +51,579 @@ pub enum CdcEventConversionError {
 
     #[error("invalid string value")]
     InvalidStr(#[from] Utf8Error),
+
+    #[error("tuple has {actual} columns but {expected} were expected")]
+    ColumnCountMismatch { expected: usize, actual: usize },
+
+    #[error("invalid relation message: {0}")]
+    InvalidRelation(#[from] std::io::Error),
+
+    #[error("table id {table_id} of table {previous} is now that of table {current}")]
+    RelationIdReused {
+        table_id: TableId,
+        previous: TableName,
+        current: TableName,
+    },
+
+    #[error("table id {0} has replica identity nothing, its updates and deletes can't be applied")]
+    NoReplicaIdentity(TableId),
+
+    #[error("skipped a change of table id {0} which has replica identity nothing")]
+    NoReplicaIdentitySkipped(TableId),
+
+    #[error("invalid wal2json message: {0}")]
+    InvalidWal2Json(#[from] serde_json::Error),
+
+    #[error("invalid {0} in wal2json message")]
+    InvalidWal2JsonField(&'static str),
+
+    #[error("table {0} isn't replicated")]
+    UnknownTable(TableName),
+
+    #[error("invalid pgoutput message: {0}")]
+    InvalidPgoutput(std::io::Error),
+
+    #[error("invalid tuple for table id {table_id}: {source}")]
+    InvalidTuple {
+        table_id: TableId,
+        tuple: Vec<Option<Bytes>>,
+        #[source]
+        source: Box<CdcEventConversionError>,
+    },
+}
+
+/// The start of a source transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeginBody {
+    final_lsn: u64,
+    timestamp: i64,
+    xid: u32,
+}
+
+impl BeginBody {
+    /// `timestamp` is the transaction's commit timestamp, in microseconds
+    /// since the Postgres epoch (2000-01-01)
+    pub fn new(final_lsn: u64, timestamp: i64, xid: u32) -> BeginBody {
+        BeginBody {
+            final_lsn,
+            timestamp,
+            xid,
+        }
+    }
+
+    /// Lsn of the transaction's commit
+    pub fn final_lsn(&self) -> u64 {
+        self.final_lsn
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn xid(&self) -> u32 {
+        self.xid
+    }
+}
+
+impl From<protocol::BeginBody> for BeginBody {
+    fn from(begin_body: protocol::BeginBody) -> Self {
+        BeginBody::new(
+            begin_body.final_lsn(),
+            begin_body.timestamp(),
+            begin_body.xid(),
+        )
+    }
+}
+
+/// The end of a source transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitBody {
+    commit_lsn: u64,
+    end_lsn: u64,
+    timestamp: i64,
+}
+
+impl CommitBody {
+    /// `timestamp` is the transaction's commit timestamp, in microseconds
+    /// since the Postgres epoch (2000-01-01)
+    pub fn new(commit_lsn: u64, end_lsn: u64, timestamp: i64) -> CommitBody {
+        CommitBody {
+            commit_lsn,
+            end_lsn,
+            timestamp,
+        }
+    }
+
+    /// Lsn of the commit, the [`BeginBody::final_lsn`] of its transaction
+    pub fn commit_lsn(&self) -> u64 {
+        self.commit_lsn
+    }
+
+    /// Lsn just past the commit
+    pub fn end_lsn(&self) -> u64 {
+        self.end_lsn
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+}
+
+impl From<protocol::CommitBody> for CommitBody {
+    fn from(commit_body: protocol::CommitBody) -> Self {
+        CommitBody::new(
+            commit_body.commit_lsn(),
+            commit_body.end_lsn(),
+            commit_body.timestamp(),
+        )
+    }
+}
+
+/// Metadata of the transaction a change was made in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionInfo {
+    pub xid: u32,
+    /// `None` unless the source has `track_commit_timestamp` on
+    pub commit_timestamp: Option<DateTime<Utc>>,
+}
+
+impl TransactionInfo {
+    /// `timestamp` is the commit timestamp of a begin message, in microseconds
+    /// since the Postgres epoch (2000-01-01). It's only kept if
+    /// `tracks_commit_timestamps`.
+    pub fn new(xid: u32, timestamp: i64, tracks_commit_timestamps: bool) -> Self {
+        const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+        let commit_timestamp = tracks_commit_timestamps.then(|| {
+            DateTime::from_timestamp_micros(timestamp + POSTGRES_EPOCH_MICROS)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC)
+        });
+        TransactionInfo {
+            xid,
+            commit_timestamp,
+        }
+    }
+}
+
+/// What the cdc stream does with a tuple whose number of columns differs from
+/// its table's schema. This happens when the schema was fetched after a column
+/// was added or dropped but the changes streamed were made before that.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnCountMismatchPolicy {
+    /// Fail the conversion with [`CdcEventConversionError::ColumnCountMismatch`]
+    #[default]
+    Error,
+    /// Fill the trailing columns missing from the tuple with NULL and drop the
+    /// tuple's columns past the end of the schema
+    PadWithNull,
+    /// Match the tuple's columns to those of the schema by name, using the
+    /// columns of the table's last relation message. Postgres sends one before
+    /// the first change to a table and after every change of its schema.
+    /// Columns of the schema which the tuple doesn't have are NULL.
+    MatchRelation,
+}
+
+/// Maps the columns of tuples to the columns of their table's schema
+#[derive(Debug, Clone, Default)]
+pub struct TupleColumnMapper {
+    policy: ColumnCountMismatchPolicy,
+    relation_columns: HashMap<TableId, Vec<String>>,
+}
+
+impl TupleColumnMapper {
+    pub fn new(policy: ColumnCountMismatchPolicy) -> TupleColumnMapper {
+        TupleColumnMapper {
+            policy,
+            relation_columns: HashMap::new(),
+        }
+    }
+
+    /// Records the columns of the tuples which follow a relation message
+    pub fn relation_received(
+        &mut self,
+        table_id: TableId,
+        relation_body: &RelationBody,
+    ) -> Result<(), CdcEventConversionError> {
+        let column_names = relation_body
+            .columns()
+            .iter()
+            .map(|column| column.name().map(|name| name.to_string()))
+            .collect::<Result<Vec<String>, _>>()?;
+        self.relation_columns.insert(table_id, column_names);
+        Ok(())
+    }
+
+    /// Returns, for every column of `column_schemas`, the position of the
+    /// tuple column holding its value or `None` if it should be NULL
+    fn tuple_positions(
+        &self,
+        table_id: TableId,
+        column_schemas: &[ColumnSchema],
+        tuple_len: usize,
+    ) -> Result<Vec<Option<usize>>, CdcEventConversionError> {
+        let relation_columns = self
+            .relation_columns
+            .get(&table_id)
+            .filter(|relation_columns| relation_columns.len() == tuple_len);
+        let positions = match (self.policy, relation_columns) {
+            _ if column_schemas.len() == tuple_len => (0..tuple_len).map(Some).collect(),
+            (ColumnCountMismatchPolicy::PadWithNull, _) => (0..column_schemas.len())
+                .map(|i| (i < tuple_len).then_some(i))
+                .collect(),
+            (ColumnCountMismatchPolicy::MatchRelation, Some(relation_columns)) => column_schemas
+                .iter()
+                .map(|column_schema| {
+                    relation_columns
+                        .iter()
+                        .position(|name| *name == column_schema.name)
+                })
+                .collect(),
+            _ => {
+                return Err(CdcEventConversionError::ColumnCountMismatch {
+                    expected: column_schemas.len(),
+                    actual: tuple_len,
+                })
+            }
+        };
+        Ok(positions)
+    }
+}
+
+/// What the cdc stream does with updates and deletes of a table with replica
+/// identity nothing. Postgres sends them without the old values of the key
+/// columns, so the changed row can't be identified in the sink.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoReplicaIdentityPolicy {
+    /// Fail the conversion with [`CdcEventConversionError::NoReplicaIdentity`]
+    #[default]
+    Error,
+    /// Drop the updates and deletes
+    Skip,
+    /// Convert updates into inserts of the new row and drop deletes
+    TreatUpdatesAsInserts,
+}
+
+/// Tracks the tables with replica identity nothing from their relation
+/// messages and applies a [`NoReplicaIdentityPolicy`] to their changes
+#[derive(Debug, Clone, Default)]
+pub struct NoReplicaIdentityTables {
+    policy: NoReplicaIdentityPolicy,
+    table_ids: HashSet<TableId>,
+}
+
+impl NoReplicaIdentityTables {
+    pub fn new(policy: NoReplicaIdentityPolicy) -> NoReplicaIdentityTables {
+        NoReplicaIdentityTables {
+            policy,
+            table_ids: HashSet::new(),
+        }
+    }
+
+    /// Records whether the table of a relation message has replica identity
+    /// nothing. Warns the first time it does.
+    pub fn relation_received(&mut self, table_id: TableId, relation_body: &RelationBody) {
+        if !matches!(relation_body.replica_identity(), ReplicaIdentity::Nothing) {
+            self.table_ids.remove(&table_id);
+            return;
+        }
+        if self.table_ids.insert(table_id) {
+            let table_name = match (relation_body.namespace(), relation_body.name()) {
+                (Ok(schema), Ok(name)) => format!("{schema}.{name}"),
+                _ => format!("with id {table_id}"),
+            };
+            warn!(
+                "table {table_name} has replica identity nothing, \
+                its updates and deletes are handled by the {:?} policy",
+                self.policy
+            );
+        }
+    }
+
+    fn contains(&self, table_id: TableId) -> bool {
+        self.table_ids.contains(&table_id)
+    }
+
+    /// Applies the policy to an update of a table with replica identity nothing
+    fn update(
+        &self,
+        table_id: TableId,
+        row: TableRow,
+        transaction_info: Option<TransactionInfo>,
+    ) -> Result<CdcEvent, CdcEventConversionError> {
+        match self.policy {
+            NoReplicaIdentityPolicy::Error => {
+                Err(CdcEventConversionError::NoReplicaIdentity(table_id))
+            }
+            NoReplicaIdentityPolicy::Skip => {
+                Err(CdcEventConversionError::NoReplicaIdentitySkipped(table_id))
+            }
+            NoReplicaIdentityPolicy::TreatUpdatesAsInserts => {
+                Ok(CdcEvent::Insert((table_id, row, transaction_info)))
+            }
+        }
+    }
+
+    /// Applies the policy to a delete of a table with replica identity nothing
+    fn delete(&self, table_id: TableId) -> CdcEventConversionError {
+        match self.policy {
+            NoReplicaIdentityPolicy::Error => CdcEventConversionError::NoReplicaIdentity(table_id),
+            NoReplicaIdentityPolicy::Skip | NoReplicaIdentityPolicy::TreatUpdatesAsInserts => {
+                CdcEventConversionError::NoReplicaIdentitySkipped(table_id)
+            }
+        }
+    }
+}
+
+/// What the cdc stream does with a relation message whose table id is that
+/// of a table in its schemas but whose schema or name differs from the
+/// table's. Table ids are oids, which Postgres reuses, so a table created
+/// after another was dropped can have the dropped table's id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReusedRelationIdPolicy {
+    /// Fail the conversion with [`CdcEventConversionError::RelationIdReused`]
+    #[default]
+    Error,
+    /// Replace the table's schema with one built from the relation message,
+    /// so that the changes which follow are converted with the new table's
+    /// columns. Columns of types not built into Postgres get their types from
+    /// the stream's [`RelationTypes`], or a type without a name whose values
+    /// are converted like those of unknown types if it has none.
+    ReplaceSchema,
+}
+
+/// Detects from their relation messages tables whose id was reused by another
+/// table and applies a [`ReusedRelationIdPolicy`] to them
+#[derive(Debug, Clone, Default)]
+pub struct ReusedRelationIds {
+    policy: ReusedRelationIdPolicy,
+}
+
+impl ReusedRelationIds {
+    pub fn new(policy: ReusedRelationIdPolicy) -> ReusedRelationIds {
+        ReusedRelationIds { policy }
+    }
+
+    /// Checks that the table of a relation message is the one `table_schemas`
+    /// has under its id and applies the policy if it isn't. Tables which
+    /// aren't in `table_schemas` are ignored.
+    pub fn relation_received(
+        &self,
+        table_id: TableId,
+        relation_body: &RelationBody,
+        table_schemas: &mut HashMap<TableId, TableSchema>,
+    ) -> Result<(), CdcEventConversionError> {
+        let Some(table_schema) = table_schemas.get_mut(&table_id) else {
+            return Ok(());
+        };
+        let table_name = TableName {
+            schema: relation_body.namespace()?.to_string(),
+            name: relation_body.name()?.to_string(),
+        };
+        if table_name == table_schema.table_name {
+            return Ok(());
+        }
+
+        match self.policy {
+            ReusedRelationIdPolicy::Error => Err(CdcEventConversionError::RelationIdReused {
+                table_id,
+                previous: table_schema.table_name.clone(),
+                current: table_name,
+            }),
+            ReusedRelationIdPolicy::ReplaceSchema => {
+                warn!(
+                    "table id {table_id} of table {} is now that of table {table_name}, \
+                    replacing its schema",
+                    table_schema.table_name
+                );
+                table_schema.column_schemas = Self::column_schemas(relation_body)?;
+                table_schema.table_name = table_name;
+                Ok(())
+            }
+        }
+    }
+
+    fn column_schemas(
+        relation_body: &RelationBody,
+    ) -> Result<Vec<ColumnSchema>, CdcEventConversionError> {
+        relation_body
+            .columns()
+            .iter()
+            .map(|column| {
+                let type_oid = column.type_id() as u32;
+                let typ = Type::from_oid(type_oid).unwrap_or_else(|| {
+                    Type::new(String::new(), type_oid, Kind::Simple, String::new())
+                });
+                Ok(ColumnSchema {
+                    name: column.name()?.to_string(),
+                    typ,
+                    modifier: column.type_modifier(),
+                    // relation messages don't say whether columns are nullable
+                    nullable: true,
+                    primary: column.flags() == 1,
+                    identity: false,
+                    domain: None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Types of columns which aren't built into Postgres, which are named by the
+/// type messages Postgres sends before the first relation message using them
+/// and resolved from the catalog by the cdc stream. Relation messages give
+/// the columns of the table schemas these types, so that a column whose type
+/// was created or changed after the schemas were fetched is converted like
+/// one of a type known from the start.
+#[derive(Debug, Clone, Default)]
+pub struct RelationTypes {
+    /// Types of type messages, which only have a name
+    named: HashMap<u32, Type>,
+    resolved: HashMap<u32, ResolvedType>,
+}
+
+impl RelationTypes {
+    /// Records the name of the type of a type message
+    pub fn type_received(&mut self, type_body: &TypeBody) -> Result<(), CdcEventConversionError> {
+        let typ = Type::new(
+            type_body.name()?.to_string(),
+            type_body.id(),
+            Kind::Simple,
+            type_body.namespace()?.to_string(),
+        );
+        self.named.insert(type_body.id(), typ);
+        Ok(())
+    }
+
+    /// Records a type resolved from the catalog
+    pub fn type_resolved(&mut self, type_oid: u32, resolved: ResolvedType) {
+        self.resolved.insert(type_oid, resolved);
+    }
+
+    /// Returns the oids of the types of the relation message's columns which
+    /// are neither built into Postgres nor resolved yet
+    pub fn unresolved_types(&self, relation_body: &RelationBody) -> Vec<u32> {
+        let mut type_oids = vec![];
+        for column in relation_body.columns() {
+            let type_oid = column.type_id() as u32;
+            if Type::from_oid(type_oid).is_none()
+                && !self.resolved.contains_key(&type_oid)
+                && !type_oids.contains(&type_oid)
+            {
+                type_oids.push(type_oid);
+            }
+        }
+        type_oids
+    }
+
+    /// Gives the columns of the table of a relation message whose type isn't
+    /// built into Postgres the type the message has for them, if it is known.
+    /// Tables which aren't in `table_schemas` are ignored.
+    pub fn relation_received(
+        &self,
+        table_id: TableId,
+        relation_body: &RelationBody,
+        table_schemas: &mut HashMap<TableId, TableSchema>,
+    ) -> Result<(), CdcEventConversionError> {
+        let Some(table_schema) = table_schemas.get_mut(&table_id) else {
+            return Ok(());
+        };
+        for column in relation_body.columns() {
+            let type_oid = column.type_id() as u32;
+            if Type::from_oid(type_oid).is_some() {
+                continue;
+            }
+            let (typ, domain) = match (self.resolved.get(&type_oid), self.named.get(&type_oid)) {
+                (Some(resolved), _) => (&resolved.typ, resolved.domain.as_ref()),
+                (None, Some(typ)) => (typ, None),
+                (None, None) => continue,
+            };
+            let name = column.name()?;
+            let Some(column_schema) = table_schema
+                .column_schemas
+                .iter_mut()
+                .find(|column_schema| column_schema.name == name)
+            else {
+                continue;
+            };
+            if column_schema.typ != *typ || column_schema.domain.as_ref() != domain {
+                info!(
+                    "column {name} of table {} now has type {}",
+                    table_schema.table_name,
+                    domain.map_or(typ.name(), String::as_str)
+                );
+                column_schema.typ = typ.clone();
+                column_schema.domain = domain.cloned();
+            }
+        }
+        Ok(())
+    }
 }
 
 pub struct CdcEventConverter;
 
 impl CdcEventConverter {
-    fn try_from_tuple_data_slice(
+    /// Returns the positions of the replica identity columns. These are the
+    /// primary key columns, or all columns for a table without a primary key
+    /// (which can only replicate updates and deletes with replica identity full).
+    pub(crate) fn key_columns(column_schemas: &[ColumnSchema]) -> Vec<usize> {
+        let key_columns: Vec<usize> = column_schemas
+            .iter()
+            .enumerate()
+            .filter(|(_, column_schema)| column_schema.primary)
+            .map(|(i, _)| i)
+            .collect();
+        if key_columns.is_empty() {
+            (0..column_schemas.len()).collect()
+        } else {
+            key_columns
+        }
+    }
+
+    /// Converts the columns of a tuple of an insert, update or delete of the
+    /// table `table_id` into a row
+    pub fn try_from_tuple_data_slice(
+        table_id: TableId,
         column_schemas: &[ColumnSchema],
         tuple_data: &[TupleData],
+        tuple_columns: &TupleColumnMapper,
+    ) -> Result<TableRow, CdcEventConversionError> {
+        tuple_columns
+            .tuple_positions(table_id, column_schemas, tuple_data.len())
+            .and_then(|positions| {
+                Self::convert_tuple_data_slice(column_schemas, tuple_data, &positions)
+            })
+            .map_err(|e| {
+                let tuple = tuple_data
+                    .iter()
+                    .map(|data| match data {
+                        TupleData::Null | TupleData::UnchangedToast => None,
+                        TupleData::Binary(bytes) | TupleData::Text(bytes) => Some(bytes.clone()),
+                    })
+                    .collect();
+                CdcEventConversionError::InvalidTuple {
+                    table_id,
+                    tuple,
+                    source: Box::new(e),
+                }
+            })
+    }
+
+    fn convert_tuple_data_slice(
+        column_schemas: &[ColumnSchema],
+        tuple_data: &[TupleData],
+        positions: &[Option<usize>],
     ) -> Result<TableRow, CdcEventConversionError> {
         let mut values = Vec::with_capacity(column_schemas.len());
 
-        for (i, column_schema) in column_schemas.iter().enumerate() {
-            let cell = match &tuple_data[i] {
-                TupleData::Null => Cell::Null,
-                TupleData::UnchangedToast => TextFormatConverter::default_value(&column_schema.typ),
-                TupleData::Binary(_) => {
+        for (column_schema, position) in column_schemas.iter().zip(positions) {
+            let cell = match position.map(|i| &tuple_data[i]) {
+                None | Some(TupleData::Null) => Cell::Null,
+                Some(TupleData::UnchangedToast) => {
+                    TextFormatConverter::default_value(&column_schema.typ)
+                }
+                Some(TupleData::Binary(_)) => {
                     return Err(CdcEventConversionError::BinaryFormatNotSupported)
                 }
-                TupleData::Text(bytes) => {
+                Some(TupleData::Text(bytes)) => {
                     let str = str::from_utf8(&bytes[..])?;
                     TextFormatConverter::try_from_str(&column_schema.typ, str)?
                 }
@@ -76,11 +638,17 @@ impl CdcEventConverter {
         table_id: TableId,
         column_schemas: &[ColumnSchema],
         insert_body: InsertBody,
+        transaction_info: Option<TransactionInfo>,
+        tuple_columns: &TupleColumnMapper,
     ) -> Result<CdcEvent, CdcEventConversionError> {
-        let row =
-            Self::try_from_tuple_data_slice(column_schemas, insert_body.tuple().tuple_data())?;
+        let row = Self::try_from_tuple_data_slice(
+            table_id,
+            column_schemas,
+            insert_body.tuple().tuple_data(),
+            tuple_columns,
+        )?;
 
-        Ok(CdcEvent::Insert((table_id, row)))
+        Ok(CdcEvent::Insert((table_id, row, transaction_info)))
     }
 
     //TODO: handle when identity columns are changed
@@ -88,23 +656,56 @@ impl CdcEventConverter {
         table_id: TableId,
         column_schemas: &[ColumnSchema],
         update_body: UpdateBody,
+        transaction_info: Option<TransactionInfo>,
+        tuple_columns: &TupleColumnMapper,
+        no_replica_identity_tables: &NoReplicaIdentityTables,
     ) -> Result<CdcEvent, CdcEventConversionError> {
+        if no_replica_identity_tables.contains(table_id) {
+            let row = Self::try_from_tuple_data_slice(
+                table_id,
+                column_schemas,
+                update_body.new_tuple().tuple_data(),
+                tuple_columns,
+            )?;
+            return no_replica_identity_tables.update(table_id, row, transaction_info);
+        }
+
         let key_row = update_body
             .key_tuple()
-            .map(|tuple| Self::try_from_tuple_data_slice(column_schemas, tuple.tuple_data()))
+            .map(|tuple| {
+                Self::try_from_tuple_data_slice(
+                    table_id,
+                    column_schemas,
+                    tuple.tuple_data(),
+                    tuple_columns,
+                )
+            })
             .transpose()?;
         let old_row = update_body
             .old_tuple()
-            .map(|tuple| Self::try_from_tuple_data_slice(column_schemas, tuple.tuple_data()))
+            .map(|tuple| {
+                Self::try_from_tuple_data_slice(
+                    table_id,
+                    column_schemas,
+                    tuple.tuple_data(),
+                    tuple_columns,
+                )
+            })
             .transpose()?;
-        let row =
-            Self::try_from_tuple_data_slice(column_schemas, update_body.new_tuple().tuple_data())?;
+        let row = Self::try_from_tuple_data_slice(
+            table_id,
+            column_schemas,
+            update_body.new_tuple().tuple_data(),
+            tuple_columns,
+        )?;
 
         Ok(CdcEvent::Update {
             table_id,
             old_row,
             key_row,
             row,
+            key_columns: Self::key_columns(column_schemas),
+            transaction_info,
         })
     }
 
@@ -112,95 +713,196 @@ impl CdcEventConverter {
         table_id: TableId,
         column_schemas: &[ColumnSchema],
         delete_body: DeleteBody,
+        transaction_info: Option<TransactionInfo>,
+        tuple_columns: &TupleColumnMapper,
+        no_replica_identity_tables: &NoReplicaIdentityTables,
     ) -> Result<CdcEvent, CdcEventConversionError> {
+        if no_replica_identity_tables.contains(table_id) {
+            return Err(no_replica_identity_tables.delete(table_id));
+        }
+
         let tuple = delete_body
             .key_tuple()
             .or(delete_body.old_tuple())
             .ok_or(CdcEventConversionError::MissingTupleInDeleteBody)?;
 
-        let row = Self::try_from_tuple_data_slice(column_schemas, tuple.tuple_data())?;
+        let row = Self::try_from_tuple_data_slice(
+            table_id,
+            column_schemas,
+            tuple.tuple_data(),
+            tuple_columns,
+        )?;
 
-        Ok(CdcEvent::Delete((table_id, row)))
+        Ok(CdcEvent::Delete {
+            table_id,
+            row,
+            key_columns: Self::key_columns(column_schemas),
+            transaction_info,
+        })
+    }
+
+    pub(crate) fn root_table_id(
+        table_id: TableId,
+        partition_roots: &HashMap<TableId, TableId>,
+    ) -> TableId {
+        partition_roots.get(&table_id).copied().unwrap_or(table_id)
     }
 
+    /// Converts a replication message into a [`CdcEvent`]. `transaction_info`
+    /// is attached to inserts, updates and deletes and should be that of the
+    /// last seen [`CdcEvent::Begin`]. Its commit timestamp is also attached to
+    /// the transaction's [`CdcEvent::Commit`]. Changes to a table in `partition_roots`
+    /// are converted as changes to the root table it maps to. The columns of
+    /// tuples are mapped to those of their table's schema by `tuple_columns`.
+    /// Updates and deletes of tables in `no_replica_identity_tables` are
+    /// converted according to its policy.
     pub fn try_from(
         value: ReplicationMessage<LogicalReplicationMessage>,
         table_schemas: &HashMap<TableId, TableSchema>,
+        partition_roots: &HashMap<TableId, TableId>,
+        transaction_info: Option<TransactionInfo>,
+        tuple_columns: &TupleColumnMapper,
+        no_replica_identity_tables: &NoReplicaIdentityTables,
     ) -> Result<CdcEvent, CdcEventConversionError> {
         match value {
-            ReplicationMessage::XLogData(xlog_data) => match xlog_data.into_data() {
-                LogicalReplicationMessage::Begin(begin_body) => Ok(CdcEvent::Begin(begin_body)),
-                LogicalReplicationMessage::Commit(commit_body) => Ok(CdcEvent::Commit(commit_body)),
-                LogicalReplicationMessage::Origin(_) => {
-                    Err(CdcEventConversionError::MessageNotSupported)
-                }
-                LogicalReplicationMessage::Relation(relation_body) => {
-                    Ok(CdcEvent::Relation(relation_body))
-                }
-                LogicalReplicationMessage::Type(type_body) => Ok(CdcEvent::Type(type_body)),
-                LogicalReplicationMessage::Insert(insert_body) => {
-                    let table_id = insert_body.rel_id();
-                    let column_schemas = &table_schemas
-                        .get(&table_id)
-                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?
-                        .column_schemas;
-                    Ok(Self::try_from_insert_body(
-                        table_id,
-                        column_schemas,
-                        insert_body,
-                    )?)
-                }
-                LogicalReplicationMessage::Update(update_body) => {
-                    let table_id = update_body.rel_id();
-                    let column_schemas = &table_schemas
-                        .get(&table_id)
-                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?
-                        .column_schemas;
-                    Ok(Self::try_from_update_body(
-                        table_id,
-                        column_schemas,
-                        update_body,
-                    )?)
-                }
-                LogicalReplicationMessage::Delete(delete_body) => {
-                    let table_id = delete_body.rel_id();
-                    let column_schemas = &table_schemas
-                        .get(&table_id)
-                        .ok_or(CdcEventConversionError::MissingSchema(table_id))?
-                        .column_schemas;
-                    Ok(Self::try_from_delete_body(
-                        table_id,
-                        column_schemas,
-                        delete_body,
-                    )?)
-                }
-                LogicalReplicationMessage::Truncate(_) => {
-                    Err(CdcEventConversionError::MessageNotSupported)
-                }
-                _ => Err(CdcEventConversionError::UnknownReplicationMessage),
-            },
+            ReplicationMessage::XLogData(xlog_data) => Self::try_from_message(
+                xlog_data.into_data(),
+                table_schemas,
+                partition_roots,
+                transaction_info,
+                tuple_columns,
+                no_replica_identity_tables,
+            ),
             ReplicationMessage::PrimaryKeepAlive(keep_alive) => Ok(CdcEvent::KeepAliveRequested {
                 reply: keep_alive.reply() == 1,
             }),
             _ => Err(CdcEventConversionError::UnknownReplicationMessage),
         }
     }
+
+    /// Converts a logical replication message into a [`CdcEvent`] like
+    /// [`CdcEventConverter::try_from`] converts the messages of xlog data
+    pub fn try_from_message(
+        message: LogicalReplicationMessage,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        partition_roots: &HashMap<TableId, TableId>,
+        transaction_info: Option<TransactionInfo>,
+        tuple_columns: &TupleColumnMapper,
+        no_replica_identity_tables: &NoReplicaIdentityTables,
+    ) -> Result<CdcEvent, CdcEventConversionError> {
+        match message {
+            LogicalReplicationMessage::Begin(begin_body) => Ok(CdcEvent::Begin(begin_body.into())),
+            LogicalReplicationMessage::Commit(commit_body) => {
+                let commit_timestamp = transaction_info.and_then(|info| info.commit_timestamp);
+                Ok(CdcEvent::Commit(commit_body.into(), commit_timestamp))
+            }
+            LogicalReplicationMessage::Origin(_) => {
+                Err(CdcEventConversionError::MessageNotSupported)
+            }
+            LogicalReplicationMessage::Relation(relation_body) => {
+                Ok(CdcEvent::Relation(relation_body))
+            }
+            LogicalReplicationMessage::Type(type_body) => Ok(CdcEvent::Type(type_body)),
+            LogicalReplicationMessage::Insert(insert_body) => {
+                let table_id = Self::root_table_id(insert_body.rel_id(), partition_roots);
+                let column_schemas = &table_schemas
+                    .get(&table_id)
+                    .ok_or(CdcEventConversionError::MissingSchema(table_id))?
+                    .column_schemas;
+                Ok(Self::try_from_insert_body(
+                    table_id,
+                    column_schemas,
+                    insert_body,
+                    transaction_info,
+                    tuple_columns,
+                )?)
+            }
+            LogicalReplicationMessage::Update(update_body) => {
+                let table_id = Self::root_table_id(update_body.rel_id(), partition_roots);
+                let column_schemas = &table_schemas
+                    .get(&table_id)
+                    .ok_or(CdcEventConversionError::MissingSchema(table_id))?
+                    .column_schemas;
+                Ok(Self::try_from_update_body(
+                    table_id,
+                    column_schemas,
+                    update_body,
+                    transaction_info,
+                    tuple_columns,
+                    no_replica_identity_tables,
+                )?)
+            }
+            LogicalReplicationMessage::Delete(delete_body) => {
+                let table_id = Self::root_table_id(delete_body.rel_id(), partition_roots);
+                let column_schemas = &table_schemas
+                    .get(&table_id)
+                    .ok_or(CdcEventConversionError::MissingSchema(table_id))?
+                    .column_schemas;
+                Ok(Self::try_from_delete_body(
+                    table_id,
+                    column_schemas,
+                    delete_body,
+                    transaction_info,
+                    tuple_columns,
+                    no_replica_identity_tables,
+                )?)
+            }
+            LogicalReplicationMessage::Truncate(_) => {
+                Err(CdcEventConversionError::MessageNotSupported)
+            }
+            _ => Err(CdcEventConversionError::UnknownReplicationMessage),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum CdcEvent {
     Begin(BeginBody),
-    Commit(CommitBody),
-    Insert((TableId, TableRow)),
+    /// The commit timestamp is `None` unless the source has
+    /// `track_commit_timestamp` on
+    Commit(CommitBody, Option<DateTime<Utc>>),
+    Insert((TableId, TableRow, Option<TransactionInfo>)),
+    /// `key_row` is only sent when the replica identity columns changed and
+    /// then holds their old values. Otherwise the key values are in `row`.
     Update {
         table_id: TableId,
         old_row: Option<TableRow>,
         key_row: Option<TableRow>,
         row: TableRow,
+        /// Positions of the replica identity columns, in column order
+        key_columns: Vec<usize>,
+        transaction_info: Option<TransactionInfo>,
+    },
+    /// `row` holds the replica identity columns of the deleted row, or all
+    /// of its columns with replica identity full
+    Delete {
+        table_id: TableId,
+        row: TableRow,
+        /// Positions of the replica identity columns, in column order
+        key_columns: Vec<usize>,
+        transaction_info: Option<TransactionInfo>,
     },
-    Delete((TableId, TableRow)),
     Relation(RelationBody),
     Type(TypeBody),
+    /// The state of the sequence `schema`.`name` after it was advanced or
+    /// set, which a sink mirroring the source applies so that `nextval()`
+    /// on the mirror doesn't return values already used on the source
+    Sequence {
+        schema: String,
+        name: String,
+        last_value: i64,
+        log_cnt: i64,
+        is_called: bool,
+    },
+    /// A message an application wrote to the WAL with
+    /// `pg_logical_emit_message`, e.g. as a marker to coordinate on. A
+    /// transactional message is sent within its transaction when it commits,
+    /// a non-transactional one as soon as it was written.
+    LogicalMessage {
+        transactional: bool,
+        prefix: String,
+        content: Vec<u8>,
+    },
     KeepAliveRequested {
         reply: bool,
     },
@@ -210,7 +912,471 @@ impl BatchBoundary for CdcEvent {
     fn is_last_in_batch(&self) -> bool {
         matches!(
             self,
-            CdcEvent::Commit(_) | CdcEvent::KeepAliveRequested { reply: _ }
+            CdcEvent::Commit(..) | CdcEvent::KeepAliveRequested { reply: _ }
+        )
+    }
+
+    /// Postgres sends keepalives when it has no changes to stream
+    fn is_idle(&self) -> bool {
+        matches!(self, CdcEvent::KeepAliveRequested { reply: _ })
+    }
+
+    fn table_id(&self) -> Option<TableId> {
+        match self {
+            CdcEvent::Insert((table_id, _, _))
+            | CdcEvent::Update { table_id, .. }
+            | CdcEvent::Delete { table_id, .. } => Some(*table_id),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use postgres_replication::protocol::{LogicalReplicationMessage, TupleData};
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        conversions::{table_row::TableRow, Cell},
+        table::{ColumnSchema, TableId, TableName, TableSchema},
+    };
+
+    use super::{
+        CdcEvent, CdcEventConversionError, CdcEventConverter, ColumnCountMismatchPolicy,
+        NoReplicaIdentityPolicy, NoReplicaIdentityTables, ReusedRelationIdPolicy,
+        ReusedRelationIds, TransactionInfo, TupleColumnMapper,
+    };
+
+    const TABLE_ID: u32 = 1;
+
+    fn column_schema(name: &str, typ: Type) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: true,
+            primary: false,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    /// The schema of a table fetched after its `email` column was added
+    fn column_schemas() -> Vec<ColumnSchema> {
+        vec![
+            column_schema("id", Type::INT4),
+            column_schema("name", Type::TEXT),
+            column_schema("email", Type::TEXT),
+        ]
+    }
+
+    /// A tuple of a row inserted before the `email` column was added
+    fn tuple_data() -> Vec<TupleData> {
+        vec![
+            TupleData::Text(Bytes::from_static(b"1")),
+            TupleData::Text(Bytes::from_static(b"alice")),
+        ]
+    }
+
+    fn convert(tuple_columns: &TupleColumnMapper) -> Result<TableRow, CdcEventConversionError> {
+        CdcEventConverter::try_from_tuple_data_slice(
+            TABLE_ID,
+            &column_schemas(),
+            &tuple_data(),
+            tuple_columns,
+        )
+    }
+
+    #[test]
+    fn tuple_missing_an_added_column_is_rejected_by_default() {
+        let tuple_columns = TupleColumnMapper::default();
+
+        let Err(CdcEventConversionError::InvalidTuple { source, .. }) = convert(&tuple_columns)
+        else {
+            panic!("expected an invalid tuple error");
+        };
+        assert!(matches!(
+            *source,
+            CdcEventConversionError::ColumnCountMismatch {
+                expected: 3,
+                actual: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn tuple_missing_an_added_column_is_padded_with_null() {
+        let tuple_columns = TupleColumnMapper::new(ColumnCountMismatchPolicy::PadWithNull);
+
+        let row = convert(&tuple_columns).expect("failed to convert tuple");
+
+        let expected = vec![Cell::I32(1), Cell::String("alice".to_string()), Cell::Null];
+        assert_eq!(row.values, expected);
+    }
+
+    #[test]
+    fn tuple_columns_are_matched_to_the_columns_of_the_last_relation() {
+        let mut tuple_columns = TupleColumnMapper::new(ColumnCountMismatchPolicy::MatchRelation);
+        // a relation message sent before the column was added, listing the
+        // columns in a different order than the schema
+        tuple_columns
+            .relation_columns
+            .insert(TABLE_ID, vec!["name".to_string(), "id".to_string()]);
+        let tuple_data = vec![
+            TupleData::Text(Bytes::from_static(b"alice")),
+            TupleData::Text(Bytes::from_static(b"1")),
+        ];
+
+        let row = CdcEventConverter::try_from_tuple_data_slice(
+            TABLE_ID,
+            &column_schemas(),
+            &tuple_data,
+            &tuple_columns,
+        )
+        .expect("failed to convert tuple");
+
+        let expected = vec![Cell::I32(1), Cell::String("alice".to_string()), Cell::Null];
+        assert_eq!(row.values, expected);
+    }
+
+    #[test]
+    fn tuple_without_a_matching_relation_is_rejected() {
+        let tuple_columns = TupleColumnMapper::new(ColumnCountMismatchPolicy::MatchRelation);
+
+        assert!(convert(&tuple_columns).is_err());
+    }
+
+    /// 2024-01-15 10:30:00 UTC in microseconds since the Postgres epoch
+    const COMMIT_TIMESTAMP: i64 = 758_629_800_000_000;
+
+    #[test]
+    fn commit_timestamp_is_kept_if_the_source_tracks_commit_timestamps() {
+        let transaction_info = TransactionInfo::new(42, COMMIT_TIMESTAMP, true);
+
+        let expected = "2024-01-15T10:30:00Z".parse().unwrap();
+        assert_eq!(transaction_info.xid, 42);
+        assert_eq!(transaction_info.commit_timestamp, Some(expected));
+    }
+
+    #[test]
+    fn commit_timestamp_is_none_if_the_source_does_not_track_commit_timestamps() {
+        let transaction_info = TransactionInfo::new(42, COMMIT_TIMESTAMP, false);
+
+        assert_eq!(transaction_info.xid, 42);
+        assert_eq!(transaction_info.commit_timestamp, None);
+    }
+
+    /// Tables with replica identity nothing as if table `TABLE_ID`'s relation
+    /// message said it has one
+    fn no_replica_identity_tables(policy: NoReplicaIdentityPolicy) -> NoReplicaIdentityTables {
+        let mut no_replica_identity_tables = NoReplicaIdentityTables::new(policy);
+        no_replica_identity_tables.table_ids.insert(TABLE_ID);
+        no_replica_identity_tables
+    }
+
+    fn updated_row() -> TableRow {
+        TableRow::from(vec![Cell::I32(1), Cell::String("alice".to_string())])
+    }
+
+    #[test]
+    fn changes_without_replica_identity_are_rejected_by_default() {
+        let tables = no_replica_identity_tables(NoReplicaIdentityPolicy::default());
+
+        let update = tables.update(TABLE_ID, updated_row(), None);
+        let delete = tables.delete(TABLE_ID);
+
+        assert!(matches!(
+            update,
+            Err(CdcEventConversionError::NoReplicaIdentity(TABLE_ID))
+        ));
+        assert!(matches!(
+            delete,
+            CdcEventConversionError::NoReplicaIdentity(TABLE_ID)
+        ));
+    }
+
+    #[test]
+    fn changes_without_replica_identity_are_skipped() {
+        let tables = no_replica_identity_tables(NoReplicaIdentityPolicy::Skip);
+
+        let update = tables.update(TABLE_ID, updated_row(), None);
+        let delete = tables.delete(TABLE_ID);
+
+        assert!(matches!(
+            update,
+            Err(CdcEventConversionError::NoReplicaIdentitySkipped(TABLE_ID))
+        ));
+        assert!(matches!(
+            delete,
+            CdcEventConversionError::NoReplicaIdentitySkipped(TABLE_ID)
+        ));
+    }
+
+    #[test]
+    fn updates_without_replica_identity_are_inserts_and_deletes_are_skipped() {
+        let tables = no_replica_identity_tables(NoReplicaIdentityPolicy::TreatUpdatesAsInserts);
+
+        let update = tables.update(TABLE_ID, updated_row(), None);
+        let delete = tables.delete(TABLE_ID);
+
+        let Ok(CdcEvent::Insert((table_id, row, None))) = update else {
+            panic!("expected an insert");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(row, updated_row());
+        assert!(matches!(
+            delete,
+            CdcEventConversionError::NoReplicaIdentitySkipped(TABLE_ID)
+        ));
+    }
+
+    /// The schemas as of before table `items`, whose id is `TABLE_ID`, was
+    /// dropped
+    fn items_table_schemas() -> HashMap<TableId, TableSchema> {
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "items".to_string(),
+            },
+            table_id: TABLE_ID,
+            column_schemas: vec![
+                column_schema("id", Type::INT4),
+                column_schema("name", Type::TEXT),
+            ],
+        };
+        HashMap::from([(TABLE_ID, table_schema)])
+    }
+
+    fn parse(message: BytesMut) -> LogicalReplicationMessage {
+        LogicalReplicationMessage::parse(&message.freeze()).expect("failed to parse message")
+    }
+
+    /// The relation message of table `orders`, which was created with the id
+    /// of the dropped table `items` and has other columns
+    fn orders_relation() -> LogicalReplicationMessage {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'R');
+        buf.put_u32(TABLE_ID);
+        buf.put_slice(b"public\0");
+        buf.put_slice(b"orders\0");
+        buf.put_u8(b'd');
+        buf.put_u16(3);
+        for (flags, name, type_oid) in [(1, "id", 20), (0, "total", 1700), (0, "paid", 16)] {
+            buf.put_u8(flags);
+            buf.put_slice(name.as_bytes());
+            buf.put_u8(0);
+            buf.put_u32(type_oid);
+            buf.put_i32(-1);
+        }
+        parse(buf)
+    }
+
+    fn orders_insert() -> LogicalReplicationMessage {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'I');
+        buf.put_u32(TABLE_ID);
+        buf.put_u8(b'N');
+        buf.put_u16(3);
+        for value in ["7", "12.50", "t"] {
+            buf.put_u8(b't');
+            buf.put_u32(value.len() as u32);
+            buf.put_slice(value.as_bytes());
+        }
+        parse(buf)
+    }
+
+    fn relation_received(
+        policy: ReusedRelationIdPolicy,
+        table_schemas: &mut HashMap<TableId, TableSchema>,
+    ) -> Result<(), CdcEventConversionError> {
+        let LogicalReplicationMessage::Relation(relation_body) = orders_relation() else {
+            panic!("expected a relation message");
+        };
+        ReusedRelationIds::new(policy).relation_received(TABLE_ID, &relation_body, table_schemas)
+    }
+
+    #[test]
+    fn reused_relation_id_is_rejected_by_default() {
+        let mut table_schemas = items_table_schemas();
+
+        let result = relation_received(ReusedRelationIdPolicy::default(), &mut table_schemas);
+
+        let Err(CdcEventConversionError::RelationIdReused {
+            table_id,
+            previous,
+            current,
+        }) = result
+        else {
+            panic!("expected a reused relation id error");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(previous.name, "items");
+        assert_eq!(current.name, "orders");
+        assert_eq!(table_schemas[&TABLE_ID].table_name.name, "items");
+    }
+
+    #[test]
+    fn changes_after_a_reused_relation_id_are_converted_with_the_new_schema() {
+        let mut table_schemas = items_table_schemas();
+
+        relation_received(ReusedRelationIdPolicy::ReplaceSchema, &mut table_schemas)
+            .expect("failed to replace the schema");
+        let event = CdcEventConverter::try_from_message(
+            orders_insert(),
+            &table_schemas,
+            &HashMap::new(),
+            None,
+            &TupleColumnMapper::default(),
+            &NoReplicaIdentityTables::default(),
+        )
+        .expect("failed to convert insert");
+
+        let table_schema = &table_schemas[&TABLE_ID];
+        assert_eq!(table_schema.table_name.name, "orders");
+        let columns: Vec<_> = table_schema
+            .column_schemas
+            .iter()
+            .map(|column_schema| (column_schema.name.as_str(), column_schema.primary))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![("id", true), ("total", false), ("paid", false)]
+        );
+        let CdcEvent::Insert((TABLE_ID, row, None)) = event else {
+            panic!("expected an insert");
+        };
+        assert_eq!(row.values[0], Cell::I64(7));
+        assert!(matches!(row.values[1], Cell::Numeric(_)));
+        assert_eq!(row.values[2], Cell::Bool(true));
+    }
+
+    #[test]
+    fn relation_of_the_same_table_keeps_its_schema() {
+        let mut table_schemas = items_table_schemas();
+        table_schemas.get_mut(&TABLE_ID).unwrap().table_name.name = "orders".to_string();
+
+        relation_received(ReusedRelationIdPolicy::ReplaceSchema, &mut table_schemas)
+            .expect("failed to check the relation");
+
+        assert_eq!(table_schemas[&TABLE_ID].column_schemas.len(), 2);
+    }
+
+    /// The schemas of table `memberships`, whose primary key is made of its
+    /// `org_id` and `user_id` columns
+    fn memberships_table_schemas() -> HashMap<TableId, TableSchema> {
+        let key_column_schema = |name| ColumnSchema {
+            primary: true,
+            nullable: false,
+            ..column_schema(name, Type::INT4)
+        };
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "memberships".to_string(),
+            },
+            table_id: TABLE_ID,
+            column_schemas: vec![
+                key_column_schema("org_id"),
+                key_column_schema("user_id"),
+                column_schema("role", Type::TEXT),
+            ],
+        };
+        HashMap::from([(TABLE_ID, table_schema)])
+    }
+
+    /// Appends the tuple data of `values` to `buf`, NULL for `None`
+    fn put_tuple(buf: &mut BytesMut, values: &[Option<&str>]) {
+        buf.put_u16(values.len() as u16);
+        for value in values {
+            match value {
+                Some(value) => {
+                    buf.put_u8(b't');
+                    buf.put_u32(value.len() as u32);
+                    buf.put_slice(value.as_bytes());
+                }
+                None => buf.put_u8(b'n'),
+            }
+        }
+    }
+
+    fn convert_memberships_message(message: LogicalReplicationMessage) -> CdcEvent {
+        CdcEventConverter::try_from_message(
+            message,
+            &memberships_table_schemas(),
+            &HashMap::new(),
+            None,
+            &TupleColumnMapper::default(),
+            &NoReplicaIdentityTables::default(),
         )
+        .expect("failed to convert message")
+    }
+
+    #[test]
+    fn update_of_a_composite_key_has_the_old_key_and_both_key_columns() {
+        // update memberships set user_id = 8 where org_id = 1 and user_id = 7;
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'U');
+        buf.put_u32(TABLE_ID);
+        buf.put_u8(b'K');
+        put_tuple(&mut buf, &[Some("1"), Some("7"), None]);
+        buf.put_u8(b'N');
+        put_tuple(&mut buf, &[Some("1"), Some("8"), Some("admin")]);
+
+        let event = convert_memberships_message(parse(buf));
+
+        let CdcEvent::Update {
+            table_id,
+            old_row,
+            key_row,
+            row,
+            key_columns,
+            ..
+        } = event
+        else {
+            panic!("expected an update");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(old_row, None);
+        assert_eq!(
+            key_row,
+            Some(TableRow::from(vec![Cell::I32(1), Cell::I32(7), Cell::Null]))
+        );
+        assert_eq!(
+            row.values,
+            vec![
+                Cell::I32(1),
+                Cell::I32(8),
+                Cell::String("admin".to_string())
+            ]
+        );
+        assert_eq!(key_columns, vec![0, 1]);
+    }
+
+    #[test]
+    fn delete_of_a_composite_key_has_both_key_columns() {
+        // delete from memberships where org_id = 1 and user_id = 8;
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'D');
+        buf.put_u32(TABLE_ID);
+        buf.put_u8(b'K');
+        put_tuple(&mut buf, &[Some("1"), Some("8"), None]);
+
+        let event = convert_memberships_message(parse(buf));
+
+        let CdcEvent::Delete {
+            table_id,
+            row,
+            key_columns,
+            ..
+        } = event
+        else {
+            panic!("expected a delete");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(row.values, vec![Cell::I32(1), Cell::I32(8), Cell::Null]);
+        assert_eq!(key_columns, vec![0, 1]);
     }
 }
@@ -0,0 +1,144 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+use tokio_postgres::types::Type;
+
+#[derive(Debug, Error)]
+pub enum GeometryParseError {
+    #[error("invalid {0} value: {1}")]
+    InvalidInput(String, String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
+
+/// A value of one of Postgres' built-in geometric types. Its [`Display`]
+/// impl prints the same text Postgres does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point(Point),
+    /// The infinite line `a*x + b*y + c = 0`
+    Line {
+        a: f64,
+        b: f64,
+        c: f64,
+    },
+    LineSegment(Point, Point),
+    /// Opposite corners of a box, upper right first
+    Box(Point, Point),
+    Path {
+        points: Vec<Point>,
+        closed: bool,
+    },
+    Polygon(Vec<Point>),
+    Circle {
+        center: Point,
+        radius: f64,
+    },
+}
+
+fn write_points(f: &mut std::fmt::Formatter<'_>, points: &[Point]) -> std::fmt::Result {
+    for (i, point) in points.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{point}")?;
+    }
+    Ok(())
+}
+
+impl Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Geometry::Point(point) => write!(f, "{point}"),
+            Geometry::Line { a, b, c } => write!(f, "{{{a},{b},{c}}}"),
+            Geometry::LineSegment(start, end) => write!(f, "[{start},{end}]"),
+            Geometry::Box(upper_right, lower_left) => write!(f, "{upper_right},{lower_left}"),
+            Geometry::Path { points, closed } => {
+                let (open, close) = if *closed { ('(', ')') } else { ('[', ']') };
+                write!(f, "{open}")?;
+                write_points(f, points)?;
+                write!(f, "{close}")
+            }
+            Geometry::Polygon(points) => {
+                write!(f, "(")?;
+                write_points(f, points)?;
+                write!(f, ")")
+            }
+            Geometry::Circle { center, radius } => write!(f, "<{center},{radius}>"),
+        }
+    }
+}
+
+impl Geometry {
+    /// Whether `typ` is one of the geometric types [`Geometry`] holds values of
+    pub fn is_geometric_type(typ: &Type) -> bool {
+        matches!(
+            *typ,
+            Type::POINT
+                | Type::LINE
+                | Type::LSEG
+                | Type::BOX
+                | Type::PATH
+                | Type::POLYGON
+                | Type::CIRCLE
+        )
+    }
+
+    /// Parses the text representation of a value of the geometric type `typ`.
+    /// The binary decoders of `postgres-protocol` don't help here because
+    /// values are replicated in the text format.
+    pub fn parse(typ: &Type, s: &str) -> Result<Geometry, GeometryParseError> {
+        let invalid = || GeometryParseError::InvalidInput(typ.name().to_string(), s.to_string());
+
+        // Every geometric type is printed as numbers separated by commas and
+        // decorated by brackets, which only tell paths apart
+        let numbers = s
+            .split(|c: char| "()[]{}<>,".contains(c))
+            .filter(|number| !number.is_empty())
+            .map(|number| number.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid())?;
+        let points = || {
+            numbers
+                .chunks_exact(2)
+                .map(|xy| Point { x: xy[0], y: xy[1] })
+                .collect::<Vec<Point>>()
+        };
+
+        let geometry = match (typ, numbers.as_slice()) {
+            (&Type::POINT, &[x, y]) => Geometry::Point(Point { x, y }),
+            (&Type::LINE, &[a, b, c]) => Geometry::Line { a, b, c },
+            (&Type::LSEG, &[x1, y1, x2, y2]) => {
+                Geometry::LineSegment(Point { x: x1, y: y1 }, Point { x: x2, y: y2 })
+            }
+            (&Type::BOX, &[x1, y1, x2, y2]) => {
+                Geometry::Box(Point { x: x1, y: y1 }, Point { x: x2, y: y2 })
+            }
+            (&Type::PATH, numbers) if !numbers.is_empty() && numbers.len() % 2 == 0 => {
+                Geometry::Path {
+                    points: points(),
+                    closed: s.starts_with('('),
+                }
+            }
+            (&Type::POLYGON, numbers) if !numbers.is_empty() && numbers.len() % 2 == 0 => {
+                Geometry::Polygon(points())
+            }
+            (&Type::CIRCLE, &[x, y, radius]) => Geometry::Circle {
+                center: Point { x, y },
+                radius,
+            },
+            _ => return Err(invalid()),
+        };
+        Ok(geometry)
+    }
+}
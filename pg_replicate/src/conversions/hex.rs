@@ -19,8 +19,40 @@ pub fn from_bytea_hex(s: &str) -> Result<Vec<u8>, ByteaHexParseError> {
         return Err(ByteaHexParseError::InvalidPrefix);
     }
 
-    let mut result = Vec::with_capacity((s.len() - 2) / 2);
-    let s = &s[2..];
+    from_hex(&s[2..])
+}
+
+/// Encodes `bytes` in Postgres' hex format of bytea, `\x` followed by
+/// lowercase hex digits, the inverse of [`from_bytea_hex`]
+pub fn to_bytea_hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!("\\x{digits}")
+}
+
+/// Encodes `bytes` in Postgres' escape format of bytea: printable ASCII
+/// characters as they are, backslashes doubled and all other bytes as `\`
+/// followed by three octal digits
+pub fn to_bytea_escape(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    s
+}
+
+/// Encodes `bytes` as uppercase hex digits without a prefix, the inverse of
+/// [`from_hex`]
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+/// Decodes hex digits without a prefix, like PostGIS' text output of EWKB
+pub fn from_hex(s: &str) -> Result<Vec<u8>, ByteaHexParseError> {
+    let mut result = Vec::with_capacity(s.len() / 2);
 
     if s.len() % 2 != 0 {
         return Err(ByteaHexParseError::OddNumerOfDigits);
@@ -0,0 +1,315 @@
+//! Intervals are replicated as the text Postgres prints them in, its default
+//! `postgres` interval style, e.g. `1 year 2 mons -3 days 04:05:06`. Sinks
+//! whose consumers expect another format parse that text into an
+//! [`Interval`] and print it in an [`IntervalFormat`].
+
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+const MICROSECONDS_PER_SECOND: i64 = 1_000_000;
+const MICROSECONDS_PER_MINUTE: i64 = 60 * MICROSECONDS_PER_SECOND;
+const MICROSECONDS_PER_HOUR: i64 = 60 * MICROSECONDS_PER_MINUTE;
+
+#[derive(Debug, Error)]
+#[error("invalid interval value: {0}")]
+pub struct IntervalParseError(String);
+
+/// How intervals are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntervalFormat {
+    /// Postgres' default output, `1 year 2 mons 3 days 04:05:06`
+    #[default]
+    Postgres,
+    /// An ISO 8601 duration, `P1Y2M3DT4H5M6S`, as Postgres prints it with
+    /// the `iso_8601` interval style. Every component has its own sign, so
+    /// `-1 days +02:00:00` is `P-1DT2H`.
+    Iso8601,
+}
+
+/// A Postgres interval. Like Postgres, it keeps months, days and time apart
+/// because neither months nor days have a fixed length. Each can be negative
+/// independently of the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+/// The components an interval is printed with, all with the sign of the
+/// field they are taken from
+struct Components {
+    years: i32,
+    months: i32,
+    days: i32,
+    hours: i64,
+    minutes: i64,
+    seconds: i64,
+    microseconds: i64,
+}
+
+impl Interval {
+    fn components(&self) -> Components {
+        Components {
+            years: self.months / 12,
+            months: self.months % 12,
+            days: self.days,
+            hours: self.microseconds / MICROSECONDS_PER_HOUR,
+            minutes: self.microseconds % MICROSECONDS_PER_HOUR / MICROSECONDS_PER_MINUTE,
+            seconds: self.microseconds % MICROSECONDS_PER_MINUTE / MICROSECONDS_PER_SECOND,
+            microseconds: self.microseconds % MICROSECONDS_PER_SECOND,
+        }
+    }
+
+    /// Prints the interval in `format`
+    pub fn to_string_with(&self, format: IntervalFormat) -> String {
+        match format {
+            IntervalFormat::Postgres => self.to_postgres_string(),
+            IntervalFormat::Iso8601 => self.to_iso8601_string(),
+        }
+    }
+
+    /// Follows `EncodeInterval` of Postgres' `INTSTYLE_POSTGRES`: a component
+    /// following a negative one gets an explicit `+` if it is positive
+    fn to_postgres_string(&self) -> String {
+        let c = self.components();
+        let mut parts = vec![];
+        let mut after_negative = false;
+        for (value, unit) in [(c.years, "year"), (c.months, "mon"), (c.days, "day")] {
+            if value == 0 {
+                continue;
+            }
+            let sign = if after_negative && value > 0 { "+" } else { "" };
+            let plural = if value != 1 { "s" } else { "" };
+            parts.push(format!("{sign}{value} {unit}{plural}"));
+            after_negative = value < 0;
+        }
+        if parts.is_empty() || self.microseconds != 0 {
+            let sign = if self.microseconds < 0 {
+                "-"
+            } else if after_negative {
+                "+"
+            } else {
+                ""
+            };
+            parts.push(format!(
+                "{sign}{:02}:{:02}:{}",
+                c.hours.abs(),
+                c.minutes.abs(),
+                seconds(c.seconds.abs(), c.microseconds.abs(), true)
+            ));
+        }
+        parts.join(" ")
+    }
+
+    /// Follows `EncodeInterval` of Postgres' `INTSTYLE_ISO_8601`
+    fn to_iso8601_string(&self) -> String {
+        if *self == Interval::default() {
+            return "PT0S".to_string();
+        }
+        let c = self.components();
+        let mut s = "P".to_string();
+        for (value, designator) in [(c.years, 'Y'), (c.months, 'M'), (c.days, 'D')] {
+            if value != 0 {
+                s.push_str(&format!("{value}{designator}"));
+            }
+        }
+        if self.microseconds != 0 {
+            s.push('T');
+            for (value, designator) in [(c.hours, 'H'), (c.minutes, 'M')] {
+                if value != 0 {
+                    s.push_str(&format!("{value}{designator}"));
+                }
+            }
+            if c.seconds != 0 || c.microseconds != 0 {
+                if c.seconds < 0 || c.microseconds < 0 {
+                    s.push('-');
+                }
+                s.push_str(&seconds(c.seconds.abs(), c.microseconds.abs(), false));
+                s.push('S');
+            }
+        }
+        s
+    }
+}
+
+/// Seconds with their fraction, if any, without trailing zeros
+fn seconds(seconds: i64, microseconds: i64, zero_padded: bool) -> String {
+    let mut s = if zero_padded {
+        format!("{seconds:02}")
+    } else {
+        seconds.to_string()
+    };
+    if microseconds != 0 {
+        let fraction = format!("{microseconds:06}");
+        s.push('.');
+        s.push_str(fraction.trim_end_matches('0'));
+    }
+    s
+}
+
+impl Display for Interval {
+    /// Prints the interval as Postgres does by default
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_postgres_string())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = IntervalParseError;
+
+    /// Parses an interval printed in Postgres' default `postgres` interval
+    /// style
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || IntervalParseError(s.to_string());
+        let mut interval = Interval::default();
+        let mut tokens = s.split_whitespace().peekable();
+        if tokens.peek().is_none() {
+            return Err(invalid());
+        }
+        while let Some(token) = tokens.next() {
+            if token.contains(':') {
+                interval.microseconds = parse_time(token).ok_or_else(invalid)?;
+                continue;
+            }
+            let value: i32 = token.parse().map_err(|_| invalid())?;
+            let months = match tokens.next() {
+                Some("year" | "years") => value.checked_mul(12),
+                Some("mon" | "mons") => Some(value),
+                Some("day" | "days") => {
+                    interval.days = value;
+                    continue;
+                }
+                _ => None,
+            };
+            interval.months = months
+                .and_then(|months| interval.months.checked_add(months))
+                .ok_or_else(invalid)?;
+        }
+        Ok(interval)
+    }
+}
+
+/// Parses the time of an interval, `[+-]HH:MM:SS[.ffffff]`, whose hours may
+/// exceed 24, to microseconds
+fn parse_time(s: &str) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let mut parts = s.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if fraction.len() > 6 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let seconds: i64 = seconds.parse().ok()?;
+    let microseconds: i64 = format!("{fraction:0<6}").parse().ok()?;
+    let time = hours
+        .checked_mul(MICROSECONDS_PER_HOUR)?
+        .checked_add(minutes * MICROSECONDS_PER_MINUTE)?
+        .checked_add(seconds * MICROSECONDS_PER_SECOND)?
+        .checked_add(microseconds)?;
+    Some(if negative { -time } else { time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interval, IntervalFormat};
+
+    fn interval(s: &str) -> Interval {
+        s.parse().expect("failed to parse interval")
+    }
+
+    #[test]
+    fn mixed_interval_is_printed_in_both_formats() {
+        let interval = interval("1 year 2 mons 3 days 04:05:06.5");
+
+        assert_eq!(
+            interval,
+            Interval {
+                months: 14,
+                days: 3,
+                microseconds: 14_706_500_000,
+            }
+        );
+        assert_eq!(
+            interval.to_string_with(IntervalFormat::Postgres),
+            "1 year 2 mons 3 days 04:05:06.5"
+        );
+        assert_eq!(
+            interval.to_string_with(IntervalFormat::Iso8601),
+            "P1Y2M3DT4H5M6.5S"
+        );
+    }
+
+    #[test]
+    fn negative_components_keep_their_own_sign() {
+        let interval = interval("-1 years -2 mons +3 days -04:05:06");
+
+        assert_eq!(interval.months, -14);
+        assert_eq!(interval.days, 3);
+        assert_eq!(
+            interval.to_string_with(IntervalFormat::Postgres),
+            "-1 years -2 mons +3 days -04:05:06"
+        );
+        assert_eq!(
+            interval.to_string_with(IntervalFormat::Iso8601),
+            "P-1Y-2M3DT-4H-5M-6S"
+        );
+    }
+
+    #[test]
+    fn time_after_a_negative_day_is_signed() {
+        let interval = interval("-1 days +02:00:00");
+
+        assert_eq!(interval.to_string(), "-1 days +02:00:00");
+        assert_eq!(interval.to_string_with(IntervalFormat::Iso8601), "P-1DT2H");
+    }
+
+    #[test]
+    fn negative_fraction_of_a_second_is_signed() {
+        let interval = interval("-00:00:00.25");
+
+        assert_eq!(interval.microseconds, -250_000);
+        assert_eq!(interval.to_string(), "-00:00:00.25");
+        assert_eq!(interval.to_string_with(IntervalFormat::Iso8601), "PT-0.25S");
+    }
+
+    #[test]
+    fn zero_interval_is_printed_in_both_formats() {
+        let interval = interval("00:00:00");
+
+        assert_eq!(interval, Interval::default());
+        assert_eq!(interval.to_string(), "00:00:00");
+        assert_eq!(interval.to_string_with(IntervalFormat::Iso8601), "PT0S");
+    }
+
+    #[test]
+    fn hours_past_a_day_are_kept() {
+        let interval = interval("1 mon 100:00:00");
+
+        assert_eq!(interval.to_string(), "1 mon 100:00:00");
+        assert_eq!(interval.to_string_with(IntervalFormat::Iso8601), "P1MT100H");
+    }
+
+    #[test]
+    fn malformed_intervals_are_rejected() {
+        for s in [
+            "",
+            "1",
+            "1 week",
+            "1 day 02:00",
+            "01:02:03.1234567",
+            "infinity",
+        ] {
+            assert!(s.parse::<Interval>().is_err(), "{s}");
+        }
+    }
+}
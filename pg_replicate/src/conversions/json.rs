@@ -0,0 +1,255 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::{Number, Value};
+
+use super::{hex, ArrayCell, Cell};
+
+/// How bytea values are written in JSON, to match what a downstream expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesFormat {
+    /// `\x` followed by hex digits, Postgres' default text output of bytea
+    Hex,
+    /// Postgres' escape format, the output with `bytea_output = escape`
+    Escape,
+    /// Standard base64 with padding
+    Base64,
+}
+
+impl BytesFormat {
+    fn to_json_value(self, bytes: &[u8]) -> Value {
+        Value::String(match self {
+            BytesFormat::Hex => hex::to_bytea_hex(bytes),
+            BytesFormat::Escape => hex::to_bytea_escape(bytes),
+            BytesFormat::Base64 => BASE64_STANDARD.encode(bytes),
+        })
+    }
+}
+
+impl Cell {
+    /// Converts the cell to JSON, the same way for every sink which writes
+    /// JSON documents:
+    ///
+    /// * NULL is `null`, also for NULL elements of arrays
+    /// * integers and finite floats are numbers. NaN and infinite floats are
+    ///   the strings `NaN`, `inf` and `-inf`
+    /// * numerics are strings, so that they keep their precision, and `NaN`
+    ///   and infinite ones are the strings Postgres prints for them
+    /// * dates are `YYYY-MM-DD`, times `HH:MM:SS` with fractional seconds if
+    ///   any, timestamps ISO 8601 without an offset and timestamps with a
+    ///   time zone RFC 3339 in UTC
+    /// * bytea is a `\x` hex string, like Postgres prints it, see
+    ///   [`Cell::to_json_value_with`] for the other formats, and EWKB is an
+    ///   uppercase hex string, like PostGIS prints it
+    /// * json and jsonb are the JSON value itself
+    /// * uuids, xml and geometric types are their text representation
+    /// * arrays are JSON arrays of their elements converted as above
+    pub fn to_json_value(&self) -> Value {
+        self.to_json_value_with(BytesFormat::Hex)
+    }
+
+    /// Converts the cell to JSON like [`Cell::to_json_value`] but writes bytea
+    /// values, also in arrays, in `bytes_format`
+    pub fn to_json_value_with(&self, bytes_format: BytesFormat) -> Value {
+        match self {
+            Cell::Null => Value::Null,
+            Cell::Bool(b) => Value::Bool(*b),
+            Cell::String(s) | Cell::Xml(s) => Value::String(s.clone()),
+            Cell::I16(i) => Value::from(*i),
+            Cell::I32(i) => Value::from(*i),
+            Cell::U32(u) => Value::from(*u),
+            Cell::I64(i) => Value::from(*i),
+            Cell::F32(f) => float_to_json(*f as f64),
+            Cell::F64(f) => float_to_json(*f),
+            Cell::Numeric(n) => Value::String(n.to_string()),
+            Cell::Date(d) => Value::String(d.format("%Y-%m-%d").to_string()),
+            Cell::Time(t) => Value::String(t.format("%H:%M:%S%.f").to_string()),
+            Cell::TimeStamp(t) => Value::String(t.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+            Cell::TimeStampTz(t) => Value::String(t.to_rfc3339()),
+            Cell::Uuid(u) => Value::String(u.to_string()),
+            Cell::Json(j) => j.clone(),
+            Cell::Bytes(b) => bytes_format.to_json_value(b),
+            Cell::Geometry(g) => Value::String(g.to_string()),
+            Cell::Ewkb(b) => Value::String(hex::to_hex(b)),
+            Cell::Array(array) => array.to_json_value_with(bytes_format),
+        }
+    }
+}
+
+impl ArrayCell {
+    /// Converts the array to a JSON array, see [`Cell::to_json_value`]
+    pub fn to_json_value(&self) -> Value {
+        self.to_json_value_with(BytesFormat::Hex)
+    }
+
+    /// Converts the array to a JSON array, see [`Cell::to_json_value_with`]
+    pub fn to_json_value_with(&self, bytes_format: BytesFormat) -> Value {
+        match self {
+            ArrayCell::Null => Value::Null,
+            ArrayCell::Bool(v) => array_to_json(v, |b| Value::Bool(*b)),
+            ArrayCell::String(v) => array_to_json(v, |s| Value::String(s.clone())),
+            ArrayCell::I16(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::I32(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::U32(v) => array_to_json(v, |u| Value::from(*u)),
+            ArrayCell::I64(v) => array_to_json(v, |i| Value::from(*i)),
+            ArrayCell::F32(v) => array_to_json(v, |f| float_to_json(*f as f64)),
+            ArrayCell::F64(v) => array_to_json(v, |f| float_to_json(*f)),
+            ArrayCell::Numeric(v) => array_to_json(v, |n| Value::String(n.to_string())),
+            ArrayCell::Date(v) => {
+                array_to_json(v, |d| Value::String(d.format("%Y-%m-%d").to_string()))
+            }
+            ArrayCell::Time(v) => {
+                array_to_json(v, |t| Value::String(t.format("%H:%M:%S%.f").to_string()))
+            }
+            ArrayCell::TimeStamp(v) => array_to_json(v, |t| {
+                Value::String(t.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+            }),
+            ArrayCell::TimeStampTz(v) => array_to_json(v, |t| Value::String(t.to_rfc3339())),
+            ArrayCell::Uuid(v) => array_to_json(v, |u| Value::String(u.to_string())),
+            ArrayCell::Json(v) => array_to_json(v, |j| j.clone()),
+            ArrayCell::Bytes(v) => array_to_json(v, |b| bytes_format.to_json_value(b)),
+        }
+    }
+}
+
+fn array_to_json<T>(values: &[Option<T>], f: impl Fn(&T) -> Value) -> Value {
+    Value::Array(
+        values
+            .iter()
+            .map(|value| value.as_ref().map(&f).unwrap_or(Value::Null))
+            .collect(),
+    )
+}
+
+fn float_to_json(f: f64) -> Value {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .unwrap_or_else(|| Value::String(f.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use crate::conversions::{
+        geometry::{Geometry, Point},
+        numeric::PgNumeric,
+        ArrayCell, Cell,
+    };
+
+    use super::BytesFormat;
+
+    #[test]
+    fn scalars_are_converted_to_json() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        let time = NaiveTime::from_hms_micro_opt(13, 4, 5, 250).unwrap();
+        let uuid = Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+        let numeric: PgNumeric = "12345678901234567890.123".parse().unwrap();
+
+        let cases = vec![
+            (Cell::Null, Value::Null),
+            (Cell::Bool(true), json!(true)),
+            (Cell::String("a".to_string()), json!("a")),
+            (Cell::I16(-1), json!(-1)),
+            (Cell::I32(2), json!(2)),
+            (Cell::U32(3), json!(3)),
+            (Cell::I64(i64::MAX), json!(i64::MAX)),
+            (Cell::F32(1.5), json!(1.5)),
+            (Cell::F64(-0.25), json!(-0.25)),
+            (Cell::F64(f64::NAN), json!("NaN")),
+            (Cell::F64(f64::INFINITY), json!("inf")),
+            (Cell::Numeric(numeric), json!("12345678901234567890.123")),
+            (Cell::Date(date), json!("2024-02-29")),
+            (Cell::Time(time), json!("13:04:05.000250")),
+            (
+                Cell::TimeStamp(date.and_time(time)),
+                json!("2024-02-29T13:04:05.000250"),
+            ),
+            (
+                Cell::TimeStampTz(date.and_time(time).and_utc()),
+                json!("2024-02-29T13:04:05.000250+00:00"),
+            ),
+            (
+                Cell::Uuid(uuid),
+                json!("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11"),
+            ),
+            (Cell::Json(json!({"a": [1, null]})), json!({"a": [1, null]})),
+            (
+                Cell::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+                json!("\\xdeadbeef"),
+            ),
+            (Cell::Xml("<a/>".to_string()), json!("<a/>")),
+            (
+                Cell::Geometry(Geometry::Point(Point { x: 1.0, y: 2.5 })),
+                json!("(1,2.5)"),
+            ),
+            (Cell::Ewkb(vec![0x01, 0xab]), json!("01AB")),
+        ];
+
+        for (cell, expected) in cases {
+            assert_eq!(cell.to_json_value(), expected, "{cell:?}");
+        }
+    }
+
+    #[test]
+    fn numerics_keep_their_trailing_zeros_in_json() {
+        let numeric: PgNumeric = "1.50".parse().unwrap();
+
+        assert_eq!(Cell::Numeric(numeric).to_json_value(), json!("1.50"));
+    }
+
+    #[test]
+    fn arrays_are_converted_to_json_arrays() {
+        let cases = vec![
+            (Cell::Array(ArrayCell::Null), Value::Null),
+            (
+                Cell::Array(ArrayCell::I32(vec![Some(1), None, Some(3)])),
+                json!([1, null, 3]),
+            ),
+            (
+                Cell::Array(ArrayCell::Numeric(vec![Some("1.10".parse().unwrap())])),
+                json!(["1.10"]),
+            ),
+            (
+                Cell::Array(ArrayCell::Bytes(vec![Some(vec![0xff]), None])),
+                json!(["\\xff", null]),
+            ),
+            (Cell::Array(ArrayCell::String(vec![None])), json!([null])),
+        ];
+
+        for (cell, expected) in cases {
+            assert_eq!(cell.to_json_value(), expected, "{cell:?}");
+        }
+    }
+
+    #[test]
+    fn bytea_is_converted_in_the_given_format() {
+        let bytes = vec![0xde, 0xad, b'\\', b'a', 0x00];
+        let cases = vec![
+            (BytesFormat::Hex, json!("\\xdead5c6100")),
+            (BytesFormat::Escape, json!("\\336\\255\\\\a\\000")),
+            (BytesFormat::Base64, json!("3q1cYQA=")),
+        ];
+
+        for (bytes_format, expected) in cases {
+            let cell = Cell::Bytes(bytes.clone());
+            assert_eq!(cell.to_json_value_with(bytes_format), expected);
+            let array = Cell::Array(ArrayCell::Bytes(vec![Some(bytes.clone()), None]));
+            assert_eq!(
+                array.to_json_value_with(bytes_format),
+                json!([expected, null])
+            );
+        }
+    }
+
+    #[test]
+    fn arrays_of_json_arrays_stay_nested() {
+        let cell = Cell::Array(ArrayCell::Json(vec![
+            Some(json!([1, [2, 3]])),
+            None,
+            Some(json!([])),
+        ]));
+
+        assert_eq!(cell.to_json_value(), json!([[1, [2, 3]], null, []]));
+    }
+}
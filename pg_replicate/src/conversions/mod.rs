@@ -2,17 +2,33 @@ use std::fmt::Debug;
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use derive_more::{TryInto, TryIntoError};
+use geometry::Geometry;
 use numeric::PgNumeric;
 use trait_gen::trait_gen;
 use uuid::Uuid;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod bool;
 pub mod cdc_event;
+pub mod geometry;
 pub mod hex;
+pub mod interval;
+pub mod json;
 pub mod numeric;
+pub mod sql;
+pub mod streamed_transactions;
 pub mod table_row;
 pub mod text;
+pub mod wal2json;
 
+/// Cells are equal if they are the same variant with equal values. Unlike
+/// with `==` on floats, a NaN [`Cell::F32`] or [`Cell::F64`] equals another
+/// NaN of the same variant, so that a cell always equals itself and its
+/// clones, and `0.0` equals `-0.0`. The floats of arrays are compared the
+/// same way, a NaN [`PgNumeric`] always equals another NaN. Cells of
+/// different variants are never equal, not even [`Cell::I32`] and
+/// [`Cell::I64`] of the same number.
 #[derive(Debug, Clone, TryInto)]
 pub enum Cell {
     #[try_into(ignore)]
@@ -31,9 +47,246 @@ pub enum Cell {
     TimeStamp(NaiveDateTime),
     TimeStampTz(DateTime<Utc>),
     Uuid(Uuid),
+    /// Numbers which don't fit an i64, u64 or f64 lose precision if the
+    /// default `json_arbitrary_precision` feature is disabled
     Json(serde_json::Value),
     Bytes(Vec<u8>),
     Array(ArrayCell),
+    Xml(String),
+    Geometry(Geometry),
+    /// A PostGIS `geometry` or `geography` in the extended well-known binary
+    /// format, passed through undecoded
+    Ewkb(Vec<u8>),
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Cell::Null, Cell::Null) => true,
+            (Cell::Bool(a), Cell::Bool(b)) => a == b,
+            (Cell::String(a), Cell::String(b)) => a == b,
+            (Cell::I16(a), Cell::I16(b)) => a == b,
+            (Cell::I32(a), Cell::I32(b)) => a == b,
+            (Cell::U32(a), Cell::U32(b)) => a == b,
+            (Cell::I64(a), Cell::I64(b)) => a == b,
+            (Cell::F32(a), Cell::F32(b)) => f32_eq(*a, *b),
+            (Cell::F64(a), Cell::F64(b)) => f64_eq(*a, *b),
+            (Cell::Numeric(a), Cell::Numeric(b)) => a == b,
+            (Cell::Date(a), Cell::Date(b)) => a == b,
+            (Cell::Time(a), Cell::Time(b)) => a == b,
+            (Cell::TimeStamp(a), Cell::TimeStamp(b)) => a == b,
+            (Cell::TimeStampTz(a), Cell::TimeStampTz(b)) => a == b,
+            (Cell::Uuid(a), Cell::Uuid(b)) => a == b,
+            (Cell::Json(a), Cell::Json(b)) => a == b,
+            (Cell::Bytes(a), Cell::Bytes(b)) => a == b,
+            (Cell::Array(a), Cell::Array(b)) => a == b,
+            (Cell::Xml(a), Cell::Xml(b)) => a == b,
+            (Cell::Geometry(a), Cell::Geometry(b)) => a == b,
+            (Cell::Ewkb(a), Cell::Ewkb(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+fn f32_eq(a: f32, b: f32) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+fn f64_eq(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// Compares the elements of arrays with `eq`, nulls only equal nulls
+fn elements_eq<T: Copy>(a: &[Option<T>], b: &[Option<T>], eq: impl Fn(T, T) -> bool) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| match (a, b) {
+            (Some(a), Some(b)) => eq(*a, *b),
+            (None, None) => true,
+            _ => false,
+        })
+}
+
+/// Borrowing accessors for sink authors. Each accessor returns `None` when the
+/// cell holds a different variant (including [`Cell::Null`]).
+impl Cell {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Cell::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Cell::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `String` or `Xml` cell
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Cell::String(value) | Cell::Xml(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i16(&self) -> Option<i16> {
+        match self {
+            Cell::I16(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `I16` or `I32` cell
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Cell::I16(value) => Some(*value as i32),
+            Cell::I32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Cell::U32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any integer cell as they all fit in an i64
+    ///
+    /// ```
+    /// use pg_replicate::conversions::Cell;
+    ///
+    /// assert_eq!(Cell::I16(7).as_i64(), Some(7));
+    /// assert_eq!(Cell::U32(u32::MAX).as_i64(), Some(u32::MAX as i64));
+    /// assert_eq!(Cell::String("7".to_string()).as_i64(), None);
+    /// assert_eq!(Cell::Null.as_i64(), None);
+    /// ```
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Cell::I16(value) => Some(*value as i64),
+            Cell::I32(value) => Some(*value as i64),
+            Cell::U32(value) => Some(*value as i64),
+            Cell::I64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Cell::F32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `F32` or `F64` cell
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Cell::F32(value) => Some(*value as f64),
+            Cell::F64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_numeric(&self) -> Option<&PgNumeric> {
+        match self {
+            Cell::Numeric(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `F32`, `F64` or `Numeric` cell. Lossy for
+    /// numerics with more digits than a double can hold, see the
+    /// `TryFrom<&PgNumeric>` impl of f64
+    pub fn as_f64_lossy(&self) -> Option<f64> {
+        match self {
+            Cell::Numeric(value) => f64::try_from(value).ok(),
+            _ => self.as_f64(),
+        }
+    }
+
+    pub fn as_date(&self) -> Option<NaiveDate> {
+        match self {
+            Cell::Date(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_time(&self) -> Option<NaiveTime> {
+        match self {
+            Cell::Time(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<NaiveDateTime> {
+        match self {
+            Cell::TimeStamp(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamptz(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Cell::TimeStampTz(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match self {
+            Cell::Uuid(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_json(&self) -> Option<&serde_json::Value> {
+        match self {
+            Cell::Json(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Cell::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of an `Array` cell. A NULL array is a
+    /// [`Cell::Null`], for which `None` is returned.
+    ///
+    /// ```
+    /// use pg_replicate::conversions::{ArrayCell, Cell};
+    ///
+    /// let cell = Cell::Array(ArrayCell::I32(vec![Some(1), None]));
+    /// let Some(ArrayCell::I32(elements)) = cell.as_array() else {
+    ///     panic!("not an int4[] cell");
+    /// };
+    /// assert_eq!(elements, &[Some(1), None]);
+    ///
+    /// assert_eq!(Cell::Null.as_array(), None);
+    /// ```
+    pub fn as_array(&self) -> Option<&ArrayCell> {
+        match self {
+            Cell::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_geometry(&self) -> Option<&Geometry> {
+        match self {
+            Cell::Geometry(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_ewkb(&self) -> Option<&[u8]> {
+        match self {
+            Cell::Ewkb(value) => Some(value),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(feature = "rust_decimal")]
@@ -100,6 +353,8 @@ impl TryFrom<Cell> for Vec<Option<T>> {
     }
 }
 
+/// A NULL array converts to `None` and an empty array, `'{}'`, to
+/// `Some(vec![])`. Elements which are NULL convert to `None` inside the vec.
 #[trait_gen(T -> 
     bool, String, i16, i32, u32, i64, f32, f64, PgNumeric, 
     NaiveDate, NaiveTime, NaiveDateTime, DateTime<Utc>,
@@ -122,6 +377,10 @@ impl TryFrom<Cell> for Option<Vec<Option<T>>> {
     }
 }
 
+/// The elements of an array value. An empty array is a variant holding an
+/// empty vec, never [`ArrayCell::Null`]: the converters represent a NULL
+/// array column as [`Cell::Null`]. Arrays are compared like [`Cell`]s are,
+/// element by element.
 #[derive(Debug, Clone, TryInto)]
 pub enum ArrayCell {
     #[try_into(ignore)]
@@ -142,4 +401,123 @@ pub enum ArrayCell {
     Uuid(Vec<Option<Uuid>>),
     Json(Vec<Option<serde_json::Value>>),
     Bytes(Vec<Option<Vec<u8>>>),
-}
\ No newline at end of file
+}
+
+impl PartialEq for ArrayCell {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ArrayCell::Null, ArrayCell::Null) => true,
+            (ArrayCell::Bool(a), ArrayCell::Bool(b)) => a == b,
+            (ArrayCell::String(a), ArrayCell::String(b)) => a == b,
+            (ArrayCell::I16(a), ArrayCell::I16(b)) => a == b,
+            (ArrayCell::I32(a), ArrayCell::I32(b)) => a == b,
+            (ArrayCell::U32(a), ArrayCell::U32(b)) => a == b,
+            (ArrayCell::I64(a), ArrayCell::I64(b)) => a == b,
+            (ArrayCell::F32(a), ArrayCell::F32(b)) => elements_eq(a, b, f32_eq),
+            (ArrayCell::F64(a), ArrayCell::F64(b)) => elements_eq(a, b, f64_eq),
+            (ArrayCell::Numeric(a), ArrayCell::Numeric(b)) => a == b,
+            (ArrayCell::Date(a), ArrayCell::Date(b)) => a == b,
+            (ArrayCell::Time(a), ArrayCell::Time(b)) => a == b,
+            (ArrayCell::TimeStamp(a), ArrayCell::TimeStamp(b)) => a == b,
+            (ArrayCell::TimeStampTz(a), ArrayCell::TimeStampTz(b)) => a == b,
+            (ArrayCell::Uuid(a), ArrayCell::Uuid(b)) => a == b,
+            (ArrayCell::Json(a), ArrayCell::Json(b)) => a == b,
+            (ArrayCell::Bytes(a), ArrayCell::Bytes(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate, NaiveTime};
+    use uuid::Uuid;
+
+    use super::{
+        geometry::{Geometry, Point},
+        ArrayCell, Cell,
+    };
+
+    /// A cell of each variant, none of which equals another
+    fn cells() -> Vec<Cell> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let time = NaiveTime::from_hms_opt(3, 4, 5).unwrap();
+        vec![
+            Cell::Null,
+            Cell::Bool(true),
+            Cell::String("a".to_string()),
+            Cell::I16(1),
+            Cell::I32(1),
+            Cell::U32(1),
+            Cell::I64(1),
+            Cell::F32(1.5),
+            Cell::F64(1.5),
+            Cell::Numeric("1.5".parse().unwrap()),
+            Cell::Date(date),
+            Cell::Time(time),
+            Cell::TimeStamp(date.and_time(time)),
+            Cell::TimeStampTz(DateTime::from_timestamp(1_704_164_645, 0).unwrap()),
+            Cell::Uuid(Uuid::nil()),
+            Cell::Json(serde_json::json!({"a": 1})),
+            Cell::Bytes(vec![1]),
+            Cell::Array(ArrayCell::I32(vec![Some(1), None])),
+            Cell::Xml("<a/>".to_string()),
+            Cell::Geometry(Geometry::Point(Point { x: 1.0, y: 2.0 })),
+            Cell::Ewkb(vec![1]),
+        ]
+    }
+
+    #[test]
+    fn cells_equal_only_cells_of_the_same_variant_and_value() {
+        let cells = cells();
+        for (i, a) in cells.iter().enumerate() {
+            for (j, b) in cells.iter().enumerate() {
+                assert_eq!(a == b, i == j, "{a:?} == {b:?}");
+            }
+            assert_eq!(a, &a.clone());
+        }
+    }
+
+    #[test]
+    fn cells_of_the_same_variant_with_different_values_differ() {
+        assert_ne!(Cell::I32(1), Cell::I32(2));
+        assert_ne!(Cell::String("a".to_string()), Cell::String("b".to_string()));
+        assert_ne!(Cell::Bytes(vec![1]), Cell::Bytes(vec![2]));
+        assert_ne!(
+            Cell::Json(serde_json::json!(1)),
+            Cell::Json(serde_json::json!("1"))
+        );
+    }
+
+    #[test]
+    fn nan_floats_equal_each_other() {
+        assert_eq!(Cell::F32(f32::NAN), Cell::F32(f32::NAN));
+        assert_eq!(Cell::F64(f64::NAN), Cell::F64(f64::NAN));
+        assert_eq!(Cell::F64(0.0), Cell::F64(-0.0));
+        assert_ne!(Cell::F64(f64::NAN), Cell::F64(1.0));
+        assert_ne!(Cell::F64(f64::NAN), Cell::F32(f32::NAN));
+        assert_eq!(
+            Cell::Numeric("NaN".parse().unwrap()),
+            Cell::Numeric("NaN".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn arrays_are_compared_element_by_element() {
+        let array = ArrayCell::F64(vec![Some(1.0), None, Some(f64::NAN)]);
+        assert_eq!(array, array.clone());
+        assert_ne!(array, ArrayCell::F64(vec![Some(1.0), None]));
+        assert_ne!(
+            array,
+            ArrayCell::F64(vec![Some(1.0), Some(0.0), Some(f64::NAN)])
+        );
+        assert_eq!(
+            ArrayCell::String(vec![Some("a".to_string()), None]),
+            ArrayCell::String(vec![Some("a".to_string()), None])
+        );
+        assert_eq!(ArrayCell::I32(vec![]), ArrayCell::I32(vec![]));
+        assert_ne!(ArrayCell::I32(vec![]), ArrayCell::I64(vec![]));
+        assert_eq!(ArrayCell::Null, ArrayCell::Null);
+        assert_ne!(ArrayCell::Null, ArrayCell::I32(vec![]));
+    }
+}
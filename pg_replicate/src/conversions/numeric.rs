@@ -8,7 +8,7 @@ use byteorder::{BigEndian, ReadBytesExt};
 use derive_more::TryInto;
 #[cfg(feature = "rust_decimal")]
 use rust_decimal::Decimal;
-use std::{fmt::Display, io::Cursor, str::FromStr};
+use std::{fmt::Display, io::Cursor, num::ParseFloatError, str::FromStr};
 use tokio_postgres::types::{FromSql, Type};
 
 /// A rust variant of the Postgres Numeric type. The full spectrum of Postgres'
@@ -154,11 +154,11 @@ impl<'a> FromSql<'a> for PgNumeric {
 
         #[cfg(not(any(feature = "bigdecimal", feature = "rust_decimal")))]
         let res: String = {
-            let _n_digits = n_digits;
-            let _weight = weight;
-            let _scale = scale;
-            let _sign = sign;
-            "TODO".to_string() // TODO: format the digits directly
+            let mut digits = Vec::with_capacity(n_digits as usize);
+            for _ in 0..n_digits {
+                digits.push(rdr.read_u16::<BigEndian>()?);
+            }
+            format_postgres_digits(sign == PgSign::Minus, weight, scale, &digits)
         };
 
         Ok(PgNumeric::Value(res))
@@ -169,6 +169,58 @@ impl<'a> FromSql<'a> for PgNumeric {
     }
 }
 
+impl PgNumeric {
+    /// Returns the number of digits after the decimal point, `None` for `NaN`
+    /// and the infinities. Numerics keep the scale they were read with, so
+    /// that `1.50` has a scale of 2 and is printed as `1.50`, not `1.5`.
+    pub fn scale(&self) -> Option<u32> {
+        match self {
+            PgNumeric::NaN | PgNumeric::PositiveInf | PgNumeric::NegativeInf => None,
+            #[cfg(feature = "bigdecimal")]
+            PgNumeric::Value(n) => Some(n.as_bigint_and_exponent().1.max(0) as u32),
+            #[cfg(feature = "rust_decimal")]
+            PgNumeric::Value(n) => Some(n.scale()),
+            #[cfg(not(any(feature = "bigdecimal", feature = "rust_decimal")))]
+            PgNumeric::Value(n) => Some(n.split_once('.').map_or(0, |(_, f)| f.len() as u32)),
+        }
+    }
+
+    /// Returns the number of digits before the decimal point, without leading
+    /// zeros, `None` for `NaN` and the infinities. Together with
+    /// [`PgNumeric::scale`] it tells whether a value fits a type of a limited
+    /// precision and scale.
+    pub fn integer_digits(&self) -> Option<u32> {
+        match self {
+            PgNumeric::NaN | PgNumeric::PositiveInf | PgNumeric::NegativeInf => None,
+            #[cfg(feature = "bigdecimal")]
+            PgNumeric::Value(n) => {
+                let (bigint, exponent) = n.as_bigint_and_exponent();
+                let digits = match bigint.sign() {
+                    Sign::NoSign => 0,
+                    Sign::Minus | Sign::Plus => n.digits() as i64,
+                };
+                Some((digits - exponent).max(0) as u32)
+            }
+            #[cfg(feature = "rust_decimal")]
+            PgNumeric::Value(n) => {
+                let mantissa = n.mantissa().unsigned_abs();
+                let digits = if mantissa == 0 {
+                    0
+                } else {
+                    mantissa.ilog10() + 1
+                };
+                Some(digits.saturating_sub(n.scale()))
+            }
+            #[cfg(not(any(feature = "bigdecimal", feature = "rust_decimal")))]
+            PgNumeric::Value(n) => {
+                let integer = n.trim_start_matches('-');
+                let integer = integer.split_once('.').map_or(integer, |(i, _)| i);
+                Some(integer.trim_start_matches('0').len() as u32)
+            }
+        }
+    }
+}
+
 impl Display for PgNumeric {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -192,6 +244,71 @@ impl Default for PgNumeric {
     }
 }
 
+/// Converts to the nearest double, which is lossy: digits beyond the 15 to 17
+/// significant ones a double holds are rounded away and values beyond its
+/// range become infinite. `NaN` and the infinities convert to their double
+/// counterparts.
+impl TryFrom<&PgNumeric> for f64 {
+    type Error = ParseFloatError;
+
+    fn try_from(numeric: &PgNumeric) -> Result<Self, Self::Error> {
+        match numeric {
+            PgNumeric::NaN => Ok(f64::NAN),
+            PgNumeric::PositiveInf => Ok(f64::INFINITY),
+            PgNumeric::NegativeInf => Ok(f64::NEG_INFINITY),
+            // The string form is the same whichever decimal feature is enabled
+            PgNumeric::Value(n) => n.to_string().parse(),
+        }
+    }
+}
+
+/// Lossy, see the `TryFrom<&PgNumeric>` impl
+impl TryFrom<PgNumeric> for f64 {
+    type Error = ParseFloatError;
+
+    fn try_from(numeric: PgNumeric) -> Result<Self, Self::Error> {
+        f64::try_from(&numeric)
+    }
+}
+
+/// Prints the base 10000 `digits` of a numeric the way Postgres does, with
+/// exactly `scale` digits after the decimal point
+#[cfg(not(any(feature = "bigdecimal", feature = "rust_decimal")))]
+fn format_postgres_digits(neg: bool, weight: i16, scale: u16, digits: &[u16]) -> String {
+    // digits before the first and after the last one sent are zeros
+    let digit = |i: i32| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i).copied())
+            .unwrap_or(0)
+    };
+
+    let mut res = String::new();
+    if neg {
+        res.push('-');
+    }
+    if weight < 0 {
+        res.push('0');
+    } else {
+        res.push_str(&digit(0).to_string());
+        for i in 1..=weight as i32 {
+            res.push_str(&format!("{:04}", digit(i)));
+        }
+    }
+    if scale > 0 {
+        let mut fraction = String::with_capacity(scale as usize + 3);
+        let mut i = weight as i32 + 1;
+        while fraction.len() < scale as usize {
+            fraction.push_str(&format!("{:04}", digit(i)));
+            i += 1;
+        }
+        fraction.truncate(scale as usize);
+        res.push('.');
+        res.push_str(&fraction);
+    }
+    res
+}
+
 #[cfg(feature = "rust_decimal")]
 fn checked_from_postgres(
     neg: bool,
@@ -254,3 +371,92 @@ fn checked_from_postgres(
     result.rescale((scale as u32).min(MAX_SCALE));
     Some(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::{FromSql, Type};
+
+    use super::PgNumeric;
+
+    /// The binary representation of a numeric with base 10000 `digits`
+    fn binary(weight: i16, negative: bool, scale: u16, digits: &[u16]) -> Vec<u8> {
+        let sign: u16 = if negative { 0x4000 } else { 0x0000 };
+        let mut raw = vec![];
+        for value in [digits.len() as u16, weight as u16, sign, scale]
+            .into_iter()
+            .chain(digits.iter().copied())
+        {
+            raw.extend_from_slice(&value.to_be_bytes());
+        }
+        raw
+    }
+
+    #[test]
+    fn parsed_numeric_keeps_its_trailing_zeros() {
+        let numeric: PgNumeric = "1.50".parse().unwrap();
+
+        assert_eq!(numeric.to_string(), "1.50");
+        assert_eq!(numeric.scale(), Some(2));
+    }
+
+    #[test]
+    fn binary_numeric_keeps_its_scale() {
+        let cases = [
+            (binary(0, false, 2, &[1, 5000]), "1.50"),
+            (binary(0, false, 4, &[1, 5000]), "1.5000"),
+            (binary(0, true, 2, &[12, 3400]), "-12.34"),
+            (binary(1, false, 2, &[1]), "10000.00"),
+            (binary(-2, false, 8, &[1200]), "0.00001200"),
+            (binary(0, false, 0, &[]), "0"),
+        ];
+
+        for (raw, expected) in cases {
+            let numeric = PgNumeric::from_sql(&Type::NUMERIC, &raw).unwrap();
+            assert_eq!(numeric.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn integer_digits_dont_count_leading_zeros() {
+        let cases = [
+            ("12345.678", 5),
+            ("-12.5", 2),
+            ("0.001", 0),
+            ("0", 0),
+            ("100", 3),
+        ];
+
+        for (numeric, expected) in cases {
+            let numeric: PgNumeric = numeric.parse().unwrap();
+            assert_eq!(numeric.integer_digits(), Some(expected), "{numeric}");
+        }
+    }
+
+    #[test]
+    fn special_numerics_have_no_scale() {
+        for numeric in [
+            PgNumeric::NaN,
+            PgNumeric::PositiveInf,
+            PgNumeric::NegativeInf,
+        ] {
+            assert_eq!(numeric.scale(), None);
+            assert_eq!(numeric.integer_digits(), None);
+        }
+    }
+
+    #[test]
+    fn numeric_beyond_double_precision_is_rounded_to_the_nearest_double() {
+        // the shortest strings the nearest doubles print as
+        let cases = [
+            ("12345678901234567890.123456789", "12345678901234567000"),
+            ("0.12345678901234567890123", "0.12345678901234568"),
+            ("-9007199254740993", "-9007199254740992"),
+        ];
+
+        for (numeric, expected) in cases {
+            let numeric: PgNumeric = numeric.parse().unwrap();
+            let double = f64::try_from(&numeric).unwrap();
+            assert_eq!(double.to_string(), expected, "{numeric}");
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use pg_escape::quote_literal;
+
+use super::{hex, numeric::PgNumeric, ArrayCell, Cell};
+
+impl Cell {
+    /// Converts the cell to a literal which can be used in a SQL statement in
+    /// place of a value of the cell's column:
+    ///
+    /// * NULL is `NULL`, booleans `true` and `false`
+    /// * integers, finite floats and finite numerics are unquoted numbers.
+    ///   NaN and infinite floats and numerics are the quoted strings Postgres
+    ///   parses them from, `'NaN'`, `'Infinity'` and `'-Infinity'`
+    /// * all other values are quoted strings of their text representation,
+    ///   which Postgres converts to the column's type. Strings with a
+    ///   backslash are escape strings, `E'...'`, so that they are read the
+    ///   same whatever `standard_conforming_strings` is set to
+    /// * bytea is in the hex format, `E'\\x...'`, and EWKB is uppercase hex
+    ///   digits, which PostGIS parses geometries from
+    /// * arrays are quoted array literals, `'{...}'`, whose elements are
+    ///   double quoted, or `NULL`
+    pub fn to_sql_literal(&self) -> String {
+        match self {
+            Cell::Null => "NULL".to_string(),
+            Cell::Bool(b) => b.to_string(),
+            Cell::I16(i) => i.to_string(),
+            Cell::I32(i) => i.to_string(),
+            Cell::U32(u) => u.to_string(),
+            Cell::I64(i) => i.to_string(),
+            Cell::F32(f) => float_literal(*f as f64),
+            Cell::F64(f) => float_literal(*f),
+            Cell::Numeric(n @ PgNumeric::Value(_)) => n.to_string(),
+            Cell::Numeric(n) => quote_literal(&n.to_string()),
+            Cell::Array(array) => array.to_sql_literal(),
+            _ => quote_literal(&cell_text(self)),
+        }
+    }
+}
+
+impl ArrayCell {
+    /// Converts the array to a quoted array literal, see
+    /// [`Cell::to_sql_literal`]
+    pub fn to_sql_literal(&self) -> String {
+        let array = match self {
+            ArrayCell::Null => return "NULL".to_string(),
+            ArrayCell::Bool(v) => array_text(v, |b| b.to_string()),
+            ArrayCell::String(v) => array_text(v, |s| s.clone()),
+            ArrayCell::I16(v) => array_text(v, |i| i.to_string()),
+            ArrayCell::I32(v) => array_text(v, |i| i.to_string()),
+            ArrayCell::U32(v) => array_text(v, |u| u.to_string()),
+            ArrayCell::I64(v) => array_text(v, |i| i.to_string()),
+            ArrayCell::F32(v) => array_text(v, |f| float_text(*f as f64)),
+            ArrayCell::F64(v) => array_text(v, |f| float_text(*f)),
+            ArrayCell::Numeric(v) => array_text(v, |n| n.to_string()),
+            ArrayCell::Date(v) => array_text(v, |d| cell_text(&Cell::Date(*d))),
+            ArrayCell::Time(v) => array_text(v, |t| cell_text(&Cell::Time(*t))),
+            ArrayCell::TimeStamp(v) => array_text(v, |t| cell_text(&Cell::TimeStamp(*t))),
+            ArrayCell::TimeStampTz(v) => array_text(v, |t| cell_text(&Cell::TimeStampTz(*t))),
+            ArrayCell::Uuid(v) => array_text(v, |u| u.to_string()),
+            ArrayCell::Json(v) => array_text(v, |j| j.to_string()),
+            ArrayCell::Bytes(v) => array_text(v, |b| hex::to_bytea_hex(b)),
+        };
+        quote_literal(&array)
+    }
+}
+
+/// The text Postgres parses a value of the cell's type from
+fn cell_text(cell: &Cell) -> String {
+    match cell {
+        Cell::Null => String::new(),
+        Cell::Bool(b) => b.to_string(),
+        Cell::String(s) | Cell::Xml(s) => s.clone(),
+        Cell::I16(i) => i.to_string(),
+        Cell::I32(i) => i.to_string(),
+        Cell::U32(u) => u.to_string(),
+        Cell::I64(i) => i.to_string(),
+        Cell::F32(f) => float_text(*f as f64),
+        Cell::F64(f) => float_text(*f),
+        Cell::Numeric(n) => n.to_string(),
+        Cell::Date(d) => d.format("%Y-%m-%d").to_string(),
+        Cell::Time(t) => t.format("%H:%M:%S%.f").to_string(),
+        Cell::TimeStamp(t) => t.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+        Cell::TimeStampTz(t) => t.to_rfc3339(),
+        Cell::Uuid(u) => u.to_string(),
+        Cell::Json(j) => j.to_string(),
+        Cell::Bytes(b) => hex::to_bytea_hex(b),
+        Cell::Geometry(g) => g.to_string(),
+        Cell::Ewkb(b) => hex::to_hex(b),
+        Cell::Array(_) => unreachable!("arrays are converted by ArrayCell::to_sql_literal"),
+    }
+}
+
+fn float_text(f: f64) -> String {
+    if f.is_nan() {
+        "NaN".to_string()
+    } else if f.is_infinite() {
+        if f > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        f.to_string()
+    }
+}
+
+fn float_literal(f: f64) -> String {
+    if f.is_finite() {
+        f.to_string()
+    } else {
+        quote_literal(&float_text(f))
+    }
+}
+
+/// The text of an array of `values`, each converted to text with `f`. Elements
+/// are double quoted, with their double quotes and backslashes escaped, so
+/// that their text is never parsed as array syntax.
+fn array_text<T>(values: &[Option<T>], f: impl Fn(&T) -> String) -> String {
+    let elements: Vec<String> = values
+        .iter()
+        .map(|value| match value {
+            Some(value) => {
+                let text = f(value).replace('\\', "\\\\").replace('"', "\\\"");
+                format!("\"{text}\"")
+            }
+            None => "NULL".to_string(),
+        })
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::conversions::{
+        geometry::{Geometry, Point},
+        numeric::PgNumeric,
+        ArrayCell, Cell,
+    };
+
+    #[test]
+    fn every_cell_is_converted_to_a_sql_literal() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let time = NaiveTime::from_hms_micro_opt(10, 30, 0, 500).unwrap();
+        let timestamp_tz = date.and_hms_opt(10, 30, 0).unwrap().and_utc();
+        let uuid = Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+        let cases = vec![
+            (Cell::Null, "NULL"),
+            (Cell::Bool(true), "true"),
+            (Cell::String("it's".to_string()), "'it''s'"),
+            (Cell::String(r"C:\temp".to_string()), r"E'C:\\temp'"),
+            (Cell::I16(-1), "-1"),
+            (Cell::I32(2), "2"),
+            (Cell::U32(3), "3"),
+            (Cell::I64(4), "4"),
+            (Cell::F32(1.5), "1.5"),
+            (Cell::F64(f64::NAN), "'NaN'"),
+            (Cell::F64(f64::NEG_INFINITY), "'-Infinity'"),
+            (Cell::Numeric("12.50".parse().unwrap()), "12.50"),
+            (Cell::Numeric(PgNumeric::PositiveInf), "'Infinity'"),
+            (Cell::Date(date), "'2024-01-15'"),
+            (Cell::Time(time), "'10:30:00.000500'"),
+            (
+                Cell::TimeStamp(date.and_time(time)),
+                "'2024-01-15 10:30:00.000500'",
+            ),
+            (
+                Cell::TimeStampTz(timestamp_tz),
+                "'2024-01-15T10:30:00+00:00'",
+            ),
+            (Cell::Uuid(uuid), "'a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11'"),
+            (
+                Cell::Json(json!({"name": "o'neil"})),
+                r#"'{"name":"o''neil"}'"#,
+            ),
+            (Cell::Bytes(vec![0xde, 0xad]), r"E'\\xdead'"),
+            (Cell::Xml("<a/>".to_string()), "'<a/>'"),
+            (
+                Cell::Geometry(Geometry::Point(Point { x: 1.0, y: 2.0 })),
+                "'(1,2)'",
+            ),
+            (Cell::Ewkb(vec![0x01, 0xab]), "'01AB'"),
+            (Cell::Array(ArrayCell::Null), "NULL"),
+            (
+                Cell::Array(ArrayCell::I32(vec![Some(1), None])),
+                r#"'{"1",NULL}'"#,
+            ),
+        ];
+
+        for (cell, expected) in cases {
+            assert_eq!(cell.to_sql_literal(), expected, "{cell:?}");
+        }
+    }
+
+    #[test]
+    fn array_elements_with_quotes_and_backslashes_are_escaped() {
+        let array = ArrayCell::String(vec![
+            Some(r#"say "hi""#.to_string()),
+            Some("it's".to_string()),
+            Some(r"a\b".to_string()),
+        ]);
+
+        assert_eq!(
+            array.to_sql_literal(),
+            r#"E'{"say \\"hi\\"","it''s","a\\\\b"}'"#
+        );
+    }
+
+    #[test]
+    fn bytea_array_elements_are_in_the_hex_format() {
+        let array = ArrayCell::Bytes(vec![Some(vec![0x01]), None]);
+
+        assert_eq!(array.to_sql_literal(), r#"E'{"\\\\x01",NULL}'"#);
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use postgres_replication::protocol::LogicalReplicationMessage;
+
+use super::cdc_event::{BeginBody, CdcEventConversionError, CommitBody};
+
+const STREAM_START_TAG: u8 = b'S';
+const STREAM_STOP_TAG: u8 = b'E';
+const STREAM_COMMIT_TAG: u8 = b'c';
+const STREAM_ABORT_TAG: u8 = b'A';
+const SEQUENCE_TAG: u8 = b'Q';
+const LOGICAL_MESSAGE_TAG: u8 = b'M';
+
+/// A message of a transaction, which is either a message of a transaction
+/// pgoutput sent on commit or a message of a streamed transaction replayed on
+/// its Stream Commit
+#[derive(Debug)]
+pub enum TransactionMessage {
+    Begin(BeginBody),
+    Message(LogicalReplicationMessage),
+    /// A change of a sequence's state, see [`StreamedTransactions::receive`]
+    Sequence {
+        schema: String,
+        name: String,
+        last_value: i64,
+        log_cnt: i64,
+        is_called: bool,
+    },
+    /// A message written to the WAL with `pg_logical_emit_message`, see
+    /// [`StreamedTransactions::receive`]
+    LogicalMessage {
+        transactional: bool,
+        prefix: String,
+        content: Vec<u8>,
+    },
+    Commit(CommitBody),
+}
+
+/// Buffers the messages of transactions which pgoutput streams before they
+/// commit, with protocol version 2 and `streaming` on. A streamed transaction
+/// is sent in segments between Stream Start and Stream Stop, interleaved with
+/// other transactions, and ends with a Stream Commit or a Stream Abort. Its
+/// messages are replayed as a regular transaction on its Stream Commit and
+/// discarded on its Stream Abort, so they are applied like those of any other
+/// transaction.
+#[derive(Debug, Default)]
+pub struct StreamedTransactions {
+    /// xid of the transaction whose segment is being streamed
+    streaming_xid: Option<u32>,
+    /// Messages of streamed transactions by their top-level xid, each with
+    /// the xid of the (sub)transaction it belongs to
+    messages: HashMap<u32, Vec<(u32, TransactionMessage)>>,
+}
+
+impl StreamedTransactions {
+    pub fn new() -> StreamedTransactions {
+        StreamedTransactions::default()
+    }
+
+    /// Decodes a pgoutput message, returning the messages to apply now. A
+    /// message of a streamed transaction is buffered and nothing is returned
+    /// until the transaction commits.
+    ///
+    /// Besides the messages `postgres_replication` parses, Sequence messages
+    /// are decoded, which pgoutput sends for the sequences of a publication
+    /// on servers supporting the logical decoding of sequences. Released
+    /// Postgres versions don't, so sources running them never send any.
+    /// Logical decoding messages are decoded as well, which pgoutput sends
+    /// with `messages` on. A non-transactional one is returned right away
+    /// even when sent within a streamed transaction, as it isn't part of it.
+    pub fn receive(
+        &mut self,
+        data: &Bytes,
+    ) -> Result<Vec<TransactionMessage>, CdcEventConversionError> {
+        let mut buf = data.clone();
+        if !buf.has_remaining() {
+            return Err(Self::invalid_message("empty message"));
+        }
+        let tag = buf.get_u8();
+        match tag {
+            STREAM_START_TAG => {
+                let xid = Self::get_u32(&mut buf)?;
+                self.streaming_xid = Some(xid);
+                self.messages.entry(xid).or_default();
+                Ok(vec![])
+            }
+            STREAM_STOP_TAG => {
+                self.streaming_xid = None;
+                Ok(vec![])
+            }
+            STREAM_COMMIT_TAG => {
+                let xid = Self::get_u32(&mut buf)?;
+                let _flags = Self::get_u8(&mut buf)?;
+                let commit_lsn = Self::get_u64(&mut buf)?;
+                let end_lsn = Self::get_u64(&mut buf)?;
+                let timestamp = Self::get_u64(&mut buf)? as i64;
+                let messages = self.messages.remove(&xid).unwrap_or_default();
+                let mut transaction = Vec::with_capacity(messages.len() + 2);
+                transaction.push(TransactionMessage::Begin(BeginBody::new(
+                    commit_lsn, timestamp, xid,
+                )));
+                transaction.extend(messages.into_iter().map(|(_, message)| message));
+                transaction.push(TransactionMessage::Commit(CommitBody::new(
+                    commit_lsn, end_lsn, timestamp,
+                )));
+                Ok(transaction)
+            }
+            STREAM_ABORT_TAG => {
+                let xid = Self::get_u32(&mut buf)?;
+                let subxid = Self::get_u32(&mut buf)?;
+                if xid == subxid {
+                    self.messages.remove(&xid);
+                } else if let Some(messages) = self.messages.get_mut(&xid) {
+                    messages.retain(|(message_xid, _)| *message_xid != subxid);
+                }
+                Ok(vec![])
+            }
+            _ => match self.streaming_xid {
+                Some(streaming_xid) => {
+                    // Messages of a streamed transaction carry the xid of
+                    // their (sub)transaction after the tag, which the
+                    // messages of protocol version 1 don't
+                    let xid = Self::get_u32(&mut buf)?;
+                    let message = if tag == SEQUENCE_TAG {
+                        Self::parse_sequence(&mut buf)?
+                    } else if tag == LOGICAL_MESSAGE_TAG {
+                        match Self::parse_logical_message(&mut buf)? {
+                            message @ TransactionMessage::LogicalMessage {
+                                transactional: false,
+                                ..
+                            } => return Ok(vec![message]),
+                            message => message,
+                        }
+                    } else {
+                        let mut message = BytesMut::with_capacity(buf.len() + 1);
+                        message.put_u8(tag);
+                        message.put(buf);
+                        TransactionMessage::Message(Self::parse(&message.freeze())?)
+                    };
+                    self.messages
+                        .entry(streaming_xid)
+                        .or_default()
+                        .push((xid, message));
+                    Ok(vec![])
+                }
+                None if tag == SEQUENCE_TAG => Ok(vec![Self::parse_sequence(&mut buf)?]),
+                None if tag == LOGICAL_MESSAGE_TAG => {
+                    Ok(vec![Self::parse_logical_message(&mut buf)?])
+                }
+                None => Ok(vec![TransactionMessage::Message(Self::parse(data)?)]),
+            },
+        }
+    }
+
+    /// Returns true if no streamed transaction is buffered
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn parse(data: &Bytes) -> Result<LogicalReplicationMessage, CdcEventConversionError> {
+        LogicalReplicationMessage::parse(data).map_err(CdcEventConversionError::InvalidPgoutput)
+    }
+
+    /// Parses the body of a Sequence message, after its tag and xid
+    fn parse_sequence(buf: &mut Bytes) -> Result<TransactionMessage, CdcEventConversionError> {
+        let _flags = Self::get_u8(buf)?;
+        let _lsn = Self::get_u64(buf)?;
+        // pgoutput sends an empty namespace for pg_catalog
+        let schema = match Self::get_string(buf)? {
+            schema if schema.is_empty() => "pg_catalog".to_string(),
+            schema => schema,
+        };
+        let name = Self::get_string(buf)?;
+        let _transactional = Self::get_u8(buf)?;
+        let last_value = Self::get_u64(buf)? as i64;
+        let log_cnt = Self::get_u64(buf)? as i64;
+        let is_called = Self::get_u8(buf)? == 1;
+        Ok(TransactionMessage::Sequence {
+            schema,
+            name,
+            last_value,
+            log_cnt,
+            is_called,
+        })
+    }
+
+    /// Parses the body of a logical decoding Message, after its tag and xid
+    fn parse_logical_message(
+        buf: &mut Bytes,
+    ) -> Result<TransactionMessage, CdcEventConversionError> {
+        let transactional = Self::get_u8(buf)? == 1;
+        let _lsn = Self::get_u64(buf)?;
+        let prefix = Self::get_string(buf)?;
+        let len = Self::get_u32(buf)? as usize;
+        if buf.remaining() < len {
+            return Err(Self::invalid_message("message too short"));
+        }
+        let content = buf.split_to(len).to_vec();
+        Ok(TransactionMessage::LogicalMessage {
+            transactional,
+            prefix,
+            content,
+        })
+    }
+
+    fn get_string(buf: &mut Bytes) -> Result<String, CdcEventConversionError> {
+        let Some(end) = buf.iter().position(|b| *b == 0) else {
+            return Err(Self::invalid_message("unterminated string"));
+        };
+        let string = buf.split_to(end);
+        buf.advance(1);
+        String::from_utf8(string.to_vec())
+            .map_err(|_| Self::invalid_message("invalid utf-8 in string"))
+    }
+
+    fn get_u8(buf: &mut Bytes) -> Result<u8, CdcEventConversionError> {
+        if buf.remaining() < 1 {
+            return Err(Self::invalid_message("message too short"));
+        }
+        Ok(buf.get_u8())
+    }
+
+    fn get_u32(buf: &mut Bytes) -> Result<u32, CdcEventConversionError> {
+        if buf.remaining() < 4 {
+            return Err(Self::invalid_message("message too short"));
+        }
+        Ok(buf.get_u32())
+    }
+
+    fn get_u64(buf: &mut Bytes) -> Result<u64, CdcEventConversionError> {
+        if buf.remaining() < 8 {
+            return Err(Self::invalid_message("message too short"));
+        }
+        Ok(buf.get_u64())
+    }
+
+    fn invalid_message(reason: &'static str) -> CdcEventConversionError {
+        CdcEventConversionError::InvalidPgoutput(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            reason,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, Bytes, BytesMut};
+    use postgres_replication::protocol::LogicalReplicationMessage;
+
+    use super::{StreamedTransactions, TransactionMessage};
+
+    const TABLE_ID: u32 = 16385;
+
+    fn stream_start(xid: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'S');
+        buf.put_u32(xid);
+        buf.put_u8(1);
+        buf.freeze()
+    }
+
+    fn stream_stop() -> Bytes {
+        Bytes::from_static(b"E")
+    }
+
+    fn stream_commit(xid: u32, commit_lsn: u64, end_lsn: u64) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'c');
+        buf.put_u32(xid);
+        buf.put_u8(0);
+        buf.put_u64(commit_lsn);
+        buf.put_u64(end_lsn);
+        buf.put_u64(1_000_000);
+        buf.freeze()
+    }
+
+    fn stream_abort(xid: u32, subxid: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'A');
+        buf.put_u32(xid);
+        buf.put_u32(subxid);
+        buf.freeze()
+    }
+
+    /// A Relation message for a table with a single int4 key column `id`
+    fn relation(xid: Option<u32>) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'R');
+        if let Some(xid) = xid {
+            buf.put_u32(xid);
+        }
+        buf.put_u32(TABLE_ID);
+        buf.put_slice(b"public\0");
+        buf.put_slice(b"items\0");
+        buf.put_u8(b'd');
+        buf.put_u16(1);
+        buf.put_u8(1);
+        buf.put_slice(b"id\0");
+        buf.put_u32(23);
+        buf.put_i32(-1);
+        buf.freeze()
+    }
+
+    fn insert(xid: Option<u32>, id: &str) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'I');
+        if let Some(xid) = xid {
+            buf.put_u32(xid);
+        }
+        buf.put_u32(TABLE_ID);
+        buf.put_u8(b'N');
+        buf.put_u16(1);
+        buf.put_u8(b't');
+        buf.put_u32(id.len() as u32);
+        buf.put_slice(id.as_bytes());
+        buf.freeze()
+    }
+
+    fn sequence(xid: Option<u32>, schema: &str, last_value: i64) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'Q');
+        if let Some(xid) = xid {
+            buf.put_u32(xid);
+        }
+        buf.put_u8(0);
+        buf.put_u64(0x100);
+        buf.put_slice(schema.as_bytes());
+        buf.put_u8(0);
+        buf.put_slice(b"items_id_seq\0");
+        buf.put_u8(xid.is_some() as u8);
+        buf.put_i64(last_value);
+        buf.put_i64(32);
+        buf.put_u8(1);
+        buf.freeze()
+    }
+
+    fn logical_message(xid: Option<u32>, transactional: bool, content: &[u8]) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'M');
+        if let Some(xid) = xid {
+            buf.put_u32(xid);
+        }
+        buf.put_u8(transactional as u8);
+        buf.put_u64(0x100);
+        buf.put_slice(b"checkpoint\0");
+        buf.put_u32(content.len() as u32);
+        buf.put_slice(content);
+        buf.freeze()
+    }
+
+    fn receive(transactions: &mut StreamedTransactions, data: Bytes) -> Vec<TransactionMessage> {
+        transactions.receive(&data).expect("failed to receive")
+    }
+
+    /// Describes messages as their tags followed by the xid of a begin
+    fn describe(messages: &[TransactionMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match message {
+                TransactionMessage::Begin(begin_body) => format!("B{}", begin_body.xid()),
+                TransactionMessage::Message(LogicalReplicationMessage::Relation(_)) => {
+                    "R".to_string()
+                }
+                TransactionMessage::Message(LogicalReplicationMessage::Insert(insert_body)) => {
+                    assert_eq!(insert_body.rel_id(), TABLE_ID);
+                    "I".to_string()
+                }
+                TransactionMessage::Message(message) => format!("{message:?}"),
+                TransactionMessage::Sequence { last_value, .. } => format!("Q{last_value}"),
+                TransactionMessage::LogicalMessage { content, .. } => {
+                    format!("M{}", String::from_utf8_lossy(content))
+                }
+                TransactionMessage::Commit(_) => "C".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn messages_outside_of_a_stream_are_returned_immediately() {
+        let mut transactions = StreamedTransactions::new();
+
+        let messages = receive(&mut transactions, insert(None, "1"));
+
+        assert_eq!(describe(&messages), vec!["I"]);
+        assert!(transactions.is_empty());
+    }
+
+    #[test]
+    fn streamed_transaction_is_replayed_on_commit() {
+        let mut transactions = StreamedTransactions::new();
+
+        assert!(receive(&mut transactions, stream_start(700)).is_empty());
+        assert!(receive(&mut transactions, relation(Some(700))).is_empty());
+        assert!(receive(&mut transactions, insert(Some(700), "1")).is_empty());
+        assert!(receive(&mut transactions, stream_stop()).is_empty());
+        // a transaction committing between segments is applied immediately
+        let messages = receive(&mut transactions, insert(None, "2"));
+        assert_eq!(describe(&messages), vec!["I"]);
+        assert!(receive(&mut transactions, stream_start(700)).is_empty());
+        assert!(receive(&mut transactions, insert(Some(700), "3")).is_empty());
+        assert!(receive(&mut transactions, stream_stop()).is_empty());
+
+        let messages = receive(&mut transactions, stream_commit(700, 0x100, 0x108));
+
+        assert_eq!(describe(&messages), vec!["B700", "R", "I", "I", "C"]);
+        let TransactionMessage::Begin(begin_body) = &messages[0] else {
+            panic!("streamed transaction doesn't start with a begin");
+        };
+        assert_eq!(begin_body.final_lsn(), 0x100);
+        let TransactionMessage::Commit(commit_body) = &messages[4] else {
+            panic!("streamed transaction doesn't end with a commit");
+        };
+        assert_eq!(commit_body.commit_lsn(), 0x100);
+        assert_eq!(commit_body.end_lsn(), 0x108);
+        assert_eq!(commit_body.timestamp(), 1_000_000);
+        assert!(transactions.is_empty());
+    }
+
+    #[test]
+    fn aborted_streamed_transaction_is_discarded() {
+        let mut transactions = StreamedTransactions::new();
+
+        receive(&mut transactions, stream_start(700));
+        receive(&mut transactions, relation(Some(700)));
+        receive(&mut transactions, insert(Some(700), "1"));
+        receive(&mut transactions, stream_stop());
+
+        assert!(receive(&mut transactions, stream_abort(700, 700)).is_empty());
+        assert!(transactions.is_empty());
+    }
+
+    #[test]
+    fn aborted_subtransaction_of_a_streamed_transaction_is_discarded() {
+        let mut transactions = StreamedTransactions::new();
+
+        receive(&mut transactions, stream_start(700));
+        receive(&mut transactions, insert(Some(700), "1"));
+        receive(&mut transactions, insert(Some(701), "2"));
+        receive(&mut transactions, stream_stop());
+        receive(&mut transactions, stream_abort(700, 701));
+
+        let messages = receive(&mut transactions, stream_commit(700, 0x100, 0x108));
+
+        assert_eq!(describe(&messages), vec!["B700", "I", "C"]);
+    }
+
+    #[test]
+    fn sequence_message_is_decoded() {
+        let mut transactions = StreamedTransactions::new();
+
+        let messages = receive(&mut transactions, sequence(None, "public", 42));
+
+        let [TransactionMessage::Sequence {
+            schema,
+            name,
+            last_value,
+            log_cnt,
+            is_called,
+        }] = messages.as_slice()
+        else {
+            panic!("expected a sequence message, got {messages:?}");
+        };
+        assert_eq!(schema, "public");
+        assert_eq!(name, "items_id_seq");
+        assert_eq!(*last_value, 42);
+        assert_eq!(*log_cnt, 32);
+        assert!(*is_called);
+    }
+
+    #[test]
+    fn sequence_message_of_pg_catalog_has_its_schema() {
+        let mut transactions = StreamedTransactions::new();
+
+        let messages = receive(&mut transactions, sequence(None, "", 1));
+
+        let [TransactionMessage::Sequence { schema, .. }] = messages.as_slice() else {
+            panic!("expected a sequence message, got {messages:?}");
+        };
+        assert_eq!(schema, "pg_catalog");
+    }
+
+    #[test]
+    fn sequence_message_of_a_streamed_transaction_is_replayed_on_commit() {
+        let mut transactions = StreamedTransactions::new();
+
+        receive(&mut transactions, stream_start(700));
+        assert!(receive(&mut transactions, sequence(Some(700), "public", 7)).is_empty());
+        receive(&mut transactions, insert(Some(700), "7"));
+        receive(&mut transactions, stream_stop());
+
+        let messages = receive(&mut transactions, stream_commit(700, 0x100, 0x108));
+
+        assert_eq!(describe(&messages), vec!["B700", "Q7", "I", "C"]);
+    }
+
+    #[test]
+    fn logical_message_is_decoded() {
+        let mut transactions = StreamedTransactions::new();
+
+        let messages = receive(&mut transactions, logical_message(None, true, b"42"));
+
+        let [TransactionMessage::LogicalMessage {
+            transactional,
+            prefix,
+            content,
+        }] = messages.as_slice()
+        else {
+            panic!("expected a logical message, got {messages:?}");
+        };
+        assert!(*transactional);
+        assert_eq!(prefix, "checkpoint");
+        assert_eq!(content, b"42");
+    }
+
+    #[test]
+    fn only_transactional_logical_messages_of_a_streamed_transaction_wait_for_its_commit() {
+        let mut transactions = StreamedTransactions::new();
+
+        receive(&mut transactions, stream_start(700));
+        assert!(receive(&mut transactions, logical_message(Some(700), true, b"1")).is_empty());
+        let messages = receive(&mut transactions, logical_message(Some(700), false, b"2"));
+        assert_eq!(describe(&messages), vec!["M2"]);
+        receive(&mut transactions, stream_stop());
+
+        let messages = receive(&mut transactions, stream_commit(700, 0x100, 0x108));
+
+        assert_eq!(describe(&messages), vec!["B700", "M1", "C"]);
+    }
+
+    #[test]
+    fn truncated_logical_message_is_rejected() {
+        let mut transactions = StreamedTransactions::new();
+        let message = logical_message(None, true, b"42");
+
+        let result = transactions.receive(&message.slice(..message.len() - 1));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn truncated_stream_message_is_rejected() {
+        let mut transactions = StreamedTransactions::new();
+
+        let result = transactions.receive(&Bytes::from_static(b"S\x00\x00"));
+
+        assert!(result.is_err());
+    }
+}
@@ -9,11 +9,26 @@ use crate::{conversions::text::TextFormatConverter, pipeline::batching::BatchBou
 
 use super::{text::FromTextError, Cell};
 
-#[derive(Debug)]
+/// Cloning a row deep copies its strings, byte buffers and arrays. Code which
+/// only passes a row on should move it, or take its cells with
+/// [`TableRow::into_values`].
+#[derive(Debug, Clone, PartialEq)]
 pub struct TableRow {
     pub values: Vec<Cell>,
 }
 
+impl TableRow {
+    pub fn into_values(self) -> Vec<Cell> {
+        self.values
+    }
+}
+
+impl From<Vec<Cell>> for TableRow {
+    fn from(values: Vec<Cell>) -> Self {
+        TableRow { values }
+    }
+}
+
 impl BatchBoundary for TableRow {
     fn is_last_in_batch(&self) -> bool {
         true
@@ -128,6 +143,96 @@ impl TableRowConverter {
             }
         }
 
+        // A row with fewer columns than its schema ends before every column
+        // schema was consumed
+        if values.len() != column_schemas.len() {
+            return Err(TableRowConversionError::NumColsMismatch);
+        }
+
         Ok(TableRow { values })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        conversions::{ArrayCell, Cell},
+        table::ColumnSchema,
+    };
+
+    use super::{TableRow, TableRowConversionError, TableRowConverter};
+
+    fn column_schemas(column_count: usize) -> Vec<ColumnSchema> {
+        (0..column_count)
+            .map(|i| ColumnSchema {
+                name: format!("column_{i}"),
+                typ: Type::INT4,
+                modifier: -1,
+                nullable: true,
+                primary: false,
+                identity: false,
+                domain: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cloned_table_row_equals_the_original() {
+        let row = TableRow::from(vec![
+            Cell::Null,
+            Cell::I64(42),
+            Cell::String("text".to_string()),
+            Cell::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+            Cell::Array(ArrayCell::I32(vec![Some(1), None])),
+        ]);
+
+        let copy = row.clone();
+
+        assert_eq!(copy, row);
+        assert_eq!(copy.into_values(), row.values);
+    }
+
+    #[test]
+    fn row_with_as_many_columns_as_its_schema_is_converted() {
+        let row = TableRowConverter::try_from(b"1\t\\N\t3\n", &column_schemas(3))
+            .expect("failed to convert row");
+
+        assert_eq!(row.values, vec![Cell::I32(1), Cell::Null, Cell::I32(3)]);
+    }
+
+    #[test]
+    fn domain_column_is_converted_as_its_base_type() {
+        // A column of `create domain positive_int as int check (value > 0)`
+        let column_schemas = vec![ColumnSchema {
+            domain: Some("positive_int".to_string()),
+            ..column_schemas(1).remove(0)
+        }];
+
+        let row =
+            TableRowConverter::try_from(b"7\n", &column_schemas).expect("failed to convert row");
+
+        assert_eq!(row.values, vec![Cell::I32(7)]);
+    }
+
+    #[test]
+    fn row_wider_than_its_schema_is_rejected() {
+        let result = TableRowConverter::try_from(b"1\t2\t3\n", &column_schemas(2));
+
+        assert!(matches!(
+            result,
+            Err(TableRowConversionError::NumColsMismatch)
+        ));
+    }
+
+    #[test]
+    fn row_narrower_than_its_schema_is_rejected() {
+        let result = TableRowConverter::try_from(b"1\t2\n", &column_schemas(3));
+
+        assert!(matches!(
+            result,
+            Err(TableRowConversionError::NumColsMismatch)
+        ));
+    }
+}
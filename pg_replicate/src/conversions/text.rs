@@ -9,12 +9,18 @@ use crate::conversions::numeric::ParseNumericInfallible;
 use bigdecimal::ParseBigDecimalError;
 use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use thiserror::Error;
-use tokio_postgres::types::Type;
+use tokio_postgres::types::{Kind, Type};
 use uuid::Uuid;
 
 use crate::conversions::{bool::parse_bool, hex};
 
-use super::{bool::ParseBoolError, hex::ByteaHexParseError, numeric::PgNumeric, ArrayCell, Cell};
+use super::{
+    bool::ParseBoolError,
+    geometry::{Geometry, GeometryParseError},
+    hex::ByteaHexParseError,
+    numeric::PgNumeric,
+    ArrayCell, Cell,
+};
 
 #[derive(Debug, Error)]
 pub enum FromTextError {
@@ -54,8 +60,14 @@ pub enum FromTextError {
     #[error("invalid array: {0}")]
     InvalidArray(#[from] ArrayParseError),
 
+    #[error("invalid geometry: {0}")]
+    InvalidGeometry(#[from] GeometryParseError),
+
     #[error("row get error: {0:?}")]
     RowGetError(#[from] Box<dyn std::error::Error + Sync + Send>),
+
+    #[error("unsupported type: {0}")]
+    UnsupportedType(String),
 }
 
 pub struct TextFormatConverter;
@@ -67,9 +79,34 @@ pub enum ArrayParseError {
 
     #[error("missing braces")]
     MissingBraces,
+
+    #[error("unbalanced braces")]
+    UnbalancedBraces,
+
+    #[error("unterminated quote")]
+    UnterminatedQuote,
 }
 
 impl TextFormatConverter {
+    /// Whether `typ` is a pseudo-type like `void`, `record` or `anyelement`,
+    /// or `unknown`, which views can have columns of but which have no
+    /// meaningful value to replicate
+    fn is_pseudo_type(typ: &Type) -> bool {
+        *typ == Type::UNKNOWN || matches!(typ.kind(), Kind::Pseudo)
+    }
+
+    /// Whether `typ` is PostGIS' `geometry` or `geography`. Extension types
+    /// have no fixed oid so they are recognized by name.
+    fn is_postgis_type(typ: &Type) -> bool {
+        matches!(typ.name(), "geometry" | "geography")
+    }
+
+    /// Whether `typ` is an enum, as resolved by
+    /// [`crate::clients::postgres::ReplicationClient::resolve_type`]
+    fn is_enum_type(typ: &Type) -> bool {
+        matches!(typ.kind(), Kind::Enum(_))
+    }
+
     pub fn default_value(typ: &Type) -> Cell {
         match *typ {
             Type::BOOL => Cell::Bool(bool::default()),
@@ -111,17 +148,24 @@ impl TextFormatConverter {
             Type::UUID_ARRAY => Cell::Array(ArrayCell::Uuid(Vec::default())),
             Type::JSON | Type::JSONB => Cell::Json(serde_json::Value::default()),
             Type::JSON_ARRAY | Type::JSONB_ARRAY => Cell::Array(ArrayCell::Json(Vec::default())),
+            Type::XML => Cell::Xml(String::default()),
             Type::OID => Cell::U32(u32::default()),
             Type::OID_ARRAY => Cell::Array(ArrayCell::U32(Vec::default())),
+            // Geometric values have no natural default
+            _ if Geometry::is_geometric_type(typ) => Cell::Null,
+            _ if Self::is_postgis_type(typ) => Cell::Ewkb(Vec::default()),
+            _ if Self::is_enum_type(typ) => Cell::String(String::default()),
+            _ if Self::is_pseudo_type(typ) => Cell::Null,
             #[cfg(feature = "unknown_types_to_bytes")]
             _ => Cell::String(String::default()),
             #[cfg(not(feature = "unknown_types_to_bytes"))]
-            _ => Err(CdcEventConversionError::UnsupportedType(
-                typ.name().to_string(),
-            )),
+            _ => Cell::Null,
         }
     }
 
+    /// Parses the text representation of a value of type `typ`. Values of
+    /// pseudo-types are rejected with [`FromTextError::UnsupportedType`]
+    /// instead of being passed through as strings.
     pub fn try_from_str(typ: &Type, str: &str) -> Result<Cell, FromTextError> {
         match *typ {
             Type::BOOL => Ok(Cell::Bool(parse_bool(str)?)),
@@ -174,13 +218,10 @@ impl TextFormatConverter {
                 |str| Ok(Some(hex::from_bytea_hex(str)?)),
                 ArrayCell::Bytes,
             ),
-            Type::DATE => {
-                let val = NaiveDate::parse_from_str(str, "%Y-%m-%d")?;
-                Ok(Cell::Date(val))
-            }
+            Type::DATE => Ok(Cell::Date(parse_date(str)?)),
             Type::DATE_ARRAY => TextFormatConverter::parse_array(
                 str,
-                |str| Ok(Some(NaiveDate::parse_from_str(str, "%Y-%m-%d")?)),
+                |str| Ok(Some(parse_date(str)?)),
                 ArrayCell::Date,
             ),
             Type::TIME => {
@@ -192,60 +233,18 @@ impl TextFormatConverter {
                 |str| Ok(Some(NaiveTime::parse_from_str(str, "%H:%M:%S%.f")?)),
                 ArrayCell::Time,
             ),
-            Type::TIMESTAMP => {
-                let val = NaiveDateTime::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f")?;
-                Ok(Cell::TimeStamp(val))
-            }
+            Type::TIMESTAMP => Ok(Cell::TimeStamp(parse_timestamp(str)?)),
             Type::TIMESTAMP_ARRAY => TextFormatConverter::parse_array(
                 str,
-                |str| {
-                    Ok(Some(NaiveDateTime::parse_from_str(
-                        str,
-                        "%Y-%m-%d %H:%M:%S%.f",
-                    )?))
-                },
+                |str| Ok(Some(parse_timestamp(str)?)),
                 ArrayCell::TimeStamp,
             ),
-            Type::TIMESTAMPTZ => {
-                let val =
-                    match DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%#z") {
-                        Ok(val) => val,
-                        Err(_) => {
-                            DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%:z")?
-                        }
-                    };
-                Ok(Cell::TimeStampTz(val.into()))
-            }
-            Type::TIMESTAMPTZ_ARRAY => {
-                match TextFormatConverter::parse_array(
-                    str,
-                    |str| {
-                        Ok(Some(
-                            DateTime::<FixedOffset>::parse_from_str(
-                                str,
-                                "%Y-%m-%d %H:%M:%S%.f%#z",
-                            )?
-                            .into(),
-                        ))
-                    },
-                    ArrayCell::TimeStampTz,
-                ) {
-                    Ok(val) => Ok(val),
-                    Err(_) => TextFormatConverter::parse_array(
-                        str,
-                        |str| {
-                            Ok(Some(
-                                DateTime::<FixedOffset>::parse_from_str(
-                                    str,
-                                    "%Y-%m-%d %H:%M:%S%.f%:z",
-                                )?
-                                .into(),
-                            ))
-                        },
-                        ArrayCell::TimeStampTz,
-                    ),
-                }
-            }
+            Type::TIMESTAMPTZ => Ok(Cell::TimeStampTz(parse_timestamptz(str)?)),
+            Type::TIMESTAMPTZ_ARRAY => TextFormatConverter::parse_array(
+                str,
+                |str| Ok(Some(parse_timestamptz(str)?)),
+                ArrayCell::TimeStampTz,
+            ),
             Type::UUID => {
                 let val = Uuid::parse_str(str)?;
                 Ok(Cell::Uuid(val))
@@ -264,6 +263,8 @@ impl TextFormatConverter {
                 |str| Ok(Some(serde_json::from_str(str)?)),
                 ArrayCell::Json,
             ),
+            // Postgres doesn't validate xml on output so neither do we
+            Type::XML => Ok(Cell::Xml(str.to_string())),
             Type::OID => {
                 let val: u32 = str.parse()?;
                 Ok(Cell::U32(val))
@@ -271,12 +272,18 @@ impl TextFormatConverter {
             Type::OID_ARRAY => {
                 TextFormatConverter::parse_array(str, |str| Ok(Some(str.parse()?)), ArrayCell::U32)
             }
+            _ if Geometry::is_geometric_type(typ) => Ok(Cell::Geometry(Geometry::parse(typ, str)?)),
+            // PostGIS prints values as hex encoded EWKB
+            _ if Self::is_postgis_type(typ) => Ok(Cell::Ewkb(hex::from_hex(str)?)),
+            // Enum values are printed as their label
+            _ if Self::is_enum_type(typ) => Ok(Cell::String(str.to_string())),
+            _ if Self::is_pseudo_type(typ) => {
+                Err(FromTextError::UnsupportedType(typ.name().to_string()))
+            }
             #[cfg(feature = "unknown_types_to_bytes")]
             _ => Ok(Cell::String(str.to_string())),
             #[cfg(not(feature = "unknown_types_to_bytes"))]
-            _ => Err(CdcEventConversionError::UnsupportedType(
-                typ.name().to_string(),
-            )),
+            _ => Err(FromTextError::UnsupportedType(typ.name().to_string())),
         }
     }
 
@@ -285,54 +292,548 @@ impl TextFormatConverter {
         P: FnMut(&str) -> Result<Option<T>, FromTextError>,
         M: FnOnce(Vec<Option<T>>) -> ArrayCell,
     {
+        let mut res = vec![];
+        for element in Self::split_array(str)? {
+            let val = match element {
+                Some(element) => parse(&element)?,
+                None => None,
+            };
+            res.push(val);
+        }
+        Ok(Cell::Array(m(res)))
+    }
+
+    /// Splits an array in Postgres' text format into its elements, `None`
+    /// for NULL ones. Elements containing braces, commas, quotes or
+    /// backslashes, like those of a `jsonb[]`, are quoted by Postgres with
+    /// quotes and backslashes inside them escaped. The elements of
+    /// multidimensional arrays are flattened in row-major order and the
+    /// dimension decoration of arrays not starting at index 1, as in
+    /// `[0:1]={1,2}`, is skipped.
+    fn split_array(str: &str) -> Result<Vec<Option<String>>, ArrayParseError> {
+        let str = match str.strip_prefix('[') {
+            Some(decorated) => match decorated.split_once('=') {
+                Some((_, str)) => str,
+                None => return Err(ArrayParseError::MissingBraces),
+            },
+            None => str,
+        };
+
         if str.len() < 2 {
-            return Err(ArrayParseError::InputTooShort.into());
+            return Err(ArrayParseError::InputTooShort);
         }
 
         if !str.starts_with('{') || !str.ends_with('}') {
-            return Err(ArrayParseError::MissingBraces.into());
+            return Err(ArrayParseError::MissingBraces);
         }
 
         let mut res = vec![];
-        let str = &str[1..(str.len() - 1)];
         let mut val_str = String::with_capacity(10);
         let mut in_quotes = false;
         let mut in_escape = false;
-        let mut chars = str.chars();
-        let mut done = str.is_empty();
-
-        while !done {
-            loop {
-                match chars.next() {
-                    Some(c) => match c {
-                        c if in_escape => {
-                            val_str.push(c);
-                            in_escape = false;
-                        }
-                        '"' => in_quotes = !in_quotes,
-                        '\\' => in_escape = true,
-                        ',' if !in_quotes => {
-                            break;
-                        }
-                        c => {
-                            val_str.push(c);
+        // a quoted element is never null, e.g. the text "NULL" in a text[] or
+        // a jsonb[] element which is always quoted by Postgres
+        let mut val_quoted = false;
+        // whether an element was started since the last delimiter, which is
+        // not the case between the closing and opening braces of the inner
+        // arrays of a multidimensional array
+        let mut in_val = false;
+        let mut depth = 0usize;
+
+        for (i, c) in str.char_indices() {
+            match c {
+                c if in_escape => {
+                    val_str.push(c);
+                    in_escape = false;
+                }
+                '\\' => {
+                    in_escape = true;
+                    in_val = true;
+                }
+                '"' => {
+                    in_quotes = !in_quotes;
+                    val_quoted = true;
+                    in_val = true;
+                }
+                c if in_quotes => val_str.push(c),
+                '{' => {
+                    if in_val {
+                        return Err(ArrayParseError::UnbalancedBraces);
+                    }
+                    depth += 1;
+                }
+                ',' | '}' => {
+                    if in_val {
+                        let val = if !val_quoted && val_str.eq_ignore_ascii_case("null") {
+                            None
+                        } else {
+                            Some(val_str.clone())
+                        };
+                        res.push(val);
+                        val_str.clear();
+                        val_quoted = false;
+                        in_val = false;
+                    }
+                    if c == '}' {
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or(ArrayParseError::UnbalancedBraces)?;
+                        // only the outermost array's closing brace may end it
+                        if depth == 0 && i != str.len() - 1 {
+                            return Err(ArrayParseError::UnbalancedBraces);
                         }
-                    },
-                    None => {
-                        done = true;
-                        break;
                     }
                 }
+                c => {
+                    val_str.push(c);
+                    in_val = true;
+                }
             }
-            let val = if val_str.to_lowercase() == "null" {
+        }
+
+        if in_quotes || in_escape {
+            return Err(ArrayParseError::UnterminatedQuote);
+        }
+
+        if depth != 0 {
+            return Err(ArrayParseError::UnbalancedBraces);
+        }
+
+        Ok(res)
+    }
+}
+
+/// Parses a date, `infinity` and `-infinity` as [`NaiveDate::MAX`] and
+/// [`NaiveDate::MIN`]
+fn parse_date(str: &str) -> Result<NaiveDate, chrono::ParseError> {
+    match str {
+        "infinity" => Ok(NaiveDate::MAX),
+        "-infinity" => Ok(NaiveDate::MIN),
+        _ => NaiveDate::parse_from_str(str, "%Y-%m-%d"),
+    }
+}
+
+/// Parses a timestamp, `infinity` and `-infinity` as [`NaiveDateTime::MAX`]
+/// and [`NaiveDateTime::MIN`]
+fn parse_timestamp(str: &str) -> Result<NaiveDateTime, chrono::ParseError> {
+    match str {
+        "infinity" => Ok(NaiveDateTime::MAX),
+        "-infinity" => Ok(NaiveDateTime::MIN),
+        _ => NaiveDateTime::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f"),
+    }
+}
+
+/// Parses a timestamp with a time zone, whose offset Postgres prints with
+/// minutes only if they aren't zero. `infinity` and `-infinity` are parsed as
+/// [`DateTime::<Utc>::MAX_UTC`] and [`DateTime::<Utc>::MIN_UTC`].
+fn parse_timestamptz(str: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    let val = match str {
+        "infinity" => return Ok(DateTime::<Utc>::MAX_UTC),
+        "-infinity" => return Ok(DateTime::<Utc>::MIN_UTC),
+        _ => match DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%#z") {
+            Ok(val) => val,
+            Err(_) => DateTime::<FixedOffset>::parse_from_str(str, "%Y-%m-%d %H:%M:%S%.f%:z")?,
+        },
+    };
+    Ok(val.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+    use postgres_replication::protocol::TupleData;
+    use tokio_postgres::types::{Kind, Type};
+
+    use crate::{
+        conversions::{
+            cdc_event::{CdcEventConverter, TupleColumnMapper},
+            geometry::{Geometry, Point},
+            table_row::TableRowConverter,
+            text::{ArrayParseError, FromTextError, TextFormatConverter},
+            ArrayCell, Cell,
+        },
+        table::ColumnSchema,
+    };
+
+    fn column_schemas(typ: Type) -> Vec<ColumnSchema> {
+        vec![ColumnSchema {
+            name: "value".to_string(),
+            typ,
+            modifier: -1,
+            nullable: true,
+            primary: false,
+            identity: false,
+            domain: None,
+        }]
+    }
+
+    /// Converts a value of type `typ` as the table copy does, from `copied`
+    /// as COPY prints it, with backslashes escaped and NULL as `\N`
+    fn copied_cell(typ: Type, copied: &str) -> Cell {
+        let row = format!("{copied}\n");
+        TableRowConverter::try_from(row.as_bytes(), &column_schemas(typ))
+            .expect("failed to convert copied row")
+            .values
+            .remove(0)
+    }
+
+    /// Converts a value of type `typ` as the cdc stream does, from `streamed`
+    /// as pgoutput sends it or `None` for NULL
+    fn streamed_cell(typ: Type, streamed: Option<&str>) -> Cell {
+        let tuple_data = match streamed {
+            Some(streamed) => TupleData::Text(Bytes::from(streamed.to_string())),
+            None => TupleData::Null,
+        };
+        CdcEventConverter::try_from_tuple_data_slice(
+            1,
+            &column_schemas(typ),
+            &[tuple_data],
+            &TupleColumnMapper::default(),
+        )
+        .expect("failed to convert streamed tuple")
+        .values
+        .remove(0)
+    }
+
+    fn json_cell_to_string(str: &str) -> String {
+        match TextFormatConverter::try_from_str(&Type::JSONB, str) {
+            Ok(Cell::Json(value)) => value.to_string(),
+            other => panic!("expected a json cell, got {other:?}"),
+        }
+    }
+
+    fn split(str: &str) -> Vec<Option<String>> {
+        TextFormatConverter::split_array(str).expect("failed to split array")
+    }
+
+    #[test]
+    fn jsonb_array_elements_are_split_on_unquoted_commas() {
+        let json_objects = match TextFormatConverter::try_from_str(
+            &Type::JSONB_ARRAY,
+            r#"{"{\"a\": 1}","{\"b\": 2}"}"#,
+        ) {
+            Ok(Cell::Array(ArrayCell::Json(json_objects))) => json_objects,
+            other => panic!("expected a json array cell, got {other:?}"),
+        };
+        assert_eq!(
+            json_objects,
+            vec![
+                Some(serde_json::json!({"a": 1})),
+                Some(serde_json::json!({"b": 2}))
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_braces_and_commas_are_part_of_elements() {
+        let elements = split(r#"{"{\"a\": [1, {\"b\": \"},{\"}]}","x,y"}"#);
+        assert_eq!(
+            elements,
+            vec![
+                Some(r#"{"a": [1, {"b": "},{"}]}"#.to_string()),
+                Some("x,y".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn unquoted_null_elements_are_none() {
+        let elements = split(r#"{NULL,"NULL",null,"{}"}"#);
+        assert_eq!(
+            elements,
+            vec![None, Some("NULL".to_string()), None, Some("{}".to_string())]
+        );
+    }
+
+    #[test]
+    fn multidimensional_arrays_are_flattened() {
+        let elements = split("[0:1][1:2]={{1,2},{3,NULL}}");
+        assert_eq!(
+            elements,
+            vec![
+                Some("1".to_string()),
+                Some("2".to_string()),
+                Some("3".to_string()),
                 None
-            } else {
-                parse(&val_str)?
-            };
-            res.push(val);
-            val_str.clear();
+            ]
+        );
+    }
+
+    /// Elements of array types without an [`ArrayCell`] variant yet, as
+    /// Postgres prints them: ranges are quoted for their commas and brackets,
+    /// intervals for their spaces, while network addresses are not quoted
+    #[test]
+    fn range_interval_and_inet_elements_are_split_whole() {
+        assert_eq!(
+            split(r#"{"[1,5)",empty,"(,3]"}"#),
+            vec![
+                Some("[1,5)".to_string()),
+                Some("empty".to_string()),
+                Some("(,3]".to_string())
+            ]
+        );
+        assert_eq!(
+            split(r#"{"1 day 02:00:00",NULL,"-1 mons"}"#),
+            vec![
+                Some("1 day 02:00:00".to_string()),
+                None,
+                Some("-1 mons".to_string())
+            ]
+        );
+        assert_eq!(
+            split("{192.168.0.1/24,::1,NULL}"),
+            vec![
+                Some("192.168.0.1/24".to_string()),
+                Some("::1".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_arrays_are_rejected() {
+        assert!(matches!(
+            TextFormatConverter::split_array("{1}{2}"),
+            Err(ArrayParseError::UnbalancedBraces)
+        ));
+        assert!(matches!(
+            TextFormatConverter::split_array(r#"{"a}"#),
+            Err(ArrayParseError::UnterminatedQuote)
+        ));
+        assert!(matches!(
+            TextFormatConverter::split_array("{1,2"),
+            Err(ArrayParseError::MissingBraces)
+        ));
+    }
+
+    #[test]
+    fn large_json_integer_is_not_a_float() {
+        let json = r#"{"id":12345678901234567890}"#;
+        assert_eq!(json_cell_to_string(json), json);
+    }
+
+    #[cfg(feature = "json_arbitrary_precision")]
+    #[test]
+    fn json_numbers_keep_all_digits() {
+        let json = r#"{"id":123456789012345678901234567890,"amount":0.12345678901234567890}"#;
+        assert_eq!(json_cell_to_string(json), json);
+    }
+
+    #[test]
+    fn point_is_parsed() {
+        let cell = TextFormatConverter::try_from_str(&Type::POINT, "(1.5,-2)").unwrap();
+        let expected = Geometry::Point(Point { x: 1.5, y: -2.0 });
+        assert_eq!(cell.as_geometry(), Some(&expected));
+        assert_eq!(expected.to_string(), "(1.5,-2)");
+    }
+
+    #[test]
+    fn postgis_geometry_is_passed_through_as_ewkb() {
+        // PostGIS' oids are assigned when the extension is created
+        let geometry = Type::new(
+            "geometry".to_string(),
+            16392,
+            Kind::Simple,
+            "public".to_string(),
+        );
+        // SRID=4326;POINT(1 2) as printed by Postgres for a geometry(Point,4326)
+        let ewkb = "0101000020E6100000000000000000F03F0000000000000040";
+
+        let cell = TextFormatConverter::try_from_str(&geometry, ewkb).unwrap();
+
+        let bytes = cell.as_ewkb().expect("expected an ewkb cell");
+        // little endian point with an srid
+        assert_eq!(bytes[..5], [0x01, 0x01, 0x00, 0x00, 0x20]);
+        assert_eq!(u32::from_le_bytes(bytes[5..9].try_into().unwrap()), 4326);
+        assert_eq!(f64::from_le_bytes(bytes[9..17].try_into().unwrap()), 1.0);
+        assert_eq!(f64::from_le_bytes(bytes[17..25].try_into().unwrap()), 2.0);
+    }
+
+    #[test]
+    fn enum_value_is_parsed_as_its_label() {
+        // Enum oids are assigned when the type is created
+        let mood = Type::new(
+            "mood".to_string(),
+            16400,
+            Kind::Enum(vec!["sad".to_string(), "happy".to_string()]),
+            "public".to_string(),
+        );
+
+        let cell = TextFormatConverter::try_from_str(&mood, "happy").unwrap();
+
+        assert_eq!(cell, Cell::String("happy".to_string()));
+        assert_eq!(
+            TextFormatConverter::default_value(&mood),
+            Cell::String(String::new())
+        );
+    }
+
+    #[test]
+    fn infinite_dates_and_timestamps_are_parsed_as_the_extremes() {
+        let cases = vec![
+            (Type::DATE, "infinity", Cell::Date(NaiveDate::MAX)),
+            (Type::DATE, "-infinity", Cell::Date(NaiveDate::MIN)),
+            (
+                Type::TIMESTAMP,
+                "infinity",
+                Cell::TimeStamp(NaiveDateTime::MAX),
+            ),
+            (
+                Type::TIMESTAMP,
+                "-infinity",
+                Cell::TimeStamp(NaiveDateTime::MIN),
+            ),
+            (
+                Type::TIMESTAMPTZ,
+                "infinity",
+                Cell::TimeStampTz(DateTime::<Utc>::MAX_UTC),
+            ),
+            (
+                Type::TIMESTAMPTZ,
+                "-infinity",
+                Cell::TimeStampTz(DateTime::<Utc>::MIN_UTC),
+            ),
+            (
+                Type::TIMESTAMPTZ_ARRAY,
+                "{-infinity,\"2024-02-29 13:04:05+01\"}",
+                Cell::Array(ArrayCell::TimeStampTz(vec![
+                    Some(DateTime::<Utc>::MIN_UTC),
+                    Some("2024-02-29T12:04:05Z".parse().unwrap()),
+                ])),
+            ),
+        ];
+
+        for (typ, str, expected) in cases {
+            let cell = TextFormatConverter::try_from_str(&typ, str)
+                .unwrap_or_else(|e| panic!("failed to parse {str} as {typ}: {e}"));
+            assert_eq!(cell, expected, "{str} as {typ}");
+        }
+    }
+
+    #[test]
+    fn arrays_with_a_null_element_are_converted_by_copy_and_cdc() {
+        let cases = vec![
+            (
+                Type::NUMERIC_ARRAY,
+                "{1.50,NULL,NaN}",
+                "{1.50,NULL,NaN}",
+                Cell::Array(ArrayCell::Numeric(vec![
+                    Some("1.50".parse().unwrap()),
+                    None,
+                    Some("NaN".parse().unwrap()),
+                ])),
+            ),
+            (
+                Type::UUID_ARRAY,
+                "{a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11,NULL}",
+                "{a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11,NULL}",
+                Cell::Array(ArrayCell::Uuid(vec![
+                    Some("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11".parse().unwrap()),
+                    None,
+                ])),
+            ),
+            (
+                Type::JSONB_ARRAY,
+                r#"{"{\\"a\\": 1}",NULL,"[1, \\"x\\"]"}"#,
+                r#"{"{\"a\": 1}",NULL,"[1, \"x\"]"}"#,
+                Cell::Array(ArrayCell::Json(vec![
+                    Some(serde_json::json!({"a": 1})),
+                    None,
+                    Some(serde_json::json!([1, "x"])),
+                ])),
+            ),
+        ];
+
+        for (typ, copied, streamed, expected) in cases {
+            assert_eq!(copied_cell(typ.clone(), copied), expected, "copied {typ}");
+            assert_eq!(
+                streamed_cell(typ.clone(), Some(streamed)),
+                expected,
+                "streamed {typ}"
+            );
         }
+    }
 
-        Ok(Cell::Array(m(res)))
+    #[test]
+    fn null_empty_and_null_element_int_arrays_are_converted_by_copy_and_cdc() {
+        // A NULL array is a NULL cell, never an `ArrayCell::Null`
+        assert_eq!(copied_cell(Type::INT4_ARRAY, r"\N"), Cell::Null);
+        assert_eq!(streamed_cell(Type::INT4_ARRAY, None), Cell::Null);
+
+        let cases = vec![
+            ("{}", Cell::Array(ArrayCell::I32(vec![]))),
+            (
+                "{1,NULL,3}",
+                Cell::Array(ArrayCell::I32(vec![Some(1), None, Some(3)])),
+            ),
+        ];
+
+        for (str, expected) in cases {
+            assert_eq!(copied_cell(Type::INT4_ARRAY, str), expected, "copied {str}");
+            assert_eq!(
+                streamed_cell(Type::INT4_ARRAY, Some(str)),
+                expected,
+                "streamed {str}"
+            );
+        }
+    }
+
+    #[test]
+    fn xml_is_passed_through_as_is() {
+        let cases = vec![
+            (
+                r#"<?xml version="1.0"?><book id="1"><title>Dune</title></book>"#,
+                r#"<?xml version="1.0"?><book id="1"><title>Dune</title></book>"#,
+            ),
+            ("", ""),
+            (
+                "<p>fish &amp; chips &lt;3 &#169;</p>",
+                "<p>fish &amp; chips &lt;3 &#169;</p>",
+            ),
+            // tabs and newlines of xml content are escaped by COPY
+            ("<a>\\tb\\n</a>", "<a>\tb\n</a>"),
+        ];
+
+        for (copied, streamed) in cases {
+            let expected = Cell::Xml(streamed.to_string());
+            assert_eq!(copied_cell(Type::XML, copied), expected, "copied {copied}");
+            assert_eq!(
+                streamed_cell(Type::XML, Some(streamed)),
+                expected,
+                "streamed {streamed}"
+            );
+        }
+    }
+
+    #[test]
+    fn pseudo_type_values_are_unsupported() {
+        let pseudo_types = [
+            Type::VOID,
+            Type::UNKNOWN,
+            Type::RECORD,
+            Type::ANY,
+            Type::ANYELEMENT,
+            Type::ANYARRAY,
+            Type::ANYNONARRAY,
+            Type::ANYENUM,
+            Type::ANYRANGE,
+            Type::CSTRING,
+            Type::INTERNAL,
+            Type::TRIGGER,
+            Type::EVENT_TRIGGER,
+        ];
+
+        for typ in pseudo_types {
+            match TextFormatConverter::try_from_str(&typ, "") {
+                Err(FromTextError::UnsupportedType(name)) => assert_eq!(name, typ.name()),
+                other => panic!("expected {typ} to be unsupported, got {other:?}"),
+            }
+            assert_eq!(
+                TextFormatConverter::default_value(&typ),
+                Cell::Null,
+                "default of {typ}"
+            );
+        }
     }
 }
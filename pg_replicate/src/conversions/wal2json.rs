@@ -0,0 +1,464 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use chrono::DateTime;
+use postgres_replication::protocol::ReplicationMessage;
+use serde::Deserialize;
+use tokio_postgres::types::PgLsn;
+
+use crate::table::{ColumnSchema, TableId, TableName, TableSchema};
+
+use super::{
+    cdc_event::{
+        BeginBody, CdcEvent, CdcEventConversionError, CdcEventConverter, CommitBody,
+        TransactionInfo,
+    },
+    table_row::TableRow,
+    text::TextFormatConverter,
+    Cell,
+};
+
+/// A message of wal2json's format version 2, with xids, timestamps and lsns
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action")]
+enum Wal2JsonMessage {
+    #[serde(rename = "B")]
+    Begin {
+        xid: u32,
+        timestamp: String,
+        lsn: String,
+    },
+    #[serde(rename = "C")]
+    Commit {
+        timestamp: String,
+        lsn: String,
+        nextlsn: String,
+    },
+    #[serde(rename = "I")]
+    Insert {
+        schema: String,
+        table: String,
+        columns: Vec<Wal2JsonColumn>,
+    },
+    #[serde(rename = "U")]
+    Update {
+        schema: String,
+        table: String,
+        columns: Vec<Wal2JsonColumn>,
+        /// The old values of the replica identity columns, or of all columns
+        /// with replica identity full
+        identity: Option<Vec<Wal2JsonColumn>>,
+    },
+    #[serde(rename = "D")]
+    Delete {
+        schema: String,
+        table: String,
+        identity: Vec<Wal2JsonColumn>,
+    },
+    /// Truncates and logical decoding messages
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct Wal2JsonColumn {
+    name: String,
+    value: serde_json::Value,
+}
+
+pub struct Wal2JsonConverter;
+
+impl Wal2JsonConverter {
+    /// Converts a replication message streamed from a wal2json slot into a
+    /// [`CdcEvent`]. Changes are attributed to tables by name through
+    /// `table_ids`, which also maps the names of partitions routed to their
+    /// root table. `transaction_info` is attached as in
+    /// [`CdcEventConverter::try_from`].
+    pub fn try_from(
+        value: ReplicationMessage<bytes::Bytes>,
+        table_ids: &HashMap<TableName, TableId>,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        transaction_info: Option<TransactionInfo>,
+    ) -> Result<CdcEvent, CdcEventConversionError> {
+        match value {
+            ReplicationMessage::XLogData(xlog_data) => {
+                Self::try_from_json(xlog_data.data(), table_ids, table_schemas, transaction_info)
+            }
+            ReplicationMessage::PrimaryKeepAlive(keep_alive) => Ok(CdcEvent::KeepAliveRequested {
+                reply: keep_alive.reply() == 1,
+            }),
+            _ => Err(CdcEventConversionError::UnknownReplicationMessage),
+        }
+    }
+
+    fn try_from_json(
+        json: &[u8],
+        table_ids: &HashMap<TableName, TableId>,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        transaction_info: Option<TransactionInfo>,
+    ) -> Result<CdcEvent, CdcEventConversionError> {
+        let table_schema = |schema: String, table: String| {
+            let table_name = TableName {
+                schema,
+                name: table,
+            };
+            let table_id = table_ids
+                .get(&table_name)
+                .ok_or(CdcEventConversionError::UnknownTable(table_name))?;
+            table_schemas
+                .get(table_id)
+                .ok_or(CdcEventConversionError::MissingSchema(*table_id))
+        };
+
+        match serde_json::from_slice(json)? {
+            Wal2JsonMessage::Begin {
+                xid,
+                timestamp,
+                lsn,
+            } => Ok(CdcEvent::Begin(BeginBody::new(
+                Self::parse_lsn(&lsn)?,
+                Self::parse_timestamp(&timestamp)?,
+                xid,
+            ))),
+            Wal2JsonMessage::Commit {
+                timestamp,
+                lsn,
+                nextlsn,
+            } => {
+                let commit_timestamp = transaction_info.and_then(|info| info.commit_timestamp);
+                Ok(CdcEvent::Commit(
+                    CommitBody::new(
+                        Self::parse_lsn(&lsn)?,
+                        Self::parse_lsn(&nextlsn)?,
+                        Self::parse_timestamp(&timestamp)?,
+                    ),
+                    commit_timestamp,
+                ))
+            }
+            Wal2JsonMessage::Insert {
+                schema,
+                table,
+                columns,
+            } => {
+                let table_schema = table_schema(schema, table)?;
+                let row = Self::try_from_columns(&table_schema.column_schemas, &columns, false)?;
+                Ok(CdcEvent::Insert((
+                    table_schema.table_id,
+                    row,
+                    transaction_info,
+                )))
+            }
+            Wal2JsonMessage::Update {
+                schema,
+                table,
+                columns,
+                identity,
+            } => {
+                let table_schema = table_schema(schema, table)?;
+                let column_schemas = &table_schema.column_schemas;
+                let key_columns = CdcEventConverter::key_columns(column_schemas);
+                let row = Self::try_from_columns(column_schemas, &columns, false)?;
+                let mut old_row = None;
+                let mut key_row = None;
+                if let Some(identity) = identity {
+                    let identity_row = Self::try_from_columns(column_schemas, &identity, true)?;
+                    if identity.len() == column_schemas.len() {
+                        old_row = Some(identity_row);
+                    } else if key_columns
+                        .iter()
+                        .any(|&i| identity_row.values[i] != row.values[i])
+                    {
+                        key_row = Some(identity_row);
+                    }
+                }
+                Ok(CdcEvent::Update {
+                    table_id: table_schema.table_id,
+                    old_row,
+                    key_row,
+                    row,
+                    key_columns,
+                    transaction_info,
+                })
+            }
+            Wal2JsonMessage::Delete {
+                schema,
+                table,
+                identity,
+            } => {
+                let table_schema = table_schema(schema, table)?;
+                let column_schemas = &table_schema.column_schemas;
+                Ok(CdcEvent::Delete {
+                    table_id: table_schema.table_id,
+                    row: Self::try_from_columns(column_schemas, &identity, true)?,
+                    key_columns: CdcEventConverter::key_columns(column_schemas),
+                    transaction_info,
+                })
+            }
+            Wal2JsonMessage::Other => Err(CdcEventConversionError::MessageNotSupported),
+        }
+    }
+
+    /// Converts the columns of a change into a row of the table of
+    /// `column_schemas`, matching them by name. Columns missing from an
+    /// identity are NULL. Those missing from new values are unchanged toasted
+    /// values, which wal2json leaves out, and get their type's default value
+    /// as they do with pgoutput.
+    fn try_from_columns(
+        column_schemas: &[ColumnSchema],
+        columns: &[Wal2JsonColumn],
+        is_identity: bool,
+    ) -> Result<TableRow, CdcEventConversionError> {
+        let mut values = Vec::with_capacity(column_schemas.len());
+        for column_schema in column_schemas {
+            let column = columns
+                .iter()
+                .find(|column| column.name == column_schema.name);
+            let cell = match column.map(|column| Self::value_text(&column.value)) {
+                None if is_identity => Cell::Null,
+                None => TextFormatConverter::default_value(&column_schema.typ),
+                Some(None) => Cell::Null,
+                Some(Some(text)) => TextFormatConverter::try_from_str(&column_schema.typ, &text)?,
+            };
+            values.push(cell);
+        }
+        Ok(TableRow { values })
+    }
+
+    /// Returns the text representation of a value, which wal2json writes as a
+    /// JSON number or boolean for numeric and boolean types and as a string
+    /// holding the text representation otherwise
+    fn value_text(value: &serde_json::Value) -> Option<Cow<'_, str>> {
+        match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(text) => Some(Cow::Borrowed(text)),
+            serde_json::Value::Bool(true) => Some(Cow::Borrowed("t")),
+            serde_json::Value::Bool(false) => Some(Cow::Borrowed("f")),
+            value => Some(Cow::Owned(value.to_string())),
+        }
+    }
+
+    fn parse_lsn(lsn: &str) -> Result<u64, CdcEventConversionError> {
+        lsn.parse::<PgLsn>()
+            .map(u64::from)
+            .map_err(|_| CdcEventConversionError::InvalidWal2JsonField("lsn"))
+    }
+
+    /// Parses a timestamp like `2024-01-15 10:30:00.123456+00` into
+    /// microseconds since the Postgres epoch (2000-01-01)
+    fn parse_timestamp(timestamp: &str) -> Result<i64, CdcEventConversionError> {
+        const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+        DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%#z")
+            .map(|timestamp| timestamp.timestamp_micros() - POSTGRES_EPOCH_MICROS)
+            .map_err(|_| CdcEventConversionError::InvalidWal2JsonField("timestamp"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        conversions::{
+            cdc_event::{CdcEvent, CdcEventConversionError, TransactionInfo},
+            table_row::TableRow,
+            Cell,
+        },
+        table::{ColumnSchema, TableId, TableName, TableSchema},
+    };
+
+    use super::Wal2JsonConverter;
+
+    const TABLE_ID: TableId = 16385;
+
+    fn column_schema(name: &str, typ: Type, primary: bool) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: !primary,
+            primary,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    /// `public.users` with the primary key `id`, a `name` and an `active` column
+    fn tables() -> (HashMap<TableName, TableId>, HashMap<TableId, TableSchema>) {
+        let table_name = TableName {
+            schema: "public".to_string(),
+            name: "users".to_string(),
+        };
+        let table_schema = TableSchema {
+            table_name: table_name.clone(),
+            table_id: TABLE_ID,
+            column_schemas: vec![
+                column_schema("id", Type::INT4, true),
+                column_schema("name", Type::TEXT, false),
+                column_schema("active", Type::BOOL, false),
+            ],
+        };
+        (
+            HashMap::from([(table_name, TABLE_ID)]),
+            HashMap::from([(TABLE_ID, table_schema)]),
+        )
+    }
+
+    fn convert(
+        json: &str,
+        transaction_info: Option<TransactionInfo>,
+    ) -> Result<CdcEvent, CdcEventConversionError> {
+        let (table_ids, table_schemas) = tables();
+        Wal2JsonConverter::try_from_json(
+            json.as_bytes(),
+            &table_ids,
+            &table_schemas,
+            transaction_info,
+        )
+    }
+
+    fn row(id: i32, name: &str, active: bool) -> TableRow {
+        TableRow {
+            values: vec![
+                Cell::I32(id),
+                Cell::String(name.to_string()),
+                Cell::Bool(active),
+            ],
+        }
+    }
+
+    #[test]
+    fn begin_and_commit_are_decoded_with_their_lsns() {
+        let begin = convert(
+            r#"{"action":"B","xid":742,"timestamp":"2024-01-15 10:30:00.000000+00","lsn":"0/16B3748","nextlsn":"0/16B3778"}"#,
+            None,
+        )
+        .unwrap();
+        let transaction_info = TransactionInfo::new(742, 758_629_800_000_000, true);
+        let commit = convert(
+            r#"{"action":"C","xid":742,"timestamp":"2024-01-15 10:30:00.000000+00","lsn":"0/16B3748","nextlsn":"0/16B3778"}"#,
+            Some(transaction_info),
+        )
+        .unwrap();
+
+        let CdcEvent::Begin(begin_body) = begin else {
+            panic!("expected a begin, got {begin:?}");
+        };
+        assert_eq!(begin_body.xid(), 742);
+        assert_eq!(begin_body.final_lsn(), 0x16B3748);
+        assert_eq!(begin_body.timestamp(), 758_629_800_000_000);
+        let CdcEvent::Commit(commit_body, commit_timestamp) = commit else {
+            panic!("expected a commit, got {commit:?}");
+        };
+        assert_eq!(commit_body.commit_lsn(), begin_body.final_lsn());
+        assert_eq!(commit_body.end_lsn(), 0x16B3778);
+        assert_eq!(commit_timestamp, transaction_info.commit_timestamp);
+    }
+
+    #[test]
+    fn insert_is_decoded_into_a_row_of_its_table() {
+        let transaction_info = TransactionInfo::new(742, 0, false);
+
+        let event = convert(
+            r#"{"action":"I","schema":"public","table":"users","columns":[{"name":"id","type":"integer","value":1},{"name":"name","type":"text","value":"alice"},{"name":"active","type":"boolean","value":true}]}"#,
+            Some(transaction_info),
+        )
+        .unwrap();
+
+        let CdcEvent::Insert((table_id, inserted_row, info)) = event else {
+            panic!("expected an insert, got {event:?}");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(inserted_row, row(1, "alice", true));
+        assert_eq!(info, Some(transaction_info));
+    }
+
+    #[test]
+    fn update_without_a_key_change_has_no_key_row() {
+        let event = convert(
+            r#"{"action":"U","schema":"public","table":"users","columns":[{"name":"id","type":"integer","value":1},{"name":"name","type":"text","value":"bob"},{"name":"active","type":"boolean","value":false}],"identity":[{"name":"id","type":"integer","value":1}]}"#,
+            None,
+        )
+        .unwrap();
+
+        let CdcEvent::Update {
+            table_id,
+            old_row,
+            key_row,
+            row: new_row,
+            key_columns,
+            ..
+        } = event
+        else {
+            panic!("expected an update, got {event:?}");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(old_row, None);
+        assert_eq!(key_row, None);
+        assert_eq!(new_row, row(1, "bob", false));
+        assert_eq!(key_columns, vec![0]);
+    }
+
+    #[test]
+    fn update_of_the_key_has_the_old_key_in_its_key_row() {
+        let event = convert(
+            r#"{"action":"U","schema":"public","table":"users","columns":[{"name":"id","type":"integer","value":2},{"name":"name","type":"text","value":"bob"},{"name":"active","type":"boolean","value":false}],"identity":[{"name":"id","type":"integer","value":1}]}"#,
+            None,
+        )
+        .unwrap();
+
+        let CdcEvent::Update { key_row, .. } = event else {
+            panic!("expected an update, got {event:?}");
+        };
+        let key_row = key_row.unwrap();
+        assert_eq!(key_row.values, vec![Cell::I32(1), Cell::Null, Cell::Null]);
+    }
+
+    #[test]
+    fn delete_is_decoded_from_its_identity() {
+        let event = convert(
+            r#"{"action":"D","schema":"public","table":"users","identity":[{"name":"id","type":"integer","value":1}]}"#,
+            None,
+        )
+        .unwrap();
+
+        let CdcEvent::Delete {
+            table_id,
+            row: deleted_row,
+            key_columns,
+            ..
+        } = event
+        else {
+            panic!("expected a delete, got {event:?}");
+        };
+        assert_eq!(table_id, TABLE_ID);
+        assert_eq!(
+            deleted_row.values,
+            vec![Cell::I32(1), Cell::Null, Cell::Null]
+        );
+        assert_eq!(key_columns, vec![0]);
+    }
+
+    #[test]
+    fn change_of_a_table_which_isnt_replicated_is_rejected() {
+        let result = convert(
+            r#"{"action":"D","schema":"public","table":"orders","identity":[{"name":"id","type":"integer","value":1}]}"#,
+            None,
+        );
+
+        assert!(matches!(
+            result,
+            Err(CdcEventConversionError::UnknownTable(table_name)) if table_name.name == "orders"
+        ));
+    }
+
+    #[test]
+    fn truncate_is_not_supported() {
+        let result = convert(r#"{"action":"T","schema":"public","table":"users"}"#, None);
+
+        assert!(matches!(
+            result,
+            Err(CdcEventConversionError::MessageNotSupported)
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// A batch size which follows how long the sink takes to write batches. It
+/// grows while writes take less than a target time and shrinks while they
+/// take longer, staying between a minimum and a maximum.
+///
+/// Clones share the size, so that the pipeline writing batches adapts the
+/// size of those the [`super::stream::BatchTimeoutStream`] reads next.
+#[derive(Debug, Clone)]
+pub struct AdaptiveBatchSize {
+    min_batch_size: usize,
+    max_batch_size: usize,
+    target_write_time: Duration,
+    current: Arc<AtomicUsize>,
+}
+
+impl AdaptiveBatchSize {
+    /// Starts at `min_batch_size`. Values of `min_batch_size` below one are
+    /// treated as one and `max_batch_size` is at least `min_batch_size`.
+    pub fn new(
+        min_batch_size: usize,
+        max_batch_size: usize,
+        target_write_time: Duration,
+    ) -> AdaptiveBatchSize {
+        let min_batch_size = min_batch_size.max(1);
+        AdaptiveBatchSize {
+            min_batch_size,
+            max_batch_size: max_batch_size.max(min_batch_size),
+            target_write_time,
+            current: Arc::new(AtomicUsize::new(min_batch_size)),
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Adapts the size to a batch of `batch_size` rows having taken
+    /// `write_time` to write. The size is scaled by how far off the target
+    /// the write time was, but at most halved or doubled at once, so that it
+    /// converges on the size whose writes take the target time without
+    /// overreacting to a single slow or fast write. A batch smaller than the
+    /// current size, flushed by its fill time, only shrinks the size when it
+    /// took longer than the target, as its fast write says nothing about how
+    /// long a batch of the current size takes.
+    pub fn record_write(&self, batch_size: usize, write_time: Duration) {
+        let current = self.current();
+        if batch_size < current && write_time <= self.target_write_time {
+            return;
+        }
+        let ratio = self.target_write_time.as_secs_f64() / write_time.as_secs_f64().max(1e-6);
+        let next = (current as f64 * ratio.clamp(0.5, 2.0)).round() as usize;
+        let next = next.clamp(self.min_batch_size, self.max_batch_size);
+        self.current.store(next, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::AdaptiveBatchSize;
+
+    /// How long a sink with a fixed cost per write of 10ms and 1ms per row
+    /// takes to write a batch of `batch_size` rows
+    fn write_time(batch_size: usize) -> Duration {
+        Duration::from_millis(10 + batch_size as u64)
+    }
+
+    #[test]
+    fn batch_size_converges_on_the_size_written_in_the_target_time() {
+        // batches of 100 rows take the target time of 110ms
+        let batch_size = AdaptiveBatchSize::new(10, 1000, Duration::from_millis(110));
+
+        for _ in 0..20 {
+            let size = batch_size.current();
+            batch_size.record_write(size, write_time(size));
+        }
+
+        assert!(
+            (98..=102).contains(&batch_size.current()),
+            "batch size {} didn't converge on 100",
+            batch_size.current()
+        );
+    }
+
+    #[test]
+    fn batch_size_shrinks_when_writes_slow_down() {
+        let batch_size = AdaptiveBatchSize::new(10, 1000, Duration::from_millis(110));
+        for _ in 0..20 {
+            let size = batch_size.current();
+            batch_size.record_write(size, write_time(size));
+        }
+
+        // the sink now takes 5ms per row, so 20 rows are written in 110ms
+        for _ in 0..20 {
+            let size = batch_size.current();
+            batch_size.record_write(size, Duration::from_millis(10 + 5 * size as u64));
+        }
+
+        assert!(
+            (19..=21).contains(&batch_size.current()),
+            "batch size {} didn't converge on 20",
+            batch_size.current()
+        );
+    }
+
+    #[test]
+    fn batch_size_stays_within_its_bounds() {
+        let batch_size = AdaptiveBatchSize::new(10, 50, Duration::from_millis(110));
+
+        for _ in 0..20 {
+            batch_size.record_write(batch_size.current(), Duration::from_millis(1));
+        }
+        assert_eq!(batch_size.current(), 50);
+
+        for _ in 0..20 {
+            batch_size.record_write(batch_size.current(), Duration::from_secs(10));
+        }
+        assert_eq!(batch_size.current(), 10);
+    }
+
+    #[test]
+    fn fast_writes_of_partial_batches_dont_grow_the_batch_size() {
+        let batch_size = AdaptiveBatchSize::new(10, 1000, Duration::from_millis(110));
+
+        batch_size.record_write(3, Duration::from_millis(13));
+        assert_eq!(batch_size.current(), 10);
+
+        batch_size.record_write(10, Duration::from_millis(20));
+        assert_eq!(batch_size.current(), 20);
+
+        batch_size.record_write(5, Duration::from_millis(440));
+        assert_eq!(batch_size.current(), 10);
+    }
+}
@@ -1,30 +1,100 @@
-use std::{collections::HashSet, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    future::{self, Future},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use futures::StreamExt;
-use tokio::pin;
+use futures::{Stream, StreamExt};
+use tokio::{
+    pin, select,
+    sync::{
+        mpsc::{self, Receiver, Sender, UnboundedSender},
+        OwnedSemaphorePermit, Semaphore,
+    },
+    time,
+};
 use tokio_postgres::types::PgLsn;
-use tracing::{debug, info};
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
 
 use crate::{
     conversions::cdc_event::{CdcEvent, CdcEventConversionError},
     pipeline::{
         batching::stream::BatchTimeoutStream,
-        sinks::BatchSink,
-        sources::{postgres::CdcStreamError, CommonSourceError, Source},
-        PipelineAction, PipelineError,
+        dead_letter::{DeadLetter, DeadLetterPayload, DeadLetterPolicy, DeadLetterThreshold},
+        identifiers::limit_identifier_length,
+        sinks::{ApplyMode, BatchSink, SchemaMismatchPolicy, SinkCredentials},
+        sources::{
+            postgres::{CdcStreamError, TableCopyStreamError},
+            CommonSourceError, Source,
+        },
+        CopyReconciliationPolicy, PipelineAction, PipelineError, ReplicaIdentityPolicy,
+        SlotGapPolicy, SnapshotConsistencyPolicy, TableCopySnapshot, TableOperations, TableStatus,
+        TableStatusUpdate,
     },
-    table::TableId,
+    table::{TableId, TableName, TableSchema},
 };
 
 use super::BatchConfig;
 
+/// Reads `batches` into `batch_sender`, each with one of `in_flight`'s
+/// permits. Reading pauses while all permits are held by batches which
+/// weren't written yet and stops once the receiver is dropped.
+async fn read_batches<S: Stream>(
+    batches: S,
+    in_flight: Arc<Semaphore>,
+    batch_sender: UnboundedSender<(S::Item, OwnedSemaphorePermit)>,
+) {
+    pin!(batches);
+    loop {
+        let permit = in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("in flight semaphore is never closed");
+        let Some(batch) = batches.next().await else {
+            break;
+        };
+        if batch_sender.send((batch, permit)).is_err() {
+            break;
+        }
+    }
+}
+
 pub struct BatchDataPipeline<Src: Source, Snk: BatchSink> {
     source: Src,
     sink: Snk,
     action: PipelineAction,
     batch_config: BatchConfig,
+    dead_letter_policy: DeadLetterPolicy,
+    dead_letter_threshold: Option<DeadLetterThreshold>,
+    schema_mismatch_policy: SchemaMismatchPolicy,
+    apply_mode: ApplyMode,
+    copy_reconciliation_policy: CopyReconciliationPolicy,
+    slot_gap_policy: SlotGapPolicy,
+    table_copy_snapshot: TableCopySnapshot,
+    snapshot_consistency_policy: SnapshotConsistencyPolicy,
+    replica_identity_policy: ReplicaIdentityPolicy,
+    credentials_receiver: Option<Receiver<SinkCredentials>>,
+    table_status_sender: Option<Sender<TableStatusUpdate>>,
+    resync_receiver: Option<Receiver<TableId>>,
+    flush_receiver: Option<Receiver<()>>,
+    tables: Option<Vec<TableName>>,
+    /// Ids of `tables`, resolved when the pipeline starts
+    replicated_tables: Option<HashSet<TableId>>,
+    column_renames: HashMap<(TableId, String), String>,
+    table_operations: HashMap<TableId, TableOperations>,
+    max_identifier_length: Option<usize>,
+    sink_write_timeout: Option<Duration>,
+    sink_ready_timeout: Option<Duration>,
 }
 
+/// How long the pipeline first waits before polling a sink which isn't ready
+/// again. The wait doubles with every poll up to [`SINK_READY_MAX_BACKOFF`].
+const SINK_READY_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+const SINK_READY_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
 impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
     pub fn new(source: Src, sink: Snk, action: PipelineAction, batch_config: BatchConfig) -> Self {
         BatchDataPipeline {
@@ -32,28 +102,613 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
             sink,
             action,
             batch_config,
+            dead_letter_policy: DeadLetterPolicy::default(),
+            dead_letter_threshold: None,
+            schema_mismatch_policy: SchemaMismatchPolicy::default(),
+            apply_mode: ApplyMode::default(),
+            copy_reconciliation_policy: CopyReconciliationPolicy::default(),
+            slot_gap_policy: SlotGapPolicy::default(),
+            table_copy_snapshot: TableCopySnapshot::default(),
+            snapshot_consistency_policy: SnapshotConsistencyPolicy::default(),
+            replica_identity_policy: ReplicaIdentityPolicy::default(),
+            credentials_receiver: None,
+            table_status_sender: None,
+            resync_receiver: None,
+            flush_receiver: None,
+            tables: None,
+            replicated_tables: None,
+            column_renames: HashMap::new(),
+            table_operations: HashMap::new(),
+            max_identifier_length: None,
+            sink_write_timeout: None,
+            sink_ready_timeout: None,
         }
     }
 
-    async fn copy_table_schemas(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+    pub fn set_dead_letter_policy(&mut self, dead_letter_policy: DeadLetterPolicy) {
+        self.dead_letter_policy = dead_letter_policy;
+    }
+
+    /// Stops the pipeline with [`PipelineError::DeadLetterThresholdExceeded`]
+    /// once records dead lettered by the [`DeadLetterPolicy::Skip`] policy
+    /// exceed `dead_letter_threshold`
+    pub fn set_dead_letter_threshold(&mut self, dead_letter_threshold: DeadLetterThreshold) {
+        self.dead_letter_threshold = Some(dead_letter_threshold);
+    }
+
+    pub fn set_schema_mismatch_policy(&mut self, schema_mismatch_policy: SchemaMismatchPolicy) {
+        self.schema_mismatch_policy = schema_mismatch_policy;
+    }
+
+    /// Fails [`BatchDataPipeline::start`] if the sink doesn't support `apply_mode`
+    pub fn set_apply_mode(&mut self, apply_mode: ApplyMode) {
+        self.apply_mode = apply_mode;
+    }
+
+    /// Row counts of a table in the source and the sink are compared after
+    /// it was copied unless `copy_reconciliation_policy` is
+    /// [`CopyReconciliationPolicy::Skip`]
+    pub fn set_copy_reconciliation_policy(
+        &mut self,
+        copy_reconciliation_policy: CopyReconciliationPolicy,
+    ) {
+        self.copy_reconciliation_policy = copy_reconciliation_policy;
+    }
+
+    /// The pipeline stops before streaming changes if the source's slot is
+    /// past the changes the sink has seen, unless `slot_gap_policy` is
+    /// [`SlotGapPolicy::Recopy`]
+    pub fn set_slot_gap_policy(&mut self, slot_gap_policy: SlotGapPolicy) {
+        self.slot_gap_policy = slot_gap_policy;
+    }
+
+    /// Tables are copied in one transaction of the source unless
+    /// `table_copy_snapshot` is [`TableCopySnapshot::PerTable`], see there
+    /// for what that gives up
+    pub fn set_table_copy_snapshot(&mut self, table_copy_snapshot: TableCopySnapshot) {
+        self.table_copy_snapshot = table_copy_snapshot;
+    }
+
+    /// After copying tables the pipeline compares the lsns of the snapshots
+    /// they were copied from, per `snapshot_consistency_policy`
+    pub fn set_snapshot_consistency_policy(
+        &mut self,
+        snapshot_consistency_policy: SnapshotConsistencyPolicy,
+    ) {
+        self.snapshot_consistency_policy = snapshot_consistency_policy;
+    }
+
+    /// Before streaming changes the pipeline looks for replicated tables
+    /// without a replica identity, per `replica_identity_policy`
+    pub fn set_replica_identity_policy(&mut self, replica_identity_policy: ReplicaIdentityPolicy) {
+        self.replica_identity_policy = replica_identity_policy;
+    }
+
+    /// Credentials sent on the other end of `credentials_receiver` are handed
+    /// to the sink before it writes the next batch
+    pub fn set_credentials_receiver(&mut self, credentials_receiver: Receiver<SinkCredentials>) {
+        self.credentials_receiver = Some(credentials_receiver);
+    }
+
+    /// Status changes of the source's tables are sent to `table_status_sender`
+    pub fn set_table_status_sender(&mut self, table_status_sender: Sender<TableStatusUpdate>) {
+        self.table_status_sender = Some(table_status_sender);
+    }
+
+    /// Requests sent on the other end of `flush_receiver` write the cdc
+    /// events received so far to the sink without waiting for the batch to
+    /// fill or time out, up to the last complete transaction, and confirm the
+    /// sink's lsn to the source. The pipeline keeps running.
+    pub fn set_flush_receiver(&mut self, flush_receiver: Receiver<()>) {
+        self.flush_receiver = Some(flush_receiver);
+    }
+
+    /// Tables whose ids are sent on the other end of `resync_receiver` are
+    /// reset in the sink, see [`BatchSink::reset_table`]. Requests received
+    /// before the tables are copied are handled right away. A request
+    /// received while streaming cdc events stops the pipeline after the
+    /// current batch was written, because a table can only be copied from a
+    /// new snapshot of the source, and the reset tables are copied again
+    /// when the pipeline is restarted.
+    pub fn set_resync_receiver(&mut self, resync_receiver: Receiver<TableId>) {
+        self.resync_receiver = Some(resync_receiver);
+    }
+
+    /// Only `tables` are copied and only their cdc events applied. Events of
+    /// the source's other tables are skipped but the lsn confirmed to the
+    /// source still advances past them. [`BatchDataPipeline::start`] fails if
+    /// a table isn't one of the source's.
+    pub fn set_tables(&mut self, tables: Vec<TableName>) {
+        self.tables = Some(tables);
+    }
+
+    /// Names columns differently in the sink than in the source. A key of
+    /// `column_renames` is a table and the name of one of its columns in the
+    /// source, its value the name of the column in the sink. The sink only
+    /// sees the new names, when it creates and validates its tables as well
+    /// as when it writes rows. [`BatchDataPipeline::start`] fails if two
+    /// columns of a table end up with the same name.
+    pub fn set_column_renames(&mut self, column_renames: HashMap<(TableId, String), String>) {
+        self.column_renames = column_renames;
+    }
+
+    /// Only applies the kinds of changes in `table_operations` to a table in
+    /// it, e.g. only inserts for an append-only analytics table. Tables not in
+    /// it have all their changes applied. [`BatchDataPipeline::start`] fails
+    /// if a table's operations can't be honoured with the sink's apply mode.
+    pub fn set_table_operations(&mut self, table_operations: HashMap<TableId, TableOperations>) {
+        self.table_operations = table_operations;
+    }
+
+    /// Shortens the table and column names the sink sees to at most
+    /// `max_identifier_length` bytes, for sinks with a shorter limit than
+    /// Postgres. The schema and name of a table are shortened separately.
+    /// Names are shortened after `column_renames` are applied, see
+    /// [`limit_identifier_length`] for how.
+    pub fn set_max_identifier_length(&mut self, max_identifier_length: usize) {
+        self.max_identifier_length = Some(max_identifier_length);
+    }
+
+    /// Fails the pipeline with [`PipelineError::SinkTimeout`] if writing
+    /// table schemas, rows or cdc events to the sink takes longer than
+    /// `sink_write_timeout`, instead of waiting for a hung sink indefinitely.
+    /// The error is transient, see [`PipelineError::is_transient`].
+    pub fn set_sink_write_timeout(&mut self, sink_write_timeout: Duration) {
+        self.sink_write_timeout = Some(sink_write_timeout);
+    }
+
+    /// Waits up to `sink_ready_timeout` for [`BatchSink::ready`] to return
+    /// true before the pipeline starts, polling it with backoff, instead of
+    /// failing at the first write to a sink which isn't reachable yet, e.g.
+    /// one starting up alongside the pipeline. Fails the pipeline with
+    /// [`PipelineError::SinkNotReady`] once the timeout elapsed. Without it
+    /// the sink isn't polled.
+    pub fn set_sink_ready_timeout(&mut self, sink_ready_timeout: Duration) {
+        self.sink_ready_timeout = Some(sink_ready_timeout);
+    }
+
+    /// The pipeline's source, e.g. to inspect a test source after the
+    /// pipeline ran
+    pub fn source(&self) -> &Src {
+        &self.source
+    }
+
+    /// The pipeline's sink, e.g. to inspect what a
+    /// [`RecordingSink`](crate::pipeline::sinks::recording::RecordingSink)
+    /// was written after the pipeline ran
+    pub fn sink(&self) -> &Snk {
+        &self.sink
+    }
+
+    /// Polls [`BatchSink::ready`] until it returns true or
+    /// `sink_ready_timeout` elapsed
+    async fn wait_for_sink(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let Some(sink_ready_timeout) = self.sink_ready_timeout else {
+            return Ok(());
+        };
+        let deadline = time::Instant::now() + sink_ready_timeout;
+        let mut backoff = SINK_READY_INITIAL_BACKOFF;
+        while !self.sink.ready().await.map_err(PipelineError::Sink)? {
+            let now = time::Instant::now();
+            if now >= deadline {
+                return Err(PipelineError::SinkNotReady(sink_ready_timeout));
+            }
+            info!("sink not ready, polling it again in {backoff:?}");
+            time::sleep(backoff.min(deadline - now)).await;
+            backoff = (backoff * 2).min(SINK_READY_MAX_BACKOFF);
+        }
+        Ok(())
+    }
+
+    /// Awaits the sink write `write`, which times out after
+    /// `sink_write_timeout`
+    async fn write_to_sink<T>(
+        sink_write_timeout: Option<Duration>,
+        operation: &'static str,
+        write: impl Future<Output = Result<T, Snk::Error>>,
+    ) -> Result<T, PipelineError<Src::Error, Snk::Error>> {
+        let result = match sink_write_timeout {
+            Some(timeout) => time::timeout(timeout, write)
+                .await
+                .map_err(|_| PipelineError::SinkTimeout { operation, timeout })?,
+            None => write.await,
+        };
+        result.map_err(PipelineError::Sink)
+    }
+
+    /// Returns the ids of `tables`, or `None` if all of the source's tables
+    /// are replicated
+    fn resolve_tables(
+        &self,
+    ) -> Result<Option<HashSet<TableId>>, PipelineError<Src::Error, Snk::Error>> {
+        let Some(tables) = &self.tables else {
+            return Ok(None);
+        };
+        let table_schemas = self.source.get_table_schemas();
+        let mut table_ids = HashSet::with_capacity(tables.len());
+        for table_name in tables {
+            let table_id = table_schemas
+                .values()
+                .find(|table_schema| &table_schema.table_name == table_name)
+                .map(|table_schema| table_schema.table_id)
+                .ok_or_else(|| PipelineError::TableNotInSource(table_name.clone()))?;
+            table_ids.insert(table_id);
+        }
+        Ok(Some(table_ids))
+    }
+
+    fn is_replicated(replicated_tables: &Option<HashSet<TableId>>, table_id: TableId) -> bool {
+        match replicated_tables {
+            Some(replicated_tables) => replicated_tables.contains(&table_id),
+            None => true,
+        }
+    }
+
+    /// Returns false for row events of tables which aren't replicated. All
+    /// other events are kept so that transaction boundaries and keepalives
+    /// still reach the sink.
+    fn is_event_replicated(replicated_tables: &Option<HashSet<TableId>>, event: &CdcEvent) -> bool {
+        let table_id = match event {
+            CdcEvent::Insert((table_id, _, _))
+            | CdcEvent::Update { table_id, .. }
+            | CdcEvent::Delete { table_id, .. } => *table_id,
+            _ => return true,
+        };
+        Self::is_replicated(replicated_tables, table_id)
+    }
+
+    /// Returns false for row events whose kind isn't applied to their table
+    fn is_operation_applied(
+        table_operations: &HashMap<TableId, TableOperations>,
+        event: &CdcEvent,
+    ) -> bool {
+        let table_id = match event {
+            CdcEvent::Insert((table_id, _, _))
+            | CdcEvent::Update { table_id, .. }
+            | CdcEvent::Delete { table_id, .. } => *table_id,
+            _ => return true,
+        };
+        table_operations
+            .get(&table_id)
+            .is_none_or(|operations| operations.allows(event))
+    }
+
+    /// Upserting sinks apply an update of a row they don't have as an insert,
+    /// so skipping a table's inserts but not its updates would still insert
+    /// the rows. Skipping deletes leaves rows deleted in the source in the
+    /// sink, which is what it's for, but it's logged as the sink no longer
+    /// mirrors the table.
+    /// Fails with the replicated tables which have no key, for a sink which
+    /// applies rows by key
+    fn check_table_keys(&self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let mut table_schemas: Vec<_> = self
+            .source
+            .get_table_schemas()
+            .values()
+            .filter(|table_schema| {
+                Self::is_replicated(&self.replicated_tables, table_schema.table_id)
+                    && !table_schema.has_primary_keys()
+            })
+            .collect();
+        if table_schemas.is_empty() {
+            return Ok(());
+        }
+        table_schemas.sort_by_key(|table_schema| table_schema.table_id);
+        let table_names = table_schemas
+            .into_iter()
+            .map(|table_schema| table_schema.table_name.clone())
+            .collect();
+        Err(PipelineError::MissingKey(table_names))
+    }
+
+    fn validate_table_operations(&self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        if self.apply_mode != ApplyMode::Upsert {
+            return Ok(());
+        }
         let table_schemas = self.source.get_table_schemas();
-        let table_schemas = table_schemas.clone();
+        for (table_id, operations) in &self.table_operations {
+            let Some(table_schema) = table_schemas.get(table_id) else {
+                continue;
+            };
+            let table_name = &table_schema.table_name;
+            if !operations.insert && operations.update {
+                return Err(PipelineError::IncoherentTableOperations(
+                    table_name.clone(),
+                    self.apply_mode,
+                ));
+            }
+            if !operations.delete {
+                warn!("deletes of table {table_name} are skipped, rows deleted in the source stay in the sink");
+            }
+        }
+        Ok(())
+    }
 
-        if !table_schemas.is_empty() {
-            self.sink
-                .write_table_schemas(table_schemas)
+    /// Takes `table_status_sender` instead of `&self` so that it can be called
+    /// while the source's table schemas are borrowed
+    async fn send_table_status(
+        table_status_sender: &Option<Sender<TableStatusUpdate>>,
+        table_schema: &TableSchema,
+        status: TableStatus,
+        lsn: Option<PgLsn>,
+    ) {
+        let Some(table_status_sender) = table_status_sender else {
+            return;
+        };
+        let update = TableStatusUpdate {
+            table_id: table_schema.table_id,
+            table_name: table_schema.table_name.clone(),
+            status,
+            lsn,
+        };
+        // A closed channel only means no one is interested in statuses anymore
+        let _ = table_status_sender.send(update).await;
+    }
+
+    async fn send_table_statuses(
+        table_status_sender: &Option<Sender<TableStatusUpdate>>,
+        table_schemas: &HashMap<TableId, TableSchema>,
+        replicated_tables: &Option<HashSet<TableId>>,
+        status: TableStatus,
+        lsn: Option<PgLsn>,
+    ) {
+        for table_schema in table_schemas.values() {
+            if !Self::is_replicated(replicated_tables, table_schema.table_id) {
+                continue;
+            }
+            Self::send_table_status(table_status_sender, table_schema, status, lsn).await;
+        }
+    }
+
+    /// Takes `sink` and `credentials_receiver` instead of `&mut self` so that
+    /// it can be called while the source's table schemas are borrowed
+    async fn reload_sink_credentials(
+        sink: &mut Snk,
+        credentials_receiver: &mut Option<Receiver<SinkCredentials>>,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let Some(credentials_receiver) = credentials_receiver else {
+            return Ok(());
+        };
+        // Only the most recently sent credentials matter
+        let mut credentials = None;
+        while let Ok(new_credentials) = credentials_receiver.try_recv() {
+            credentials = Some(new_credentials);
+        }
+        if let Some(credentials) = credentials {
+            info!("reloading sink credentials");
+            sink.reload_credentials(credentials)
                 .await
                 .map_err(PipelineError::Sink)?;
         }
+        Ok(())
+    }
+
+    /// Waits for a request on `flush_receiver`, forever if there is none
+    async fn flush_requested(flush_receiver: &mut Option<Receiver<()>>) -> Option<()> {
+        match flush_receiver {
+            Some(flush_receiver) => flush_receiver.recv().await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Resets the replicated tables requested on `resync_receiver` in the sink
+    /// and returns the ids of those which were reset
+    async fn reset_resynced_tables(
+        &mut self,
+    ) -> Result<HashSet<TableId>, PipelineError<Src::Error, Snk::Error>> {
+        let mut reset_tables = HashSet::new();
+        let Some(resync_receiver) = &mut self.resync_receiver else {
+            return Ok(reset_tables);
+        };
+        while let Ok(table_id) = resync_receiver.try_recv() {
+            let table_schemas = self.source.get_table_schemas();
+            let Some(table_schema) = table_schemas.get(&table_id) else {
+                warn!("can't re-sync table {table_id} which is not one of the source's tables");
+                continue;
+            };
+            if !Self::is_replicated(&self.replicated_tables, table_id) {
+                warn!(
+                    "can't re-sync table {} which is not replicated",
+                    table_schema.table_name
+                );
+                continue;
+            }
+            if !self
+                .sink
+                .reset_table(table_id)
+                .await
+                .map_err(PipelineError::Sink)?
+            {
+                warn!(
+                    "can't re-sync table {}, the sink doesn't support resetting tables",
+                    table_schema.table_name
+                );
+                continue;
+            }
+            info!("reset table {} to copy it again", table_schema.table_name);
+            reset_tables.insert(table_id);
+        }
+        Ok(reset_tables)
+    }
+
+    /// Compares the rows of a copied table in the sink with those in the
+    /// source, counting rows sent to the dead letter sink as copied. Takes
+    /// `source` and `sink` instead of `&self` so that it can be called while
+    /// the source's table schemas are borrowed.
+    async fn reconcile_table(
+        source: &Src,
+        sink: &mut Snk,
+        policy: CopyReconciliationPolicy,
+        table_schema: &TableSchema,
+        dead_lettered_rows: u64,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        if matches!(policy, CopyReconciliationPolicy::Skip) {
+            return Ok(());
+        }
+        let table_name = &table_schema.table_name;
+        let Some(sink_rows) = sink
+            .row_count(table_schema.table_id)
+            .await
+            .map_err(PipelineError::Sink)?
+        else {
+            warn!("sink can't count rows, skipping reconciliation of table {table_name}");
+            return Ok(());
+        };
+        let source_rows = source
+            .get_row_count(table_name)
+            .await
+            .map_err(PipelineError::Source)?;
+        if sink_rows + dead_lettered_rows == source_rows {
+            info!("table {table_name} reconciled with {source_rows} rows");
+            return Ok(());
+        }
+        match policy {
+            CopyReconciliationPolicy::Skip => Ok(()),
+            CopyReconciliationPolicy::Warn => {
+                warn!(
+                    "table {table_name} has {sink_rows} rows in the sink after its copy but {source_rows} in the source"
+                );
+                Ok(())
+            }
+            CopyReconciliationPolicy::Fail => Err(PipelineError::RowCountMismatch {
+                table_name: table_name.clone(),
+                source_rows,
+                sink_rows,
+            }),
+        }
+    }
+
+    /// Returns the schema of the sink's table for `table_schema`, which has
+    /// the columns of `table_schema` renamed by `column_renames` and its
+    /// names shortened to `max_identifier_length`
+    fn sink_table_schema(
+        column_renames: &HashMap<(TableId, String), String>,
+        max_identifier_length: Option<usize>,
+        table_schema: &TableSchema,
+    ) -> Result<TableSchema, PipelineError<Src::Error, Snk::Error>> {
+        let limit = |name: &str| match max_identifier_length {
+            Some(max_length) => limit_identifier_length(name, max_length),
+            None => name.to_string(),
+        };
+        let mut sink_table_schema = table_schema.clone();
+        sink_table_schema.table_name.schema = limit(&table_schema.table_name.schema);
+        sink_table_schema.table_name.name = limit(&table_schema.table_name.name);
+        let mut column_names = HashSet::with_capacity(sink_table_schema.column_schemas.len());
+        for column_schema in &mut sink_table_schema.column_schemas {
+            let key = (table_schema.table_id, column_schema.name.clone());
+            if let Some(new_name) = column_renames.get(&key) {
+                column_schema.name.clone_from(new_name);
+            }
+            column_schema.name = limit(&column_schema.name);
+            if !column_names.insert(column_schema.name.clone()) {
+                return Err(PipelineError::ColumnNameCollision {
+                    table_name: table_schema.table_name.clone(),
+                    column_name: column_schema.name.clone(),
+                });
+            }
+        }
+        Ok(sink_table_schema)
+    }
+
+    #[instrument(skip_all)]
+    async fn copy_table_schemas(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let table_schemas = self
+            .source
+            .get_table_schemas()
+            .iter()
+            .filter(|(table_id, _)| Self::is_replicated(&self.replicated_tables, **table_id))
+            .map(|(table_id, table_schema)| {
+                let sink_table_schema = Self::sink_table_schema(
+                    &self.column_renames,
+                    self.max_identifier_length,
+                    table_schema,
+                )?;
+                Ok((*table_id, sink_table_schema))
+            })
+            .collect::<Result<HashMap<TableId, TableSchema>, PipelineError<_, _>>>()?;
+
+        if !table_schemas.is_empty() {
+            Self::write_to_sink(
+                self.sink_write_timeout,
+                "write_table_schemas",
+                self.sink.write_table_schemas(table_schemas.clone()),
+            )
+            .await?;
+        }
+
+        for table_schema in table_schemas.values() {
+            self.validate_table_schema(table_schema).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn validate_table_schema(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let mut schema_diff = self
+            .sink
+            .validate_schema(table_schema)
+            .await
+            .map_err(PipelineError::Sink)?;
+
+        if schema_diff.is_empty() {
+            return Ok(());
+        }
+
+        match self.schema_mismatch_policy {
+            SchemaMismatchPolicy::AutoMigrate => {
+                info!(
+                    "migrating schema of table {} in the sink: {schema_diff}",
+                    table_schema.table_name
+                );
+                self.sink
+                    .migrate_schema(table_schema, &schema_diff)
+                    .await
+                    .map_err(PipelineError::Sink)?;
+                schema_diff = self
+                    .sink
+                    .validate_schema(table_schema)
+                    .await
+                    .map_err(PipelineError::Sink)?;
+                if !schema_diff.is_empty() {
+                    return Err(PipelineError::SchemaMismatch(
+                        table_schema.table_name.clone(),
+                        schema_diff,
+                    ));
+                }
+            }
+            SchemaMismatchPolicy::Warn => {
+                warn!(
+                    "schema of table {} in the sink doesn't match the source: {schema_diff}",
+                    table_schema.table_name
+                );
+            }
+            SchemaMismatchPolicy::Fail => {
+                return Err(PipelineError::SchemaMismatch(
+                    table_schema.table_name.clone(),
+                    schema_diff,
+                ));
+            }
+        }
 
         Ok(())
     }
 
+    /// Copies the tables not in `copied_tables` and returns whether any were
+    #[instrument(skip_all)]
     async fn copy_tables(
         &mut self,
         copied_tables: &HashSet<TableId>,
-    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+    ) -> Result<bool, PipelineError<Src::Error, Snk::Error>> {
         let start = Instant::now();
+        let mut copied_any = false;
+        // The source starts out in the transaction of the slot's snapshot
+        let mut in_transaction = true;
+        let check_snapshots = self.snapshot_consistency_policy != SnapshotConsistencyPolicy::Skip;
+        let mut snapshot_lsn = self.source.get_snapshot_lsn();
+        let mut snapshot_lsns = vec![];
         let table_schemas = self.source.get_table_schemas();
 
         let mut keys: Vec<u32> = table_schemas.keys().copied().collect();
@@ -61,61 +716,338 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
 
         for key in keys {
             let table_schema = table_schemas.get(&key).expect("failed to get table key");
+            if !Self::is_replicated(&self.replicated_tables, table_schema.table_id) {
+                continue;
+            }
+            // Truncating a copied table would wipe rows which cdc events have
+            // since been applied to and which won't be copied again
             if copied_tables.contains(&table_schema.table_id) {
                 info!("table {} already copied.", table_schema.table_name);
                 continue;
             }
 
+            copied_any = true;
+
+            if !in_transaction {
+                self.source
+                    .begin_transaction()
+                    .await
+                    .map_err(PipelineError::Source)?;
+                in_transaction = true;
+                if check_snapshots {
+                    let lsn = self
+                        .source
+                        .current_wal_lsn()
+                        .await
+                        .map_err(PipelineError::Source)?;
+                    snapshot_lsn = Some(lsn);
+                }
+            }
+            if let Some(snapshot_lsn) = snapshot_lsn {
+                snapshot_lsns.push((table_schema.table_name.clone(), snapshot_lsn));
+            }
+
+            Self::send_table_status(
+                &self.table_status_sender,
+                table_schema,
+                TableStatus::Copying,
+                None,
+            )
+            .await;
+
+            // Copies are not resumed mid-table: a table whose copy was interrupted
+            // is copied again from a new snapshot, so its partial rows must go
             self.sink
                 .truncate_table(table_schema.table_id)
                 .await
                 .map_err(PipelineError::Sink)?;
 
+            // The sink sees its own names of the table and its columns, as in
+            // `write_table_schemas`
+            let sink_table_schema = Self::sink_table_schema(
+                &self.column_renames,
+                self.max_identifier_length,
+                table_schema,
+            )?;
+            self.sink
+                .begin_table_copy(table_schema.table_id, &sink_table_schema)
+                .await
+                .map_err(PipelineError::Sink)?;
+
             let table_rows = self
                 .source
                 .get_table_copy_stream(&table_schema.table_name, &table_schema.column_schemas)
                 .await
                 .map_err(PipelineError::Source)?;
 
-            let batch_timeout_stream =
-                BatchTimeoutStream::new(table_rows, self.batch_config.clone());
+            let batch_timeout_stream = BatchTimeoutStream::new(
+                table_rows,
+                self.batch_config.for_table(table_schema.table_id),
+            );
 
-            pin!(batch_timeout_stream);
+            // Batches are read while earlier ones are written, with each
+            // holding a permit until it was written
+            let in_flight = Arc::new(Semaphore::new(self.batch_config.max_in_flight_batches));
+            let (batch_sender, mut batch_receiver) = mpsc::unbounded_channel();
+            let read = async {
+                read_batches(batch_timeout_stream, in_flight, batch_sender).await;
+                Ok::<_, PipelineError<Src::Error, Snk::Error>>(())
+            };
 
-            while let Some(batch) = batch_timeout_stream.next().await {
-                info!("got {} table copy events in a batch", batch.len());
-                //TODO: Avoid a vec copy
-                let mut rows = Vec::with_capacity(batch.len());
-                for row in batch {
-                    rows.push(row.map_err(CommonSourceError::TableCopyStream)?);
+            let sink = &mut self.sink;
+            let credentials_receiver = &mut self.credentials_receiver;
+            let dead_letter_policy = &mut self.dead_letter_policy;
+            let dead_letter_threshold = &mut self.dead_letter_threshold;
+            let sink_write_timeout = self.sink_write_timeout;
+            let adaptive_batch_size = self.batch_config.adaptive_batch_size().cloned();
+            let write = async {
+                let mut dead_lettered_rows = 0;
+                while let Some((batch, _permit)) = batch_receiver.recv().await {
+                    info!("got {} table copy events in a batch", batch.len());
+                    //TODO: Avoid a vec copy
+                    let mut rows = Vec::with_capacity(batch.len());
+                    for row in batch {
+                        match row {
+                            Ok(row) => rows.push(row),
+                            Err(TableCopyStreamError::ConversionError { row, source }) => {
+                                let Some(dead_letter_sink) = dead_letter_policy.dead_letter_sink()
+                                else {
+                                    let e = TableCopyStreamError::ConversionError { row, source };
+                                    return Err(CommonSourceError::TableCopyStream(e).into());
+                                };
+                                let dead_letter = DeadLetter {
+                                    table_id: Some(table_schema.table_id),
+                                    payload: DeadLetterPayload::CopyRow(row),
+                                    error: Box::new(source),
+                                };
+                                dead_letter_sink.write_dead_letter(dead_letter).await?;
+                                dead_lettered_rows += 1;
+                                if let Some(dead_letter_threshold) = dead_letter_threshold {
+                                    dead_letter_threshold.record(time::Instant::now())?;
+                                }
+                            }
+                            Err(e) => return Err(CommonSourceError::TableCopyStream(e).into()),
+                        }
+                    }
+                    Self::reload_sink_credentials(sink, credentials_receiver).await?;
+                    let span = info_span!(
+                        "write_table_rows",
+                        table_id = table_schema.table_id,
+                        rows = rows.len()
+                    );
+                    let batch_size = rows.len();
+                    let write_started = time::Instant::now();
+                    Self::write_to_sink(
+                        sink_write_timeout,
+                        "write_table_rows",
+                        sink.write_table_rows(rows, table_schema.table_id),
+                    )
+                    .instrument(span)
+                    .await?;
+                    if let Some(adaptive_batch_size) = &adaptive_batch_size {
+                        adaptive_batch_size.record_write(batch_size, write_started.elapsed());
+                    }
                 }
-                self.sink
-                    .write_table_rows(rows, table_schema.table_id)
-                    .await
-                    .map_err(PipelineError::Sink)?;
-            }
+                Ok::<_, PipelineError<Src::Error, Snk::Error>>(dead_lettered_rows)
+            };
+
+            // Stops reading as soon as writing fails
+            let ((), dead_lettered_rows) = tokio::try_join!(read, write)?;
+
+            self.sink
+                .end_table_copy(table_schema.table_id)
+                .await
+                .map_err(PipelineError::Sink)?;
 
             self.sink
                 .table_copied(table_schema.table_id)
                 .await
                 .map_err(PipelineError::Sink)?;
+
+            Self::reconcile_table(
+                &self.source,
+                &mut self.sink,
+                self.copy_reconciliation_policy,
+                table_schema,
+                dead_lettered_rows,
+            )
+            .await?;
+
+            if self.table_copy_snapshot == TableCopySnapshot::PerTable {
+                self.source
+                    .commit_transaction()
+                    .await
+                    .map_err(PipelineError::Source)?;
+                in_transaction = false;
+            }
+
+            Self::send_table_status(
+                &self.table_status_sender,
+                table_schema,
+                TableStatus::Copied,
+                None,
+            )
+            .await;
+        }
+        if in_transaction {
+            self.source
+                .commit_transaction()
+                .await
+                .map_err(PipelineError::Source)?;
         }
-        self.source
-            .commit_transaction()
-            .await
-            .map_err(PipelineError::Source)?;
 
         let end = Instant::now();
         let seconds = (end - start).as_secs();
         debug!("took {seconds} seconds to copy tables");
 
+        if check_snapshots {
+            self.check_snapshot_consistency(snapshot_lsns)?;
+        }
+
+        Ok(copied_any)
+    }
+
+    /// Checks that the tables copied in this run were all read from snapshots
+    /// at the same lsn, so that rows referencing each other across tables are
+    /// consistent. Tables whose snapshot lsn isn't known are not compared.
+    fn check_snapshot_consistency(
+        &self,
+        snapshot_lsns: Vec<(TableName, PgLsn)>,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let mut lsns = snapshot_lsns.iter().map(|(_, lsn)| lsn);
+        let Some(first_lsn) = lsns.next() else {
+            return Ok(());
+        };
+        if lsns.all(|lsn| lsn == first_lsn) {
+            return Ok(());
+        }
+        let error = PipelineError::InconsistentSnapshots(snapshot_lsns);
+        match self.snapshot_consistency_policy {
+            SnapshotConsistencyPolicy::Skip => Ok(()),
+            SnapshotConsistencyPolicy::Warn => {
+                warn!("{error}, rows referencing other tables may be inconsistent");
+                Ok(())
+            }
+            SnapshotConsistencyPolicy::Fail => Err(error),
+        }
+    }
+
+    async fn check_replica_identities(&self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        if self.replica_identity_policy == ReplicaIdentityPolicy::Skip {
+            return Ok(());
+        }
+        let mut table_names = self
+            .source
+            .tables_without_replica_identity()
+            .await
+            .map_err(PipelineError::Source)?;
+        if let Some(tables) = &self.tables {
+            table_names.retain(|table_name| tables.contains(table_name));
+        }
+        if table_names.is_empty() {
+            return Ok(());
+        }
+        let error = PipelineError::MissingReplicaIdentity(table_names);
+        match self.replica_identity_policy {
+            ReplicaIdentityPolicy::Skip => Ok(()),
+            ReplicaIdentityPolicy::Warn => {
+                warn!("{error}");
+                Ok(())
+            }
+            ReplicaIdentityPolicy::Fail => Err(error),
+        }
+    }
+
+    /// Checks that the source's slot can still stream every change after
+    /// `sink_lsn`, which the tables in `copied_tables`, copied in earlier runs,
+    /// are missing. If it can't, the tables are reset to be copied again or
+    /// the pipeline stops, per the slot gap policy.
+    async fn reconcile_slot_position(
+        &mut self,
+        copied_tables: &mut HashSet<TableId>,
+        sink_lsn: PgLsn,
+    ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        let Some(slot_position) = self.source.get_slot_position() else {
+            return Ok(());
+        };
+        if copied_tables.is_empty() || sink_lsn >= slot_position.confirmed_flush_lsn {
+            return Ok(());
+        }
+        // A slot which existed before this run starts at the lsn it was
+        // created at, which a sink that hasn't streamed any change yet is
+        // behind of even if nothing was missed. A slot created in this run
+        // is newer than the tables copied earlier, so they miss changes.
+        let slot_created = self.source.get_snapshot_lsn().is_some();
+        if sink_lsn == PgLsn::from(0) && !slot_created {
+            warn!(
+                "the sink hasn't streamed any changes, can't tell whether tables copied \
+                earlier miss changes before the slot's position {}",
+                slot_position.confirmed_flush_lsn
+            );
+            return Ok(());
+        }
+
+        let error = PipelineError::SlotAheadOfSink {
+            sink_lsn,
+            restart_lsn: slot_position.restart_lsn,
+            confirmed_flush_lsn: slot_position.confirmed_flush_lsn,
+        };
+        if self.slot_gap_policy == SlotGapPolicy::Error
+            || !matches!(self.action, PipelineAction::Both)
+        {
+            return Err(error);
+        }
+        warn!("{error}, copying the tables again");
+        for &table_id in copied_tables.iter() {
+            if !self
+                .sink
+                .reset_table(table_id)
+                .await
+                .map_err(PipelineError::Sink)?
+            {
+                return Err(error);
+            }
+        }
+        copied_tables.clear();
         Ok(())
     }
 
+    /// Returns the lsn after which cdc must start so that changes to tables
+    /// copied in this run are neither missed nor applied twice. That is the
+    /// lsn of the copy's snapshot, unless the sink has already seen later ones.
+    fn cdc_start_lsn(&self, last_lsn: PgLsn, tables_copied: bool) -> PgLsn {
+        if !tables_copied {
+            return last_lsn;
+        }
+        match self.source.get_snapshot_lsn() {
+            Some(snapshot_lsn) => {
+                info!("handing off from table copy to cdc at snapshot lsn {snapshot_lsn}");
+                last_lsn.max(snapshot_lsn)
+            }
+            None => {
+                warn!(
+                    "tables were not copied from the replication slot's snapshot, \
+                    changes committed between the slot's position and the copy may be applied twice"
+                );
+                last_lsn
+            }
+        }
+    }
+
+    #[instrument(skip_all)]
     async fn copy_cdc_events(
         &mut self,
         last_lsn: PgLsn,
     ) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        Self::send_table_statuses(
+            &self.table_status_sender,
+            self.source.get_table_schemas(),
+            &self.replicated_tables,
+            TableStatus::Streaming,
+            Some(last_lsn),
+        )
+        .await;
+
         let mut last_lsn: u64 = last_lsn.into();
         last_lsn += 1;
         let cdc_events = self
@@ -130,28 +1062,83 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
 
         pin!(batch_timeout_stream);
 
-        while let Some(batch) = batch_timeout_stream.next().await {
+        loop {
+            let (batch, flush_requested) = select! {
+                batch = batch_timeout_stream.next() => match batch {
+                    Some(batch) => (batch, false),
+                    None => break,
+                },
+                Some(()) = Self::flush_requested(&mut self.flush_receiver) => {
+                    info!("flushing the current batch on request");
+                    (batch_timeout_stream.as_mut().take_batch(), true)
+                }
+            };
             info!("got {} cdc events in a batch", batch.len());
             let mut send_status_update = false;
             let mut events = Vec::with_capacity(batch.len());
             for event in batch {
-                if let Err(CdcStreamError::CdcEventConversion(
-                    CdcEventConversionError::MissingSchema(_),
-                )) = event
-                {
-                    continue;
-                }
-                let event = event.map_err(CommonSourceError::CdcStream)?;
+                let event = match event {
+                    Ok(event) => event,
+                    Err(CdcStreamError::CdcEventConversion(
+                        CdcEventConversionError::MissingSchema(_)
+                        | CdcEventConversionError::NoReplicaIdentitySkipped(_),
+                    )) => continue,
+                    Err(CdcStreamError::CdcEventConversion(
+                        CdcEventConversionError::InvalidTuple { table_id, .. },
+                    )) if !Self::is_replicated(&self.replicated_tables, table_id) => continue,
+                    Err(CdcStreamError::CdcEventConversion(
+                        CdcEventConversionError::InvalidTuple {
+                            table_id,
+                            tuple,
+                            source,
+                        },
+                    )) => {
+                        let Some(dead_letter_sink) = self.dead_letter_policy.dead_letter_sink()
+                        else {
+                            let e = CdcEventConversionError::InvalidTuple {
+                                table_id,
+                                tuple,
+                                source,
+                            };
+                            return Err(CommonSourceError::CdcStream(e.into()).into());
+                        };
+                        let dead_letter = DeadLetter {
+                            table_id: Some(table_id),
+                            payload: DeadLetterPayload::Tuple(tuple),
+                            error: source,
+                        };
+                        dead_letter_sink.write_dead_letter(dead_letter).await?;
+                        if let Some(dead_letter_threshold) = &mut self.dead_letter_threshold {
+                            dead_letter_threshold.record(time::Instant::now())?;
+                        }
+                        continue;
+                    }
+                    Err(e) => return Err(CommonSourceError::CdcStream(e).into()),
+                };
                 if let CdcEvent::KeepAliveRequested { reply } = event {
-                    send_status_update = reply;
+                    send_status_update = reply || self.batch_config.flush_on_idle;
                 };
-                events.push(event);
+                if Self::is_event_replicated(&self.replicated_tables, &event)
+                    && Self::is_operation_applied(&self.table_operations, &event)
+                {
+                    events.push(event);
+                }
+            }
+            send_status_update |= flush_requested;
+            Self::reload_sink_credentials(&mut self.sink, &mut self.credentials_receiver).await?;
+            let span = info_span!("write_cdc_events", events = events.len());
+            let batch_size = events.len();
+            let write_started = time::Instant::now();
+            let last_lsn = Self::write_to_sink(
+                self.sink_write_timeout,
+                "write_cdc_events",
+                self.sink.write_cdc_events(events),
+            )
+            .instrument(span)
+            .await?;
+            if let Some(adaptive_batch_size) = self.batch_config.adaptive_batch_size() {
+                adaptive_batch_size.record_write(batch_size, write_started.elapsed());
             }
-            let last_lsn = self
-                .sink
-                .write_cdc_events(events)
-                .await
-                .map_err(PipelineError::Sink)?;
             if send_status_update {
                 info!("sending status update with lsn: {last_lsn}");
                 let inner = unsafe {
@@ -165,23 +1152,83 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
                     .send_status_update(last_lsn)
                     .await
                     .map_err(CommonSourceError::StatusUpdate)?;
+                Self::send_table_statuses(
+                    &self.table_status_sender,
+                    self.source.get_table_schemas(),
+                    &self.replicated_tables,
+                    TableStatus::Streaming,
+                    Some(last_lsn),
+                )
+                .await;
+                // Lag is only reported, failing to query it is no reason to stop
+                match self.source.current_wal_lsn().await {
+                    Ok(current_wal_lsn) => {
+                        let lag = u64::from(current_wal_lsn).saturating_sub(u64::from(last_lsn));
+                        info!("replication lag: {lag} bytes behind lsn {current_wal_lsn}");
+                    }
+                    Err(e) => warn!("failed to get the source's current wal lsn: {e}"),
+                }
+            }
+            if !self.reset_resynced_tables().await?.is_empty() {
+                info!("stopping the pipeline to copy the reset tables when it is restarted");
+                break;
             }
         }
 
         Ok(())
     }
 
+    #[instrument(name = "pipeline", skip_all)]
     pub async fn start(&mut self) -> Result<(), PipelineError<Src::Error, Snk::Error>> {
+        if !self.sink.supports_apply_mode(self.apply_mode) {
+            return Err(PipelineError::UnsupportedApplyMode(self.apply_mode));
+        }
+        self.sink.set_apply_mode(self.apply_mode);
+
+        self.wait_for_sink().await?;
+
+        let capabilities = self.sink.capabilities();
+        info!("sink capabilities: {capabilities:?}");
+        let streams_cdc = !matches!(self.action, PipelineAction::TableCopiesOnly);
+        if streams_cdc
+            && !capabilities.supports_delete
+            && self
+                .source
+                .publishes_deletes()
+                .await
+                .map_err(PipelineError::Source)?
+        {
+            return Err(PipelineError::DeletesNotSupported);
+        }
+
+        self.replicated_tables = self.resolve_tables()?;
+        if capabilities.requires_key {
+            self.check_table_keys()?;
+        }
+        self.validate_table_operations()?;
+        if streams_cdc {
+            self.check_replica_identities().await?;
+        }
+
         let resumption_state = self
             .sink
             .get_resumption_state()
             .await
             .map_err(PipelineError::Sink)?;
 
+        let mut copied_tables = resumption_state.copied_tables;
+        for table_id in self.reset_resynced_tables().await? {
+            copied_tables.remove(&table_id);
+        }
+        if streams_cdc {
+            self.reconcile_slot_position(&mut copied_tables, resumption_state.last_lsn)
+                .await?;
+        }
+
         match self.action {
             PipelineAction::TableCopiesOnly => {
                 self.copy_table_schemas().await?;
-                self.copy_tables(&resumption_state.copied_tables).await?;
+                self.copy_tables(&copied_tables).await?;
             }
             PipelineAction::CdcOnly => {
                 self.copy_table_schemas().await?;
@@ -189,11 +1236,1523 @@ impl<Src: Source, Snk: BatchSink> BatchDataPipeline<Src, Snk> {
             }
             PipelineAction::Both => {
                 self.copy_table_schemas().await?;
-                self.copy_tables(&resumption_state.copied_tables).await?;
-                self.copy_cdc_events(resumption_state.last_lsn).await?;
+                let tables_copied = self.copy_tables(&copied_tables).await?;
+                let start_lsn = self.cdc_start_lsn(resumption_state.last_lsn, tables_copied);
+                self.copy_cdc_events(start_lsn).await?;
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use bytes::{BufMut, Bytes, BytesMut};
+    use futures::StreamExt;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::{testing::trace::InMemorySpanExporter, trace::TracerProvider};
+    use tokio::{
+        pin,
+        sync::{mpsc, Semaphore},
+        time::timeout,
+    };
+    use tokio_postgres::types::{PgLsn, Type};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::{
+        conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+        pipeline::{
+            batching::{
+                data_pipeline::{read_batches, BatchDataPipeline},
+                BatchConfig,
+            },
+            dead_letter::{
+                DeadLetter, DeadLetterPayload, DeadLetterPolicy, DeadLetterSink,
+                DeadLetterSinkError, DeadLetterThreshold, LoggingDeadLetterSink,
+            },
+            sinks::{
+                BatchSink, ColumnTypeMismatch, InfallibleSinkError, SchemaDiff,
+                SchemaMismatchPolicy, SinkCapabilities, SinkCredentials,
+            },
+            sources::{
+                postgres::{CdcStream, TableCopyStream},
+                InfallibleSourceError, SlotPosition, Source,
+            },
+            CopyReconciliationPolicy, PipelineAction, PipelineError, PipelineResumptionState,
+            SlotGapPolicy, SnapshotConsistencyPolicy, TableCopySnapshot, TableOperations,
+        },
+        table::{ColumnSchema, TableId, TableInfo, TableName, TableSchema},
+    };
+
+    /// A source of which only what the pipeline needs to copy tables is
+    /// implemented
+    struct TestSource {
+        table_schemas: HashMap<TableId, TableSchema>,
+        publishes_deletes: bool,
+        /// Tables copied and transactions begun and committed, in order
+        calls: Mutex<Vec<String>>,
+        /// Rows of each table in the COPY text format
+        copy_rows: Vec<&'static str>,
+        slot_position: Option<SlotPosition>,
+        snapshot_lsn: Option<PgLsn>,
+        /// The source's WAL position, which every begun transaction advances
+        /// like other transactions committed between per table copies would
+        wal_lsn: Mutex<u64>,
+        /// pgoutput messages the cdc stream replays
+        cdc_messages: Vec<Bytes>,
+        /// Rows the source counts in each table, as many as `copy_rows` if
+        /// `None`
+        row_count: Option<u64>,
+    }
+
+    impl TestSource {
+        fn with_tables(names: &[&str]) -> TestSource {
+            let table_schemas = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let table_id = i as TableId + 1;
+                    let table_schema = TableSchema {
+                        table_name: table_name(name),
+                        table_id,
+                        column_schemas: vec![],
+                    };
+                    (table_id, table_schema)
+                })
+                .collect();
+            TestSource {
+                table_schemas,
+                publishes_deletes: false,
+                calls: Mutex::new(vec![]),
+                copy_rows: vec![],
+                slot_position: None,
+                snapshot_lsn: None,
+                wal_lsn: Mutex::new(0),
+                cdc_messages: vec![],
+                row_count: None,
+            }
+        }
+    }
+
+    fn table_name(name: &str) -> TableName {
+        TableName {
+            schema: "public".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[async_trait]
+    impl Source for TestSource {
+        type Error = InfallibleSourceError;
+
+        fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+            &self.table_schemas
+        }
+
+        async fn list_tables(&self) -> Result<Vec<TableInfo>, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn tables_without_replica_identity(&self) -> Result<Vec<TableName>, Self::Error> {
+            Ok(vec![])
+        }
+
+        async fn get_table_copy_stream(
+            &self,
+            table_name: &TableName,
+            column_schemas: &[ColumnSchema],
+        ) -> Result<TableCopyStream, Self::Error> {
+            let call = format!("copy {}", table_name.name);
+            self.calls.lock().unwrap().push(call);
+            Ok(TableCopyStream::from_rows(&self.copy_rows, column_schemas))
+        }
+
+        async fn get_row_count(&self, _table_name: &TableName) -> Result<u64, Self::Error> {
+            Ok(self.row_count.unwrap_or(self.copy_rows.len() as u64))
+        }
+
+        fn get_snapshot_lsn(&self) -> Option<PgLsn> {
+            self.snapshot_lsn
+        }
+
+        fn get_slot_position(&self) -> Option<SlotPosition> {
+            self.slot_position
+        }
+
+        async fn commit_transaction(&self) -> Result<(), Self::Error> {
+            self.calls.lock().unwrap().push("commit".to_string());
+            Ok(())
+        }
+
+        async fn begin_transaction(&self) -> Result<(), Self::Error> {
+            self.calls.lock().unwrap().push("begin".to_string());
+            *self.wal_lsn.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        async fn publishes_deletes(&self) -> Result<bool, Self::Error> {
+            Ok(self.publishes_deletes)
+        }
+
+        async fn current_wal_lsn(&self) -> Result<PgLsn, Self::Error> {
+            Ok(PgLsn::from(*self.wal_lsn.lock().unwrap()))
+        }
+
+        /// Replays the transactions of `cdc_messages` which commit at or after
+        /// `start_lsn`, like Postgres streams the changes of a slot
+        async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<CdcStream, Self::Error> {
+            let mut skipped = false;
+            let messages = self
+                .cdc_messages
+                .iter()
+                .filter(|message| {
+                    if message[0] == b'B' {
+                        let final_lsn = u64::from_be_bytes(message[1..9].try_into().unwrap());
+                        skipped = PgLsn::from(final_lsn) < start_lsn;
+                    }
+                    let replayed = !skipped;
+                    if message[0] == b'C' {
+                        skipped = false;
+                    }
+                    replayed
+                })
+                .cloned()
+                .collect();
+            Ok(CdcStream::replay(messages, self.table_schemas.clone()))
+        }
+
+        async fn drop_slot(&self, _slot_name: &str) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    /// A sink which can only append rows
+    struct AppendOnlySink;
+
+    #[async_trait]
+    impl BatchSink for AppendOnlySink {
+        type Error = InfallibleSinkError;
+
+        async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn write_table_schemas(
+            &mut self,
+            _table_schemas: HashMap<TableId, TableSchema>,
+        ) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        async fn write_table_rows(
+            &mut self,
+            _rows: Vec<TableRow>,
+            _table_id: TableId,
+        ) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        async fn write_cdc_events(&mut self, _events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn table_copied(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        async fn truncate_table(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn capabilities(&self) -> SinkCapabilities {
+            SinkCapabilities {
+                supports_delete: false,
+                supports_schema_evolution: false,
+                supports_transactions: false,
+                idempotent: false,
+                requires_key: false,
+            }
+        }
+    }
+
+    /// A sink which records the tables it was asked to create, truncate and
+    /// reset and which reports `copied_tables` as already copied
+    #[derive(Default)]
+    struct RecordingSink {
+        copied_tables: HashSet<TableId>,
+        written_table_ids: Vec<TableId>,
+        written_column_names: HashMap<TableId, Vec<String>>,
+        truncated_table_ids: Vec<TableId>,
+        reset_table_ids: Vec<TableId>,
+        written_rows: Vec<TableRow>,
+        /// The lsn of the last change written before the pipeline started
+        last_lsn: u64,
+        /// How long writing table schemas takes, like a slow or hung sink
+        write_delay: Option<Duration>,
+        /// When the sink becomes ready, like a sink starting up alongside
+        /// the pipeline. `None` is ready right away.
+        ready_at: Option<time::Instant>,
+        /// How often the sink was asked whether it is ready
+        ready_polls: usize,
+        /// Calls bracketing table copies, with the id of their table
+        copy_calls: Vec<(&'static str, TableId)>,
+        /// Column names of the schemas table copies began with
+        copy_column_names: HashMap<TableId, Vec<String>>,
+        /// Differences reported for every table, like a sink with tables
+        /// left over from another source
+        schema_diff: SchemaDiff,
+        /// Changes written, in order
+        written_events: Vec<CdcEvent>,
+        /// Access key id of the credentials last reloaded
+        access_key_id: Option<String>,
+        /// Access key id the sink had when each batch of rows was written
+        batch_access_key_ids: Vec<Option<String>>,
+        /// Credentials sent to the pipeline once the first batch of rows is
+        /// written, like credentials rotated while a table is copied
+        rotated_credentials: Option<(mpsc::Sender<SinkCredentials>, SinkCredentials)>,
+        /// Whether tables must have a key, which most tables of the tests lack
+        requires_key: bool,
+    }
+
+    #[async_trait]
+    impl BatchSink for RecordingSink {
+        type Error = InfallibleSinkError;
+
+        async fn ready(&mut self) -> Result<bool, Self::Error> {
+            self.ready_polls += 1;
+            Ok(self
+                .ready_at
+                .is_none_or(|ready_at| time::Instant::now() >= ready_at))
+        }
+
+        async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+            Ok(PipelineResumptionState {
+                copied_tables: self.copied_tables.clone(),
+                last_lsn: PgLsn::from(self.last_lsn),
+            })
+        }
+
+        async fn write_table_schemas(
+            &mut self,
+            table_schemas: HashMap<TableId, TableSchema>,
+        ) -> Result<(), Self::Error> {
+            if let Some(write_delay) = self.write_delay {
+                tokio::time::sleep(write_delay).await;
+            }
+            self.written_table_ids.extend(table_schemas.keys());
+            for table_schema in table_schemas.values() {
+                let column_names = table_schema
+                    .column_schemas
+                    .iter()
+                    .map(|column_schema| column_schema.name.clone())
+                    .collect();
+                self.written_column_names
+                    .insert(table_schema.table_id, column_names);
+            }
+            Ok(())
+        }
+
+        async fn write_table_rows(
+            &mut self,
+            rows: Vec<TableRow>,
+            _table_id: TableId,
+        ) -> Result<(), Self::Error> {
+            self.written_rows.extend(rows);
+            self.batch_access_key_ids.push(self.access_key_id.clone());
+            if let Some((credentials_sender, credentials)) = self.rotated_credentials.take() {
+                credentials_sender.send(credentials).await.unwrap();
+            }
+            Ok(())
+        }
+
+        async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+            self.written_events.extend(events);
+            Ok(PgLsn::from(self.last_lsn))
+        }
+
+        async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+            self.copy_calls.push(("table_copied", table_id));
+            self.copied_tables.insert(table_id);
+            Ok(())
+        }
+
+        async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+            self.copy_calls.push(("truncate_table", table_id));
+            self.truncated_table_ids.push(table_id);
+            Ok(())
+        }
+
+        async fn begin_table_copy(
+            &mut self,
+            table_id: TableId,
+            table_schema: &TableSchema,
+        ) -> Result<(), Self::Error> {
+            self.copy_calls.push(("begin_table_copy", table_id));
+            let column_names = table_schema
+                .column_schemas
+                .iter()
+                .map(|column_schema| column_schema.name.clone())
+                .collect();
+            self.copy_column_names.insert(table_id, column_names);
+            Ok(())
+        }
+
+        async fn end_table_copy(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+            self.copy_calls.push(("end_table_copy", table_id));
+            Ok(())
+        }
+
+        async fn reset_table(&mut self, table_id: TableId) -> Result<bool, Self::Error> {
+            self.reset_table_ids.push(table_id);
+            self.copied_tables.remove(&table_id);
+            Ok(true)
+        }
+
+        fn capabilities(&self) -> SinkCapabilities {
+            SinkCapabilities {
+                supports_delete: true,
+                supports_schema_evolution: false,
+                supports_transactions: false,
+                idempotent: false,
+                requires_key: self.requires_key,
+            }
+        }
+
+        async fn validate_schema(
+            &mut self,
+            _table_schema: &TableSchema,
+        ) -> Result<SchemaDiff, Self::Error> {
+            Ok(self.schema_diff.clone())
+        }
+
+        async fn row_count(&mut self, _table_id: TableId) -> Result<Option<u64>, Self::Error> {
+            Ok(Some(self.written_rows.len() as u64))
+        }
+
+        async fn reload_credentials(
+            &mut self,
+            credentials: SinkCredentials,
+        ) -> Result<(), Self::Error> {
+            if let SinkCredentials::AccessKey { access_key_id, .. } = credentials {
+                self.access_key_id = Some(access_key_id);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn sink_without_deletes_is_rejected_for_a_source_publishing_deletes() {
+        let source = TestSource {
+            table_schemas: HashMap::new(),
+            publishes_deletes: true,
+            calls: Mutex::new(vec![]),
+            copy_rows: vec![],
+            slot_position: None,
+            snapshot_lsn: None,
+            wal_lsn: Mutex::new(0),
+            cdc_messages: vec![],
+            row_count: None,
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline =
+            BatchDataPipeline::new(source, AppendOnlySink, PipelineAction::Both, batch_config);
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(result, Err(PipelineError::DeletesNotSupported)));
+    }
+
+    #[tokio::test]
+    async fn only_named_tables_of_the_source_are_copied() {
+        let source = TestSource::with_tables(&["a", "b", "c"]);
+        // b is already copied, so copying a or c would truncate them first
+        let sink = RecordingSink {
+            copied_tables: HashSet::from([2]),
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline =
+            BatchDataPipeline::new(source, sink, PipelineAction::TableCopiesOnly, batch_config);
+        pipeline.set_tables(vec![table_name("b")]);
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(pipeline.sink.written_table_ids, vec![2]);
+        assert!(pipeline.sink.truncated_table_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tables_are_copied_in_one_transaction_by_default() {
+        let source = TestSource::with_tables(&["a", "b"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+
+        pipeline.start().await.unwrap();
+
+        let calls = pipeline.source.calls.lock().unwrap();
+        assert_eq!(*calls, vec!["copy a", "copy b", "commit"]);
+    }
+
+    #[tokio::test]
+    async fn per_table_snapshots_commit_after_each_table() {
+        let source = TestSource::with_tables(&["a", "b", "c"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.set_table_copy_snapshot(TableCopySnapshot::PerTable);
+        pipeline.sink.copied_tables = HashSet::from([2]);
+
+        pipeline.start().await.unwrap();
+
+        let calls = pipeline.source.calls.lock().unwrap();
+        assert_eq!(
+            *calls,
+            vec!["copy a", "commit", "begin", "copy c", "commit"]
+        );
+        assert_eq!(pipeline.sink.copied_tables, HashSet::from([1, 2, 3]));
+    }
+
+    /// A pipeline copying `customers` and `orders`, whose rows reference
+    /// customers, from a slot's snapshot at lsn 100
+    fn pipeline_copying_related_tables(
+        table_copy_snapshot: TableCopySnapshot,
+    ) -> BatchDataPipeline<TestSource, RecordingSink> {
+        let mut source = TestSource::with_tables(&["customers", "orders"]);
+        source.snapshot_lsn = Some(PgLsn::from(100));
+        *source.wal_lsn.lock().unwrap() = 100;
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.set_table_copy_snapshot(table_copy_snapshot);
+        pipeline.set_snapshot_consistency_policy(SnapshotConsistencyPolicy::Fail);
+        pipeline
+    }
+
+    #[tokio::test]
+    async fn tables_copied_from_one_snapshot_are_consistent() {
+        let mut pipeline = pipeline_copying_related_tables(TableCopySnapshot::Shared);
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(pipeline.sink.copied_tables, HashSet::from([1, 2]));
+    }
+
+    #[tokio::test]
+    async fn tables_copied_from_per_table_snapshots_are_inconsistent() {
+        let mut pipeline = pipeline_copying_related_tables(TableCopySnapshot::PerTable);
+
+        let result = pipeline.start().await;
+
+        let Err(PipelineError::InconsistentSnapshots(snapshot_lsns)) = result else {
+            panic!("tables from different snapshots were not flagged");
+        };
+        assert_eq!(
+            snapshot_lsns,
+            vec![
+                (table_name("customers"), PgLsn::from(100)),
+                (table_name("orders"), PgLsn::from(101)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn table_copies_are_bracketed_by_the_copy_lifecycle_calls() {
+        let source = TestSource::with_tables(&["a", "b", "c"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.sink.copied_tables = HashSet::from([2]);
+
+        pipeline.start().await.unwrap();
+
+        let mut expected = vec![];
+        for table_id in [1, 3] {
+            expected.extend([
+                ("truncate_table", table_id),
+                ("begin_table_copy", table_id),
+                ("end_table_copy", table_id),
+                ("table_copied", table_id),
+            ]);
+        }
+        assert_eq!(pipeline.sink.copy_calls, expected);
+    }
+
+    #[tokio::test]
+    async fn column_with_another_type_in_the_sink_stops_the_pipeline() {
+        let source = TestSource::with_tables(&["a"]);
+        let schema_diff = SchemaDiff {
+            type_mismatches: vec![ColumnTypeMismatch {
+                column_name: "id".to_string(),
+                expected: "INT64".to_string(),
+                actual: "STRING".to_string(),
+            }],
+            ..Default::default()
+        };
+        let sink = RecordingSink {
+            schema_diff: schema_diff.clone(),
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline =
+            BatchDataPipeline::new(source, sink, PipelineAction::TableCopiesOnly, batch_config);
+        pipeline.set_schema_mismatch_policy(SchemaMismatchPolicy::Fail);
+
+        let result = pipeline.start().await;
+
+        let Err(PipelineError::SchemaMismatch(mismatched_table, diff)) = result else {
+            panic!("pipeline started despite the schema mismatch");
+        };
+        assert_eq!(mismatched_table, table_name("a"));
+        assert_eq!(diff, schema_diff);
+        assert!(pipeline.sink.truncated_table_ids.is_empty());
+        assert!(pipeline.sink.written_rows.is_empty());
+    }
+
+    /// Copies a table with two rows which can't be converted among its rows,
+    /// skipping those with a threshold of `max_dead_letters`
+    fn copy_with_dead_letter_threshold(
+        max_dead_letters: usize,
+    ) -> BatchDataPipeline<TestSource, RecordingSink> {
+        let mut source = source_with_columns();
+        let table_schema = source.table_schemas.get_mut(&1).unwrap();
+        table_schema.column_schemas = vec![column_schema("id")];
+        source.copy_rows = vec!["1\n", "x\n", "2\n", "y\n", "3\n"];
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.set_dead_letter_policy(DeadLetterPolicy::Skip(Box::new(LoggingDeadLetterSink)));
+        pipeline.set_dead_letter_threshold(DeadLetterThreshold::new(
+            max_dead_letters,
+            Duration::from_secs(60),
+        ));
+        pipeline
+    }
+
+    #[tokio::test]
+    async fn dead_letters_within_the_threshold_are_skipped() {
+        let mut pipeline = copy_with_dead_letter_threshold(2);
+
+        pipeline.start().await.unwrap();
+
+        let ids: Vec<Cell> = pipeline
+            .sink
+            .written_rows
+            .iter()
+            .map(|row| row.values[0].clone())
+            .collect();
+        assert_eq!(ids, vec![Cell::I32(1), Cell::I32(2), Cell::I32(3)]);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_past_the_threshold_stop_the_pipeline() {
+        let mut pipeline = copy_with_dead_letter_threshold(1);
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(
+            result,
+            Err(PipelineError::DeadLetterThresholdExceeded(_))
+        ));
+        assert!(pipeline.sink.written_rows.is_empty());
+    }
+
+    /// A dead letter sink which keeps the records it was given
+    #[derive(Clone, Default)]
+    struct RecordingDeadLetterSink(Arc<Mutex<Vec<DeadLetter>>>);
+
+    #[async_trait]
+    impl DeadLetterSink for RecordingDeadLetterSink {
+        async fn write_dead_letter(
+            &mut self,
+            dead_letter: DeadLetter,
+        ) -> Result<(), DeadLetterSinkError> {
+            self.0.lock().unwrap().push(dead_letter);
+            Ok(())
+        }
+    }
+
+    /// A pgoutput Insert into table `a`, of [`source_with_id_column`], with
+    /// `id` as the text of its only column
+    fn insert_message(id: &str) -> Bytes {
+        let mut insert = BytesMut::new();
+        insert.put_u8(b'I');
+        insert.put_u32(1);
+        insert.put_u8(b'N');
+        insert.put_u16(1);
+        insert.put_u8(b't');
+        insert.put_u32(id.len() as u32);
+        insert.put_slice(id.as_bytes());
+        insert.freeze()
+    }
+
+    /// The messages of a transaction `xid` committed at `commit_lsn` with the
+    /// messages of its `changes`
+    fn transaction_messages(commit_lsn: u64, xid: u32, changes: Vec<Bytes>) -> Vec<Bytes> {
+        let mut begin = BytesMut::new();
+        begin.put_u8(b'B');
+        begin.put_u64(commit_lsn);
+        begin.put_i64(1_000_000);
+        begin.put_u32(xid);
+        let mut commit = BytesMut::new();
+        commit.put_u8(b'C');
+        commit.put_u8(0);
+        commit.put_u64(commit_lsn);
+        commit.put_u64(commit_lsn + 8);
+        commit.put_i64(1_000_000);
+        let mut messages = vec![begin.freeze()];
+        messages.extend(changes);
+        messages.push(commit.freeze());
+        messages
+    }
+
+    /// A source with a table `a` with an int4 column `id`, which streams a
+    /// transaction inserting the rows `ids`
+    fn source_with_id_column(ids: &[&str]) -> TestSource {
+        let mut source = TestSource::with_tables(&["a"]);
+        let table_schema = source.table_schemas.get_mut(&1).unwrap();
+        table_schema.column_schemas = vec![column_schema("id")];
+        let mut relation = BytesMut::new();
+        relation.put_u8(b'R');
+        relation.put_u32(1);
+        relation.put_slice(b"public\0");
+        relation.put_slice(b"a\0");
+        relation.put_u8(b'd');
+        relation.put_u16(1);
+        relation.put_u8(0);
+        relation.put_slice(b"id\0");
+        relation.put_u32(23);
+        relation.put_i32(-1);
+        let mut changes = vec![relation.freeze()];
+        changes.extend(ids.iter().map(|id| insert_message(id)));
+        source.cdc_messages = transaction_messages(0x100, 700, changes);
+        source
+    }
+
+    #[tokio::test]
+    async fn malformed_change_is_dead_lettered_while_the_others_are_written() {
+        let source = source_with_id_column(&["1", "x", "2"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::CdcOnly,
+            batch_config,
+        );
+        let dead_letters = RecordingDeadLetterSink::default();
+        pipeline.set_dead_letter_policy(DeadLetterPolicy::Skip(Box::new(dead_letters.clone())));
+
+        pipeline.start().await.unwrap();
+
+        let inserted: Vec<Cell> = pipeline
+            .sink
+            .written_events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((_, row, _)) => Some(row.values[0].clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(inserted, vec![Cell::I32(1), Cell::I32(2)]);
+        let dead_letters = dead_letters.0.lock().unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].table_id, Some(1));
+        let DeadLetterPayload::Tuple(tuple) = &dead_letters[0].payload else {
+            panic!("expected the tuple of the change");
+        };
+        assert_eq!(tuple, &vec![Some(Bytes::from_static(b"x"))]);
+    }
+
+    #[tokio::test]
+    async fn malformed_change_stops_the_pipeline_by_default() {
+        let source = source_with_id_column(&["1", "x", "2"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::CdcOnly,
+            batch_config,
+        );
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(result, Err(PipelineError::CommonSource(_))));
+    }
+
+    #[tokio::test]
+    async fn named_table_missing_from_the_source_is_rejected() {
+        let source = TestSource::with_tables(&["a", "b", "c"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::Both,
+            batch_config,
+        );
+        pipeline.set_tables(vec![table_name("b"), table_name("d")]);
+
+        let result = pipeline.start().await;
+
+        assert!(
+            matches!(result, Err(PipelineError::TableNotInSource(name)) if name == table_name("d"))
+        );
+    }
+
+    #[tokio::test]
+    async fn logical_message_reaches_the_sink() {
+        let mut source = TestSource::with_tables(&[]);
+        let mut begin = BytesMut::new();
+        begin.put_u8(b'B');
+        begin.put_u64(0x108);
+        begin.put_i64(1_000_000);
+        begin.put_u32(700);
+        let mut message = BytesMut::new();
+        message.put_u8(b'M');
+        message.put_u8(1);
+        message.put_u64(0x100);
+        message.put_slice(b"checkpoint\0");
+        message.put_u32(2);
+        message.put_slice(b"42");
+        let mut commit = BytesMut::new();
+        commit.put_u8(b'C');
+        commit.put_u8(0);
+        commit.put_u64(0x100);
+        commit.put_u64(0x108);
+        commit.put_i64(1_000_000);
+        source.cdc_messages = vec![begin.freeze(), message.freeze(), commit.freeze()];
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::CdcOnly,
+            batch_config,
+        );
+
+        pipeline.start().await.unwrap();
+
+        let messages: Vec<_> = pipeline
+            .sink
+            .written_events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::LogicalMessage {
+                    transactional,
+                    prefix,
+                    content,
+                } => Some((*transactional, prefix.as_str(), content.as_slice())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(messages, vec![(true, "checkpoint", b"42".as_slice())]);
+    }
+
+    #[tokio::test]
+    async fn table_without_a_key_is_refused_by_a_sink_requiring_one() {
+        let mut source = TestSource::with_tables(&["orders", "events"]);
+        let orders = source.table_schemas.get_mut(&1).unwrap();
+        orders.column_schemas = vec![ColumnSchema {
+            primary: true,
+            ..column_schema("id")
+        }];
+        let sink = RecordingSink {
+            requires_key: true,
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(source, sink, PipelineAction::Both, batch_config);
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(
+            result,
+            Err(PipelineError::MissingKey(table_names))
+                if table_names == vec![table_name("events")]
+        ));
+        assert!(pipeline.sink.written_table_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resynced_table_is_reset_without_touching_the_others() {
+        let source = TestSource::with_tables(&["a", "b", "c"]);
+        let sink = RecordingSink {
+            copied_tables: HashSet::from([1, 2, 3]),
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(source, sink, PipelineAction::Both, batch_config);
+        let (resync_sender, resync_receiver) = mpsc::channel(10);
+        pipeline.set_resync_receiver(resync_receiver);
+        resync_sender.send(2).await.unwrap();
+
+        let reset_tables = pipeline.reset_resynced_tables().await.unwrap();
+
+        assert_eq!(reset_tables, HashSet::from([2]));
+        assert_eq!(pipeline.sink.reset_table_ids, vec![2]);
+        assert!(pipeline.sink.truncated_table_ids.is_empty());
+        // Only b is copied again when the pipeline starts
+        let resumption_state = pipeline.sink.get_resumption_state().await.unwrap();
+        assert_eq!(resumption_state.copied_tables, HashSet::from([1, 3]));
+    }
+
+    #[tokio::test]
+    async fn resync_of_a_table_which_is_not_replicated_is_ignored() {
+        let source = TestSource::with_tables(&["a", "b", "c"]);
+        let sink = RecordingSink {
+            copied_tables: HashSet::from([1, 2, 3]),
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(source, sink, PipelineAction::Both, batch_config);
+        pipeline.replicated_tables = Some(HashSet::from([1]));
+        let (resync_sender, resync_receiver) = mpsc::channel(10);
+        pipeline.set_resync_receiver(resync_receiver);
+        resync_sender.send(2).await.unwrap();
+        resync_sender.send(4).await.unwrap();
+
+        let reset_tables = pipeline.reset_resynced_tables().await.unwrap();
+
+        assert!(reset_tables.is_empty());
+        assert!(pipeline.sink.reset_table_ids.is_empty());
+    }
+
+    fn column_schema(name: &str) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ: Type::INT4,
+            modifier: -1,
+            nullable: true,
+            primary: false,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    /// A source with a table `a` with the columns `id`, `usr` and `user_id`
+    fn source_with_columns() -> TestSource {
+        let mut source = TestSource::with_tables(&["a"]);
+        let table_schema = source.table_schemas.get_mut(&1).unwrap();
+        table_schema.column_schemas = vec![
+            column_schema("id"),
+            column_schema("usr"),
+            column_schema("user_id"),
+        ];
+        source
+    }
+
+    #[tokio::test]
+    async fn renamed_column_has_its_new_name_in_the_sink() {
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source_with_columns(),
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.sink.copied_tables = HashSet::from([1]);
+        pipeline.set_column_renames(HashMap::from([
+            ((1, "usr".to_string()), "owner_id".to_string()),
+            ((1, "user_id".to_string()), "usr".to_string()),
+        ]));
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(
+            pipeline.sink.written_column_names[&1],
+            vec!["id", "owner_id", "usr"]
+        );
+        // the source's schema, which rows are read with, keeps the original names
+        let source_column_names: Vec<&str> = pipeline.source.table_schemas[&1]
+            .column_schemas
+            .iter()
+            .map(|column_schema| column_schema.name.as_str())
+            .collect();
+        assert_eq!(source_column_names, vec!["id", "usr", "user_id"]);
+    }
+
+    #[tokio::test]
+    async fn table_copy_begins_with_the_renamed_columns() {
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source_with_columns(),
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.set_column_renames(HashMap::from([(
+            (1, "usr".to_string()),
+            "owner_id".to_string(),
+        )]));
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(
+            pipeline.sink.copy_column_names[&1],
+            vec!["id", "owner_id", "user_id"]
+        );
+    }
+
+    #[tokio::test]
+    async fn long_column_names_are_shortened_to_distinct_names_in_the_sink() {
+        let mut source = TestSource::with_tables(&["a"]);
+        source.table_schemas.get_mut(&1).unwrap().column_schemas = vec![
+            column_schema("id"),
+            column_schema("total_amount_including_tax_in_eur"),
+            column_schema("total_amount_including_tax_in_usd"),
+        ];
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.sink.copied_tables = HashSet::from([1]);
+        pipeline.set_max_identifier_length(20);
+
+        pipeline.start().await.unwrap();
+
+        let column_names = &pipeline.sink.written_column_names[&1];
+        assert_eq!(column_names[0], "id");
+        assert!(column_names[1..]
+            .iter()
+            .all(|name| name.len() <= 20 && name.starts_with("total_amoun_")));
+        assert_ne!(column_names[1], column_names[2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_sink_write_times_out_with_a_transient_error() {
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            TestSource::with_tables(&["a"]),
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.sink.write_delay = Some(Duration::from_secs(3600));
+        pipeline.set_sink_write_timeout(Duration::from_secs(30));
+
+        let result = timeout(Duration::from_secs(60), pipeline.start())
+            .await
+            .expect("pipeline hung on the sink write");
+
+        let error = result.expect_err("sink write didn't time out");
+        assert!(matches!(
+            error,
+            PipelineError::SinkTimeout {
+                operation: "write_table_schemas",
+                ..
+            }
+        ));
+        assert!(error.is_transient());
+        assert!(pipeline.sink.written_table_ids.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pipeline_waits_for_the_sink_to_be_ready() {
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            TestSource::with_tables(&["a"]),
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        let started = time::Instant::now();
+        pipeline.sink.ready_at = Some(started + Duration::from_secs(5));
+        pipeline.set_sink_ready_timeout(Duration::from_secs(60));
+
+        pipeline.start().await.expect("pipeline failed");
+
+        // polled right away and after waiting 1, 2 and 4 seconds
+        assert_eq!(pipeline.sink.ready_polls, 4);
+        assert_eq!(started.elapsed(), Duration::from_secs(7));
+        assert_eq!(pipeline.sink.written_table_ids, vec![1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sink_which_isnt_ready_in_time_fails_the_pipeline() {
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            TestSource::with_tables(&["a"]),
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        let started = time::Instant::now();
+        pipeline.sink.ready_at = Some(started + Duration::from_secs(3600));
+        pipeline.set_sink_ready_timeout(Duration::from_secs(60));
+
+        let error = pipeline.start().await.expect_err("sink was ready");
+
+        assert!(matches!(error, PipelineError::SinkNotReady(_)));
+        assert!(error.is_transient());
+        assert_eq!(started.elapsed(), Duration::from_secs(60));
+        assert!(pipeline.sink.written_table_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn renamed_column_colliding_with_another_column_is_rejected() {
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source_with_columns(),
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.set_column_renames(HashMap::from([(
+            (1, "usr".to_string()),
+            "user_id".to_string(),
+        )]));
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(
+            result,
+            Err(PipelineError::ColumnNameCollision { column_name, .. }) if column_name == "user_id"
+        ));
+        assert!(pipeline.sink.written_table_ids.is_empty());
+    }
+
+    /// A pipeline copying table `a`, copied in an earlier run, and streaming
+    /// changes from a slot which retains WAL from lsn 200 on and confirmed
+    /// changes up to 300, into a sink which has seen changes up to `sink_lsn`
+    fn pipeline_with_slot_ahead_of(sink_lsn: u64) -> BatchDataPipeline<TestSource, RecordingSink> {
+        let mut source = TestSource::with_tables(&["a"]);
+        source.slot_position = Some(SlotPosition {
+            restart_lsn: PgLsn::from(200),
+            confirmed_flush_lsn: PgLsn::from(300),
+        });
+        let sink = RecordingSink {
+            copied_tables: HashSet::from([1]),
+            last_lsn: sink_lsn,
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        BatchDataPipeline::new(source, sink, PipelineAction::Both, batch_config)
+    }
+
+    #[tokio::test]
+    async fn sink_behind_the_slots_retained_wal_stops_the_pipeline() {
+        let mut pipeline = pipeline_with_slot_ahead_of(100);
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(
+            result,
+            Err(PipelineError::SlotAheadOfSink { sink_lsn, restart_lsn, confirmed_flush_lsn })
+                if sink_lsn == PgLsn::from(100)
+                    && restart_lsn == PgLsn::from(200)
+                    && confirmed_flush_lsn == PgLsn::from(300)
+        ));
+        assert!(pipeline.source.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn slot_gap_with_the_recopy_policy_resets_copied_tables() {
+        let mut pipeline = pipeline_with_slot_ahead_of(100);
+        pipeline.set_slot_gap_policy(SlotGapPolicy::Recopy);
+        let mut copied_tables = HashSet::from([1]);
+
+        pipeline
+            .reconcile_slot_position(&mut copied_tables, PgLsn::from(100))
+            .await
+            .unwrap();
+
+        assert!(copied_tables.is_empty());
+        assert_eq!(pipeline.sink.reset_table_ids, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn sink_which_never_streamed_is_not_behind_an_existing_slot() {
+        let mut pipeline = pipeline_with_slot_ahead_of(0);
+        let mut copied_tables = HashSet::from([1]);
+
+        pipeline
+            .reconcile_slot_position(&mut copied_tables, PgLsn::from(0))
+            .await
+            .unwrap();
+
+        assert_eq!(copied_tables, HashSet::from([1]));
+        assert!(pipeline.sink.reset_table_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pipeline_spans_are_exported_as_children_of_the_pipeline_span() {
+        let exporter = InMemorySpanExporter::default();
+        let tracer_provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let source = TestSource::with_tables(&["a"]);
+        let sink = RecordingSink {
+            copied_tables: HashSet::from([1]),
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline =
+            BatchDataPipeline::new(source, sink, PipelineAction::TableCopiesOnly, batch_config);
+
+        let guard = tracing::subscriber::set_default(subscriber);
+        pipeline.start().await.unwrap();
+        drop(guard);
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = |name: &str| {
+            spans
+                .iter()
+                .find(|span| span.name == name)
+                .unwrap_or_else(|| panic!("no {name} span was exported"))
+        };
+        let pipeline_span = span("pipeline").span_context.clone();
+        for name in ["copy_table_schemas", "copy_tables"] {
+            let child = span(name);
+            assert_eq!(child.parent_span_id, pipeline_span.span_id());
+            assert_eq!(child.span_context.trace_id(), pipeline_span.trace_id());
+        }
+    }
+
+    #[test]
+    fn cdc_row_events_of_other_tables_are_skipped() {
+        type Pipeline = BatchDataPipeline<TestSource, RecordingSink>;
+        let replicated_tables = Some(HashSet::from([2]));
+        let insert = |table_id| CdcEvent::Insert((table_id, TableRow { values: vec![] }, None));
+
+        assert!(!Pipeline::is_event_replicated(
+            &replicated_tables,
+            &insert(1)
+        ));
+        assert!(Pipeline::is_event_replicated(
+            &replicated_tables,
+            &insert(2)
+        ));
+        assert!(!Pipeline::is_event_replicated(
+            &replicated_tables,
+            &insert(3)
+        ));
+        // Keepalives must still reach the sink for the slot to progress
+        let keepalive = CdcEvent::KeepAliveRequested { reply: true };
+        assert!(Pipeline::is_event_replicated(
+            &replicated_tables,
+            &keepalive
+        ));
+        assert!(Pipeline::is_event_replicated(&None, &insert(1)));
+    }
+
+    #[test]
+    fn only_inserts_of_an_insert_only_table_are_applied() {
+        type Pipeline = BatchDataPipeline<TestSource, RecordingSink>;
+        let insert_only = TableOperations {
+            insert: true,
+            update: false,
+            delete: false,
+            truncate: false,
+        };
+        let table_operations = HashMap::from([(1, insert_only)]);
+        let insert = |table_id| CdcEvent::Insert((table_id, TableRow { values: vec![] }, None));
+        let update = |table_id| CdcEvent::Update {
+            table_id,
+            old_row: None,
+            key_row: None,
+            row: TableRow { values: vec![] },
+            key_columns: vec![],
+            transaction_info: None,
+        };
+        let delete = |table_id| CdcEvent::Delete {
+            table_id,
+            row: TableRow { values: vec![] },
+            key_columns: vec![],
+            transaction_info: None,
+        };
+
+        assert!(Pipeline::is_operation_applied(
+            &table_operations,
+            &insert(1)
+        ));
+        assert!(!Pipeline::is_operation_applied(
+            &table_operations,
+            &update(1)
+        ));
+        assert!(!Pipeline::is_operation_applied(
+            &table_operations,
+            &delete(1)
+        ));
+        // Other tables have all their changes applied
+        assert!(Pipeline::is_operation_applied(
+            &table_operations,
+            &update(2)
+        ));
+        assert!(Pipeline::is_operation_applied(
+            &table_operations,
+            &delete(2)
+        ));
+        // Keepalives must still reach the sink for the slot to progress
+        let keepalive = CdcEvent::KeepAliveRequested { reply: true };
+        assert!(Pipeline::is_operation_applied(
+            &table_operations,
+            &keepalive
+        ));
+    }
+
+    #[tokio::test]
+    async fn skipping_inserts_but_not_updates_is_rejected_for_an_upserting_sink() {
+        let source = TestSource::with_tables(&["a"]);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::Both,
+            batch_config,
+        );
+        let updates_only = TableOperations {
+            insert: false,
+            ..TableOperations::default()
+        };
+        pipeline.set_table_operations(HashMap::from([(1, updates_only)]));
+
+        let result = pipeline.start().await;
+
+        assert!(matches!(
+            result,
+            Err(PipelineError::IncoherentTableOperations(name, _)) if name == table_name("a")
+        ));
+        assert!(pipeline.sink.written_table_ids.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reading_pauses_while_max_in_flight_batches_are_unwritten() {
+        let batches_read = AtomicUsize::new(0);
+        let batches = futures::stream::iter(0..10).inspect(|_| {
+            batches_read.fetch_add(1, Ordering::Relaxed);
+        });
+        let (batch_sender, mut batch_receiver) = mpsc::unbounded_channel();
+        let read = read_batches(batches, Arc::new(Semaphore::new(3)), batch_sender);
+        pin!(read);
+
+        // A stalled sink writes nothing, so reading stops after three batches
+        let result = timeout(Duration::from_secs(60), read.as_mut()).await;
+        assert!(result.is_err());
+        assert_eq!(batches_read.load(Ordering::Relaxed), 3);
+
+        // Writing a batch releases its permit and lets one more be read
+        let (batch, permit) = batch_receiver.recv().await.unwrap();
+        assert_eq!(batch, 0);
+        drop(permit);
+        let result = timeout(Duration::from_secs(60), read.as_mut()).await;
+        assert!(result.is_err());
+        assert_eq!(batches_read.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn reloaded_credentials_are_used_from_the_next_batch_on() {
+        let mut source = source_with_columns();
+        let table_schema = source.table_schemas.get_mut(&1).unwrap();
+        table_schema.column_schemas = vec![column_schema("id")];
+        source.copy_rows = vec!["1\n", "2\n", "3\n"];
+        let (credentials_sender, credentials_receiver) = mpsc::channel(1);
+        let sink = RecordingSink {
+            rotated_credentials: Some((
+                credentials_sender,
+                SinkCredentials::AccessKey {
+                    access_key_id: "rotated".to_string(),
+                    secret_access_key: "secret".to_string(),
+                },
+            )),
+            ..Default::default()
+        };
+        let batch_config = BatchConfig::new(1, Duration::from_secs(10));
+        let mut pipeline =
+            BatchDataPipeline::new(source, sink, PipelineAction::TableCopiesOnly, batch_config);
+        pipeline.set_credentials_receiver(credentials_receiver);
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(pipeline.sink.written_rows.len(), 3);
+        let rotated = Some("rotated".to_string());
+        assert_eq!(
+            pipeline.sink.batch_access_key_ids,
+            vec![None, rotated.clone(), rotated]
+        );
+    }
+
+    #[tokio::test]
+    async fn row_committed_before_the_snapshot_is_written_once_after_the_handoff() {
+        let mut source = source_with_columns();
+        let table_schema = source.table_schemas.get_mut(&1).unwrap();
+        table_schema.column_schemas = vec![column_schema("id")];
+        // Row 2 was inserted while the table was copied. Its transaction
+        // committed before the snapshot at 0x150, so the copy has it, but it
+        // is still in the WAL along with row 3's, which committed afterwards.
+        source.snapshot_lsn = Some(PgLsn::from(0x150));
+        source.copy_rows = vec!["1\n", "2\n"];
+        source.cdc_messages = transaction_messages(0x100, 700, vec![insert_message("2")]);
+        source
+            .cdc_messages
+            .extend(transaction_messages(0x200, 701, vec![insert_message("3")]));
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::Both,
+            batch_config,
+        );
+
+        pipeline.start().await.unwrap();
+
+        let copied = pipeline.sink.written_rows.iter();
+        let streamed = pipeline
+            .sink
+            .written_events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((_, row, _)) => Some(row),
+                _ => None,
+            });
+        let ids: Vec<_> = copied.chain(streamed).map(|row| &row.values[0]).collect();
+        assert_eq!(ids, vec![&Cell::I32(1), &Cell::I32(2), &Cell::I32(3)]);
+    }
+
+    #[tokio::test]
+    async fn row_missing_from_the_copy_stops_the_pipeline() {
+        let mut source = source_with_columns();
+        let table_schema = source.table_schemas.get_mut(&1).unwrap();
+        table_schema.column_schemas = vec![column_schema("id")];
+        source.copy_rows = vec!["1\n", "2\n"];
+        // the source has a row which the copy didn't return
+        source.row_count = Some(3);
+        let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+        let mut pipeline = BatchDataPipeline::new(
+            source,
+            RecordingSink::default(),
+            PipelineAction::TableCopiesOnly,
+            batch_config,
+        );
+        pipeline.set_copy_reconciliation_policy(CopyReconciliationPolicy::Fail);
+
+        let result = pipeline.start().await;
+
+        let Err(PipelineError::RowCountMismatch {
+            table_name: mismatched_table,
+            source_rows,
+            sink_rows,
+        }) = result
+        else {
+            panic!("expected a row count mismatch");
+        };
+        assert_eq!(mismatched_table, table_name("a"));
+        assert_eq!((source_rows, sink_rows), (3, 2));
+    }
+
+    #[cfg(feature = "postgres_tests")]
+    mod postgres {
+        use std::time::Duration;
+
+        use crate::{
+            clients::postgres::{
+                test_database::{connection_settings, sql_client, unique_name},
+                ConnectionOptions, SlotOptions,
+            },
+            pipeline::{
+                batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+                sources::postgres::{PostgresSource, TableNamesFrom},
+                PipelineAction, PipelineError, ReplicaIdentityPolicy,
+            },
+        };
+
+        use super::RecordingSink;
+
+        #[tokio::test]
+        async fn tables_without_replica_identity_are_reported_before_streaming() {
+            let sql_client = sql_client().await;
+            let orders = unique_name("test_orders");
+            let events = unique_name("test_events");
+            let publication = unique_name("test_publication");
+            sql_client
+                .batch_execute(&format!(
+                    "create table {orders} (id int primary key);
+                    create table {events} (id int primary key);
+                    alter table {events} replica identity nothing;
+                    create publication {publication} for table {orders}, {events};"
+                ))
+                .await
+                .unwrap();
+            let (host, port, username, password) = connection_settings();
+            let source = PostgresSource::new(
+                &host,
+                port,
+                "postgres",
+                &username,
+                Some(password),
+                Some(unique_name("test_slot")),
+                SlotOptions::Temporary,
+                TableNamesFrom::Publication(publication.clone()),
+                &ConnectionOptions::default(),
+            )
+            .await;
+            let result = match source {
+                Ok(source) => {
+                    let batch_config = BatchConfig::new(1000, Duration::from_secs(10));
+                    let mut pipeline = BatchDataPipeline::new(
+                        source,
+                        RecordingSink::default(),
+                        PipelineAction::Both,
+                        batch_config,
+                    );
+                    pipeline.set_replica_identity_policy(ReplicaIdentityPolicy::Fail);
+                    let result = pipeline.start().await;
+                    assert!(pipeline.sink.written_table_ids.is_empty());
+                    Ok(result)
+                }
+                Err(e) => Err(e),
+            };
+            sql_client
+                .batch_execute(&format!(
+                    "drop publication {publication}; drop table {orders}, {events};"
+                ))
+                .await
+                .unwrap();
+
+            let result = result.expect("failed to create the source");
+            match result {
+                Err(PipelineError::MissingReplicaIdentity(table_names)) => {
+                    let names: Vec<_> = table_names
+                        .iter()
+                        .map(|table_name| table_name.name.as_str())
+                        .collect();
+                    assert_eq!(names, vec![events.as_str()]);
+                }
+                other => panic!("expected the table without replica identity, got {other:?}"),
+            }
+        }
+    }
+}
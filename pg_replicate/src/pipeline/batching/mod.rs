@@ -1,11 +1,29 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
+use crate::table::TableId;
+
+use self::adaptive::AdaptiveBatchSize;
+
+pub mod adaptive;
 pub mod data_pipeline;
 pub mod stream;
 
 /// A trait to indicate which items in a stream can be the last in a batch.
 pub trait BatchBoundary: Sized {
     fn is_last_in_batch(&self) -> bool;
+
+    /// Whether the item shows that the stream has nothing more to send for
+    /// now. With [`BatchConfig::set_flush_on_idle`] a batch ending on such an
+    /// item is flushed right away.
+    fn is_idle(&self) -> bool {
+        false
+    }
+
+    /// The table the item belongs to, whose [`TableBatchConfig`] then
+    /// applies to the batch the item is in
+    fn table_id(&self) -> Option<TableId> {
+        None
+    }
 }
 
 // For an item wrapped in a result we fall back to the item
@@ -18,12 +36,38 @@ impl<T: BatchBoundary, E> BatchBoundary for Result<T, E> {
             Err(_) => true,
         }
     }
+
+    fn is_idle(&self) -> bool {
+        match self {
+            Ok(v) => v.is_idle(),
+            Err(_) => false,
+        }
+    }
+
+    fn table_id(&self) -> Option<TableId> {
+        match self {
+            Ok(v) => v.table_id(),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Batch settings of a table which differ from those of the pipeline. Those
+/// which are `None` are the pipeline's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableBatchConfig {
+    pub max_batch_size: Option<usize>,
+    pub max_batch_fill_time: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BatchConfig {
     max_batch_size: usize,
     max_batch_fill_time: Duration,
+    max_in_flight_batches: usize,
+    flush_on_idle: bool,
+    adaptive_batch_size: Option<AdaptiveBatchSize>,
+    table_configs: HashMap<TableId, TableBatchConfig>,
 }
 
 impl BatchConfig {
@@ -31,6 +75,98 @@ impl BatchConfig {
         BatchConfig {
             max_batch_size,
             max_batch_fill_time,
+            max_in_flight_batches: 1,
+            flush_on_idle: false,
+            adaptive_batch_size: None,
+            table_configs: HashMap::new(),
+        }
+    }
+
+    /// Table copy batches are read from the source while earlier ones are
+    /// written to the sink. Reading stops once `max_in_flight_batches` batches
+    /// were read but not yet written, which bounds memory use under a slow
+    /// sink. Cdc batches are always written one at a time because the lsn
+    /// confirmed to the source must follow the sink. Values below one are
+    /// treated as one.
+    pub fn set_max_in_flight_batches(&mut self, max_in_flight_batches: usize) {
+        self.max_in_flight_batches = max_in_flight_batches.max(1);
+    }
+
+    /// Cdc batches are flushed as soon as the source signals that it is idle,
+    /// with a keepalive, instead of when `max_batch_fill_time` elapses. The
+    /// lsn of the last change written is then confirmed to the source right
+    /// away, so a pipeline on a low traffic source doesn't hold back its
+    /// slot for the whole fill time.
+    pub fn set_flush_on_idle(&mut self, flush_on_idle: bool) {
+        self.flush_on_idle = flush_on_idle;
+    }
+
+    /// Adapts the batch size to how long the sink takes to write batches.
+    /// Batches start at `min_batch_size` rows and grow, up to
+    /// `max_batch_size`, while the sink writes them in less than
+    /// `target_write_time`, and shrink again when writes take longer. A sink
+    /// error stops the pipeline, so there are no failed writes to shrink on.
+    pub fn set_adaptive_batch_size(&mut self, min_batch_size: usize, target_write_time: Duration) {
+        self.adaptive_batch_size = Some(AdaptiveBatchSize::new(
+            min_batch_size,
+            self.max_batch_size,
+            target_write_time,
+        ));
+    }
+
+    pub fn adaptive_batch_size(&self) -> Option<&AdaptiveBatchSize> {
+        self.adaptive_batch_size.as_ref()
+    }
+
+    /// The size at which batches are flushed, which with an adaptive batch
+    /// size is its current size, if smaller than `max_batch_size`
+    pub fn max_batch_size(&self) -> usize {
+        self.capped_batch_size(self.max_batch_size)
+    }
+
+    fn capped_batch_size(&self, max_batch_size: usize) -> usize {
+        match &self.adaptive_batch_size {
+            Some(adaptive_batch_size) => adaptive_batch_size.current().min(max_batch_size),
+            None => max_batch_size,
         }
     }
+
+    /// Overrides the batch size and fill time for the table `table_id`, e.g.
+    /// to write the rows of a busy table in larger batches. A table copy is
+    /// batched with its table's settings. Cdc batches hold the changes of
+    /// several tables and are flushed with the smallest size and fill time of
+    /// the tables they hold changes of.
+    pub fn set_table_config(&mut self, table_id: TableId, table_config: TableBatchConfig) {
+        self.table_configs.insert(table_id, table_config);
+    }
+
+    /// Returns the config for batches of the table `table_id`, which has the
+    /// overrides of the table, if any, applied
+    pub fn for_table(&self, table_id: TableId) -> BatchConfig {
+        let mut batch_config = self.clone();
+        if let Some(table_config) = self.table_configs.get(&table_id) {
+            if let Some(max_batch_size) = table_config.max_batch_size {
+                batch_config.max_batch_size = max_batch_size;
+            }
+            if let Some(max_batch_fill_time) = table_config.max_batch_fill_time {
+                batch_config.max_batch_fill_time = max_batch_fill_time;
+            }
+        }
+        batch_config
+    }
+
+    /// Returns the batch size and fill time of the table `table_id`, those of
+    /// [`BatchConfig::for_table`], without cloning the config
+    pub fn table_batch_limits(&self, table_id: TableId) -> (usize, Duration) {
+        let table_config = self
+            .table_configs
+            .get(&table_id)
+            .copied()
+            .unwrap_or_default();
+        let max_batch_size = table_config.max_batch_size.unwrap_or(self.max_batch_size);
+        let max_batch_fill_time = table_config
+            .max_batch_fill_time
+            .unwrap_or(self.max_batch_fill_time);
+        (self.capped_batch_size(max_batch_size), max_batch_fill_time)
+    }
 }
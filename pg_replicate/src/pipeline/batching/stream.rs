@@ -1,6 +1,8 @@
 use futures::{ready, Future, Stream};
 use pin_project_lite::pin_project;
-use tokio::time::{sleep, Sleep};
+use std::time::Duration;
+
+use tokio::time::{sleep, sleep_until, Instant, Sleep};
 
 use core::pin::Pin;
 use core::task::{Context, Poll};
@@ -13,6 +15,17 @@ pin_project! {
     /// reaches max_size or when a timeout expires. The underlying streams items
     /// must implement [`BatchBoundary`]. A batch is guaranteed to end on an
     /// item which returns true from [`BatchBoundary::is_last_in_batch`]
+    ///
+    /// With [`BatchConfig::set_flush_on_idle`] a batch is also flushed
+    /// immediately on an item which returns true from
+    /// [`BatchBoundary::is_idle`].
+    ///
+    /// A batch holding items of tables with a [`super::TableBatchConfig`] is
+    /// flushed at the smallest max_size and fill timeout of those tables.
+    ///
+    /// The fill timeout is a [`tokio::time`] timer, so pausing tokio's clock
+    /// with [`tokio::time::pause`] makes the moments batches are flushed at
+    /// deterministic
     #[must_use = "streams do nothing unless polled"]
     #[derive(Debug)]
     pub struct BatchTimeoutStream<B: BatchBoundary, S: Stream<Item = B>> {
@@ -22,6 +35,9 @@ pin_project! {
         deadline: Option<Sleep>,
         items: Vec<S::Item>,
         batch_config: BatchConfig,
+        // max_size and fill timeout of the tables of the current batch's items
+        batch_limits: Option<(usize, Duration)>,
+        batch_started: Instant,
         reset_timer: bool,
         inner_stream_ended: bool,
     }
@@ -32,8 +48,10 @@ impl<B: BatchBoundary, S: Stream<Item = B>> BatchTimeoutStream<B, S> {
         BatchTimeoutStream {
             stream,
             deadline: None,
-            items: Vec::with_capacity(batch_config.max_batch_size),
+            items: Vec::with_capacity(batch_config.max_batch_size()),
             batch_config,
+            batch_limits: None,
+            batch_started: Instant::now(),
             reset_timer: true,
             inner_stream_ended: false,
         }
@@ -42,6 +60,24 @@ impl<B: BatchBoundary, S: Stream<Item = B>> BatchTimeoutStream<B, S> {
     pub fn get_inner_mut(&mut self) -> &mut S {
         &mut self.stream
     }
+
+    /// Takes the items of the batch being filled up to the last item which
+    /// may end a batch, without waiting for the batch to fill or time out.
+    /// The items after it stay in the batch, so a taken batch still ends on
+    /// a transaction boundary, and the taken batch is empty if there's no
+    /// such item yet.
+    pub fn take_batch(self: Pin<&mut Self>) -> Vec<S::Item> {
+        let this = self.project();
+        let Some(last_in_batch) = this.items.iter().rposition(|item| item.is_last_in_batch())
+        else {
+            return vec![];
+        };
+        let rest = this.items.split_off(last_in_batch + 1);
+        if rest.is_empty() {
+            *this.reset_timer = true;
+        }
+        std::mem::replace(this.items, rest)
+    }
 }
 
 impl<B: BatchBoundary, S: Stream<Item = B>> Stream for BatchTimeoutStream<B, S> {
@@ -54,19 +90,41 @@ impl<B: BatchBoundary, S: Stream<Item = B>> Stream for BatchTimeoutStream<B, S>
         }
         loop {
             if *this.reset_timer {
+                *this.batch_started = Instant::now();
+                *this.batch_limits = None;
                 this.deadline
                     .set(Some(sleep(this.batch_config.max_batch_fill_time)));
                 *this.reset_timer = false;
             }
             if this.items.is_empty() {
-                this.items.reserve_exact(this.batch_config.max_batch_size);
+                this.items.reserve_exact(this.batch_config.max_batch_size());
             }
             match this.stream.as_mut().poll_next(cx) {
                 Poll::Pending => break,
                 Poll::Ready(Some(item)) => {
+                    if let Some(table_id) = item.table_id() {
+                        let (table_max_size, table_max_fill_time) =
+                            this.batch_config.table_batch_limits(table_id);
+                        let (max_size, max_fill_time) = match *this.batch_limits {
+                            Some((max_size, max_fill_time)) => (
+                                max_size.min(table_max_size),
+                                max_fill_time.min(table_max_fill_time),
+                            ),
+                            None => (table_max_size, table_max_fill_time),
+                        };
+                        if *this.batch_limits != Some((max_size, max_fill_time)) {
+                            this.deadline
+                                .set(Some(sleep_until(*this.batch_started + max_fill_time)));
+                            *this.batch_limits = Some((max_size, max_fill_time));
+                        }
+                    }
+                    let max_batch_size = this
+                        .batch_limits
+                        .map_or(this.batch_config.max_batch_size(), |(max_size, _)| max_size);
                     let is_last_in_batch = item.is_last_in_batch();
+                    let flush_now = this.batch_config.flush_on_idle && item.is_idle();
                     this.items.push(item);
-                    if this.items.len() >= this.batch_config.max_batch_size && is_last_in_batch {
+                    if is_last_in_batch && (this.items.len() >= max_batch_size || flush_now) {
                         *this.reset_timer = true;
                         return Poll::Ready(Some(std::mem::take(this.items)));
                     }
@@ -101,3 +159,330 @@ impl<B: BatchBoundary, S: Stream<Item = B>> Stream for BatchTimeoutStream<B, S>
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{stream, StreamExt};
+    use tokio::{
+        pin,
+        sync::mpsc::{self, UnboundedSender},
+        time::timeout,
+    };
+
+    use crate::{
+        pipeline::batching::{
+            stream::BatchTimeoutStream, BatchBoundary, BatchConfig, TableBatchConfig,
+        },
+        table::TableId,
+    };
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Item {
+        id: u32,
+        is_last_in_batch: bool,
+        is_idle: bool,
+        table_id: Option<TableId>,
+    }
+
+    impl BatchBoundary for Item {
+        fn is_last_in_batch(&self) -> bool {
+            self.is_last_in_batch
+        }
+
+        fn is_idle(&self) -> bool {
+            self.is_idle
+        }
+
+        fn table_id(&self) -> Option<TableId> {
+            self.table_id
+        }
+    }
+
+    fn send(sender: &UnboundedSender<Item>, id: u32, is_last_in_batch: bool) {
+        let item = Item {
+            id,
+            is_last_in_batch,
+            is_idle: false,
+            table_id: None,
+        };
+        sender.send(item).expect("failed to send item");
+    }
+
+    /// Sends a change of the table `table_id` as its own transaction
+    fn send_change(sender: &UnboundedSender<Item>, id: u32, table_id: TableId) {
+        let item = Item {
+            id,
+            is_last_in_batch: true,
+            is_idle: false,
+            table_id: Some(table_id),
+        };
+        sender.send(item).expect("failed to send item");
+    }
+
+    /// Sends an item like a keepalive of an idle source
+    fn send_idle(sender: &UnboundedSender<Item>, id: u32) {
+        let item = Item {
+            id,
+            is_last_in_batch: true,
+            is_idle: true,
+            table_id: None,
+        };
+        sender.send(item).expect("failed to send item");
+    }
+
+    fn ids(batch: Option<Vec<Item>>) -> Vec<u32> {
+        batch
+            .expect("stream ended")
+            .iter()
+            .map(|item| item.id)
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_is_flushed_when_fill_time_elapses() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let batch_config = BatchConfig::new(10, Duration::from_secs(1));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, true);
+        send(&sender, 2, true);
+
+        let early = timeout(Duration::from_millis(999), batches.next()).await;
+        assert!(early.is_err());
+        let batch = timeout(Duration::from_millis(2), batches.next())
+            .await
+            .expect("batch not flushed after the fill time");
+        assert_eq!(ids(batch), vec![1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn taken_batch_ends_on_the_last_boundary_item() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let batch_config = BatchConfig::new(10, Duration::from_secs(1));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, false);
+        send(&sender, 2, true);
+        send(&sender, 3, false);
+        let early = timeout(Duration::from_millis(1), batches.next()).await;
+        assert!(early.is_err());
+
+        let taken = batches.as_mut().take_batch();
+        assert_eq!(ids(Some(taken)), vec![1, 2]);
+        assert!(batches.as_mut().take_batch().is_empty());
+
+        send(&sender, 4, true);
+        let batch = timeout(Duration::from_secs(1), batches.next())
+            .await
+            .expect("batch not flushed after the fill time");
+        assert_eq!(ids(batch), vec![3, 4]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_is_flushed_when_max_size_is_reached() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let batch_config = BatchConfig::new(2, Duration::from_secs(1));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, true);
+        send(&sender, 2, true);
+        send(&sender, 3, true);
+
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("full batch not flushed immediately");
+        assert_eq!(ids(batch), vec![1, 2]);
+
+        let early = timeout(Duration::from_millis(999), batches.next()).await;
+        assert!(early.is_err());
+        let batch = timeout(Duration::from_millis(2), batches.next())
+            .await
+            .expect("batch not flushed after the fill time");
+        assert_eq!(ids(batch), vec![3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_are_flushed_at_the_adapted_batch_size() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let mut batch_config = BatchConfig::new(10, Duration::from_secs(1));
+        batch_config.set_adaptive_batch_size(2, Duration::from_millis(100));
+        let adaptive_batch_size = batch_config.adaptive_batch_size().unwrap().clone();
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        for id in 1..=6 {
+            send(&sender, id, true);
+        }
+
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("batch of the min size not flushed immediately");
+        assert_eq!(ids(batch), vec![1, 2]);
+
+        // the batch was written in half the target time
+        adaptive_batch_size.record_write(2, Duration::from_millis(50));
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("batch of the grown size not flushed immediately");
+        assert_eq!(ids(batch), vec![3, 4, 5, 6]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn full_batch_is_flushed_on_a_boundary_item() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let batch_config = BatchConfig::new(2, Duration::from_secs(1));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, false);
+        send(&sender, 2, false);
+        send(&sender, 3, true);
+
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("full batch not flushed immediately");
+        assert_eq!(ids(batch), vec![1, 2, 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_source_is_flushed_without_waiting_for_the_fill_time() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let mut batch_config = BatchConfig::new(10, Duration::from_secs(60));
+        batch_config.set_flush_on_idle(true);
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, true);
+        send_idle(&sender, 2);
+
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("batch not flushed when the source went idle");
+        assert_eq!(ids(batch), vec![1, 2]);
+
+        // an idle source's keepalive alone is flushed too, so its lsn is
+        // confirmed promptly
+        send_idle(&sender, 3);
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("keepalive not flushed when the source went idle");
+        assert_eq!(ids(batch), vec![3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_source_waits_for_the_fill_time_without_flush_on_idle() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let batch_config = BatchConfig::new(10, Duration::from_secs(60));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, true);
+        send_idle(&sender, 2);
+
+        let early = timeout(Duration::from_secs(59), batches.next()).await;
+        assert!(early.is_err());
+        let batch = timeout(Duration::from_secs(2), batches.next())
+            .await
+            .expect("batch not flushed after the fill time");
+        assert_eq!(ids(batch), vec![1, 2]);
+    }
+
+    fn table_batch_size(max_batch_size: usize) -> TableBatchConfig {
+        TableBatchConfig {
+            max_batch_size: Some(max_batch_size),
+            max_batch_fill_time: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tables_are_flushed_at_their_own_batch_sizes() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let mut batch_config = BatchConfig::new(10, Duration::from_secs(1));
+        batch_config.set_table_config(1, table_batch_size(2));
+        batch_config.set_table_config(2, table_batch_size(4));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        for id in 1..=2 {
+            send_change(&sender, id, 1);
+        }
+        for id in 3..=6 {
+            send_change(&sender, id, 2);
+        }
+
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("full batch of table 1 not flushed immediately");
+        assert_eq!(ids(batch), vec![1, 2]);
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("full batch of table 2 not flushed immediately");
+        assert_eq!(ids(batch), vec![3, 4, 5, 6]);
+
+        // a table without overrides is batched with the pipeline's config
+        for id in 7..=9 {
+            send_change(&sender, id, 3);
+        }
+        let early = timeout(Duration::from_millis(999), batches.next()).await;
+        assert!(early.is_err());
+        let batch = timeout(Duration::from_millis(2), batches.next())
+            .await
+            .expect("batch not flushed after the fill time");
+        assert_eq!(ids(batch), vec![7, 8, 9]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_is_flushed_at_the_shortest_fill_time_of_its_tables() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let mut batch_config = BatchConfig::new(10, Duration::from_secs(60));
+        let table_config = TableBatchConfig {
+            max_batch_size: None,
+            max_batch_fill_time: Some(Duration::from_secs(1)),
+        };
+        batch_config.set_table_config(2, table_config);
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send_change(&sender, 1, 1);
+        send_change(&sender, 2, 2);
+
+        let early = timeout(Duration::from_millis(999), batches.next()).await;
+        assert!(early.is_err());
+        let batch = timeout(Duration::from_millis(2), batches.next())
+            .await
+            .expect("batch not flushed after the fill time of table 2");
+        assert_eq!(ids(batch), vec![1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn remaining_items_are_flushed_when_the_stream_ends() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let items = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let batch_config = BatchConfig::new(10, Duration::from_secs(1));
+        let batches = BatchTimeoutStream::new(items, batch_config);
+        pin!(batches);
+
+        send(&sender, 1, false);
+        drop(sender);
+
+        let batch = timeout(Duration::from_millis(1), batches.next())
+            .await
+            .expect("batch not flushed when the stream ended");
+        assert_eq!(ids(batch), vec![1]);
+        assert!(batches.next().await.is_none());
+    }
+}
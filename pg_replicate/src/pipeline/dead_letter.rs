@@ -0,0 +1,159 @@
+use std::{collections::VecDeque, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::error;
+
+use crate::table::TableId;
+
+/// The raw form of a record which failed conversion
+#[derive(Debug, Clone)]
+pub enum DeadLetterPayload {
+    /// A row from the table copy stream in Postgres' COPY text format
+    CopyRow(Bytes),
+    /// The columns of a tuple from the cdc stream. A `None` is either
+    /// a null or an unchanged toast value.
+    Tuple(Vec<Option<Bytes>>),
+}
+
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub table_id: Option<TableId>,
+    pub payload: DeadLetterPayload,
+    pub error: Box<dyn std::error::Error + Send + Sync>,
+}
+
+#[derive(Debug, Error)]
+#[error("dead letter sink error: {0}")]
+pub struct DeadLetterSinkError(#[source] pub Box<dyn std::error::Error + Send + Sync>);
+
+/// A sink which receives records which failed conversion
+#[async_trait]
+pub trait DeadLetterSink: Send {
+    async fn write_dead_letter(
+        &mut self,
+        dead_letter: DeadLetter,
+    ) -> Result<(), DeadLetterSinkError>;
+}
+
+/// What a pipeline does when a record fails conversion
+#[derive(Default)]
+pub enum DeadLetterPolicy {
+    /// Abort the pipeline with the conversion error
+    #[default]
+    Abort,
+    /// Write the record to a dead letter sink and continue with the next one
+    Skip(Box<dyn DeadLetterSink>),
+}
+
+impl DeadLetterPolicy {
+    /// Returns the dead letter sink if failed records should be skipped
+    pub fn dead_letter_sink(&mut self) -> Option<&mut Box<dyn DeadLetterSink>> {
+        match self {
+            DeadLetterPolicy::Abort => None,
+            DeadLetterPolicy::Skip(dead_letter_sink) => Some(dead_letter_sink),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("more than {max_dead_letters} records were dead lettered within {window:?}")]
+pub struct DeadLetterThresholdExceeded {
+    pub max_dead_letters: usize,
+    pub window: Duration,
+}
+
+/// Halts a pipeline which dead letters more than `max_dead_letters` records
+/// within `window`. Skipping a poison record keeps the pipeline going, but
+/// dead lettering every record, e.g. those of a type the pipeline can't
+/// convert at all, would only hide a problem which needs fixing.
+#[derive(Debug, Clone)]
+pub struct DeadLetterThreshold {
+    max_dead_letters: usize,
+    window: Duration,
+    /// When the records dead lettered within the last `window` were, oldest
+    /// first
+    dead_lettered_at: VecDeque<Instant>,
+}
+
+impl DeadLetterThreshold {
+    pub fn new(max_dead_letters: usize, window: Duration) -> DeadLetterThreshold {
+        DeadLetterThreshold {
+            max_dead_letters,
+            window,
+            dead_lettered_at: VecDeque::with_capacity(max_dead_letters + 1),
+        }
+    }
+
+    /// Records that a record was dead lettered at `now`, failing if more
+    /// than `max_dead_letters` were within the window ending at `now`
+    pub fn record(&mut self, now: Instant) -> Result<(), DeadLetterThresholdExceeded> {
+        while let Some(&oldest) = self.dead_lettered_at.front() {
+            if now.duration_since(oldest) < self.window {
+                break;
+            }
+            self.dead_lettered_at.pop_front();
+        }
+        self.dead_lettered_at.push_back(now);
+        if self.dead_lettered_at.len() > self.max_dead_letters {
+            return Err(DeadLetterThresholdExceeded {
+                max_dead_letters: self.max_dead_letters,
+                window: self.window,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A dead letter sink which only logs the failed records
+pub struct LoggingDeadLetterSink;
+
+#[async_trait]
+impl DeadLetterSink for LoggingDeadLetterSink {
+    async fn write_dead_letter(
+        &mut self,
+        dead_letter: DeadLetter,
+    ) -> Result<(), DeadLetterSinkError> {
+        error!(
+            "skipping record of table id {:?} which failed conversion: {}, payload: {:?}",
+            dead_letter.table_id, dead_letter.error, dead_letter.payload
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::DeadLetterThreshold;
+
+    #[test]
+    fn dead_letters_up_to_the_threshold_are_accepted() {
+        let mut threshold = DeadLetterThreshold::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(threshold.record(now).is_ok());
+        assert!(threshold.record(now).is_ok());
+        let exceeded = threshold.record(now).unwrap_err();
+
+        assert_eq!(exceeded.max_dead_letters, 2);
+        assert_eq!(exceeded.window, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn dead_letters_older_than_the_window_are_forgotten() {
+        let mut threshold = DeadLetterThreshold::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        for minute in 0..10 {
+            let now = start + Duration::from_secs(minute * 60);
+            assert!(threshold.record(now).is_ok());
+            assert!(threshold.record(now + Duration::from_secs(30)).is_ok());
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Shortening of source identifiers which are too long for a sink
+
+/// Length in bytes of the suffix appended to shortened identifiers: an
+/// underscore followed by eight hex digits of a hash of the full identifier
+const HASH_SUFFIX_LENGTH: usize = 9;
+
+/// Identifiers are shortened to at least this many bytes
+pub const MIN_IDENTIFIER_LENGTH: usize = HASH_SUFFIX_LENGTH + 1;
+
+/// Returns `identifier` if it is at most `max_length` bytes long. Otherwise
+/// returns its longest prefix which, followed by a hash of the whole
+/// identifier, is at most `max_length` bytes long. The same identifier
+/// always maps to the same result, and identifiers sharing a long prefix map
+/// to different results. `max_length` is raised to [`MIN_IDENTIFIER_LENGTH`]
+/// if it is shorter.
+pub fn limit_identifier_length(identifier: &str, max_length: usize) -> String {
+    if identifier.len() <= max_length {
+        return identifier.to_string();
+    }
+    let max_length = max_length.max(MIN_IDENTIFIER_LENGTH);
+    let mut prefix_length = max_length - HASH_SUFFIX_LENGTH;
+    while !identifier.is_char_boundary(prefix_length) {
+        prefix_length -= 1;
+    }
+    format!(
+        "{}_{:08x}",
+        &identifier[..prefix_length],
+        fnv1a_hash(identifier.as_bytes()) as u32
+    )
+}
+
+/// 64 bit FNV-1a, which unlike std's hashers is stable across releases, so
+/// identifiers map to the same names in the sink after an upgrade
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{limit_identifier_length, MIN_IDENTIFIER_LENGTH};
+
+    #[test]
+    fn identifier_within_the_limit_is_unchanged() {
+        assert_eq!(limit_identifier_length("customer_id", 11), "customer_id");
+    }
+
+    #[test]
+    fn identifier_over_the_limit_is_truncated_with_a_hash_suffix() {
+        let identifier = "a_very_long_column_name_which_a_sink_can_not_store";
+
+        let limited = limit_identifier_length(identifier, 30);
+
+        assert_eq!(limited.len(), 30);
+        assert_eq!(limited[..21], identifier[..21]);
+        assert!(limited[21..].starts_with('_'));
+        // the mapping is deterministic
+        assert_eq!(limited, limit_identifier_length(identifier, 30));
+    }
+
+    #[test]
+    fn identifiers_colliding_after_truncation_get_different_suffixes() {
+        let first = "customer_lifetime_value_adjusted_for_inflation_in_2023";
+        let second = "customer_lifetime_value_adjusted_for_inflation_in_2024";
+
+        let first_limited = limit_identifier_length(first, 30);
+        let second_limited = limit_identifier_length(second, 30);
+
+        assert_eq!(first_limited[..21], second_limited[..21]);
+        assert_ne!(first_limited, second_limited);
+    }
+
+    #[test]
+    fn multi_byte_characters_are_not_split() {
+        let limited = limit_identifier_length("ääääääääääääääää", 16);
+
+        assert!(limited.len() <= 16);
+        assert!(limited.starts_with("äää_"));
+    }
+
+    #[test]
+    fn limit_is_raised_to_fit_the_hash_suffix() {
+        let limited = limit_identifier_length("some_long_identifier", 3);
+
+        assert_eq!(limited.len(), MIN_IDENTIFIER_LENGTH);
+    }
+}
@@ -1,13 +1,18 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt::Display, time::Duration};
 
 use sinks::SinkError;
 use sources::SourceError;
 use thiserror::Error;
 use tokio_postgres::types::PgLsn;
 
-use crate::table::TableId;
+use crate::{
+    conversions::cdc_event::CdcEvent,
+    table::{TableId, TableName},
+};
 
 pub mod batching;
+pub mod dead_letter;
+pub mod identifiers;
 pub mod sinks;
 pub mod sources;
 
@@ -18,6 +23,156 @@ pub enum PipelineAction {
     Both,
 }
 
+/// What a pipeline does when a table's row count in the sink differs from
+/// the source after the table was copied
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CopyReconciliationPolicy {
+    /// Don't count rows after copies
+    #[default]
+    Skip,
+    /// Log the difference and continue
+    Warn,
+    /// Stop the pipeline
+    Fail,
+}
+
+/// Which snapshots of the source a pipeline copies tables from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableCopySnapshot {
+    /// All tables are copied in one transaction, so they are consistent
+    /// with each other and with the lsn cdc starts at. The transaction's
+    /// snapshot is held until the last table was copied, which keeps the
+    /// source from vacuuming rows deleted in the meantime.
+    #[default]
+    Shared,
+    /// Each table is copied in its own transaction, which is committed once
+    /// the table was copied, so no snapshot is held for longer than one
+    /// table's copy. Tables are no longer consistent with each other: a
+    /// table copied later can contain rows which reference rows changed or
+    /// deleted after an earlier table was copied. Cdc still starts at the
+    /// snapshot of the first copy, so changes committed between that and a
+    /// later table's copy are applied to rows which already contain them,
+    /// which duplicates inserts in sinks that don't upsert.
+    PerTable,
+}
+
+/// What a pipeline does when the tables it copied in one run were read from
+/// snapshots at different lsns, which happens with
+/// [`TableCopySnapshot::PerTable`]. The lsn of a later table's snapshot is the
+/// source's WAL position when its transaction began.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotConsistencyPolicy {
+    /// Don't compare the snapshots, their lsns aren't looked up
+    #[default]
+    Skip,
+    /// Log the tables' snapshot lsns and continue
+    Warn,
+    /// Stop the pipeline once the tables were copied, before streaming
+    Fail,
+}
+
+/// What a pipeline which streams changes does when the source's replication
+/// slot is past the last LSN the sink has seen, like after the sink was
+/// restored from a backup or the slot was dropped and created again. Changes
+/// between the two can't be streamed anymore, so tables copied in an earlier
+/// run would miss them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlotGapPolicy {
+    /// Stop the pipeline with [`PipelineError::SlotAheadOfSink`]
+    #[default]
+    Error,
+    /// Reset the tables copied in an earlier run in the sink and copy them
+    /// again, from a snapshot which isn't the slot's, which can apply changes
+    /// committed after the slot's position twice, see
+    /// [`TableCopySnapshot::PerTable`]. Pipelines which only stream changes,
+    /// and sinks which can't reset tables, stop as with
+    /// [`SlotGapPolicy::Error`].
+    Recopy,
+}
+
+/// Kinds of changes to a table which a pipeline applies to the sink. Changes
+/// of the other kinds are skipped, but the lsn confirmed to the source still
+/// advances past them. Truncates aren't converted to cdc events, so
+/// `truncate` doesn't change what reaches the sink yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableOperations {
+    pub insert: bool,
+    pub update: bool,
+    pub delete: bool,
+    pub truncate: bool,
+}
+
+impl Default for TableOperations {
+    fn default() -> Self {
+        TableOperations {
+            insert: true,
+            update: true,
+            delete: true,
+            truncate: true,
+        }
+    }
+}
+
+impl TableOperations {
+    /// Returns false for row events of a kind which isn't applied. All other
+    /// events are allowed.
+    pub fn allows(&self, event: &CdcEvent) -> bool {
+        match event {
+            CdcEvent::Insert(_) => self.insert,
+            CdcEvent::Update { .. } => self.update,
+            CdcEvent::Delete { .. } => self.delete,
+            _ => true,
+        }
+    }
+}
+
+/// What a pipeline which streams changes does when tables of the source lack
+/// a replica identity, see [`sources::Source::tables_without_replica_identity`].
+/// Their updates and deletes can't be replicated, which otherwise only shows
+/// once the first one is streamed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplicaIdentityPolicy {
+    /// Don't check the tables before streaming
+    #[default]
+    Skip,
+    /// Log the tables and continue
+    Warn,
+    /// Refuse to start the pipeline
+    Fail,
+}
+
+/// Replication status of a table in a pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableStatus {
+    /// The table's rows are being copied
+    Copying,
+    /// The table's rows were copied but cdc events aren't applied yet
+    Copied,
+    /// Cdc events of the table are being applied
+    Streaming,
+}
+
+impl Display for TableStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableStatus::Copying => write!(f, "copying"),
+            TableStatus::Copied => write!(f, "copied"),
+            TableStatus::Streaming => write!(f, "streaming"),
+        }
+    }
+}
+
+/// Sent by a pipeline whenever a table's status changes and, while
+/// streaming, whenever the lsn confirmed to the source advances
+#[derive(Debug, Clone)]
+pub struct TableStatusUpdate {
+    pub table_id: TableId,
+    pub table_name: TableName,
+    pub status: TableStatus,
+    pub lsn: Option<PgLsn>,
+}
+
+#[derive(Debug, Clone)]
 pub struct PipelineResumptionState {
     pub copied_tables: HashSet<TableId>,
     pub last_lsn: PgLsn,
@@ -33,4 +188,93 @@ pub enum PipelineError<SrcErr: SourceError, SnkErr: SinkError> {
 
     #[error("source error: {0}")]
     CommonSource(#[from] sources::CommonSourceError),
+
+    #[error("dead letter error: {0}")]
+    DeadLetter(#[from] dead_letter::DeadLetterSinkError),
+
+    #[error("{0}, stopping the pipeline")]
+    DeadLetterThresholdExceeded(#[from] dead_letter::DeadLetterThresholdExceeded),
+
+    #[error("schema of table {0} in the sink doesn't match the source: {1}")]
+    SchemaMismatch(TableName, sinks::SchemaDiff),
+
+    #[error("table {table_name} has {sink_rows} rows in the sink after its copy but {source_rows} in the source")]
+    RowCountMismatch {
+        table_name: TableName,
+        source_rows: u64,
+        sink_rows: u64,
+    },
+
+    #[error("sink doesn't support the {0} apply mode")]
+    UnsupportedApplyMode(sinks::ApplyMode),
+
+    #[error("source publishes deletes but the sink doesn't support them")]
+    DeletesNotSupported,
+
+    #[error(
+        "table {0} skips inserts but applies updates, which the {1} apply mode applies as inserts"
+    )]
+    IncoherentTableOperations(TableName, sinks::ApplyMode),
+
+    #[error("table {0} is not one of the source's tables")]
+    TableNotInSource(TableName),
+
+    #[error("more than one column of table {table_name} is named {column_name} in the sink")]
+    ColumnNameCollision {
+        table_name: TableName,
+        column_name: String,
+    },
+
+    #[error("the sink has seen changes up to lsn {sink_lsn} but the source's slot has confirmed those up to {confirmed_flush_lsn} and retains WAL from {restart_lsn} on, changes in between can't be streamed")]
+    SlotAheadOfSink {
+        sink_lsn: PgLsn,
+        restart_lsn: PgLsn,
+        confirmed_flush_lsn: PgLsn,
+    },
+
+    #[error("sink {operation} timed out after {timeout:?}")]
+    SinkTimeout {
+        operation: &'static str,
+        timeout: Duration,
+    },
+
+    #[error("sink not ready after {0:?}")]
+    SinkNotReady(Duration),
+
+    #[error("tables were copied from snapshots at different lsns: {}", describe_snapshot_lsns(.0))]
+    InconsistentSnapshots(Vec<(TableName, PgLsn)>),
+
+    #[error("updates and deletes of tables without a replica identity can't be replicated: {}", describe_table_names(.0))]
+    MissingReplicaIdentity(Vec<TableName>),
+
+    #[error("the sink applies rows by key but these tables have neither a primary key nor a unique index as their replica identity: {}", describe_table_names(.0))]
+    MissingKey(Vec<TableName>),
+}
+
+fn describe_table_names(table_names: &[TableName]) -> String {
+    table_names
+        .iter()
+        .map(|table_name| table_name.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_snapshot_lsns(snapshot_lsns: &[(TableName, PgLsn)]) -> String {
+    snapshot_lsns
+        .iter()
+        .map(|(table_name, lsn)| format!("{table_name} at {lsn}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl<SrcErr: SourceError, SnkErr: SinkError> PipelineError<SrcErr, SnkErr> {
+    /// Returns true if the error is likely to go away when the pipeline is
+    /// restarted, like a sink write which timed out, rather than persist until
+    /// the source, sink or config is changed
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            PipelineError::SinkTimeout { .. } | PipelineError::SinkNotReady(_)
+        )
+    }
 }
@@ -4,16 +4,44 @@ use async_trait::async_trait;
 use gcp_bigquery_client::error::BQError;
 use thiserror::Error;
 use tokio_postgres::types::{PgLsn, Type};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
-    clients::bigquery::BigQueryClient,
-    conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+    clients::bigquery::{
+        BigQueryClient, BigQueryColumn, BigQueryNumericType, EncodedRow, NumericPolicy,
+    },
+    conversions::{cdc_event::CdcEvent, table_row::TableRow, ArrayCell, Cell},
     pipeline::PipelineResumptionState,
     table::{ColumnSchema, TableId, TableName, TableSchema},
 };
 
-use super::{BatchSink, SinkError};
+use super::{
+    serialization::SerializationPool, ApplyMode, BatchSink, ColumnTypeMismatch, SchemaDiff,
+    SinkCapabilities, SinkCredentials, SinkError,
+};
+
+/// Column holding the operation of a row in [`ApplyMode::AppendOnly`]
+const OPERATION_COLUMN: &str = "_operation";
+
+/// Column marking deleted rows in [`ApplyMode::SoftDelete`]
+const DELETED_COLUMN: &str = "_deleted";
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Insert => "INSERT",
+            Operation::Update => "UPDATE",
+            Operation::Delete => "DELETE",
+        }
+    }
+}
 
 #[derive(Debug, Error)]
 pub enum BigQuerySinkError {
@@ -31,6 +59,18 @@ pub enum BigQuerySinkError {
 
     #[error("commit message without begin message")]
     CommitWithoutBegin,
+
+    #[error("only service account keys are supported as credentials")]
+    UnsupportedCredentials,
+
+    #[error(
+        "value {value} of column {column_name} is beyond the range of bigquery's {column_type}"
+    )]
+    NumericOutOfRange {
+        column_name: String,
+        value: String,
+        column_type: BigQueryNumericType,
+    },
 }
 
 impl SinkError for BigQuerySinkError {}
@@ -41,6 +81,8 @@ pub struct BigQueryBatchSink {
     table_schemas: Option<HashMap<TableId, TableSchema>>,
     committed_lsn: Option<PgLsn>,
     final_lsn: Option<PgLsn>,
+    serialization_pool: SerializationPool,
+    apply_mode: ApplyMode,
 }
 
 impl BigQueryBatchSink {
@@ -56,6 +98,8 @@ impl BigQueryBatchSink {
             table_schemas: None,
             committed_lsn: None,
             final_lsn: None,
+            serialization_pool: SerializationPool::default(),
+            apply_mode: ApplyMode::default(),
         })
     }
 
@@ -71,9 +115,79 @@ impl BigQueryBatchSink {
             table_schemas: None,
             committed_lsn: None,
             final_lsn: None,
+            serialization_pool: SerializationPool::default(),
+            apply_mode: ApplyMode::default(),
         })
     }
 
+    pub fn set_serialization_pool(&mut self, serialization_pool: SerializationPool) {
+        self.serialization_pool = serialization_pool;
+    }
+
+    /// Sets the type numeric columns are created with and which values are
+    /// rejected before they are written, see [`NumericPolicy`]. Tables created
+    /// with another policy are reported as mismatching by
+    /// [`BatchSink::validate_schema`].
+    pub fn set_numeric_policy(&mut self, numeric_policy: NumericPolicy) {
+        self.client.set_numeric_policy(numeric_policy);
+    }
+
+    /// Fails for numerics beyond the range of the numeric columns, which
+    /// BigQuery would otherwise reject or round when they are streamed
+    fn check_numeric_ranges(
+        numeric_policy: NumericPolicy,
+        table_schema: &TableSchema,
+        table_rows: &[TableRow],
+    ) -> Result<(), BigQuerySinkError> {
+        for table_row in table_rows {
+            for (column_schema, cell) in table_schema.column_schemas.iter().zip(&table_row.values) {
+                let numerics: Vec<_> = match cell {
+                    Cell::Numeric(numeric) => vec![numeric],
+                    Cell::Array(ArrayCell::Numeric(numerics)) => {
+                        numerics.iter().flatten().collect()
+                    }
+                    _ => continue,
+                };
+                for numeric in numerics {
+                    if let Err(column_type) = numeric_policy.check(numeric) {
+                        return Err(BigQuerySinkError::NumericOutOfRange {
+                            column_name: column_schema.name.clone(),
+                            value: numeric.to_string(),
+                            column_type,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes `table_rows` on the serialization pool and streams them to the
+    /// table of `table_id`
+    async fn stream_rows(
+        &mut self,
+        table_id: TableId,
+        table_rows: Vec<TableRow>,
+    ) -> Result<(), BigQuerySinkError> {
+        let table_schema = self.get_table_schema(table_id)?;
+        Self::check_numeric_ranges(self.client.numeric_policy(), table_schema, &table_rows)?;
+        let table_name = Self::table_name_in_bq(&table_schema.table_name);
+        let table_descriptor = table_schema.into();
+        let encoded_rows = self
+            .serialization_pool
+            .serialize(table_rows, |table_row| EncodedRow::new(&table_row))
+            .await;
+        self.client
+            .stream_rows(
+                &self.dataset_id,
+                table_name,
+                &table_descriptor,
+                &encoded_rows,
+            )
+            .await?;
+        Ok(())
+    }
+
     fn get_table_schema(&self, table_id: TableId) -> Result<&TableSchema, BigQuerySinkError> {
         self.table_schemas
             .as_ref()
@@ -82,14 +196,122 @@ impl BigQueryBatchSink {
             .ok_or(BigQuerySinkError::MissingTableId(table_id))
     }
 
+    /// Returns the schema of the table in BigQuery for `table_schema`. Tables
+    /// get an extra column for the operation in [`ApplyMode::AppendOnly`],
+    /// where they have no primary key so that rows are always appended, and
+    /// one marking deleted rows in [`ApplyMode::SoftDelete`]. Non primary key
+    /// columns are nullable in both modes because deletes only have the
+    /// values of the primary key unless the replica identity is full.
+    fn table_schema_in_bq(apply_mode: ApplyMode, table_schema: &TableSchema) -> TableSchema {
+        let mut table_schema = table_schema.clone();
+        match apply_mode {
+            ApplyMode::Upsert => {}
+            ApplyMode::AppendOnly => {
+                for column_schema in &mut table_schema.column_schemas {
+                    column_schema.nullable |= !column_schema.primary;
+                    column_schema.primary = false;
+                }
+                table_schema.column_schemas.push(ColumnSchema {
+                    name: OPERATION_COLUMN.to_string(),
+                    typ: Type::TEXT,
+                    modifier: 0,
+                    nullable: false,
+                    primary: false,
+                    identity: false,
+                    domain: None,
+                });
+            }
+            ApplyMode::SoftDelete => {
+                for column_schema in &mut table_schema.column_schemas {
+                    column_schema.nullable |= !column_schema.primary;
+                }
+                table_schema.column_schemas.push(ColumnSchema {
+                    name: DELETED_COLUMN.to_string(),
+                    typ: Type::BOOL,
+                    modifier: 0,
+                    nullable: false,
+                    primary: false,
+                    identity: false,
+                    domain: None,
+                });
+            }
+        }
+        table_schema
+    }
+
+    /// Appends the values of the columns added by [`Self::table_schema_in_bq`]
+    /// and the change type, if the table has one, to a row
+    fn push_operation(apply_mode: ApplyMode, table_row: &mut TableRow, operation: Operation) {
+        let change_type = match operation {
+            Operation::Delete => "DELETE",
+            Operation::Insert | Operation::Update => "UPSERT",
+        };
+        match apply_mode {
+            ApplyMode::Upsert => {
+                table_row.values.push(Cell::String(change_type.to_string()));
+            }
+            ApplyMode::AppendOnly => {
+                table_row
+                    .values
+                    .push(Cell::String(operation.as_str().to_string()));
+            }
+            ApplyMode::SoftDelete => {
+                let deleted = matches!(operation, Operation::Delete);
+                table_row.values.push(Cell::Bool(deleted));
+                table_row.values.push(Cell::String("UPSERT".to_string()));
+            }
+        }
+    }
+
     fn table_name_in_bq(table_name: &TableName) -> String {
         format!("{}_{}", table_name.schema, table_name.name)
     }
+
+    /// Compares the `expected_columns` of a table with its `columns` in
+    /// BigQuery
+    fn schema_diff(
+        expected_columns: Vec<BigQueryColumn>,
+        columns: &[BigQueryColumn],
+    ) -> SchemaDiff {
+        let mut schema_diff = SchemaDiff::default();
+        for expected in expected_columns {
+            let Some(actual) = columns.iter().find(|c| c.name == expected.name) else {
+                schema_diff.missing_columns.push(expected.name);
+                continue;
+            };
+            if !actual.data_type.eq_ignore_ascii_case(&expected.data_type) {
+                schema_diff.type_mismatches.push(ColumnTypeMismatch {
+                    column_name: expected.name.clone(),
+                    expected: expected.data_type,
+                    actual: actual.data_type.clone(),
+                });
+            }
+            // a nullable column in bigquery can still hold a not null column's values
+            if expected.nullable && !actual.nullable {
+                schema_diff.nullability_mismatches.push(expected.name);
+            }
+        }
+        schema_diff
+    }
 }
 
 #[async_trait]
 impl BatchSink for BigQueryBatchSink {
     type Error = BigQuerySinkError;
+
+    /// Ready once a query of the dataset succeeds. Every error is taken as
+    /// the dataset not being reachable yet, as BigQuery's errors don't tell
+    /// transient ones apart.
+    async fn ready(&mut self) -> Result<bool, Self::Error> {
+        match self.client.table_exists(&self.dataset_id, "last_lsn").await {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                warn!("bigquery dataset {} not ready: {e}", self.dataset_id);
+                Ok(false)
+            }
+        }
+    }
+
     async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
         info!("getting resumption state from bigquery");
         let copied_table_column_schemas = [ColumnSchema {
@@ -98,6 +320,8 @@ impl BatchSink for BigQueryBatchSink {
             modifier: 0,
             nullable: false,
             primary: true,
+            identity: false,
+            domain: None,
         }];
 
         self.client
@@ -115,6 +339,8 @@ impl BatchSink for BigQueryBatchSink {
                 modifier: 0,
                 nullable: false,
                 primary: true,
+                identity: false,
+                domain: None,
             },
             ColumnSchema {
                 name: "lsn".to_string(),
@@ -122,6 +348,8 @@ impl BatchSink for BigQueryBatchSink {
                 modifier: 0,
                 nullable: false,
                 primary: false,
+                identity: false,
+                domain: None,
             },
         ];
         if self
@@ -147,6 +375,15 @@ impl BatchSink for BigQueryBatchSink {
         &mut self,
         table_schemas: HashMap<TableId, TableSchema>,
     ) -> Result<(), Self::Error> {
+        let table_schemas: HashMap<TableId, TableSchema> = table_schemas
+            .into_iter()
+            .map(|(table_id, table_schema)| {
+                (
+                    table_id,
+                    Self::table_schema_in_bq(self.apply_mode, &table_schema),
+                )
+            })
+            .collect();
         for table_schema in table_schemas.values() {
             let table_name = Self::table_name_in_bq(&table_schema.table_name);
             self.client
@@ -168,17 +405,11 @@ impl BatchSink for BigQueryBatchSink {
         mut table_rows: Vec<TableRow>,
         table_id: TableId,
     ) -> Result<(), Self::Error> {
-        let table_schema = self.get_table_schema(table_id)?;
-        let table_name = Self::table_name_in_bq(&table_schema.table_name);
-        let table_descriptor = table_schema.into();
-
         for table_row in &mut table_rows {
-            table_row.values.push(Cell::String("UPSERT".to_string()));
+            Self::push_operation(self.apply_mode, table_row, Operation::Insert);
         }
 
-        self.client
-            .stream_rows(&self.dataset_id, table_name, &table_descriptor, &table_rows)
-            .await?;
+        self.stream_rows(table_id, table_rows).await?;
 
         Ok(())
     }
@@ -192,7 +423,7 @@ impl BatchSink for BigQueryBatchSink {
                     let final_lsn_u64 = begin_body.final_lsn();
                     self.final_lsn = Some(final_lsn_u64.into());
                 }
-                CdcEvent::Commit(commit_body) => {
+                CdcEvent::Commit(commit_body, _) => {
                     let commit_lsn: PgLsn = commit_body.commit_lsn().into();
                     if let Some(final_lsn) = self.final_lsn {
                         if commit_lsn == final_lsn {
@@ -204,8 +435,8 @@ impl BatchSink for BigQueryBatchSink {
                         Err(BigQuerySinkError::CommitWithoutBegin)?
                     }
                 }
-                CdcEvent::Insert((table_id, mut table_row)) => {
-                    table_row.values.push(Cell::String("UPSERT".to_string()));
+                CdcEvent::Insert((table_id, mut table_row, _)) => {
+                    Self::push_operation(self.apply_mode, &mut table_row, Operation::Insert);
                     let table_rows: &mut Vec<TableRow> =
                         table_name_to_table_rows.entry(table_id).or_default();
                     table_rows.push(table_row);
@@ -215,14 +446,21 @@ impl BatchSink for BigQueryBatchSink {
                     old_row: _,
                     key_row: _,
                     row: mut table_row,
+                    key_columns: _,
+                    transaction_info: _,
                 } => {
-                    table_row.values.push(Cell::String("UPSERT".to_string()));
+                    Self::push_operation(self.apply_mode, &mut table_row, Operation::Update);
                     let table_rows: &mut Vec<TableRow> =
                         table_name_to_table_rows.entry(table_id).or_default();
                     table_rows.push(table_row);
                 }
-                CdcEvent::Delete((table_id, mut table_row)) => {
-                    table_row.values.push(Cell::String("DELETE".to_string()));
+                CdcEvent::Delete {
+                    table_id,
+                    row: mut table_row,
+                    key_columns: _,
+                    transaction_info: _,
+                } => {
+                    Self::push_operation(self.apply_mode, &mut table_row, Operation::Delete);
                     let table_rows: &mut Vec<TableRow> =
                         table_name_to_table_rows.entry(table_id).or_default();
                     table_rows.push(table_row);
@@ -230,16 +468,13 @@ impl BatchSink for BigQueryBatchSink {
                 CdcEvent::Relation(_) => {}
                 CdcEvent::KeepAliveRequested { reply: _ } => {}
                 CdcEvent::Type(_) => {}
+                CdcEvent::Sequence { .. } => {}
+                CdcEvent::LogicalMessage { .. } => {}
             }
         }
 
         for (table_id, table_rows) in table_name_to_table_rows {
-            let table_schema = self.get_table_schema(table_id)?;
-            let table_name = Self::table_name_in_bq(&table_schema.table_name);
-            let table_descriptor = table_schema.into();
-            self.client
-                .stream_rows(&self.dataset_id, table_name, &table_descriptor, &table_rows)
-                .await?;
+            self.stream_rows(table_id, table_rows).await?;
         }
 
         if new_last_lsn != PgLsn::from(0) {
@@ -263,4 +498,197 @@ impl BatchSink for BigQueryBatchSink {
     async fn truncate_table(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    /// The table is truncated before it's removed from the copied tables.
+    /// [`BatchSink::truncate_table`] doesn't remove rows in BigQuery, so the
+    /// other way around a failure in between would leave rows which the next
+    /// copy duplicates.
+    async fn reset_table(&mut self, table_id: TableId) -> Result<bool, Self::Error> {
+        let table_schema = self.get_table_schema(table_id)?;
+        let table_name = Self::table_name_in_bq(&table_schema.table_name);
+        self.client
+            .truncate_table(&self.dataset_id, &table_name)
+            .await?;
+        self.client
+            .delete_from_copied_tables(&self.dataset_id, table_id)
+            .await?;
+        Ok(true)
+    }
+
+    /// Rows streamed in the last few minutes might not be counted yet, as
+    /// tables are created with a `max_staleness` of five minutes
+    async fn row_count(&mut self, table_id: TableId) -> Result<Option<u64>, Self::Error> {
+        let table_schema = self.get_table_schema(table_id)?;
+        let table_name = Self::table_name_in_bq(&table_schema.table_name);
+        let row_count = self
+            .client
+            .get_row_count(&self.dataset_id, &table_name)
+            .await?;
+        Ok(Some(row_count))
+    }
+
+    fn supports_apply_mode(&self, _apply_mode: ApplyMode) -> bool {
+        true
+    }
+
+    fn set_apply_mode(&mut self, apply_mode: ApplyMode) {
+        self.apply_mode = apply_mode;
+    }
+
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities {
+            supports_delete: true,
+            supports_schema_evolution: true,
+            supports_transactions: false,
+            // Rows are upserted by their primary key except in append only mode
+            idempotent: self.apply_mode != ApplyMode::AppendOnly,
+            // Rows of tables without a key would be appended instead
+            requires_key: self.apply_mode != ApplyMode::AppendOnly,
+        }
+    }
+
+    async fn reload_credentials(
+        &mut self,
+        credentials: SinkCredentials,
+    ) -> Result<(), Self::Error> {
+        let SinkCredentials::ServiceAccountKey(gcp_sa_key) = credentials else {
+            return Err(BigQuerySinkError::UnsupportedCredentials);
+        };
+        info!("reloading bigquery service account key");
+        self.client.reload_key(&gcp_sa_key).await?;
+        Ok(())
+    }
+
+    async fn validate_schema(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<SchemaDiff, Self::Error> {
+        let table_name = Self::table_name_in_bq(&table_schema.table_name);
+        let columns = self
+            .client
+            .get_table_columns(&self.dataset_id, &table_name)
+            .await?;
+        let expected_columns = table_schema
+            .column_schemas
+            .iter()
+            .map(|column_schema| self.client.expected_column(column_schema))
+            .collect();
+        Ok(Self::schema_diff(expected_columns, &columns))
+    }
+
+    async fn migrate_schema(
+        &mut self,
+        table_schema: &TableSchema,
+        schema_diff: &SchemaDiff,
+    ) -> Result<(), Self::Error> {
+        let table_name = Self::table_name_in_bq(&table_schema.table_name);
+        for column_schema in &table_schema.column_schemas {
+            if schema_diff.missing_columns.contains(&column_schema.name) {
+                self.client
+                    .add_column(&self.dataset_id, &table_name, column_schema)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        clients::bigquery::BigQueryColumn,
+        conversions::{table_row::TableRow, Cell},
+        pipeline::sinks::{ApplyMode, SchemaDiff},
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::{BigQueryBatchSink, Operation};
+
+    fn column(name: &str, data_type: &str, nullable: bool) -> BigQueryColumn {
+        BigQueryColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable,
+        }
+    }
+
+    #[test]
+    fn column_missing_in_bigquery_is_reported() {
+        let expected_columns = vec![
+            column("id", "int64", false),
+            column("name", "string", true),
+            column("email", "string", true),
+        ];
+        // INFORMATION_SCHEMA.COLUMNS reports types in upper case
+        let columns = [column("id", "INT64", false), column("name", "STRING", true)];
+
+        let schema_diff = BigQueryBatchSink::schema_diff(expected_columns, &columns);
+
+        assert_eq!(
+            schema_diff,
+            SchemaDiff {
+                missing_columns: vec!["email".to_string()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(schema_diff.to_string(), "missing column `email`");
+    }
+
+    #[test]
+    fn delete_appends_a_tombstone_row_in_append_only_mode() {
+        let key_column_schema = ColumnSchema {
+            name: "id".to_string(),
+            typ: Type::INT4,
+            modifier: -1,
+            nullable: false,
+            primary: true,
+            identity: false,
+            domain: None,
+        };
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "items".to_string(),
+            },
+            table_id: 1,
+            column_schemas: vec![
+                key_column_schema.clone(),
+                ColumnSchema {
+                    name: "name".to_string(),
+                    typ: Type::TEXT,
+                    nullable: false,
+                    primary: false,
+                    ..key_column_schema
+                },
+            ],
+        };
+        // a delete only has the values of the primary key
+        let mut tombstone = TableRow::from(vec![Cell::I32(1), Cell::Null]);
+
+        let table_schema_in_bq =
+            BigQueryBatchSink::table_schema_in_bq(ApplyMode::AppendOnly, &table_schema);
+        BigQueryBatchSink::push_operation(ApplyMode::AppendOnly, &mut tombstone, Operation::Delete);
+
+        // without a primary key the row is appended rather than replacing the
+        // inserted one
+        let columns: Vec<_> = table_schema_in_bq
+            .column_schemas
+            .iter()
+            .map(|c| (c.name.as_str(), c.primary, c.nullable))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("id", false, false),
+                ("name", false, true),
+                ("_operation", false, false)
+            ]
+        );
+        assert_eq!(
+            tombstone.values,
+            vec![Cell::I32(1), Cell::Null, Cell::String("DELETE".to_string())]
+        );
+    }
 }
@@ -0,0 +1,439 @@
+//! A sink wrapper which writes only the net effect of the changes to each
+//! row in a batch, reducing the writes to the inner sink.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{
+        cdc_event::{CdcEvent, CdcEventConverter},
+        table_row::TableRow,
+        Cell,
+    },
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableSchema},
+};
+
+use super::{ApplyMode, BatchSink, SchemaDiff, SinkCapabilities, SinkCredentials};
+
+/// Collapses the changes to the same key in a batch before writing it to the
+/// inner sink, e.g. an insert followed by a delete is dropped and an insert
+/// followed by updates is written as an insert of the latest row.
+///
+/// Changes are only collapsed between transaction boundaries, so every
+/// commit written to the inner sink still covers exactly the changes of its
+/// transaction. Updates which change a row's key also end the changes
+/// collapsed together. Changes are written unchanged unless the inner sink
+/// applies them in [`ApplyMode::Upsert`] mode, because the other modes keep
+/// the history of every row.
+pub struct CompactingSink<S: BatchSink> {
+    inner: S,
+    apply_mode: ApplyMode,
+    /// Key column positions of the tables written, needed for inserts which
+    /// don't carry them
+    key_columns: HashMap<TableId, Vec<usize>>,
+}
+
+impl<S: BatchSink> CompactingSink<S> {
+    pub fn new(inner: S) -> CompactingSink<S> {
+        CompactingSink {
+            inner,
+            apply_mode: ApplyMode::default(),
+            key_columns: HashMap::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Identifies a row by its table and key values. Cells aren't hashable, so
+/// key values are compared by their debug representation, which is the same
+/// for equal cells.
+type RowKey = (TableId, String);
+
+fn row_key(table_id: TableId, row: &TableRow, key_columns: &[usize]) -> Option<RowKey> {
+    let key = key_columns
+        .iter()
+        .map(|&i| row.values.get(i))
+        .collect::<Option<Vec<&Cell>>>()?;
+    Some((table_id, format!("{key:?}")))
+}
+
+/// Returns the key of the row changed by `event`, or `None` if the event
+/// isn't a change which can be collapsed with others
+fn event_key(key_columns: &HashMap<TableId, Vec<usize>>, event: &CdcEvent) -> Option<RowKey> {
+    match event {
+        CdcEvent::Insert((table_id, row, _)) => row_key(*table_id, row, key_columns.get(table_id)?),
+        CdcEvent::Update {
+            table_id,
+            old_row,
+            key_row: None,
+            row,
+            key_columns,
+            ..
+        } => {
+            let key = row_key(*table_id, row, key_columns)?;
+            // With replica identity full a key change isn't sent as a key row
+            match old_row {
+                Some(old_row) if row_key(*table_id, old_row, key_columns)? != key => None,
+                _ => Some(key),
+            }
+        }
+        CdcEvent::Delete {
+            table_id,
+            row,
+            key_columns,
+            ..
+        } => row_key(*table_id, row, key_columns),
+        _ => None,
+    }
+}
+
+/// Combines two changes to the same row into their net effect, or `None` if
+/// they cancel out
+fn merge(earlier: CdcEvent, later: CdcEvent) -> Option<CdcEvent> {
+    match (earlier, later) {
+        // The row didn't exist before the insert
+        (CdcEvent::Insert(_), CdcEvent::Delete { .. }) => None,
+        (
+            CdcEvent::Insert((table_id, _, _)),
+            CdcEvent::Update {
+                row,
+                transaction_info,
+                ..
+            },
+        ) => Some(CdcEvent::Insert((table_id, row, transaction_info))),
+        (
+            CdcEvent::Update { old_row, .. },
+            CdcEvent::Update {
+                table_id,
+                row,
+                key_columns,
+                transaction_info,
+                ..
+            },
+        ) => Some(CdcEvent::Update {
+            table_id,
+            old_row,
+            key_row: None,
+            row,
+            key_columns,
+            transaction_info,
+        }),
+        (
+            CdcEvent::Delete { key_columns, .. },
+            CdcEvent::Insert((table_id, row, transaction_info)),
+        ) => Some(CdcEvent::Update {
+            table_id,
+            old_row: None,
+            key_row: None,
+            row,
+            key_columns,
+            transaction_info,
+        }),
+        // Otherwise the later change wins, e.g. a delete after an update
+        (_, later) => Some(later),
+    }
+}
+
+/// Collapses the changes to the same row between transaction boundaries and
+/// other events which aren't row changes. A collapsed change takes the
+/// position of the first change to its row.
+fn compact(key_columns: &HashMap<TableId, Vec<usize>>, events: Vec<CdcEvent>) -> Vec<CdcEvent> {
+    let mut compacted: Vec<Option<CdcEvent>> = Vec::with_capacity(events.len());
+    let mut positions: HashMap<RowKey, usize> = HashMap::new();
+    for event in events {
+        let Some(key) = event_key(key_columns, &event) else {
+            positions.clear();
+            compacted.push(Some(event));
+            continue;
+        };
+        match positions.get(&key) {
+            Some(&position) => {
+                let earlier = compacted[position]
+                    .take()
+                    .expect("the change at a recorded position is present");
+                match merge(earlier, event) {
+                    Some(merged) => compacted[position] = Some(merged),
+                    None => {
+                        positions.remove(&key);
+                    }
+                }
+            }
+            None => {
+                positions.insert(key, compacted.len());
+                compacted.push(Some(event));
+            }
+        }
+    }
+    compacted.into_iter().flatten().collect()
+}
+
+#[async_trait]
+impl<S: BatchSink + Send> BatchSink for CompactingSink<S> {
+    type Error = S::Error;
+
+    async fn ready(&mut self) -> Result<bool, Self::Error> {
+        self.inner.ready().await
+    }
+
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        self.inner.get_resumption_state().await
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        for (table_id, table_schema) in &table_schemas {
+            self.key_columns.insert(
+                *table_id,
+                CdcEventConverter::key_columns(&table_schema.column_schemas),
+            );
+        }
+        self.inner.write_table_schemas(table_schemas).await
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        self.inner.write_table_rows(rows, table_id).await
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let events = if self.apply_mode == ApplyMode::Upsert {
+            compact(&self.key_columns, events)
+        } else {
+            events
+        };
+        self.inner.write_cdc_events(events).await
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.inner.table_copied(table_id).await
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.inner.truncate_table(table_id).await
+    }
+
+    async fn begin_table_copy(
+        &mut self,
+        table_id: TableId,
+        table_schema: &TableSchema,
+    ) -> Result<(), Self::Error> {
+        self.inner.begin_table_copy(table_id, table_schema).await
+    }
+
+    async fn end_table_copy(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.inner.end_table_copy(table_id).await
+    }
+
+    async fn reset_table(&mut self, table_id: TableId) -> Result<bool, Self::Error> {
+        self.inner.reset_table(table_id).await
+    }
+
+    async fn row_count(&mut self, table_id: TableId) -> Result<Option<u64>, Self::Error> {
+        self.inner.row_count(table_id).await
+    }
+
+    async fn reload_credentials(
+        &mut self,
+        credentials: SinkCredentials,
+    ) -> Result<(), Self::Error> {
+        self.inner.reload_credentials(credentials).await
+    }
+
+    fn supports_apply_mode(&self, apply_mode: ApplyMode) -> bool {
+        self.inner.supports_apply_mode(apply_mode)
+    }
+
+    fn set_apply_mode(&mut self, apply_mode: ApplyMode) {
+        self.apply_mode = apply_mode;
+        self.inner.set_apply_mode(apply_mode);
+    }
+
+    fn capabilities(&self) -> SinkCapabilities {
+        self.inner.capabilities()
+    }
+
+    async fn validate_schema(
+        &mut self,
+        table_schema: &TableSchema,
+    ) -> Result<SchemaDiff, Self::Error> {
+        self.inner.validate_schema(table_schema).await
+    }
+
+    async fn migrate_schema(
+        &mut self,
+        table_schema: &TableSchema,
+        schema_diff: &SchemaDiff,
+    ) -> Result<(), Self::Error> {
+        self.inner.migrate_schema(table_schema, schema_diff).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+        table::TableId,
+    };
+
+    use super::compact;
+
+    const TABLE_ID: TableId = 1;
+
+    /// Table 1 has the key column `id` followed by a `value` column
+    fn key_columns() -> HashMap<TableId, Vec<usize>> {
+        HashMap::from([(TABLE_ID, vec![0])])
+    }
+
+    fn row(id: i32, value: &str) -> TableRow {
+        TableRow::from(vec![Cell::I32(id), Cell::String(value.to_string())])
+    }
+
+    fn insert(id: i32, value: &str) -> CdcEvent {
+        CdcEvent::Insert((TABLE_ID, row(id, value), None))
+    }
+
+    fn update(id: i32, value: &str) -> CdcEvent {
+        CdcEvent::Update {
+            table_id: TABLE_ID,
+            old_row: None,
+            key_row: None,
+            row: row(id, value),
+            key_columns: vec![0],
+            transaction_info: None,
+        }
+    }
+
+    fn delete(id: i32) -> CdcEvent {
+        CdcEvent::Delete {
+            table_id: TABLE_ID,
+            row: TableRow::from(vec![Cell::I32(id), Cell::Null]),
+            key_columns: vec![0],
+            transaction_info: None,
+        }
+    }
+
+    fn keep_alive() -> CdcEvent {
+        CdcEvent::KeepAliveRequested { reply: false }
+    }
+
+    #[test]
+    fn insert_followed_by_delete_is_dropped() {
+        let events = compact(&key_columns(), vec![insert(1, "a"), delete(1)]);
+
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn insert_followed_by_updates_becomes_insert_of_latest_row() {
+        let events = compact(
+            &key_columns(),
+            vec![insert(1, "a"), update(1, "b"), update(1, "c")],
+        );
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], CdcEvent::Insert((TABLE_ID, r, _)) if *r == row(1, "c")));
+    }
+
+    #[test]
+    fn updates_become_the_latest_update() {
+        let events = compact(&key_columns(), vec![update(1, "a"), update(1, "b")]);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], CdcEvent::Update { row: r, .. } if *r == row(1, "b")));
+    }
+
+    #[test]
+    fn update_followed_by_delete_becomes_delete() {
+        let events = compact(&key_columns(), vec![update(1, "a"), delete(1)]);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], CdcEvent::Delete { .. }));
+    }
+
+    #[test]
+    fn delete_followed_by_insert_becomes_update() {
+        let events = compact(&key_columns(), vec![delete(1), insert(1, "a")]);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            CdcEvent::Update { row: r, key_columns, .. } if *r == row(1, "a") && *key_columns == vec![0]
+        ));
+    }
+
+    #[test]
+    fn insert_update_and_delete_cancel_out_and_a_reinsert_remains() {
+        let events = compact(
+            &key_columns(),
+            vec![insert(1, "a"), update(1, "b"), delete(1), insert(1, "c")],
+        );
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], CdcEvent::Insert((_, r, _)) if *r == row(1, "c")));
+    }
+
+    #[test]
+    fn changes_to_different_keys_are_kept_in_order() {
+        let events = compact(
+            &key_columns(),
+            vec![insert(1, "a"), insert(2, "b"), update(1, "c")],
+        );
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], CdcEvent::Insert((_, r, _)) if *r == row(1, "c")));
+        assert!(matches!(&events[1], CdcEvent::Insert((_, r, _)) if *r == row(2, "b")));
+    }
+
+    #[test]
+    fn changes_are_not_collapsed_across_other_events() {
+        let events = compact(
+            &key_columns(),
+            vec![insert(1, "a"), keep_alive(), delete(1)],
+        );
+
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn key_changing_updates_are_not_collapsed() {
+        let key_change = CdcEvent::Update {
+            table_id: TABLE_ID,
+            old_row: None,
+            key_row: Some(TableRow::from(vec![Cell::I32(1), Cell::Null])),
+            row: row(2, "a"),
+            key_columns: vec![0],
+            transaction_info: None,
+        };
+
+        let events = compact(
+            &key_columns(),
+            vec![update(1, "a"), key_change, update(2, "b")],
+        );
+
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn inserts_of_tables_without_a_schema_are_not_collapsed() {
+        let events = compact(&HashMap::new(), vec![insert(1, "a"), delete(1)]);
+
+        assert_eq!(events.len(), 2);
+    }
+}
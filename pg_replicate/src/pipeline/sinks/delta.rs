@@ -83,6 +83,8 @@ impl BatchSink for DeltaSink {
                 modifier: 0,
                 nullable: false,
                 primary: true,
+                identity: false,
+                domain: None,
             },
             ColumnSchema {
                 name: "lsn".to_string(),
@@ -90,6 +92,8 @@ impl BatchSink for DeltaSink {
                 modifier: 0,
                 nullable: false,
                 primary: false,
+                identity: false,
+                domain: None,
             },
         ];
 
@@ -165,7 +169,7 @@ impl BatchSink for DeltaSink {
                     let final_lsn_u64 = begin_body.final_lsn();
                     self.final_lsn = Some(final_lsn_u64.into());
                 }
-                CdcEvent::Commit(commit_body) => {
+                CdcEvent::Commit(commit_body, _) => {
                     let commit_lsn: PgLsn = commit_body.commit_lsn().into();
                     if let Some(final_lsn) = self.final_lsn {
                         if commit_lsn == final_lsn {
@@ -177,24 +181,31 @@ impl BatchSink for DeltaSink {
                         Err(DeltaSinkError::CommitWithoutBegin)?
                     }
                 }
-                CdcEvent::Insert(insert) => {
-                    let (table_id, mut table_row) = insert;
+                CdcEvent::Insert((table_id, mut table_row, _)) => {
                     Self::add_optional_columns(&mut table_row, "I");
                     rows_batch.entry(table_id).or_default().push(table_row);
                 }
-                CdcEvent::Update(update) => {
-                    let (table_id, mut table_row) = update;
+                CdcEvent::Update {
+                    table_id,
+                    row: mut table_row,
+                    ..
+                } => {
                     Self::add_optional_columns(&mut table_row, "U");
                     rows_batch.entry(table_id).or_default().push(table_row);
                 }
-                CdcEvent::Delete(delete) => {
-                    let (table_id, mut table_row) = delete;
+                CdcEvent::Delete {
+                    table_id,
+                    row: mut table_row,
+                    ..
+                } => {
                     Self::add_optional_columns(&mut table_row, "D");
                     rows_batch.entry(table_id).or_default().push(table_row);
                 }
                 CdcEvent::Relation(_) => {}
                 CdcEvent::KeepAliveRequested { reply: _ } => {}
                 CdcEvent::Type(_) => {}
+                CdcEvent::Sequence { .. } => {}
+                CdcEvent::LogicalMessage { .. } => {}
             };
         }
 
@@ -96,7 +96,7 @@ impl DuckDbExecutor {
                                 self.final_lsn = Some(final_lsn.into());
                                 self.begin_transaction()
                             }
-                            CdcEvent::Commit(commit_body) => {
+                            CdcEvent::Commit(commit_body, _) => {
                                 let commit_lsn: PgLsn = commit_body.commit_lsn().into();
                                 if let Some(final_lsn) = self.final_lsn {
                                     if commit_lsn == final_lsn {
@@ -113,21 +113,28 @@ impl DuckDbExecutor {
                                     Err(DuckDbExecutorError::CommitWithoutBegin)
                                 }
                             }
-                            CdcEvent::Insert((table_id, table_row)) => {
+                            CdcEvent::Insert((table_id, table_row, _)) => {
                                 self.insert_row(table_id, table_row)
                             }
                             CdcEvent::Update {
                                 table_id,
                                 old_row: _,
-                                key_row: _,
+                                key_row,
                                 row: table_row,
-                            } => self.update_row(table_id, table_row),
-                            CdcEvent::Delete((table_id, table_row)) => {
-                                self.delete_row(table_id, table_row)
-                            }
+                                key_columns,
+                                transaction_info: _,
+                            } => self.update_row(table_id, &key_columns, key_row, table_row),
+                            CdcEvent::Delete {
+                                table_id,
+                                row: table_row,
+                                key_columns,
+                                transaction_info: _,
+                            } => self.delete_row(table_id, &key_columns, table_row),
                             CdcEvent::Relation(_) => Ok(()),
                             CdcEvent::KeepAliveRequested { reply: _ } => Ok(()),
                             CdcEvent::Type(_) => Ok(()),
+                            CdcEvent::Sequence { .. } => Ok(()),
+                            CdcEvent::LogicalMessage { .. } => Ok(()),
                         };
 
                         let committed_lsn = self.committed_lsn.expect("committed lsn is none");
@@ -168,6 +175,8 @@ impl DuckDbExecutor {
             modifier: 0,
             nullable: false,
             primary: true,
+            identity: false,
+            domain: None,
         }];
         self.client
             .create_schema_if_missing(&copied_tables_table_name.schema)?;
@@ -185,6 +194,8 @@ impl DuckDbExecutor {
             modifier: 0,
             nullable: false,
             primary: true,
+            identity: false,
+            domain: None,
         }];
         if self
             .client
@@ -231,20 +242,26 @@ impl DuckDbExecutor {
     fn update_row(
         &self,
         table_id: TableId,
+        key_columns: &[usize],
+        key_row: Option<TableRow>,
         table_row: TableRow,
     ) -> Result<(), DuckDbExecutorError> {
         let table_schema = self.get_table_schema(table_id)?;
-        self.client.update_row(table_schema, &table_row)?;
+        let key_row = key_row.as_ref().unwrap_or(&table_row);
+        self.client
+            .update_row(table_schema, key_columns, key_row, &table_row)?;
         Ok(())
     }
 
     fn delete_row(
         &self,
         table_id: TableId,
+        key_columns: &[usize],
         table_row: TableRow,
     ) -> Result<(), DuckDbExecutorError> {
         let table_schema = self.get_table_schema(table_id)?;
-        self.client.delete_row(table_schema, &table_row)?;
+        self.client
+            .delete_row(table_schema, key_columns, &table_row)?;
         Ok(())
     }
 
@@ -8,7 +8,10 @@ use tokio_postgres::types::PgLsn;
 use crate::{
     clients::duckdb::DuckDbClient,
     conversions::{cdc_event::CdcEvent, table_row::TableRow},
-    pipeline::{sinks::BatchSink, PipelineResumptionState},
+    pipeline::{
+        sinks::{BatchSink, SinkCapabilities},
+        PipelineResumptionState,
+    },
     table::{TableId, TableSchema},
 };
 
@@ -180,4 +183,15 @@ impl BatchSink for DuckDbSink {
         }
         Ok(())
     }
+
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities {
+            supports_delete: true,
+            supports_schema_evolution: false,
+            // Each source transaction is applied in a duckdb transaction
+            supports_transactions: true,
+            idempotent: false,
+            requires_key: true,
+        }
+    }
 }
@@ -0,0 +1,457 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+use tracing::{info, warn};
+
+use crate::{
+    clients::elasticsearch::{BulkOperation, ElasticsearchClient, ElasticsearchClientError},
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableSchema},
+};
+
+use super::{BatchSink, SinkError};
+
+#[derive(Debug, Error)]
+pub enum ElasticsearchSinkError {
+    #[error("elasticsearch client error: {0}")]
+    Client(#[from] ElasticsearchClientError),
+
+    #[error("missing table schemas")]
+    MissingTableSchemas,
+
+    #[error("missing table id: {0}")]
+    MissingTableId(TableId),
+
+    #[error("incorrect commit lsn: {0} (expected: {1})")]
+    IncorrectCommitLsn(PgLsn, PgLsn),
+
+    #[error("commit message without begin message")]
+    CommitWithoutBegin,
+}
+
+impl SinkError for ElasticsearchSinkError {}
+
+/// A sink which bulk indexes table rows as documents in Elasticsearch or
+/// OpenSearch, one index per table. Document ids are made of primary key
+/// values so updates replace and deletes remove the document of a row.
+pub struct ElasticsearchSink {
+    client: ElasticsearchClient,
+    table_schemas: Option<HashMap<TableId, TableSchema>>,
+    created_indices: HashSet<TableId>,
+    committed_lsn: Option<PgLsn>,
+    final_lsn: Option<PgLsn>,
+}
+
+impl ElasticsearchSink {
+    pub fn new(
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+        index_prefix: String,
+    ) -> ElasticsearchSink {
+        let client = ElasticsearchClient::new(url, username, password, index_prefix);
+        ElasticsearchSink {
+            client,
+            table_schemas: None,
+            created_indices: HashSet::new(),
+            committed_lsn: None,
+            final_lsn: None,
+        }
+    }
+
+    fn get_table_schema(&self, table_id: TableId) -> Result<&TableSchema, ElasticsearchSinkError> {
+        self.table_schemas
+            .as_ref()
+            .ok_or(ElasticsearchSinkError::MissingTableSchemas)?
+            .get(&table_id)
+            .ok_or(ElasticsearchSinkError::MissingTableId(table_id))
+    }
+
+    /// Creates the index of a table on the first write to it
+    async fn create_index_if_missing(
+        &mut self,
+        table_id: TableId,
+    ) -> Result<(), ElasticsearchSinkError> {
+        if self.created_indices.contains(&table_id) {
+            return Ok(());
+        }
+        let table_schema = self.get_table_schema(table_id)?;
+        self.client
+            .create_index_if_missing(&table_schema.table_name, &table_schema.column_schemas)
+            .await?;
+        self.created_indices.insert(table_id);
+        Ok(())
+    }
+
+    fn index_operation(
+        &self,
+        table_id: TableId,
+        table_row: &TableRow,
+    ) -> Result<BulkOperation, ElasticsearchSinkError> {
+        let table_schema = self.get_table_schema(table_id)?;
+        let column_schemas = &table_schema.column_schemas;
+        Ok(BulkOperation::Index {
+            index: self.client.index_name(&table_schema.table_name),
+            id: ElasticsearchClient::document_id(column_schemas, table_row),
+            document: ElasticsearchClient::table_row_to_document(column_schemas, table_row),
+        })
+    }
+
+    fn delete_operation(
+        &self,
+        table_id: TableId,
+        table_row: &TableRow,
+    ) -> Result<Option<BulkOperation>, ElasticsearchSinkError> {
+        let table_schema = self.get_table_schema(table_id)?;
+        let Some(id) = ElasticsearchClient::document_id(&table_schema.column_schemas, table_row)
+        else {
+            warn!(
+                "skipping delete in table {} which has no primary key",
+                table_schema.table_name
+            );
+            return Ok(None);
+        };
+        Ok(Some(BulkOperation::Delete {
+            index: self.client.index_name(&table_schema.table_name),
+            id,
+        }))
+    }
+}
+
+#[async_trait]
+impl BatchSink for ElasticsearchSink {
+    type Error = ElasticsearchSinkError;
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        info!("getting resumption state from elasticsearch");
+        let copied_tables = self.client.get_copied_table_ids().await?;
+        let last_lsn = self.client.get_last_lsn().await?;
+
+        self.committed_lsn = Some(last_lsn);
+
+        Ok(PipelineResumptionState {
+            copied_tables,
+            last_lsn,
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        self.table_schemas = Some(table_schemas);
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        table_rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        self.create_index_if_missing(table_id).await?;
+        let operations = table_rows
+            .iter()
+            .map(|table_row| self.index_operation(table_id, table_row))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.client.bulk(operations).await?;
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let mut operations = vec![];
+        let mut new_last_lsn = PgLsn::from(0);
+        for event in events {
+            match event {
+                CdcEvent::Begin(begin_body) => {
+                    let final_lsn_u64 = begin_body.final_lsn();
+                    self.final_lsn = Some(final_lsn_u64.into());
+                }
+                CdcEvent::Commit(commit_body, _) => {
+                    let commit_lsn: PgLsn = commit_body.commit_lsn().into();
+                    if let Some(final_lsn) = self.final_lsn {
+                        if commit_lsn == final_lsn {
+                            new_last_lsn = commit_lsn;
+                        } else {
+                            Err(ElasticsearchSinkError::IncorrectCommitLsn(
+                                commit_lsn, final_lsn,
+                            ))?
+                        }
+                    } else {
+                        Err(ElasticsearchSinkError::CommitWithoutBegin)?
+                    }
+                }
+                CdcEvent::Insert((table_id, table_row, _)) => {
+                    self.create_index_if_missing(table_id).await?;
+                    operations.push(self.index_operation(table_id, &table_row)?);
+                }
+                CdcEvent::Update {
+                    table_id,
+                    old_row,
+                    key_row,
+                    row,
+                    ..
+                } => {
+                    self.create_index_if_missing(table_id).await?;
+                    // A changed primary key moves the row to a new document id
+                    if let Some(old_row) = key_row.or(old_row) {
+                        let table_schema = self.get_table_schema(table_id)?;
+                        let column_schemas = &table_schema.column_schemas;
+                        let old_id = ElasticsearchClient::document_id(column_schemas, &old_row);
+                        let new_id = ElasticsearchClient::document_id(column_schemas, &row);
+                        if old_id != new_id {
+                            operations.extend(self.delete_operation(table_id, &old_row)?);
+                        }
+                    }
+                    operations.push(self.index_operation(table_id, &row)?);
+                }
+                CdcEvent::Delete { table_id, row, .. } => {
+                    operations.extend(self.delete_operation(table_id, &row)?);
+                }
+                CdcEvent::Relation(_) => {}
+                CdcEvent::KeepAliveRequested { reply: _ } => {}
+                CdcEvent::Type(_) => {}
+                CdcEvent::Sequence { .. } => {}
+                CdcEvent::LogicalMessage { .. } => {}
+            }
+        }
+
+        self.client.bulk(operations).await?;
+
+        if new_last_lsn != PgLsn::from(0) {
+            self.client.set_last_lsn(new_last_lsn).await?;
+            self.committed_lsn = Some(new_last_lsn);
+        }
+
+        let committed_lsn = self.committed_lsn.expect("committed lsn is none");
+        Ok(committed_lsn)
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.client.insert_into_copied_tables(table_id).await?;
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        let table_schema = self.get_table_schema(table_id)?;
+        self.client
+            .delete_all_documents(&table_schema.table_name)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        conversions::{
+            cdc_event::{BeginBody, CdcEvent, CommitBody},
+            table_row::TableRow,
+            Cell,
+        },
+        pipeline::sinks::BatchSink,
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::{ElasticsearchSink, ElasticsearchSinkError};
+
+    fn column_schema(name: &str, typ: Type, primary: bool) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: !primary,
+            primary,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    async fn sink(url: &str, index_prefix: &str) -> ElasticsearchSink {
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "Users".to_string(),
+            },
+            table_id: 1,
+            column_schemas: vec![
+                column_schema("id", Type::INT4, true),
+                column_schema("name", Type::TEXT, false),
+            ],
+        };
+        let username = std::env::var("ELASTICSEARCH_USERNAME").ok();
+        let password = std::env::var("ELASTICSEARCH_PASSWORD").ok();
+        let mut sink = ElasticsearchSink::new(
+            url.to_string(),
+            username,
+            password,
+            index_prefix.to_string(),
+        );
+        sink.write_table_schemas(HashMap::from([(1, table_schema)]))
+            .await
+            .unwrap();
+        sink
+    }
+
+    fn row(id: i32, name: &str) -> TableRow {
+        TableRow::from(vec![Cell::I32(id), Cell::String(name.to_string())])
+    }
+
+    #[tokio::test]
+    async fn commit_of_another_transaction_fails_with_both_lsns() {
+        // Nothing is sent before the commit is checked
+        let mut sink = sink("http://localhost:1", "test_").await;
+        let events = vec![
+            CdcEvent::Begin(BeginBody::new(0x100, 0, 700)),
+            CdcEvent::Commit(CommitBody::new(0x200, 0x208, 0), None),
+        ];
+
+        let error = sink.write_cdc_events(events).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            ElasticsearchSinkError::IncorrectCommitLsn(..)
+        ));
+        assert_eq!(
+            error.to_string(),
+            "incorrect commit lsn: 0/200 (expected: 0/100)"
+        );
+    }
+
+    #[cfg(feature = "elasticsearch_tests")]
+    mod elasticsearch {
+        use reqwest::StatusCode;
+        use serde_json::{json, Value};
+        use uuid::Uuid;
+
+        use crate::{
+            conversions::{
+                cdc_event::{BeginBody, CdcEvent, CommitBody},
+                table_row::TableRow,
+                Cell,
+            },
+            pipeline::sinks::BatchSink,
+        };
+
+        use super::{row, sink};
+
+        fn url() -> String {
+            std::env::var("ELASTICSEARCH_URL")
+                .unwrap_or_else(|_| "http://localhost:9200".to_string())
+        }
+
+        /// An index prefix no other test run uses
+        fn index_prefix() -> String {
+            format!("test_{}_", Uuid::new_v4().simple())
+        }
+
+        async fn request(method: reqwest::Method, path: &str) -> reqwest::Response {
+            let builder = reqwest::Client::new().request(method, format!("{}/{path}", url()));
+            let builder = match std::env::var("ELASTICSEARCH_USERNAME") {
+                Ok(username) => {
+                    builder.basic_auth(username, std::env::var("ELASTICSEARCH_PASSWORD").ok())
+                }
+                Err(_) => builder,
+            };
+            builder.send().await.unwrap()
+        }
+
+        /// Returns the source of the document, or `None` if there is none
+        async fn document(index: &str, id: &str) -> Option<Value> {
+            let response = request(reqwest::Method::GET, &format!("{index}/_doc/{id}")).await;
+            if response.status() == StatusCode::NOT_FOUND {
+                return None;
+            }
+            let response: Value = response.error_for_status().unwrap().json().await.unwrap();
+            Some(response["_source"].clone())
+        }
+
+        async fn delete_indices(index_prefix: &str) {
+            for index in ["public.users", "_pg_replicate"] {
+                request(reqwest::Method::DELETE, &format!("{index_prefix}{index}")).await;
+            }
+        }
+
+        #[tokio::test]
+        async fn rows_and_changes_are_indexed_by_primary_key() {
+            let index_prefix = index_prefix();
+            let index = format!("{index_prefix}public.users");
+            let mut sink = sink(&url(), &index_prefix).await;
+
+            sink.write_table_rows(vec![row(1, "alice"), row(2, "bob")], 1)
+                .await
+                .unwrap();
+            sink.table_copied(1).await.unwrap();
+            sink.get_resumption_state().await.unwrap();
+            let events = vec![
+                CdcEvent::Begin(BeginBody::new(0x100, 0, 700)),
+                CdcEvent::Insert((1, row(3, "carol"), None)),
+                CdcEvent::Update {
+                    table_id: 1,
+                    old_row: None,
+                    key_row: None,
+                    row: row(1, "alicia"),
+                    key_columns: vec![0],
+                    transaction_info: None,
+                },
+                CdcEvent::Update {
+                    table_id: 1,
+                    old_row: None,
+                    key_row: Some(TableRow::from(vec![Cell::I32(2), Cell::Null])),
+                    row: row(4, "bob"),
+                    key_columns: vec![0],
+                    transaction_info: None,
+                },
+                CdcEvent::Delete {
+                    table_id: 1,
+                    row: TableRow::from(vec![Cell::I32(3), Cell::Null]),
+                    key_columns: vec![0],
+                    transaction_info: None,
+                },
+                CdcEvent::Commit(CommitBody::new(0x100, 0x108, 0), None),
+            ];
+            let lsn = sink.write_cdc_events(events).await.unwrap();
+
+            assert_eq!(u64::from(lsn), 0x100);
+            assert_eq!(
+                document(&index, "1").await,
+                Some(json!({"id": 1, "name": "alicia"}))
+            );
+            assert_eq!(document(&index, "2").await, None);
+            assert_eq!(document(&index, "3").await, None);
+            assert_eq!(
+                document(&index, "4").await,
+                Some(json!({"id": 4, "name": "bob"}))
+            );
+
+            let mut restarted = sink(&url(), &index_prefix).await;
+            let state = restarted.get_resumption_state().await.unwrap();
+            assert_eq!(u64::from(state.last_lsn), 0x100);
+            assert!(state.copied_tables.contains(&1));
+
+            delete_indices(&index_prefix).await;
+        }
+
+        #[tokio::test]
+        async fn truncate_deletes_all_documents_of_the_table() {
+            let index_prefix = index_prefix();
+            let index = format!("{index_prefix}public.users");
+            let mut sink = sink(&url(), &index_prefix).await;
+            sink.write_table_rows(vec![row(1, "alice"), row(2, "bob")], 1)
+                .await
+                .unwrap();
+
+            sink.truncate_table(1).await.unwrap();
+
+            assert_eq!(document(&index, "1").await, None);
+            assert_eq!(document(&index, "2").await, None);
+
+            delete_indices(&index_prefix).await;
+        }
+    }
+}
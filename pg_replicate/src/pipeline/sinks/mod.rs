@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display};
 
 use async_trait::async_trait;
 use thiserror::Error;
@@ -13,10 +13,20 @@ use super::PipelineResumptionState;
 
 #[cfg(feature = "bigquery")]
 pub mod bigquery;
+pub mod compacting;
 #[cfg(feature = "delta")]
 pub mod delta;
 #[cfg(feature = "duckdb")]
 pub mod duckdb;
+#[cfg(feature = "elasticsearch")]
+pub mod elasticsearch;
+#[cfg(feature = "object_store")]
+pub mod object_store;
+#[cfg(feature = "test-util")]
+pub mod recording;
+pub mod serialization;
+#[cfg(feature = "sql")]
+pub mod sql;
 #[cfg(feature = "stdout")]
 pub mod stdout;
 
@@ -27,9 +37,131 @@ pub trait SinkError: std::error::Error + Send + Sync + 'static {}
 pub enum InfallibleSinkError {}
 impl SinkError for InfallibleSinkError {}
 
+/// A column whose type in the sink differs from the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnTypeMismatch {
+    pub column_name: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Differences between a source table's schema and its table in the sink
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    /// Columns present in the source but missing in the sink
+    pub missing_columns: Vec<String>,
+    pub type_mismatches: Vec<ColumnTypeMismatch>,
+    /// Columns which are nullable in the source but not in the sink
+    pub nullability_mismatches: Vec<String>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing_columns.is_empty()
+            && self.type_mismatches.is_empty()
+            && self.nullability_mismatches.is_empty()
+    }
+}
+
+impl Display for SchemaDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut differences = vec![];
+        for column_name in &self.missing_columns {
+            differences.push(format!("missing column `{column_name}`"));
+        }
+        for mismatch in &self.type_mismatches {
+            differences.push(format!(
+                "column `{}` has type `{}` (expected: `{}`)",
+                mismatch.column_name, mismatch.actual, mismatch.expected
+            ));
+        }
+        for column_name in &self.nullability_mismatches {
+            differences.push(format!("column `{column_name}` has different nullability"));
+        }
+        f.write_str(&differences.join(", "))
+    }
+}
+
+/// What a pipeline does when a sink's table schema differs from the source
+#[derive(Debug, Clone, Copy, Default)]
+pub enum SchemaMismatchPolicy {
+    /// Ask the sink to migrate its table and fail if differences remain
+    AutoMigrate,
+    /// Log the differences and continue
+    #[default]
+    Warn,
+    /// Refuse to start the pipeline
+    Fail,
+}
+
+/// How a sink applies updates and deletes to its tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApplyMode {
+    /// Updates replace rows in place and deletes remove them
+    #[default]
+    Upsert,
+    /// Rows are never changed in place. Every insert, update and delete is
+    /// appended as a new row with a column holding the operation, deletes
+    /// appending a tombstone row.
+    AppendOnly,
+    /// Like [`ApplyMode::Upsert`] but deletes mark rows as deleted instead of
+    /// removing them
+    SoftDelete,
+}
+
+impl Display for ApplyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApplyMode::Upsert => f.write_str("upsert"),
+            ApplyMode::AppendOnly => f.write_str("append only"),
+            ApplyMode::SoftDelete => f.write_str("soft delete"),
+        }
+    }
+}
+
+/// What a sink can do, consulted by the pipeline before it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkCapabilities {
+    /// Deletes are applied to, or recorded in, the sink's tables instead of
+    /// being dropped
+    pub supports_delete: bool,
+    /// [`BatchSink::migrate_schema`] can resolve schema differences
+    pub supports_schema_evolution: bool,
+    /// The changes of a source transaction are applied atomically
+    pub supports_transactions: bool,
+    /// Writing the same rows or events again leaves the sink unchanged
+    pub idempotent: bool,
+    /// Rows are applied by the key of their table, so tables with neither a
+    /// primary key nor a unique index as their replica identity can't be
+    /// replicated to the sink
+    pub requires_key: bool,
+}
+
+/// New secrets for a sink's client, e.g. after a key rotation
+#[derive(Clone)]
+pub enum SinkCredentials {
+    /// A GCP service account key in JSON
+    ServiceAccountKey(String),
+    /// An access key pair, as used by S3
+    AccessKey {
+        access_key_id: String,
+        secret_access_key: String,
+    },
+}
+
 #[async_trait]
 pub trait BatchSink {
     type Error: SinkError;
+
+    /// Returns whether the sink can be written to, e.g. whether its database
+    /// is reachable. Returns `Ok(false)` while the sink is unavailable but
+    /// may become available by waiting and an error when waiting won't help.
+    /// Polled before the pipeline starts, see
+    /// [`crate::pipeline::batching::data_pipeline::BatchDataPipeline::set_sink_ready_timeout`].
+    async fn ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
     async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error>;
     async fn write_table_schemas(
         &mut self,
@@ -42,5 +174,101 @@ pub trait BatchSink {
     ) -> Result<(), Self::Error>;
     async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error>;
     async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error>;
+
+    /// Removes all rows of a table before it is copied. It is never called for
+    /// tables in [`PipelineResumptionState::copied_tables`], so data loaded by a
+    /// completed copy survives a restart. A copy interrupted before
+    /// [`BatchSink::table_copied`] is restarted from the beginning of a new
+    /// snapshot, so its partial rows are truncated. Must be idempotent.
     async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error>;
+
+    /// Called when the copy of a table starts, after
+    /// [`BatchSink::truncate_table`] and before its first rows are written,
+    /// e.g. to open the files its rows are written to. `table_schema` is the
+    /// schema of the sink's table, as passed to
+    /// [`BatchSink::write_table_schemas`].
+    async fn begin_table_copy(
+        &mut self,
+        _table_id: TableId,
+        _table_schema: &TableSchema,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called when all rows of a table were written, before
+    /// [`BatchSink::table_copied`], e.g. to close its files or commit its
+    /// snapshot. Not called if the copy fails, in which case the table is
+    /// truncated and copied again when the pipeline restarts.
+    async fn end_table_copy(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Removes all rows of a copied table and its entry in
+    /// [`PipelineResumptionState::copied_tables`], so that the table is copied
+    /// again from scratch the next time the pipeline starts. Returns `false`
+    /// if the sink can't reset tables. Must be idempotent.
+    async fn reset_table(&mut self, _table_id: TableId) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Returns the number of rows in the sink's table of `table_id`, or `None`
+    /// if the sink can't count them. Used to reconcile a table with the source
+    /// after it was copied.
+    async fn row_count(&mut self, _table_id: TableId) -> Result<Option<u64>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Replaces the credentials of the sink's client without restarting the
+    /// pipeline. Called before the next batch is written after credentials
+    /// were sent to the pipeline. Sinks without credentials ignore them.
+    async fn reload_credentials(
+        &mut self,
+        _credentials: SinkCredentials,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Whether the sink can apply changes in `apply_mode`. Sinks which don't
+    /// override it only support [`ApplyMode::Upsert`], which is how they
+    /// apply changes anyway.
+    fn supports_apply_mode(&self, apply_mode: ApplyMode) -> bool {
+        apply_mode == ApplyMode::Upsert
+    }
+
+    /// Sets how the sink applies changes. Called before
+    /// [`BatchSink::write_table_schemas`] with a mode for which
+    /// [`BatchSink::supports_apply_mode`] returned true.
+    fn set_apply_mode(&mut self, _apply_mode: ApplyMode) {}
+
+    /// Sinks which don't override it apply deletes by the key of their
+    /// tables but can do nothing else
+    fn capabilities(&self) -> SinkCapabilities {
+        SinkCapabilities {
+            supports_delete: true,
+            supports_schema_evolution: false,
+            supports_transactions: false,
+            idempotent: false,
+            requires_key: true,
+        }
+    }
+
+    /// Compares the sink's table for `table_schema` against it. Called after
+    /// [`BatchSink::write_table_schemas`] and before any rows are written, so
+    /// that a table left over from another source is reported before writes
+    /// to it fail, see [`SchemaMismatchPolicy`].
+    async fn validate_schema(
+        &mut self,
+        _table_schema: &TableSchema,
+    ) -> Result<SchemaDiff, Self::Error> {
+        Ok(SchemaDiff::default())
+    }
+
+    /// Tries to resolve the differences in `schema_diff` by altering the sink's table
+    async fn migrate_schema(
+        &mut self,
+        _table_schema: &TableSchema,
+        _schema_diff: &SchemaDiff,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
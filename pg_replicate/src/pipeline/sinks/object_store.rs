@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+use tracing::info;
+
+use crate::{
+    clients::object_store::{
+        ObjectFormat, ObjectStoreBackend, ObjectStoreClient, ObjectStoreClientError,
+    },
+    conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableSchema},
+};
+
+use super::{BatchSink, SinkCredentials, SinkError};
+
+#[derive(Debug, Error)]
+pub enum ObjectStoreSinkError {
+    #[error("object store client error: {0}")]
+    Client(#[from] ObjectStoreClientError),
+
+    #[error("missing table schemas")]
+    MissingTableSchemas,
+
+    #[error("missing table id: {0}")]
+    MissingTableId(TableId),
+
+    #[error("incorrect commit lsn: {0} (expected: {1})")]
+    IncorrectCommitLsn(PgLsn, PgLsn),
+
+    #[error("commit message without begin message")]
+    CommitWithoutBegin,
+
+    #[error("credentials don't match the object store backend")]
+    UnsupportedCredentials,
+}
+
+impl SinkError for ObjectStoreSinkError {}
+
+/// A sink which writes tables as JSONL or Parquet files to S3, GCS, Azure Blob
+/// Storage or memory. Every batch of rows is written as a new file and a
+/// `_change_type` column (`UPSERT` or `DELETE`) is appended to each row.
+pub struct ObjectStoreSink {
+    client: ObjectStoreClient,
+    table_schemas: Option<HashMap<TableId, TableSchema>>,
+    committed_lsn: Option<PgLsn>,
+    final_lsn: Option<PgLsn>,
+}
+
+impl ObjectStoreSink {
+    pub fn new(
+        backend: ObjectStoreBackend,
+        prefix: &str,
+        format: ObjectFormat,
+    ) -> Result<ObjectStoreSink, ObjectStoreSinkError> {
+        let client = ObjectStoreClient::new(backend, prefix, format)?;
+        Ok(ObjectStoreSink {
+            client,
+            table_schemas: None,
+            committed_lsn: None,
+            final_lsn: None,
+        })
+    }
+
+    fn get_table_schema(&self, table_id: TableId) -> Result<&TableSchema, ObjectStoreSinkError> {
+        self.table_schemas
+            .as_ref()
+            .ok_or(ObjectStoreSinkError::MissingTableSchemas)?
+            .get(&table_id)
+            .ok_or(ObjectStoreSinkError::MissingTableId(table_id))
+    }
+
+    async fn write_rows(
+        &self,
+        table_id: TableId,
+        table_rows: &[TableRow],
+    ) -> Result<(), ObjectStoreSinkError> {
+        let table_schema = self.get_table_schema(table_id)?;
+        let path = self
+            .client
+            .write_rows(
+                &table_schema.table_name,
+                &table_schema.column_schemas,
+                table_rows,
+            )
+            .await?;
+        info!("wrote {} rows to {path}", table_rows.len());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BatchSink for ObjectStoreSink {
+    type Error = ObjectStoreSinkError;
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        info!("getting resumption state from object store");
+        let copied_tables = self.client.get_copied_table_ids().await?;
+        let last_lsn = self.client.get_last_lsn().await?;
+
+        self.committed_lsn = Some(last_lsn);
+
+        Ok(PipelineResumptionState {
+            copied_tables,
+            last_lsn,
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        self.table_schemas = Some(table_schemas);
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        mut table_rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        for table_row in &mut table_rows {
+            table_row.values.push(Cell::String("UPSERT".to_string()));
+        }
+        self.write_rows(table_id, &table_rows).await
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        let mut table_id_to_table_rows: HashMap<TableId, Vec<TableRow>> = HashMap::new();
+        let mut new_last_lsn = PgLsn::from(0);
+        for event in events {
+            match event {
+                CdcEvent::Begin(begin_body) => {
+                    let final_lsn_u64 = begin_body.final_lsn();
+                    self.final_lsn = Some(final_lsn_u64.into());
+                }
+                CdcEvent::Commit(commit_body, _) => {
+                    let commit_lsn: PgLsn = commit_body.commit_lsn().into();
+                    if let Some(final_lsn) = self.final_lsn {
+                        if commit_lsn == final_lsn {
+                            new_last_lsn = commit_lsn;
+                        } else {
+                            Err(ObjectStoreSinkError::IncorrectCommitLsn(
+                                commit_lsn, final_lsn,
+                            ))?
+                        }
+                    } else {
+                        Err(ObjectStoreSinkError::CommitWithoutBegin)?
+                    }
+                }
+                CdcEvent::Insert((table_id, mut table_row, _)) => {
+                    table_row.values.push(Cell::String("UPSERT".to_string()));
+                    table_id_to_table_rows
+                        .entry(table_id)
+                        .or_default()
+                        .push(table_row);
+                }
+                CdcEvent::Update {
+                    table_id,
+                    row: mut table_row,
+                    ..
+                } => {
+                    table_row.values.push(Cell::String("UPSERT".to_string()));
+                    table_id_to_table_rows
+                        .entry(table_id)
+                        .or_default()
+                        .push(table_row);
+                }
+                CdcEvent::Delete {
+                    table_id,
+                    row: mut table_row,
+                    ..
+                } => {
+                    table_row.values.push(Cell::String("DELETE".to_string()));
+                    table_id_to_table_rows
+                        .entry(table_id)
+                        .or_default()
+                        .push(table_row);
+                }
+                CdcEvent::Relation(_) => {}
+                CdcEvent::KeepAliveRequested { reply: _ } => {}
+                CdcEvent::Type(_) => {}
+                CdcEvent::Sequence { .. } => {}
+                CdcEvent::LogicalMessage { .. } => {}
+            }
+        }
+
+        for (table_id, table_rows) in table_id_to_table_rows {
+            self.write_rows(table_id, &table_rows).await?;
+        }
+
+        if new_last_lsn != PgLsn::from(0) {
+            self.client.set_last_lsn(new_last_lsn).await?;
+            self.committed_lsn = Some(new_last_lsn);
+        }
+
+        let committed_lsn = self.committed_lsn.expect("committed lsn is none");
+        Ok(committed_lsn)
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.client.insert_into_copied_tables(table_id).await?;
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        let table_schema = self.get_table_schema(table_id)?;
+        self.client
+            .delete_table_files(&table_schema.table_name)
+            .await?;
+        Ok(())
+    }
+    async fn reload_credentials(
+        &mut self,
+        credentials: SinkCredentials,
+    ) -> Result<(), Self::Error> {
+        let backend = match (self.client.backend().clone(), credentials) {
+            (
+                ObjectStoreBackend::S3 { bucket, region, .. },
+                SinkCredentials::AccessKey {
+                    access_key_id,
+                    secret_access_key,
+                },
+            ) => ObjectStoreBackend::S3 {
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+            },
+            (
+                ObjectStoreBackend::Gcs { bucket, .. },
+                SinkCredentials::ServiceAccountKey(service_account_key),
+            ) => ObjectStoreBackend::Gcs {
+                bucket,
+                service_account_key,
+            },
+            _ => return Err(ObjectStoreSinkError::UnsupportedCredentials),
+        };
+        info!("reloading object store credentials");
+        self.client.reload_backend(backend)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! A sink which keeps in memory what a pipeline writes to it, for testing
+//! pipelines without a real sink.
+//!
+//! ```
+//! use std::collections::HashSet;
+//!
+//! use pg_replicate::pipeline::{sinks::recording::RecordingSink, PipelineResumptionState};
+//! use pg_replicate::tokio_postgres::types::PgLsn;
+//!
+//! // A sink which has already copied table 1
+//! let mut sink = RecordingSink::new();
+//! sink.set_resumption_state(PipelineResumptionState {
+//!     copied_tables: HashSet::from([1]),
+//!     last_lsn: PgLsn::from(0),
+//! });
+//!
+//! // ...build a pipeline writing to `sink` and start it...
+//!
+//! assert!(sink.table_rows().is_empty());
+//! assert!(sink.truncated_tables().is_empty());
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableSchema},
+};
+
+use super::{BatchSink, InfallibleSinkError};
+
+/// Records every call a pipeline makes to it. Copied tables and the lsn of
+/// the last commit written are also kept in its resumption state, so a
+/// pipeline started again with the same sink resumes where it stopped.
+pub struct RecordingSink {
+    resumption_state: PipelineResumptionState,
+    resumption_state_reads: usize,
+    table_schemas: HashMap<TableId, TableSchema>,
+    table_rows: Vec<(TableId, Vec<TableRow>)>,
+    cdc_events: Vec<Vec<CdcEvent>>,
+    copied_tables: Vec<TableId>,
+    truncated_tables: Vec<TableId>,
+}
+
+impl Default for RecordingSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecordingSink {
+    /// A sink which hasn't copied any tables or seen any changes
+    pub fn new() -> RecordingSink {
+        RecordingSink {
+            resumption_state: PipelineResumptionState {
+                copied_tables: HashSet::new(),
+                last_lsn: PgLsn::from(0),
+            },
+            resumption_state_reads: 0,
+            table_schemas: HashMap::new(),
+            table_rows: vec![],
+            cdc_events: vec![],
+            copied_tables: vec![],
+            truncated_tables: vec![],
+        }
+    }
+
+    /// Sets the state returned by [`BatchSink::get_resumption_state`]
+    pub fn set_resumption_state(&mut self, resumption_state: PipelineResumptionState) {
+        self.resumption_state = resumption_state;
+    }
+
+    /// Number of times [`BatchSink::get_resumption_state`] was called
+    pub fn resumption_state_reads(&self) -> usize {
+        self.resumption_state_reads
+    }
+
+    /// Schemas of the tables written, by table id
+    pub fn table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    /// Batches of rows written while copying tables, with the id of the
+    /// table they were written to, in the order they were written
+    pub fn table_rows(&self) -> &[(TableId, Vec<TableRow>)] {
+        &self.table_rows
+    }
+
+    /// Rows written to the table `table_id`, across all batches
+    pub fn rows_of_table(&self, table_id: TableId) -> Vec<&TableRow> {
+        self.table_rows
+            .iter()
+            .filter(|(id, _)| *id == table_id)
+            .flat_map(|(_, rows)| rows)
+            .collect()
+    }
+
+    /// Batches of cdc events written, in the order they were written
+    pub fn cdc_events(&self) -> &[Vec<CdcEvent>] {
+        &self.cdc_events
+    }
+
+    /// Ids of the tables whose copy completed, in the order they completed
+    pub fn copied_tables(&self) -> &[TableId] {
+        &self.copied_tables
+    }
+
+    /// Ids of the tables truncated, in the order they were truncated
+    pub fn truncated_tables(&self) -> &[TableId] {
+        &self.truncated_tables
+    }
+}
+
+#[async_trait]
+impl BatchSink for RecordingSink {
+    type Error = InfallibleSinkError;
+
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        self.resumption_state_reads += 1;
+        Ok(self.resumption_state.clone())
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        self.table_schemas.extend(table_schemas);
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        self.table_rows.push((table_id, rows));
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        for event in &events {
+            if let CdcEvent::Commit(commit_body, _) = event {
+                self.resumption_state.last_lsn = commit_body.commit_lsn().into();
+            }
+        }
+        self.cdc_events.push(events);
+        Ok(self.resumption_state.last_lsn)
+    }
+
+    async fn table_copied(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.copied_tables.push(table_id);
+        self.resumption_state.copied_tables.insert(table_id);
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        self.truncated_tables.push(table_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use tokio_postgres::types::PgLsn;
+
+    use crate::{
+        conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell},
+        pipeline::{sinks::BatchSink, PipelineResumptionState},
+    };
+
+    use super::RecordingSink;
+
+    fn row(id: i32) -> TableRow {
+        TableRow::from(vec![Cell::I32(id)])
+    }
+
+    #[tokio::test]
+    async fn rows_are_recorded_by_table_in_the_order_they_were_written() {
+        let mut sink = RecordingSink::new();
+
+        sink.write_table_rows(vec![row(1), row(2)], 1)
+            .await
+            .unwrap();
+        sink.write_table_rows(vec![row(10)], 2).await.unwrap();
+        sink.write_table_rows(vec![row(3)], 1).await.unwrap();
+
+        assert_eq!(sink.table_rows().len(), 3);
+        assert_eq!(sink.rows_of_table(1), vec![&row(1), &row(2), &row(3)]);
+        assert_eq!(sink.rows_of_table(2), vec![&row(10)]);
+        assert!(sink.rows_of_table(3).is_empty());
+    }
+
+    #[tokio::test]
+    async fn set_resumption_state_is_returned_and_reads_are_counted() {
+        let mut sink = RecordingSink::new();
+        sink.set_resumption_state(PipelineResumptionState {
+            copied_tables: HashSet::from([1, 2]),
+            last_lsn: PgLsn::from(42),
+        });
+
+        let resumption_state = sink.get_resumption_state().await.unwrap();
+        sink.get_resumption_state().await.unwrap();
+
+        assert_eq!(resumption_state.copied_tables, HashSet::from([1, 2]));
+        assert_eq!(resumption_state.last_lsn, PgLsn::from(42));
+        assert_eq!(sink.resumption_state_reads(), 2);
+    }
+
+    #[tokio::test]
+    async fn copied_tables_are_resumed_from() {
+        let mut sink = RecordingSink::new();
+
+        sink.truncate_table(1).await.unwrap();
+        sink.table_copied(1).await.unwrap();
+
+        assert_eq!(sink.truncated_tables(), &[1]);
+        assert_eq!(sink.copied_tables(), &[1]);
+        let resumption_state = sink.get_resumption_state().await.unwrap();
+        assert_eq!(resumption_state.copied_tables, HashSet::from([1]));
+    }
+
+    #[tokio::test]
+    async fn cdc_events_are_recorded_in_batches() {
+        let mut sink = RecordingSink::new();
+        let insert = |id| CdcEvent::Insert((1, row(id), None));
+
+        let lsn = sink
+            .write_cdc_events(vec![insert(1), insert(2)])
+            .await
+            .unwrap();
+        sink.write_cdc_events(vec![insert(3)]).await.unwrap();
+
+        // Without commits the last lsn doesn't move
+        assert_eq!(lsn, PgLsn::from(0));
+        assert_eq!(sink.cdc_events().len(), 2);
+        assert_eq!(sink.cdc_events()[0].len(), 2);
+        assert_eq!(sink.cdc_events()[1].len(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+/// A pool of blocking tasks which serializes rows into a sink's wire format
+/// in parallel before they are written. Rows are split into one contiguous
+/// chunk per worker and the results are returned in the order of the rows.
+#[derive(Debug, Clone, Copy)]
+pub struct SerializationPool {
+    workers: usize,
+}
+
+impl Default for SerializationPool {
+    /// A single worker, which serializes rows inline in the pipeline task
+    fn default() -> Self {
+        SerializationPool { workers: 1 }
+    }
+}
+
+impl SerializationPool {
+    pub fn new(workers: usize) -> SerializationPool {
+        SerializationPool {
+            workers: workers.max(1),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub async fn serialize<T, R, F>(&self, rows: Vec<T>, serialize: F) -> Vec<R>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        if self.workers == 1 || rows.len() < 2 {
+            return rows.into_iter().map(serialize).collect();
+        }
+
+        let num_rows = rows.len();
+        let chunk_size = num_rows.div_ceil(self.workers);
+        let serialize = Arc::new(serialize);
+        let mut rows = rows.into_iter();
+        let mut handles = Vec::with_capacity(self.workers);
+        loop {
+            let chunk: Vec<T> = rows.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            let serialize = serialize.clone();
+            handles.push(tokio::task::spawn_blocking(move || {
+                chunk
+                    .into_iter()
+                    .map(|row| serialize(row))
+                    .collect::<Vec<R>>()
+            }));
+        }
+
+        let mut serialized = Vec::with_capacity(num_rows);
+        for handle in handles {
+            let chunk = handle
+                .await
+                .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+            serialized.extend(chunk);
+        }
+        serialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SerializationPool;
+
+    #[tokio::test]
+    async fn rows_are_returned_in_order_whichever_worker_finishes_first() {
+        let rows: Vec<u64> = (0..10).collect();
+
+        // earlier rows take longer so that the last chunks are done first
+        let serialized = SerializationPool::new(4)
+            .serialize(rows.clone(), |row| {
+                std::thread::sleep(Duration::from_millis(10 * (10 - row)));
+                row.to_string()
+            })
+            .await;
+
+        let expected: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        assert_eq!(serialized, expected);
+    }
+
+    #[tokio::test]
+    async fn rows_are_returned_in_order_with_more_workers_than_rows() {
+        let serialized = SerializationPool::new(8)
+            .serialize(vec![1, 2, 3], |row| row * 10)
+            .await;
+
+        assert_eq!(serialized, vec![10, 20, 30]);
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use async_trait::async_trait;
+use pg_escape::{quote_identifier, quote_literal};
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    pipeline::PipelineResumptionState,
+    table::{TableId, TableSchema},
+};
+
+use super::{BatchSink, SinkError};
+
+#[derive(Debug, Error)]
+pub enum SqlSinkError {
+    #[error("failed to write statements: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("missing table id: {0}")]
+    MissingTableId(TableId),
+}
+
+impl SinkError for SqlSinkError {}
+
+/// A sink which writes the rows and changes it receives as SQL statements,
+/// one per line, which can be run against a database with the same tables,
+/// e.g. to migrate them or to see what a pipeline would apply. Copied rows
+/// are `INSERT`s and changes are `INSERT`s, `UPDATE`s and `DELETE`s wrapped
+/// in the `BEGIN` and `COMMIT` of their transaction. Updates and deletes find
+/// rows by the values of their table's key columns. Values are written with
+/// [`crate::conversions::Cell::to_sql_literal`].
+///
+/// Like the stdout sink it keeps no state, every start copies the tables
+/// again.
+pub struct SqlSink {
+    writer: Box<dyn Write + Send>,
+    table_schemas: HashMap<TableId, TableSchema>,
+    last_lsn: PgLsn,
+}
+
+impl SqlSink {
+    /// A sink writing statements to `writer`
+    pub fn new(writer: Box<dyn Write + Send>) -> SqlSink {
+        SqlSink {
+            writer,
+            table_schemas: HashMap::new(),
+            last_lsn: PgLsn::from(0),
+        }
+    }
+
+    /// A sink writing statements to stdout
+    pub fn stdout() -> SqlSink {
+        SqlSink::new(Box::new(io::stdout()))
+    }
+
+    /// A sink writing statements to the file at `path`, which is truncated if
+    /// it exists
+    pub fn file(path: &Path) -> Result<SqlSink, SqlSinkError> {
+        let file = File::create(path)?;
+        Ok(SqlSink::new(Box::new(BufWriter::new(file))))
+    }
+
+    fn table_schema(&self, table_id: TableId) -> Result<&TableSchema, SqlSinkError> {
+        self.table_schemas
+            .get(&table_id)
+            .ok_or(SqlSinkError::MissingTableId(table_id))
+    }
+
+    fn insert_statement(&self, table_id: TableId, row: &TableRow) -> Result<String, SqlSinkError> {
+        let table_schema = self.table_schema(table_id)?;
+        let columns: Vec<String> = table_schema
+            .column_schemas
+            .iter()
+            .map(|column_schema| quote_identifier(&column_schema.name).to_string())
+            .collect();
+        let values: Vec<String> = row
+            .values
+            .iter()
+            .map(|cell| cell.to_sql_literal())
+            .collect();
+        Ok(format!(
+            "INSERT INTO {} ({}) VALUES ({});",
+            table_schema.table_name.as_quoted_identifier(),
+            columns.join(", "),
+            values.join(", ")
+        ))
+    }
+
+    /// `key_row` holds the values the key columns had before the update
+    fn update_statement(
+        &self,
+        table_id: TableId,
+        key_row: &TableRow,
+        row: &TableRow,
+        key_columns: &[usize],
+    ) -> Result<String, SqlSinkError> {
+        let table_schema = self.table_schema(table_id)?;
+        let assignments: Vec<String> = table_schema
+            .column_schemas
+            .iter()
+            .zip(&row.values)
+            .map(|(column_schema, cell)| {
+                format!(
+                    "{} = {}",
+                    quote_identifier(&column_schema.name),
+                    cell.to_sql_literal()
+                )
+            })
+            .collect();
+        Ok(format!(
+            "UPDATE {} SET {} WHERE {};",
+            table_schema.table_name.as_quoted_identifier(),
+            assignments.join(", "),
+            Self::key_condition(table_schema, key_row, key_columns)
+        ))
+    }
+
+    fn delete_statement(
+        &self,
+        table_id: TableId,
+        row: &TableRow,
+        key_columns: &[usize],
+    ) -> Result<String, SqlSinkError> {
+        let table_schema = self.table_schema(table_id)?;
+        Ok(format!(
+            "DELETE FROM {} WHERE {};",
+            table_schema.table_name.as_quoted_identifier(),
+            Self::key_condition(table_schema, row, key_columns)
+        ))
+    }
+
+    /// Matches the values of `row`'s key columns. NULL keys, possible with
+    /// replica identity full, are matched with `IS NULL`.
+    fn key_condition(table_schema: &TableSchema, row: &TableRow, key_columns: &[usize]) -> String {
+        let conditions: Vec<String> = key_columns
+            .iter()
+            .map(|&i| {
+                let column = quote_identifier(&table_schema.column_schemas[i].name);
+                match row.values[i].to_sql_literal().as_str() {
+                    "NULL" => format!("{column} IS NULL"),
+                    value => format!("{column} = {value}"),
+                }
+            })
+            .collect();
+        conditions.join(" AND ")
+    }
+
+    /// The statement applying `event`, if it changes the tables
+    fn cdc_event_statement(&mut self, event: &CdcEvent) -> Result<Option<String>, SqlSinkError> {
+        let statement = match event {
+            CdcEvent::Begin(_) => "BEGIN;".to_string(),
+            CdcEvent::Commit(commit_body, _) => {
+                self.last_lsn = commit_body.commit_lsn().into();
+                "COMMIT;".to_string()
+            }
+            CdcEvent::Insert((table_id, row, _)) => self.insert_statement(*table_id, row)?,
+            CdcEvent::Update {
+                table_id,
+                old_row,
+                key_row,
+                row,
+                key_columns,
+                ..
+            } => {
+                let key_row = key_row.as_ref().or(old_row.as_ref()).unwrap_or(row);
+                self.update_statement(*table_id, key_row, row, key_columns)?
+            }
+            CdcEvent::Delete {
+                table_id,
+                row,
+                key_columns,
+                ..
+            } => self.delete_statement(*table_id, row, key_columns)?,
+            CdcEvent::Sequence {
+                schema,
+                name,
+                last_value,
+                is_called,
+                ..
+            } => {
+                let sequence = format!("{}.{}", quote_identifier(schema), quote_identifier(name));
+                format!(
+                    "SELECT setval({}, {last_value}, {is_called});",
+                    quote_literal(&sequence)
+                )
+            }
+            CdcEvent::Relation(_)
+            | CdcEvent::Type(_)
+            | CdcEvent::LogicalMessage { .. }
+            | CdcEvent::KeepAliveRequested { .. } => return Ok(None),
+        };
+        Ok(Some(statement))
+    }
+}
+
+#[async_trait]
+impl BatchSink for SqlSink {
+    type Error = SqlSinkError;
+
+    async fn get_resumption_state(&mut self) -> Result<PipelineResumptionState, Self::Error> {
+        Ok(PipelineResumptionState {
+            copied_tables: HashSet::new(),
+            last_lsn: PgLsn::from(0),
+        })
+    }
+
+    async fn write_table_schemas(
+        &mut self,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<(), Self::Error> {
+        self.table_schemas.extend(table_schemas);
+        Ok(())
+    }
+
+    async fn write_table_rows(
+        &mut self,
+        rows: Vec<TableRow>,
+        table_id: TableId,
+    ) -> Result<(), Self::Error> {
+        for row in &rows {
+            let statement = self.insert_statement(table_id, row)?;
+            writeln!(self.writer, "{statement}")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    async fn write_cdc_events(&mut self, events: Vec<CdcEvent>) -> Result<PgLsn, Self::Error> {
+        for event in &events {
+            if let Some(statement) = self.cdc_event_statement(event)? {
+                writeln!(self.writer, "{statement}")?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(self.last_lsn)
+    }
+
+    async fn table_copied(&mut self, _table_id: TableId) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn truncate_table(&mut self, table_id: TableId) -> Result<(), Self::Error> {
+        let table_schema = self.table_schema(table_id)?;
+        let statement = format!(
+            "TRUNCATE {};",
+            table_schema.table_name.as_quoted_identifier()
+        );
+        writeln!(self.writer, "{statement}")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use tokio_postgres::types::Type;
+
+    use crate::{
+        conversions::{
+            cdc_event::{BeginBody, CdcEvent, CommitBody},
+            table_row::TableRow,
+            Cell,
+        },
+        pipeline::sinks::BatchSink,
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::SqlSink;
+
+    /// A writer whose output the test can read after the sink wrote to it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            let output = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+            output.lines().map(|line| line.to_string()).collect()
+        }
+    }
+
+    fn column_schema(name: &str, typ: Type, primary: bool) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: !primary,
+            primary,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    async fn sink() -> (SqlSink, SharedBuffer) {
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "Files".to_string(),
+            },
+            table_id: 1,
+            column_schemas: vec![
+                column_schema("id", Type::INT4, true),
+                column_schema("title", Type::TEXT, false),
+                column_schema("payload", Type::BYTEA, false),
+            ],
+        };
+        let buffer = SharedBuffer::default();
+        let mut sink = SqlSink::new(Box::new(buffer.clone()));
+        sink.write_table_schemas(HashMap::from([(1, table_schema)]))
+            .await
+            .unwrap();
+        (sink, buffer)
+    }
+
+    fn row(id: i32, title: &str, payload: &[u8]) -> TableRow {
+        TableRow::from(vec![
+            Cell::I32(id),
+            Cell::String(title.to_string()),
+            Cell::Bytes(payload.to_vec()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn changes_are_written_as_statements_of_their_transaction() {
+        let (mut sink, buffer) = sink().await;
+        let events = vec![
+            CdcEvent::Begin(BeginBody::new(0x100, 0, 700)),
+            CdcEvent::Insert((1, row(1, "o'neil.txt", &[0xca, 0xfe]), None)),
+            CdcEvent::Update {
+                table_id: 1,
+                old_row: None,
+                key_row: None,
+                row: row(1, "renamed.txt", &[]),
+                key_columns: vec![0],
+                transaction_info: None,
+            },
+            CdcEvent::Delete {
+                table_id: 1,
+                row: TableRow::from(vec![Cell::I32(1), Cell::Null, Cell::Null]),
+                key_columns: vec![0],
+                transaction_info: None,
+            },
+            CdcEvent::Commit(CommitBody::new(0x100, 0x108, 0), None),
+        ];
+
+        let lsn = sink.write_cdc_events(events).await.unwrap();
+
+        assert_eq!(u64::from(lsn), 0x100);
+        assert_eq!(
+            buffer.lines(),
+            vec![
+                "BEGIN;",
+                r#"INSERT INTO public."Files" (id, title, payload) VALUES (1, 'o''neil.txt', E'\\xcafe');"#,
+                r#"UPDATE public."Files" SET id = 1, title = 'renamed.txt', payload = E'\\x' WHERE id = 1;"#,
+                r#"DELETE FROM public."Files" WHERE id = 1;"#,
+                "COMMIT;",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn update_of_a_key_finds_the_row_by_its_old_key() {
+        let (mut sink, buffer) = sink().await;
+        let update = CdcEvent::Update {
+            table_id: 1,
+            old_row: None,
+            key_row: Some(TableRow::from(vec![Cell::I32(1), Cell::Null, Cell::Null])),
+            row: row(2, "a", &[]),
+            key_columns: vec![0],
+            transaction_info: None,
+        };
+
+        sink.write_cdc_events(vec![update]).await.unwrap();
+
+        assert_eq!(
+            buffer.lines(),
+            vec![
+                r#"UPDATE public."Files" SET id = 2, title = 'a', payload = E'\\x' WHERE id = 1;"#
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn copied_rows_are_inserts_after_the_truncate() {
+        let (mut sink, buffer) = sink().await;
+
+        sink.truncate_table(1).await.unwrap();
+        sink.write_table_rows(vec![row(1, r"C:\notes.txt", &[0x00])], 1)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            buffer.lines(),
+            vec![
+                r#"TRUNCATE public."Files";"#,
+                r#"INSERT INTO public."Files" (id, title, payload) VALUES (1, E'C:\\notes.txt', E'\\x00');"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_with_a_null_key_value_matches_it_with_is_null() {
+        let (mut sink, buffer) = sink().await;
+        let delete = CdcEvent::Delete {
+            table_id: 1,
+            row: row(1, "a", &[]),
+            key_columns: vec![0, 1],
+            transaction_info: None,
+        };
+        let delete_null = CdcEvent::Delete {
+            table_id: 1,
+            row: TableRow::from(vec![Cell::I32(1), Cell::Null, Cell::Null]),
+            key_columns: vec![0, 1],
+            transaction_info: None,
+        };
+
+        sink.write_cdc_events(vec![delete, delete_null])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            buffer.lines(),
+            vec![
+                r#"DELETE FROM public."Files" WHERE id = 1 AND title = 'a';"#,
+                r#"DELETE FROM public."Files" WHERE id = 1 AND title IS NULL;"#,
+            ]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use async_trait::async_trait;
 use thiserror::Error;
 use tokio_postgres::types::PgLsn;
 
-use crate::table::{ColumnSchema, TableId, TableName, TableSchema};
+use crate::table::{ColumnSchema, TableId, TableInfo, TableName, TableSchema};
 
 use self::postgres::{
     CdcStream, CdcStreamError, PostgresSourceError, StatusUpdateError, TableCopyStream,
@@ -12,6 +12,9 @@ use self::postgres::{
 };
 
 pub mod postgres;
+pub mod replay;
+#[cfg(feature = "test-util")]
+pub mod vec;
 
 pub trait SourceError: std::error::Error + Send + Sync + 'static {}
 
@@ -37,19 +40,68 @@ pub enum CommonSourceError {
 
 impl SourceError for CommonSourceError {}
 
+/// Where a source's replication slot is, see [`Source::get_slot_position`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotPosition {
+    /// The oldest LSN whose WAL the slot retains. Changes before it can't be
+    /// streamed anymore.
+    pub restart_lsn: PgLsn,
+    /// The LSN up to which changes were confirmed as applied. Streams start
+    /// after it even if an earlier LSN is asked for.
+    pub confirmed_flush_lsn: PgLsn,
+}
+
 #[async_trait]
 pub trait Source {
     type Error: SourceError;
 
     fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema>;
 
+    /// Lists all tables the source was created with, including those which
+    /// are not replicated because they lack a replica identity
+    async fn list_tables(&self) -> Result<Vec<TableInfo>, Self::Error>;
+
+    /// Returns the tables the source was created with whose updates and
+    /// deletes can't be replicated, like those with `REPLICA IDENTITY NOTHING`
+    /// or the default replica identity and no primary key
+    async fn tables_without_replica_identity(&self) -> Result<Vec<TableName>, Self::Error>;
+
     async fn get_table_copy_stream(
         &self,
         table_name: &TableName,
         column_schemas: &[ColumnSchema],
     ) -> Result<TableCopyStream, Self::Error>;
 
+    /// Counts the rows of a table in the same snapshot as the rows returned
+    /// by [`Source::get_table_copy_stream`]
+    async fn get_row_count(&self, table_name: &TableName) -> Result<u64, Self::Error>;
+
+    /// The LSN of the consistent snapshot the table copy streams read from,
+    /// if it is known. Changes committed after it are not in the copied rows
+    /// and must be streamed by cdc, changes committed before it already are.
+    fn get_snapshot_lsn(&self) -> Option<PgLsn>;
+
+    /// The position of the replication slot the cdc stream reads from when
+    /// the source was created, if it has a slot
+    fn get_slot_position(&self) -> Option<SlotPosition>;
+
     async fn commit_transaction(&self) -> Result<(), Self::Error>;
 
+    /// Starts a new transaction, which table copy streams read from until it
+    /// is committed with [`Source::commit_transaction`]. Its snapshot is newer
+    /// than the one [`Source::get_snapshot_lsn`] returns.
+    async fn begin_transaction(&self) -> Result<(), Self::Error>;
+
+    /// Whether the cdc stream of the source contains deletes
+    async fn publishes_deletes(&self) -> Result<bool, Self::Error>;
+
+    /// The source's current WAL position, which cdc events lag behind
+    async fn current_wal_lsn(&self) -> Result<PgLsn, Self::Error>;
+
     async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<CdcStream, Self::Error>;
+
+    /// Drops the replication slot named `slot_name`, if it exists, so that
+    /// the source no longer retains WAL for it. Fails if the slot is in use,
+    /// like by a replicator which is still running.
+    async fn drop_slot(&self, slot_name: &str) -> Result<(), Self::Error>;
 }
@@ -1,34 +1,75 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    io,
+    path::{Path, PathBuf},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
     time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
-use futures::{ready, Stream};
+use bytes::Bytes;
+use futures::{future::BoxFuture, ready, FutureExt, Stream, StreamExt};
 use pin_project_lite::pin_project;
-use postgres_replication::LogicalReplicationStream;
+use postgres_replication::{
+    protocol::ReplicationMessage, LogicalReplicationStream, ReplicationStream,
+};
 use thiserror::Error;
+use tokio::sync::mpsc;
 use tokio_postgres::{types::PgLsn, CopyOutStream};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
-    clients::postgres::{ReplicationClient, ReplicationClientError},
+    clients::postgres::{
+        ConnectionOptions, ConnectivityReport, CursorStream, OutputPlugin, ReplicationClient,
+        ReplicationClientError, ResolvedType, SlotOptions, TypeResolver,
+    },
     conversions::{
-        cdc_event::{CdcEvent, CdcEventConversionError, CdcEventConverter},
+        cdc_event::{
+            CdcEvent, CdcEventConversionError, CdcEventConverter, ColumnCountMismatchPolicy,
+            NoReplicaIdentityPolicy, NoReplicaIdentityTables, RelationTypes,
+            ReusedRelationIdPolicy, ReusedRelationIds, TransactionInfo, TupleColumnMapper,
+        },
+        streamed_transactions::{StreamedTransactions, TransactionMessage},
         table_row::{TableRow, TableRowConversionError, TableRowConverter},
+        wal2json::Wal2JsonConverter,
     },
-    table::{ColumnSchema, TableId, TableName, TableSchema},
+    table::{ColumnSchema, TableId, TableInfo, TableName, TableSchema},
 };
 
-use super::{Source, SourceError};
+use super::{replay::WalCapture, SlotPosition, Source, SourceError};
 
 pub enum TableNamesFrom {
     Vec(Vec<TableName>),
     Publication(String),
 }
 
+/// What is done with a table which has more columns than the maximum set with
+/// [`PostgresSource::limit_column_count`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnLimitPolicy {
+    /// Fail with [`PostgresSourceError::TooManyColumns`]
+    #[default]
+    Error,
+    /// Log a warning and replicate the table anyway
+    Warn,
+}
+
+/// How [`PostgresSource`] reads the rows of the tables it copies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CopyStrategy {
+    /// A single `COPY` of each table, the fastest way to read it
+    #[default]
+    Copy,
+    /// A cursor declared in the copy's transaction, `fetch_rows` rows of
+    /// which are fetched at a time. Slower than `COPY` but for tables too
+    /// large to copy within `statement_timeout` each fetch is timed out on
+    /// its own.
+    Cursor { fetch_rows: usize },
+}
+
 #[derive(Debug, Error)]
 pub enum PostgresSourceError {
     #[error("replication client error: {0}")]
@@ -39,18 +80,47 @@ pub enum PostgresSourceError {
 
     #[error("cdc stream can only be started with a slot_name")]
     MissingSlotName,
+
+    #[error("columns of partition {0} differ from those of its parent {1}")]
+    PartitionColumnsMismatch(TableName, TableName),
+
+    #[error("table {0} has no column named {1}")]
+    MissingColumn(TableName, String),
+
+    #[error("table {0} has {1} columns, more than the maximum of {2}")]
+    TooManyColumns(TableName, usize, usize),
+
+    #[error("failed to open wal capture file: {0}")]
+    WalCapture(#[source] io::Error),
 }
 
 impl SourceError for PostgresSourceError {}
 
 pub struct PostgresSource {
     replication_client: ReplicationClient,
+    table_names: Vec<TableName>,
     table_schemas: HashMap<TableId, TableSchema>,
     slot_name: Option<String>,
     publication: Option<String>,
+    snapshot_lsn: Option<PgLsn>,
+    slot_position: Option<SlotPosition>,
+    partitions: HashMap<TableName, Vec<TableName>>,
+    partition_roots: HashMap<TableId, TableId>,
+    projected_tables: HashSet<TableName>,
+    copy_prefetch_rows: usize,
+    copy_strategy: CopyStrategy,
+    column_count_mismatch_policy: ColumnCountMismatchPolicy,
+    no_replica_identity_policy: NoReplicaIdentityPolicy,
+    reused_relation_id_policy: ReusedRelationIdPolicy,
+    tracks_commit_timestamps: bool,
+    output_plugin: OutputPlugin,
+    stream_in_progress_transactions: bool,
+    wal_capture_path: Option<PathBuf>,
+    logical_messages: bool,
 }
 
 impl PostgresSource {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         host: &str,
         port: u16,
@@ -58,25 +128,274 @@ impl PostgresSource {
         username: &str,
         password: Option<String>,
         slot_name: Option<String>,
+        slot_options: SlotOptions,
         table_names_from: TableNamesFrom,
+        connection_options: &ConnectionOptions,
     ) -> Result<PostgresSource, PostgresSourceError> {
-        let replication_client =
-            ReplicationClient::connect_no_tls(host, port, database, username, password).await?;
+        let replication_client = ReplicationClient::connect_no_tls(
+            host,
+            port,
+            database,
+            username,
+            password,
+            connection_options,
+        )
+        .await?;
+        let tracks_commit_timestamps = replication_client.tracks_commit_timestamps().await?;
+        if !tracks_commit_timestamps {
+            warn!(
+                "track_commit_timestamp is off in the source, \
+                changes will have no commit timestamps"
+            );
+        }
         replication_client.begin_readonly_transaction().await?;
+        let mut snapshot_lsn = None;
+        let mut slot_position = None;
         if let Some(ref slot_name) = slot_name {
-            replication_client.get_or_create_slot(slot_name).await?;
+            let slot_info = replication_client
+                .get_slot_with_options(slot_name, slot_options)
+                .await?;
+            slot_position = Some(SlotPosition {
+                restart_lsn: slot_info.restart_lsn,
+                confirmed_flush_lsn: slot_info.confirmed_flush_lsn,
+            });
+            // Only a newly created slot exports its snapshot to the transaction
+            // which the tables are copied in. An existing slot's position is
+            // older than the snapshot of the transaction.
+            if slot_info.created {
+                snapshot_lsn = Some(slot_info.confirmed_flush_lsn);
+            }
         }
         let (table_names, publication) =
             Self::get_table_names_and_publication(&replication_client, table_names_from).await?;
         let table_schemas = replication_client.get_table_schemas(&table_names).await?;
         Ok(PostgresSource {
             replication_client,
+            table_names,
             table_schemas,
             publication,
             slot_name,
+            snapshot_lsn,
+            slot_position,
+            partitions: HashMap::new(),
+            partition_roots: HashMap::new(),
+            projected_tables: HashSet::new(),
+            copy_prefetch_rows: 0,
+            copy_strategy: CopyStrategy::default(),
+            column_count_mismatch_policy: ColumnCountMismatchPolicy::default(),
+            no_replica_identity_policy: NoReplicaIdentityPolicy::default(),
+            reused_relation_id_policy: ReusedRelationIdPolicy::default(),
+            tracks_commit_timestamps,
+            output_plugin: connection_options.output_plugin,
+            stream_in_progress_transactions: connection_options.stream_in_progress_transactions,
+            wal_capture_path: None,
+            logical_messages: false,
         })
     }
 
+    /// Sets how many rows of a table being copied are read from Postgres ahead
+    /// of the rows being written to the sink. Rows are read ahead on a
+    /// separate task so that network reads overlap with conversions and sink
+    /// writes, which helps with wide rows and high latency links. 0, the
+    /// default, reads a row only when the previous one was consumed.
+    pub fn set_copy_prefetch_rows(&mut self, copy_prefetch_rows: usize) {
+        self.copy_prefetch_rows = copy_prefetch_rows;
+    }
+
+    /// Sets how the rows of tables are read, with a single `COPY` by default
+    pub fn set_copy_strategy(&mut self, copy_strategy: CopyStrategy) {
+        self.copy_strategy = copy_strategy;
+    }
+
+    /// Sets what the cdc stream does with changes whose number of columns
+    /// differs from their table's schema, e.g. rows inserted before a column
+    /// was added. These fail to convert by default.
+    pub fn set_column_count_mismatch_policy(
+        &mut self,
+        column_count_mismatch_policy: ColumnCountMismatchPolicy,
+    ) {
+        self.column_count_mismatch_policy = column_count_mismatch_policy;
+    }
+
+    /// Appends the pgoutput messages of the cdc stream to the file at `path`,
+    /// which [`super::replay::FileReplaySource`] can replay to reproduce
+    /// conversion issues without this source. Messages are streamed undecoded
+    /// with protocol version 2 to capture them, as when in progress
+    /// transactions are streamed, which needs Postgres 14 or newer. Streams
+    /// of wal2json slots are not captured.
+    pub fn capture_wal(&mut self, path: &Path) {
+        self.wal_capture_path = Some(path.to_path_buf());
+    }
+
+    /// Streams the messages applications write to the WAL with
+    /// `pg_logical_emit_message` as [`CdcEvent::LogicalMessage`]s. Messages
+    /// are streamed undecoded with protocol version 2 to decode them, as when
+    /// in progress transactions are streamed, which needs Postgres 14 or
+    /// newer. wal2json slots don't stream them.
+    pub fn stream_logical_messages(&mut self) {
+        self.logical_messages = true;
+    }
+
+    /// Sets what the cdc stream does with updates and deletes of tables with
+    /// replica identity nothing. These fail to convert by default.
+    pub fn set_no_replica_identity_policy(
+        &mut self,
+        no_replica_identity_policy: NoReplicaIdentityPolicy,
+    ) {
+        self.no_replica_identity_policy = no_replica_identity_policy;
+    }
+
+    /// Sets what the cdc stream does when a table's id turns out to be that
+    /// of another table, like one created with the id of a dropped table.
+    /// Its changes fail to convert by default rather than being converted
+    /// with the columns of the dropped table.
+    pub fn set_reused_relation_id_policy(
+        &mut self,
+        reused_relation_id_policy: ReusedRelationIdPolicy,
+    ) {
+        self.reused_relation_id_policy = reused_relation_id_policy;
+    }
+
+    /// Replaces the schemas of tables which are partitions of a partitioned
+    /// table by the schema of their root partitioned table. Rows of all the
+    /// partitions are then copied, and their changes streamed, as rows of the
+    /// root table so that sinks see a single table.
+    ///
+    /// Partitions must have the same columns, in the same order, as their
+    /// root table. Must be called before tables are copied.
+    pub async fn route_partitions_to_parent(&mut self) -> Result<(), PostgresSourceError> {
+        let mut root_schemas: HashMap<TableName, TableSchema> = HashMap::new();
+        let table_ids: Vec<TableId> = self.table_schemas.keys().copied().collect();
+        for table_id in table_ids {
+            let Some(root_name) = self.replication_client.get_partition_root(table_id).await?
+            else {
+                continue;
+            };
+            let root_schema = match root_schemas.get(&root_name) {
+                Some(root_schema) => root_schema.clone(),
+                None => {
+                    let root_schema = self
+                        .replication_client
+                        .get_table_schema(root_name.clone())
+                        .await?;
+                    root_schemas.insert(root_name.clone(), root_schema.clone());
+                    root_schema
+                }
+            };
+            let table_schema = self
+                .table_schemas
+                .remove(&table_id)
+                .expect("missing table schema");
+            let column_names = table_schema.column_schemas.iter().map(|c| &c.name);
+            let root_column_names = root_schema.column_schemas.iter().map(|c| &c.name);
+            if !column_names.eq(root_column_names) {
+                return Err(PostgresSourceError::PartitionColumnsMismatch(
+                    table_schema.table_name,
+                    root_name,
+                ));
+            }
+            info!(
+                "routing partition {} to its parent table {root_name}",
+                table_schema.table_name
+            );
+            self.partitions
+                .entry(root_name)
+                .or_default()
+                .push(table_schema.table_name);
+            self.partition_roots.insert(table_id, root_schema.table_id);
+            self.table_schemas.insert(root_schema.table_id, root_schema);
+        }
+        Ok(())
+    }
+
+    /// Keeps only the columns named in `table_columns` of each of its tables,
+    /// in their order in the table. The copy query of such a table selects
+    /// only those columns, so excluded ones, like wide bytea columns, never
+    /// leave the source.
+    ///
+    /// Changes are still streamed with all columns, which the cdc stream
+    /// matches to the kept ones by name, so this sets the column count
+    /// mismatch policy to [`ColumnCountMismatchPolicy::MatchRelation`]. Must be
+    /// called after [`PostgresSource::route_partitions_to_parent`], if at all,
+    /// and before tables are copied.
+    pub fn project_columns(
+        &mut self,
+        table_columns: HashMap<TableName, HashSet<String>>,
+    ) -> Result<(), PostgresSourceError> {
+        for (table_name, column_names) in table_columns {
+            let Some(table_schema) = self
+                .table_schemas
+                .values_mut()
+                .find(|table_schema| table_schema.table_name == table_name)
+            else {
+                continue;
+            };
+            for column_name in &column_names {
+                let exists = table_schema
+                    .column_schemas
+                    .iter()
+                    .any(|column_schema| column_schema.name == *column_name);
+                if !exists {
+                    return Err(PostgresSourceError::MissingColumn(
+                        table_name,
+                        column_name.clone(),
+                    ));
+                }
+            }
+            table_schema
+                .column_schemas
+                .retain(|column_schema| column_names.contains(&column_schema.name));
+            self.projected_tables.insert(table_name);
+        }
+        self.column_count_mismatch_policy = ColumnCountMismatchPolicy::MatchRelation;
+        Ok(())
+    }
+
+    /// Checks that no table has more than `max_columns` columns, applying
+    /// `policy` to those which do. Very wide tables can exceed the column
+    /// limits of sinks or make converting their rows very slow, which this
+    /// reports before any row is copied. Must be called after
+    /// [`PostgresSource::project_columns`], if at all, so that only the kept
+    /// columns are counted.
+    pub fn limit_column_count(
+        &self,
+        max_columns: usize,
+        policy: ColumnLimitPolicy,
+    ) -> Result<(), PostgresSourceError> {
+        for table_schema in self.table_schemas.values() {
+            check_column_count(table_schema, max_columns, policy)?;
+        }
+        Ok(())
+    }
+
+    /// Connects to the database and checks that it can be replicated from
+    /// with the given publication and slot. Unlike [`PostgresSource::new`]
+    /// this doesn't create the slot.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn check_connectivity(
+        host: &str,
+        port: u16,
+        database: &str,
+        username: &str,
+        password: Option<String>,
+        slot_name: Option<&str>,
+        publication: Option<&str>,
+        connection_options: &ConnectionOptions,
+    ) -> Result<ConnectivityReport, PostgresSourceError> {
+        let replication_client = ReplicationClient::connect_no_tls(
+            host,
+            port,
+            database,
+            username,
+            password,
+            connection_options,
+        )
+        .await?;
+        Ok(replication_client
+            .check_connectivity(publication, slot_name)
+            .await?)
+    }
+
     fn publication(&self) -> Option<&String> {
         self.publication.as_ref()
     }
@@ -108,6 +427,31 @@ impl PostgresSource {
     }
 }
 
+fn check_column_count(
+    table_schema: &TableSchema,
+    max_columns: usize,
+    policy: ColumnLimitPolicy,
+) -> Result<(), PostgresSourceError> {
+    let column_count = table_schema.column_schemas.len();
+    if column_count <= max_columns {
+        return Ok(());
+    }
+    match policy {
+        ColumnLimitPolicy::Error => Err(PostgresSourceError::TooManyColumns(
+            table_schema.table_name.clone(),
+            column_count,
+            max_columns,
+        )),
+        ColumnLimitPolicy::Warn => {
+            warn!(
+                "table {} has {column_count} columns, more than the maximum of {max_columns}",
+                table_schema.table_name
+            );
+            Ok(())
+        }
+    }
+}
+
 #[async_trait]
 impl Source for PostgresSource {
     type Error = PostgresSourceError;
@@ -116,6 +460,24 @@ impl Source for PostgresSource {
         &self.table_schemas
     }
 
+    async fn list_tables(&self) -> Result<Vec<TableInfo>, Self::Error> {
+        Ok(self
+            .replication_client
+            .get_table_infos(&self.table_names)
+            .await?)
+    }
+
+    async fn tables_without_replica_identity(&self) -> Result<Vec<TableName>, Self::Error> {
+        let table_names = self
+            .list_tables()
+            .await?
+            .into_iter()
+            .filter(|table_info| !table_info.has_replica_identity)
+            .map(|table_info| table_info.table_name)
+            .collect();
+        Ok(table_names)
+    }
+
     async fn get_table_copy_stream(
         &self,
         table_name: &TableName,
@@ -123,11 +485,50 @@ impl Source for PostgresSource {
     ) -> Result<TableCopyStream, Self::Error> {
         info!("starting table copy stream for table {table_name}");
 
-        let stream = self
-            .replication_client
-            .get_table_copy_stream(table_name)
-            .await
-            .map_err(PostgresSourceError::ReplicationClient)?;
+        if let CopyStrategy::Cursor { fetch_rows } = self.copy_strategy {
+            let tables = match self.partitions.get(table_name) {
+                Some(partitions) => partitions.as_slice(),
+                None => std::slice::from_ref(table_name),
+            };
+            let stream = self
+                .replication_client
+                .get_cursor_stream(tables, column_schemas, fetch_rows)
+                .await?;
+            let stream = if self.copy_prefetch_rows > 0 {
+                CopyRowStream::prefetched(stream, self.copy_prefetch_rows)
+            } else {
+                CopyRowStream::Cursor(stream)
+            };
+            return Ok(TableCopyStream {
+                stream,
+                column_schemas: column_schemas.to_vec(),
+            });
+        }
+
+        let stream = match self.partitions.get(table_name) {
+            Some(partitions) => {
+                self.replication_client
+                    .get_partitions_copy_stream(partitions, column_schemas)
+                    .await
+            }
+            None if self.projected_tables.contains(table_name) => {
+                self.replication_client
+                    .get_table_columns_copy_stream(table_name, column_schemas)
+                    .await
+            }
+            None => {
+                self.replication_client
+                    .get_table_copy_stream(table_name)
+                    .await
+            }
+        }
+        .map_err(PostgresSourceError::ReplicationClient)?;
+
+        let stream = if self.copy_prefetch_rows > 0 {
+            CopyRowStream::prefetched(stream, self.copy_prefetch_rows)
+        } else {
+            CopyRowStream::Direct(Box::pin(stream))
+        };
 
         Ok(TableCopyStream {
             stream,
@@ -135,6 +536,25 @@ impl Source for PostgresSource {
         })
     }
 
+    async fn get_row_count(&self, table_name: &TableName) -> Result<u64, Self::Error> {
+        let Some(partitions) = self.partitions.get(table_name) else {
+            return Ok(self.replication_client.get_row_count(table_name).await?);
+        };
+        let mut row_count = 0;
+        for partition in partitions {
+            row_count += self.replication_client.get_row_count(partition).await?;
+        }
+        Ok(row_count)
+    }
+
+    fn get_snapshot_lsn(&self) -> Option<PgLsn> {
+        self.snapshot_lsn
+    }
+
+    fn get_slot_position(&self) -> Option<SlotPosition> {
+        self.slot_position
+    }
+
     async fn commit_transaction(&self) -> Result<(), Self::Error> {
         self.replication_client
             .commit_txn()
@@ -143,29 +563,135 @@ impl Source for PostgresSource {
         Ok(())
     }
 
+    async fn begin_transaction(&self) -> Result<(), Self::Error> {
+        self.replication_client
+            .begin_readonly_transaction()
+            .await
+            .map_err(PostgresSourceError::ReplicationClient)?;
+        Ok(())
+    }
+
+    async fn publishes_deletes(&self) -> Result<bool, Self::Error> {
+        let Some(publication) = &self.publication else {
+            return Ok(false);
+        };
+        let publishes_deletes = self
+            .replication_client
+            .publication_publishes_deletes(publication)
+            .await?;
+        Ok(publishes_deletes)
+    }
+
+    async fn current_wal_lsn(&self) -> Result<PgLsn, Self::Error> {
+        let current_wal_lsn = self.replication_client.get_current_wal_lsn().await?;
+        Ok(current_wal_lsn)
+    }
+
     async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<CdcStream, Self::Error> {
         info!("starting cdc stream at lsn {start_lsn}");
-        let publication = self
-            .publication()
-            .ok_or(PostgresSourceError::MissingPublication)?;
         let slot_name = self
             .slot_name()
             .ok_or(PostgresSourceError::MissingSlotName)?;
-        let stream = self
-            .replication_client
-            .get_logical_replication_stream(publication, slot_name, start_lsn)
-            .await
-            .map_err(PostgresSourceError::ReplicationClient)?;
+        // wal2json sends no relation messages whose types need resolving
+        let type_resolver: Option<Arc<dyn TypeResolver>> = match self.output_plugin {
+            OutputPlugin::Pgoutput => {
+                Some(Arc::new(self.replication_client.type_resolver().await?))
+            }
+            OutputPlugin::Wal2Json => None,
+        };
+        let stream = match self.output_plugin {
+            OutputPlugin::Pgoutput
+                if self.stream_in_progress_transactions
+                    || self.wal_capture_path.is_some()
+                    || self.logical_messages =>
+            {
+                let publication = self
+                    .publication()
+                    .ok_or(PostgresSourceError::MissingPublication)?;
+                let stream = self
+                    .replication_client
+                    .get_streaming_logical_replication_stream(
+                        publication,
+                        slot_name,
+                        start_lsn,
+                        self.logical_messages,
+                    )
+                    .await
+                    .map_err(PostgresSourceError::ReplicationClient)?;
+                ChangeStream::StreamingPgoutput {
+                    stream,
+                    transactions: StreamedTransactions::new(),
+                }
+            }
+            OutputPlugin::Pgoutput => {
+                let publication = self
+                    .publication()
+                    .ok_or(PostgresSourceError::MissingPublication)?;
+                let stream = self
+                    .replication_client
+                    .get_logical_replication_stream(publication, slot_name, start_lsn)
+                    .await
+                    .map_err(PostgresSourceError::ReplicationClient)?;
+                ChangeStream::Pgoutput { stream }
+            }
+            OutputPlugin::Wal2Json => {
+                let mut table_ids: HashMap<TableName, TableId> = self
+                    .table_schemas
+                    .values()
+                    .map(|table_schema| (table_schema.table_name.clone(), table_schema.table_id))
+                    .collect();
+                for (root_name, partition_names) in &self.partitions {
+                    let root_id = table_ids[root_name];
+                    for partition_name in partition_names {
+                        table_ids.insert(partition_name.clone(), root_id);
+                    }
+                }
+                let mut table_names: Vec<TableName> = table_ids.keys().cloned().collect();
+                table_names.sort_by_key(|table_name| table_name.to_string());
+                let stream = self
+                    .replication_client
+                    .get_wal2json_replication_stream(slot_name, start_lsn, &table_names)
+                    .await
+                    .map_err(PostgresSourceError::ReplicationClient)?;
+                ChangeStream::Wal2Json { stream, table_ids }
+            }
+        };
 
-        const TIME_SEC_CONVERSION: u64 = 946_684_800;
-        let postgres_epoch = UNIX_EPOCH + Duration::from_secs(TIME_SEC_CONVERSION);
+        let wal_capture = match &self.wal_capture_path {
+            Some(_) if self.output_plugin == OutputPlugin::Wal2Json => {
+                warn!("streams of wal2json slots are not captured");
+                None
+            }
+            Some(path) => Some(WalCapture::open(path).map_err(PostgresSourceError::WalCapture)?),
+            None => None,
+        };
 
         Ok(CdcStream {
             stream,
             table_schemas: self.table_schemas.clone(),
-            postgres_epoch,
+            partition_roots: self.partition_roots.clone(),
+            postgres_epoch: postgres_epoch(),
+            transaction_info: None,
+            pending_messages: VecDeque::new(),
+            tuple_columns: TupleColumnMapper::new(self.column_count_mismatch_policy),
+            no_replica_identity_tables: NoReplicaIdentityTables::new(
+                self.no_replica_identity_policy,
+            ),
+            reused_relation_ids: ReusedRelationIds::new(self.reused_relation_id_policy),
+            relation_types: RelationTypes::default(),
+            type_resolver,
+            resolving_types: None,
+            tracks_commit_timestamps: self.tracks_commit_timestamps,
+            wal_capture,
         })
     }
+
+    async fn drop_slot(&self, slot_name: &str) -> Result<(), Self::Error> {
+        if self.replication_client.drop_slot(slot_name).await? {
+            info!("dropped slot {slot_name}");
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -173,29 +699,109 @@ pub enum TableCopyStreamError {
     #[error("tokio_postgres error: {0}")]
     TokioPostgresError(#[from] tokio_postgres::Error),
 
-    #[error("conversion error: {0}")]
-    ConversionError(TableRowConversionError),
+    #[error("conversion error: {source}")]
+    ConversionError {
+        row: Bytes,
+        #[source]
+        source: TableRowConversionError,
+    },
+}
+
+/// Rows of a table copy, either read from Postgres when polled or read ahead
+/// on a separate task, or rows given as they are
+enum CopyRowStream {
+    Direct(Pin<Box<CopyOutStream>>),
+    Cursor(CursorStream),
+    Prefetched(mpsc::Receiver<Result<Bytes, tokio_postgres::Error>>),
+    Rows(VecDeque<TableRow>),
+}
+
+impl CopyRowStream {
+    /// Reads up to `prefetch_rows` rows of `stream` ahead of its consumer.
+    /// The task reading them stops once the returned stream is dropped.
+    fn prefetched(
+        stream: impl Stream<Item = Result<Bytes, tokio_postgres::Error>> + Send + 'static,
+        prefetch_rows: usize,
+    ) -> CopyRowStream {
+        let (sender, receiver) = mpsc::channel(prefetch_rows);
+        tokio::spawn(async move {
+            let mut stream = Box::pin(stream);
+            while let Some(row) = stream.next().await {
+                if sender.send(row).await.is_err() {
+                    break;
+                }
+            }
+        });
+        CopyRowStream::Prefetched(receiver)
+    }
 }
 
 pin_project! {
     #[must_use = "streams do nothing unless polled"]
     pub struct TableCopyStream {
-        #[pin]
-        stream: CopyOutStream,
+        stream: CopyRowStream,
         column_schemas: Vec<ColumnSchema>,
     }
 }
 
+impl TableCopyStream {
+    /// A stream without rows, for sources which have none to copy
+    pub(crate) fn empty(column_schemas: &[ColumnSchema]) -> TableCopyStream {
+        let (_, receiver) = mpsc::channel(1);
+        TableCopyStream {
+            stream: CopyRowStream::Prefetched(receiver),
+            column_schemas: column_schemas.to_vec(),
+        }
+    }
+
+    /// A stream of `rows`, which are already converted, for sources which
+    /// don't read them from Postgres like those of tests
+    pub fn from_table_rows(
+        rows: Vec<TableRow>,
+        column_schemas: &[ColumnSchema],
+    ) -> TableCopyStream {
+        TableCopyStream {
+            stream: CopyRowStream::Rows(rows.into()),
+            column_schemas: column_schemas.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl TableCopyStream {
+    /// A stream of `rows` in the COPY text format, for tests of pipelines
+    /// copying tables
+    pub(crate) fn from_rows(rows: &[&str], column_schemas: &[ColumnSchema]) -> TableCopyStream {
+        let (sender, receiver) = mpsc::channel(rows.len().max(1));
+        for row in rows {
+            let row = Bytes::copy_from_slice(row.as_bytes());
+            sender
+                .try_send(Ok(row))
+                .expect("channel has room for every row");
+        }
+        TableCopyStream {
+            stream: CopyRowStream::Prefetched(receiver),
+            column_schemas: column_schemas.to_vec(),
+        }
+    }
+}
+
 impl Stream for TableCopyStream {
     type Item = Result<TableRow, TableCopyStreamError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.project();
-        match ready!(this.stream.poll_next(cx)) {
+        let row = match this.stream {
+            CopyRowStream::Direct(stream) => ready!(stream.as_mut().poll_next(cx)),
+            CopyRowStream::Cursor(stream) => ready!(stream.poll_next_unpin(cx)),
+            CopyRowStream::Prefetched(receiver) => ready!(receiver.poll_recv(cx)),
+            CopyRowStream::Rows(rows) => return Poll::Ready(rows.pop_front().map(Ok)),
+        };
+        match row {
             Some(Ok(row)) => match TableRowConverter::try_from(&row, this.column_schemas) {
                 Ok(row) => Poll::Ready(Some(Ok(row))),
-                Err(e) => {
-                    let e = TableCopyStreamError::ConversionError(e);
+                Err(source) => {
+                    let e = TableCopyStreamError::ConversionError { row, source };
                     Poll::Ready(Some(Err(e)))
                 }
             },
@@ -212,18 +818,75 @@ pub enum CdcStreamError {
 
     #[error("cdc event conversion error: {0}")]
     CdcEventConversion(#[from] CdcEventConversionError),
+
+    #[error("failed to capture wal: {0}")]
+    WalCapture(#[from] io::Error),
+
+    #[error("failed to resolve a type: {0}")]
+    TypeResolution(#[from] ReplicationClientError),
+}
+
+pin_project! {
+    /// Replication messages of a slot, in the format of its output plugin
+    #[project = ChangeStreamProj]
+    enum ChangeStream {
+        Pgoutput {
+            #[pin]
+            stream: LogicalReplicationStream,
+        },
+        // pgoutput with protocol version 2, which streams large transactions
+        // before they commit
+        StreamingPgoutput {
+            #[pin]
+            stream: ReplicationStream,
+            transactions: StreamedTransactions,
+        },
+        Wal2Json {
+            #[pin]
+            stream: ReplicationStream,
+            // Ids of the tables wal2json names in changes
+            table_ids: HashMap<TableName, TableId>,
+        },
+        // Captured pgoutput messages, see `super::replay`
+        Replay {
+            messages: VecDeque<Bytes>,
+            transactions: StreamedTransactions,
+        },
+        // Events which need no conversion, see `CdcStream::from_events`
+        Events {
+            events: VecDeque<CdcEvent>,
+        },
+    }
 }
 
 pin_project! {
     #[must_use = "streams do nothing unless polled"]
     pub struct CdcStream {
         #[pin]
-        stream: LogicalReplicationStream,
+        stream: ChangeStream,
         table_schemas: HashMap<TableId, TableSchema>,
+        partition_roots: HashMap<TableId, TableId>,
         postgres_epoch: SystemTime,
+        transaction_info: Option<TransactionInfo>,
+        // Messages of a committed streamed transaction not yet converted
+        pending_messages: VecDeque<TransactionMessage>,
+        tuple_columns: TupleColumnMapper,
+        no_replica_identity_tables: NoReplicaIdentityTables,
+        reused_relation_ids: ReusedRelationIds,
+        relation_types: RelationTypes,
+        // Resolves the types relation messages have which relation_types
+        // hasn't resolved, if the stream's source has a catalog to look them up
+        type_resolver: Option<Arc<dyn TypeResolver>>,
+        // Lookup of the unresolved types of a relation message, which is
+        // returned once they are resolved
+        resolving_types: Option<(ResolvingTypes, CdcEvent)>,
+        tracks_commit_timestamps: bool,
+        wal_capture: Option<WalCapture>,
     }
 }
 
+type ResolvingTypes = BoxFuture<'static, Result<Vec<(u32, ResolvedType)>, ReplicationClientError>>;
+
 #[derive(Debug, Error)]
 pub enum StatusUpdateError {
     #[error("system time error: {0}")]
@@ -233,16 +896,90 @@ pub enum StatusUpdateError {
     TokioPostgres(#[from] tokio_postgres::Error),
 }
 
+/// The time Postgres' timestamps count from, 2000-01-01
+fn postgres_epoch() -> SystemTime {
+    const TIME_SEC_CONVERSION: u64 = 946_684_800;
+    UNIX_EPOCH + Duration::from_secs(TIME_SEC_CONVERSION)
+}
+
 impl CdcStream {
+    /// A stream converting captured pgoutput `messages` of the tables in
+    /// `table_schemas`, see [`super::replay::FileReplaySource`]
+    pub(crate) fn replay(
+        messages: Vec<Bytes>,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> CdcStream {
+        CdcStream {
+            stream: ChangeStream::Replay {
+                messages: messages.into(),
+                transactions: StreamedTransactions::new(),
+            },
+            table_schemas,
+            partition_roots: HashMap::new(),
+            postgres_epoch: postgres_epoch(),
+            transaction_info: None,
+            pending_messages: VecDeque::new(),
+            tuple_columns: TupleColumnMapper::new(ColumnCountMismatchPolicy::default()),
+            no_replica_identity_tables: NoReplicaIdentityTables::new(
+                NoReplicaIdentityPolicy::default(),
+            ),
+            reused_relation_ids: ReusedRelationIds::default(),
+            relation_types: RelationTypes::default(),
+            type_resolver: None,
+            resolving_types: None,
+            tracks_commit_timestamps: false,
+            wal_capture: None,
+        }
+    }
+
+    /// A stream of `events`, which are already converted, for sources which
+    /// don't stream them from Postgres like those of tests. The schemas of
+    /// the tables the events change are `table_schemas`.
+    pub fn from_events(
+        events: Vec<CdcEvent>,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> CdcStream {
+        CdcStream {
+            stream: ChangeStream::Events {
+                events: events.into(),
+            },
+            table_schemas,
+            partition_roots: HashMap::new(),
+            postgres_epoch: postgres_epoch(),
+            transaction_info: None,
+            pending_messages: VecDeque::new(),
+            tuple_columns: TupleColumnMapper::new(ColumnCountMismatchPolicy::default()),
+            no_replica_identity_tables: NoReplicaIdentityTables::new(
+                NoReplicaIdentityPolicy::default(),
+            ),
+            reused_relation_ids: ReusedRelationIds::default(),
+            relation_types: RelationTypes::default(),
+            type_resolver: None,
+            resolving_types: None,
+            tracks_commit_timestamps: false,
+            wal_capture: None,
+        }
+    }
+
     pub async fn send_status_update(
         self: Pin<&mut Self>,
         lsn: PgLsn,
     ) -> Result<(), StatusUpdateError> {
         let this = self.project();
         let ts = this.postgres_epoch.elapsed()?.as_micros() as i64;
-        this.stream
-            .standby_status_update(lsn, lsn, lsn, ts, 0)
-            .await?;
+        match this.stream.project() {
+            ChangeStreamProj::Pgoutput { stream } => {
+                stream.standby_status_update(lsn, lsn, lsn, ts, 0).await?
+            }
+            ChangeStreamProj::StreamingPgoutput { stream, .. } => {
+                stream.standby_status_update(lsn, lsn, lsn, ts, 0).await?
+            }
+            ChangeStreamProj::Wal2Json { stream, .. } => {
+                stream.standby_status_update(lsn, lsn, lsn, ts, 0).await?
+            }
+            // There's no source to confirm replayed messages or events to
+            ChangeStreamProj::Replay { .. } | ChangeStreamProj::Events { .. } => {}
+        }
 
         Ok(())
     }
@@ -252,14 +989,787 @@ impl Stream for CdcStream {
     type Item = Result<CdcEvent, CdcStreamError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.project();
-        match ready!(this.stream.poll_next(cx)) {
-            Some(Ok(msg)) => match CdcEventConverter::try_from(msg, this.table_schemas) {
-                Ok(row) => Poll::Ready(Some(Ok(row))),
-                Err(e) => Poll::Ready(Some(Err(e.into()))),
+        let mut this = self.project();
+        let converted = loop {
+            if let Some((resolving, _)) = this.resolving_types {
+                let resolved = ready!(resolving.as_mut().poll(cx));
+                let (_, relation) = this.resolving_types.take().expect("types are resolving");
+                match resolved {
+                    Ok(resolved) => {
+                        for (type_oid, resolved) in resolved {
+                            this.relation_types.type_resolved(type_oid, resolved);
+                        }
+                        break Ok(relation);
+                    }
+                    Err(e) => return Poll::Ready(Some(Err(e.into()))),
+                }
+            }
+            let converted = if let Some(message) = this.pending_messages.pop_front() {
+                match message {
+                    TransactionMessage::Begin(begin_body) => Ok(CdcEvent::Begin(begin_body)),
+                    TransactionMessage::Message(message) => CdcEventConverter::try_from_message(
+                        message,
+                        this.table_schemas,
+                        this.partition_roots,
+                        *this.transaction_info,
+                        this.tuple_columns,
+                        this.no_replica_identity_tables,
+                    ),
+                    TransactionMessage::Sequence {
+                        schema,
+                        name,
+                        last_value,
+                        log_cnt,
+                        is_called,
+                    } => Ok(CdcEvent::Sequence {
+                        schema,
+                        name,
+                        last_value,
+                        log_cnt,
+                        is_called,
+                    }),
+                    TransactionMessage::LogicalMessage {
+                        transactional,
+                        prefix,
+                        content,
+                    } => Ok(CdcEvent::LogicalMessage {
+                        transactional,
+                        prefix,
+                        content,
+                    }),
+                    TransactionMessage::Commit(commit_body) => {
+                        let commit_timestamp =
+                            this.transaction_info.and_then(|info| info.commit_timestamp);
+                        Ok(CdcEvent::Commit(commit_body, commit_timestamp))
+                    }
+                }
+            } else {
+                match this.stream.as_mut().project() {
+                    ChangeStreamProj::Pgoutput { stream } => match ready!(stream.poll_next(cx)) {
+                        Some(Ok(msg)) => CdcEventConverter::try_from(
+                            msg,
+                            this.table_schemas,
+                            this.partition_roots,
+                            *this.transaction_info,
+                            this.tuple_columns,
+                            this.no_replica_identity_tables,
+                        ),
+                        Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                        None => return Poll::Ready(None),
+                    },
+                    ChangeStreamProj::StreamingPgoutput {
+                        stream,
+                        transactions,
+                    } => match ready!(stream.poll_next(cx)) {
+                        Some(Ok(ReplicationMessage::XLogData(xlog_data))) => {
+                            if let Some(wal_capture) = this.wal_capture {
+                                if let Err(e) = wal_capture.capture(xlog_data.data()) {
+                                    return Poll::Ready(Some(Err(e.into())));
+                                }
+                            }
+                            // Messages of a streamed transaction are buffered
+                            // until it commits and are converted from
+                            // pending_messages
+                            match transactions.receive(xlog_data.data()) {
+                                Ok(messages) => {
+                                    this.pending_messages.extend(messages);
+                                    continue;
+                                }
+                                Err(e) => Err(e),
+                            }
+                        }
+                        Some(Ok(ReplicationMessage::PrimaryKeepAlive(keep_alive))) => {
+                            Ok(CdcEvent::KeepAliveRequested {
+                                reply: keep_alive.reply() == 1,
+                            })
+                        }
+                        Some(Ok(_)) => Err(CdcEventConversionError::UnknownReplicationMessage),
+                        Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                        None => return Poll::Ready(None),
+                    },
+                    ChangeStreamProj::Replay {
+                        messages,
+                        transactions,
+                    } => match messages.pop_front() {
+                        Some(message) => match transactions.receive(&message) {
+                            Ok(messages) => {
+                                this.pending_messages.extend(messages);
+                                continue;
+                            }
+                            Err(e) => Err(e),
+                        },
+                        None => return Poll::Ready(None),
+                    },
+                    ChangeStreamProj::Events { events } => match events.pop_front() {
+                        Some(event) => Ok(event),
+                        None => return Poll::Ready(None),
+                    },
+                    ChangeStreamProj::Wal2Json { stream, table_ids } => {
+                        match ready!(stream.poll_next(cx)) {
+                            Some(Ok(msg)) => Wal2JsonConverter::try_from(
+                                msg,
+                                table_ids,
+                                this.table_schemas,
+                                *this.transaction_info,
+                            ),
+                            Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                            None => return Poll::Ready(None),
+                        }
+                    }
+                }
+            };
+            // The relation is returned once the types of its columns which
+            // aren't known yet are resolved
+            if let (Ok(CdcEvent::Relation(relation_body)), Some(type_resolver)) =
+                (&converted, this.type_resolver.as_ref())
+            {
+                let type_oids = this.relation_types.unresolved_types(relation_body);
+                if !type_oids.is_empty() {
+                    let type_resolver = type_resolver.clone();
+                    let resolving = async move {
+                        let mut resolved = Vec::with_capacity(type_oids.len());
+                        for type_oid in type_oids {
+                            resolved.push((type_oid, type_resolver.resolve_type(type_oid).await?));
+                        }
+                        Ok::<_, ReplicationClientError>(resolved)
+                    }
+                    .boxed();
+                    let relation = converted.expect("converted is a relation");
+                    *this.resolving_types = Some((resolving, relation));
+                    continue;
+                }
+            }
+            break converted;
+        };
+        match converted {
+            Ok(event) => {
+                match &event {
+                    CdcEvent::Begin(begin_body) => {
+                        *this.transaction_info = Some(TransactionInfo::new(
+                            begin_body.xid(),
+                            begin_body.timestamp(),
+                            *this.tracks_commit_timestamps,
+                        ))
+                    }
+                    CdcEvent::Commit(..) => *this.transaction_info = None,
+                    CdcEvent::Type(type_body) => {
+                        if let Err(e) = this.relation_types.type_received(type_body) {
+                            return Poll::Ready(Some(Err(e.into())));
+                        }
+                    }
+                    CdcEvent::Relation(relation_body) => {
+                        let table_id = CdcEventConverter::root_table_id(
+                            relation_body.rel_id(),
+                            this.partition_roots,
+                        );
+                        // Partitions have their own names, their root table's
+                        // name is in the schemas
+                        if relation_body.rel_id() == table_id {
+                            if let Err(e) = this.reused_relation_ids.relation_received(
+                                table_id,
+                                relation_body,
+                                this.table_schemas,
+                            ) {
+                                return Poll::Ready(Some(Err(e.into())));
+                            }
+                        }
+                        if let Err(e) = this.relation_types.relation_received(
+                            table_id,
+                            relation_body,
+                            this.table_schemas,
+                        ) {
+                            return Poll::Ready(Some(Err(e.into())));
+                        }
+                        this.no_replica_identity_tables
+                            .relation_received(table_id, relation_body);
+                        if let Err(e) = this
+                            .tuple_columns
+                            .relation_received(table_id, relation_body)
+                        {
+                            return Poll::Ready(Some(Err(e.into())));
+                        }
+                    }
+                    _ => {}
+                }
+                Poll::Ready(Some(Ok(event)))
+            }
+            Err(e) => Poll::Ready(Some(Err(e.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use async_trait::async_trait;
+    use bytes::{BufMut, Bytes, BytesMut};
+    use futures::{StreamExt, TryStreamExt};
+    use tokio_postgres::types::{Kind, Type};
+
+    use crate::{
+        clients::postgres::{copy_text_line, ReplicationClientError, ResolvedType, TypeResolver},
+        conversions::{
+            cdc_event::{CdcEvent, TransactionInfo},
+            table_row::TableRow,
+            Cell,
+        },
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::{
+        check_column_count, CdcStream, ColumnLimitPolicy, CopyRowStream, PostgresSourceError,
+        TableCopyStream,
+    };
+
+    fn table_schema(column_count: usize) -> TableSchema {
+        let column_schemas = (0..column_count)
+            .map(|i| ColumnSchema {
+                name: format!("column_{i}"),
+                typ: Type::INT4,
+                modifier: -1,
+                nullable: true,
+                primary: i == 0,
+                identity: false,
+                domain: None,
+            })
+            .collect();
+        TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "wide".to_string(),
             },
-            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
-            None => Poll::Ready(None),
+            table_id: 1,
+            column_schemas,
+        }
+    }
+
+    #[test]
+    fn table_exceeding_the_column_limit_is_rejected() {
+        let result = check_column_count(&table_schema(101), 100, ColumnLimitPolicy::Error);
+
+        assert!(matches!(
+            result,
+            Err(PostgresSourceError::TooManyColumns(table_name, 101, 100))
+                if table_name.name == "wide"
+        ));
+    }
+
+    #[test]
+    fn table_within_the_column_limit_is_accepted() {
+        let result = check_column_count(&table_schema(100), 100, ColumnLimitPolicy::Error);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn table_exceeding_the_column_limit_is_accepted_with_the_warn_policy() {
+        let result = check_column_count(&table_schema(101), 100, ColumnLimitPolicy::Warn);
+
+        assert!(result.is_ok());
+    }
+
+    fn column_schema(name: &str, typ: Type) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            typ,
+            modifier: -1,
+            nullable: true,
+            primary: false,
+            identity: false,
+            domain: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn cursor_copy_converts_rows_like_copy() {
+        let column_schemas = vec![
+            column_schema("id", Type::INT4),
+            column_schema("title", Type::TEXT),
+            column_schema("payload", Type::TEXT),
+        ];
+        // Each row as COPY sends it and as a cursor fetches it
+        let mut copy_rows = vec![];
+        let mut fetched_rows = vec![];
+        for i in 0..2500 {
+            let (title, copied_title) = match i % 4 {
+                0 => ("tab\there", r"tab\there"),
+                1 => ("two\nlines", r"two\nlines"),
+                2 => (r"C:\temp", r"C:\\temp"),
+                _ => ("plain", "plain"),
+            };
+            let payload = (i % 7 != 0).then(|| (i * 2).to_string());
+            let copied_payload = payload.clone().unwrap_or_else(|| r"\N".to_string());
+            copy_rows.push(format!("{i}\t{copied_title} {i}\t{copied_payload}\n"));
+            let id = i.to_string();
+            let title = format!("{title} {i}");
+            fetched_rows.push(copy_text_line(
+                [Some(id.as_str()), Some(title.as_str()), payload.as_deref()].into_iter(),
+            ));
+        }
+        let copy_rows: Vec<&str> = copy_rows.iter().map(String::as_str).collect();
+        let copy_stream = TableCopyStream::from_rows(&copy_rows, &column_schemas);
+        let cursor_stream = TableCopyStream {
+            stream: CopyRowStream::Cursor(
+                futures::stream::iter(fetched_rows.into_iter().map(Ok)).boxed(),
+            ),
+            column_schemas: column_schemas.clone(),
+        };
+
+        let copied: Vec<TableRow> = copy_stream.try_collect().await.unwrap();
+        let fetched: Vec<TableRow> = cursor_stream.try_collect().await.unwrap();
+
+        assert_eq!(copied.len(), 2500);
+        assert_eq!(fetched, copied);
+    }
+
+    #[tokio::test]
+    async fn prefetched_copy_reads_up_to_the_prefetch_rows_ahead() {
+        let column_schemas = vec![column_schema("id", Type::INT4)];
+        let rows_read = Arc::new(AtomicUsize::new(0));
+        let rows = {
+            let rows_read = rows_read.clone();
+            futures::stream::iter(0..10).map(move |i| {
+                rows_read.fetch_add(1, Ordering::SeqCst);
+                Ok(Bytes::from(format!("{i}\n")))
+            })
+        };
+        let stream = TableCopyStream {
+            stream: CopyRowStream::prefetched(rows, 3),
+            column_schemas,
+        };
+
+        // The prefetch rows fill the channel and one more waits to be sent
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while rows_read.load(Ordering::SeqCst) < 4 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("rows weren't read ahead");
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(rows_read.load(Ordering::SeqCst), 4);
+
+        let copied: Vec<TableRow> = stream.try_collect().await.unwrap();
+        let ids: Vec<Cell> = copied
+            .into_iter()
+            .map(|row| row.values.into_iter().next().unwrap())
+            .collect();
+        assert_eq!(ids, (0..10).map(Cell::I32).collect::<Vec<_>>());
+    }
+
+    const TABLE_ID: u32 = 16385;
+    const MOOD_OID: u32 = 16400;
+
+    fn begin(xid: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'B');
+        buf.put_u64(0x108);
+        buf.put_i64(1_000_000);
+        buf.put_u32(xid);
+        buf.freeze()
+    }
+
+    fn commit() -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'C');
+        buf.put_u8(0);
+        buf.put_u64(0x100);
+        buf.put_u64(0x108);
+        buf.put_i64(1_000_000);
+        buf.freeze()
+    }
+
+    /// A Type message for the enum `public.mood`
+    fn mood_type() -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'Y');
+        buf.put_u32(MOOD_OID);
+        buf.put_slice(b"public\0");
+        buf.put_slice(b"mood\0");
+        buf.freeze()
+    }
+
+    /// A Relation message for a table with an int4 key column `id` and a
+    /// column `mood` of the type with oid `mood_oid`
+    fn relation(mood_oid: u32) -> Bytes {
+        relation_of(TABLE_ID, "people", mood_oid)
+    }
+
+    /// A Relation message for table `table_id` named `name` with the columns
+    /// of [`relation`]
+    fn relation_of(table_id: u32, name: &str, mood_oid: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'R');
+        buf.put_u32(table_id);
+        buf.put_slice(b"public\0");
+        buf.put_slice(name.as_bytes());
+        buf.put_u8(0);
+        buf.put_u8(b'd');
+        buf.put_u16(2);
+        buf.put_u8(1);
+        buf.put_slice(b"id\0");
+        buf.put_u32(23);
+        buf.put_i32(-1);
+        buf.put_u8(0);
+        buf.put_slice(b"mood\0");
+        buf.put_u32(mood_oid);
+        buf.put_i32(-1);
+        buf.freeze()
+    }
+
+    fn insert(id: &str, mood: &str) -> Bytes {
+        insert_into(TABLE_ID, id, mood)
+    }
+
+    fn insert_into(table_id: u32, id: &str, mood: &str) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'I');
+        buf.put_u32(table_id);
+        buf.put_u8(b'N');
+        buf.put_u16(2);
+        for value in [id, mood] {
+            buf.put_u8(b't');
+            buf.put_u32(value.len() as u32);
+            buf.put_slice(value.as_bytes());
+        }
+        buf.freeze()
+    }
+
+    /// Schema of the table as it was when the stream started, before `mood`
+    /// was altered to a type created afterwards
+    fn people_schemas() -> HashMap<u32, TableSchema> {
+        let mut id = column_schema("id", Type::INT4);
+        id.primary = true;
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "people".to_string(),
+            },
+            table_id: TABLE_ID,
+            column_schemas: vec![id, column_schema("mood", Type::INT4)],
+        };
+        HashMap::from([(TABLE_ID, table_schema)])
+    }
+
+    /// Resolves every type to the enum `mood`, like the catalog would
+    #[derive(Default)]
+    struct MoodResolver {
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TypeResolver for MoodResolver {
+        async fn resolve_type(
+            &self,
+            type_oid: u32,
+        ) -> Result<ResolvedType, ReplicationClientError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            let labels = vec!["sad".to_string(), "happy".to_string()];
+            Ok(ResolvedType {
+                typ: Type::new(
+                    "mood".to_string(),
+                    type_oid,
+                    Kind::Enum(labels),
+                    "public".to_string(),
+                ),
+                domain: None,
+            })
+        }
+    }
+
+    fn inserted_rows(events: &[CdcEvent]) -> Vec<Vec<Cell>> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((_, row, _)) => Some(row.values.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn column_of_an_enum_created_mid_stream_decodes() {
+        let messages = vec![
+            begin(700),
+            mood_type(),
+            relation(MOOD_OID),
+            insert("1", "happy"),
+            commit(),
+            begin(701),
+            relation(MOOD_OID),
+            insert("2", "sad"),
+            commit(),
+        ];
+        let resolver = Arc::new(MoodResolver::default());
+        let mut stream = CdcStream::replay(messages, people_schemas());
+        stream.type_resolver = Some(resolver.clone());
+
+        let events: Vec<CdcEvent> = stream.try_collect().await.unwrap();
+
+        assert_eq!(
+            inserted_rows(&events),
+            vec![
+                vec![Cell::I32(1), Cell::String("happy".to_string())],
+                vec![Cell::I32(2), Cell::String("sad".to_string())],
+            ]
+        );
+        // The type is looked up once and the relation still reaches sinks
+        assert_eq!(resolver.lookups.load(Ordering::SeqCst), 1);
+        let relations = events
+            .iter()
+            .filter(|event| matches!(event, CdcEvent::Relation(_)))
+            .count();
+        assert_eq!(relations, 2);
+    }
+
+    #[tokio::test]
+    async fn type_messages_name_column_types_without_a_resolver() {
+        let messages = vec![begin(700), mood_type(), relation(MOOD_OID)];
+        let mut stream = Box::pin(CdcStream::replay(messages, people_schemas()));
+
+        while let Some(event) = stream.next().await {
+            event.unwrap();
+        }
+
+        let mood = &stream.table_schemas[&TABLE_ID].column_schemas[1];
+        assert_eq!(mood.typ.oid(), MOOD_OID);
+        assert_eq!(mood.typ.name(), "mood");
+        assert_eq!(mood.typ.schema(), "public");
+    }
+
+    #[tokio::test]
+    async fn changes_of_a_transaction_carry_its_xid_and_commit_timestamp() {
+        let messages = vec![
+            begin(700),
+            relation(23),
+            insert("1", "1"),
+            insert("2", "2"),
+            commit(),
+            begin(701),
+            insert("3", "3"),
+            commit(),
+        ];
+        let mut stream = CdcStream::replay(messages, people_schemas());
+        stream.tracks_commit_timestamps = true;
+
+        let events: Vec<CdcEvent> = stream.try_collect().await.unwrap();
+
+        // The begin messages' timestamp is a second after the Postgres epoch
+        let commit_timestamp = "2000-01-01T00:00:01Z".parse().ok();
+        let transaction_infos: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((_, _, transaction_info)) => Some(*transaction_info),
+                _ => None,
+            })
+            .collect();
+        let transaction_info = |xid| {
+            Some(TransactionInfo {
+                xid,
+                commit_timestamp,
+            })
+        };
+        assert_eq!(
+            transaction_infos,
+            vec![
+                transaction_info(700),
+                transaction_info(700),
+                transaction_info(701)
+            ]
+        );
+        let commit_timestamps: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Commit(_, commit_timestamp) => Some(*commit_timestamp),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(commit_timestamps, vec![commit_timestamp, commit_timestamp]);
+    }
+
+    #[tokio::test]
+    async fn inserts_into_two_partitions_are_inserts_into_their_parent() {
+        // people_2024 and people_2025 are partitions of people
+        let (people_2024, people_2025) = (16386, 16387);
+        let messages = vec![
+            begin(700),
+            relation_of(people_2024, "people_2024", 23),
+            insert_into(people_2024, "1", "1"),
+            relation_of(people_2025, "people_2025", 23),
+            insert_into(people_2025, "2", "2"),
+            commit(),
+        ];
+        let mut stream = CdcStream::replay(messages, people_schemas());
+        stream.partition_roots = HashMap::from([(people_2024, TABLE_ID), (people_2025, TABLE_ID)]);
+        let mut stream = Box::pin(stream);
+
+        let mut events = vec![];
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+
+        let inserted_table_ids: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((table_id, _, _)) => Some(*table_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(inserted_table_ids, vec![TABLE_ID, TABLE_ID]);
+        assert_eq!(
+            inserted_rows(&events),
+            vec![
+                vec![Cell::I32(1), Cell::I32(1)],
+                vec![Cell::I32(2), Cell::I32(2)],
+            ]
+        );
+        // the parent keeps its name although the partitions have their own
+        assert_eq!(stream.table_schemas[&TABLE_ID].table_name.name, "people");
+    }
+
+    #[cfg(feature = "postgres_tests")]
+    mod postgres {
+        use std::time::Duration;
+
+        use futures::StreamExt;
+        use tokio_postgres::error::SqlState;
+
+        use crate::{
+            clients::postgres::{
+                test_database::{connection_settings, sql_client, unique_name},
+                ConnectionOptions, SlotOptions,
+            },
+            conversions::{
+                cdc_event::{CdcEvent, NoReplicaIdentityPolicy},
+                Cell,
+            },
+            pipeline::sources::{
+                postgres::{PostgresSource, TableNamesFrom},
+                Source,
+            },
+        };
+
+        /// A source of the tables of `publication` with a temporary slot
+        async fn source(publication: &str) -> PostgresSource {
+            let (host, port, username, password) = connection_settings();
+            PostgresSource::new(
+                &host,
+                port,
+                "postgres",
+                &username,
+                Some(password),
+                Some(unique_name("test_slot")),
+                SlotOptions::Temporary,
+                TableNamesFrom::Publication(publication.to_string()),
+                &ConnectionOptions::default(),
+            )
+            .await
+            .expect("failed to create the source")
+        }
+
+        /// The changes `source` streams up to the end of the first transaction
+        async fn first_transaction(source: &PostgresSource) -> Vec<CdcEvent> {
+            source.commit_transaction().await.unwrap();
+            let start_lsn = source.get_snapshot_lsn().expect("slot wasn't created");
+            let mut stream = Box::pin(source.get_cdc_stream(start_lsn).await.unwrap());
+            let mut events = vec![];
+            loop {
+                let event = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                    .await
+                    .expect("no commit was streamed")
+                    .expect("the stream ended")
+                    .unwrap();
+                match event {
+                    CdcEvent::Commit(_, _) => return events,
+                    CdcEvent::Insert(_) | CdcEvent::Update { .. } | CdcEvent::Delete { .. } => {
+                        events.push(event)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn updates_and_deletes_of_a_table_with_replica_identity_nothing() {
+            let sql_client = sql_client().await;
+            let table = unique_name("test_moods");
+            let publication = unique_name("test_publication");
+            sql_client
+                .batch_execute(&format!(
+                    "create table {table} (id int primary key, mood text);
+                    alter table {table} replica identity nothing;
+                    create publication {publication} for table {table};"
+                ))
+                .await
+                .unwrap();
+
+            let mut results = vec![];
+            for (id, policy) in [
+                (1, NoReplicaIdentityPolicy::Error),
+                (2, NoReplicaIdentityPolicy::Skip),
+                (3, NoReplicaIdentityPolicy::TreatUpdatesAsInserts),
+            ] {
+                let mut source = source(&publication).await;
+                source.set_no_replica_identity_policy(policy);
+                let has_schema = source
+                    .get_table_schemas()
+                    .values()
+                    .any(|table_schema| table_schema.table_name.name == table);
+                sql_client
+                    .batch_execute(&format!("insert into {table} values ({id}, 'happy');"))
+                    .await
+                    .unwrap();
+                let update = sql_client
+                    .batch_execute(&format!("update {table} set mood = 'sad' where id = {id};"))
+                    .await;
+                let delete = sql_client
+                    .batch_execute(&format!("delete from {table} where id = {id};"))
+                    .await;
+                let changes: Vec<_> = first_transaction(&source)
+                    .await
+                    .into_iter()
+                    .map(|event| match event {
+                        CdcEvent::Insert((_, row, _)) => ("insert", row.values[0].clone()),
+                        CdcEvent::Update { row, .. } => ("update", row.values[0].clone()),
+                        CdcEvent::Delete { row, .. } => ("delete", row.values[0].clone()),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                results.push((
+                    policy,
+                    has_schema,
+                    update.map_err(|e| e.code().cloned()),
+                    delete.map_err(|e| e.code().cloned()),
+                    changes,
+                ));
+            }
+            sql_client
+                .batch_execute(&format!(
+                    "drop publication {publication}; drop table {table};"
+                ))
+                .await
+                .unwrap();
+
+            // Postgres refuses updates and deletes of a table without a
+            // replica identity in a publication which publishes them, so
+            // whatever the policy only the insert is streamed
+            let refused = Err(Some(SqlState::OBJECT_NOT_IN_PREREQUISITE_STATE));
+            for (policy, has_schema, update, delete, changes) in results {
+                let id = changes.first().map(|(_, id)| id.clone());
+                assert!(has_schema, "{policy:?}: table has no schema");
+                assert_eq!(update, refused, "{policy:?}: update wasn't refused");
+                assert_eq!(delete, refused, "{policy:?}: delete wasn't refused");
+                assert_eq!(changes.len(), 1, "{policy:?}: {changes:?}");
+                assert_eq!(changes[0].0, "insert", "{policy:?}: {changes:?}");
+                assert!(matches!(id, Some(Cell::I32(_))), "{policy:?}: {id:?}");
+            }
         }
     }
 }
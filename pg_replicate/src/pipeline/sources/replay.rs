@@ -0,0 +1,307 @@
+//! Captures the pgoutput messages a [`super::postgres::PostgresSource`]
+//! streams to a file, see [`super::postgres::PostgresSource::capture_wal`],
+//! and replays them through the same conversions with [`FileReplaySource`],
+//! so that a conversion issue in a captured stream can be reproduced without
+//! the source it was captured from.
+//!
+//! A capture file is a sequence of messages, each a big-endian u32 length
+//! followed by that many bytes of a pgoutput message.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thiserror::Error;
+use tokio_postgres::types::PgLsn;
+
+use crate::table::{ColumnSchema, TableId, TableInfo, TableName, TableSchema};
+
+use super::{
+    postgres::{CdcStream, TableCopyStream},
+    SlotPosition, Source, SourceError,
+};
+
+/// Appends the messages of a cdc stream to a capture file
+pub struct WalCapture {
+    writer: BufWriter<File>,
+}
+
+impl WalCapture {
+    /// Opens the capture file at `path`, creating it if it doesn't exist.
+    /// Messages are appended to the ones captured earlier.
+    pub fn open(path: &Path) -> io::Result<WalCapture> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(WalCapture {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Appends `message` and flushes it, so that the messages received before
+    /// a failure are in the file
+    pub fn capture(&mut self, message: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(message.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(message)?;
+        self.writer.flush()
+    }
+}
+
+/// Reads all messages of a capture file from `reader`
+pub fn read_captured_messages(mut reader: impl Read) -> io::Result<Vec<Bytes>> {
+    let mut messages = vec![];
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let mut message = vec![0u8; u32::from_be_bytes(len) as usize];
+        reader.read_exact(&mut message)?;
+        messages.push(Bytes::from(message));
+    }
+    Ok(messages)
+}
+
+#[derive(Debug, Error)]
+pub enum FileReplaySourceError {
+    #[error("failed to read capture file: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl SourceError for FileReplaySourceError {}
+
+/// A source whose cdc stream replays the messages of a capture file. Its
+/// tables have no rows to copy, so pipelines replaying a capture should only
+/// stream changes. `table_schemas` must be the schemas of the tables at the
+/// time the messages were captured.
+pub struct FileReplaySource {
+    table_schemas: HashMap<TableId, TableSchema>,
+    messages: Vec<Bytes>,
+}
+
+impl FileReplaySource {
+    pub fn new(
+        path: &Path,
+        table_schemas: HashMap<TableId, TableSchema>,
+    ) -> Result<FileReplaySource, FileReplaySourceError> {
+        let messages = read_captured_messages(File::open(path)?)?;
+        Ok(FileReplaySource {
+            table_schemas,
+            messages,
+        })
+    }
+}
+
+#[async_trait]
+impl Source for FileReplaySource {
+    type Error = FileReplaySourceError;
+
+    fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    async fn list_tables(&self) -> Result<Vec<TableInfo>, Self::Error> {
+        let table_infos = self
+            .table_schemas
+            .values()
+            .map(|table_schema| TableInfo {
+                table_name: table_schema.table_name.clone(),
+                estimated_row_count: None,
+                size_bytes: 0,
+                has_replica_identity: table_schema.has_primary_keys(),
+            })
+            .collect();
+        Ok(table_infos)
+    }
+
+    /// Changes of the captured tables were streamed, so they have one
+    async fn tables_without_replica_identity(&self) -> Result<Vec<TableName>, Self::Error> {
+        Ok(vec![])
+    }
+
+    async fn get_table_copy_stream(
+        &self,
+        _table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<TableCopyStream, Self::Error> {
+        Ok(TableCopyStream::empty(column_schemas))
+    }
+
+    async fn get_row_count(&self, _table_name: &TableName) -> Result<u64, Self::Error> {
+        Ok(0)
+    }
+
+    fn get_snapshot_lsn(&self) -> Option<PgLsn> {
+        None
+    }
+
+    fn get_slot_position(&self) -> Option<SlotPosition> {
+        None
+    }
+
+    async fn commit_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn begin_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publishes_deletes(&self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
+    async fn current_wal_lsn(&self) -> Result<PgLsn, Self::Error> {
+        Ok(PgLsn::from(0))
+    }
+
+    /// Replays all captured messages, whatever `start_lsn` is
+    async fn get_cdc_stream(&self, _start_lsn: PgLsn) -> Result<CdcStream, Self::Error> {
+        Ok(CdcStream::replay(
+            self.messages.clone(),
+            self.table_schemas.clone(),
+        ))
+    }
+
+    async fn drop_slot(&self, _slot_name: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use futures::StreamExt;
+    use tokio_postgres::types::{PgLsn, Type};
+
+    use crate::{
+        conversions::{cdc_event::CdcEvent, Cell},
+        pipeline::sources::{postgres::CdcStream, Source},
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::{FileReplaySource, WalCapture};
+
+    const TABLE_ID: u32 = 16385;
+
+    fn begin(xid: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'B');
+        buf.put_u64(0x108);
+        buf.put_i64(1_000_000);
+        buf.put_u32(xid);
+        buf.freeze()
+    }
+
+    fn commit() -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'C');
+        buf.put_u8(0);
+        buf.put_u64(0x100);
+        buf.put_u64(0x108);
+        buf.put_i64(1_000_000);
+        buf.freeze()
+    }
+
+    /// A Relation message for a table with a single int4 key column `id`
+    fn relation() -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'R');
+        buf.put_u32(TABLE_ID);
+        buf.put_slice(b"public\0");
+        buf.put_slice(b"items\0");
+        buf.put_u8(b'd');
+        buf.put_u16(1);
+        buf.put_u8(1);
+        buf.put_slice(b"id\0");
+        buf.put_u32(23);
+        buf.put_i32(-1);
+        buf.freeze()
+    }
+
+    fn insert(id: &str) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(b'I');
+        buf.put_u32(TABLE_ID);
+        buf.put_u8(b'N');
+        buf.put_u16(1);
+        buf.put_u8(b't');
+        buf.put_u32(id.len() as u32);
+        buf.put_slice(id.as_bytes());
+        buf.freeze()
+    }
+
+    fn table_schemas() -> HashMap<u32, TableSchema> {
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "items".to_string(),
+            },
+            table_id: TABLE_ID,
+            column_schemas: vec![ColumnSchema {
+                name: "id".to_string(),
+                typ: Type::INT4,
+                modifier: -1,
+                nullable: false,
+                primary: true,
+                identity: false,
+                domain: None,
+            }],
+        };
+        HashMap::from([(TABLE_ID, table_schema)])
+    }
+
+    async fn events(stream: CdcStream) -> Vec<CdcEvent> {
+        stream
+            .map(|event| event.expect("failed to convert event"))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn captured_messages_are_replayed_as_the_same_events() {
+        let messages = vec![begin(700), relation(), insert("1"), insert("2"), commit()];
+        let path = std::env::temp_dir().join(format!(
+            "pg_replicate_wal_capture_test_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let mut wal_capture = WalCapture::open(&path).expect("failed to open capture file");
+        for message in &messages {
+            wal_capture.capture(message).expect("failed to capture");
+        }
+        drop(wal_capture);
+
+        let source = FileReplaySource::new(&path, table_schemas()).expect("failed to read");
+        let replayed = events(source.get_cdc_stream(PgLsn::from(0)).await.unwrap()).await;
+        let converted = events(CdcStream::replay(messages, table_schemas())).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(format!("{replayed:?}"), format!("{converted:?}"));
+        let inserted: Vec<_> = replayed
+            .iter()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((table_id, row, _)) => Some((*table_id, row.values.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            inserted,
+            vec![
+                (TABLE_ID, vec![Cell::I32(1)]),
+                (TABLE_ID, vec![Cell::I32(2)]),
+            ]
+        );
+        assert!(matches!(replayed.first(), Some(CdcEvent::Begin(_))));
+        assert!(matches!(replayed.last(), Some(CdcEvent::Commit(..))));
+    }
+}
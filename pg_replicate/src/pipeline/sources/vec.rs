@@ -0,0 +1,346 @@
+//! A source which streams scripted table rows and cdc events from memory,
+//! for testing pipelines without a Postgres database.
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use pg_replicate::conversions::{cdc_event::CdcEvent, table_row::TableRow, Cell};
+//! use pg_replicate::pipeline::sources::vec::VecSource;
+//!
+//! let mut source = VecSource::new(HashMap::new());
+//! source.set_table_rows(1, vec![TableRow::from(vec![Cell::I32(1)])]);
+//! source.set_cdc_events(vec![CdcEvent::Insert((1, TableRow::from(vec![Cell::I32(2)]), None))]);
+//!
+//! // ...build a pipeline reading from `source` and start it...
+//! ```
+
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use tokio_postgres::types::PgLsn;
+
+use crate::{
+    conversions::{cdc_event::CdcEvent, table_row::TableRow},
+    table::{ColumnSchema, TableId, TableInfo, TableName, TableSchema},
+};
+
+use super::{
+    postgres::{CdcStream, TableCopyStream},
+    InfallibleSourceError, SlotPosition, Source,
+};
+
+/// Copies the rows set with [`VecSource::set_table_rows`] and streams the
+/// events set with [`VecSource::set_cdc_events`]. The events are streamed
+/// once, by the first cdc stream, whatever lsn it starts at, and the stream
+/// ends after them.
+pub struct VecSource {
+    table_schemas: HashMap<TableId, TableSchema>,
+    table_rows: HashMap<TableId, Vec<TableRow>>,
+    publishes_deletes: bool,
+    cdc_events: Mutex<Vec<CdcEvent>>,
+    cdc_start_lsns: Mutex<Vec<PgLsn>>,
+    snapshot_lsn: Option<PgLsn>,
+    slot_position: Option<SlotPosition>,
+}
+
+impl VecSource {
+    /// A source of the tables in `table_schemas`, which have no rows and no
+    /// changes until they are set
+    pub fn new(table_schemas: HashMap<TableId, TableSchema>) -> VecSource {
+        VecSource {
+            table_schemas,
+            table_rows: HashMap::new(),
+            publishes_deletes: false,
+            cdc_events: Mutex::new(vec![]),
+            cdc_start_lsns: Mutex::new(vec![]),
+            snapshot_lsn: None,
+            slot_position: None,
+        }
+    }
+
+    /// Sets the rows copied from the table `table_id`
+    pub fn set_table_rows(&mut self, table_id: TableId, rows: Vec<TableRow>) {
+        self.table_rows.insert(table_id, rows);
+    }
+
+    /// Sets the events of the cdc stream. [`Source::publishes_deletes`] is
+    /// true if they contain a delete.
+    pub fn set_cdc_events(&mut self, events: Vec<CdcEvent>) {
+        self.publishes_deletes = events
+            .iter()
+            .any(|event| matches!(event, CdcEvent::Delete { .. }));
+        self.cdc_events = Mutex::new(events);
+    }
+
+    /// Sets the lsn returned by [`Source::get_snapshot_lsn`]
+    pub fn set_snapshot_lsn(&mut self, snapshot_lsn: PgLsn) {
+        self.snapshot_lsn = Some(snapshot_lsn);
+    }
+
+    /// Sets the position returned by [`Source::get_slot_position`]
+    pub fn set_slot_position(&mut self, slot_position: SlotPosition) {
+        self.slot_position = Some(slot_position);
+    }
+
+    /// The lsns cdc streams were started at, in the order they were started
+    pub fn cdc_start_lsns(&self) -> Vec<PgLsn> {
+        self.cdc_start_lsns
+            .lock()
+            .expect("cdc start lsns lock poisoned")
+            .clone()
+    }
+
+    fn table_id(&self, table_name: &TableName) -> Option<TableId> {
+        self.table_schemas
+            .values()
+            .find(|table_schema| &table_schema.table_name == table_name)
+            .map(|table_schema| table_schema.table_id)
+    }
+}
+
+#[async_trait]
+impl Source for VecSource {
+    type Error = InfallibleSourceError;
+
+    fn get_table_schemas(&self) -> &HashMap<TableId, TableSchema> {
+        &self.table_schemas
+    }
+
+    async fn list_tables(&self) -> Result<Vec<TableInfo>, Self::Error> {
+        let table_infos = self
+            .table_schemas
+            .values()
+            .map(|table_schema| TableInfo {
+                table_name: table_schema.table_name.clone(),
+                estimated_row_count: self
+                    .table_rows
+                    .get(&table_schema.table_id)
+                    .map(|rows| rows.len() as i64),
+                size_bytes: 0,
+                has_replica_identity: table_schema.has_primary_keys(),
+            })
+            .collect();
+        Ok(table_infos)
+    }
+
+    async fn tables_without_replica_identity(&self) -> Result<Vec<TableName>, Self::Error> {
+        Ok(vec![])
+    }
+
+    async fn get_table_copy_stream(
+        &self,
+        table_name: &TableName,
+        column_schemas: &[ColumnSchema],
+    ) -> Result<TableCopyStream, Self::Error> {
+        let rows = self
+            .table_id(table_name)
+            .and_then(|table_id| self.table_rows.get(&table_id))
+            .cloned()
+            .unwrap_or_default();
+        Ok(TableCopyStream::from_table_rows(rows, column_schemas))
+    }
+
+    async fn get_row_count(&self, table_name: &TableName) -> Result<u64, Self::Error> {
+        let row_count = self
+            .table_id(table_name)
+            .and_then(|table_id| self.table_rows.get(&table_id))
+            .map_or(0, |rows| rows.len() as u64);
+        Ok(row_count)
+    }
+
+    fn get_snapshot_lsn(&self) -> Option<PgLsn> {
+        self.snapshot_lsn
+    }
+
+    fn get_slot_position(&self) -> Option<SlotPosition> {
+        self.slot_position
+    }
+
+    async fn commit_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn begin_transaction(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn publishes_deletes(&self) -> Result<bool, Self::Error> {
+        Ok(self.publishes_deletes)
+    }
+
+    async fn current_wal_lsn(&self) -> Result<PgLsn, Self::Error> {
+        Ok(PgLsn::from(0))
+    }
+
+    async fn get_cdc_stream(&self, start_lsn: PgLsn) -> Result<CdcStream, Self::Error> {
+        self.cdc_start_lsns
+            .lock()
+            .expect("cdc start lsns lock poisoned")
+            .push(start_lsn);
+        let events =
+            std::mem::take(&mut *self.cdc_events.lock().expect("cdc events lock poisoned"));
+        Ok(CdcStream::from_events(events, self.table_schemas.clone()))
+    }
+
+    async fn drop_slot(&self, _slot_name: &str) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    };
+
+    use tokio_postgres::types::{PgLsn, Type};
+
+    use crate::{
+        conversions::{
+            cdc_event::{BeginBody, CdcEvent, CommitBody},
+            table_row::TableRow,
+            Cell,
+        },
+        pipeline::{
+            batching::{data_pipeline::BatchDataPipeline, BatchConfig},
+            sinks::recording::RecordingSink,
+            PipelineAction, PipelineResumptionState,
+        },
+        table::{ColumnSchema, TableName, TableSchema},
+    };
+
+    use super::VecSource;
+
+    fn row(id: i32) -> TableRow {
+        TableRow::from(vec![Cell::I32(id)])
+    }
+
+    fn insert(id: i32) -> CdcEvent {
+        CdcEvent::Insert((1, row(id), None))
+    }
+
+    fn transaction(commit_lsn: u64, ids: &[i32]) -> Vec<CdcEvent> {
+        let mut events = vec![CdcEvent::Begin(BeginBody::new(commit_lsn, 0, 700))];
+        events.extend(ids.iter().map(|id| insert(*id)));
+        events.push(CdcEvent::Commit(
+            CommitBody::new(commit_lsn, commit_lsn + 8, 0),
+            None,
+        ));
+        events
+    }
+
+    fn source() -> VecSource {
+        let table_schema = TableSchema {
+            table_name: TableName {
+                schema: "public".to_string(),
+                name: "items".to_string(),
+            },
+            table_id: 1,
+            column_schemas: vec![ColumnSchema {
+                name: "id".to_string(),
+                typ: Type::INT4,
+                modifier: -1,
+                nullable: false,
+                primary: true,
+            }],
+        };
+        let mut source = VecSource::new(HashMap::from([(1, table_schema)]));
+        source.set_table_rows(1, vec![row(1), row(2)]);
+        source.set_cdc_events(transaction(0x100, &[3, 4]));
+        source
+    }
+
+    fn pipeline(
+        source: VecSource,
+        sink: RecordingSink,
+        action: PipelineAction,
+    ) -> BatchDataPipeline<VecSource, RecordingSink> {
+        let batch_config = BatchConfig::new(1000, Duration::from_millis(10));
+        BatchDataPipeline::new(source, sink, action, batch_config)
+    }
+
+    fn inserted_ids(sink: &RecordingSink) -> Vec<i32> {
+        sink.cdc_events()
+            .iter()
+            .flatten()
+            .filter_map(|event| match event {
+                CdcEvent::Insert((_, row, _)) => match row.values[0] {
+                    Cell::I32(id) => Some(id),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn table_copies_only_copies_rows_without_streaming() {
+        let mut pipeline = pipeline(
+            source(),
+            RecordingSink::new(),
+            PipelineAction::TableCopiesOnly,
+        );
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(pipeline.sink().rows_of_table(1), vec![&row(1), &row(2)]);
+        assert_eq!(pipeline.sink().copied_tables(), &[1]);
+        assert!(pipeline.sink().cdc_events().is_empty());
+        assert!(pipeline.source().cdc_start_lsns().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cdc_only_streams_after_the_sinks_last_lsn() {
+        let mut sink = RecordingSink::new();
+        sink.set_resumption_state(PipelineResumptionState {
+            copied_tables: HashSet::new(),
+            last_lsn: PgLsn::from(0x80),
+        });
+        let mut pipeline = pipeline(source(), sink, PipelineAction::CdcOnly);
+
+        pipeline.start().await.unwrap();
+
+        assert!(pipeline.sink().table_rows().is_empty());
+        assert_eq!(inserted_ids(pipeline.sink()), vec![3, 4]);
+        assert_eq!(pipeline.source().cdc_start_lsns(), vec![PgLsn::from(0x81)]);
+    }
+
+    #[tokio::test]
+    async fn both_copies_rows_then_streams_changes() {
+        let mut pipeline = pipeline(source(), RecordingSink::new(), PipelineAction::Both);
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(pipeline.sink().rows_of_table(1), vec![&row(1), &row(2)]);
+        assert_eq!(pipeline.sink().copied_tables(), &[1]);
+        assert_eq!(inserted_ids(pipeline.sink()), vec![3, 4]);
+        assert_eq!(pipeline.source().cdc_start_lsns(), vec![PgLsn::from(1)]);
+    }
+
+    #[tokio::test]
+    async fn both_hands_off_to_cdc_at_the_snapshot_lsn() {
+        let mut source = source();
+        source.set_snapshot_lsn(PgLsn::from(0x80));
+        let mut pipeline = pipeline(source, RecordingSink::new(), PipelineAction::Both);
+
+        pipeline.start().await.unwrap();
+
+        assert_eq!(pipeline.source().cdc_start_lsns(), vec![PgLsn::from(0x81)]);
+    }
+
+    #[tokio::test]
+    async fn already_copied_tables_are_not_copied_again() {
+        let mut sink = RecordingSink::new();
+        sink.set_resumption_state(PipelineResumptionState {
+            copied_tables: HashSet::from([1]),
+            last_lsn: PgLsn::from(0),
+        });
+        let mut pipeline = pipeline(source(), sink, PipelineAction::Both);
+
+        pipeline.start().await.unwrap();
+
+        assert!(pipeline.sink().table_rows().is_empty());
+        assert_eq!(inserted_ids(pipeline.sink()), vec![3, 4]);
+    }
+}
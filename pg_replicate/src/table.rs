@@ -3,7 +3,7 @@ use std::fmt::Display;
 use pg_escape::quote_identifier;
 use tokio_postgres::types::Type;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TableName {
     pub schema: String,
     pub name: String,
@@ -31,11 +31,35 @@ pub struct ColumnSchema {
     pub typ: Type,
     pub modifier: TypeModifier,
     pub nullable: bool,
+    /// True if the column is part of the table's key, which is its primary
+    /// key or, for a table without one, the unique index its replica
+    /// identity uses
     pub primary: bool,
+    /// True if the column's values default to the next value of a sequence,
+    /// like those of `serial` and identity columns, which sinks can create
+    /// as their own equivalent of an identity column
+    pub identity: bool,
+    /// Name of the domain the column was declared with, if any. `typ` is
+    /// then the domain's base type, whose format the column's values have
+    pub domain: Option<String>,
 }
 
 pub type TableId = u32;
 
+/// Information to plan the replication of a table with
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    pub table_name: TableName,
+    /// Estimate from the last vacuum or analyze, `None` if the table never had one
+    pub estimated_row_count: Option<i64>,
+    /// Size on disk, in bytes, including indexes and toast data
+    pub size_bytes: i64,
+    /// True if updates and deletes to the table can be replicated, i.e. its
+    /// replica identity is full, it is the default and the table has a primary
+    /// key or it uses a unique index
+    pub has_replica_identity: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct TableSchema {
     pub table_name: TableName,
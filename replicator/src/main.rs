@@ -1,19 +1,38 @@
 use std::{error::Error, time::Duration};
 
-use configuration::{get_configuration, BatchSettings, SinkSettings, SourceSettings};
-use pg_replicate::pipeline::{
-    batching::{data_pipeline::BatchDataPipeline, BatchConfig},
-    sinks::bigquery::BigQueryBatchSink,
-    sources::postgres::{PostgresSource, TableNamesFrom},
-    PipelineAction,
+use configuration::{
+    get_configuration, AuthMethod, BatchSettings, OutputPlugin, SinkSettings, SlotOptions,
+    SourceSettings,
 };
-use tracing::{error, info};
+use opentelemetry_sdk::trace::Tracer;
+use pg_replicate::{
+    clients::postgres::{self, ConnectionOptions},
+    pipeline::{
+        batching::{data_pipeline::BatchDataPipeline, BatchConfig, TableBatchConfig},
+        dead_letter::{DeadLetterPolicy, DeadLetterThreshold, LoggingDeadLetterSink},
+        sinks::{bigquery::BigQueryBatchSink, serialization::SerializationPool, SinkCredentials},
+        sources::postgres::{PostgresSource, TableNamesFrom},
+        PipelineAction, TableCopySnapshot, TableOperations,
+    },
+};
+use status::{receive_resync_requests, record_table_statuses};
+use telemetry::{init_tracer, replicator_span};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::{self, Sender},
+};
+use tracing::{error, info, warn, Instrument};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use wal_retention::{monitor_wal_retention, SourceDatabase};
 
 mod configuration;
+mod status;
+mod telemetry;
+mod wal_retention;
 
 // APP_SOURCE__POSTGRES__PASSWORD and APP_SINK__BIGQUERY__PROJECT_ID environment variables must be set
-// before running because these are sensitive values which can't be configured in the config files
+// before running because these are sensitive values which can't be configured in the config files.
+// The same goes for APP_STATUS__PASSWORD if table statuses are recorded.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     if let Err(e) = main_impl().await {
@@ -23,13 +42,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn init_tracing() {
+/// Spans are also exported with `tracer` if one is given
+fn init_tracing(tracer: Option<Tracer>) {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "replicator=info".into()),
         )
         .with(tracing_subscriber::fmt::layer())
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
         .init();
 }
 
@@ -39,15 +60,58 @@ fn set_log_level() {
     }
 }
 
+/// On every SIGHUP asks the pipeline to flush its current batch and confirm
+/// its lsn, e.g. before taking a backup of the source, and re-reads the
+/// configuration to send the sink's service account key to the pipeline, so
+/// that a rotated key is picked up without a restart
+fn handle_sighup(
+    credentials_sender: Sender<SinkCredentials>,
+    flush_sender: Sender<()>,
+) -> std::io::Result<()> {
+    let mut sighup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            info!("received SIGHUP, flushing the current batch and reloading sink credentials");
+            // A flush which is still pending covers this one too
+            let _ = flush_sender.try_send(());
+            let settings = match get_configuration() {
+                Ok(settings) => settings,
+                Err(e) => {
+                    warn!("failed to reload configuration: {e}");
+                    continue;
+                }
+            };
+            let SinkSettings::BigQuery {
+                service_account_key,
+                ..
+            } = settings.sink;
+            let credentials = SinkCredentials::ServiceAccountKey(service_account_key);
+            if credentials_sender.send(credentials).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
 async fn main_impl() -> Result<(), Box<dyn Error>> {
     set_log_level();
-    init_tracing();
+    let settings = get_configuration();
+    let tracer = match settings
+        .as_ref()
+        .ok()
+        .and_then(|settings| settings.telemetry.as_ref())
+    {
+        Some(telemetry) => Some(init_tracer(&telemetry.otlp_endpoint)?),
+        None => None,
+    };
+    init_tracing(tracer);
 
     rustls::crypto::aws_lc_rs::default_provider()
         .install_default()
         .expect("failed to install default crypto provider");
 
-    let settings = get_configuration()?;
+    let settings = settings?;
 
     info!("settings: {settings:#?}");
 
@@ -58,35 +122,139 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
         username,
         password,
         slot_name,
+        slot_options,
         publication,
+        statement_timeout_ms,
+        tcp_keepalives_idle_secs,
+        connect_timeout_secs,
+        route_partitions_to_parent,
+        copy_prefetch_rows,
+        auth,
+        output_plugin,
+        stream_in_progress_transactions,
+        snapshot_per_table,
+        logical_messages,
+        application_name,
+        socks5_proxy,
+        role,
+        search_path,
     } = settings.source;
 
-    let postgres_source = PostgresSource::new(
+    let connection_options = ConnectionOptions {
+        statement_timeout: Duration::from_millis(statement_timeout_ms),
+        tcp_keepalives_idle: Duration::from_secs(tcp_keepalives_idle_secs),
+        connect_timeout: connect_timeout_secs.map(Duration::from_secs),
+        auth: match auth {
+            AuthMethod::Any => postgres::AuthMethod::Any,
+            AuthMethod::ScramSha256ChannelBinding => {
+                postgres::AuthMethod::ScramSha256ChannelBinding
+            }
+        },
+        output_plugin: match output_plugin {
+            OutputPlugin::Pgoutput => postgres::OutputPlugin::Pgoutput,
+            OutputPlugin::Wal2Json => postgres::OutputPlugin::Wal2Json,
+        },
+        stream_in_progress_transactions,
+        application_name,
+        socks5_proxy,
+        role,
+        search_path,
+    };
+
+    let slot_options = match slot_options {
+        SlotOptions::UseExisting => postgres::SlotOptions::UseExisting,
+        SlotOptions::CreateIfMissing => postgres::SlotOptions::CreateIfMissing,
+        SlotOptions::Temporary => postgres::SlotOptions::Temporary,
+    };
+
+    let wal_retention_halt = match settings.wal_retention {
+        Some(wal_retention_settings) => {
+            let source_database = SourceDatabase {
+                host: &host,
+                port,
+                name: &name,
+                username: &username,
+                password: password.as_deref(),
+                options: &connection_options,
+            };
+            // Replication doesn't depend on the monitor, so it goes on
+            // unmonitored if the monitor can't connect
+            match monitor_wal_retention(&source_database, slot_name.clone(), wal_retention_settings)
+                .await
+            {
+                Ok(halt) => Some(halt),
+                Err(e) => {
+                    warn!("failed to start monitoring wal retained for slot {slot_name}: {e}");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut postgres_source = PostgresSource::new(
         &host,
         port,
         &name,
         &username,
         password,
         Some(slot_name),
+        slot_options,
         TableNamesFrom::Publication(publication),
+        &connection_options,
     )
     .await?;
 
+    postgres_source.set_copy_prefetch_rows(copy_prefetch_rows);
+    if logical_messages {
+        postgres_source.stream_logical_messages();
+    }
+
+    if route_partitions_to_parent {
+        postgres_source.route_partitions_to_parent().await?;
+    }
+
     let SinkSettings::BigQuery {
         project_id,
         dataset_id,
         service_account_key,
     } = settings.sink;
 
-    let bigquery_sink =
+    let mut bigquery_sink =
         BigQueryBatchSink::new_with_key(project_id, dataset_id, &service_account_key).await?;
 
     let BatchSettings {
         max_size,
         max_fill_secs,
+        serialization_workers,
+        max_in_flight_batches,
+        flush_on_idle,
+        sink_write_timeout_secs,
+        sink_ready_timeout_secs,
+        adaptive,
+        table_overrides,
+        table_operations,
+        dead_letter,
     } = settings.batch;
 
-    let batch_config = BatchConfig::new(max_size, Duration::from_secs(max_fill_secs));
+    bigquery_sink.set_serialization_pool(SerializationPool::new(serialization_workers));
+
+    let mut batch_config = BatchConfig::new(max_size, Duration::from_secs(max_fill_secs));
+    batch_config.set_max_in_flight_batches(max_in_flight_batches);
+    batch_config.set_flush_on_idle(flush_on_idle);
+    if let Some(adaptive) = adaptive {
+        batch_config.set_adaptive_batch_size(
+            adaptive.min_size,
+            Duration::from_millis(adaptive.target_write_ms),
+        );
+    }
+    for (table_id, table_settings) in table_overrides {
+        let table_config = TableBatchConfig {
+            max_batch_size: table_settings.max_size,
+            max_batch_fill_time: table_settings.max_fill_secs.map(Duration::from_secs),
+        };
+        batch_config.set_table_config(table_id, table_config);
+    }
     let mut pipeline = BatchDataPipeline::new(
         postgres_source,
         bigquery_sink,
@@ -94,7 +262,74 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
         batch_config,
     );
 
-    pipeline.start().await?;
+    if let Some(sink_write_timeout_secs) = sink_write_timeout_secs {
+        pipeline.set_sink_write_timeout(Duration::from_secs(sink_write_timeout_secs));
+    }
+
+    if let Some(sink_ready_timeout_secs) = sink_ready_timeout_secs {
+        pipeline.set_sink_ready_timeout(Duration::from_secs(sink_ready_timeout_secs));
+    }
+
+    if snapshot_per_table {
+        pipeline.set_table_copy_snapshot(TableCopySnapshot::PerTable);
+    }
+
+    let table_operations = table_operations
+        .into_iter()
+        .map(|(table_id, operations)| {
+            let operations = TableOperations {
+                insert: operations.insert,
+                update: operations.update,
+                delete: operations.delete,
+                truncate: operations.truncate,
+            };
+            (table_id, operations)
+        })
+        .collect();
+    pipeline.set_table_operations(table_operations);
+
+    if let Some(dead_letter) = dead_letter {
+        pipeline.set_dead_letter_policy(DeadLetterPolicy::Skip(Box::new(LoggingDeadLetterSink)));
+        pipeline.set_dead_letter_threshold(DeadLetterThreshold::new(
+            dead_letter.max_dead_letters,
+            Duration::from_secs(dead_letter.window_secs),
+        ));
+    }
+
+    let (credentials_sender, credentials_receiver) = mpsc::channel(1);
+    pipeline.set_credentials_receiver(credentials_receiver);
+    let (flush_sender, flush_receiver) = mpsc::channel(1);
+    pipeline.set_flush_receiver(flush_receiver);
+    handle_sighup(credentials_sender, flush_sender)?;
+
+    if let Some(status_settings) = settings.status {
+        let (table_status_sender, table_status_receiver) = mpsc::channel(100);
+        pipeline.set_table_status_sender(table_status_sender);
+        record_table_statuses(&status_settings, table_status_receiver).await?;
+
+        let (resync_sender, resync_receiver) = mpsc::channel(100);
+        pipeline.set_resync_receiver(resync_receiver);
+        receive_resync_requests(&status_settings, resync_sender).await?;
+    }
+
+    let trace_parent = settings
+        .telemetry
+        .and_then(|telemetry| telemetry.trace_parent);
+    let replication = pipeline
+        .start()
+        .instrument(replicator_span(trace_parent.as_deref()));
+    let result: Result<(), Box<dyn Error>> = match wal_retention_halt {
+        Some(wal_retention_halt) => tokio::select! {
+            result = replication => result.map_err(Into::into),
+            Ok(retained_wal_bytes) = wal_retention_halt => Err(format!(
+                "stopped because the slot retains {retained_wal_bytes} bytes of wal, more than allowed"
+            )
+            .into()),
+        },
+        None => replication.await.map_err(Into::into),
+    };
+    opentelemetry::global::shutdown_tracer_provider();
+    result?;
 
     Ok(())
 }
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use pg_replicate::{
+    pipeline::TableStatusUpdate,
+    table::TableId,
+    tokio_postgres::{self, Client, Config, NoTls},
+};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{info, warn};
+
+use crate::configuration::StatusSettings;
+
+const UPSERT_TABLE_STATUS: &str = r#"
+    insert into app.pipeline_tables (pipeline_id, table_id, schema_name, table_name, status, lsn)
+    values ($1, $2, $3, $4, $5, $6)
+    on conflict (pipeline_id, table_id) do update
+    set schema_name = excluded.schema_name,
+        table_name = excluded.table_name,
+        status = excluded.status,
+        lsn = excluded.lsn,
+        updated_at = now()
+"#;
+
+/// Clears the re-sync requests of the pipeline's tables and returns their ids
+const TAKE_RESYNC_REQUESTS: &str = r#"
+    update app.pipeline_tables
+    set resync_requested = false
+    where pipeline_id = $1 and resync_requested
+    returning table_id
+"#;
+
+/// How often the api's database is checked for re-sync requests
+const RESYNC_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+async fn connect(settings: &StatusSettings) -> Result<Client, tokio_postgres::Error> {
+    let mut config = Config::new();
+    config
+        .host(&settings.host)
+        .port(settings.port)
+        .dbname(&settings.name)
+        .user(&settings.username);
+    if let Some(password) = &settings.password {
+        config.password(password);
+    }
+
+    info!("connecting to the status database");
+    let (client, connection) = config.connect(NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            warn!("status database connection error: {e}");
+        }
+    });
+    Ok(client)
+}
+
+/// Connects to the api's database and spawns a task which records the
+/// statuses received on `table_status_receiver` in it
+pub async fn record_table_statuses(
+    settings: &StatusSettings,
+    mut table_status_receiver: Receiver<TableStatusUpdate>,
+) -> Result<(), tokio_postgres::Error> {
+    let client = connect(settings).await?;
+    let pipeline_id = settings.pipeline_id;
+
+    tokio::spawn(async move {
+        while let Some(update) = table_status_receiver.recv().await {
+            // Failing to record a status must not stop replication
+            if let Err(e) = record_table_status(&client, pipeline_id, &update).await {
+                warn!(
+                    "failed to record status of table {}: {e}",
+                    update.table_name
+                );
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Connects to the api's database and spawns a task which sends the ids of
+/// tables whose re-sync was requested through the api to `resync_sender`.
+/// A request is cleared once it's sent, so it isn't repeated if the re-sync
+/// fails.
+pub async fn receive_resync_requests(
+    settings: &StatusSettings,
+    resync_sender: Sender<TableId>,
+) -> Result<(), tokio_postgres::Error> {
+    let client = connect(settings).await?;
+    let pipeline_id = settings.pipeline_id;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RESYNC_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let rows = match client.query(TAKE_RESYNC_REQUESTS, &[&pipeline_id]).await {
+                Ok(rows) => rows,
+                // Failing to check for requests must not stop replication
+                Err(e) => {
+                    warn!("failed to read re-sync requests: {e}");
+                    continue;
+                }
+            };
+            for row in rows {
+                let table_id: i64 = row.get("table_id");
+                info!("re-sync of table {table_id} requested");
+                if resync_sender.send(table_id as TableId).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn record_table_status(
+    client: &Client,
+    pipeline_id: i64,
+    update: &TableStatusUpdate,
+) -> Result<(), tokio_postgres::Error> {
+    let table_id = i64::from(update.table_id);
+    let status = update.status.to_string();
+    client
+        .execute(
+            UPSERT_TABLE_STATUS,
+            &[
+                &pipeline_id,
+                &table_id,
+                &update.table_name.schema,
+                &update.table_name.name,
+                &status,
+                &update.lsn,
+            ],
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use opentelemetry::{
+    global,
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const SERVICE_NAME: &str = "replicator";
+
+/// Builds a tracer which exports spans to the OTLP collector at `otlp_endpoint`
+pub fn init_tracer(otlp_endpoint: &str) -> Result<Tracer, TraceError> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(otlp_endpoint)
+        .build()?;
+    let tracer_provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+        .build();
+    let tracer = tracer_provider.tracer(SERVICE_NAME);
+    global::set_tracer_provider(tracer_provider);
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(tracer)
+}
+
+/// Returns the span the pipeline runs in. It is a child of the span of
+/// `trace_parent`, a W3C `traceparent`, if one is given.
+pub fn replicator_span(trace_parent: Option<&str>) -> Span {
+    let span = info_span!("replicator");
+    if let Some(trace_parent) = trace_parent {
+        let carrier = HashMap::from([("traceparent".to_string(), trace_parent.to_string())]);
+        let parent = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+        span.set_parent(parent);
+    }
+    span
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use pg_replicate::{
+    clients::postgres::{ConnectionOptions, ReplicationClient, ReplicationClientError},
+    tokio_postgres::{self, Client},
+};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::configuration::WalRetentionSettings;
+
+/// WAL the source retains for the slot, which is all WAL from the slot's
+/// `restart_lsn` on
+const RETAINED_WAL_BYTES: &str = r#"
+    select pg_wal_lsn_diff(pg_current_wal_lsn(), restart_lsn)::bigint as retained_wal_bytes
+    from pg_replication_slots
+    where slot_name = $1
+"#;
+
+/// The source's database a slot's retained WAL is checked on, connected to
+/// like the source with `options`
+pub struct SourceDatabase<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub name: &'a str,
+    pub username: &'a str,
+    pub password: Option<&'a str>,
+    pub options: &'a ConnectionOptions,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WalRetention {
+    WithinLimit,
+    Exceeded { retained_wal_bytes: u64 },
+}
+
+/// Compares the WAL retained for the slot with the configured maximum
+pub fn check_wal_retention(
+    retained_wal_bytes: u64,
+    settings: &WalRetentionSettings,
+) -> WalRetention {
+    if retained_wal_bytes > settings.max_retained_wal_bytes {
+        WalRetention::Exceeded { retained_wal_bytes }
+    } else {
+        WalRetention::WithinLimit
+    }
+}
+
+/// Logs the retained WAL as the `retained_wal_bytes` field, so that it can
+/// be alerted on like a gauge, and warns if it exceeds the maximum
+fn report_wal_retention(
+    slot_name: &str,
+    retained_wal_bytes: u64,
+    settings: &WalRetentionSettings,
+) -> WalRetention {
+    let retention = check_wal_retention(retained_wal_bytes, settings);
+    match retention {
+        WalRetention::WithinLimit => {
+            info!(slot_name, retained_wal_bytes, "slot retained wal");
+        }
+        WalRetention::Exceeded { .. } => {
+            warn!(
+                slot_name,
+                retained_wal_bytes,
+                max_retained_wal_bytes = settings.max_retained_wal_bytes,
+                "slot retains more wal than allowed"
+            );
+        }
+    }
+    retention
+}
+
+async fn connect(source: &SourceDatabase<'_>) -> Result<Client, ReplicationClientError> {
+    ReplicationClient::connect_query_client_no_tls(
+        source.host,
+        source.port,
+        source.name,
+        source.username,
+        source.password.map(str::to_string),
+        source.options,
+    )
+    .await
+}
+
+async fn get_retained_wal_bytes(
+    client: &Client,
+    slot_name: &str,
+) -> Result<Option<u64>, tokio_postgres::Error> {
+    let row = client.query_opt(RETAINED_WAL_BYTES, &[&slot_name]).await?;
+    // restart_lsn is null until a slot reserves WAL
+    Ok(row
+        .and_then(|row| row.get::<_, Option<i64>>("retained_wal_bytes"))
+        .map(|retained_wal_bytes| retained_wal_bytes.max(0) as u64))
+}
+
+/// Connects to the source without a replication connection, through the
+/// source's proxy and with its timeouts and authentication, and spawns a
+/// task which checks the WAL retained for `slot_name` every
+/// `check_interval_secs`. The returned receiver gets the retained WAL once
+/// it exceeds the maximum if `halt` is set, and never otherwise.
+pub async fn monitor_wal_retention(
+    source: &SourceDatabase<'_>,
+    slot_name: String,
+    settings: WalRetentionSettings,
+) -> Result<oneshot::Receiver<u64>, ReplicationClientError> {
+    let client = connect(source).await?;
+    let (halt_sender, halt_receiver) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(settings.check_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            let retained_wal_bytes = match get_retained_wal_bytes(&client, &slot_name).await {
+                Ok(Some(retained_wal_bytes)) => retained_wal_bytes,
+                Ok(None) => continue,
+                // Failing to check the retained WAL must not stop replication
+                Err(e) => {
+                    warn!("failed to check wal retained for slot {slot_name}: {e}");
+                    continue;
+                }
+            };
+            let retention = report_wal_retention(&slot_name, retained_wal_bytes, &settings);
+            if settings.halt && retention != WalRetention::WithinLimit {
+                let _ = halt_sender.send(retained_wal_bytes);
+                return;
+            }
+        }
+    });
+
+    Ok(halt_receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::WalRetentionSettings;
+
+    use super::{check_wal_retention, report_wal_retention, WalRetention};
+
+    fn settings() -> WalRetentionSettings {
+        WalRetentionSettings {
+            max_retained_wal_bytes: 1024,
+            check_interval_secs: 60,
+            halt: true,
+        }
+    }
+
+    #[test]
+    fn retained_wal_up_to_the_maximum_is_within_the_limit() {
+        let settings = settings();
+        assert_eq!(check_wal_retention(0, &settings), WalRetention::WithinLimit);
+        assert_eq!(
+            check_wal_retention(1024, &settings),
+            WalRetention::WithinLimit
+        );
+        assert_eq!(
+            check_wal_retention(1025, &settings),
+            WalRetention::Exceeded {
+                retained_wal_bytes: 1025
+            }
+        );
+    }
+
+    #[test]
+    fn retained_wal_is_reported_with_its_retention() {
+        let settings = settings();
+        assert_eq!(
+            report_wal_retention("slot", 10, &settings),
+            WalRetention::WithinLimit
+        );
+        assert_eq!(
+            report_wal_retention("slot", 2048, &settings),
+            WalRetention::Exceeded {
+                retained_wal_bytes: 2048
+            }
+        );
+    }
+}